@@ -14,6 +14,19 @@ struct Args {
     /// Socket directory path
     #[arg(long, help = "Directory path for property service sockets")]
     socket_dir: Option<PathBuf>,
+
+    /// Replace sockets served by a live process outside the service lock
+    #[arg(long, help = "Take over sockets held by another live process")]
+    takeover: bool,
+
+    /// Record every set, for the `replay` example
+    #[arg(long, help = "Record every set to this file")]
+    record: Option<PathBuf>,
+
+    /// Keep `persist.` properties in this file across restarts; keep it
+    /// outside the properties directory, which is recreated on start
+    #[arg(long, help = "Keep persist. properties in this file")]
+    persistent_properties: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -24,23 +37,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Initialize logging
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    // Setup directories
-    let properties_dir = args
-        .properties_dir
-        .unwrap_or_else(|| PathBuf::from("__properties__"));
+    // Setup directories. The default is per-process so several example
+    // instances (or test runs) never share areas or sockets.
+    let properties_dir = args.properties_dir.unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("rsprops_example_{}", std::process::id()))
+    });
     let socket_dir = args
         .socket_dir
         .unwrap_or_else(|| properties_dir.join("sockets"));
 
-    // Clean and create directories
+    // Clean and create directories. The socket directory is left alone:
+    // the service replaces stale sockets itself and refuses to start if
+    // another service still owns it.
     let _ = remove_dir_all(&properties_dir);
-    let _ = remove_dir_all(&socket_dir);
     create_dir_all(&properties_dir)?;
     create_dir_all(&socket_dir)?;
 
     println!("📁 Created directories:");
     println!("   Properties: {properties_dir:?}");
     println!("   Sockets: {socket_dir:?}");
+    println!("👉 Point clients at this service with:");
+    println!(
+        "   export {}={}",
+        rsproperties::SOCKET_DIR_ENV,
+        socket_dir.display()
+    );
+    println!("   getprop --properties-dir {}", properties_dir.display());
 
     // Create PropertyConfig
     let config = rsproperties::PropertyConfig::with_both_dirs(properties_dir, socket_dir);
@@ -48,32 +70,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("🚀 Starting rsproperties services...");
 
     // Initialize the services
-    let (socket_service, properties_service) = rsproperties_service::run(
+    let takeover = if args.takeover {
+        rsproperties_service::TakeoverPolicy::Replace
+    } else {
+        rsproperties_service::TakeoverPolicy::Refuse
+    };
+    let mut options = rsproperties_service::ServiceOptions::default().takeover(takeover);
+    if let Some(path) = args.record {
+        println!("⏺️  Recording sets to {path:?}");
+        options = options.record(path);
+    }
+    if let Some(path) = args.persistent_properties {
+        println!("💾 Keeping persist. properties in {path:?}");
+        options = options.persistent_properties(path);
+    }
+    let runtime = rsproperties_service::ServiceRuntime::start(
         config,
         vec![], // property_contexts_files
         vec![], // build_prop_files
+        options,
     )
     .await?;
 
     println!("✅ Services started successfully!");
-    println!("🔄 Services are running. Press Ctrl+C to stop.");
-
-    // Handle graceful shutdown
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            println!("\n🛑 Shutdown signal received...");
-        }
-        result1 = socket_service.join_handle => {
-            if let Err(e) = result1 {
-                eprintln!("❌ Socket service error: {e}");
-            }
-        }
-        result2 = properties_service.join_handle => {
-            if let Err(e) = result2 {
-                eprintln!("❌ Properties service error: {e}");
-            }
-        }
-    }
+    println!("🔄 Services are running. Press Ctrl+C (or send SIGTERM) to stop; SIGHUP reloads.");
+
+    // Graceful shutdown on SIGTERM/SIGINT; SIGHUP only bumps the reload
+    // counter here, as the example has no settings to re-read.
+    runtime
+        .serve_until_signal(|| {
+            println!("🔁 Reload requested");
+        })
+        .await?;
 
     println!("👋 Services stopped.");
     Ok(())
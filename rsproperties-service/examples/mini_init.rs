@@ -0,0 +1,611 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A minimal init for containers, built from the pieces this workspace
+//! provides:
+//!
+//! 1. **Bootstrap** — `property_contexts` files are compiled into
+//!    `property_info`, the per-context areas are created and the build.prop
+//!    files are loaded into them (`rsproperties_service::run_with_options`),
+//!    printing each file parsed and each entry that did not apply. With
+//!    `--requirements`, missing properties get their defaults and a
+//!    missing required one stops the boot.
+//! 2. **Property service** — clients `setprop` through the socket service
+//!    as on Android; SIGTERM/SIGINT shut down, SIGHUP reloads.
+//! 3. **Persistent properties** — `persist.*` values are loaded from
+//!    `<root>/data/property/persistent_properties` at boot, then
+//!    `ro.persistent_properties.ready` is set; later changes are written
+//!    back to the file, and setting one to `""` removes it.
+//! 4. **Control properties** — `ctl.start`, `ctl.stop` and `ctl.restart`
+//!    with a service name start and stop the services declared with
+//!    `--service`, publishing `init.svc.<name>` and
+//!    `init.svc_debug_pid.<name>`. Unlike Android, the service stores
+//!    control properties; init acknowledges one by clearing it, so the
+//!    same request can be made again.
+//! 5. **Property triggers** — `on property:` sections of the `--rc` files
+//!    run through `RcTriggerEngine`; `setprop ctl.start <name>` starts a
+//!    service from a trigger.
+//!
+//! Children are reaped with `waitpid(-1)`, so the example also collects
+//! orphans when it runs as PID 1 (or, on Linux, as a child subreaper).
+//!
+//! Without `--service` and `--rc` it boots a built-in demo: a `demo`
+//! service (`sleep 3600`) started once `sys.boot_completed=1`.
+//! `--self-test` boots the demo, drives every subsystem through the socket
+//! the way a client would, and exits non-zero if any check fails:
+//!
+//! ```bash
+//! cargo run -p rsproperties-service --example mini_init -- --self-test
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, remove_dir_all};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use rsproperties::mirror::ServiceSink;
+use rsproperties::{FrozenProperties, PropFileEditor, PropertyRequirement, SystemProperties};
+use rsproperties_service::{PreloadEvent, RcTriggerEngine, Restart, ServiceRuntime, ShutdownToken};
+use rustix::process::{Pid, Signal, WaitOptions};
+
+const PERSIST_PREFIX: &str = "persist.";
+const PERSIST_READY_PROPERTY: &str = "ro.persistent_properties.ready";
+const BOOT_COMPLETED_PROPERTY: &str = "sys.boot_completed";
+
+const DEFAULT_PROPERTY_CONTEXTS: &str = "\
+ctl.        u:object_r:ctl_prop:s0        prefix string
+init.svc.   u:object_r:init_svc_prop:s0   prefix string
+persist.    u:object_r:persist_prop:s0    prefix string
+ro.         u:object_r:build_prop:s0      prefix string
+sys.        u:object_r:system_prop:s0     prefix string
+";
+
+const DEFAULT_BUILD_PROP: &str = "\
+# Loaded into the areas at bootstrap.
+ro.mini_init.version=1
+ro.build.type=userdebug
+";
+
+const DEMO_SERVICE: &str = "demo=sleep 3600";
+
+const DEMO_RC: &str = "\
+on property:sys.boot_completed=1
+    setprop ctl.start demo
+
+on property:init.svc.demo=running
+    setprop mini_init.demo.seen 1
+";
+
+#[derive(Parser, Debug)]
+#[command(name = "mini_init")]
+#[command(about = "Minimal init for containers built on rsproperties")]
+struct Args {
+    /// Root directory of the properties, sockets and persistent data
+    #[arg(long, help = "Root directory (default: a per-process temp dir)")]
+    root: Option<PathBuf>,
+
+    /// property_contexts files compiled into property_info
+    #[arg(long, help = "property_contexts file (repeatable)")]
+    property_contexts: Vec<PathBuf>,
+
+    /// build.prop files loaded at bootstrap
+    #[arg(long, help = "build.prop file (repeatable)")]
+    build_prop: Vec<PathBuf>,
+
+    /// Defaults and required properties checked against the build.prop
+    /// files (`name required` / `name default <value>` lines)
+    #[arg(long, help = "Bootstrap requirements file")]
+    requirements: Option<PathBuf>,
+
+    /// init.rc-style files with `on property:` triggers
+    #[arg(long, help = "rc file with property triggers (repeatable)")]
+    rc: Vec<PathBuf>,
+
+    /// Services controlled through ctl.*, as `name=command [args...]`
+    #[arg(long, help = "Service as name=command [args...] (repeatable)")]
+    service: Vec<String>,
+
+    /// Boot the built-in demo, check every subsystem, then shut down
+    #[arg(long, conflicts_with_all = ["rc", "service"])]
+    self_test: bool,
+}
+
+#[derive(Debug)]
+struct Service {
+    argv: Vec<String>,
+    pid: Option<Pid>,
+    /// Start again once the running instance has been reaped.
+    restart: bool,
+}
+
+/// The init loop: reacts to property changes and reaps children. Runs on
+/// its own thread because property waits block.
+struct Init {
+    services: BTreeMap<String, Service>,
+    engine: RcTriggerEngine,
+    persist_path: PathBuf,
+    persist: PropFileEditor,
+    /// Snapshot of the previous pass, for finding what changed.
+    previous: FrozenProperties,
+}
+
+impl Init {
+    fn new(
+        services: BTreeMap<String, Service>,
+        engine: RcTriggerEngine,
+        persist_path: PathBuf,
+    ) -> rsproperties::Result<Self> {
+        let persist = if persist_path.exists() {
+            PropFileEditor::load(&persist_path)?
+        } else {
+            PropFileEditor::parse(b"")
+        };
+        for (name, value) in persist.entries() {
+            if name.starts_with(PERSIST_PREFIX) {
+                rsproperties::set(name, value)?;
+            } else {
+                log::warn!("Ignoring non-persistent '{name}' in {persist_path:?}");
+            }
+        }
+        rsproperties::set(PERSIST_READY_PROPERTY, "true")?;
+
+        Ok(Self {
+            services,
+            engine,
+            persist_path,
+            persist,
+            previous: rsproperties::system_properties().freeze()?,
+        })
+    }
+
+    fn run(&mut self, shutdown: &ShutdownToken) -> rsproperties::Result<()> {
+        let props = rsproperties::system_properties();
+        // The first pass fires the triggers that already hold, like init's.
+        self.process(props)?;
+        rsproperties::set(BOOT_COMPLETED_PROPERTY, "1")?;
+
+        // The timeout bounds how long an exited child stays a zombie and
+        // how long shutdown takes to be noticed.
+        let timeout = rsproperties::Timespec {
+            tv_sec: 0,
+            tv_nsec: 200_000_000,
+        };
+        while !shutdown.is_shutdown() {
+            props.wait(None, Some(self.previous.serial()), Some(&timeout));
+            self.reap();
+            if props.context_serial() != self.previous.serial() {
+                self.process(props)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the control and persistent properties that changed since
+    /// the previous pass, then runs the triggers.
+    fn process(&mut self, props: &SystemProperties) -> rsproperties::Result<()> {
+        let current = props.freeze()?;
+        let mut persist_changed = false;
+        for (name, value) in current.iter() {
+            if self.previous.get(name) == Some(value) {
+                continue;
+            }
+            if let Some(command) = name.strip_prefix("ctl.") {
+                if !value.is_empty() {
+                    self.control(command, value);
+                    if let Err(e) = rsproperties::set(name, "") {
+                        log::warn!("Failed to acknowledge '{name}': {e}");
+                    }
+                }
+            } else if name.starts_with(PERSIST_PREFIX) {
+                persist_changed |= self.persist_update(name, value);
+            }
+        }
+        if persist_changed {
+            if let Err(e) = self.persist.save(&self.persist_path) {
+                log::error!("Failed to save persistent properties: {e}");
+            }
+        }
+        self.previous = current;
+
+        // Triggers take their own snapshot, so they also see the
+        // acknowledgements and service states published above.
+        let report = self.engine.process(props, &mut ServiceSink)?;
+        // Failed commands are logged by the engine.
+        for trigger in &report.fired {
+            log::info!("Trigger 'on {trigger}' fired");
+        }
+        Ok(())
+    }
+
+    fn persist_update(&mut self, name: &str, value: &str) -> bool {
+        if value.is_empty() {
+            return self.persist.remove(name);
+        }
+        if self.persist.get(name) == Some(value) {
+            return false;
+        }
+        match self.persist.set(name, value) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Not persisting '{name}': {e}");
+                false
+            }
+        }
+    }
+
+    fn control(&mut self, command: &str, name: &str) {
+        let Some(service) = self.services.get_mut(name) else {
+            log::warn!("ctl.{command}: no service '{name}'");
+            return;
+        };
+        match (command, service.pid) {
+            ("start", None) | ("restart", None) => self.start(name),
+            ("start", Some(_)) => log::info!("Service '{name}' is already running"),
+            ("stop", Some(pid)) => {
+                service.restart = false;
+                terminate(pid);
+                publish_state(name, "stopping");
+            }
+            ("restart", Some(pid)) => {
+                service.restart = true;
+                terminate(pid);
+                publish_state(name, "restarting");
+            }
+            ("stop", None) => {}
+            _ => log::warn!("Unsupported control property 'ctl.{command}'"),
+        }
+    }
+
+    fn start(&mut self, name: &str) {
+        let Some(service) = self.services.get_mut(name) else {
+            return;
+        };
+        service.restart = false;
+        match std::process::Command::new(&service.argv[0])
+            .args(&service.argv[1..])
+            .spawn()
+        {
+            // The `Child` is dropped without waiting: `reap` collects the
+            // exit status by pid, along with orphans.
+            Ok(child) => {
+                let pid = Pid::from_child(&child);
+                log::info!("Started service '{name}' (pid {})", pid.as_raw_nonzero());
+                service.pid = Some(pid);
+                publish(
+                    &format!("init.svc_debug_pid.{name}"),
+                    &pid.as_raw_nonzero().to_string(),
+                );
+                publish_state(name, "running");
+            }
+            Err(e) => {
+                log::error!("Failed to start service '{name}': {e}");
+                publish_state(name, "stopped");
+            }
+        }
+    }
+
+    /// Collects every exited child. Runs on the same thread as the
+    /// triggers, so it never steals the status of an `exec` action's child.
+    fn reap(&mut self) {
+        while let Ok(Some((pid, status))) = rustix::process::waitpid(None, WaitOptions::NOHANG) {
+            let Some((name, service)) = self
+                .services
+                .iter_mut()
+                .find(|(_, service)| service.pid == Some(pid))
+            else {
+                log::debug!("Reaped orphan {}", pid.as_raw_nonzero());
+                continue;
+            };
+            log::info!("Service '{name}' exited: {status:?}");
+            service.pid = None;
+            let name = name.clone();
+            if service.restart {
+                self.start(&name);
+            } else {
+                publish(&format!("init.svc_debug_pid.{name}"), "");
+                publish_state(&name, "stopped");
+            }
+        }
+    }
+
+    /// Terminates the services at shutdown. Nothing is published: nobody
+    /// acts on the states any more.
+    fn stop_all(&mut self) {
+        for service in self.services.values() {
+            if let Some(pid) = service.pid {
+                terminate(pid);
+            }
+        }
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while self.services.values().any(|s| s.pid.is_some()) && Instant::now() < deadline {
+            while let Ok(Some((pid, _))) = rustix::process::waitpid(None, WaitOptions::NOHANG) {
+                for service in self.services.values_mut() {
+                    if service.pid == Some(pid) {
+                        service.pid = None;
+                    }
+                }
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        for (name, service) in &self.services {
+            if let Some(pid) = service.pid {
+                log::warn!("Service '{name}' ignored SIGTERM; killing it");
+                let _ = rustix::process::kill_process(pid, Signal::KILL);
+                let _ = rustix::process::waitpid(Some(pid), WaitOptions::empty());
+            }
+        }
+    }
+}
+
+fn terminate(pid: Pid) {
+    if let Err(e) = rustix::process::kill_process(pid, Signal::TERM) {
+        log::warn!("Failed to signal {}: {e}", pid.as_raw_nonzero());
+    }
+}
+
+fn publish(name: &str, value: &str) {
+    if let Err(e) = rsproperties::set(name, value) {
+        log::warn!("Failed to set '{name}': {e}");
+    }
+}
+
+fn publish_state(name: &str, state: &str) {
+    publish(&format!("init.svc.{name}"), state);
+}
+
+fn parse_service(spec: &str) -> Result<(String, Service), String> {
+    let (name, command) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("service '{spec}' is not name=command"))?;
+    let argv: Vec<String> = command.split_whitespace().map(str::to_owned).collect();
+    if name.is_empty() || argv.is_empty() {
+        return Err(format!("service '{spec}' has no name or no command"));
+    }
+    Ok((
+        name.to_owned(),
+        Service {
+            argv,
+            pid: None,
+            restart: false,
+        },
+    ))
+}
+
+/// Prints the bootstrap's build.prop load and the entries it did not apply.
+fn report_preload(event: PreloadEvent<'_>) {
+    match event {
+        PreloadEvent::FileParsed {
+            path,
+            properties,
+            elapsed,
+        } => println!(
+            "📄 Parsed {} ({properties} properties so far, {elapsed:?})",
+            path.display()
+        ),
+        PreloadEvent::FileFailed { path, error } => {
+            eprintln!("❌ Cannot load {}: {error}", path.display())
+        }
+        PreloadEvent::Skipped { name, error } | PreloadEvent::Failed { name, error } => {
+            eprintln!("⚠️  {name} not applied: {error}")
+        }
+        PreloadEvent::Requirements(report) => {
+            for name in &report.defaulted {
+                println!("🧩 {name} defaulted");
+            }
+            for name in &report.missing {
+                eprintln!("❌ Required property {name} is missing");
+            }
+        }
+        PreloadEvent::Finished(summary) => println!(
+            "📦 Loaded {} properties from {} files ({} skipped) in {:?}",
+            summary.added,
+            summary.files,
+            summary.skipped,
+            summary.parse_time + summary.apply_time
+        ),
+        _ => {}
+    }
+}
+
+/// Writes `contents` to `dir/name` and returns the path.
+fn write_default(dir: &Path, name: &str, contents: &str) -> std::io::Result<PathBuf> {
+    let path = dir.join(name);
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// Waits until `check` holds, re-checking on every property change.
+fn wait_until(what: &str, mut check: impl FnMut() -> bool) -> Result<(), String> {
+    let props = rsproperties::system_properties();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let serial = props.context_serial();
+        if check() {
+            println!("   ✅ {what}");
+            return Ok(());
+        }
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            return Err(format!("timed out waiting for: {what}"));
+        };
+        let timeout = rsproperties::Timespec {
+            tv_sec: 0,
+            tv_nsec: left.min(Duration::from_millis(100)).subsec_nanos() as _,
+        };
+        props.wait(None, Some(serial), Some(&timeout));
+    }
+}
+
+fn is(name: &str, expected: &str) -> bool {
+    rsproperties::get::<String>(name).is_ok_and(|value| value == expected)
+}
+
+/// Drives the built-in demo like a client would.
+fn self_test(persist_path: &Path) -> Result<(), String> {
+    rsproperties::wait_for_service(Duration::from_secs(5)).map_err(|e| e.to_string())?;
+    let set = |name: &str, value: &str| rsproperties::set(name, value).map_err(|e| e.to_string());
+
+    println!("🧪 Bootstrap");
+    wait_until("build.prop loaded", || is("ro.mini_init.version", "1"))?;
+
+    println!("🧪 Persistent properties");
+    wait_until("persistent properties loaded", || {
+        is(PERSIST_READY_PROPERTY, "true") && is("persist.mini_init.seeded", "1")
+    })?;
+    set("persist.mini_init.counter", "42")?;
+    set("persist.mini_init.seeded", "")?;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let saved = PropFileEditor::load(persist_path).map_err(|e| e.to_string())?;
+        if saved.get("persist.mini_init.counter") == Some("42")
+            && saved.get("persist.mini_init.seeded").is_none()
+        {
+            println!("   ✅ changes written back");
+            break;
+        }
+        if Instant::now() > deadline {
+            return Err("persistent properties were not written back".to_owned());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    println!("🧪 Triggers and control properties");
+    wait_until("boot trigger started demo", || {
+        is("init.svc.demo", "running") && is("mini_init.demo.seen", "1")
+    })?;
+    let pid = rsproperties::get::<String>("init.svc_debug_pid.demo").map_err(|e| e.to_string())?;
+    set("ctl.restart", "demo")?;
+    wait_until("ctl.restart replaced the process", || {
+        is("init.svc.demo", "running")
+            && rsproperties::get::<String>("init.svc_debug_pid.demo")
+                .is_ok_and(|new| !new.is_empty() && new != pid)
+    })?;
+    set("ctl.stop", "demo")?;
+    wait_until("ctl.stop stopped it", || is("init.svc.demo", "stopped"))?;
+    set("ctl.start", "demo")?;
+    wait_until("ctl.start started it again", || {
+        is("init.svc.demo", "running") && is("ctl.start", "")
+    })?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = Args::parse();
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let root = args.root.unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("rsprops_mini_init_{}", std::process::id()))
+    });
+    let properties_dir = root.join("properties");
+    let socket_dir = root.join("sockets");
+    let etc_dir = root.join("etc");
+    let persist_path = root.join("data/property/persistent_properties");
+
+    // The areas start fresh on every boot, as on a tmpfs; the persistent
+    // data directory is kept.
+    let _ = remove_dir_all(&properties_dir);
+    for dir in [&properties_dir, &socket_dir, &etc_dir] {
+        create_dir_all(dir)?;
+    }
+    create_dir_all(persist_path.parent().unwrap())?;
+    if args.self_test {
+        std::fs::write(&persist_path, "persist.mini_init.seeded=1\n")?;
+    }
+
+    let property_contexts = if args.property_contexts.is_empty() {
+        vec![write_default(
+            &etc_dir,
+            "property_contexts",
+            DEFAULT_PROPERTY_CONTEXTS,
+        )?]
+    } else {
+        args.property_contexts
+    };
+    let build_prop = if args.build_prop.is_empty() {
+        vec![write_default(&etc_dir, "build.prop", DEFAULT_BUILD_PROP)?]
+    } else {
+        args.build_prop
+    };
+    let requirements = match &args.requirements {
+        Some(path) => PropertyRequirement::parse_from_file(path)?,
+        None => Vec::new(),
+    };
+    let (service_specs, rc_files) = if args.service.is_empty() && args.rc.is_empty() {
+        let rc = write_default(&etc_dir, "init.rc", DEMO_RC)?;
+        (vec![DEMO_SERVICE.to_owned()], vec![rc])
+    } else {
+        (args.service, args.rc)
+    };
+
+    let services = service_specs
+        .iter()
+        .map(|spec| parse_service(spec))
+        .collect::<Result<BTreeMap<_, _>, _>>()?;
+    let mut engine = RcTriggerEngine::default();
+    for rc in &rc_files {
+        engine.extend_from_file(rc)?;
+    }
+    for skipped in engine.unsupported() {
+        log::info!("Skipping unsupported rc entry: {skipped}");
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Err(e) = rustix::process::set_child_subreaper(Some(rustix::process::getpid())) {
+        log::warn!("Cannot become a child subreaper ({e}); orphans are reaped only as PID 1");
+    }
+
+    println!("📁 Root: {root:?}");
+    println!("👉 Point clients at this init with:");
+    println!(
+        "   export {}={}",
+        rsproperties::SOCKET_DIR_ENV,
+        socket_dir.display()
+    );
+
+    let config = rsproperties::PropertyConfig::with_both_dirs(properties_dir, socket_dir);
+    let mut runtime = ServiceRuntime::start(
+        config,
+        property_contexts,
+        build_prop,
+        rsproperties_service::ServiceOptions::default()
+            .preload_progress(report_preload)
+            .requirements(requirements),
+    )
+    .await?;
+    println!("✅ Property service started");
+
+    // Not restarted: the services it started would be orphaned. A failure
+    // shuts the runtime down, as nothing would manage them any more.
+    let mut init = Some((services, engine, persist_path.clone()));
+    runtime.spawn_blocking("init loop", Restart::Never, move |shutdown| {
+        let (services, engine, persist_path) = init.take().ok_or("init loop already ran")?;
+        let mut init = Init::new(services, engine, persist_path)?;
+        let result = init.run(&shutdown);
+        init.stop_all();
+        Ok(result?)
+    });
+
+    let test_thread = args.self_test.then(|| {
+        std::thread::spawn(move || {
+            let result = self_test(&persist_path);
+            // Shut down through the same path as `kill -TERM 1`.
+            let _ = rustix::process::kill_process(rustix::process::getpid(), Signal::TERM);
+            result
+        })
+    });
+
+    runtime
+        .serve_until_signal(|| {
+            println!("🔁 Reload requested");
+        })
+        .await?;
+    println!("👋 Stopped.");
+
+    if let Some(test_thread) = test_thread {
+        test_thread.join().expect("self-test panicked")?;
+        println!("✅ Self-test passed");
+    }
+    Ok(())
+}
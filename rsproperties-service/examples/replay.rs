@@ -0,0 +1,64 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `replay` - re-apply a recorded property session
+//!
+//! Sends the sets a service recorded (`example_service --record <file>`,
+//! or `ServiceOptions::record`) to the service behind a socket directory,
+//! with the recorded timing, so a bug that depends on the order and
+//! timing of sets during boot reproduces on a developer machine.
+//!
+//! Usage:
+//!   replay --socket-dir <dir> <recording> [--speed <factor> | --unpaced]
+//!
+//! Examples:
+//!   replay --socket-dir /tmp/props/sockets boot.rec              # Original timing
+//!   replay --socket-dir /tmp/props/sockets boot.rec --speed 10   # Ten times as fast
+//!   replay --socket-dir /tmp/props/sockets boot.rec --unpaced    # Back to back
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use rsproperties_service::ReplaySpeed;
+
+#[derive(Parser, Debug)]
+#[command(name = "replay")]
+#[command(about = "Re-apply a recorded property session")]
+struct Args {
+    /// Recording to replay
+    recording: PathBuf,
+
+    /// Socket directory of the service to replay into
+    #[arg(long, help = "Directory path for property service sockets")]
+    socket_dir: PathBuf,
+
+    /// Divide the recorded gaps by this factor
+    #[arg(long, value_name = "FACTOR", conflicts_with = "unpaced")]
+    speed: Option<f64>,
+
+    /// Send the sets back to back
+    #[arg(long)]
+    unpaced: bool,
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let args = Args::parse();
+    rsproperties::init(rsproperties::PropertyConfig::with_socket_dir(
+        args.socket_dir,
+    ));
+    let speed = match (args.speed, args.unpaced) {
+        (_, true) => ReplaySpeed::Unpaced,
+        (Some(factor), false) => ReplaySpeed::Scaled(factor),
+        (None, false) => ReplaySpeed::Original,
+    };
+
+    match rsproperties_service::replay(&args.recording, speed) {
+        Ok(sets) => println!("Replayed {sets} sets"),
+        Err(e) => {
+            eprintln!("replay: {e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-client authorization of the writes the socket service receives,
+//! see [`AccessPolicy`].
+
+use std::sync::Arc;
+
+use tokio::net::UnixStream;
+
+/// Who is on the other end of a connection, as the kernel reports it
+/// (`SO_PEERCRED` on Linux and Android).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// `None` on platforms that do not report the peer's pid.
+    pub pid: Option<i32>,
+}
+
+impl PeerCredentials {
+    pub fn new(uid: u32, gid: u32, pid: Option<i32>) -> Self {
+        Self { uid, gid, pid }
+    }
+
+    /// The credentials of `stream`'s peer, taken when it connected.
+    pub(crate) fn of(stream: &UnixStream) -> std::io::Result<Self> {
+        let cred = stream.peer_cred()?;
+        Ok(Self::new(cred.uid(), cred.gid(), cred.pid()))
+    }
+}
+
+/// `uid=1000 gid=1000 pid=42`, as the service logs the sender of a set.
+impl std::fmt::Display for PeerCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "uid={} gid={}", self.uid, self.gid)?;
+        match self.pid {
+            Some(pid) => write!(f, " pid={pid}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Decides which clients may write which properties, in the place of the
+/// SELinux `property_service { set }` check Android's init makes.
+///
+/// The store asks the policy about every set (V1, SETPROP2 and each
+/// SETPROP_BATCH entry) and every UNSETPROP a socket client sends, handing
+/// it the peer's credentials, the name as it is about to be stored —
+/// canonicalized and through the [`crate::TransformChain`] — and the
+/// SELinux context `property_info` assigns to that name (`None` when no
+/// entry covers it). So `" PERSIST.X"` is checked as `persist.x`, and a
+/// transform cannot rename a set past the policy. A refused SETPROP2 or
+/// UNSETPROP is answered with `PROP_ERROR_PERMISSION_DENIED`; a refused V1
+/// set, which gets no reply, is dropped. A connection whose credentials
+/// cannot be read is refused every write.
+///
+/// The policy runs on the store's actor and should not block.
+/// Without one (see [`crate::ServiceOptions::access_policy`]) the service
+/// uses [`PermissivePolicy`], so the socket file's mode is the only access
+/// control.
+pub trait AccessPolicy: std::fmt::Debug + Send + Sync {
+    /// Whether the client with `credentials` may write `name`.
+    fn check(&self, credentials: &PeerCredentials, name: &str, context: Option<&str>) -> bool;
+
+    /// Whether the client with `credentials` may fetch the areas of a
+    /// memfd-backed store (`PROP_MSG_GET_AREAS`), which lets it read every
+    /// property. A refused client gets `PROP_ERROR_PERMISSION_DENIED`.
+    /// Allowed by default, as reading a file-backed store is.
+    fn check_areas(&self, credentials: &PeerCredentials) -> bool {
+        let _ = credentials;
+        true
+    }
+}
+
+/// Lets every client write every property.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PermissivePolicy;
+
+impl AccessPolicy for PermissivePolicy {
+    fn check(&self, _credentials: &PeerCredentials, _name: &str, _context: Option<&str>) -> bool {
+        true
+    }
+}
+
+/// The client of one socket connection, as the [`AccessPolicy`] sees it.
+#[derive(Debug, Clone)]
+pub(crate) struct Peer {
+    /// `None` when they could not be read; every write is refused then.
+    pub credentials: Option<PeerCredentials>,
+    pub policy: Arc<dyn AccessPolicy>,
+}
+
+impl Peer {
+    /// Whether the client may write `name`, whose context is `context`.
+    pub(crate) fn allows(&self, name: &str, context: Option<&str>) -> bool {
+        match &self.credentials {
+            Some(credentials) => self.policy.check(credentials, name, context),
+            None => false,
+        }
+    }
+
+    /// Whether the client may fetch the memfd areas.
+    pub(crate) fn allows_areas(&self) -> bool {
+        match &self.credentials {
+            Some(credentials) => self.policy.check_areas(credentials),
+            None => false,
+        }
+    }
+}
+
+/// A shared policy, for callers that keep a handle on it.
+impl<P: AccessPolicy + ?Sized> AccessPolicy for std::sync::Arc<P> {
+    fn check(&self, credentials: &PeerCredentials, name: &str, context: Option<&str>) -> bool {
+        (**self).check(credentials, name, context)
+    }
+
+    fn check_areas(&self, credentials: &PeerCredentials) -> bool {
+        (**self).check_areas(credentials)
+    }
+}
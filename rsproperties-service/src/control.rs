@@ -0,0 +1,122 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Control properties: `ctl.start`, `ctl.stop`, `ctl.restart` and the
+//! other `ctl.` names, which Android's init treats as commands rather than
+//! values, see [`ControlHandler`].
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::access::PeerCredentials;
+
+/// Names starting with this are control properties.
+pub const CONTROL_PREFIX: &str = "ctl.";
+
+/// The commands every init understands; see [`ControlMessage::action`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ControlAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+/// A set of a `ctl.` property, dispatched to a [`ControlHandler`] instead
+/// of being stored.
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ControlMessage {
+    /// The name after `ctl.`, e.g. `start` or `interface_restart`.
+    pub command: String,
+    /// The value: the service (or interface) the command is for.
+    pub target: String,
+    /// The socket client that sent the command, for a handler that only
+    /// takes commands from some users; `None` when the service set it
+    /// itself or its credentials could not be read.
+    pub credentials: Option<PeerCredentials>,
+}
+
+impl ControlMessage {
+    /// The message for a set of `name` to `value`, or `None` when `name`
+    /// is not a control property.
+    pub fn parse(name: &str, value: &str) -> Option<Self> {
+        let command = name.strip_prefix(CONTROL_PREFIX)?;
+        Some(Self {
+            command: command.to_owned(),
+            target: value.to_owned(),
+            credentials: None,
+        })
+    }
+
+    /// The command, if it is one of the [`ControlAction`]s; the handler
+    /// decides what other commands mean.
+    pub fn action(&self) -> Option<ControlAction> {
+        match self.command.as_str() {
+            "start" => Some(ControlAction::Start),
+            "stop" => Some(ControlAction::Stop),
+            "restart" => Some(ControlAction::Restart),
+            _ => None,
+        }
+    }
+}
+
+// The target is a value like any other; keep it out of logs, as
+// `PropertyMessage` does.
+impl std::fmt::Debug for ControlMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ControlMessage")
+            .field("command", &self.command)
+            .field("target", &format_args!("<{} bytes>", self.target.len()))
+            .field("credentials", &self.credentials)
+            .finish()
+    }
+}
+
+/// Receives the control properties clients set, so an init-like daemon
+/// can start and stop its services on `setprop ctl.start <name>`.
+///
+/// With a handler configured (see [`crate::ServiceOptions::control_handler`])
+/// `ctl.` properties are no longer stored, as on Android: each set is
+/// handed to the handler, and an `Err` from it reaches the client as
+/// `PROP_ERROR_HANDLE_CONTROL_MESSAGE` with the reason. Without one they
+/// are stored like any other property.
+///
+/// The callback runs on the properties service, which handles no other
+/// set meanwhile, so it should only record the request; use
+/// [`Self::channel`] to handle the messages on a task of your own. Clones
+/// share the callback.
+#[derive(Clone)]
+pub struct ControlHandler(Arc<ControlFn>);
+
+type ControlFn = dyn Fn(&ControlMessage) -> Result<(), String> + Send + Sync;
+
+impl ControlHandler {
+    /// Wraps `f`.
+    pub fn new(f: impl Fn(&ControlMessage) -> Result<(), String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// A handler forwarding every message to the returned receiver. A set
+    /// fails once the receiver is dropped.
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<ControlMessage>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let handler = Self::new(move |message| {
+            sender
+                .send(message.clone())
+                .map_err(|_| "control messages are no longer handled".to_owned())
+        });
+        (handler, receiver)
+    }
+
+    pub(crate) fn handle(&self, message: &ControlMessage) -> Result<(), String> {
+        (self.0)(message)
+    }
+}
+
+impl std::fmt::Debug for ControlHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ControlHandler(..)")
+    }
+}
@@ -0,0 +1,422 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-disk history of the values selected properties took, see
+//! [`History`].
+//!
+//! The log is a plain append-only text file, one set per line:
+//! milliseconds since the Unix epoch, the name and the value, separated by
+//! single spaces. Backslashes and newlines in the value are escaped as
+//! `\\` and `\n`. When the file outgrows [`HistoryConfig::max_file_bytes`]
+//! it is rotated like logrotate does: `history.log` becomes
+//! `history.log.1`, `history.log.1` becomes `history.log.2`, and so on up
+//! to [`HistoryConfig::max_files`].
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The file being appended to; rotated files carry a `.N` suffix.
+const LOG_FILENAME: &str = "history.log";
+
+/// Which properties a [`History`] records, where, and how much of it is
+/// kept.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct HistoryConfig {
+    /// Directory holding the log files. Created if missing.
+    pub dir: PathBuf,
+    /// Name prefixes to record, e.g. `persist.radio.`. A set is recorded
+    /// when its name starts with any of them; an empty list records
+    /// nothing.
+    pub prefixes: Vec<String>,
+    /// Size at which the current file is rotated (default 1 MiB).
+    pub max_file_bytes: u64,
+    /// Rotated files kept besides the current one (default 8). The oldest
+    /// is deleted when another rotation would exceed it.
+    pub max_files: usize,
+    /// Rotated files whose last record is older than this are deleted
+    /// (default: kept until `max_files` pushes them out).
+    pub max_age: Option<Duration>,
+}
+
+impl HistoryConfig {
+    pub fn new<I, S>(dir: impl Into<PathBuf>, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            dir: dir.into(),
+            prefixes: prefixes.into_iter().map(Into::into).collect(),
+            max_file_bytes: 1024 * 1024,
+            max_files: 8,
+            max_age: None,
+        }
+    }
+
+    /// Sets the rotation size.
+    pub fn max_file_bytes(mut self, bytes: u64) -> Self {
+        self.max_file_bytes = bytes;
+        self
+    }
+
+    /// Sets the number of rotated files kept.
+    pub fn max_files(mut self, files: usize) -> Self {
+        self.max_files = files;
+        self
+    }
+
+    /// Sets the age beyond which rotated files are deleted.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Whether sets of `name` are recorded.
+    pub fn records(&self, name: &str) -> bool {
+        self.prefixes.iter().any(|prefix| name.starts_with(prefix))
+    }
+}
+
+/// One recorded set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HistoryEntry {
+    pub name: String,
+    pub value: String,
+    /// When the service stored the value, to the millisecond.
+    pub at: SystemTime,
+}
+
+/// Append-only log of the values properties under the configured prefixes
+/// were set to, for questions like "what values did `persist.radio.foo`
+/// take over the last week" on devices with persistent storage.
+///
+/// The properties service keeps one when [`crate::ServiceOptions::history`]
+/// is set and records every set it stores under the prefixes; values
+/// loaded from build.prop files at startup are not recorded. Any process
+/// may read the log with [`history_of`] while the service writes it.
+///
+/// Unlike the service's logs, the files hold values: they are created
+/// with mode 0600, and only the prefixes asked for are recorded. Records
+/// are written but not synced, so the last few may be lost on a power
+/// cut; a record torn by a crash is skipped when reading. One writer per
+/// directory.
+pub struct History {
+    config: HistoryConfig,
+    file: File,
+    len: u64,
+}
+
+impl History {
+    /// Opens (or starts) the log in `config.dir` and applies the retention
+    /// limits to the files already there.
+    pub fn open(config: HistoryConfig) -> io::Result<Self> {
+        std::fs::create_dir_all(&config.dir)?;
+        let file = open_log(&config.dir)?;
+        let len = file.metadata()?.len();
+        let history = Self { config, file, len };
+        history.prune(SystemTime::now());
+        Ok(history)
+    }
+
+    pub fn config(&self) -> &HistoryConfig {
+        &self.config
+    }
+
+    /// Appends a record of `name` being set to `value` at `at`, if the
+    /// configured prefixes select `name`; rotates first when the record
+    /// would push the current file past its size.
+    pub fn record(&mut self, name: &str, value: &str, at: SystemTime) -> io::Result<()> {
+        if !self.config.records(name) {
+            return Ok(());
+        }
+        let line = encode(name, value, at);
+        if self.len > 0 && self.len + line.len() as u64 > self.config.max_file_bytes {
+            self.rotate(at)?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    /// [`history_of`] this log.
+    pub fn history_of(&self, name: &str, since: SystemTime) -> io::Result<Vec<HistoryEntry>> {
+        history_of(&self.config.dir, name, since)
+    }
+
+    fn rotate(&mut self, now: SystemTime) -> io::Result<()> {
+        let dir = &self.config.dir;
+        if self.config.max_files == 0 {
+            std::fs::remove_file(dir.join(LOG_FILENAME))?;
+        } else {
+            for n in (1..self.config.max_files).rev() {
+                match std::fs::rename(rotated_path(dir, n), rotated_path(dir, n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            std::fs::rename(dir.join(LOG_FILENAME), rotated_path(dir, 1))?;
+        }
+        self.file = open_log(dir)?;
+        self.len = 0;
+        self.prune(now);
+        Ok(())
+    }
+
+    /// Deletes rotated files beyond `max_files` (left by a larger earlier
+    /// setting) and, with `max_age`, those last written before it.
+    fn prune(&self, now: SystemTime) {
+        let files = match rotated_files(&self.config.dir) {
+            Ok(files) => files,
+            Err(e) => {
+                log::warn!("Failed to list the history in {:?}: {e}", self.config.dir);
+                return;
+            }
+        };
+        let cutoff = self.config.max_age.and_then(|age| now.checked_sub(age));
+        for (n, path) in files {
+            let expired = || {
+                cutoff.is_some_and(|cutoff| {
+                    path.metadata()
+                        .and_then(|metadata| metadata.modified())
+                        .is_ok_and(|modified| modified < cutoff)
+                })
+            };
+            if n > self.config.max_files || expired() {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    log::warn!("Failed to delete the history file {path:?}: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// The values `name` was set to since `since`, oldest first, from the log
+/// in `dir`. An empty or missing directory has no history.
+pub fn history_of(dir: &Path, name: &str, since: SystemTime) -> io::Result<Vec<HistoryEntry>> {
+    let mut paths: Vec<PathBuf> = match rotated_files(dir) {
+        Ok(files) => files.into_iter().map(|(_, path)| path).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    paths.push(dir.join(LOG_FILENAME));
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            // Rotated away since the listing; its records moved to a file
+            // already read or about to be.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        let mut line_no = 0;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            line_no += 1;
+            let Some(record) = line.strip_suffix('\n') else {
+                // Torn by a crash mid-write.
+                break;
+            };
+            match decode(record) {
+                Some(entry) if entry.name == name && entry.at >= since => entries.push(entry),
+                Some(_) => {}
+                None => log::warn!("Skipping malformed history record {path:?}:{line_no}"),
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn open_log(dir: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(dir.join(LOG_FILENAME))
+}
+
+fn rotated_path(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("{LOG_FILENAME}.{n}"))
+}
+
+/// The rotated files in `dir`, oldest (highest suffix) first.
+fn rotated_files(dir: &Path) -> io::Result<Vec<(usize, PathBuf)>> {
+    let prefix = format!("{LOG_FILENAME}.");
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(n) = file_name
+            .to_str()
+            .and_then(|file_name| file_name.strip_prefix(&prefix))
+            .and_then(|n| n.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        files.push((n, entry.path()));
+    }
+    files.sort_unstable_by_key(|(n, _)| std::cmp::Reverse(*n));
+    Ok(files)
+}
+
+pub(crate) fn encode(name: &str, value: &str, at: SystemTime) -> String {
+    let millis = at
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_millis());
+    let mut line = format!("{millis} {name} ");
+    for c in value.chars() {
+        match c {
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            c => line.push(c),
+        }
+    }
+    line.push('\n');
+    line
+}
+
+pub(crate) fn decode(record: &str) -> Option<HistoryEntry> {
+    let mut fields = record.splitn(3, ' ');
+    let millis: u64 = fields.next()?.parse().ok()?;
+    let name = fields.next()?;
+    let escaped = fields.next()?;
+
+    let mut value = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '\\' => value.push('\\'),
+                'n' => value.push('\n'),
+                _ => return None,
+            }
+        } else {
+            value.push(c);
+        }
+    }
+    Some(HistoryEntry {
+        name: name.to_owned(),
+        value,
+        at: UNIX_EPOCH + Duration::from_millis(millis),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rsprops_history_{tag}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for value in ["", "1", "two words", "a\\nb", "line\nbreak\\", " x "] {
+            let line = encode("persist.radio.foo", value, at(1_700_000_000));
+            let entry = decode(line.strip_suffix('\n').unwrap()).unwrap();
+            assert_eq!(entry.name, "persist.radio.foo");
+            assert_eq!(entry.value, value);
+            assert_eq!(entry.at, at(1_700_000_000));
+        }
+        assert!(decode("x persist.radio.foo 1").is_none());
+        assert!(decode("1 persist.radio.foo a\\tb").is_none());
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let dir = temp_dir("query");
+        let mut history = History::open(HistoryConfig::new(&dir, ["persist.radio."])).unwrap();
+        history.record("persist.radio.foo", "a", at(10)).unwrap();
+        history.record("persist.radio.bar", "x", at(11)).unwrap();
+        history
+            .record("persist.sys.foo", "ignored", at(12))
+            .unwrap();
+        history.record("persist.radio.foo", "b\nc", at(20)).unwrap();
+
+        let values = |since| -> Vec<String> {
+            history
+                .history_of("persist.radio.foo", since)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.value)
+                .collect()
+        };
+        assert_eq!(values(at(0)), ["a", "b\nc"]);
+        assert_eq!(values(at(15)), ["b\nc"]);
+        assert!(history_of(&dir, "persist.sys.foo", at(0))
+            .unwrap()
+            .is_empty());
+
+        // A torn last record is skipped, the rest still reads.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(LOG_FILENAME))
+            .unwrap();
+        file.write_all(b"30 persist.radio.foo tor").unwrap();
+        assert_eq!(values(at(0)), ["a", "b\nc"]);
+
+        assert!(history_of(&dir.join("missing"), "persist.radio.foo", at(0))
+            .unwrap()
+            .is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rotation_and_retention() {
+        let dir = temp_dir("rotation");
+        let record_len = encode("persist.radio.n", "0", at(1000)).len() as u64;
+        let config = HistoryConfig::new(&dir, ["persist.radio."])
+            .max_file_bytes(2 * record_len)
+            .max_files(2);
+        let mut history = History::open(config.clone()).unwrap();
+        for i in 0..7u64 {
+            history
+                .record("persist.radio.n", &i.to_string(), at(1000 + i))
+                .unwrap();
+        }
+        // Two records per file; with two rotated files, 0 and 1 are gone.
+        let kept: Vec<String> = history
+            .history_of("persist.radio.n", at(0))
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.value)
+            .collect();
+        assert_eq!(kept, ["2", "3", "4", "5", "6"]);
+        assert!(!rotated_path(&dir, 3).exists());
+
+        // Reopening with a smaller limit drops the excess; an age limit
+        // drops rotated files not written to within it.
+        drop(history);
+        History::open(config.clone().max_files(1)).unwrap();
+        assert!(!rotated_path(&dir, 2).exists());
+        assert!(rotated_path(&dir, 1).exists());
+        File::options()
+            .write(true)
+            .open(rotated_path(&dir, 1))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(7200))
+            .unwrap();
+        History::open(config.max_age(Duration::from_secs(3600))).unwrap();
+        assert!(!rotated_path(&dir, 1).exists());
+        assert!(dir.join(LOG_FILENAME).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
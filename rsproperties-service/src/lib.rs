@@ -7,22 +7,75 @@
 //! socket service, allowing for non-blocking property value reception and parsing.
 
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use rsactor::{Actor, ActorRef, ActorResult};
 
+pub mod access;
+pub mod control;
+pub mod history;
+pub mod persistent_properties;
+pub mod preload;
 pub mod properties_service;
+pub mod property_actor;
+pub mod rc_triggers;
+pub mod recording;
+pub mod runtime;
+pub mod set_listener;
 pub mod socket_service;
+pub mod transform;
 
-pub use socket_service::{SocketService, SocketServiceArgs};
+pub use socket_service::{
+    RestartPolicy, SocketService, SocketServiceArgs, SocketStats, TakeoverPolicy,
+};
 
-pub use properties_service::PropertiesService;
+pub use access::{AccessPolicy, PeerCredentials, PermissivePolicy};
+
+pub use control::{ControlAction, ControlHandler, ControlMessage, CONTROL_PREFIX};
+
+pub use history::{history_of, History, HistoryConfig, HistoryEntry};
+
+pub use persistent_properties::{
+    read_persistent_properties, PersistentProperties, PERSISTENT_PROPERTIES_READY_PROPERTY,
+};
+
+pub use preload::{PreloadEvent, PreloadProgress, PreloadSummary, RequiredPolicy};
+
+pub use properties_service::{PropertiesService, ServiceStats};
+
+pub use property_actor::{PropertyActor, PropertyActorArgs};
+
+pub use rc_triggers::{RcTriggerEngine, TriggerReport};
+
+pub use recording::{read_recording, replay, replay_with, Recorder, ReplaySpeed};
+
+pub use runtime::{ChildFailure, Restart, ServiceRuntime, ShutdownToken, TaskResult};
+
+pub use set_listener::{SetEvent, SetListener};
+
+pub use transform::{Transform, TransformChain};
 
 pub(crate) struct ReadyMessage;
 
+/// Property [`serve_until_signal`] increments on every SIGHUP, so
+/// processes waiting on it (`SystemProperties::wait`, `rsprops watch`)
+/// learn that the service reloaded.
+pub const RELOAD_COUNT_PROPERTY: &str = "sys.rsproperties.reload_count";
+
 #[derive(Clone)]
 pub(crate) struct PropertyMessage {
     pub name: String,
     pub value: String,
+    /// The socket client that sent the set, whose [`AccessPolicy`] the
+    /// store checks; `None` for the service's own sets and those made
+    /// through a [`PropertyActor`].
+    pub peer: Option<access::Peer>,
+}
+
+impl PropertyMessage {
+    pub fn credentials(&self) -> Option<PeerCredentials> {
+        self.peer.as_ref().and_then(|peer| peer.credentials)
+    }
 }
 
 // Mask `value` in `Debug` output so log-level captures don't spill
@@ -35,6 +88,7 @@ impl std::fmt::Debug for PropertyMessage {
         f.debug_struct("PropertyMessage")
             .field("name", &self.name)
             .field("value", &format_args!("<{} bytes>", self.value.len()))
+            .field("credentials", &self.credentials())
             .finish()
     }
 }
@@ -69,6 +123,177 @@ pub async fn run(
         ServiceContext<PropertiesService>,
     ),
     Box<dyn std::error::Error + Send + Sync>,
+> {
+    run_with_options(
+        config,
+        property_contexts_files,
+        build_prop_files,
+        ServiceOptions::default(),
+    )
+    .await
+}
+
+/// Service behavior beyond the directory configuration, for
+/// [`run_with_options`].
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ServiceOptions {
+    /// What to do when a socket path is served by a live process outside
+    /// the service lock (see [`TakeoverPolicy`]).
+    pub takeover: TakeoverPolicy,
+    /// How names received from clients are canonicalized before they are
+    /// stored. The default trims surrounding whitespace only.
+    pub name_policy: rsproperties::wire::NamePolicy,
+    /// How often the accept loop may recover from a panic (see
+    /// [`RestartPolicy`]).
+    pub restart_policy: RestartPolicy,
+    /// Rewrites applied to every set before it is stored (see
+    /// [`TransformChain`]). Empty by default.
+    pub transforms: TransformChain,
+    /// Where to keep an on-disk [`History`] of sets, if anywhere (the
+    /// default). [`run_tenants`] gives each tenant its own log in a
+    /// subdirectory of `dir` named after the tenant.
+    pub history: Option<HistoryConfig>,
+    /// Keep a serial per context area besides the global one, for stores
+    /// with many rapidly-changing properties: readers waiting with
+    /// `SystemProperties::wait_any_in` then only wake for changes to their
+    /// context. Off by default.
+    pub context_serials: bool,
+    /// Where to report the progress of the build.prop load (see
+    /// [`PreloadProgress`]), if anywhere (the default). [`run_tenants`]
+    /// reports every tenant's load to it, one after the other.
+    pub preload_progress: Option<PreloadProgress>,
+    /// Defaults for properties the build.prop files leave out, and the
+    /// properties that must be present before the service serves (see
+    /// `rsproperties::PropertyRequirement`). None by default.
+    pub requirements: Vec<rsproperties::PropertyRequirement>,
+    /// What a required property missing at bootstrap does: fail the
+    /// start (the default) or log and start anyway.
+    pub required_policy: RequiredPolicy,
+    /// Where to record every set for [`replay`], if anywhere (the default;
+    /// see [`Recorder`]). [`run_tenants`] gives each tenant its own file,
+    /// named after this one with `.<tenant>` appended.
+    pub record: Option<PathBuf>,
+    /// Where to keep the `persist.` properties across restarts, if
+    /// anywhere (the default; see [`PersistentProperties`]). Android keeps
+    /// them in `/data/property/persistent_properties`. [`run_tenants`]
+    /// gives each tenant its own file, named after this one with
+    /// `.<tenant>` appended.
+    pub persistent_properties: Option<PathBuf>,
+    /// Where sets of `ctl.` properties go instead of the store, if
+    /// anywhere (see [`ControlHandler`]); by default they are stored.
+    /// [`run_tenants`] hands every tenant's to it.
+    pub control_handler: Option<ControlHandler>,
+    /// Where every stored set is forwarded, if anywhere (the default; see
+    /// [`SetListener`]). [`run_tenants`] hands every tenant's to it.
+    pub set_listener: Option<SetListener>,
+    /// Which clients may write which properties (see [`AccessPolicy`]);
+    /// by default every client may write every property.
+    pub access_policy: Option<Arc<dyn AccessPolicy>>,
+}
+
+impl ServiceOptions {
+    /// Sets the takeover policy.
+    pub fn takeover(mut self, policy: TakeoverPolicy) -> Self {
+        self.takeover = policy;
+        self
+    }
+
+    /// Sets the name canonicalization policy.
+    pub fn name_policy(mut self, policy: rsproperties::wire::NamePolicy) -> Self {
+        self.name_policy = policy;
+        self
+    }
+
+    /// Sets the accept-loop panic recovery policy.
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Sets the rewrites applied to incoming sets.
+    pub fn transforms(mut self, transforms: TransformChain) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Sets the on-disk history of sets.
+    pub fn history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(config);
+        self
+    }
+
+    /// Sets whether per-context serials are kept.
+    pub fn context_serials(mut self, enable: bool) -> Self {
+        self.context_serials = enable;
+        self
+    }
+
+    /// Sets the callback receiving the build.prop load progress.
+    pub fn preload_progress(mut self, f: impl FnMut(PreloadEvent<'_>) + Send + 'static) -> Self {
+        self.preload_progress = Some(PreloadProgress::new(f));
+        self
+    }
+
+    /// Sets the bootstrap defaults and required properties.
+    pub fn requirements(mut self, requirements: Vec<rsproperties::PropertyRequirement>) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    /// Sets what a missing required property does.
+    pub fn required_policy(mut self, policy: RequiredPolicy) -> Self {
+        self.required_policy = policy;
+        self
+    }
+
+    /// Sets the file every set is recorded to.
+    pub fn record(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record = Some(path.into());
+        self
+    }
+
+    /// Sets the file the `persist.` properties are kept in.
+    pub fn persistent_properties(mut self, path: impl Into<PathBuf>) -> Self {
+        self.persistent_properties = Some(path.into());
+        self
+    }
+
+    /// Sets the handler of control properties.
+    pub fn control_handler(mut self, handler: ControlHandler) -> Self {
+        self.control_handler = Some(handler);
+        self
+    }
+
+    /// Sets the listener receiving every stored set.
+    pub fn set_listener(mut self, listener: SetListener) -> Self {
+        self.set_listener = Some(listener);
+        self
+    }
+
+    /// Sets the policy deciding which clients may write which properties.
+    pub fn access_policy(mut self, policy: impl AccessPolicy + 'static) -> Self {
+        self.access_policy = Some(Arc::new(policy));
+        self
+    }
+}
+
+/// [`run`] with explicit [`ServiceOptions`].
+///
+/// The socket directory is owned through a lock file holding the service's
+/// PID: a second service on the same directory fails with
+/// `Error::Lock` regardless of the takeover policy.
+pub async fn run_with_options(
+    config: rsproperties::PropertyConfig,
+    property_contexts_files: Vec<PathBuf>,
+    build_prop_files: Vec<PathBuf>,
+    options: ServiceOptions,
+) -> Result<
+    (
+        ServiceContext<SocketService>,
+        ServiceContext<PropertiesService>,
+    ),
+    Box<dyn std::error::Error + Send + Sync>,
 > {
     // Use `try_init` rather than `init`: if the global properties_dir /
     // socket_dir cells were already committed (e.g. earlier service
@@ -79,13 +304,60 @@ pub async fn run(
     // to wrong paths.
     rsproperties::try_init(config)?;
 
-    let properties_service = properties_service::run(property_contexts_files, build_prop_files);
+    let mut properties_args =
+        properties_service::PropertiesServiceArgs::new(property_contexts_files, build_prop_files)
+            .with_name_policy(options.name_policy.clone())
+            .with_transforms(options.transforms.clone())
+            .with_context_serials(options.context_serials)
+            .with_requirements(options.requirements.clone(), options.required_policy);
+    if let Some(history) = &options.history {
+        properties_args = properties_args.with_history(history.clone());
+    }
+    if let Some(progress) = &options.preload_progress {
+        properties_args = properties_args.with_preload_progress(progress.clone());
+    }
+    if let Some(path) = &options.record {
+        properties_args = properties_args.with_recording(path.clone());
+    }
+    if let Some(path) = &options.persistent_properties {
+        properties_args = properties_args.with_persistent_properties(path.clone());
+    }
+    if let Some(handler) = &options.control_handler {
+        properties_args = properties_args.with_control_handler(handler.clone());
+    }
+    if let Some(listener) = &options.set_listener {
+        properties_args = properties_args.with_set_listener(listener.clone());
+    }
+    start(
+        properties_args,
+        rsproperties::socket_dir().to_path_buf(),
+        &options,
+    )
+    .await
+}
+
+/// Spawns one properties/socket actor pair and waits until it serves.
+async fn start(
+    properties_args: properties_service::PropertiesServiceArgs,
+    socket_dir: PathBuf,
+    options: &ServiceOptions,
+) -> Result<
+    (
+        ServiceContext<SocketService>,
+        ServiceContext<PropertiesService>,
+    ),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let properties_service = properties_service::run_with_args(properties_args);
 
     // Initialize the socket service
-    let socket_service = socket_service::run(SocketServiceArgs {
-        socket_dir: rsproperties::socket_dir().to_path_buf(),
-        properties_service: properties_service.actor_ref.clone(),
-    });
+    let mut socket_args = SocketServiceArgs::new(socket_dir, properties_service.actor_ref.clone())
+        .with_takeover(options.takeover)
+        .with_restart_policy(options.restart_policy);
+    if let Some(policy) = &options.access_policy {
+        socket_args = socket_args.with_access_policy(policy.clone());
+    }
+    let socket_service = socket_service::run(socket_args);
 
     // Sequential readiness checks (not an eagerly-evaluated pair): if the
     // socket service already failed, waiting for the properties service's
@@ -101,12 +373,273 @@ pub async fn run(
     if let Err(e) = properties_service.actor_ref.ask(ReadyMessage).await {
         let _ = socket_service.actor_ref.stop().await;
         let _ = properties_service.actor_ref.stop().await;
+        // A failed initialisation only shows up here as a closed mailbox;
+        // the reason (a missing required property, say) is in the result.
+        if let Ok(result) = properties_service.join_handle.await {
+            if let Some(error) = result.error() {
+                return Err(format!("Failed to start properties service: {error}").into());
+            }
+        }
         return Err(format!("Failed to start properties service: {e}").into());
     }
 
+    // Published last, once both actors are up, so clients blocked in
+    // `rsproperties::wait_for_service` never observe half-populated areas.
+    let ready = properties_service
+        .actor_ref
+        .ask(PropertyMessage {
+            name: rsproperties::SERVICE_READY_PROPERTY.to_owned(),
+            value: "1".to_owned(),
+            peer: None,
+        })
+        .await;
+    if !matches!(ready, Ok(Ok(()))) {
+        let _ = socket_service.actor_ref.stop().await;
+        let _ = properties_service.actor_ref.stop().await;
+        return Err(format!("Failed to publish {}", rsproperties::SERVICE_READY_PROPERTY).into());
+    }
+
     Ok((socket_service, properties_service))
 }
 
+/// One property directory served by [`run_tenants`]: its areas, its
+/// sockets and the files its areas are built from.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TenantConfig {
+    /// Identifies the tenant in logs and in [`Tenant::name`]; unique
+    /// within a [`ServiceConfig`].
+    pub name: String,
+    /// Directory the tenant's property areas and info trie are written to.
+    pub properties_dir: PathBuf,
+    /// Directory of the tenant's `property_service` sockets. Clients reach
+    /// the tenant by connecting here.
+    pub socket_dir: PathBuf,
+    pub property_contexts_files: Vec<PathBuf>,
+    pub build_prop_files: Vec<PathBuf>,
+    /// File names of the tenant's areas; AOSP by default.
+    pub layout: rsproperties::Layout,
+}
+
+impl TenantConfig {
+    pub fn new(
+        name: impl Into<String>,
+        properties_dir: impl Into<PathBuf>,
+        socket_dir: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            properties_dir: properties_dir.into(),
+            socket_dir: socket_dir.into(),
+            property_contexts_files: Vec::new(),
+            build_prop_files: Vec::new(),
+            layout: rsproperties::Layout::default(),
+        }
+    }
+
+    /// Sets the `property_contexts` files the info trie is built from.
+    pub fn property_contexts_files(mut self, files: Vec<PathBuf>) -> Self {
+        self.property_contexts_files = files;
+        self
+    }
+
+    /// Sets the build.prop files loaded into the fresh areas.
+    pub fn build_prop_files(mut self, files: Vec<PathBuf>) -> Self {
+        self.build_prop_files = files;
+        self
+    }
+
+    /// Sets the area file names.
+    pub fn layout(mut self, layout: rsproperties::Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+
+/// Configuration of a service managing several independent property
+/// directories, for [`run_tenants`].
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ServiceConfig {
+    pub tenants: Vec<TenantConfig>,
+    /// Applied to every tenant.
+    pub options: ServiceOptions,
+}
+
+impl ServiceConfig {
+    /// Adds a tenant.
+    pub fn tenant(mut self, tenant: TenantConfig) -> Self {
+        self.tenants.push(tenant);
+        self
+    }
+
+    /// Sets the options shared by all tenants.
+    pub fn options(mut self, options: ServiceOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// A tenant started by [`run_tenants`].
+pub struct Tenant {
+    pub name: String,
+    pub socket_service: ServiceContext<SocketService>,
+    pub properties_service: ServiceContext<PropertiesService>,
+}
+
+/// What [`Tenant::stats`] reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TenantStats {
+    pub name: String,
+    pub service: ServiceStats,
+    pub socket: SocketStats,
+}
+
+impl Tenant {
+    /// Set counters of this tenant's properties service and panic and
+    /// timeout counters of its socket service.
+    pub async fn stats(&self) -> Result<TenantStats, rsactor::Error> {
+        let service = self
+            .properties_service
+            .actor_ref
+            .ask(properties_service::StatsMessage)
+            .await?;
+        let socket = self
+            .socket_service
+            .actor_ref
+            .ask(socket_service::StatsMessage)
+            .await?;
+        Ok(TenantStats {
+            name: self.name.clone(),
+            service,
+            socket,
+        })
+    }
+
+    /// Stops the socket service, draining its connections, then the
+    /// properties service.
+    pub async fn stop(self) {
+        let _ = self.socket_service.actor_ref.stop().await;
+        let _ = self.socket_service.join_handle.await;
+        let _ = self.properties_service.actor_ref.stop().await;
+        let _ = self.properties_service.join_handle.await;
+    }
+}
+
+/// Runs one properties/socket service pair per tenant in this process.
+///
+/// Unlike [`run`], nothing process-global is configured: each tenant is
+/// served from its own properties directory and socket directory, so a
+/// host can serve every container or VM guest from one process. Clients
+/// pick a tenant by the socket directory they connect to; readers open a
+/// tenant with `SystemProperties::open` on its properties directory.
+///
+/// Tenant names, properties directories and socket directories must be
+/// unique. If a tenant fails to start, the tenants already started are
+/// stopped and the error names the failing one.
+pub async fn run_tenants(
+    config: ServiceConfig,
+) -> Result<Vec<Tenant>, Box<dyn std::error::Error + Send + Sync>> {
+    for (i, tenant) in config.tenants.iter().enumerate() {
+        if let Some(other) = config.tenants[..i].iter().find(|other| {
+            other.name == tenant.name
+                || other.properties_dir == tenant.properties_dir
+                || other.socket_dir == tenant.socket_dir
+        }) {
+            return Err(format!(
+                "tenants '{}' and '{}' share a name or a directory",
+                other.name, tenant.name
+            )
+            .into());
+        }
+    }
+
+    let mut tenants: Vec<Tenant> = Vec::with_capacity(config.tenants.len());
+    for tenant in config.tenants {
+        let started = match std::fs::create_dir_all(&tenant.properties_dir) {
+            Ok(()) => {
+                let mut properties_args = properties_service::PropertiesServiceArgs::new(
+                    tenant.property_contexts_files,
+                    tenant.build_prop_files,
+                )
+                .with_name_policy(config.options.name_policy.clone())
+                .with_transforms(config.options.transforms.clone())
+                .with_context_serials(config.options.context_serials)
+                .with_requirements(
+                    config.options.requirements.clone(),
+                    config.options.required_policy,
+                )
+                .with_properties_dir(tenant.properties_dir, tenant.layout);
+                if let Some(history) = &config.options.history {
+                    let mut history = history.clone();
+                    history.dir = history.dir.join(&tenant.name);
+                    properties_args = properties_args.with_history(history);
+                }
+                if let Some(progress) = &config.options.preload_progress {
+                    properties_args = properties_args.with_preload_progress(progress.clone());
+                }
+                if let Some(path) = &config.options.record {
+                    properties_args =
+                        properties_args.with_recording(tenant_file(path, &tenant.name));
+                }
+                if let Some(path) = &config.options.persistent_properties {
+                    properties_args =
+                        properties_args.with_persistent_properties(tenant_file(path, &tenant.name));
+                }
+                if let Some(handler) = &config.options.control_handler {
+                    properties_args = properties_args.with_control_handler(handler.clone());
+                }
+                if let Some(listener) = &config.options.set_listener {
+                    properties_args = properties_args.with_set_listener(listener.clone());
+                }
+                start(properties_args, tenant.socket_dir, &config.options).await
+            }
+            Err(e) => Err(e.into()),
+        };
+        match started {
+            Ok((socket_service, properties_service)) => {
+                log::info!("Tenant '{}' started", tenant.name);
+                tenants.push(Tenant {
+                    name: tenant.name,
+                    socket_service,
+                    properties_service,
+                });
+            }
+            Err(e) => {
+                for started in tenants {
+                    started.stop().await;
+                }
+                return Err(format!("Failed to start tenant '{}': {e}", tenant.name).into());
+            }
+        }
+    }
+    Ok(tenants)
+}
+
+/// `path` with `.<tenant>` appended to its file name.
+fn tenant_file(path: &std::path::Path, tenant: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".{tenant}"));
+    path.with_file_name(file_name)
+}
+
+/// Serves until SIGTERM or SIGINT, then shuts down gracefully: the socket
+/// service stops accepting and drains the connections it already
+/// accepted, then the properties service stops.
+///
+/// Shorthand for [`ServiceRuntime::serve_until_signal`] on a runtime
+/// without tasks; see there for SIGHUP and failures.
+pub async fn serve_until_signal(
+    socket_service: ServiceContext<SocketService>,
+    properties_service: ServiceContext<PropertiesService>,
+    on_reload: impl FnMut(),
+) -> std::io::Result<()> {
+    ServiceRuntime::new(socket_service, properties_service)
+        .serve_until_signal(on_reload)
+        .await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -116,8 +649,16 @@ mod tests {
         let msg = PropertyMessage {
             name: "test.key".to_string(),
             value: "test.value".to_string(),
+            peer: Some(access::Peer {
+                credentials: Some(PeerCredentials::new(1000, 1000, Some(42))),
+                policy: Arc::new(PermissivePolicy),
+            }),
         };
         assert_eq!(msg.name, "test.key");
         assert_eq!(msg.value, "test.value");
+        assert_eq!(
+            msg.credentials().unwrap().to_string(),
+            "uid=1000 gid=1000 pid=42"
+        );
     }
 }
@@ -0,0 +1,311 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `persist.` properties kept across restarts of the property service,
+//! see [`PersistentProperties`].
+//!
+//! The file has the format of Android's
+//! `/data/property/persistent_properties`: a serialized
+//! `PersistentProperties` protobuf message,
+//!
+//! ```text
+//! message PersistentProperties {
+//!     repeated PersistentPropertyRecord properties = 1;
+//! }
+//! message PersistentPropertyRecord {
+//!     optional string name = 1;
+//!     optional string value = 2;
+//! }
+//! ```
+//!
+//! so a file pulled from a device loads here, and one written here loads
+//! on a device. Fields other than these are skipped when reading.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Names starting with this are persisted.
+pub const PERSISTENT_PROPERTY_PREFIX: &str = "persist.";
+
+/// Set to `true` once the persisted values are loaded, as Android's init
+/// does; a client that reads `persist.` properties at boot waits for it.
+pub const PERSISTENT_PROPERTIES_READY_PROPERTY: &str = "ro.persistent_properties.ready";
+
+/// Whether sets of `name` are persisted.
+pub fn is_persistent(name: &str) -> bool {
+    name.starts_with(PERSISTENT_PROPERTY_PREFIX)
+}
+
+/// The `persist.` properties stored in one file, rewritten on every
+/// change so a set the service acknowledged survives a restart.
+///
+/// The service keeps one when [`crate::ServiceOptions::persistent_properties`]
+/// is set: at startup it applies the stored values over the build.prop
+/// ones, and then stores every `persist.` set. Each change writes the
+/// whole file to `<path>.tmp`, syncs it and renames it over `path`, so a
+/// crash leaves either the old file or the new one. The file holds
+/// values, so it is created with mode 0600. One writer per file.
+#[derive(Debug)]
+pub struct PersistentProperties {
+    path: PathBuf,
+    values: BTreeMap<String, String>,
+}
+
+impl PersistentProperties {
+    /// Loads the properties stored at `path`; a missing file holds none.
+    /// Fails with [`io::ErrorKind::InvalidData`] for a file that is not a
+    /// `PersistentProperties` message.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let values = read_persistent_properties(&path)?;
+        Ok(Self { path, values })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The stored properties, by name.
+    pub fn values(&self) -> &BTreeMap<String, String> {
+        &self.values
+    }
+
+    /// Stores `name` with `value`, rewriting the file unless the value is
+    /// already stored. Names without the `persist.` prefix are ignored.
+    pub fn set(&mut self, name: &str, value: &str) -> io::Result<()> {
+        if !is_persistent(name) || self.values.get(name).is_some_and(|stored| stored == value) {
+            return Ok(());
+        }
+        let previous = self.values.insert(name.to_owned(), value.to_owned());
+        if let Err(e) = self.write() {
+            // Keep the map what the file holds.
+            match previous {
+                Some(previous) => self.values.insert(name.to_owned(), previous),
+                None => self.values.remove(name),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Forgets `name`, rewriting the file if it was stored.
+    pub fn remove(&mut self, name: &str) -> io::Result<()> {
+        let Some(previous) = self.values.remove(name) else {
+            return Ok(());
+        };
+        if let Err(e) = self.write() {
+            self.values.insert(name.to_owned(), previous);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn write(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp = self.path.with_file_name(tmp_name);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        file.write_all(&encode(&self.values))?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        // The rename itself is only durable once the directory is synced.
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// The properties stored at `path`, by name; empty when the file does not
+/// exist. Records without the `persist.` prefix are dropped. Fails with
+/// [`io::ErrorKind::InvalidData`] for a file that is not a
+/// `PersistentProperties` message.
+pub fn read_persistent_properties(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    let mut bytes = Vec::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_end(&mut bytes)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let records = decode(&bytes).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{path:?} is not a persistent properties file"),
+        )
+    })?;
+    let mut values = BTreeMap::new();
+    for (name, value) in records {
+        if is_persistent(&name) {
+            values.insert(name, value);
+        } else {
+            log::warn!("Ignoring non-persistent property {name} in {path:?}");
+        }
+    }
+    Ok(values)
+}
+
+/// Field 1 or 2 with the length-delimited wire type.
+const NAME_TAG: u8 = 1 << 3 | 2;
+const VALUE_TAG: u8 = 2 << 3 | 2;
+const RECORD_TAG: u8 = NAME_TAG;
+
+fn encode(values: &BTreeMap<String, String>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut record = Vec::new();
+    for (name, value) in values {
+        record.clear();
+        put_field(&mut record, NAME_TAG, name.as_bytes());
+        put_field(&mut record, VALUE_TAG, value.as_bytes());
+        put_field(&mut out, RECORD_TAG, &record);
+    }
+    out
+}
+
+fn put_field(out: &mut Vec<u8>, tag: u8, bytes: &[u8]) {
+    out.push(tag);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn put_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// The `(name, value)` records of a serialized message, or `None` if it
+/// is malformed. A record without a name is dropped; one without a value
+/// has the empty value, as protobuf defaults it.
+fn decode(mut bytes: &[u8]) -> Option<Vec<(String, String)>> {
+    let mut records = Vec::new();
+    while !bytes.is_empty() {
+        let (field, payload) = next_field(&mut bytes)?;
+        let (1, Some(mut record)) = (field, payload) else {
+            continue;
+        };
+        let (mut name, mut value) = (None, String::new());
+        while !record.is_empty() {
+            match next_field(&mut record)? {
+                (1, Some(bytes)) => name = Some(String::from_utf8(bytes.to_vec()).ok()?),
+                (2, Some(bytes)) => value = String::from_utf8(bytes.to_vec()).ok()?,
+                _ => {}
+            }
+        }
+        if let Some(name) = name {
+            records.push((name, value));
+        }
+    }
+    Some(records)
+}
+
+/// Splits the next field off `bytes`: its number and, for the
+/// length-delimited wire type, its payload. Other wire types are skipped.
+fn next_field<'a>(bytes: &mut &'a [u8]) -> Option<(u64, Option<&'a [u8]>)> {
+    let key = next_varint(bytes)?;
+    let skip = match key & 7 {
+        0 => {
+            next_varint(bytes)?;
+            0
+        }
+        1 => 8,
+        2 => {
+            let len = usize::try_from(next_varint(bytes)?).ok()?;
+            let payload = bytes.get(..len)?;
+            *bytes = &bytes[len..];
+            return Some((key >> 3, Some(payload)));
+        }
+        5 => 4,
+        _ => return None,
+    };
+    *bytes = bytes.get(skip..)?;
+    Some((key >> 3, None))
+}
+
+fn next_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        n |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let values = BTreeMap::from([
+            ("persist.a".to_owned(), "1".to_owned()),
+            ("persist.long".to_owned(), "x".repeat(300)),
+            ("persist.empty".to_owned(), String::new()),
+        ]);
+        let decoded: BTreeMap<_, _> = decode(&encode(&values)).unwrap().into_iter().collect();
+        assert_eq!(decoded, values);
+
+        // Bytes as protoc serializes {name: "persist.x" value: "y"}, with
+        // an unknown varint field in the record.
+        let mut bytes = vec![0x0a, 16, 0x0a, 9];
+        bytes.extend_from_slice(b"persist.x");
+        bytes.extend_from_slice(&[0x12, 1, b'y', 0x18, 0x2a]);
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            [("persist.x".to_owned(), "y".to_owned())]
+        );
+
+        assert!(decode(&[0x0a, 5, 0x0a]).is_none());
+        assert!(decode(&[0x0f]).is_none());
+        assert!(decode(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_survives_reopen() {
+        let dir = std::env::temp_dir().join(format!("rsprops_persist_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("persistent_properties");
+
+        let mut stored = PersistentProperties::open(&path).unwrap();
+        assert!(stored.values().is_empty());
+        stored.set("persist.sys.locale", "ko-KR").unwrap();
+        stored.set("persist.sys.timezone", "Asia/Seoul").unwrap();
+        stored.set("persist.sys.locale", "en-US").unwrap();
+        stored.set("sys.not_persisted", "1").unwrap();
+        stored.set("persist.sys.removed", "1").unwrap();
+        stored.remove("persist.sys.removed").unwrap();
+        stored.remove("persist.sys.never_set").unwrap();
+
+        let reopened = PersistentProperties::open(&path).unwrap();
+        assert_eq!(
+            reopened.values(),
+            &BTreeMap::from([
+                ("persist.sys.locale".to_owned(), "en-US".to_owned()),
+                ("persist.sys.timezone".to_owned(), "Asia/Seoul".to_owned()),
+            ])
+        );
+        assert!(!dir.join("persistent_properties.tmp").exists());
+
+        std::fs::write(&path, b"not a message\xff").unwrap();
+        let error = PersistentProperties::open(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
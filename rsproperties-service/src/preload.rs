@@ -0,0 +1,118 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The build.prop load a property service runs before it serves: its
+//! progress, see [`PreloadProgress`], and what a missing required
+//! property does, see [`RequiredPolicy`].
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rsproperties::RequirementReport;
+
+/// One step of the build.prop load, reported to a [`PreloadProgress`].
+///
+/// Events carry property names but never values. A load ends with
+/// [`PreloadEvent::Finished`], unless a file cannot be read, a required
+/// property is missing or an entry cannot be stored, which fails the
+/// service start after the event naming it.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum PreloadEvent<'a> {
+    /// File `index` of `total` (counting from 0) is about to be parsed.
+    FileStarted {
+        path: &'a Path,
+        index: usize,
+        total: usize,
+    },
+    /// A file and its imports were parsed. `properties` counts the
+    /// distinct names collected so far, this file's and the earlier ones'.
+    FileParsed {
+        path: &'a Path,
+        properties: usize,
+        elapsed: Duration,
+    },
+    /// A file could not be read; the load stops here.
+    FileFailed {
+        path: &'a Path,
+        error: &'a rsproperties::Error,
+    },
+    /// A property was added to the store.
+    Added { name: &'a str },
+    /// A property was skipped: no `property_contexts` entry declares it
+    /// and the service requires one.
+    Skipped {
+        name: &'a str,
+        error: &'a rsproperties::Error,
+    },
+    /// A property could not be stored; the load stops here.
+    Failed {
+        name: &'a str,
+        error: &'a rsproperties::Error,
+    },
+    /// The files were parsed and the requirements checked against them,
+    /// before any property is stored. With [`RequiredPolicy::Fail`] and a
+    /// required property missing, the load stops here.
+    Requirements(&'a RequirementReport),
+    /// Every file was parsed and every property applied.
+    Finished(PreloadSummary),
+}
+
+/// What the property service does when a required property (see
+/// `rsproperties::PropertyRequirement`) is missing from its build.prop
+/// files and has no default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequiredPolicy {
+    /// Fail the service start, naming the missing properties.
+    #[default]
+    Fail,
+    /// Log the missing properties and start anyway.
+    Warn,
+}
+
+/// Totals of a finished build.prop load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PreloadSummary {
+    /// build.prop files parsed, not counting their imports.
+    pub files: usize,
+    /// Properties added to the store.
+    pub added: usize,
+    /// Properties skipped as undeclared.
+    pub skipped: usize,
+    /// Time spent parsing the files.
+    pub parse_time: Duration,
+    /// Time spent storing the properties.
+    pub apply_time: Duration,
+}
+
+/// Callback receiving the [`PreloadEvent`]s of the build.prop load, so an
+/// embedder can show boot progress and log which entries did not apply.
+///
+/// It runs on the blocking task that initializes the store, before the
+/// service serves, so it should return quickly. Clones share the callback.
+#[derive(Clone)]
+pub struct PreloadProgress(Arc<Mutex<PreloadFn>>);
+
+type PreloadFn = dyn FnMut(PreloadEvent<'_>) + Send;
+
+impl PreloadProgress {
+    /// Wraps `f`.
+    pub fn new(f: impl FnMut(PreloadEvent<'_>) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(f)))
+    }
+
+    pub(crate) fn report(&self, event: PreloadEvent<'_>) {
+        // A panic in an earlier call poisons the lock; keep reporting.
+        let mut f = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        f(event);
+    }
+}
+
+impl std::fmt::Debug for PreloadProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PreloadProgress(..)")
+    }
+}
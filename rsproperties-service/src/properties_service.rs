@@ -2,13 +2,44 @@ use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
 
 use rsactor::{Actor, ActorRef, ActorWeak};
-use rsproperties::{build_trie, load_properties_from_file, PropertyInfoEntry, SystemProperties};
+use rsproperties::wire::NamePolicy;
+use rsproperties::{
+    apply_requirements, build_trie, load_properties_from_file, Backing, Layout, PropertyInfoEntry,
+    PropertyRequirement, SystemProperties,
+};
+
+use crate::access::{Peer, PeerCredentials};
+use crate::control::{ControlHandler, ControlMessage};
+use crate::history::{History, HistoryConfig};
+use crate::persistent_properties::{
+    is_persistent, PersistentProperties, PERSISTENT_PROPERTIES_READY_PROPERTY,
+};
+use crate::preload::{PreloadEvent, PreloadProgress, PreloadSummary, RequiredPolicy};
+use crate::recording::Recorder;
+use crate::set_listener::{SetEvent, SetListener};
+use crate::transform::TransformChain;
 
 pub struct PropertiesServiceArgs {
     property_contexts_files: Vec<PathBuf>,
     build_prop_files: Vec<PathBuf>,
+    name_policy: NamePolicy,
+    transforms: TransformChain,
+    properties_dir: Option<(PathBuf, Layout)>,
+    backing: Option<Backing>,
+    require_declared: Option<bool>,
+    read_only_prefixes: Option<Vec<String>>,
+    history: Option<HistoryConfig>,
+    context_serials: bool,
+    preload_progress: Option<PreloadProgress>,
+    requirements: Vec<PropertyRequirement>,
+    required_policy: RequiredPolicy,
+    recording: Option<PathBuf>,
+    persistent_properties: Option<PathBuf>,
+    control_handler: Option<ControlHandler>,
+    set_listener: Option<SetListener>,
 }
 
 impl PropertiesServiceArgs {
@@ -19,12 +50,212 @@ impl PropertiesServiceArgs {
         Self {
             property_contexts_files,
             build_prop_files,
+            name_policy: NamePolicy::default(),
+            transforms: TransformChain::default(),
+            properties_dir: None,
+            backing: None,
+            require_declared: None,
+            read_only_prefixes: None,
+            history: None,
+            context_serials: false,
+            preload_progress: None,
+            requirements: Vec::new(),
+            required_policy: RequiredPolicy::default(),
+            recording: None,
+            persistent_properties: None,
+            control_handler: None,
+            set_listener: None,
         }
     }
+
+    /// Sets how names received from clients are canonicalized before they
+    /// are stored (see [`rsproperties::wire::canonicalize_name_with`]).
+    pub fn with_name_policy(mut self, policy: NamePolicy) -> Self {
+        self.name_policy = policy;
+        self
+    }
+
+    /// Sets the rewrites applied to each set after its name is
+    /// canonicalized and before it is validated and stored.
+    pub fn with_transforms(mut self, transforms: TransformChain) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Serves the areas in `dir`, written with `layout`, instead of the
+    /// process-global `rsproperties::properties_dir()` and
+    /// `rsproperties::layout()` — for services managing several property
+    /// directories (see [`crate::run_tenants`]).
+    pub fn with_properties_dir(mut self, dir: impl Into<PathBuf>, layout: Layout) -> Self {
+        self.properties_dir = Some((dir.into(), layout));
+        self
+    }
+
+    /// Keeps the areas in files or in memfds handed out over the socket,
+    /// instead of the process-global `rsproperties::backing()`. With
+    /// [`Backing::Memfd`] nothing is written to the properties directory.
+    pub fn with_backing(mut self, backing: Backing) -> Self {
+        self.backing = Some(backing);
+        self
+    }
+
+    /// Refuses sets of properties no `property_contexts` entry declares
+    /// (with `PROP_ERROR_PERMISSION_DENIED`), instead of following the
+    /// process-global `rsproperties::require_declared()`. Undeclared
+    /// entries of the build.prop files are skipped, as AOSP init does.
+    pub fn with_require_declared(mut self, require: bool) -> Self {
+        self.require_declared = Some(require);
+        self
+    }
+
+    /// Refuses client sets that would change a property under any of
+    /// `prefixes` (with `PROP_ERROR_READ_ONLY_PROPERTY`), as for `ro.`
+    /// ones, instead of following the process-global
+    /// `rsproperties::read_only_prefixes()`. The build.prop files still
+    /// populate them.
+    pub fn with_read_only_prefixes<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.read_only_prefixes = Some(prefixes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Records the sets stored under the configured prefixes in an
+    /// on-disk [`History`].
+    pub fn with_history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(config);
+        self
+    }
+
+    /// Keeps a serial per context area (see
+    /// `SystemProperties::enable_context_serials`), so readers using
+    /// `wait_any_in` are only woken by changes to their context.
+    pub fn with_context_serials(mut self, enable: bool) -> Self {
+        self.context_serials = enable;
+        self
+    }
+
+    /// Reports the progress of the build.prop load, and each entry that
+    /// was skipped or failed, to `progress`.
+    pub fn with_preload_progress(mut self, progress: PreloadProgress) -> Self {
+        self.preload_progress = Some(progress);
+        self
+    }
+
+    /// Sets the defaults of the build.prop properties the files leave
+    /// out, and which properties must be present, with `policy` deciding
+    /// what a missing one does.
+    pub fn with_requirements(
+        mut self,
+        requirements: Vec<PropertyRequirement>,
+        policy: RequiredPolicy,
+    ) -> Self {
+        self.requirements = requirements;
+        self.required_policy = policy;
+        self
+    }
+
+    /// Records every set the service stores to a [`Recorder`] at `path`.
+    pub fn with_recording(mut self, path: impl Into<PathBuf>) -> Self {
+        self.recording = Some(path.into());
+        self
+    }
+
+    /// Keeps the `persist.` properties in a [`PersistentProperties`] file
+    /// at `path`: its values are applied over the build.prop ones at
+    /// startup, and every `persist.` set the service stores is written
+    /// to it.
+    pub fn with_persistent_properties(mut self, path: impl Into<PathBuf>) -> Self {
+        self.persistent_properties = Some(path.into());
+        self
+    }
+
+    /// Hands sets of `ctl.` properties to `handler` instead of storing
+    /// them (see [`ControlHandler`]).
+    pub fn with_control_handler(mut self, handler: ControlHandler) -> Self {
+        self.control_handler = Some(handler);
+        self
+    }
+
+    /// Forwards every set the service stores to `listener` (see
+    /// [`SetListener`]).
+    pub fn with_set_listener(mut self, listener: SetListener) -> Self {
+        self.set_listener = Some(listener);
+        self
+    }
+}
+
+/// Counters of the sets a [`PropertiesService`] handled since it started,
+/// including the ones the service issues itself
+/// (`rsproperties::SERVICE_READY_PROPERTY`,
+/// [`crate::RELOAD_COUNT_PROPERTY`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ServiceStats {
+    /// Sets and removals that were stored.
+    pub applied: u64,
+    /// Sets and removals that were refused: bad names or values, `ro.`
+    /// rewrites, full areas, control messages the [`ControlHandler`]
+    /// failed.
+    pub rejected: u64,
+    /// Control messages the [`ControlHandler`] accepted.
+    pub control: u64,
+    /// Sets and removals the socket's [`crate::AccessPolicy`] refused.
+    pub denied: u64,
+}
+
+pub(crate) struct StatsMessage;
+
+/// Asks for the memfds of a [`Backing::Memfd`] store, to hand to a client.
+pub(crate) struct MemfdAreasMessage;
+
+/// Reads one property from the store; see `property_actor::Get`.
+pub(crate) struct GetMessage {
+    pub name: String,
+}
+
+/// Snapshots the store; see `property_actor::List`.
+pub(crate) struct FreezeMessage;
+
+/// Removes one property from the store, for the socket's UNSETPROP; see
+/// [`rsproperties::wire::PROP_MSG_UNSETPROP`].
+pub(crate) struct UnsetMessage {
+    pub name: String,
+    pub peer: Peer,
+}
+
+/// Applies several sets in one turn, so no other set lands between them;
+/// for the socket's [`rsproperties::wire::PROP_MSG_SETPROP_BATCH`].
+pub(crate) struct BatchMessage {
+    pub sets: Vec<crate::PropertyMessage>,
 }
 
 pub struct PropertiesService {
     system_properties: SystemProperties,
+    pipeline: Pipeline,
+}
+
+/// Everything a write goes through besides the store: the name policy and
+/// transforms before it, the persistence, history and listener after it.
+/// Kept apart from the store so a batch can hold the store in
+/// [`SystemProperties::batch`] while each set still reaches all of these.
+struct Pipeline {
+    name_policy: NamePolicy,
+    transforms: TransformChain,
+    history: Option<History>,
+    recorder: Option<Recorder>,
+    persistent: Option<PersistentProperties>,
+    control_handler: Option<ControlHandler>,
+    set_listener: Option<SetListener>,
+    stats: ServiceStats,
+}
+
+/// What [`Pipeline::apply`] did with an accepted set.
+enum Applied {
+    Stored,
+    Dispatched,
 }
 
 /// Wrap any error implementing the standard `Error` trait into an
@@ -38,8 +269,10 @@ where
 }
 
 /// Synchronous initialisation: parses property_contexts files, writes the
-/// trie to `property_info`, loads build.prop files, and applies them to a
-/// freshly-mapped `SystemProperties` area.
+/// trie to `property_info`, loads build.prop files, checks them against
+/// the requirements, and applies them to a
+/// freshly-mapped `SystemProperties` area. With [`Backing::Memfd`] the trie
+/// and the areas go to new memfds instead and `dir` is not touched.
 ///
 /// Kept synchronous on purpose — every step is blocking I/O against the
 /// filesystem and we don't want to scatter `spawn_blocking` calls through
@@ -51,10 +284,18 @@ where
 /// the trie) is deterministic across runs. Which *file* wins a key
 /// conflict is already deterministic — `load_properties_from_file`
 /// overwrites in call order — the map only fixes the apply order.
+#[allow(clippy::too_many_arguments)]
 fn init_system_properties_sync(
     property_contexts_files: Vec<PathBuf>,
     build_prop_files: Vec<PathBuf>,
     dir: &Path,
+    layout: &Layout,
+    backing: Backing,
+    require_declared: bool,
+    read_only_prefixes: Option<Vec<String>>,
+    progress: Option<PreloadProgress>,
+    requirements: &[PropertyRequirement],
+    required_policy: RequiredPolicy,
 ) -> std::io::Result<SystemProperties> {
     let mut property_infos = Vec::new();
     for file in property_contexts_files {
@@ -69,33 +310,174 @@ fn init_system_properties_sync(
     let data: Vec<u8> =
         build_trie(&property_infos, "u:object_r:build_prop:s0", "string").map_err(io_other)?;
 
-    File::create(dir.join("property_info"))?.write_all(&data)?;
+    if backing == Backing::Files {
+        File::create(layout.property_info_path(dir))?.write_all(&data)?;
+    }
 
     // `load_properties_from_file` only accepts `&mut HashMap` (other
     // callers depend on that signature). Re-collect into a `BTreeMap`
     // before the apply loop so the iteration order is fully determined
     // by the keys, not by HashMap's randomised hash seed.
+    let report = |event: PreloadEvent<'_>| {
+        if let Some(progress) = &progress {
+            progress.report(event);
+        }
+    };
+    let mut summary = PreloadSummary {
+        files: build_prop_files.len(),
+        ..PreloadSummary::default()
+    };
     let mut properties_unordered: HashMap<String, String> = HashMap::new();
-    for file in build_prop_files {
-        load_properties_from_file(&file, None, "u:r:init:s0", &mut properties_unordered)
-            .map_err(io_other)?;
+    for (index, path) in build_prop_files.iter().enumerate() {
+        report(PreloadEvent::FileStarted {
+            path,
+            index,
+            total: summary.files,
+        });
+        let started = Instant::now();
+        if let Err(error) =
+            load_properties_from_file(path, None, "u:r:init:s0", &mut properties_unordered)
+        {
+            report(PreloadEvent::FileFailed {
+                path,
+                error: &error,
+            });
+            return Err(io_other(error));
+        }
+        let elapsed = started.elapsed();
+        summary.parse_time += elapsed;
+        report(PreloadEvent::FileParsed {
+            path,
+            properties: properties_unordered.len(),
+            elapsed,
+        });
     }
-    let properties: BTreeMap<String, String> = properties_unordered.into_iter().collect();
+    let mut properties: BTreeMap<String, String> = properties_unordered.into_iter().collect();
 
-    let mut system_properties = SystemProperties::new_area(dir).map_err(io_other)?;
+    // Before the area exists: a boot missing a required property fails
+    // without leaving a half-populated store behind.
+    if !requirements.is_empty() {
+        let requirement_report = apply_requirements(&mut properties, requirements);
+        report(PreloadEvent::Requirements(&requirement_report));
+        for name in &requirement_report.defaulted {
+            log::info!("{name} is not in the build.prop files; using its default");
+        }
+        if !requirement_report.is_satisfied() {
+            let missing = requirement_report.missing.join(", ");
+            match required_policy {
+                RequiredPolicy::Warn => log::warn!("Required properties missing: {missing}"),
+                RequiredPolicy::Fail => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("required properties missing: {missing}"),
+                    ))
+                }
+            }
+        }
+    }
+
+    let mut system_properties = match backing {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Backing::Memfd => SystemProperties::new_memfd_area(&data, layout),
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        Backing::Memfd => Err(Error::InvalidArgument(
+            "memfd-backed property stores are only supported on Linux and Android".into(),
+        )),
+        _ => SystemProperties::new_area_with_layout(dir, layout),
+    }
+    .map_err(io_other)?;
+    system_properties.set_require_declared(require_declared);
+    if let Some(prefixes) = read_only_prefixes {
+        system_properties.set_read_only_prefixes(prefixes);
+    }
     // `new_area` starts from a freshly-recreated, empty area and the
     // BTreeMap keys are unique, so every key is new — `add` alone covers
     // the loop. (The previous `find → update` branch was unreachable; had
     // it ever been reached, `update` would have rejected the `ro.` keys
     // that dominate build.prop files and killed the whole init.)
-    for (key, value) in properties.iter() {
-        system_properties
-            .add(key.as_str(), value.as_str())
-            .map_err(io_other)?;
-    }
+    // One batch: waiters are woken once for the whole load, not twice
+    // per key. The service owns the read-only namespaces, so the load
+    // runs with them writable.
+    let started = Instant::now();
+    system_properties
+        .override_read_only(|props| {
+            props.batch(|props| {
+                properties.iter().try_for_each(|(key, value)| {
+                    let name = key.as_str();
+                    match props.add(name, value.as_str()) {
+                        Ok(()) => {
+                            summary.added += 1;
+                            report(PreloadEvent::Added { name });
+                            Ok(())
+                        }
+                        // Already logged; one stray entry must not fail the boot.
+                        Err(error @ Error::Undeclared { .. }) => {
+                            summary.skipped += 1;
+                            report(PreloadEvent::Skipped {
+                                name,
+                                error: &error,
+                            });
+                            Ok(())
+                        }
+                        Err(error) => {
+                            report(PreloadEvent::Failed {
+                                name,
+                                error: &error,
+                            });
+                            Err(error)
+                        }
+                    }
+                })
+            })
+        })
+        .map_err(io_other)?;
+    summary.apply_time = started.elapsed();
+    report(PreloadEvent::Finished(summary));
     Ok(system_properties)
 }
 
+/// Opens the persistent properties at `path` and applies them to
+/// `system_properties`, then sets [`PERSISTENT_PROPERTIES_READY_PROPERTY`].
+///
+/// A file that cannot be parsed is moved aside to `<path>.corrupt` and the
+/// service starts without persisted values, as Android's init does rather
+/// than failing the boot; a value the store refuses (an undeclared name,
+/// say) is skipped.
+fn load_persistent_properties(
+    system_properties: &mut SystemProperties,
+    path: PathBuf,
+) -> std::io::Result<PersistentProperties> {
+    let persistent = match PersistentProperties::open(&path) {
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+            let mut aside = path.file_name().unwrap_or_default().to_owned();
+            aside.push(".corrupt");
+            let aside = path.with_file_name(aside);
+            log::error!("{e}; moving it to {aside:?} and starting without persisted values");
+            std::fs::rename(&path, &aside)?;
+            PersistentProperties::open(path)?
+        }
+        result => result?,
+    };
+    system_properties
+        .override_read_only(|props| {
+            props.batch(|props| {
+                for (name, value) in persistent.values() {
+                    if let Err(e) = props.set(name, value) {
+                        log::warn!("Skipping persisted property {name}: {e}");
+                    }
+                }
+                props.set(PERSISTENT_PROPERTIES_READY_PROPERTY, "true")
+            })
+        })
+        .map_err(io_other)?;
+    log::info!(
+        "Loaded {} persistent properties from {:?}",
+        persistent.values().len(),
+        persistent.path()
+    );
+    Ok(persistent)
+}
+
 impl Actor for PropertiesService {
     type Args = PropertiesServiceArgs;
     type Error = std::io::Error;
@@ -108,18 +490,69 @@ impl Actor for PropertiesService {
         args: Self::Args,
         _actor_ref: &rsactor::ActorRef<Self>,
     ) -> std::result::Result<Self, Self::Error> {
-        let dir = rsproperties::properties_dir().to_path_buf();
+        // Without an explicit directory: the same directory and layout the
+        // in-process readers latched, so the files written here are the
+        // ones they open.
+        let (dir, layout) = args.properties_dir.unwrap_or_else(|| {
+            (
+                rsproperties::properties_dir().to_path_buf(),
+                rsproperties::layout().clone(),
+            )
+        });
         // Filesystem + mmap + trie build all block. Run them on a blocking
         // task so the tokio worker that polls this actor is free to drive
         // other tasks (notably the sibling SocketService) while
         // initialisation runs.
-        let system_properties = tokio::task::spawn_blocking(move || {
-            init_system_properties_sync(args.property_contexts_files, args.build_prop_files, &dir)
-        })
-        .await
-        .map_err(|e| std::io::Error::other(format!("init join failed: {e}")))??;
+        let backing = args.backing.unwrap_or_else(rsproperties::backing);
+        let require_declared = args
+            .require_declared
+            .unwrap_or_else(rsproperties::require_declared);
+        let history = args.history;
+        let recording = args.recording;
+        let persistent_path = args.persistent_properties;
+        let context_serials = args.context_serials;
+        let (system_properties, history, recorder, persistent) =
+            tokio::task::spawn_blocking(move || {
+                let mut system_properties = init_system_properties_sync(
+                    args.property_contexts_files,
+                    args.build_prop_files,
+                    &dir,
+                    &layout,
+                    backing,
+                    require_declared,
+                    args.read_only_prefixes,
+                    args.preload_progress,
+                    &args.requirements,
+                    args.required_policy,
+                )?;
+                if context_serials {
+                    system_properties
+                        .enable_context_serials()
+                        .map_err(io_other)?;
+                }
+                let persistent = persistent_path
+                    .map(|path| load_persistent_properties(&mut system_properties, path))
+                    .transpose()?;
+                let history = history.map(History::open).transpose()?;
+                let recorder = recording.map(Recorder::create).transpose()?;
+                Ok::<_, std::io::Error>((system_properties, history, recorder, persistent))
+            })
+            .await
+            .map_err(|e| std::io::Error::other(format!("init join failed: {e}")))??;
 
-        Ok(PropertiesService { system_properties })
+        Ok(PropertiesService {
+            system_properties,
+            pipeline: Pipeline {
+                name_policy: args.name_policy,
+                transforms: args.transforms,
+                history,
+                recorder,
+                persistent,
+                control_handler: args.control_handler,
+                set_listener: args.set_listener,
+                stats: ServiceStats::default(),
+            },
+        })
     }
 
     async fn on_stop(
@@ -148,61 +581,418 @@ impl rsactor::Message<crate::ReadyMessage> for PropertiesService {
     }
 }
 
-use rsproperties::wire::{validate_property_name, validate_value_len};
+impl rsactor::Message<StatsMessage> for PropertiesService {
+    type Reply = ServiceStats;
+
+    async fn handle(&mut self, _message: StatsMessage, _actor_ref: &ActorRef<Self>) -> Self::Reply {
+        self.pipeline.stats
+    }
+}
+
+impl rsactor::Message<MemfdAreasMessage> for PropertiesService {
+    /// Duplicates of the memfds, named after the files they stand for;
+    /// `InvalidArgument` for a file-backed store.
+    type Reply = rsproperties::Result<Vec<(String, std::os::fd::OwnedFd)>>;
+
+    async fn handle(
+        &mut self,
+        _message: MemfdAreasMessage,
+        _actor_ref: &ActorRef<Self>,
+    ) -> Self::Reply {
+        self.system_properties.memfd_areas()
+    }
+}
+
+impl rsactor::Message<GetMessage> for PropertiesService {
+    /// `None` when the property is not set.
+    type Reply = rsproperties::Result<Option<String>>;
+
+    async fn handle(&mut self, message: GetMessage, _actor_ref: &ActorRef<Self>) -> Self::Reply {
+        // A name that could never be set would otherwise read as "not set".
+        rsproperties::wire::validate_property_name(&message.name)?;
+        match self.system_properties.get_with_result(&message.name) {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl rsactor::Message<FreezeMessage> for PropertiesService {
+    type Reply = rsproperties::Result<rsproperties::FrozenProperties>;
+
+    async fn handle(
+        &mut self,
+        _message: FreezeMessage,
+        _actor_ref: &ActorRef<Self>,
+    ) -> Self::Reply {
+        self.system_properties.freeze()
+    }
+}
+
+use rsproperties::wire::{
+    canonicalize_name_with, validate_property_name, validate_value_len_with,
+    PROP_ERROR_HANDLE_CONTROL_MESSAGE, PROP_ERROR_INVALID_NAME, PROP_ERROR_INVALID_VALUE,
+    PROP_ERROR_PERMISSION_DENIED, PROP_ERROR_READ_ONLY_PROPERTY, PROP_ERROR_SET_FAILED,
+};
+use rsproperties::{Error, SetError};
 
 impl rsactor::Message<crate::PropertyMessage> for PropertiesService {
-    type Reply = bool;
+    /// The rejection carries the wire status code and the reason the
+    /// socket layer forwards to the client.
+    type Reply = std::result::Result<(), SetError>;
 
     async fn handle(
         &mut self,
         message: crate::PropertyMessage,
         _actor_ref: &ActorRef<Self>,
     ) -> Self::Reply {
+        self.pipeline
+            .apply_counted(&mut self.system_properties, message)
+    }
+}
+
+impl rsactor::Message<BatchMessage> for PropertiesService {
+    /// One reply per set, in order, as for a single set.
+    type Reply = Vec<std::result::Result<(), SetError>>;
+
+    /// The sets share one [`SystemProperties::batch`]: waiters are woken
+    /// once for the lot rather than after each set.
+    async fn handle(&mut self, message: BatchMessage, _actor_ref: &ActorRef<Self>) -> Self::Reply {
+        let pipeline = &mut self.pipeline;
+        let applied = self.system_properties.batch(|props| {
+            Ok(message
+                .sets
+                .into_iter()
+                .map(|set| pipeline.apply_counted(props, set))
+                .collect())
+        });
+        match applied {
+            Ok(results) => results,
+            Err(_) => unreachable!("a batch whose body never fails"),
+        }
+    }
+}
+
+impl rsactor::Message<UnsetMessage> for PropertiesService {
+    /// Like a set's: the wire status code and the reason.
+    type Reply = std::result::Result<(), SetError>;
+
+    async fn handle(&mut self, message: UnsetMessage, _actor_ref: &ActorRef<Self>) -> Self::Reply {
+        let pipeline = &mut self.pipeline;
+        let name = match canonicalize_name_with(&message.name, &pipeline.name_policy) {
+            Ok(canonical) => canonical.into_string(),
+            Err(e) => {
+                log::error!("Rejected unsetprop: {e}");
+                pipeline.stats.rejected += 1;
+                return Err(rejection(&message.name, PROP_ERROR_INVALID_NAME, &e));
+            }
+        };
+        if let Err(e) = pipeline.check_access(
+            &self.system_properties,
+            Some(&message.peer),
+            &name,
+            "remove",
+        ) {
+            pipeline.stats.denied += 1;
+            return Err(e);
+        }
+        match pipeline.unset(
+            &mut self.system_properties,
+            &name,
+            message.peer.credentials.as_ref(),
+        ) {
+            Ok(()) => pipeline.stats.applied += 1,
+            Err(e) => {
+                pipeline.stats.rejected += 1;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ` from uid=… gid=… pid=…` for the log line of a client's write, or
+/// nothing for the service's own.
+fn sender(credentials: Option<&PeerCredentials>) -> String {
+    credentials.map_or_else(String::new, |credentials| format!(" from {credentials}"))
+}
+
+impl Pipeline {
+    /// Asks the [`crate::AccessPolicy`] of `peer`, the socket client
+    /// behind a write, whether it may `verb` `name` — the canonical name
+    /// the write is about to store, under the context `property_info`
+    /// assigns to that same name. The service's own writes (`None`) are
+    /// not checked.
+    fn check_access(
+        &self,
+        props: &SystemProperties,
+        peer: Option<&Peer>,
+        name: &str,
+        verb: &str,
+    ) -> std::result::Result<(), SetError> {
+        let Some(peer) = peer else {
+            return Ok(());
+        };
+        let context = match props.describe(name) {
+            Ok(description) => description.context,
+            // An invalid name: the store refuses the write itself.
+            Err(e) => {
+                log::debug!("No context for '{name}': {e}");
+                None
+            }
+        };
+        if peer.allows(name, context.as_deref()) {
+            return Ok(());
+        }
+        log::warn!(
+            "{verb} of '{name}' denied by the access policy{}",
+            sender(peer.credentials.as_ref())
+        );
+        Err(SetError::new(
+            name,
+            PROP_ERROR_PERMISSION_DENIED,
+            Some(format!("not allowed to {verb} '{name}'")),
+        ))
+    }
+
+    /// Removes canonical `name` from the store and, for a `persist.`
+    /// property, from the persistent file. Transforms and the control
+    /// handler only see sets. Removing a property that is not set
+    /// succeeds.
+    fn unset(
+        &mut self,
+        props: &mut SystemProperties,
+        name: &str,
+        credentials: Option<&PeerCredentials>,
+    ) -> std::result::Result<(), SetError> {
+        match props.remove(name) {
+            Ok(removed) => {
+                log::info!(
+                    "Removed property: {name} (was set: {removed}){}",
+                    sender(credentials)
+                );
+                // Like a set, the removal stands even if the file cannot
+                // be rewritten; the value then comes back on restart.
+                if let Some(persistent) = &mut self.persistent {
+                    if let Err(e) = persistent.remove(name) {
+                        log::error!(
+                            "Failed to unpersist {name} from {:?}: {e}",
+                            persistent.path()
+                        );
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Failed to remove property '{name}': {e}");
+                let code = match e {
+                    Error::PermissionDenied(_) => PROP_ERROR_READ_ONLY_PROPERTY,
+                    _ => PROP_ERROR_SET_FAILED,
+                };
+                Err(rejection(name, code, &e))
+            }
+        }
+    }
+
+    /// Applies a set: its name made canonical and passed through the
+    /// transforms, then the access check on that name, then
+    /// [`Self::apply`]. Counted in the stats.
+    fn apply_counted(
+        &mut self,
+        props: &mut SystemProperties,
+        message: crate::PropertyMessage,
+    ) -> std::result::Result<(), SetError> {
         log::debug!("Handling property message: {message:?}");
-        let name = message.name;
-        let value = message.value;
+        let credentials = message.credentials();
+        let (name, value) = match self.resolve(&message.name, message.value) {
+            Ok(set) => set,
+            Err(e) => {
+                self.stats.rejected += 1;
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.check_access(props, message.peer.as_ref(), &name, "set") {
+            self.stats.denied += 1;
+            return Err(e);
+        }
+        match self.apply(props, name, value, credentials) {
+            Ok(Applied::Stored) => self.stats.applied += 1,
+            Ok(Applied::Dispatched) => self.stats.control += 1,
+            Err(e) => {
+                self.stats.rejected += 1;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
 
+    /// The name and value a set of `name` stores: the name canonicalized,
+    /// then both through the transforms.
+    fn resolve(
+        &self,
+        original: &str,
+        value: String,
+    ) -> std::result::Result<(String, String), SetError> {
         // Single source-of-truth for name + length policy — client and
         // server use the same `rsproperties::wire` functions so policy
-        // drift (e.g. `>` vs `>=`) cannot reappear.
-        if let Err(e) = validate_property_name(&name) {
-            log::error!("Rejected setprop: {e}");
-            return false;
+        // drift (e.g. `>` vs `>=`) cannot reappear. Canonicalizing (rather
+        // than only validating) on ingest means a sloppy client's
+        // " sys.foo" is stored as "sys.foo", never as a second spelling.
+        let name = match canonicalize_name_with(original, &self.name_policy) {
+            Ok(name) => name.into_string(),
+            Err(e) => {
+                log::error!("Rejected setprop: {e}");
+                return Err(rejection(original, PROP_ERROR_INVALID_NAME, &e));
+            }
+        };
+        if name != original {
+            log::debug!("Canonicalized property name {original:?} -> {name}");
         }
-        if let Err(e) = validate_value_len(&name, &value) {
+        // Transforms run before validation so their output is held to the
+        // same rules as a client's; a renamed set is validated under its
+        // new name.
+        if self.transforms.is_empty() {
+            return Ok((name, value));
+        }
+        {
+            let original = name.clone();
+            let (name, value) = match self.transforms.apply(name, value) {
+                Ok(set) => set,
+                Err(reason) => {
+                    log::error!("Rejected setprop {original}: {reason}");
+                    return Err(SetError::new(
+                        &original,
+                        PROP_ERROR_INVALID_VALUE,
+                        Some(reason),
+                    ));
+                }
+            };
+            if name != original {
+                if let Err(e) = validate_property_name(&name) {
+                    log::error!("Rejected setprop {original}: {e}");
+                    return Err(rejection(&original, PROP_ERROR_INVALID_NAME, &e));
+                }
+                log::debug!("Transformed property name {original} -> {name}");
+            }
+            Ok((name, value))
+        }
+    }
+
+    /// Stores (or dispatches, for a control property) a set [`Self::resolve`]d
+    /// and allowed.
+    fn apply(
+        &mut self,
+        props: &mut SystemProperties,
+        name: String,
+        value: String,
+        credentials: Option<PeerCredentials>,
+    ) -> std::result::Result<Applied, SetError> {
+        let read_only_prefixes = props.read_only_prefixes();
+        if let Err(e) = validate_value_len_with(&name, &value, read_only_prefixes) {
             log::error!("Rejected setprop: {e}");
-            return false;
+            return Err(rejection(&name, PROP_ERROR_INVALID_VALUE, &e));
+        }
+
+        // After the transforms, so a rewrite can route to (or away from) a
+        // control property; the value is held to the usual limits first.
+        if let Some(handler) = &self.control_handler {
+            if let Some(mut control) = ControlMessage::parse(&name, &value) {
+                control.credentials = credentials;
+                log::info!(
+                    "Control message: {name} (<{} bytes>){}",
+                    value.len(),
+                    sender(credentials.as_ref())
+                );
+                return match handler.handle(&control) {
+                    Ok(()) => Ok(Applied::Dispatched),
+                    Err(reason) => {
+                        log::error!("Control message {name} failed: {reason}");
+                        Err(SetError::new(
+                            &name,
+                            PROP_ERROR_HANDLE_CONTROL_MESSAGE,
+                            Some(reason),
+                        ))
+                    }
+                };
+            }
         }
 
         // Delegate to `set`, which already encapsulates the find →
         // update-or-add sequence (plus the `ro.` rejection) — duplicating
         // that logic here invited policy drift between the two copies.
-        match self.system_properties.set(&name, &value) {
+        match props.set(&name, &value) {
             Ok(()) => {
                 // Mask the value (same policy as `PropertyMessage`'s Debug
                 // impl and the socket layer): values may carry sensitive
                 // payloads, and logging them here would defeat the masking
                 // everywhere upstream.
-                log::info!("Set property: {name} (<{} bytes>)", value.len());
-                true
+                log::info!(
+                    "Set property: {name} (<{} bytes>){}",
+                    value.len(),
+                    sender(credentials.as_ref())
+                );
+                // The set stands either way; a full or read-only disk only
+                // costs the record.
+                if let Some(persistent) = &mut self.persistent {
+                    if is_persistent(&name) {
+                        if let Err(e) = persistent.set(&name, &value) {
+                            log::error!("Failed to persist {name} to {:?}: {e}", persistent.path());
+                        }
+                    }
+                }
+                let now = SystemTime::now();
+                if let Some(history) = &mut self.history {
+                    if let Err(e) = history.record(&name, &value, now) {
+                        log::warn!("Failed to record {name} in the history: {e}");
+                    }
+                }
+                if let Some(recorder) = &mut self.recorder {
+                    if let Err(e) = recorder.record(&name, &value, now) {
+                        log::warn!("Failed to record {name} to {:?}: {e}", recorder.path());
+                    }
+                }
+                if let Some(listener) = &self.set_listener {
+                    listener.notify(SetEvent {
+                        name,
+                        value,
+                        credentials,
+                    });
+                }
+                Ok(Applied::Stored)
             }
             Err(e) => {
                 log::error!("Failed to set property '{name}': {e}");
-                false
+                let code = match e {
+                    Error::PermissionDenied(_) => PROP_ERROR_READ_ONLY_PROPERTY,
+                    Error::InvalidArgument(_) => PROP_ERROR_INVALID_VALUE,
+                    Error::Undeclared { .. } => PROP_ERROR_PERMISSION_DENIED,
+                    _ => PROP_ERROR_SET_FAILED,
+                };
+                Err(rejection(&name, code, &e))
             }
         }
     }
 }
 
+/// The validators' messages describe the name and the value's length,
+/// never the value itself, so they are safe to hand back to the client.
+fn rejection(name: &str, code: i32, e: &Error) -> SetError {
+    SetError::new(name, code, Some(e.to_string()))
+}
+
 pub fn run(
     property_contexts_files: Vec<PathBuf>,
     build_prop_files: Vec<PathBuf>,
 ) -> crate::ServiceContext<PropertiesService> {
-    let args = PropertiesServiceArgs {
+    run_with_args(PropertiesServiceArgs::new(
         property_contexts_files,
         build_prop_files,
-    };
+    ))
+}
 
+/// [`run`] with fully specified [`PropertiesServiceArgs`].
+pub fn run_with_args(args: PropertiesServiceArgs) -> crate::ServiceContext<PropertiesService> {
     let (actor_ref, join_handle) = rsactor::spawn(args);
     crate::ServiceContext {
         actor_ref,
@@ -0,0 +1,511 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! One actor for a whole property service, see [`PropertyActor`].
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rsactor::{Actor, ActorRef, ActorWeak};
+use rsproperties::wire::PROP_ERROR_SET_FAILED;
+use rsproperties::{Error, PropertyConfig, SetError, SystemProperties, Timespec};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::properties_service::{FreezeMessage, GetMessage};
+use crate::{PropertiesService, ServiceContext, ServiceOptions, SocketService};
+
+/// How long a blocked [`Wait`] or [`Subscribe`] sleeps at most before it
+/// checks whether its caller or the actor went away.
+const WAIT_SLICE: Duration = Duration::from_millis(200);
+
+/// Changes a [`Subscribe`] receiver buffers before the watcher waits for
+/// the subscriber to catch up.
+const SUBSCRIBE_BUFFER: usize = 64;
+
+/// Arguments of a [`PropertyActor`]: the same as [`crate::run_with_options`]
+/// takes.
+pub struct PropertyActorArgs {
+    config: PropertyConfig,
+    property_contexts_files: Vec<PathBuf>,
+    build_prop_files: Vec<PathBuf>,
+    options: ServiceOptions,
+}
+
+impl PropertyActorArgs {
+    pub fn new(
+        config: PropertyConfig,
+        property_contexts_files: Vec<PathBuf>,
+        build_prop_files: Vec<PathBuf>,
+    ) -> Self {
+        Self {
+            config,
+            property_contexts_files,
+            build_prop_files,
+            options: ServiceOptions::default(),
+        }
+    }
+
+    /// Sets the service options.
+    pub fn with_options(mut self, options: ServiceOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// A property service behind a single actor, for tokio applications.
+///
+/// Starting it does what [`crate::run_with_options`] does — configures the
+/// process-global directories and spawns the properties and socket
+/// services — and the actor then owns both: stopping it drains the socket
+/// service and stops the store, in that order. If either service dies,
+/// the next request fails and the actor stops itself, so a supervisor
+/// watching its join handle learns about it.
+///
+/// The messages are [`Get`], [`Set`], [`List`], [`Wait`] and
+/// [`Subscribe`]. Reads and sets go to the writable store; sets take the
+/// same validation path as a socket client's. Waits and subscriptions run
+/// on blocking threads against the global reader, so they never hold up
+/// the actor:
+///
+/// ```rust,no_run
+/// # async fn demo() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use rsproperties_service::property_actor::{self, Get, PropertyActorArgs, Set, Wait};
+///
+/// let config = rsproperties::PropertyConfig::with_both_dirs("/tmp/props", "/tmp/props/sockets");
+/// let service = property_actor::run(PropertyActorArgs::new(config, vec![], vec![])).await?;
+/// let props = &service.actor_ref;
+///
+/// props.ask(Set::new("my_app.mode", "fast")).await??;
+/// assert_eq!(props.ask(Get::new("my_app.mode")).await??.as_deref(), Some("fast"));
+/// let changed = props.ask_join(Wait::new("my_app.mode").timeout(std::time::Duration::from_secs(1))).await??;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PropertyActor {
+    socket_service: ServiceContext<SocketService>,
+    properties_service: ServiceContext<PropertiesService>,
+    /// Tells waiters and watchers that the actor stopped.
+    stopped: Arc<AtomicBool>,
+}
+
+/// Reads a property from the store: `None` when it is not set.
+#[derive(Debug, Clone)]
+pub struct Get {
+    pub name: String,
+}
+
+impl Get {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+/// Sets a property, with the validation and status codes a socket client
+/// gets.
+#[derive(Clone)]
+pub struct Set {
+    pub name: String,
+    pub value: String,
+}
+
+impl Set {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+// Same masking as `PropertyMessage`: values stay out of logs.
+impl std::fmt::Debug for Set {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Set")
+            .field("name", &self.name)
+            .field("value", &format_args!("<{} bytes>", self.value.len()))
+            .finish()
+    }
+}
+
+/// Every property whose name starts with `prefix`, sorted by name. An
+/// empty prefix lists everything.
+#[derive(Debug, Clone, Default)]
+pub struct List {
+    pub prefix: String,
+}
+
+impl List {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+/// Waits until a property's value differs from the one it had when the
+/// wait started (being set for the first time counts) and returns the new
+/// value, or `None` once `timeout` passes. Send with
+/// [`ActorRef::ask_join`].
+#[derive(Debug, Clone)]
+pub struct Wait {
+    pub name: String,
+    /// `None` waits until the change or until the actor stops.
+    pub timeout: Option<Duration>,
+}
+
+impl Wait {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            timeout: None,
+        }
+    }
+
+    /// Gives up after `timeout`; one too large to add to the current time,
+    /// such as [`Duration::MAX`], is the same as none.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Streams changes to properties whose name starts with `prefix`. The
+/// watcher stops when the receiver is dropped or the actor stops.
+///
+/// Changes are found by comparing snapshots after every change of the
+/// global serial, so a value set and reset between two snapshots is not
+/// reported. A subscriber that falls 64 changes behind slows the watcher
+/// down rather than losing changes.
+#[derive(Debug, Clone, Default)]
+pub struct Subscribe {
+    pub prefix: String,
+}
+
+impl Subscribe {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+/// A change reported to a [`Subscribe`]r.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PropertyChange {
+    pub name: String,
+    pub value: String,
+}
+
+impl Actor for PropertyActor {
+    type Args = PropertyActorArgs;
+    type Error = std::io::Error;
+    type IdleEvent = ();
+
+    async fn on_start(
+        args: Self::Args,
+        _actor_ref: &ActorRef<Self>,
+    ) -> std::result::Result<Self, Self::Error> {
+        let (socket_service, properties_service) = crate::run_with_options(
+            args.config,
+            args.property_contexts_files,
+            args.build_prop_files,
+            args.options,
+        )
+        .await
+        .map_err(std::io::Error::other)?;
+        Ok(PropertyActor {
+            socket_service,
+            properties_service,
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    async fn on_stop(
+        &mut self,
+        _actor_weak: &ActorWeak<Self>,
+        killed: bool,
+    ) -> std::result::Result<(), Self::Error> {
+        self.stopped.store(true, Ordering::Release);
+        // Socket service first: its drain forwards the last in-flight sets
+        // to the properties service, which must still be running.
+        let _ = self.socket_service.actor_ref.stop().await;
+        let _ = (&mut self.socket_service.join_handle).await;
+        let _ = self.properties_service.actor_ref.stop().await;
+        let _ = (&mut self.properties_service.join_handle).await;
+        if killed {
+            log::warn!("PropertyActor killed");
+        } else {
+            log::info!("PropertyActor stopped");
+        }
+        Ok(())
+    }
+}
+
+impl PropertyActor {
+    /// Fails the caller's request when a service died; the actor stops
+    /// itself so the failure reaches its supervisor.
+    fn check_services(&self, actor_ref: &ActorRef<Self>) -> bool {
+        let alive = !self.socket_service.join_handle.is_finished()
+            && !self.properties_service.join_handle.is_finished();
+        if !alive {
+            log::error!("A property service exited; stopping PropertyActor");
+            let actor_ref = actor_ref.clone();
+            tokio::spawn(async move {
+                let _ = actor_ref.stop().await;
+            });
+        }
+        alive
+    }
+}
+
+impl rsactor::Message<crate::ReadyMessage> for PropertyActor {
+    type Reply = ();
+
+    async fn handle(
+        &mut self,
+        _message: crate::ReadyMessage,
+        _actor_ref: &ActorRef<Self>,
+    ) -> Self::Reply {
+    }
+}
+
+impl rsactor::Message<Get> for PropertyActor {
+    type Reply = rsproperties::Result<Option<String>>;
+
+    async fn handle(&mut self, message: Get, actor_ref: &ActorRef<Self>) -> Self::Reply {
+        if !self.check_services(actor_ref) {
+            return Err(Error::Closed);
+        }
+        self.properties_service
+            .actor_ref
+            .ask(GetMessage { name: message.name })
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to reach the properties service: {e}");
+                Err(Error::Closed)
+            })
+    }
+}
+
+impl rsactor::Message<Set> for PropertyActor {
+    type Reply = std::result::Result<(), SetError>;
+
+    async fn handle(&mut self, message: Set, actor_ref: &ActorRef<Self>) -> Self::Reply {
+        let name = message.name.clone();
+        if !self.check_services(actor_ref) {
+            return Err(SetError::new(
+                &name,
+                PROP_ERROR_SET_FAILED,
+                Some("property service stopped".to_owned()),
+            ));
+        }
+        self.properties_service
+            .actor_ref
+            .ask(crate::PropertyMessage {
+                name: message.name,
+                value: message.value,
+                peer: None,
+            })
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to reach the properties service: {e}");
+                Err(SetError::new(
+                    &name,
+                    PROP_ERROR_SET_FAILED,
+                    Some(e.to_string()),
+                ))
+            })
+    }
+}
+
+impl rsactor::Message<List> for PropertyActor {
+    type Reply = rsproperties::Result<Vec<(String, String)>>;
+
+    async fn handle(&mut self, message: List, actor_ref: &ActorRef<Self>) -> Self::Reply {
+        if !self.check_services(actor_ref) {
+            return Err(Error::Closed);
+        }
+        let frozen = self
+            .properties_service
+            .actor_ref
+            .ask(FreezeMessage)
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to reach the properties service: {e}");
+                Err(Error::Closed)
+            })?;
+        let mut properties: Vec<(String, String)> = frozen
+            .into_map()
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(&message.prefix))
+            .collect();
+        properties.sort_unstable();
+        Ok(properties)
+    }
+}
+
+impl rsactor::Message<Wait> for PropertyActor {
+    type Reply = JoinHandle<rsproperties::Result<Option<String>>>;
+
+    async fn handle(&mut self, message: Wait, actor_ref: &ActorRef<Self>) -> Self::Reply {
+        let alive = self.check_services(actor_ref);
+        let stopped = self.stopped.clone();
+        tokio::task::spawn_blocking(move || {
+            if !alive {
+                return Err(Error::Closed);
+            }
+            rsproperties::wire::validate_property_name(&message.name)?;
+            wait_for_change(
+                rsproperties::try_system_properties()?,
+                &message.name,
+                message.timeout,
+                &stopped,
+            )
+        })
+    }
+}
+
+impl rsactor::Message<Subscribe> for PropertyActor {
+    type Reply = mpsc::Receiver<PropertyChange>;
+
+    async fn handle(&mut self, message: Subscribe, actor_ref: &ActorRef<Self>) -> Self::Reply {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBE_BUFFER);
+        if !self.check_services(actor_ref) {
+            // Dropping the sender ends the subscription right away.
+            return receiver;
+        }
+        let stopped = self.stopped.clone();
+        tokio::task::spawn_blocking(move || match rsproperties::try_system_properties() {
+            Ok(props) => watch(props, &message.prefix, &sender, &stopped),
+            Err(e) => log::error!("Subscription to '{}' failed: {e}", message.prefix),
+        });
+        receiver
+    }
+}
+
+fn slice(deadline: Option<Instant>) -> Timespec {
+    let remaining = deadline.map_or(WAIT_SLICE, |deadline| {
+        deadline
+            .saturating_duration_since(Instant::now())
+            .min(WAIT_SLICE)
+    });
+    Timespec {
+        tv_sec: remaining.as_secs() as _,
+        tv_nsec: remaining.subsec_nanos() as _,
+    }
+}
+
+fn current(props: &SystemProperties, name: &str) -> rsproperties::Result<Option<String>> {
+    match props.get_with_result(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Blocks until `name` changes, in slices so a stopped actor is noticed.
+fn wait_for_change(
+    props: &SystemProperties,
+    name: &str,
+    timeout: Option<Duration>,
+    stopped: &AtomicBool,
+) -> rsproperties::Result<Option<String>> {
+    // A timeout too large to add is no deadline at all.
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    // Serial before value: a set landing in between ends the first wait
+    // at once instead of being missed.
+    let mut serial = props.context_serial();
+    let initial = current(props, name)?;
+    loop {
+        if let Some(new) = props.wait(None, Some(serial), Some(&slice(deadline))) {
+            serial = new;
+            let value = current(props, name)?;
+            if value != initial {
+                return Ok(value);
+            }
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Ok(None);
+        }
+        if stopped.load(Ordering::Acquire) {
+            return Err(Error::Closed);
+        }
+    }
+}
+
+/// Sends the changes under `prefix` until the subscriber or the actor
+/// goes away.
+fn watch(
+    props: &SystemProperties,
+    prefix: &str,
+    sender: &mpsc::Sender<PropertyChange>,
+    stopped: &AtomicBool,
+) {
+    let snapshot = |props: &SystemProperties| -> Option<HashMap<String, String>> {
+        match props.freeze() {
+            Ok(frozen) => Some(
+                frozen
+                    .into_map()
+                    .into_iter()
+                    .filter(|(name, _)| name.starts_with(prefix))
+                    .collect(),
+            ),
+            Err(e) => {
+                log::error!("Subscription to '{prefix}' failed: {e}");
+                None
+            }
+        }
+    };
+    let mut serial = props.context_serial();
+    let Some(mut previous) = snapshot(props) else {
+        return;
+    };
+    while !sender.is_closed() && !stopped.load(Ordering::Acquire) {
+        let Some(new) = props.wait(None, Some(serial), Some(&slice(None))) else {
+            continue;
+        };
+        serial = new;
+        let Some(next) = snapshot(props) else {
+            return;
+        };
+        let mut changes: Vec<PropertyChange> = next
+            .iter()
+            .filter(|(name, value)| previous.get(*name) != Some(*value))
+            .map(|(name, value)| PropertyChange {
+                name: name.clone(),
+                value: value.clone(),
+            })
+            .collect();
+        changes.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        for change in changes {
+            if sender.blocking_send(change).is_err() {
+                return;
+            }
+        }
+        previous = next;
+    }
+}
+
+/// Spawns a [`PropertyActor`] and waits until both services serve. A
+/// startup failure is returned as the error `on_start` reported.
+pub async fn run(
+    args: PropertyActorArgs,
+) -> Result<ServiceContext<PropertyActor>, Box<dyn std::error::Error + Send + Sync>> {
+    let (actor_ref, join_handle) = rsactor::spawn::<PropertyActor>(args);
+    if let Err(e) = actor_ref.ask(crate::ReadyMessage).await {
+        let startup_error = join_handle.await.ok().and_then(|r| r.into_error());
+        return Err(match startup_error {
+            Some(error) => error.into(),
+            None => format!("Failed to start PropertyActor: {e}").into(),
+        });
+    }
+    Ok(ServiceContext {
+        actor_ref,
+        join_handle,
+    })
+}
@@ -0,0 +1,562 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Android init `on property:` triggers for systems without Android init.
+//!
+//! [`RcTriggerEngine`] reads the property-trigger subset of init.rc
+//! syntax and runs the matching actions when properties change:
+//!
+//! ```text
+//! # Comments and blank lines are ignored; `\` continues a line.
+//! on property:sys.usb.config=adb && property:sys.boot_completed=1
+//!     write /sys/class/android_usb/android0/enable 1
+//!     setprop sys.usb.state ${sys.usb.config}
+//!
+//! on property:vendor.wifi.mode=*
+//!     exec -- /usr/bin/wifi-reconfigure "${vendor.wifi.mode:-off}"
+//! ```
+//!
+//! Supported commands are `setprop <name> <value>`, `write <path>
+//! <content>` and `exec [--] <command> [<argument>...]`; arguments may be
+//! double-quoted and may reference properties as `${name}` or
+//! `${name:-default}`, expanded when the action runs. `exec` runs the
+//! command directly (no shell) and waits for it, like init's; the
+//! SELinux label, user and group options before `--` are not supported.
+//!
+//! Other sections — `on boot` and other event triggers, `service`,
+//! `import` — and other commands are skipped with a warning and listed by
+//! [`RcTriggerEngine::unsupported`], so a real init.rc can be fed in as
+//! it is.
+//!
+//! # Firing
+//!
+//! A trigger's conditions (`property:name=value`, or `=*` for any value)
+//! are joined with `&&`. Like init, the first [`RcTriggerEngine::process`]
+//! fires every trigger whose conditions already hold; after that a
+//! trigger fires when one of its properties changed since the previous
+//! pass and all its conditions hold. Triggers fire in file order, and
+//! `setprop`s made by actions are seen by the next pass, so triggers can
+//! chain.
+
+use std::path::Path;
+use std::time::Duration;
+
+use rsproperties::mirror::MirrorSink;
+use rsproperties::{Error, FrozenProperties, Result, SystemProperties};
+
+#[derive(Debug, Clone)]
+struct Condition {
+    name: String,
+    /// `*` matches any value.
+    value: String,
+}
+
+#[derive(Debug, Clone)]
+enum Command {
+    SetProp { name: String, value: String },
+    Write { path: String, content: String },
+    Exec(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+struct Action {
+    /// The command as written, for reports and logs.
+    text: String,
+    command: Command,
+}
+
+#[derive(Debug, Clone)]
+struct Trigger {
+    text: String,
+    conditions: Vec<Condition>,
+    actions: Vec<Action>,
+}
+
+/// Result of one [`RcTriggerEngine::process`].
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct TriggerReport {
+    /// Triggers that fired, as written after `on`, in firing order.
+    pub fired: Vec<String>,
+    /// Commands that failed, with the reason. A failed command does not
+    /// stop the rest of its action list, as with init.
+    pub failed: Vec<(String, Error)>,
+}
+
+impl TriggerReport {
+    /// `true` when no trigger fired.
+    pub fn is_empty(&self) -> bool {
+        self.fired.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Property-triggered actions parsed from init.rc-style files; see the
+/// [module documentation](self).
+///
+/// ```rust,no_run
+/// use rsproperties::mirror::ServiceSink;
+/// use rsproperties_service::RcTriggerEngine;
+/// use std::path::Path;
+///
+/// let mut engine = RcTriggerEngine::load(Path::new("/etc/init/usb.rc")).unwrap();
+/// let props = rsproperties::system_properties();
+/// loop {
+///     if let Some(report) = engine.wait_and_process(props, &mut ServiceSink, None).unwrap() {
+///         for (command, e) in &report.failed {
+///             log::warn!("{command}: {e}");
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct RcTriggerEngine {
+    triggers: Vec<Trigger>,
+    unsupported: Vec<String>,
+    /// Snapshot of the previous pass; `None` before the first.
+    previous: Option<FrozenProperties>,
+}
+
+impl RcTriggerEngine {
+    /// Reads and parses an init.rc-style file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut engine = Self::default();
+        engine.add_rc(&text, &path.display().to_string())?;
+        Ok(engine)
+    }
+
+    /// Parses init.rc-style text.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut engine = Self::default();
+        engine.add_rc(text, "<rc>")?;
+        Ok(engine)
+    }
+
+    /// Adds the triggers of another file, after the ones already loaded.
+    pub fn extend_from_file(&mut self, path: &Path) -> Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        self.add_rc(&text, &path.display().to_string())
+    }
+
+    /// The triggers, as written after `on`.
+    pub fn triggers(&self) -> impl Iterator<Item = &str> {
+        self.triggers.iter().map(|trigger| trigger.text.as_str())
+    }
+
+    /// Sections and commands that were skipped, as `origin:line: text`.
+    pub fn unsupported(&self) -> &[String] {
+        &self.unsupported
+    }
+
+    fn add_rc(&mut self, text: &str, origin: &str) -> Result<()> {
+        // `None`: inside a skipped section (or before the first one).
+        let mut current: Option<Trigger> = None;
+        for (line_no, line) in logical_lines(text) {
+            let tokens =
+                tokenize(&line).map_err(|e| Error::Parse(format!("{origin}:{line_no}: {e}")))?;
+            let Some(keyword) = tokens.first() else {
+                continue;
+            };
+            let location = format!("{origin}:{line_no}");
+            match keyword.as_str() {
+                "on" | "service" | "import" => {
+                    self.triggers.extend(current.take());
+                    if keyword == "on" {
+                        current = parse_trigger(&tokens[1..], &location)?;
+                    }
+                    if current.is_none() {
+                        log::warn!("{location}: skipping unsupported section '{line}'");
+                        self.unsupported.push(format!("{location}: {line}"));
+                    }
+                }
+                _ => {
+                    let Some(trigger) = current.as_mut() else {
+                        // Body of a skipped section, already reported.
+                        continue;
+                    };
+                    match parse_command(&tokens, &location)? {
+                        Some(command) => trigger.actions.push(Action {
+                            text: line.clone(),
+                            command,
+                        }),
+                        None => {
+                            log::warn!("{location}: skipping unsupported command '{keyword}'");
+                            self.unsupported.push(format!("{location}: {line}"));
+                        }
+                    }
+                }
+            }
+        }
+        self.triggers.extend(current);
+        Ok(())
+    }
+
+    /// Fires the triggers due since the previous pass (see the [module
+    /// documentation](self#firing)) and runs their actions, writing
+    /// `setprop`s into `sink`.
+    ///
+    /// Command failures are collected in the report; an error is returned
+    /// only when `source` cannot be read.
+    pub fn process(
+        &mut self,
+        source: &SystemProperties,
+        sink: &mut dyn MirrorSink,
+    ) -> Result<TriggerReport> {
+        let current = source.freeze()?;
+        let mut report = TriggerReport::default();
+        for trigger in &self.triggers {
+            let changed = match &self.previous {
+                None => true,
+                Some(previous) => trigger
+                    .conditions
+                    .iter()
+                    .any(|c| previous.get(&c.name) != current.get(&c.name)),
+            };
+            if !changed || !trigger.conditions.iter().all(|c| c.holds(&current)) {
+                continue;
+            }
+            log::debug!("Trigger 'on {}' fired", trigger.text);
+            report.fired.push(trigger.text.clone());
+            for action in &trigger.actions {
+                if let Err(e) = action.command.run(&current, sink) {
+                    log::warn!("'{}' failed: {e}", action.text);
+                    report.failed.push((action.text.clone(), e));
+                }
+            }
+        }
+        self.previous = Some(current);
+        Ok(report)
+    }
+
+    /// Waits until `source` changes after the previous pass, or `timeout`
+    /// elapses, then [`Self::process`]es. Returns `Ok(None)` on a timeout;
+    /// before the first pass it processes without waiting.
+    pub fn wait_and_process(
+        &mut self,
+        source: &SystemProperties,
+        sink: &mut dyn MirrorSink,
+        timeout: Option<Duration>,
+    ) -> Result<Option<TriggerReport>> {
+        if let Some(previous) = &self.previous {
+            let serial = previous.serial();
+            let timeout = timeout.map(|t| rsproperties::Timespec {
+                tv_sec: t.as_secs() as _,
+                tv_nsec: t.subsec_nanos() as _,
+            });
+            // `None` is a timeout or a wait failure; the serial tells
+            // them apart from a change that raced the wait.
+            if source.wait(None, Some(serial), timeout.as_ref()).is_none()
+                && source.context_serial() == serial
+            {
+                return Ok(None);
+            }
+        }
+        self.process(source, sink).map(Some)
+    }
+}
+
+impl Condition {
+    fn holds(&self, properties: &FrozenProperties) -> bool {
+        match properties.get(&self.name) {
+            Some(value) => self.value == "*" || value == self.value,
+            None => false,
+        }
+    }
+}
+
+impl Command {
+    fn run(&self, properties: &FrozenProperties, sink: &mut dyn MirrorSink) -> Result<()> {
+        let get = |name: &str| properties.get(name);
+        match self {
+            Command::SetProp { name, value } => sink.set(&expand(name, get)?, &expand(value, get)?),
+            Command::Write { path, content } => {
+                std::fs::write(expand(path, get)?, expand(content, get)?)?;
+                Ok(())
+            }
+            Command::Exec(args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| expand(arg, get))
+                    .collect::<Result<Vec<_>>>()?;
+                let status = std::process::Command::new(&args[0])
+                    .args(&args[1..])
+                    .status()?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(Error::Io(std::io::Error::other(format!(
+                        "{} exited with {status}",
+                        args[0]
+                    ))))
+                }
+            }
+        }
+    }
+}
+
+/// `on` arguments to a trigger; `None` when it is not made of property
+/// conditions only.
+fn parse_trigger(args: &[String], location: &str) -> Result<Option<Trigger>> {
+    let mut conditions = Vec::new();
+    let mut expect_condition = true;
+    for arg in args {
+        if !expect_condition {
+            if arg != "&&" {
+                return Err(Error::Parse(format!(
+                    "{location}: expected '&&' between triggers, found '{arg}'"
+                )));
+            }
+            expect_condition = true;
+            continue;
+        }
+        let Some(condition) = arg.strip_prefix("property:") else {
+            return Ok(None);
+        };
+        let Some((name, value)) = condition.split_once('=') else {
+            return Err(Error::Parse(format!(
+                "{location}: property trigger '{arg}' has no '='"
+            )));
+        };
+        if name.is_empty() {
+            return Err(Error::Parse(format!(
+                "{location}: property trigger '{arg}' has no name"
+            )));
+        }
+        conditions.push(Condition {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        });
+        expect_condition = false;
+    }
+    if conditions.is_empty() || expect_condition {
+        return Err(Error::Parse(format!("{location}: incomplete trigger")));
+    }
+    Ok(Some(Trigger {
+        text: args.join(" "),
+        conditions,
+        actions: Vec::new(),
+    }))
+}
+
+/// `None` for commands outside the supported subset.
+fn parse_command(tokens: &[String], location: &str) -> Result<Option<Command>> {
+    let arity = |n: usize| {
+        if tokens.len() == n + 1 {
+            Ok(())
+        } else {
+            Err(Error::Parse(format!(
+                "{location}: '{}' takes {n} arguments, found {}",
+                tokens[0],
+                tokens.len() - 1
+            )))
+        }
+    };
+    let command = match tokens[0].as_str() {
+        "setprop" => {
+            arity(2)?;
+            Command::SetProp {
+                name: tokens[1].clone(),
+                value: tokens[2].clone(),
+            }
+        }
+        "write" => {
+            arity(2)?;
+            Command::Write {
+                path: tokens[1].clone(),
+                content: tokens[2].clone(),
+            }
+        }
+        "exec" => {
+            let args = match tokens[1..].iter().position(|t| t == "--") {
+                Some(0) => &tokens[2..],
+                Some(_) => {
+                    return Err(Error::Parse(format!(
+                        "{location}: exec options before '--' are not supported"
+                    )))
+                }
+                None => &tokens[1..],
+            };
+            if args.is_empty() {
+                return Err(Error::Parse(format!("{location}: exec without a command")));
+            }
+            Command::Exec(args.to_vec())
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(command))
+}
+
+/// Joins `\`-continued lines and drops comments and blank lines; yields
+/// each logical line with the number of its first physical line.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (index, raw) in text.lines().enumerate() {
+        let (continued, body) = match raw.strip_suffix('\\') {
+            Some(body) => (true, body),
+            None => (false, raw),
+        };
+        let (start, mut line) = pending.take().unwrap_or((index + 1, String::new()));
+        line.push_str(body);
+        if continued {
+            line.push(' ');
+            pending = Some((start, line));
+            continue;
+        }
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            lines.push((start, line.to_owned()));
+        }
+    }
+    if let Some((start, line)) = pending {
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            lines.push((start, line.to_owned()));
+        }
+    }
+    lines
+}
+
+/// Splits on whitespace; `"..."` groups words and `\` escapes the next
+/// character (`\n` and `\t` are a newline and a tab).
+fn tokenize(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_token = false;
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_token = true;
+            }
+            '\\' => {
+                match chars.next() {
+                    Some('n') => token.push('\n'),
+                    Some('t') => token.push('\t'),
+                    Some(c) => token.push(c),
+                    None => return Err("trailing '\\'".into()),
+                }
+                in_token = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut token));
+                    in_token = false;
+                }
+            }
+            c => {
+                token.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("unterminated '\"'".into());
+    }
+    if in_token {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Expands `${name}` and `${name:-default}` with the values `get`
+/// returns; an unset property without a default is an error, as in init.
+fn expand<'a>(text: &str, get: impl Fn(&str) -> Option<&'a str>) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| Error::Parse(format!("unterminated '${{' in '{text}'")))?;
+        let reference = &after[..end];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        match (get(name).filter(|v| !v.is_empty()), default) {
+            (Some(value), _) => out.push_str(value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => {
+                return Err(Error::NotFound(format!(
+                    "'{name}' referenced in '{text}' is not set"
+                )))
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subset() {
+        let engine = RcTriggerEngine::parse(
+            "import /init.usb.rc\n\
+             on boot\n\
+             \x20   setprop ignored 1\n\
+             # comment\n\
+             on property:a.b=1 && \\\n\
+             \x20   property:c.d=*\n\
+             \x20   setprop e.f \"two words\"\n\
+             \x20   start foo\n\
+             \x20   exec -- /bin/echo ${a.b}\n\
+             service foo /bin/foo\n\
+             \x20   class main\n\
+             on property:g.h=\n\
+             \x20   write /tmp/x \"\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            engine.triggers().collect::<Vec<_>>(),
+            ["property:a.b=1 && property:c.d=*", "property:g.h="]
+        );
+        assert_eq!(engine.unsupported().len(), 4);
+        assert!(engine.unsupported()[2].ends_with("start foo"));
+        let actions: Vec<_> = engine.triggers[0].actions.iter().map(|a| &a.text).collect();
+        assert_eq!(
+            actions,
+            ["setprop e.f \"two words\"", "exec -- /bin/echo ${a.b}"]
+        );
+        match &engine.triggers[0].actions[0].command {
+            Command::SetProp { value, .. } => assert_eq!(value, "two words"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for rc in [
+            "on property:a.b\n",
+            "on property:a.b=1 property:c=2\n",
+            "on property:a.b=1 &&\n",
+            "on property:a=1\n setprop a\n",
+            "on property:a=1\n exec u:r:su:s0 -- /bin/true\n",
+            "on property:a=1\n write \"/tmp/x 1\n",
+        ] {
+            assert!(
+                matches!(RcTriggerEngine::parse(rc), Err(Error::Parse(_))),
+                "{rc:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let properties: std::collections::HashMap<&str, &str> =
+            [("a.b", "1"), ("empty", "")].into_iter().collect();
+        let get = |name: &str| properties.get(name).copied();
+        assert_eq!(expand("x${a.b}y", get).unwrap(), "x1y");
+        assert_eq!(expand("${missing:-d}", get).unwrap(), "d");
+        assert_eq!(expand("${empty:-d}", get).unwrap(), "d");
+        assert!(matches!(expand("${missing}", get), Err(Error::NotFound(_))));
+        assert!(matches!(expand("${a.b", get), Err(Error::Parse(_))));
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recording every set a property service stores, and replaying the
+//! recording, see [`Recorder`] and [`replay`].
+//!
+//! A recording has the format of the [`crate::History`] log: one set per
+//! line, milliseconds since the Unix epoch, the name and the escaped
+//! value. Unlike the history it holds every property and is never
+//! rotated, so it describes one session completely.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use rsproperties::{ContextWithLocation, Error};
+
+use crate::history::{decode, encode, HistoryEntry};
+
+/// Writes every set the properties service stores to one file, for
+/// [`replay`] to re-apply later — the way to reproduce a bug that only
+/// shows up after a particular sequence of sets.
+///
+/// The service keeps one when [`crate::ServiceOptions::record`] is set.
+/// Values loaded from build.prop files at startup are not recorded:
+/// replay against a service started from the same files. The file holds
+/// values, so it is created with mode 0600; an existing one is
+/// overwritten. Records are written but not synced.
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+}
+
+impl Recorder {
+    /// Starts a recording at `path`, creating its directory if needed.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)?;
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a record of `name` being set to `value` at `at`.
+    pub fn record(&mut self, name: &str, value: &str, at: SystemTime) -> io::Result<()> {
+        self.file.write_all(encode(name, value, at).as_bytes())
+    }
+}
+
+/// The sets recorded in `path`, in the order they were stored. A record
+/// torn by a crash at the end of the file is dropped; any other malformed
+/// record fails with [`io::ErrorKind::InvalidData`].
+pub fn read_recording(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    let mut line = String::new();
+    let mut line_no = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_no += 1;
+        let Some(record) = line.strip_suffix('\n') else {
+            log::warn!("Skipping the torn last record of {path:?}");
+            break;
+        };
+        match decode(record) {
+            Some(entry) => entries.push(entry),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed record {path:?}:{line_no}"),
+                ))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// How fast [`replay`] re-applies a recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub enum ReplaySpeed {
+    /// With the gaps between the sets as recorded.
+    #[default]
+    Original,
+    /// With the recorded gaps divided by this factor: `2.0` replays
+    /// twice as fast. Must be positive.
+    Scaled(f64),
+    /// Back to back.
+    Unpaced,
+}
+
+/// Re-applies the recording at `path` through [`rsproperties::set`], so
+/// it reaches the property service [`rsproperties::init`] configured, and
+/// returns the number of sets. Start that service from the same
+/// property_contexts and build.prop files as the recorded one.
+///
+/// Stops at the first set that fails, with the error naming its record.
+pub fn replay(path: &Path, speed: ReplaySpeed) -> rsproperties::Result<usize> {
+    replay_with(path, speed, rsproperties::set::<str>)
+}
+
+/// [`replay`] with `apply` storing each set instead, e.g. into a
+/// `SystemProperties` the caller owns.
+pub fn replay_with<F>(path: &Path, speed: ReplaySpeed, mut apply: F) -> rsproperties::Result<usize>
+where
+    F: FnMut(&str, &str) -> rsproperties::Result<()>,
+{
+    let factor = match speed {
+        ReplaySpeed::Original => Some(1.0),
+        ReplaySpeed::Scaled(factor) if factor.is_finite() && factor > 0.0 => Some(factor),
+        ReplaySpeed::Scaled(factor) => {
+            return Err(Error::InvalidArgument(format!(
+                "replay speed must be positive, not {factor}"
+            )))
+        }
+        ReplaySpeed::Unpaced => None,
+    };
+    let entries = read_recording(path)
+        .context_with_location(format!("Failed to read the recording {path:?}"))?;
+    let Some(first) = entries.first().map(|entry| entry.at) else {
+        return Ok(0);
+    };
+
+    // Paced against the start rather than the previous set, so time spent
+    // applying does not add up over a long recording.
+    let started = Instant::now();
+    for (index, entry) in entries.iter().enumerate() {
+        if let Some(factor) = factor {
+            // A wall clock stepped back while recording reads as no gap.
+            let offset = entry.at.duration_since(first).unwrap_or(Duration::ZERO);
+            let due = started + offset.div_f64(factor);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+        apply(&entry.name, &entry.value).with_context_location(|| {
+            format!("Failed to replay record {} ({})", index + 1, entry.name)
+        })?;
+    }
+    Ok(entries.len())
+}
@@ -0,0 +1,377 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Supervision of everything a property service process runs, see
+//! [`ServiceRuntime`].
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use rsactor::{Actor, ActorRef, ActorResult};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+use crate::socket_service::{
+    catch_panic, install_panic_hook, log_panic, CatchUnwind, RestartBudget,
+};
+use crate::{
+    PropertiesService, PropertyMessage, RestartPolicy, ServiceContext, ServiceOptions,
+    SocketService, RELOAD_COUNT_PROPERTY,
+};
+
+/// What a supervised task returns; an error counts as a failure.
+pub type TaskResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// What [`ServiceRuntime`] does when a task fails — returns an error or
+/// panics. A task that returns `Ok` is done and never restarted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Restart {
+    /// The failure brings the runtime down (see [`ServiceRuntime::failed`]).
+    #[default]
+    Never,
+    /// The task is started again, as long as the policy allows; the
+    /// failure that exceeds it brings the runtime down.
+    OnFailure(RestartPolicy),
+}
+
+/// Tells supervised tasks that the runtime is shutting down. Tasks are
+/// expected to return soon after: [`ServiceRuntime::shutdown`] waits for
+/// them.
+#[derive(Debug, Clone)]
+pub struct ShutdownToken(watch::Receiver<bool>);
+
+impl ShutdownToken {
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once shutdown began.
+    pub async fn wait(&mut self) {
+        // An error means the runtime is gone, which is a shutdown too.
+        let _ = self.0.wait_for(|shutdown| *shutdown).await;
+    }
+}
+
+/// A supervised child that failed for good, from [`ServiceRuntime::failed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChildFailure {
+    /// `socket service`, `properties service`, or the name a task was
+    /// spawned with.
+    pub child: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ChildFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.child, self.reason)
+    }
+}
+
+impl std::error::Error for ChildFailure {}
+
+/// An actor of the runtime; `join` is taken once it has been awaited.
+struct ActorChild<T: Actor> {
+    name: &'static str,
+    actor_ref: ActorRef<T>,
+    join: Option<JoinHandle<ActorResult<T>>>,
+}
+
+impl<T: Actor> ActorChild<T>
+where
+    T::Error: std::fmt::Display,
+{
+    fn new(name: &'static str, context: ServiceContext<T>) -> Self {
+        Self {
+            name,
+            actor_ref: context.actor_ref,
+            join: Some(context.join_handle),
+        }
+    }
+
+    /// Resolves when the actor exits; never, once it was awaited.
+    async fn exited(&mut self) -> ChildFailure {
+        let Some(join) = &mut self.join else {
+            return std::future::pending().await;
+        };
+        let reason = match join.await {
+            Ok(result) => match result.error() {
+                Some(e) => format!("exited unexpectedly: {e}"),
+                None => "exited unexpectedly".to_owned(),
+            },
+            Err(e) => format!("exited unexpectedly: {e}"),
+        };
+        self.join = None;
+        ChildFailure {
+            child: self.name.to_owned(),
+            reason,
+        }
+    }
+
+    async fn stop(&mut self) {
+        self.actor_ref.stop().await;
+        if let Some(join) = self.join.take() {
+            let _ = join.await;
+        }
+    }
+}
+
+/// One property service process under supervision: the properties
+/// service, the socket service in front of it, and the tasks the process
+/// runs around them — persistent-property writers, watchers, control
+/// loops.
+///
+/// Tasks are started with [`Self::spawn`] or [`Self::spawn_blocking`],
+/// each with a [`Restart`] strategy and a [`ShutdownToken`].
+/// [`Self::shutdown`] tears everything down in dependency order: tasks
+/// first, latest first, as they talk to the services; then the socket
+/// service, whose drain still forwards sets; then the properties service.
+///
+/// The socket service restarts its own accept loop (see
+/// [`RestartPolicy`]); either service exiting on its own is a failure of
+/// the runtime.
+///
+/// ```rust,no_run
+/// use rsproperties_service::{Restart, ServiceOptions, ServiceRuntime};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let config = rsproperties::PropertyConfig::with_both_dirs("/tmp/props", "/tmp/sockets");
+/// let mut runtime = ServiceRuntime::start(config, vec![], vec![], ServiceOptions::default()).await?;
+/// runtime.spawn_blocking("watcher", Restart::Never, |shutdown| {
+///     while !shutdown.is_shutdown() {
+///         // wait for and handle property changes
+/// #       break;
+///     }
+///     Ok(())
+/// });
+/// runtime.serve_until_signal(|| {}).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServiceRuntime {
+    socket_service: ActorChild<SocketService>,
+    properties_service: ActorChild<PropertiesService>,
+    /// Supervisors of the spawned tasks, in start order.
+    tasks: Vec<(String, JoinHandle<()>)>,
+    shutdown: watch::Sender<bool>,
+    failure_sender: mpsc::UnboundedSender<ChildFailure>,
+    failures: mpsc::UnboundedReceiver<ChildFailure>,
+}
+
+impl ServiceRuntime {
+    /// Supervises a service pair started by [`crate::run`] and friends.
+    pub fn new(
+        socket_service: ServiceContext<SocketService>,
+        properties_service: ServiceContext<PropertiesService>,
+    ) -> Self {
+        install_panic_hook();
+        let (failure_sender, failures) = mpsc::unbounded_channel();
+        Self {
+            socket_service: ActorChild::new("socket service", socket_service),
+            properties_service: ActorChild::new("properties service", properties_service),
+            tasks: Vec::new(),
+            shutdown: watch::Sender::new(false),
+            failure_sender,
+            failures,
+        }
+    }
+
+    /// [`crate::run_with_options`], supervised.
+    pub async fn start(
+        config: rsproperties::PropertyConfig,
+        property_contexts_files: Vec<PathBuf>,
+        build_prop_files: Vec<PathBuf>,
+        options: ServiceOptions,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let (socket_service, properties_service) =
+            crate::run_with_options(config, property_contexts_files, build_prop_files, options)
+                .await?;
+        Ok(Self::new(socket_service, properties_service))
+    }
+
+    pub fn socket_service(&self) -> &ActorRef<SocketService> {
+        &self.socket_service.actor_ref
+    }
+
+    pub fn properties_service(&self) -> &ActorRef<PropertiesService> {
+        &self.properties_service.actor_ref
+    }
+
+    /// A token that fires when [`Self::shutdown`] begins.
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        ShutdownToken(self.shutdown.subscribe())
+    }
+
+    /// Runs the future `factory` returns as a task of the runtime, calling
+    /// `factory` again for each restart.
+    pub fn spawn<F, Fut>(&mut self, name: impl Into<String>, restart: Restart, mut factory: F)
+    where
+        F: FnMut(ShutdownToken) -> Fut + Send + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        let name = name.into();
+        let mut supervisor = Supervisor::new(&name, restart, self);
+        let join = tokio::spawn(async move {
+            loop {
+                let result = CatchUnwind(Box::pin(factory(supervisor.token.clone()))).await;
+                if !supervisor.restart(result) {
+                    break;
+                }
+            }
+        });
+        self.tasks.push((name, join));
+    }
+
+    /// [`Self::spawn`] for a blocking function, run on tokio's blocking
+    /// pool; it should check [`ShutdownToken::is_shutdown`] between
+    /// waits.
+    pub fn spawn_blocking<F>(&mut self, name: impl Into<String>, restart: Restart, mut f: F)
+    where
+        F: FnMut(ShutdownToken) -> TaskResult + Send + 'static,
+    {
+        let name = name.into();
+        let mut supervisor = Supervisor::new(&name, restart, self);
+        let join = tokio::task::spawn_blocking(move || loop {
+            let result = catch_panic(|| f(supervisor.token.clone()));
+            if !supervisor.restart(result) {
+                break;
+            }
+        });
+        self.tasks.push((name, join));
+    }
+
+    /// Resolves when a child failed for good: a service exited on its own,
+    /// or a task failed beyond its [`Restart`] strategy. Cancel-safe; each
+    /// failure is reported once.
+    pub async fn failed(&mut self) -> ChildFailure {
+        tokio::select! {
+            failure = self.socket_service.exited() => failure,
+            failure = self.properties_service.exited() => failure,
+            // `self` holds a sender, so the channel never closes.
+            Some(failure) = self.failures.recv() => failure,
+        }
+    }
+
+    /// Serves until SIGTERM or SIGINT, then [`Self::shutdown`]s.
+    ///
+    /// SIGHUP calls `on_reload` — the service has no configuration file of
+    /// its own, so re-reading the caller's settings (log filters, policies)
+    /// is up to it — and then increments [`RELOAD_COUNT_PROPERTY`].
+    ///
+    /// A child failing for good (see [`Self::failed`]) shuts the runtime
+    /// down too, and is returned as the error.
+    pub async fn serve_until_signal(mut self, mut on_reload: impl FnMut()) -> std::io::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        let mut reloads: u64 = 0;
+
+        loop {
+            tokio::select! {
+                _ = terminate.recv() => {
+                    log::info!("SIGTERM received; shutting down");
+                    break;
+                }
+                _ = interrupt.recv() => {
+                    log::info!("SIGINT received; shutting down");
+                    break;
+                }
+                _ = hangup.recv() => {
+                    log::info!("SIGHUP received; reloading");
+                    on_reload();
+                    reloads += 1;
+                    let published = self
+                        .properties_service
+                        .actor_ref
+                        .ask(PropertyMessage {
+                            name: RELOAD_COUNT_PROPERTY.to_owned(),
+                            value: reloads.to_string(),
+                            peer: None,
+                        })
+                        .await;
+                    if !matches!(published, Ok(Ok(()))) {
+                        log::warn!("Failed to publish {RELOAD_COUNT_PROPERTY}");
+                    }
+                }
+                failure = self.failed() => {
+                    log::error!("{failure}; shutting down");
+                    self.shutdown().await;
+                    return Err(std::io::Error::other(failure));
+                }
+            }
+        }
+
+        self.shutdown().await;
+        Ok(())
+    }
+
+    /// Stops everything in dependency order (see the [type docs](Self)):
+    /// fires the [`ShutdownToken`]s and waits for the tasks, latest first,
+    /// then stops the socket service, draining its connections, and then
+    /// the properties service.
+    pub async fn shutdown(mut self) {
+        self.shutdown.send_replace(true);
+        while let Some((name, join)) = self.tasks.pop() {
+            if let Err(e) = join.await {
+                log::error!("Supervisor of task '{name}' failed: {e}");
+            }
+        }
+        // Socket service first: its drain forwards the last in-flight sets
+        // to the properties service, which must still be running.
+        self.socket_service.stop().await;
+        self.properties_service.stop().await;
+    }
+}
+
+/// Restart bookkeeping of one task, moved into its supervisor loop.
+struct Supervisor {
+    name: String,
+    budget: Option<RestartBudget>,
+    token: ShutdownToken,
+    failures: mpsc::UnboundedSender<ChildFailure>,
+}
+
+impl Supervisor {
+    fn new(name: &str, restart: Restart, runtime: &ServiceRuntime) -> Self {
+        Self {
+            name: name.to_owned(),
+            budget: match restart {
+                Restart::Never => None,
+                Restart::OnFailure(policy) => Some(RestartBudget::new(policy)),
+            },
+            token: runtime.shutdown_token(),
+            failures: runtime.failure_sender.clone(),
+        }
+    }
+
+    /// Judges one run of the task; `true` to run it again.
+    fn restart(&mut self, result: Result<TaskResult, Box<dyn std::any::Any + Send>>) -> bool {
+        let reason = match result {
+            Ok(Ok(())) => return false,
+            Ok(Err(e)) => format!("failed: {e}"),
+            Err(payload) => {
+                log_panic(&format!("Task '{}'", self.name), &*payload);
+                "panicked".to_owned()
+            }
+        };
+        if self.token.is_shutdown() {
+            log::warn!("Task '{}' {reason} during shutdown", self.name);
+            return false;
+        }
+        if let Some(budget) = &mut self.budget {
+            if budget.record(Instant::now()) {
+                log::warn!("Task '{}' {reason}; restarting", self.name);
+                return true;
+            }
+        }
+        log::error!("Task '{}' {reason}", self.name);
+        let _ = self.failures.send(ChildFailure {
+            child: self.name.clone(),
+            reason,
+        });
+        false
+    }
+}
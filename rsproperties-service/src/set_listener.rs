@@ -0,0 +1,64 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! An async stream of the sets the service stores, see [`SetListener`].
+
+use tokio::sync::mpsc;
+
+use crate::access::PeerCredentials;
+
+/// A set the properties service stored, as it was stored: after name
+/// canonicalization and the [`crate::TransformChain`].
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SetEvent {
+    pub name: String,
+    pub value: String,
+    /// The socket client that sent the set; `None` for the service's own
+    /// sets and those made through a [`crate::PropertyActor`].
+    pub credentials: Option<PeerCredentials>,
+}
+
+// Values stay out of logs, as in `PropertyMessage`.
+impl std::fmt::Debug for SetEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SetEvent")
+            .field("name", &self.name)
+            .field("value", &format_args!("<{} bytes>", self.value.len()))
+            .field("credentials", &self.credentials)
+            .finish()
+    }
+}
+
+/// Forwards every set the service stores to a channel, so a task on the
+/// embedding runtime can react to them without polling the store.
+///
+/// Refused sets, removals and control messages handed to a
+/// [`crate::ControlHandler`] are not forwarded. The service never waits
+/// on the receiver: while the channel is full new sets are dropped from
+/// it (and logged), and once the receiver is dropped nothing is sent.
+/// Clones share the channel.
+#[derive(Debug, Clone)]
+pub struct SetListener(mpsc::Sender<SetEvent>);
+
+impl SetListener {
+    /// A listener and the receiver of its events, buffering up to
+    /// `capacity` of them.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero, as [`mpsc::channel`] does.
+    pub fn channel(capacity: usize) -> (Self, mpsc::Receiver<SetEvent>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (Self(sender), receiver)
+    }
+
+    pub(crate) fn notify(&self, event: SetEvent) {
+        match self.0.try_send(event) {
+            Ok(()) | Err(mpsc::error::TrySendError::Closed(_)) => {}
+            Err(mpsc::error::TrySendError::Full(event)) => {
+                log::warn!("Set listener is full; dropped the set of {}", event.name);
+            }
+        }
+    }
+}
@@ -1,10 +1,19 @@
 // Copyright 2024 Jeff Kim <hiking90@gmail.com>
 // SPDX-License-Identifier: Apache-2.0
 
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
 use std::os::unix::fs::PermissionsExt;
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::Duration;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Once};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use log::{debug, error, info, trace, warn};
 use tokio::fs;
@@ -16,14 +25,29 @@ use tokio_stream::StreamExt;
 
 use rsactor::{Actor, ActorRef, ActorWeak};
 
+use crate::access::{AccessPolicy, Peer, PeerCredentials, PermissivePolicy};
+
 use rsproperties::errors::*;
 use rsproperties::wire::{
-    MAX_WIRE_NAME_LEN, MAX_WIRE_VALUE_LEN, PROP_ERROR, PROP_MSG_SETPROP, PROP_MSG_SETPROP2,
-    PROP_NAME_MAX, PROP_SUCCESS, PROP_VALUE_MAX,
+    encode_error_response, MAX_WIRE_BATCH, MAX_WIRE_NAME_LEN, MAX_WIRE_VALUE_LEN,
+    PROP_ERROR_INVALID_CMD, PROP_ERROR_INVALID_NAME, PROP_ERROR_INVALID_VALUE,
+    PROP_ERROR_READ_DATA, PROP_ERROR_SET_FAILED, PROP_MSG_SETPROP, PROP_MSG_SETPROP2,
+    PROP_MSG_SETPROP_BATCH, PROP_MSG_UNSETPROP, PROP_NAME_MAX, PROP_SUCCESS, PROP_VALUE_MAX,
 };
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use rsproperties::wire::{PROP_ERROR, PROP_MSG_GET_AREAS};
 
 /// Upper bound on simultaneously *serviced* client connections. Each
 /// handler task holds one permit for the duration of the exchange.
+///
+/// Together with [`MAX_WAITING_CLIENTS`] and [`CLIENT_TIMEOUT`] this is
+/// the whole backpressure story: a handler forwards its set with `ask`
+/// through the `PropertiesService` actor's bounded mailbox and answers the
+/// client only with the store's reply. A stalled store therefore fills the
+/// mailbox, then the permits, then the waiting slots; clients time out
+/// without a success status instead of sets piling up in memory.
+/// [`SocketStats::in_flight_connections`] and
+/// [`SocketStats::waiting_connections`] show how far it got.
 const MAX_CONCURRENT_CLIENTS: usize = 64;
 
 /// Upper bound on accepted connections *waiting* for a handler permit.
@@ -39,6 +63,13 @@ const MAX_WAITING_CLIENTS: usize = 256;
 /// stuck clients are torn down rather than tying up a task indefinitely.
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Deadline for each read of a request. A client that stops sending
+/// mid-message (half a length word, a name cut short) is dropped once it
+/// has been silent this long, instead of holding a handler slot for the
+/// whole `CLIENT_TIMEOUT`; a client trickling bytes is still bounded by
+/// `CLIENT_TIMEOUT`. Both expiries are counted in [`SocketStats`].
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Permissions applied to the bound Unix socket files. `0o660`
 /// (rw-rw----) matches the AOSP init policy for property service sockets
 /// — readable/writable by owner and group, denied to others. Without
@@ -46,14 +77,11 @@ const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 /// is environment-dependent and frequently leaves the socket
 /// world-readable.
 ///
-/// **Access model.** This file mode is the service's *entire* access
-/// control: any peer that can `connect()` (i.e. has write permission on
-/// the socket file — owner or group) may set any non-`ro.` property.
-/// Unlike AOSP init, no per-property SO_PEERCRED / property_contexts
-/// authorization is performed, and the "property" / "system" sockets
-/// share one handler; peer credentials are logged for auditability only.
-/// This is an intentional simplification for host-side deployments —
-/// restrict the socket directory / group membership accordingly.
+/// **Access model.** Any peer that can `connect()` (i.e. has write
+/// permission on the socket file — owner or group) reaches the handler;
+/// which properties it may then write is up to the [`AccessPolicy`],
+/// which by default ([`PermissivePolicy`]) allows every non-`ro.` one.
+/// The "property" / "system" sockets share one handler and one policy.
 const SOCKET_FILE_MODE: u32 = 0o660;
 
 /// Backoff applied when `accept()` returns an error. Without it, a
@@ -63,6 +91,11 @@ const SOCKET_FILE_MODE: u32 = 0o660;
 /// clears, long enough to dampen the loop.
 const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
 
+/// How long a graceful stop waits for in-flight connections: a waiting
+/// connection may spend `CLIENT_TIMEOUT` getting a handler slot and
+/// another `CLIENT_TIMEOUT` being served.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2 * CLIENT_TIMEOUT.as_secs());
+
 /// Applies `SOCKET_FILE_MODE` to a freshly-bound Unix socket file.
 /// `UnixListener::bind` creates the socket with permissions derived from
 /// the process umask; an explicit chmod removes that environmental
@@ -115,9 +148,332 @@ async fn bind_socket_with_mode(path: &Path) -> std::io::Result<UnixListener> {
     Ok(listener)
 }
 
+/// Lock file inside the socket directory, held (`flock`) for the
+/// service's lifetime and containing its PID. A second service pointed at
+/// the same directory fails to start instead of silently replacing the
+/// first one's sockets.
+const SERVICE_LOCK_FILENAME: &str = ".property_service.lock";
+
+/// How long the liveness probe of an existing socket may take. A live
+/// service accepts (or at least queues) the connection immediately; only
+/// a wedged listener with a full backlog takes this long, and it counts as
+/// live.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What to do when a socket path is already served by a live process that
+/// does not hold the service lock (e.g. a different property service
+/// implementation, or a version of this one that predates the lock).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TakeoverPolicy {
+    /// Fail to start with `AddrInUse` (default).
+    #[default]
+    Refuse,
+    /// Replace the socket anyway; the other process keeps its listener
+    /// but no longer receives new connections.
+    Replace,
+}
+
+/// Takes the service lock in `socket_dir`, recording our PID in it.
+/// Same hardening as the property area's writer lock: `O_NOFOLLOW` and
+/// mode 0600, so neither a planted symlink nor another user can squat it.
+fn acquire_service_lock(socket_dir: &Path) -> Result<std::fs::File> {
+    use rustix::fs as rfs;
+    use std::io::{Read, Seek, Write};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let lock_path = socket_dir.join(SERVICE_LOCK_FILENAME);
+    let mut lock_file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .custom_flags(rfs::OFlags::NOFOLLOW.bits() as _)
+        .mode(0o600)
+        .open(&lock_path)
+        .context_with_location(format!("Failed to open service lock {lock_path:?}"))?;
+    rfs::fchmod(&lock_file, rfs::Mode::RUSR | rfs::Mode::WUSR)
+        .context_with_location(format!("Failed to restrict mode of {lock_path:?}"))?;
+    if let Err(e) = rfs::flock(&lock_file, rfs::FlockOperation::NonBlockingLockExclusive) {
+        let mut holder = String::new();
+        let _ = lock_file.read_to_string(&mut holder);
+        let holder = holder.trim();
+        error!("Another property service (pid {holder}) owns {socket_dir:?}");
+        return Err(Error::Lock(format!(
+            "socket directory {socket_dir:?} is owned by another property service \
+             (pid {holder}): {e}"
+        )));
+    }
+    lock_file.set_len(0)?;
+    lock_file.rewind()?;
+    writeln!(lock_file, "{}", std::process::id())?;
+    Ok(lock_file)
+}
+
+/// Checks whether something is still accepting connections on `path`
+/// before it is replaced. Under the service lock, a live listener belongs
+/// to a process outside the lock protocol; `policy` decides whether to
+/// take the path over. A refused connection is a stale file left by a
+/// crashed service and is always replaced.
+async fn probe_existing_socket(path: &Path, policy: TakeoverPolicy) -> Result<()> {
+    match tokio::time::timeout(PROBE_TIMEOUT, UnixStream::connect(path)).await {
+        // Timed out: a wedged listener with a full backlog is still live.
+        Err(_) | Ok(Ok(_)) => {}
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            info!("Replacing stale socket {}", path.display());
+            return Ok(());
+        }
+        // Cannot tell (e.g. EACCES): treat like a live owner rather than
+        // risk hijacking someone else's socket.
+        Ok(Err(e)) => warn!("Could not probe existing socket {}: {e}", path.display()),
+    }
+    match policy {
+        TakeoverPolicy::Replace => {
+            warn!("Taking over socket {} from a live process", path.display());
+            Ok(())
+        }
+        TakeoverPolicy::Refuse => {
+            error!("Socket {} is served by a live process", path.display());
+            Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!(
+                    "{} is served by another process; stop it or use the Replace takeover policy",
+                    path.display()
+                ),
+            )
+            .into())
+        }
+    }
+}
+
+/// How often the accept loop may recover from a panic before the socket
+/// service gives up.
+///
+/// A panic while dispatching an accepted connection is caught, logged
+/// with its backtrace and counted in [`SocketStats::listener_restarts`];
+/// the listener then resumes accepting. More than `max_restarts`
+/// recoveries within `window` stop the service with an error instead, so
+/// a persistent fault surfaces (e.g. through
+/// [`crate::serve_until_signal`]) rather than looping. A panic inside a
+/// connection handler only ends that connection and is counted in
+/// [`SocketStats::handler_panics`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RestartPolicy {
+    pub max_restarts: u32,
+    pub window: Duration,
+}
+
+impl RestartPolicy {
+    pub fn new(max_restarts: u32, window: Duration) -> Self {
+        Self {
+            max_restarts,
+            window,
+        }
+    }
+}
+
+impl Default for RestartPolicy {
+    /// Five restarts per minute.
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Panic and timeout counters of a [`SocketService`] since it started,
+/// and the current depth of its connection queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SocketStats {
+    /// Connection handlers that panicked; each lost only its connection.
+    pub handler_panics: u64,
+    /// Times the accept loop recovered from a panic (see
+    /// [`RestartPolicy`]).
+    pub listener_restarts: u64,
+    /// Connections dropped because the client sent nothing for 2 s in
+    /// the middle of a request.
+    pub read_timeouts: u64,
+    /// Connections dropped because the whole exchange took more than
+    /// 10 s.
+    pub exchange_timeouts: u64,
+    /// Connections being served now, out of at most 64. Pinned at the
+    /// limit while the properties service does not keep up.
+    pub in_flight_connections: usize,
+    /// Accepted connections waiting for a handler slot, out of at most
+    /// 256; connections beyond that are dropped.
+    pub waiting_connections: usize,
+}
+
+fn is_read_timeout(e: &Error) -> bool {
+    matches!(e, Error::Io(io) if io.kind() == std::io::ErrorKind::TimedOut)
+}
+
+/// Counters the connection tasks update themselves.
+#[derive(Default)]
+struct HandlerCounters {
+    panics: AtomicU64,
+    read_timeouts: AtomicU64,
+    exchange_timeouts: AtomicU64,
+}
+
+/// `read_exact` under `READ_TIMEOUT`. An expiry is an I/O error of kind
+/// `TimedOut`, as the client reports its own timeouts.
+async fn read_exact_timed(stream: &mut UnixStream, buf: &mut [u8]) -> std::io::Result<()> {
+    match tokio::time::timeout(READ_TIMEOUT, stream.read_exact(buf)).await {
+        Ok(result) => result.map(drop),
+        Err(_elapsed) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("client sent nothing for {READ_TIMEOUT:?}"),
+        )),
+    }
+}
+
+pub(crate) struct StatsMessage;
+
+/// The code and reason the client of a batch gets for one entry.
+fn batch_status(reply: std::result::Result<(), SetError>) -> (i32, String) {
+    match reply {
+        Ok(()) => (PROP_SUCCESS, String::new()),
+        Err(e) => (e.code, e.message.unwrap_or_default()),
+    }
+}
+
+/// Accept-loop recoveries counted against a [`RestartPolicy`].
+pub(crate) struct RestartBudget {
+    policy: RestartPolicy,
+    /// Times of the recoveries within the policy window.
+    recent: VecDeque<Instant>,
+    total: u64,
+}
+
+impl RestartBudget {
+    pub(crate) fn new(policy: RestartPolicy) -> Self {
+        Self {
+            policy,
+            recent: VecDeque::new(),
+            total: 0,
+        }
+    }
+
+    /// Records a recovery at `now`; `false` when the budget is spent.
+    pub(crate) fn record(&mut self, now: Instant) -> bool {
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > self.policy.window)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= self.policy.max_restarts as usize {
+            return false;
+        }
+        self.recent.push_back(now);
+        self.total += 1;
+        true
+    }
+}
+
+thread_local! {
+    /// Set while a `CatchUnwind` polls, so the panic hook only pays for a
+    /// backtrace when the panic is going to be caught and reported here.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Chains a panic hook that keeps the backtrace of panics raised under
+/// `catch_panic` for `log_panic`. The previous hook still runs.
+pub(crate) fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(Cell::get) {
+                PANIC_BACKTRACE.with(|slot| *slot.borrow_mut() = Some(Backtrace::force_capture()));
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Runs `f`, catching a panic on this thread.
+pub(crate) fn catch_panic<R>(f: impl FnOnce() -> R) -> std::result::Result<R, Box<dyn Any + Send>> {
+    let was_catching = CATCHING.with(|c| c.replace(true));
+    let result = std::panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(was_catching));
+    result
+}
+
+/// Logs a panic caught by `catch_panic`, with its message and backtrace.
+pub(crate) fn log_panic(context: &str, payload: &(dyn Any + Send)) {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic payload>");
+    match PANIC_BACKTRACE.with(|slot| slot.borrow_mut().take()) {
+        Some(backtrace) => error!("{context} panicked: {message}\n{backtrace}"),
+        None => error!("{context} panicked: {message}"),
+    }
+}
+
+/// A future whose panics are caught (see `catch_panic`) and returned as
+/// the payload.
+pub(crate) struct CatchUnwind<F>(pub(crate) Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::result::Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match catch_panic(|| self.0.as_mut().poll(cx)) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+/// Arguments of a [`SocketService`]. `#[non_exhaustive]`: build with
+/// [`SocketServiceArgs::new`] and the `with_*` setters.
+#[non_exhaustive]
 pub struct SocketServiceArgs {
     pub socket_dir: PathBuf,
     pub properties_service: ActorRef<crate::PropertiesService>,
+    pub takeover: TakeoverPolicy,
+    pub restart_policy: RestartPolicy,
+    pub access_policy: Arc<dyn AccessPolicy>,
+}
+
+impl SocketServiceArgs {
+    pub fn new(
+        socket_dir: PathBuf,
+        properties_service: ActorRef<crate::PropertiesService>,
+    ) -> Self {
+        Self {
+            socket_dir,
+            properties_service,
+            takeover: TakeoverPolicy::default(),
+            restart_policy: RestartPolicy::default(),
+            access_policy: Arc::new(PermissivePolicy),
+        }
+    }
+
+    pub fn with_takeover(mut self, takeover: TakeoverPolicy) -> Self {
+        self.takeover = takeover;
+        self
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    pub fn with_access_policy(mut self, access_policy: Arc<dyn AccessPolicy>) -> Self {
+        self.access_policy = access_policy;
+        self
+    }
 }
 
 // Run the service in a separate task
@@ -149,6 +505,14 @@ pub struct SocketService {
     /// Limits accepted-but-not-yet-serviced connections (fd backpressure);
     /// see `MAX_WAITING_CLIENTS`.
     waiting_sem: Arc<Semaphore>,
+    restarts: RestartBudget,
+    access_policy: Arc<dyn AccessPolicy>,
+    /// Shared with the connection tasks, which count their own panics
+    /// and timeouts.
+    counters: Arc<HandlerCounters>,
+    /// Service lock (`SERVICE_LOCK_FILENAME`); held until the actor drops,
+    /// after `Drop` has removed the sockets.
+    _lock: std::fs::File,
 }
 
 impl Actor for SocketService {
@@ -173,6 +537,11 @@ impl Actor for SocketService {
             fs::create_dir_all(&args.socket_dir).await?;
         }
 
+        // Before touching anything in the directory: another service
+        // owning it must keep its sockets.
+        let lock = acquire_service_lock(&args.socket_dir)?;
+        install_panic_hook();
+
         let property_socket_path = args
             .socket_dir
             .join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
@@ -207,6 +576,8 @@ impl Actor for SocketService {
             property_socket_path.display(),
             system_socket_path.display()
         );
+        probe_existing_socket(&property_socket_path, args.takeover).await?;
+        probe_existing_socket(&system_socket_path, args.takeover).await?;
         let property_listener = bind_socket_with_mode(&property_socket_path).await?;
         let system_listener = bind_socket_with_mode(&system_socket_path).await?;
         info!("AsyncPropertySocketService started successfully");
@@ -229,6 +600,10 @@ impl Actor for SocketService {
             properties_service: args.properties_service,
             connection_sem: Arc::new(Semaphore::new(MAX_CONCURRENT_CLIENTS)),
             waiting_sem: Arc::new(Semaphore::new(MAX_WAITING_CLIENTS)),
+            restarts: RestartBudget::new(args.restart_policy),
+            access_policy: args.access_policy,
+            counters: Arc::new(HandlerCounters::default()),
+            _lock: lock,
         })
     }
 
@@ -260,15 +635,99 @@ impl Actor for SocketService {
             }
         };
 
-        // Peer credentials: logged for auditability — see the access-model
-        // note on `SOCKET_FILE_MODE` (no per-property authorization).
-        if let Ok(cred) = stream.peer_cred() {
-            debug!(
-                "Client connected on {source} listener (uid={}, gid={})",
-                cred.uid(),
-                cred.gid()
-            );
+        if let Err(payload) = catch_panic(|| self.dispatch(stream, source)) {
+            log_panic(&format!("{source} listener"), payload.as_ref());
+            self.record_restart(source)?;
         }
+        Ok(())
+    }
+
+    async fn on_stop(
+        &mut self,
+        _actor_weak: &ActorWeak<Self>,
+        killed: bool,
+    ) -> std::result::Result<(), Self::Error> {
+        // A graceful stop is normal operation — keep it at `info!` so log
+        // monitors don't alarm on routine shutdowns; only a kill warrants
+        // `warn!`.
+        if killed {
+            warn!("SocketService killed — cleaning up resources");
+            return Ok(());
+        }
+        info!("SocketService stopping gracefully");
+
+        // No more accepts happen once the actor loop has exited; drain the
+        // connections already accepted so their sets reach the properties
+        // service before it is stopped too. Every connection task holds a
+        // waiting-room or a handler permit, so owning all of both means
+        // none is left.
+        let drain = async {
+            let waiting = self
+                .waiting_sem
+                .acquire_many(MAX_WAITING_CLIENTS as u32)
+                .await;
+            let handlers = self
+                .connection_sem
+                .acquire_many(MAX_CONCURRENT_CLIENTS as u32)
+                .await;
+            (waiting, handlers)
+        };
+        if tokio::time::timeout(DRAIN_TIMEOUT, drain).await.is_err() {
+            warn!("Connections still in flight after {DRAIN_TIMEOUT:?}; stopping anyway");
+        }
+        Ok(())
+    }
+}
+
+impl rsactor::Message<StatsMessage> for SocketService {
+    type Reply = SocketStats;
+
+    async fn handle(&mut self, _message: StatsMessage, _actor_ref: &ActorRef<Self>) -> Self::Reply {
+        SocketStats {
+            handler_panics: self.counters.panics.load(Ordering::Relaxed),
+            listener_restarts: self.restarts.total,
+            read_timeouts: self.counters.read_timeouts.load(Ordering::Relaxed),
+            exchange_timeouts: self.counters.exchange_timeouts.load(Ordering::Relaxed),
+            in_flight_connections: MAX_CONCURRENT_CLIENTS - self.connection_sem.available_permits(),
+            waiting_connections: MAX_WAITING_CLIENTS - self.waiting_sem.available_permits(),
+        }
+    }
+}
+
+impl rsactor::Message<crate::ReadyMessage> for SocketService {
+    type Reply = ();
+
+    async fn handle(
+        &mut self,
+        _message: crate::ReadyMessage,
+        _actor_ref: &ActorRef<Self>,
+    ) -> Self::Reply {
+    }
+}
+
+impl SocketService {
+    /// Hands an accepted connection to a handler task. Synchronous, so the
+    /// accept loop can catch a panic here and keep its listener.
+    fn dispatch(&mut self, stream: UnixStream, source: &'static str) {
+        // Taken once per connection, for the `AccessPolicy` (see the
+        // access-model note on `SOCKET_FILE_MODE`).
+        let credentials = match PeerCredentials::of(&stream) {
+            Ok(credentials) => {
+                debug!(
+                    "Client connected on {source} listener (uid={}, gid={})",
+                    credentials.uid, credentials.gid
+                );
+                Some(credentials)
+            }
+            Err(e) => {
+                warn!("No credentials for {source} client, refusing its writes: {e}");
+                None
+            }
+        };
+        let peer = Peer {
+            credentials,
+            policy: self.access_policy.clone(),
+        };
 
         // Bound the number of concurrently in-flight client handlers
         // WITHOUT awaiting in the actor loop — the previous inline
@@ -285,11 +744,12 @@ impl Actor for SocketService {
             Ok(p) => p,
             Err(_) => {
                 warn!("Waiting room full; dropping {source} connection");
-                return Ok(()); // `stream` dropped → connection closed
+                return; // `stream` dropped → connection closed
             }
         };
         let sem = self.connection_sem.clone();
         let connection_sender = self.properties_service.clone();
+        let counters = self.counters.clone();
         tokio::spawn(async move {
             let permit = {
                 let _waiting = waiting; // released once a handler slot is ours
@@ -308,61 +768,61 @@ impl Actor for SocketService {
                 }
             };
             let _permit = permit; // dropped when the task ends
-            match tokio::time::timeout(
-                CLIENT_TIMEOUT,
-                Self::handle_client(stream, connection_sender),
-            )
-            .await
-            {
-                Ok(Ok(())) => {}
-                Ok(Err(e)) => error!("Error handling client: {e}"),
+            let handler = CatchUnwind(Box::pin(Self::handle_client(
+                stream,
+                connection_sender,
+                peer,
+            )));
+            match tokio::time::timeout(CLIENT_TIMEOUT, handler).await {
+                Ok(Ok(Ok(()))) => {}
+                Ok(Ok(Err(e))) if is_read_timeout(&e) => {
+                    counters.read_timeouts.fetch_add(1, Ordering::Relaxed);
+                    warn!("Dropping {source} connection: {e}");
+                }
+                Ok(Ok(Err(e))) => error!("Error handling client: {e}"),
+                Ok(Err(payload)) => {
+                    counters.panics.fetch_add(1, Ordering::Relaxed);
+                    log_panic(&format!("{source} connection handler"), payload.as_ref());
+                }
                 Err(_elapsed) => {
+                    counters.exchange_timeouts.fetch_add(1, Ordering::Relaxed);
                     warn!("Client exchange timed out after {CLIENT_TIMEOUT:?}, dropping connection")
                 }
             }
         });
-        Ok(())
     }
 
-    async fn on_stop(
-        &mut self,
-        _actor_weak: &ActorWeak<Self>,
-        killed: bool,
-    ) -> std::result::Result<(), Self::Error> {
-        // A graceful stop is normal operation — keep it at `info!` so log
-        // monitors don't alarm on routine shutdowns; only a kill warrants
-        // `warn!`.
-        if killed {
-            warn!("SocketService killed — cleaning up resources");
-        } else {
-            info!("SocketService stopping gracefully");
+    /// Counts an accept-loop recovery against the [`RestartPolicy`];
+    /// fails once the policy's budget is spent, which stops the actor.
+    fn record_restart(&mut self, source: &str) -> Result<()> {
+        let policy = self.restarts.policy;
+        if !self.restarts.record(Instant::now()) {
+            error!("{source} listener panicked too often; stopping the socket service");
+            return Err(Error::LimitExceeded(format!(
+                "{source} listener panicked more than {} times in {:?}",
+                policy.max_restarts, policy.window
+            )));
         }
+        warn!(
+            "Restarting {source} listener loop ({} of {} in {:?})",
+            self.restarts.recent.len(),
+            policy.max_restarts,
+            policy.window
+        );
         Ok(())
     }
-}
 
-impl rsactor::Message<crate::ReadyMessage> for SocketService {
-    type Reply = ();
-
-    async fn handle(
-        &mut self,
-        _message: crate::ReadyMessage,
-        _actor_ref: &ActorRef<Self>,
-    ) -> Self::Reply {
-    }
-}
-
-impl SocketService {
     /// Handles a client connection
     async fn handle_client(
         mut stream: UnixStream,
         service: ActorRef<crate::PropertiesService>,
+        peer: Peer,
     ) -> Result<()> {
         trace!("Handling new client connection");
 
         // Read the command (u32)
         let mut cmd_buf = [0u8; 4];
-        if let Err(e) = stream.read_exact(&mut cmd_buf).await {
+        if let Err(e) = read_exact_timed(&mut stream, &mut cmd_buf).await {
             // Connect-then-close without writing (port probes, health
             // checks) is routine — not worth an `error!` in the caller.
             if e.kind() == std::io::ErrorKind::UnexpectedEof {
@@ -378,15 +838,33 @@ impl SocketService {
         match cmd {
             PROP_MSG_SETPROP => {
                 trace!("Processing SETPROP (V1) command");
-                Self::handle_setprop_v1(&mut stream, service).await?;
+                Self::handle_setprop_v1(&mut stream, service, &peer).await?;
             }
             PROP_MSG_SETPROP2 => {
                 trace!("Processing SETPROP2 command");
-                Self::handle_setprop2(&mut stream, service).await?;
+                Self::handle_setprop2(&mut stream, service, &peer).await?;
+            }
+            PROP_MSG_UNSETPROP => {
+                trace!("Processing UNSETPROP command");
+                Self::handle_unsetprop(&mut stream, service, &peer).await?;
+            }
+            PROP_MSG_SETPROP_BATCH => {
+                trace!("Processing SETPROP_BATCH command");
+                Self::handle_setprop_batch(&mut stream, service, &peer).await?;
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            PROP_MSG_GET_AREAS => {
+                trace!("Processing GET_AREAS command");
+                Self::handle_get_areas(&mut stream, service, &peer).await?;
             }
             _ => {
                 warn!("Unknown command received: 0x{cmd:08X}");
-                Self::send_response(&mut stream, PROP_ERROR).await?;
+                Self::send_error(
+                    &mut stream,
+                    PROP_ERROR_INVALID_CMD,
+                    &format!("unknown command 0x{cmd:08X}"),
+                )
+                .await?;
             }
         }
 
@@ -394,6 +872,96 @@ impl SocketService {
         Ok(())
     }
 
+    /// Handles the rsproperties GET_AREAS command: replies with the memfds
+    /// of a memfd-backed store, each in its own message carrying the fd as
+    /// `SCM_RIGHTS` (see [`rsproperties::wire::PROP_MSG_GET_AREAS`]). A
+    /// file-backed store answers with an error, as AOSP init would.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    async fn handle_get_areas(
+        stream: &mut UnixStream,
+        service: ActorRef<crate::PropertiesService>,
+        peer: &Peer,
+    ) -> Result<()> {
+        if !peer.allows_areas() {
+            warn!("GET_AREAS denied by the access policy");
+            let _ = Self::send_error(
+                stream,
+                rsproperties::wire::PROP_ERROR_PERMISSION_DENIED,
+                "not allowed to fetch the property areas",
+            )
+            .await;
+            return Ok(());
+        }
+        let areas = match service
+            .ask(crate::properties_service::MemfdAreasMessage)
+            .await
+        {
+            Ok(Ok(areas)) => areas,
+            Ok(Err(e)) => {
+                debug!("GET_AREAS refused: {e}");
+                let _ = Self::send_error(stream, PROP_ERROR_INVALID_CMD, &e.to_string()).await;
+                return Ok(());
+            }
+            Err(e) => {
+                error!("Failed to fetch memfd areas: {e}");
+                let _ = Self::send_error(stream, PROP_ERROR, "property service unavailable").await;
+                return Ok(());
+            }
+        };
+        debug!("Handing out {} memfd areas", areas.len());
+
+        let mut header = PROP_SUCCESS.to_ne_bytes().to_vec();
+        header.extend_from_slice(&(areas.len() as u32).to_ne_bytes());
+        stream.write_all(&header).await?;
+        for (name, fd) in &areas {
+            let mut frame = (name.len() as u32).to_ne_bytes().to_vec();
+            frame.extend_from_slice(name.as_bytes());
+            Self::send_with_fd(stream, &frame, fd).await?;
+        }
+        stream.flush().await?;
+        Ok(())
+    }
+
+    /// Sends `bytes` with `fd` attached to the first of them. The client
+    /// reads each such message with a separate `recvmsg`, so they must
+    /// not share a write with other data.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    async fn send_with_fd(
+        stream: &UnixStream,
+        bytes: &[u8],
+        fd: &std::os::fd::OwnedFd,
+    ) -> Result<()> {
+        use rustix::net::{sendmsg, SendAncillaryBuffer, SendAncillaryMessage, SendFlags};
+        use std::mem::MaybeUninit;
+        use std::os::fd::AsFd;
+
+        let fds = [fd.as_fd()];
+        let mut sent = 0;
+        while sent < bytes.len() {
+            stream.writable().await?;
+            let result = stream.try_io(tokio::io::Interest::WRITABLE, || {
+                let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(1))];
+                let mut control = SendAncillaryBuffer::new(&mut space);
+                if sent == 0 {
+                    control.push(SendAncillaryMessage::ScmRights(&fds));
+                }
+                sendmsg(
+                    stream,
+                    &[std::io::IoSlice::new(&bytes[sent..])],
+                    &mut control,
+                    SendFlags::NOSIGNAL,
+                )
+                .map_err(std::io::Error::from)
+            });
+            match result {
+                Ok(n) => sent += n,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
     /// Handles the legacy V1 SETPROP command: after the already-consumed
     /// command word, a fixed-size payload of `PROP_NAME_MAX` name bytes and
     /// `PROP_VALUE_MAX` value bytes, both NUL-padded.
@@ -405,13 +973,14 @@ impl SocketService {
     async fn handle_setprop_v1(
         stream: &mut UnixStream,
         service: ActorRef<crate::PropertiesService>,
+        peer: &Peer,
     ) -> Result<()> {
         trace!("Handling SETPROP (V1) request");
 
         let mut name_buf = [0u8; PROP_NAME_MAX];
-        stream.read_exact(&mut name_buf).await?;
+        read_exact_timed(stream, &mut name_buf).await?;
         let mut value_buf = [0u8; PROP_VALUE_MAX];
-        stream.read_exact(&mut value_buf).await?;
+        read_exact_timed(stream, &mut value_buf).await?;
         // AOSP V1 parity: init forces the last byte of both fields to NUL
         // before use (`prop_name[PROP_NAME_MAX-1] = 0`), capping names at
         // 31 chars and values at 91 bytes. Without this a non-bionic client
@@ -423,12 +992,16 @@ impl SocketService {
         let value = Self::string_from_fixed(&value_buf)?;
         info!("Forwarding V1 property: '{name}' ({} bytes)", value.len());
 
-        let property_msg = crate::PropertyMessage { name, value };
+        let property_msg = crate::PropertyMessage {
+            name,
+            value,
+            peer: Some(peer.clone()),
+        };
         match service.ask(property_msg).await {
-            Ok(true) => {}
+            Ok(Ok(())) => {}
             // The property name was already logged by the `info!` above;
             // mirroring the V2 handler, the result logs omit it.
-            Ok(false) => warn!("V1 property was rejected by service"),
+            Ok(Err(e)) => warn!("V1 property was rejected by service: {e}"),
             Err(e) => error!("Failed to forward V1 property: {e}"),
         }
 
@@ -457,9 +1030,117 @@ impl SocketService {
     async fn handle_setprop2(
         stream: &mut UnixStream,
         service: ActorRef<crate::PropertiesService>,
+        peer: &Peer,
     ) -> Result<()> {
         trace!("Handling SETPROP2 request");
+        let (name, value) = Self::read_name_value(stream).await?;
+
+        info!("Forwarding property: '{name}' ({} bytes)", value.len());
+
+        let property_msg = crate::PropertyMessage {
+            name,
+            value,
+            peer: Some(peer.clone()),
+        };
+
+        match service.ask(property_msg).await {
+            Ok(Ok(())) => Self::send_response(stream, PROP_SUCCESS).await?,
+            Ok(Err(e)) => {
+                warn!("Property message was not processed by service");
+                let message = e.message.as_deref().unwrap_or_default();
+                Self::send_error(stream, e.code, message).await?;
+            }
+            Err(e) => {
+                error!("Failed to send property message through channel: {e}");
+                Self::send_error(
+                    stream,
+                    PROP_ERROR_SET_FAILED,
+                    "property service unavailable",
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles the rsproperties SETPROP_BATCH command: a count and that
+    /// many SETPROP2 name/value pairs, answered with one status per entry
+    /// (see [`rsproperties::wire::PROP_MSG_SETPROP_BATCH`]). The whole
+    /// frame is read before anything is applied, and the entries go to
+    /// the store in one message.
+    async fn handle_setprop_batch(
+        stream: &mut UnixStream,
+        service: ActorRef<crate::PropertiesService>,
+        peer: &Peer,
+    ) -> Result<()> {
+        let count = Self::read_u32(stream).await? as usize;
+        if count > MAX_WIRE_BATCH {
+            error!("Batch too large: {count} entries (max {MAX_WIRE_BATCH})");
+            let _ = Self::send_error(
+                stream,
+                PROP_ERROR_READ_DATA,
+                &format!("batch of {count} entries exceeds the wire cap ({MAX_WIRE_BATCH})"),
+            )
+            .await;
+            return Err(rsproperties::errors::Error::FileValidation(format!(
+                "Batch too large: {count}"
+            )));
+        }
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            entries.push(Self::read_name_value(stream).await?);
+        }
+
+        let sets: Vec<_> = entries
+            .into_iter()
+            .map(|(name, value)| crate::PropertyMessage {
+                name,
+                value,
+                peer: Some(peer.clone()),
+            })
+            .collect();
+        info!("Forwarding a batch of {count} properties");
 
+        let replies = if sets.is_empty() {
+            Vec::new()
+        } else {
+            match service
+                .ask(crate::properties_service::BatchMessage { sets })
+                .await
+            {
+                Ok(replies) => replies,
+                Err(e) => {
+                    error!("Failed to send batch message through channel: {e}");
+                    let unavailable = SetError::new(
+                        "",
+                        PROP_ERROR_SET_FAILED,
+                        Some("property service unavailable".to_owned()),
+                    );
+                    vec![Err(unavailable); count]
+                }
+            }
+        };
+        let mut replies = replies.into_iter();
+        let mut response = Vec::new();
+        response.extend_from_slice(&PROP_SUCCESS.to_ne_bytes());
+        response.extend_from_slice(&(count as u32).to_ne_bytes());
+        for _ in 0..count {
+            let (code, message) = match replies.next() {
+                Some(reply) => batch_status(reply),
+                None => (PROP_ERROR_SET_FAILED, "no reply from the store".to_owned()),
+            };
+            response.extend_from_slice(&encode_error_response(code, &message));
+        }
+        stream.write_all(&response).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    /// Reads the length-prefixed name and value of a SETPROP2 frame. A
+    /// frame it cannot read is answered with a V2 error code before the
+    /// error is returned.
+    async fn read_name_value(stream: &mut UnixStream) -> Result<(String, String)> {
         // Read name length and name
         let name_len = Self::read_u32(stream).await?;
         trace!("Name length: {name_len}");
@@ -469,7 +1150,12 @@ impl SocketService {
             // Best-effort like every other V2 failure response: `?` here
             // would replace the real error with a write failure when the
             // peer is already gone.
-            let _ = Self::send_response(stream, PROP_ERROR).await;
+            let _ = Self::send_error(
+                stream,
+                PROP_ERROR_INVALID_NAME,
+                &format!("name length {name_len} exceeds the wire cap ({MAX_WIRE_NAME_LEN} bytes)"),
+            )
+            .await;
             return Err(rsproperties::errors::Error::FileValidation(format!(
                 "Name length too large: {name_len}"
             )));
@@ -484,7 +1170,7 @@ impl SocketService {
         let name = match Self::read_string(stream, name_len as usize).await {
             Ok(name) => name,
             Err(e) => {
-                let _ = Self::send_response(stream, PROP_ERROR).await;
+                let _ = Self::send_error(stream, PROP_ERROR_READ_DATA, &e.to_string()).await;
                 return Err(e);
             }
         };
@@ -494,7 +1180,7 @@ impl SocketService {
         let value_len = match Self::read_u32(stream).await {
             Ok(len) => len,
             Err(e) => {
-                let _ = Self::send_response(stream, PROP_ERROR).await;
+                let _ = Self::send_error(stream, PROP_ERROR_READ_DATA, &e.to_string()).await;
                 return Err(e);
             }
         };
@@ -503,7 +1189,14 @@ impl SocketService {
         if value_len as usize > MAX_WIRE_VALUE_LEN {
             error!("Value length too large: {value_len} (max {MAX_WIRE_VALUE_LEN})");
             // Best-effort — see the name-length branch above.
-            let _ = Self::send_response(stream, PROP_ERROR).await;
+            let _ = Self::send_error(
+                stream,
+                PROP_ERROR_INVALID_VALUE,
+                &format!(
+                    "value length {value_len} exceeds the wire cap ({MAX_WIRE_VALUE_LEN} bytes)"
+                ),
+            )
+            .await;
             return Err(rsproperties::errors::Error::FileValidation(format!(
                 "Value length too large: {value_len}"
             )));
@@ -512,7 +1205,7 @@ impl SocketService {
         let value = match Self::read_string(stream, value_len as usize).await {
             Ok(value) => value,
             Err(e) => {
-                let _ = Self::send_response(stream, PROP_ERROR).await;
+                let _ = Self::send_error(stream, PROP_ERROR_READ_DATA, &e.to_string()).await;
                 return Err(e);
             }
         };
@@ -520,20 +1213,61 @@ impl SocketService {
         // are public on the wire (and surface in getprop output), but
         // values are not.
         debug!("Property value length: {} bytes", value.len());
+        Ok((name, value))
+    }
 
-        info!("Forwarding property: '{name}' ({} bytes)", value.len());
+    /// Handles the rsproperties UNSETPROP command: a length-prefixed name,
+    /// answered with a V2 status (see
+    /// [`rsproperties::wire::PROP_MSG_UNSETPROP`]).
+    async fn handle_unsetprop(
+        stream: &mut UnixStream,
+        service: ActorRef<crate::PropertiesService>,
+        peer: &Peer,
+    ) -> Result<()> {
+        let name_len = Self::read_u32(stream).await?;
+        if name_len as usize > MAX_WIRE_NAME_LEN {
+            error!("Name length too large: {name_len} (max {MAX_WIRE_NAME_LEN})");
+            // Best-effort, as in `read_name_value`.
+            let _ = Self::send_error(
+                stream,
+                PROP_ERROR_INVALID_NAME,
+                &format!("name length {name_len} exceeds the wire cap ({MAX_WIRE_NAME_LEN} bytes)"),
+            )
+            .await;
+            return Err(rsproperties::errors::Error::FileValidation(format!(
+                "Name length too large: {name_len}"
+            )));
+        }
+        let name = match Self::read_string(stream, name_len as usize).await {
+            Ok(name) => name,
+            Err(e) => {
+                let _ = Self::send_error(stream, PROP_ERROR_READ_DATA, &e.to_string()).await;
+                return Err(e);
+            }
+        };
 
-        let property_msg = crate::PropertyMessage { name, value };
+        info!("Forwarding removal of property: '{name}'");
 
-        match service.ask(property_msg).await {
-            Ok(true) => Self::send_response(stream, PROP_SUCCESS).await?,
-            Ok(false) => {
-                warn!("Property message was not processed by service");
-                Self::send_response(stream, PROP_ERROR).await?;
+        match service
+            .ask(crate::properties_service::UnsetMessage {
+                name,
+                peer: peer.clone(),
+            })
+            .await
+        {
+            Ok(Ok(())) => Self::send_response(stream, PROP_SUCCESS).await?,
+            Ok(Err(e)) => {
+                let message = e.message.as_deref().unwrap_or_default();
+                Self::send_error(stream, e.code, message).await?;
             }
             Err(e) => {
-                error!("Failed to send property message through channel: {e}");
-                Self::send_response(stream, PROP_ERROR).await?;
+                error!("Failed to send unset message through channel: {e}");
+                Self::send_error(
+                    stream,
+                    PROP_ERROR_SET_FAILED,
+                    "property service unavailable",
+                )
+                .await?;
             }
         }
 
@@ -543,7 +1277,7 @@ impl SocketService {
     /// Reads a u32 value from the stream
     async fn read_u32(stream: &mut UnixStream) -> Result<u32> {
         let mut buf = [0u8; 4];
-        stream.read_exact(&mut buf).await?;
+        read_exact_timed(stream, &mut buf).await?;
         Ok(u32::from_ne_bytes(buf))
     }
 
@@ -554,7 +1288,7 @@ impl SocketService {
         }
 
         let mut buf = vec![0u8; len];
-        stream.read_exact(&mut buf).await?;
+        read_exact_timed(stream, &mut buf).await?;
 
         // Reject NUL bytes instead of truncating at the first one: V2
         // strings are length-prefixed and sent without a terminator
@@ -574,6 +1308,18 @@ impl SocketService {
         String::from_utf8(buf).map_err(|e| rsproperties::errors::Error::Utf8(e.utf8_error()))
     }
 
+    /// Sends an error code followed by its reason (see
+    /// `wire::encode_error_response`). The reason must not contain the
+    /// property value.
+    async fn send_error(stream: &mut UnixStream, code: i32, message: &str) -> Result<()> {
+        trace!("Sending error response: {code:#x}");
+        stream
+            .write_all(&encode_error_response(code, message))
+            .await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
     /// Sends a response to the client
     async fn send_response(stream: &mut UnixStream, response: i32) -> Result<()> {
         trace!("Sending response: {response}");
@@ -590,11 +1336,11 @@ impl Drop for SocketService {
 
         // Drop runs in sync context — keep blocking std::fs here (rare path).
         //
-        // Note: this unlinks by *name* without verifying the file is the
-        // one this instance bound. If a new SocketService instance binds
-        // the same paths before an old instance drops, the old Drop would
-        // remove the new instance's live sockets — don't run two instances
-        // against one socket_dir (the design assumes a single service).
+        // This unlinks by *name*. That is safe because the service lock is
+        // still held here (the `_lock` field drops after this body): no
+        // other instance can have bound these paths in the meantime —
+        // except one started with `TakeoverPolicy::Replace` against a
+        // lock-less owner, which accepted that risk.
         for socket_name in [
             rsproperties::PROPERTY_SERVICE_SOCKET_NAME,
             rsproperties::PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME,
@@ -610,3 +1356,42 @@ impl Drop for SocketService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_budget() {
+        let mut budget = RestartBudget::new(RestartPolicy::new(2, Duration::from_secs(60)));
+        let start = Instant::now();
+        assert!(budget.record(start));
+        assert!(budget.record(start + Duration::from_secs(1)));
+        assert!(!budget.record(start + Duration::from_secs(2)));
+        // The first recovery leaves the window.
+        assert!(budget.record(start + Duration::from_secs(61)));
+        assert_eq!(budget.total, 3);
+
+        let mut never = RestartBudget::new(RestartPolicy::new(0, Duration::from_secs(60)));
+        assert!(!never.record(start));
+    }
+
+    #[tokio::test]
+    async fn test_catch_unwind_keeps_backtrace() {
+        install_panic_hook();
+        let ok = CatchUnwind(Box::pin(async { 7 })).await;
+        assert_eq!(ok.unwrap(), 7);
+
+        let caught = CatchUnwind(Box::pin(async {
+            tokio::task::yield_now().await;
+            panic!("handler bug");
+        }))
+        .await;
+        let payload = caught.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"handler bug"));
+        assert!(PANIC_BACKTRACE.with(|slot| slot.borrow().is_some()));
+        log_panic("test handler", payload.as_ref());
+        assert!(PANIC_BACKTRACE.with(|slot| slot.borrow().is_none()));
+        assert!(!CATCHING.with(Cell::get));
+    }
+}
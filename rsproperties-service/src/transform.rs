@@ -0,0 +1,182 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rewrites applied to sets on their way into the store, see
+//! [`TransformChain`].
+
+use std::sync::Arc;
+
+/// A custom rewrite: the new name and value, or the reason to refuse the
+/// set.
+pub type TransformFn = dyn Fn(&str, &str) -> Result<(String, String), String> + Send + Sync;
+
+/// One rewrite of a set, see [`TransformChain::add`].
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum Transform {
+    /// Trims surrounding ASCII whitespace from the value.
+    TrimValue,
+    /// Stores boolean spellings as `"1"` (`true`, `yes`, `on`, `y`) and
+    /// `"0"` (`false`, `no`, `off`, `n`), ignoring ASCII case — the form
+    /// Android components expect. Other values pass unchanged.
+    NormalizeBool,
+    /// Replaces the rule's prefix of the name with this one, for clients
+    /// still writing legacy names.
+    RenamePrefix(String),
+    /// Any other rewrite. An `Err` refuses the set; its message is sent
+    /// back to the client, so it must not contain the value.
+    Custom(Arc<TransformFn>),
+}
+
+impl std::fmt::Debug for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TrimValue => f.write_str("TrimValue"),
+            Self::NormalizeBool => f.write_str("NormalizeBool"),
+            Self::RenamePrefix(to) => f.debug_tuple("RenamePrefix").field(to).finish(),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Transform {
+    /// [`Transform::Custom`] from a closure.
+    pub fn custom(
+        f: impl Fn(&str, &str) -> Result<(String, String), String> + Send + Sync + 'static,
+    ) -> Self {
+        Self::Custom(Arc::new(f))
+    }
+
+    fn apply(&self, prefix: &str, name: String, value: String) -> Result<(String, String), String> {
+        match self {
+            Self::TrimValue => {
+                let trimmed = value.trim_matches(|c: char| c.is_ascii_whitespace());
+                let value = if trimmed.len() == value.len() {
+                    value
+                } else {
+                    trimmed.to_owned()
+                };
+                Ok((name, value))
+            }
+            Self::NormalizeBool => {
+                let normalized = match value.to_ascii_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" | "y" => Some("1"),
+                    "0" | "false" | "no" | "off" | "n" => Some("0"),
+                    _ => None,
+                };
+                Ok((name, normalized.map_or(value, str::to_owned)))
+            }
+            Self::RenamePrefix(to) => Ok((format!("{to}{}", &name[prefix.len()..]), value)),
+            Self::Custom(f) => f(&name, &value),
+        }
+    }
+}
+
+/// Per-prefix rewrites the property service applies to every set after
+/// canonicalizing its name and before validating and storing it, so value
+/// hygiene is enforced in one place instead of in every client.
+///
+/// Rules run in the order they were added, each one only if the name *as
+/// the previous rules left it* starts with its prefix (an empty prefix
+/// matches every name). The result is validated like any set, so a rule
+/// cannot smuggle in a name or value a client could not have sent.
+///
+/// ```rust
+/// use rsproperties_service::transform::{Transform, TransformChain};
+///
+/// let chain = TransformChain::new()
+///     .add("legacy.wifi.", Transform::RenamePrefix("vendor.wifi.".into()))
+///     .add("", Transform::TrimValue)
+///     .add("vendor.wifi.", Transform::NormalizeBool);
+/// assert_eq!(
+///     chain.apply("legacy.wifi.enabled".into(), " true ".into()),
+///     Ok(("vendor.wifi.enabled".into(), "1".into()))
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TransformChain {
+    rules: Vec<(String, Transform)>,
+}
+
+impl TransformChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a rule for names starting with `prefix`.
+    pub fn add(mut self, prefix: impl Into<String>, transform: Transform) -> Self {
+        self.rules.push((prefix.into(), transform));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Runs the matching rules over a set; `Err` carries the refusing
+    /// rule's message.
+    pub fn apply(&self, name: String, value: String) -> Result<(String, String), String> {
+        self.rules
+            .iter()
+            .try_fold((name, value), |(name, value), (prefix, transform)| {
+                if name.starts_with(prefix.as_str()) {
+                    transform.apply(prefix, name, value)
+                } else {
+                    Ok((name, value))
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(chain: &TransformChain, name: &str, value: &str) -> Result<(String, String), String> {
+        chain.apply(name.to_owned(), value.to_owned())
+    }
+
+    #[test]
+    fn test_builtin_transforms() {
+        let chain = TransformChain::new()
+            .add("persist.", Transform::TrimValue)
+            .add("persist.sys.flag.", Transform::NormalizeBool);
+        assert_eq!(
+            set(&chain, "persist.sys.flag.a", " Yes\t"),
+            Ok(("persist.sys.flag.a".into(), "1".into()))
+        );
+        assert_eq!(
+            set(&chain, "persist.sys.flag.b", "OFF"),
+            Ok(("persist.sys.flag.b".into(), "0".into()))
+        );
+        // Not a boolean spelling, and a prefix that does not match.
+        assert_eq!(
+            set(&chain, "persist.sys.flag.c", "maybe"),
+            Ok(("persist.sys.flag.c".into(), "maybe".into()))
+        );
+        assert_eq!(
+            set(&chain, "sys.flag", " true "),
+            Ok(("sys.flag".into(), " true ".into()))
+        );
+    }
+
+    #[test]
+    fn test_rules_see_earlier_rewrites() {
+        let chain = TransformChain::new()
+            .add("old.", Transform::RenamePrefix("new.".into()))
+            .add("new.", Transform::NormalizeBool)
+            .add(
+                "new.locked.",
+                Transform::custom(|name, _| Err(format!("{name} is managed centrally"))),
+            );
+        assert_eq!(
+            set(&chain, "old.feature", "true"),
+            Ok(("new.feature".into(), "1".into()))
+        );
+        assert_eq!(
+            set(&chain, "old.locked.x", "1"),
+            Err("new.locked.x is managed centrally".into())
+        );
+        assert!(TransformChain::new().is_empty());
+    }
+}
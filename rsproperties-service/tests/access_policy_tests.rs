@@ -0,0 +1,219 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The service asks its `AccessPolicy` before every write, handing it the
+//! client's credentials, the name as it is stored and that name's
+//! context, and answers a refused write with
+//! `PROP_ERROR_PERMISSION_DENIED`.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use rsproperties::wire::{NamePolicy, PROP_ERROR_PERMISSION_DENIED, PROP_MSG_SETPROP2};
+use rsproperties::{PropertiesClient, PropertyConfig, SystemProperties};
+use rsproperties_service::{
+    run_tenants, AccessPolicy, PeerCredentials, ServiceConfig, ServiceOptions, TenantConfig,
+    Transform, TransformChain,
+};
+
+mod common;
+use common::temp_dir;
+
+/// Refuses writes to the vendor context and records what it was asked.
+#[derive(Debug, Default)]
+struct NoVendor {
+    seen: Mutex<Vec<(PeerCredentials, String, Option<String>)>>,
+}
+
+impl AccessPolicy for NoVendor {
+    fn check(&self, credentials: &PeerCredentials, name: &str, context: Option<&str>) -> bool {
+        self.seen
+            .lock()
+            .unwrap()
+            .push((*credentials, name.to_owned(), context.map(str::to_owned)));
+        context != Some("u:object_r:vendor_prop:s0")
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_access_policy() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = temp_dir("access_policy");
+    std::fs::create_dir_all(&dir).unwrap();
+    let contexts = dir.join("property_contexts");
+    std::fs::write(
+        &contexts,
+        "vendor. u:object_r:vendor_prop:s0\n\
+         test. u:object_r:test_prop:s0\n",
+    )
+    .unwrap();
+    let policy = Arc::new(NoVendor::default());
+    let sockets = dir.join("sockets");
+    let tenant =
+        TenantConfig::new("t", dir.join("t"), &sockets).property_contexts_files(vec![contexts]);
+    let config = ServiceConfig::default()
+        .tenant(tenant)
+        .options(ServiceOptions::default().access_policy(policy.clone()));
+    let tenants = run_tenants(config).await.unwrap();
+    let store = SystemProperties::open(&dir.join("t")).unwrap();
+
+    let client =
+        PropertiesClient::new(PropertyConfig::with_both_dirs(dir.join("t"), &sockets)).unwrap();
+    client.set("test.access.mode", "1").unwrap();
+    let err = client.set("vendor.access.mode", "1").unwrap_err();
+    assert!(
+        matches!(err, rsproperties::Error::ServiceError(ref e) if e.code == PROP_ERROR_PERMISSION_DENIED),
+        "{err:?}"
+    );
+    let err = client.remove("vendor.access.mode").unwrap_err();
+    assert!(
+        matches!(err, rsproperties::Error::ServiceError(ref e) if e.code == PROP_ERROR_PERMISSION_DENIED),
+        "{err:?}"
+    );
+    assert_eq!(store.get_with_result("test.access.mode").unwrap(), "1");
+    assert!(store.get_with_result("vendor.access.mode").is_err());
+
+    let seen = policy.seen.lock().unwrap().clone();
+    let names: Vec<_> = seen
+        .iter()
+        .map(|(_, name, context)| (name.as_str(), context.as_deref()))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("test.access.mode", Some("u:object_r:test_prop:s0")),
+            ("vendor.access.mode", Some("u:object_r:vendor_prop:s0")),
+            ("vendor.access.mode", Some("u:object_r:vendor_prop:s0")),
+        ]
+    );
+    // The client is this process.
+    let uid = std::fs::metadata(&dir).unwrap().uid();
+    for (credentials, _, _) in &seen {
+        assert_eq!(credentials.uid, uid);
+        assert_eq!(credentials.pid, Some(std::process::id() as i32));
+    }
+
+    drop(store);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// A SETPROP2 frame with a name the library client would refuse to send.
+async fn setprop2_raw(socket_dir: &Path, name: &str, value: &str) -> i32 {
+    let socket_path = socket_dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_SETPROP2.to_ne_bytes());
+    msg.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(name.as_bytes());
+    msg.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(value.as_bytes());
+    stream.write_all(&msg).await.unwrap();
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).await.unwrap();
+    i32::from_ne_bytes(status)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_policy_sees_the_stored_name() {
+    let dir = temp_dir("access_canonical");
+    std::fs::create_dir_all(&dir).unwrap();
+    let contexts = dir.join("property_contexts");
+    std::fs::write(
+        &contexts,
+        "vendor. u:object_r:vendor_prop:s0\n\
+         test. u:object_r:test_prop:s0\n",
+    )
+    .unwrap();
+    let policy = Arc::new(NoVendor::default());
+    let sockets = dir.join("sockets");
+    let tenant =
+        TenantConfig::new("t", dir.join("t"), &sockets).property_contexts_files(vec![contexts]);
+    let options = ServiceOptions::default()
+        .access_policy(policy.clone())
+        .name_policy(NamePolicy::default().lowercase(true))
+        .transforms(
+            TransformChain::new().add("test.legacy.", Transform::RenamePrefix("vendor.".into())),
+        );
+    let tenants = run_tenants(ServiceConfig::default().tenant(tenant).options(options))
+        .await
+        .unwrap();
+    let store = SystemProperties::open(&dir.join("t")).unwrap();
+
+    // Spellings of a denied name that only become it on the way in.
+    for name in [
+        " vendor.access.x",
+        "VENDOR.ACCESS.X",
+        "test.legacy.access.x",
+    ] {
+        assert_eq!(
+            setprop2_raw(&sockets, name, "1").await,
+            PROP_ERROR_PERMISSION_DENIED,
+            "{name:?}"
+        );
+    }
+    assert!(store.get_with_result("vendor.access.x").is_err());
+
+    let seen = policy.seen.lock().unwrap().clone();
+    for (_, name, context) in &seen {
+        assert_eq!(name, "vendor.access.x");
+        assert_eq!(context.as_deref(), Some("u:object_r:vendor_prop:s0"));
+    }
+    assert_eq!(seen.len(), 3);
+    let stats = tenants[0].stats().await.unwrap();
+    assert_eq!((stats.service.denied, stats.service.rejected), (3, 0));
+
+    drop(store);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Refuses every client the memfd areas.
+#[derive(Debug)]
+struct NoAreas;
+
+impl AccessPolicy for NoAreas {
+    fn check(&self, _credentials: &PeerCredentials, _name: &str, _context: Option<&str>) -> bool {
+        true
+    }
+
+    fn check_areas(&self, _credentials: &PeerCredentials) -> bool {
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_areas_denied() {
+    let dir = temp_dir("access_areas");
+    std::fs::create_dir_all(&dir).unwrap();
+    let sockets = dir.join("sockets");
+    let tenant = TenantConfig::new("t", dir.join("t"), &sockets);
+    let options = ServiceOptions::default().access_policy(NoAreas);
+    let tenants = run_tenants(ServiceConfig::default().tenant(tenant).options(options))
+        .await
+        .unwrap();
+
+    let socket_path = sockets.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+    stream
+        .write_all(&rsproperties::wire::PROP_MSG_GET_AREAS.to_ne_bytes())
+        .await
+        .unwrap();
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).await.unwrap();
+    assert_eq!(i32::from_ne_bytes(status), PROP_ERROR_PERMISSION_DENIED);
+
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,231 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The SETPROP_BATCH extension: `set_many` sets every entry over one
+//! connection per batch and reports each entry's status, the service
+//! refuses an oversized batch as a whole, and a service without the
+//! extension gets the entries one by one.
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use rsproperties::wire::{
+    MAX_WIRE_BATCH, MAX_WIRE_VALUE_LEN, PROP_ERROR_INVALID_CMD, PROP_ERROR_PERMISSION_DENIED,
+    PROP_ERROR_READ_DATA, PROP_ERROR_READ_ONLY_PROPERTY, PROP_MSG_SETPROP2, PROP_MSG_SETPROP_BATCH,
+    PROP_SUCCESS,
+};
+use rsproperties::{PropertiesClient, PropertyConfig, SystemProperties};
+use rsproperties_service::{
+    run_tenants, AccessPolicy, PeerCredentials, ServiceConfig, ServiceOptions, TenantConfig,
+};
+
+mod common;
+use common::temp_dir;
+
+/// Refuses the `test.batch.denied.` properties.
+#[derive(Debug)]
+struct DenyPrefix;
+
+impl AccessPolicy for DenyPrefix {
+    fn check(&self, _credentials: &PeerCredentials, name: &str, _context: Option<&str>) -> bool {
+        !name.starts_with("test.batch.denied.")
+    }
+}
+
+fn service_code(result: &rsproperties::Result<()>) -> Option<i32> {
+    match result {
+        Err(rsproperties::Error::ServiceError(e)) => Some(e.code),
+        _ => None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_many_reports_each_entry() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = temp_dir("batch_entries");
+    let build_prop = dir.join("build.prop");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(&build_prop, "ro.test.batch.sku=a\n").unwrap();
+    let tenant = TenantConfig::new("t", dir.join("t"), dir.join("sockets"))
+        .build_prop_files(vec![build_prop]);
+    let config = ServiceConfig::default()
+        .tenant(tenant)
+        .options(ServiceOptions::default().access_policy(DenyPrefix));
+    let sockets = dir.join("sockets");
+    let tenants = run_tenants(config).await.unwrap();
+    let client =
+        PropertiesClient::new(PropertyConfig::with_both_dirs(dir.join("t"), &sockets)).unwrap();
+
+    let results = client
+        .set_many(&[
+            ("test.batch.a", "1"),
+            ("ro.test.batch.sku", "b"),
+            ("test..batch", "1"),
+            ("test.batch.denied.x", "1"),
+            ("test.batch.b", "2"),
+        ])
+        .unwrap();
+    assert_eq!(results.len(), 5);
+    assert!(results[0].is_ok());
+    assert_eq!(
+        service_code(&results[1]),
+        Some(PROP_ERROR_READ_ONLY_PROPERTY)
+    );
+    // Refused by the client, never sent.
+    assert!(matches!(
+        results[2],
+        Err(rsproperties::Error::InvalidArgument(_))
+    ));
+    assert_eq!(
+        service_code(&results[3]),
+        Some(PROP_ERROR_PERMISSION_DENIED)
+    );
+    assert!(results[4].is_ok());
+    let store = SystemProperties::open(&dir.join("t")).unwrap();
+    assert_eq!(store.get_with_result("test.batch.a").unwrap(), "1");
+    assert_eq!(store.get_with_result("test.batch.b").unwrap(), "2");
+    assert_eq!(store.get_with_result("ro.test.batch.sku").unwrap(), "a");
+    assert!(store.get_with_result("test.batch.denied.x").is_err());
+
+    // Longer lists are split into several batches.
+    let entries: Vec<(String, String)> = (0..MAX_WIRE_BATCH + 10)
+        .map(|i| (format!("test.batch.many.{i}"), i.to_string()))
+        .collect();
+    let results = client.set_many(&entries).unwrap();
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(
+        store
+            .get_with_result(&format!("test.batch.many.{}", MAX_WIRE_BATCH + 9))
+            .unwrap(),
+        (MAX_WIRE_BATCH + 9).to_string()
+    );
+
+    // The access policy's refusal is not the store's.
+    let stats = tenants[0].stats().await.unwrap();
+    assert_eq!((stats.service.rejected, stats.service.denied), (1, 1));
+    drop(store);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_oversized_batch_is_refused() {
+    let dir = temp_dir("batch_oversized");
+    let config =
+        ServiceConfig::default().tenant(TenantConfig::new("t", dir.join("t"), dir.join("sockets")));
+    let tenants = run_tenants(config).await.unwrap();
+
+    let socket_path = dir
+        .join("sockets")
+        .join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_SETPROP_BATCH.to_ne_bytes());
+    msg.extend_from_slice(&(MAX_WIRE_BATCH as u32 + 1).to_ne_bytes());
+    stream.write_all(&msg).await.unwrap();
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).await.unwrap();
+    assert_eq!(i32::from_ne_bytes(status), PROP_ERROR_READ_DATA);
+
+    let stats = tenants[0].stats().await.unwrap();
+    assert_eq!(stats.service.applied, 1); // The ready property.
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Answers a batch like AOSP init and records the SETPROP2 sets.
+fn serve_without_batches(socket_dir: &Path, sets: Arc<Mutex<Vec<String>>>) {
+    std::fs::create_dir_all(socket_dir).unwrap();
+    let listener = std::os::unix::net::UnixListener::bind(
+        socket_dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME),
+    )
+    .unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut word = [0u8; 4];
+            stream.read_exact(&mut word).unwrap();
+            if u32::from_ne_bytes(word) != PROP_MSG_SETPROP2 {
+                stream
+                    .write_all(&PROP_ERROR_INVALID_CMD.to_ne_bytes())
+                    .unwrap();
+                continue;
+            }
+            let mut fields = Vec::new();
+            for _ in 0..2 {
+                stream.read_exact(&mut word).unwrap();
+                let mut field = vec![0u8; u32::from_ne_bytes(word) as usize];
+                stream.read_exact(&mut field).unwrap();
+                fields.push(String::from_utf8(field).unwrap());
+            }
+            sets.lock().unwrap().push(fields.join("="));
+            stream.write_all(&PROP_SUCCESS.to_ne_bytes()).unwrap();
+        }
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_many_falls_back_to_single_sets() {
+    let dir = temp_dir("batch_fallback");
+    let config =
+        ServiceConfig::default().tenant(TenantConfig::new("t", dir.join("t"), dir.join("sockets")));
+    let tenants = run_tenants(config).await.unwrap();
+    let sets = Arc::new(Mutex::new(Vec::new()));
+    let legacy = dir.join("legacy_sockets");
+    serve_without_batches(&legacy, sets.clone());
+
+    let client =
+        PropertiesClient::new(PropertyConfig::with_both_dirs(dir.join("t"), &legacy)).unwrap();
+    let results = client
+        .set_many(&[("test.batch.x", "1"), ("test.batch.y", "2")])
+        .unwrap();
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(*sets.lock().unwrap(), ["test.batch.x=1", "test.batch.y=2"]);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_many_falls_back_when_a_large_batch_is_cut_off() {
+    let dir = temp_dir("batch_fallback_large");
+    let config =
+        ServiceConfig::default().tenant(TenantConfig::new("t", dir.join("t"), dir.join("sockets")));
+    let tenants = run_tenants(config).await.unwrap();
+    let sets = Arc::new(Mutex::new(Vec::new()));
+    let legacy = dir.join("legacy_sockets");
+    serve_without_batches(&legacy, sets.clone());
+
+    // Far more than the socket buffers hold: the service answers and
+    // closes while the client is still writing.
+    let value = "v".repeat(MAX_WIRE_VALUE_LEN);
+    let names: Vec<String> = (0..MAX_WIRE_BATCH)
+        .map(|i| format!("ro.test.batch.large.{i}"))
+        .collect();
+    let entries: Vec<(&str, &str)> = names
+        .iter()
+        .map(|name| (name.as_str(), value.as_str()))
+        .collect();
+    let client =
+        PropertiesClient::new(PropertyConfig::with_both_dirs(dir.join("t"), &legacy)).unwrap();
+    let results = client.set_many(&entries).unwrap();
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(sets.lock().unwrap().len(), MAX_WIRE_BATCH);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
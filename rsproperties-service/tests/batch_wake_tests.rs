@@ -0,0 +1,68 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A SETPROP_BATCH is applied in one `SystemProperties::batch`: its
+//! updates wake each record and the global serial once, however many
+//! entries there are — counted through a wrapping wait backend.
+//!
+//! Backends latch process-wide on first use, so this binary has a single
+//! #[test] fn that installs the backend before the service starts.
+
+#![cfg(target_os = "linux")]
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use rsproperties::backend::{FutexBackend, WaitBackend, WaitOutcome};
+use rsproperties::{PropertiesClient, PropertyConfig, Result, Timespec};
+use rsproperties_service::{run_tenants, ServiceConfig, TenantConfig};
+
+mod common;
+use common::temp_dir;
+
+struct CountingWake {
+    wakes: AtomicUsize,
+}
+
+impl WaitBackend for CountingWake {
+    fn wait(&self, serial: &AtomicU32, value: u32, timeout: Option<&Timespec>) -> WaitOutcome {
+        FutexBackend.wait(serial, value, timeout)
+    }
+
+    fn wake(&self, serial: &AtomicU32) -> Result<usize> {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+        FutexBackend.wake(serial)
+    }
+}
+
+static WAKE: CountingWake = CountingWake {
+    wakes: AtomicUsize::new(0),
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_batch_coalesces_wakes() {
+    rsproperties::backend::set_wait_backend(&WAKE).unwrap();
+
+    let dir = temp_dir("batch_wakes");
+    let config =
+        ServiceConfig::default().tenant(TenantConfig::new("t", dir.join("t"), dir.join("sockets")));
+    let tenants = run_tenants(config).await.unwrap();
+    let client = PropertiesClient::new(PropertyConfig::with_both_dirs(
+        dir.join("t"),
+        dir.join("sockets"),
+    ))
+    .unwrap();
+
+    let names = ["test.wake.a", "test.wake.b", "test.wake.c"];
+    let entries: Vec<(&str, &str)> = names.iter().map(|name| (*name, "0")).collect();
+    assert!(client.set_many(&entries).unwrap().iter().all(Result::is_ok));
+
+    // Unbatched, each update would wake its record and the global serial.
+    let entries: Vec<(&str, &str)> = names.iter().map(|name| (*name, "1")).collect();
+    let before = WAKE.wakes.load(Ordering::SeqCst);
+    assert!(client.set_many(&entries).unwrap().iter().all(Result::is_ok));
+    assert_eq!(WAKE.wakes.load(Ordering::SeqCst) - before, names.len() + 1);
+
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+// Minimal bionic property client for bionic_conformance_tests.rs.
+//
+// Build it statically with the NDK so it runs on a plain Linux kernel:
+//
+//   $NDK/toolchains/llvm/prebuilt/linux-x86_64/bin/x86_64-linux-android30-clang \
+//       -static -O2 -o prop_client-x86_64 prop_client.c
+//
+// (aarch64-linux-android30-clang and prop_client-aarch64 on arm64 hosts.)
+//
+//   prop_client set NAME VALUE   exit 0 if __system_property_set returned 0
+//   prop_client get NAME         print the value; exit 2 if it is unset
+
+#include <stdio.h>
+#include <string.h>
+#include <sys/system_properties.h>
+
+int main(int argc, char** argv) {
+    if (argc == 4 && strcmp(argv[1], "set") == 0) {
+        int ret = __system_property_set(argv[2], argv[3]);
+        printf("%d\n", ret);
+        return ret == 0 ? 0 : 1;
+    }
+    if (argc == 3 && strcmp(argv[1], "get") == 0) {
+        char value[PROP_VALUE_MAX];
+        int len = __system_property_get(argv[2], value);
+        printf("%s\n", value);
+        return len > 0 ? 0 : 2;
+    }
+    fprintf(stderr, "usage: %s set NAME VALUE | get NAME\n", argv[0]);
+    return 64;
+}
@@ -0,0 +1,171 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wire and area compatibility with a real bionic client (feature
+//! `bionic-conformance`).
+//!
+//! `tests/bionic/prop_client.c`, statically linked against bionic with the
+//! NDK, calls `__system_property_set`/`__system_property_get` against this
+//! crate's service. Bionic hard-codes `/dev/socket` and
+//! `/dev/__properties__`, so each client runs in a private user + mount
+//! namespace (`unshare`) whose `/dev` is a tmpfs with those two names
+//! linked to the test's directories. The user namespace also maps our uid
+//! to root, which bionic requires of the area files' owner.
+//!
+//! The client binary is `$RSPROPERTIES_BIONIC_CLIENT`, or
+//! `tests/bionic/prop_client-<arch>`; see `prop_client.c` for how to build
+//! it. With the feature enabled, a missing binary is a failure, not a
+//! skip.
+
+#![cfg(all(feature = "bionic-conformance", target_os = "linux"))]
+
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::Duration;
+
+use rsproperties::test_support::TestEnv;
+use rsproperties::PropertyConfig;
+
+fn client_binary() -> PathBuf {
+    let path = std::env::var_os("RSPROPERTIES_BIONIC_CLIENT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/bionic")
+                .join(format!("prop_client-{}", std::env::consts::ARCH))
+        });
+    assert!(
+        path.is_file(),
+        "bionic client {path:?} not found: build tests/bionic/prop_client.c with the NDK \
+         (see the comment at its top) or set RSPROPERTIES_BIONIC_CLIENT"
+    );
+    path
+}
+
+/// Runs the bionic client with `/dev/socket` and `/dev/__properties__`
+/// pointing at `socket_dir` and `properties_dir`.
+fn run_client(socket_dir: &Path, properties_dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new("unshare")
+        .args(["--user", "--map-root-user", "--mount", "--", "sh", "-c"])
+        .arg(
+            "mount -t tmpfs tmpfs /dev && ln -s \"$1\" /dev/socket && \
+             ln -s \"$2\" /dev/__properties__ && shift 2 && exec \"$@\"",
+        )
+        .arg("sh")
+        .arg(socket_dir)
+        .arg(properties_dir)
+        .arg(client_binary())
+        .args(args)
+        .output()
+        .expect("failed to run unshare (util-linux)");
+    if !output.stderr.is_empty() {
+        eprintln!("client stderr: {}", String::from_utf8_lossy(&output.stderr));
+    }
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_owned()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_bionic_client() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let client = client_binary();
+    eprintln!("bionic client: {client:?}");
+
+    let root = std::env::temp_dir().join(format!("rsprops_bionic_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let properties_dir = root.join("properties");
+    let socket_dir = root.join("sockets");
+    create_dir_all(&properties_dir).unwrap();
+    create_dir_all(&socket_dir).unwrap();
+    let build_prop = root.join("build.prop");
+    File::create(&build_prop)
+        .unwrap()
+        .write_all(b"ro.property_service.version=2\nro.bionic.fixture=from_build_prop\n")
+        .unwrap();
+
+    let _services = rsproperties_service::run(
+        PropertyConfig::with_both_dirs(&properties_dir, &socket_dir),
+        vec![],
+        vec![build_prop],
+    )
+    .await
+    .expect("Failed to start services");
+    rsproperties::wait_for_service(Duration::from_secs(5)).unwrap();
+
+    let client = {
+        let socket_dir = socket_dir.clone();
+        move |properties_dir: PathBuf, args: Vec<String>| {
+            let socket_dir = socket_dir.clone();
+            tokio::task::spawn_blocking(move || {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                run_client(&socket_dir, &properties_dir, &args)
+            })
+        }
+    };
+    let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    // Bionic reads our trie and areas.
+    let out = client(properties_dir.clone(), args(&["get", "ro.bionic.fixture"]))
+        .await
+        .unwrap();
+    assert!(out.status.success(), "get failed: {out:?}");
+    assert_eq!(stdout(&out), "from_build_prop");
+
+    // V2 set, up to the longest value bionic sends for a non-`ro.` name.
+    let long = "x".repeat(rsproperties::PROP_VALUE_MAX - 1);
+    for (name, value) in [("test.bionic.v2", "hello"), ("test.bionic.long", &long)] {
+        let out = client(properties_dir.clone(), args(&["set", name, value]))
+            .await
+            .unwrap();
+        assert!(out.status.success(), "V2 set of {name} failed: {out:?}");
+        assert_eq!(rsproperties::get::<String>(name).unwrap(), value);
+
+        let out = client(properties_dir.clone(), args(&["get", name]))
+            .await
+            .unwrap();
+        assert_eq!(stdout(&out), value);
+    }
+
+    // A rejection reaches bionic as a failed set, despite the reason
+    // trailing the status code.
+    let out = client(
+        properties_dir.clone(),
+        args(&["set", "ro.bionic.fixture", "other"]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(out.status.code(), Some(1), "{out:?}");
+    assert_eq!(stdout(&out), "-1");
+    assert_eq!(
+        rsproperties::get::<String>("ro.bionic.fixture").unwrap(),
+        "from_build_prop"
+    );
+
+    // V1: the client picks the protocol from its own areas, which only
+    // hold `ro.property_service.version`.
+    let v1_env = TestEnv::builder()
+        .property("ro.property_service.version", "1")
+        .without_service()
+        .build()
+        .unwrap();
+    let out = client(
+        v1_env.properties_dir().to_path_buf(),
+        args(&["set", "test.bionic.v1", "legacy"]),
+    )
+    .await
+    .unwrap();
+    assert!(out.status.success(), "V1 set failed: {out:?}");
+    assert_eq!(
+        rsproperties::get::<String>("test.bionic.v1").unwrap(),
+        "legacy"
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+}
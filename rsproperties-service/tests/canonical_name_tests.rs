@@ -0,0 +1,48 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Names are canonicalized on ingest: a raw SETPROP2 frame with a sloppy
+//! name (the library client would refuse to send one) is stored under the
+//! canonical name, and a name that cannot be repaired is refused.
+
+mod common;
+use common::init_test;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use rsproperties::wire::{PROP_ERROR_INVALID_NAME, PROP_MSG_SETPROP2, PROP_SUCCESS};
+
+async fn setprop2_raw(name: &str, value: &str) -> i32 {
+    let socket_path = rsproperties::socket_dir().join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_SETPROP2.to_ne_bytes());
+    msg.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(name.as_bytes());
+    msg.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(value.as_bytes());
+    stream.write_all(&msg).await.unwrap();
+
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).await.unwrap();
+    i32::from_ne_bytes(status)
+}
+
+#[tokio::test]
+async fn test_sloppy_name_is_canonicalized() {
+    let _ = init_test().await;
+
+    assert_eq!(
+        setprop2_raw("  test.canonical.padded\n", "v").await,
+        PROP_SUCCESS
+    );
+    let value: String = rsproperties::get("test.canonical.padded").unwrap();
+    assert_eq!(value, "v");
+
+    assert_eq!(
+        setprop2_raw(" test..canonical ", "v").await,
+        PROP_ERROR_INVALID_NAME
+    );
+}
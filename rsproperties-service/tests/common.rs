@@ -1,27 +1,67 @@
+// Shared by many test binaries, each using only some of it.
+#![allow(dead_code)]
+
 use std::fs::{create_dir_all, remove_dir_all};
-use std::{path::PathBuf, str::FromStr};
+use std::path::{Path, PathBuf};
 
 use tokio::sync::OnceCell;
 
 use rsproperties::PropertyConfig;
 
-use rsproperties_service::{PropertiesService, ServiceContext, SocketService};
+use rsproperties_service::{PropertiesService, ServiceOptions, ServiceRuntime, SocketService};
 
 use rsactor::ActorRef;
 
-pub static TEST_PROPERTIES_DIR: &str = "__properties__";
+/// Per-process, so concurrent test binaries and CI shards never share
+/// areas or sockets. Removed when the test binary exits.
+pub fn test_properties_dir() -> PathBuf {
+    static DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir =
+            std::env::temp_dir().join(format!("rsprops_service_tests_{}", std::process::id()));
+        rsproperties::test_support::remove_at_exit(&dir);
+        dir
+    })
+    .clone()
+}
+
+/// `rsprops_<tag>_<pid>` in the temp dir, emptied now and removed when
+/// the test binary exits. Not created.
+pub fn temp_dir(tag: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rsprops_{tag}_{}", std::process::id()));
+    let _ = remove_dir_all(&dir);
+    rsproperties::test_support::remove_at_exit(&dir);
+    dir
+}
+
+/// Writes `contexts` to `dir/property_contexts`, creating `dir`, and
+/// returns the `property_info` trie built from it, with
+/// `u:object_r:default_prop:s0` / `string` for undeclared names.
+pub fn contexts_trie(dir: &Path, contexts: &str) -> Vec<u8> {
+    create_dir_all(dir).unwrap();
+    let contexts_path = dir.join("property_contexts");
+    std::fs::write(&contexts_path, contexts).unwrap();
+
+    let (entries, errors) =
+        rsproperties::PropertyInfoEntry::parse_from_file(&contexts_path, false).unwrap();
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    rsproperties::build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap()
+}
+
+/// [`contexts_trie`], written to `dir/property_info` and returned.
+pub fn build_property_info(dir: &Path, contexts: &str) -> Vec<u8> {
+    let data = contexts_trie(dir, contexts);
+    std::fs::write(dir.join("property_info"), &data).unwrap();
+    data
+}
 
 static SERVICES: OnceCell<(ActorRef<SocketService>, ActorRef<PropertiesService>)> =
     OnceCell::const_new();
 
-async fn inti() -> (
-    ServiceContext<SocketService>,
-    ServiceContext<PropertiesService>,
-) {
+async fn inti() -> ServiceRuntime {
     let _ = env_logger::builder().is_test(true).try_init();
 
-    let properties_dir =
-        PathBuf::from_str(TEST_PROPERTIES_DIR).expect("Failed to parse properties directory path");
+    let properties_dir = test_properties_dir();
     let socket_dir = properties_dir.join("sockets");
 
     remove_dir_all(&properties_dir).unwrap_or_default();
@@ -31,7 +71,7 @@ async fn inti() -> (
 
     let config = PropertyConfig::with_both_dirs(properties_dir, socket_dir);
 
-    rsproperties_service::run(config, vec![], vec![])
+    ServiceRuntime::start(config, vec![], vec![], ServiceOptions::default())
         .await
         .expect("Failed to start services")
 }
@@ -51,19 +91,16 @@ pub async fn init_test() -> (ActorRef<SocketService>, ActorRef<PropertiesService
                     .expect("Failed to create Tokio runtime");
 
                 runtime.block_on(async {
-                    let services = inti().await;
-                    let _ =
-                        sender.send((services.0.actor_ref.clone(), services.1.actor_ref.clone()));
-
-                    let (res1, res2) =
-                        tokio::join!(services.0.join_handle, services.1.join_handle,);
-                    // Handle potential errors from join handles
-                    if let Err(e) = res1 {
-                        eprintln!("Socket service error: {e}");
-                    }
-                    if let Err(e) = res2 {
-                        eprintln!("Properties service error: {e}");
-                    }
+                    let mut services = inti().await;
+                    let _ = sender.send((
+                        services.socket_service().clone(),
+                        services.properties_service().clone(),
+                    ));
+
+                    // Serves until the test binary exits.
+                    let failure = services.failed().await;
+                    eprintln!("Service runtime failed: {failure}");
+                    services.shutdown().await;
                 });
             });
 
@@ -0,0 +1,69 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A service started with `Backing::Memfd` writes nothing to the
+//! properties directory, and the global reader gets its areas over the
+//! socket.
+//!
+//! Own test binary because the backing is latched once per process.
+
+#![cfg(target_os = "linux")]
+
+use std::fs::{create_dir_all, remove_dir_all};
+use std::time::Duration;
+
+use rsproperties::{Backing, PropertyConfig};
+
+#[test]
+fn test_memfd_backed_service() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let properties_dir =
+        std::env::temp_dir().join(format!("rsprops_memfd_service_{}", std::process::id()));
+    let socket_dir = properties_dir.join("sockets");
+    remove_dir_all(&properties_dir).unwrap_or_default();
+    // The service thread outlives the test; remove its files at exit.
+    rsproperties::test_support::remove_at_exit(&properties_dir);
+    create_dir_all(&socket_dir).unwrap();
+
+    let config = PropertyConfig::builder()
+        .properties_dir(&properties_dir)
+        .socket_dir(&socket_dir)
+        .backing(Backing::Memfd)
+        .build();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let services = rsproperties_service::run(config, vec![], vec![])
+                .await
+                .expect("Failed to start services");
+            sender.send(()).unwrap();
+            let _ = tokio::join!(services.0.join_handle, services.1.join_handle);
+        });
+    });
+    receiver.recv().unwrap();
+
+    assert_eq!(rsproperties::backing(), Backing::Memfd);
+    rsproperties::wait_for_service(Duration::from_secs(5)).unwrap();
+    rsproperties::set("test.memfd.service", "1").unwrap();
+    assert_eq!(
+        rsproperties::get::<String>("test.memfd.service").unwrap(),
+        "1"
+    );
+    rsproperties::set("test.memfd.service", "2").unwrap();
+    assert_eq!(
+        rsproperties::get::<String>("test.memfd.service").unwrap(),
+        "2"
+    );
+
+    // Only the socket directory: no property_info, no areas.
+    let entries: Vec<_> = std::fs::read_dir(&properties_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["sockets"]);
+}
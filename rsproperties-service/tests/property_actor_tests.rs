@@ -0,0 +1,127 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `PropertyActor`: sets and reads through the actor, waits and
+//! subscriptions that see those sets, and a shutdown that takes both
+//! services down.
+//!
+//! Own test binary because the actor configures the process-global
+//! directories.
+
+use std::time::Duration;
+
+use rsproperties::{PropertyConfig, SetErrorKind};
+use rsproperties_service::property_actor::{self, Get, List, Set, Subscribe, Wait};
+use rsproperties_service::PropertyActorArgs;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_property_actor() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = std::env::temp_dir().join(format!("rsprops_actor_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sockets")).unwrap();
+
+    let config = PropertyConfig::with_both_dirs(&dir, dir.join("sockets"));
+    let service = property_actor::run(PropertyActorArgs::new(config, vec![], vec![]))
+        .await
+        .unwrap();
+    let props = service.actor_ref.clone();
+
+    props
+        .ask(Set::new("test.actor.mode", "fast"))
+        .await
+        .unwrap()
+        .unwrap();
+    props
+        .ask(Set::new("test.actor.level", "3"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        props
+            .ask(Get::new("test.actor.mode"))
+            .await
+            .unwrap()
+            .unwrap(),
+        Some("fast".to_owned())
+    );
+    assert_eq!(
+        props
+            .ask(Get::new("test.actor.unset"))
+            .await
+            .unwrap()
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        props.ask(List::new("test.actor.")).await.unwrap().unwrap(),
+        vec![
+            ("test.actor.level".to_owned(), "3".to_owned()),
+            ("test.actor.mode".to_owned(), "fast".to_owned()),
+        ]
+    );
+    let rejected = props
+        .ask(Set::new("test..actor", "1"))
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(rejected.kind(), SetErrorKind::InvalidName);
+
+    // A wait that times out, then one that sees a set made meanwhile.
+    let timed_out = props
+        .ask_join(Wait::new("test.actor.mode").timeout(Duration::from_millis(50)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(timed_out, None);
+    let mut changes = props.ask(Subscribe::new("test.actor.")).await.unwrap();
+    // `Duration::MAX` has no representable deadline and waits unbounded.
+    let waiter = {
+        let props = props.clone();
+        tokio::spawn(async move {
+            props
+                .ask_join(Wait::new("test.actor.mode").timeout(Duration::MAX))
+                .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    props
+        .ask(Set::new("test.other.ignored", "x"))
+        .await
+        .unwrap()
+        .unwrap();
+    props
+        .ask(Set::new("test.actor.mode", "slow"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        waiter.await.unwrap().unwrap().unwrap(),
+        Some("slow".to_owned())
+    );
+
+    let mut seen = Vec::new();
+    while !seen.iter().any(|change: &property_actor::PropertyChange| {
+        change.name == "test.actor.mode" && change.value == "slow"
+    }) {
+        let change = tokio::time::timeout(Duration::from_secs(10), changes.recv())
+            .await
+            .expect("subscription reports the set")
+            .expect("subscription stays open");
+        seen.push(change);
+    }
+    assert!(seen
+        .iter()
+        .all(|change| change.name.starts_with("test.actor.")));
+
+    // Stopping the actor takes the services and the subscription down.
+    props.stop().await;
+    assert!(service.join_handle.await.unwrap().stopped_normally());
+    let closed = tokio::time::timeout(Duration::from_secs(10), async {
+        while changes.recv().await.is_some() {}
+    })
+    .await;
+    assert!(closed.is_ok(), "subscription ends with the actor");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,77 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `RcTriggerEngine` driven off a property directory: triggers fire on
+//! the change that satisfies them, `setprop`s chain into later passes,
+//! and `write` / `exec` run with properties expanded.
+
+use std::time::Duration;
+
+use rsproperties::SystemProperties;
+use rsproperties_service::RcTriggerEngine;
+
+mod common;
+use common::build_property_info;
+
+#[test]
+fn test_rc_triggers() {
+    let dir = std::env::temp_dir().join(format!("rsprops_rc_triggers_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, "test. u:object_r:test_prop:s0 prefix string\n");
+    let (written, executed) = (dir.join("written"), dir.join("executed"));
+
+    let rc = format!(
+        "on boot\n\
+         \x20   setprop test.rc.boot 1\n\
+         \n\
+         on property:test.rc.mode=on && property:test.rc.ready=1\n\
+         \x20   setprop test.rc.state ${{test.rc.mode}}-active\n\
+         \x20   write {written} \"mode ${{test.rc.mode}}\"\n\
+         \n\
+         on property:test.rc.state=*\n\
+         \x20   exec -- /bin/sh -c \"echo $1 > {executed}\" sh ${{test.rc.state}}\n\
+         \x20   exec /bin/false\n",
+        written = written.display(),
+        executed = executed.display(),
+    );
+    let mut engine = RcTriggerEngine::parse(&rc).unwrap();
+    assert_eq!(engine.triggers().count(), 2);
+    assert_eq!(engine.unsupported().len(), 1);
+
+    let mut props = SystemProperties::new_area(&dir).unwrap();
+    props.set("test.rc.ready", "1").unwrap();
+    let reader = SystemProperties::open(&dir).unwrap();
+
+    // Nothing holds yet; the next pass waits for a change.
+    assert!(engine.process(&reader, &mut props).unwrap().is_empty());
+    assert!(engine
+        .wait_and_process(&reader, &mut props, Some(Duration::from_millis(10)))
+        .unwrap()
+        .is_none());
+
+    props.set("test.rc.mode", "on").unwrap();
+    let report = engine.process(&reader, &mut props).unwrap();
+    assert_eq!(
+        report.fired,
+        ["property:test.rc.mode=on && property:test.rc.ready=1"]
+    );
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+    assert_eq!(props.get_with_result("test.rc.state").unwrap(), "on-active");
+    assert_eq!(std::fs::read_to_string(&written).unwrap(), "mode on");
+
+    // The setprop above fires the second trigger on the next pass.
+    let report = engine
+        .wait_and_process(&reader, &mut props, Some(Duration::from_secs(5)))
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.fired, ["property:test.rc.state=*"]);
+    assert_eq!(std::fs::read_to_string(&executed).unwrap(), "on-active\n");
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "exec /bin/false");
+
+    // An unrelated change fires nothing.
+    props.set("test.rc.other", "x").unwrap();
+    assert!(engine.process(&reader, &mut props).unwrap().is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
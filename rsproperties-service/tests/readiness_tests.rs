@@ -0,0 +1,42 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The service announces readiness only after startup completed, and
+//! `service_status` reports a running service as available.
+
+mod common;
+use common::init_test;
+
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_service_publishes_ready_property() {
+    let _ = init_test().await;
+
+    let ready: String = rsproperties::get(rsproperties::SERVICE_READY_PROPERTY).unwrap();
+    assert_eq!(ready, "1");
+
+    tokio::task::spawn_blocking(|| rsproperties::wait_for_service(Duration::from_secs(5)))
+        .await
+        .unwrap()
+        .expect("running service is ready");
+}
+
+#[tokio::test]
+async fn test_service_status_of_running_service() {
+    let _ = init_test().await;
+
+    let status = tokio::task::spawn_blocking(rsproperties::service_status)
+        .await
+        .unwrap();
+    assert!(status.socket_present, "{status:?}");
+    assert!(status.connectable, "{status:?}");
+    assert!(status.ready, "{status:?}");
+    assert!(status.is_available());
+    assert!(status.latency.is_some());
+    assert_eq!(status.error, None);
+    assert_eq!(
+        status.socket_path,
+        rsproperties::socket_dir().join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME)
+    );
+}
@@ -0,0 +1,142 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `ServiceRuntime`: tasks are restarted per their strategy, a failure
+//! beyond it is reported, and shutdown stops the tasks while the services
+//! still serve them.
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rsproperties::wire::{PROP_MSG_SETPROP2, PROP_SUCCESS};
+use rsproperties::SystemProperties;
+use rsproperties_service::{
+    run_tenants, Restart, RestartPolicy, ServiceConfig, ServiceRuntime, TenantConfig,
+};
+
+mod common;
+use common::temp_dir;
+
+async fn start(dir: &Path) -> ServiceRuntime {
+    let tenant = run_tenants(ServiceConfig::default().tenant(TenantConfig::new(
+        "t",
+        dir,
+        dir.join("sockets"),
+    )))
+    .await
+    .unwrap()
+    .pop()
+    .unwrap();
+    ServiceRuntime::new(tenant.socket_service, tenant.properties_service)
+}
+
+fn setprop2(socket_dir: &Path, name: &str, value: &str) -> i32 {
+    let socket_path = socket_dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(socket_path).unwrap();
+
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_SETPROP2.to_ne_bytes());
+    msg.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(name.as_bytes());
+    msg.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(value.as_bytes());
+    stream.write_all(&msg).unwrap();
+
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).unwrap();
+    i32::from_ne_bytes(status)
+}
+
+#[tokio::test]
+async fn test_runtime_restarts_and_shuts_down_in_order() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = temp_dir("runtime_order");
+    let socket_dir = dir.join("sockets");
+    let mut runtime = start(&dir).await;
+
+    // Fails twice, then runs until shutdown.
+    let runs = Arc::new(AtomicUsize::new(0));
+    runtime.spawn(
+        "flaky",
+        Restart::OnFailure(RestartPolicy::new(2, Duration::from_secs(60))),
+        {
+            let runs = runs.clone();
+            move |mut shutdown| {
+                let run = runs.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if run < 2 {
+                        return Err(format!("run {run} failed").into());
+                    }
+                    shutdown.wait().await;
+                    Ok(())
+                }
+            }
+        },
+    );
+
+    // Sets a last value through the socket once shutdown begins, as a
+    // persistent-property writer flushing would.
+    runtime.spawn_blocking("flusher", Restart::Never, {
+        let socket_dir = socket_dir.clone();
+        move |shutdown| {
+            while !shutdown.is_shutdown() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(
+                setprop2(&socket_dir, "test.runtime.flushed", "1"),
+                PROP_SUCCESS
+            );
+            Ok(())
+        }
+    });
+
+    tokio::time::timeout(Duration::from_millis(200), runtime.failed())
+        .await
+        .expect_err("no child should have failed for good");
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+    tokio::time::timeout(Duration::from_secs(30), runtime.shutdown())
+        .await
+        .expect("runtime did not shut down");
+    assert!(!socket_dir
+        .join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME)
+        .exists());
+    let props = SystemProperties::open(&dir).unwrap();
+    assert_eq!(props.get_with_result("test.runtime.flushed").unwrap(), "1");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_runtime_reports_failure_beyond_restart() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = temp_dir("runtime_failure");
+    let mut runtime = start(&dir).await;
+
+    runtime.spawn_blocking("watcher", Restart::Never, |_| Err("lost the area".into()));
+    let failure = tokio::time::timeout(Duration::from_secs(10), runtime.failed())
+        .await
+        .expect("failure was not reported");
+    assert_eq!(failure.child, "watcher");
+    assert!(failure.reason.contains("lost the area"), "{failure}");
+
+    // The services are unaffected until the runtime is shut down.
+    let socket_dir = dir.join("sockets");
+    let status = tokio::task::spawn_blocking({
+        let socket_dir = socket_dir.clone();
+        move || setprop2(&socket_dir, "test.runtime.after_failure", "1")
+    })
+    .await
+    .unwrap();
+    assert_eq!(status, PROP_SUCCESS);
+    runtime.shutdown().await;
+    assert!(!socket_dir
+        .join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME)
+        .exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
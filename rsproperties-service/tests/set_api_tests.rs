@@ -425,6 +425,67 @@ async fn test_set_and_get_integration() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_set_and_confirm() -> anyhow::Result<()> {
+    setup_test_env().await;
+
+    let timeout = std::time::Duration::from_secs(5);
+    for value in ["first", "second"] {
+        let latency = rsproperties::set_and_confirm("test.set.confirm", value, timeout)?;
+        assert!(latency <= timeout);
+        assert_eq!(rsproperties::get::<String>("test.set.confirm")?, value);
+    }
+    // A timeout with no representable deadline waits without a bound.
+    rsproperties::set_and_confirm("test.set.confirm", "third", std::time::Duration::MAX)?;
+    // Rejections surface as from `set`, without waiting.
+    assert!(matches!(
+        rsproperties::set_and_confirm("test..confirm", "1", timeout),
+        Err(rsproperties::Error::InvalidArgument(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_coalescing_setter() -> anyhow::Result<()> {
+    setup_test_env().await;
+
+    let mut setter = rsproperties::CoalescingSetter::new(std::time::Duration::from_secs(60));
+    for percent in 0..=100 {
+        setter.set("test.coalesce.progress", &percent)?;
+    }
+    setter.set("test.coalesce.stage", "download")?;
+    // Only the first value of each property went out.
+    assert_eq!(rsproperties::get::<String>("test.coalesce.progress")?, "0");
+    assert_eq!(
+        rsproperties::get::<String>("test.coalesce.stage")?,
+        "download"
+    );
+    assert_eq!(setter.pending(), 1);
+
+    setter.flush()?;
+    assert_eq!(setter.pending(), 0);
+    assert_eq!(
+        rsproperties::get::<String>("test.coalesce.progress")?,
+        "100"
+    );
+
+    // After a flush the next set goes out at once; drop flushes the rest.
+    setter.set("test.coalesce.stage", "verify")?;
+    setter.set("test.coalesce.stage", "install")?;
+    assert_eq!(
+        rsproperties::get::<String>("test.coalesce.stage")?,
+        "verify"
+    );
+    drop(setter);
+    assert_eq!(
+        rsproperties::get::<String>("test.coalesce.stage")?,
+        "install"
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_set_concurrent_properties() -> anyhow::Result<()> {
     setup_test_env().await;
@@ -0,0 +1,74 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rejections reach the client as a specific status code plus a reason:
+//! `rsproperties::set` surfaces them as `Error::ServiceError(SetError)`,
+//! and raw frames see the AOSP code followed by the message trailer.
+
+mod common;
+use common::init_test;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use rsproperties::wire::{PROP_ERROR_INVALID_VALUE, PROP_MSG_SETPROP2, PROP_VALUE_MAX};
+use rsproperties::{Error, SetErrorKind};
+
+/// Sends a SETPROP2 frame and reads the status code and, if the service
+/// sent one, the reason.
+async fn setprop2_raw(name: &str, value: &str) -> (i32, Option<String>) {
+    let socket_path = rsproperties::socket_dir().join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_SETPROP2.to_ne_bytes());
+    msg.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(name.as_bytes());
+    msg.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(value.as_bytes());
+    stream.write_all(&msg).await.unwrap();
+
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    let code = i32::from_ne_bytes(rest[..4].try_into().unwrap());
+    let message = (rest.len() > 8).then(|| String::from_utf8(rest[8..].to_vec()).unwrap());
+    (code, message)
+}
+
+#[tokio::test]
+async fn test_read_only_rejection_carries_reason() {
+    let _ = init_test().await;
+
+    rsproperties::set("ro.test.set_error", "first").unwrap();
+    let err = rsproperties::set("ro.test.set_error", "second").unwrap_err();
+    let Error::ServiceError(set_error) = err else {
+        panic!("expected ServiceError, got {err:?}");
+    };
+    assert_eq!(set_error.kind(), SetErrorKind::ReadOnly);
+    assert_eq!(set_error.name, "ro.test.set_error");
+    let message = set_error
+        .message
+        .as_deref()
+        .expect("service sends a reason");
+    assert!(message.contains("read-only"), "{message}");
+    assert!(
+        !message.contains("second"),
+        "reason must not echo the value"
+    );
+}
+
+#[tokio::test]
+async fn test_oversized_value_reports_limit() {
+    let _ = init_test().await;
+
+    // The library client refuses this before sending; a foreign client
+    // gets the limit spelled out.
+    let value = "x".repeat(PROP_VALUE_MAX + 10);
+    let (code, message) = setprop2_raw("test.set_error.long", &value).await;
+    assert_eq!(code, PROP_ERROR_INVALID_VALUE);
+    let message = message.expect("service sends a reason");
+    assert!(
+        message.contains(&format!("max {}", PROP_VALUE_MAX - 1)),
+        "{message}"
+    );
+}
@@ -0,0 +1,91 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `serve_until_signal`: SIGHUP runs the reload hook and bumps the reload
+//! counter, SIGTERM shuts both services down and removes the sockets.
+//!
+//! Signals go to the whole test process, so this file holds one #[test].
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustix::process::{getpid, kill_process, Signal};
+use tokio::signal::unix::{signal, SignalKind};
+
+use rsproperties::PropertyConfig;
+use rsproperties_service::RELOAD_COUNT_PROPERTY;
+
+async fn wait_for_value(name: &str, expected: &str) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while rsproperties::get::<String>(name).ok().as_deref() != Some(expected) {
+        assert!(
+            Instant::now() < deadline,
+            "{name} never became {expected:?}"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_serve_until_signal() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    // Install the handlers up front: a signal raised before
+    // `serve_until_signal` registers its own would otherwise take the
+    // default action and kill the test process.
+    let _hangup = signal(SignalKind::hangup()).unwrap();
+    let _terminate = signal(SignalKind::terminate()).unwrap();
+
+    let root = std::env::temp_dir().join(format!("rsprops_signal_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let properties_dir = root.join("properties");
+    let socket_dir = root.join("sockets");
+    std::fs::create_dir_all(&properties_dir).unwrap();
+    std::fs::create_dir_all(&socket_dir).unwrap();
+
+    let (socket_service, properties_service) = rsproperties_service::run(
+        PropertyConfig::with_both_dirs(&properties_dir, &socket_dir),
+        vec![],
+        vec![],
+    )
+    .await
+    .expect("Failed to start services");
+
+    let reloads = Arc::new(AtomicUsize::new(0));
+    let served = tokio::spawn({
+        let reloads = reloads.clone();
+        rsproperties_service::serve_until_signal(socket_service, properties_service, move || {
+            reloads.fetch_add(1, Ordering::SeqCst);
+        })
+    });
+
+    kill_process(getpid(), Signal::HUP).unwrap();
+    wait_for_value(RELOAD_COUNT_PROPERTY, "1").await;
+    kill_process(getpid(), Signal::HUP).unwrap();
+    wait_for_value(RELOAD_COUNT_PROPERTY, "2").await;
+    assert_eq!(reloads.load(Ordering::SeqCst), 2);
+
+    tokio::task::spawn_blocking(|| rsproperties::set("test.signal.before_stop", "1"))
+        .await
+        .unwrap()
+        .unwrap();
+
+    kill_process(getpid(), Signal::TERM).unwrap();
+    tokio::time::timeout(Duration::from_secs(30), served)
+        .await
+        .expect("service did not stop on SIGTERM")
+        .unwrap()
+        .unwrap();
+
+    assert!(!socket_dir
+        .join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME)
+        .exists());
+    // The areas outlive the service.
+    assert_eq!(
+        rsproperties::get::<String>("test.signal.before_stop").unwrap(),
+        "1"
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+}
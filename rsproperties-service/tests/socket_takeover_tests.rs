@@ -0,0 +1,106 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Socket directory ownership: a second service on a live directory fails
+//! to start, stale sockets are replaced, and a live foreign listener is
+//! only replaced under `TakeoverPolicy::Replace`.
+
+mod common;
+use common::init_test;
+
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rsproperties_service::{socket_service, SocketServiceArgs, TakeoverPolicy};
+
+fn temp_socket_dir(tag: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rsprops_takeover_{tag}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn inode(path: &Path) -> u64 {
+    std::fs::metadata(path).unwrap().ino()
+}
+
+/// Waits until the service has replaced the file at `path` (a new inode),
+/// i.e. `on_start` got past binding.
+async fn wait_replaced(path: &Path, old_inode: u64) {
+    for _ in 0..100 {
+        if std::fs::metadata(path).is_ok_and(|m| m.ino() != old_inode) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("{path:?} was not replaced");
+}
+
+#[tokio::test]
+async fn test_second_service_on_same_dir_is_refused() {
+    let (_, properties_service) = init_test().await;
+
+    let second = socket_service::run(
+        SocketServiceArgs::new(rsproperties::socket_dir().to_path_buf(), properties_service)
+            .with_takeover(TakeoverPolicy::Replace),
+    );
+    let result = second.join_handle.await.unwrap();
+    assert!(result.is_startup_failed(), "second service must not start");
+
+    // The first service still owns its sockets and answers.
+    rsproperties::set("test.takeover.still_served", "yes").unwrap();
+    let value: String = rsproperties::get("test.takeover.still_served").unwrap();
+    assert_eq!(value, "yes");
+}
+
+#[tokio::test]
+async fn test_live_foreign_socket() {
+    let (_, properties_service) = init_test().await;
+    let dir = temp_socket_dir("live");
+    let path = dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let _foreign = tokio::net::UnixListener::bind(&path).unwrap();
+    let foreign_inode = inode(&path);
+
+    let refused = socket_service::run(
+        SocketServiceArgs::new(dir.clone(), properties_service.clone())
+            .with_takeover(TakeoverPolicy::Refuse),
+    );
+    let result = refused.join_handle.await.unwrap();
+    assert!(
+        result.is_startup_failed(),
+        "live socket must not be replaced"
+    );
+    assert_eq!(inode(&path), foreign_inode);
+
+    let replaced = socket_service::run(
+        SocketServiceArgs::new(dir.clone(), properties_service)
+            .with_takeover(TakeoverPolicy::Replace),
+    );
+    wait_replaced(&path, foreign_inode).await;
+    replaced.actor_ref.stop().await;
+    assert!(replaced.join_handle.await.unwrap().is_completed());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_stale_socket_is_replaced() {
+    let (_, properties_service) = init_test().await;
+    let dir = temp_socket_dir("stale");
+    let path = dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    // Tokio does not unlink on drop: the file stays behind, refusing
+    // connections, like after a crash.
+    drop(tokio::net::UnixListener::bind(&path).unwrap());
+    let stale_inode = inode(&path);
+
+    let service = socket_service::run(
+        SocketServiceArgs::new(dir.clone(), properties_service)
+            .with_takeover(TakeoverPolicy::Refuse),
+    );
+    wait_replaced(&path, stale_inode).await;
+    service.actor_ref.stop().await;
+    assert!(service.join_handle.await.unwrap().is_completed());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
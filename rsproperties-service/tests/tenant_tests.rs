@@ -0,0 +1,707 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! One service process serving two property directories: a set reaches
+//! only the tenant whose socket it was sent to, and each tenant counts its
+//! own sets. Service options such as value transforms and the history
+//! apply to every tenant, and a client stalling mid-request is dropped
+//! and counted. The build.prop load reports its progress and applies
+//! the bootstrap requirements. A recorded session replays into a fresh
+//! store, `persist.` properties survive a restart, `ctl.` properties
+//! reach the control handler instead of the store, and a set listener
+//! receives the stored sets.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use rsproperties::wire::{
+    PROP_ERROR_HANDLE_CONTROL_MESSAGE, PROP_ERROR_INVALID_NAME, PROP_ERROR_INVALID_VALUE,
+    PROP_ERROR_READ_DATA, PROP_MSG_SETPROP2, PROP_SUCCESS,
+};
+use rsproperties::{PropertyRequirement, SystemProperties};
+use rsproperties_service::{
+    history_of, read_persistent_properties, read_recording, replay_with, run_tenants,
+    ControlAction, ControlHandler, HistoryConfig, PreloadEvent, ReplaySpeed, RequiredPolicy,
+    ServiceConfig, ServiceOptions, SetListener, TenantConfig, Transform, TransformChain,
+    PERSISTENT_PROPERTIES_READY_PROPERTY,
+};
+
+mod common;
+use common::{build_property_info, temp_dir};
+
+async fn setprop2_raw(socket_dir: &Path, name: &str, value: &str) -> i32 {
+    let socket_path = socket_dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_SETPROP2.to_ne_bytes());
+    msg.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(name.as_bytes());
+    msg.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(value.as_bytes());
+    stream.write_all(&msg).await.unwrap();
+
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).await.unwrap();
+    i32::from_ne_bytes(status)
+}
+
+#[tokio::test]
+async fn test_tenants_are_isolated() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let (guest_a, guest_b) = (temp_dir("tenant_a"), temp_dir("tenant_b"));
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("a", &guest_a, guest_a.join("sockets")))
+        .tenant(TenantConfig::new("b", &guest_b, guest_b.join("sockets")));
+    let tenants = run_tenants(config).await.unwrap();
+    assert_eq!(tenants.len(), 2);
+
+    let sockets_a = guest_a.join("sockets");
+    let sockets_b = guest_b.join("sockets");
+    assert_eq!(
+        setprop2_raw(&sockets_a, "test.tenant.name", "a").await,
+        PROP_SUCCESS
+    );
+    assert_eq!(
+        setprop2_raw(&sockets_b, "test.tenant.name", "b").await,
+        PROP_SUCCESS
+    );
+    assert_eq!(
+        setprop2_raw(&sockets_b, "test.tenant.only_b", "1").await,
+        PROP_SUCCESS
+    );
+    assert_eq!(
+        setprop2_raw(&sockets_b, "test..tenant", "1").await,
+        PROP_ERROR_INVALID_NAME
+    );
+
+    let a = SystemProperties::open(&guest_a).unwrap();
+    let b = SystemProperties::open(&guest_b).unwrap();
+    assert_eq!(a.get_with_result("test.tenant.name").unwrap(), "a");
+    assert_eq!(b.get_with_result("test.tenant.name").unwrap(), "b");
+    assert!(a.get_with_result("test.tenant.only_b").is_err());
+    assert_eq!(b.get_with_result("test.tenant.only_b").unwrap(), "1");
+
+    // The readiness property counts as one applied set per tenant.
+    let stats_a = tenants[0].stats().await.unwrap();
+    let stats_b = tenants[1].stats().await.unwrap();
+    assert_eq!(stats_a.name, "a");
+    assert_eq!((stats_a.service.applied, stats_a.service.rejected), (2, 0));
+    assert_eq!(stats_b.name, "b");
+    assert_eq!((stats_b.service.applied, stats_b.service.rejected), (3, 1));
+
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&guest_a);
+    let _ = std::fs::remove_dir_all(&guest_b);
+}
+
+#[tokio::test]
+async fn test_stalled_client_is_dropped() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = temp_dir("tenant_stalled");
+    let sockets = dir.join("sockets");
+    let config = ServiceConfig::default().tenant(TenantConfig::new("a", &dir, &sockets));
+    let tenants = run_tenants(config).await.unwrap();
+
+    // The command word, the name length and half of the name, then
+    // silence.
+    let socket_path = sockets.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+    stream
+        .write_all(&PROP_MSG_SETPROP2.to_ne_bytes())
+        .await
+        .unwrap();
+    stream.write_all(&4u32.to_ne_bytes()).await.unwrap();
+    stream.write_all(b"te").await.unwrap();
+
+    // The stalled client holds a handler slot, and nothing is queued.
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let stats = loop {
+        let stats = tenants[0].stats().await.unwrap();
+        if stats.socket.in_flight_connections == 1 || std::time::Instant::now() > deadline {
+            break stats;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(stats.socket.in_flight_connections, 1);
+    assert_eq!(stats.socket.waiting_connections, 0);
+
+    // The service answers with an error and closes well before the
+    // 10 s exchange deadline.
+    let mut reply = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut reply))
+        .await
+        .expect("the service must drop a stalled client")
+        .unwrap();
+    assert_eq!(
+        reply.get(..4),
+        Some(&PROP_ERROR_READ_DATA.to_ne_bytes()[..])
+    );
+
+    // Other clients were served meanwhile, and are after.
+    assert_eq!(
+        setprop2_raw(&sockets, "test.stalled.after", "1").await,
+        PROP_SUCCESS
+    );
+    let stats = tenants[0].stats().await.unwrap();
+    assert_eq!(stats.socket.read_timeouts, 1);
+    assert_eq!(stats.socket.exchange_timeouts, 0);
+
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_tenants_must_not_share_directories() {
+    let dir = temp_dir("tenant_shared");
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("a", dir.join("a"), dir.join("sockets")))
+        .tenant(TenantConfig::new("b", dir.join("b"), dir.join("sockets")));
+    let err = run_tenants(config).await.err().expect("shared socket dir");
+    assert!(err.to_string().contains("'a' and 'b'"), "{err}");
+    assert!(!dir.exists(), "nothing is started on a bad config");
+}
+
+#[tokio::test]
+async fn test_transforms_rewrite_sets() {
+    let dir = temp_dir("tenant_transform");
+    let transforms = TransformChain::new()
+        .add(
+            "test.legacy.",
+            Transform::RenamePrefix("test.xform.".into()),
+        )
+        .add("test.xform.", Transform::TrimValue)
+        .add("test.xform.flag.", Transform::NormalizeBool)
+        .add(
+            "test.xform.locked.",
+            Transform::custom(|name, _| Err(format!("{name} is managed centrally"))),
+        )
+        .add("test.xform.bad.", Transform::RenamePrefix("test..".into()));
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("t", &dir, dir.join("sockets")))
+        .options(ServiceOptions::default().transforms(transforms));
+    let tenants = run_tenants(config).await.unwrap();
+    let sockets = dir.join("sockets");
+
+    for (name, value) in [
+        ("test.xform.flag.wifi", " On "),
+        ("test.legacy.flag.bt", "false"),
+        ("test.xform.label", "  kitchen\n"),
+        ("test.other", " untouched "),
+    ] {
+        assert_eq!(setprop2_raw(&sockets, name, value).await, PROP_SUCCESS);
+    }
+    assert_eq!(
+        setprop2_raw(&sockets, "test.xform.locked.mode", "1").await,
+        PROP_ERROR_INVALID_VALUE
+    );
+    // A rewritten name is validated like one sent by a client.
+    assert_eq!(
+        setprop2_raw(&sockets, "test.xform.bad.x", "1").await,
+        PROP_ERROR_INVALID_NAME
+    );
+
+    let props = SystemProperties::open(&dir).unwrap();
+    assert_eq!(props.get_with_result("test.xform.flag.wifi").unwrap(), "1");
+    assert_eq!(props.get_with_result("test.xform.flag.bt").unwrap(), "0");
+    assert!(props.get_with_result("test.legacy.flag.bt").is_err());
+    assert_eq!(
+        props.get_with_result("test.xform.label").unwrap(),
+        "kitchen"
+    );
+    assert_eq!(props.get_with_result("test.other").unwrap(), " untouched ");
+    assert!(props.get_with_result("test.xform.locked.mode").is_err());
+
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_history_records_selected_prefixes() {
+    let dir = temp_dir("tenant_history");
+    let history_dir = dir.join("history");
+    let started = SystemTime::now() - Duration::from_secs(1);
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("t", dir.join("t"), dir.join("sockets")))
+        .options(
+            ServiceOptions::default().history(HistoryConfig::new(&history_dir, ["test.radio."])),
+        );
+    let tenants = run_tenants(config).await.unwrap();
+    let sockets = dir.join("sockets");
+
+    for (name, value) in [
+        ("test.radio.mode", "lte"),
+        ("test.other", "1"),
+        ("test.radio.mode", "nr\nsa"),
+    ] {
+        assert_eq!(setprop2_raw(&sockets, name, value).await, PROP_SUCCESS);
+    }
+
+    // Each tenant logs to its own subdirectory.
+    let tenant_history = history_dir.join("t");
+    let entries = history_of(&tenant_history, "test.radio.mode", started).unwrap();
+    let values: Vec<&str> = entries.iter().map(|entry| entry.value.as_str()).collect();
+    assert_eq!(values, ["lte", "nr\nsa"]);
+    assert!(entries[0].at >= started && entries[0].at <= entries[1].at);
+    assert!(history_of(&tenant_history, "test.other", started)
+        .unwrap()
+        .is_empty());
+    assert!(history_of(
+        &tenant_history,
+        "test.radio.mode",
+        SystemTime::now() + Duration::from_secs(60)
+    )
+    .unwrap()
+    .is_empty());
+
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_preload_progress_reports_each_step() {
+    let dir = temp_dir("tenant_preload");
+    std::fs::create_dir_all(&dir).unwrap();
+    let first = dir.join("first.prop");
+    let second = dir.join("second.prop");
+    std::fs::write(&first, "test.preload.a=1\ntest.preload.b=2\n").unwrap();
+    std::fs::write(&second, "test.preload.b=3\ntest.preload.c=4\n").unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let summary = Arc::new(Mutex::new(None));
+    let options = {
+        let (events, summary) = (events.clone(), summary.clone());
+        ServiceOptions::default().preload_progress(move |event| {
+            let line = match event {
+                PreloadEvent::FileStarted { path, index, total } => {
+                    format!("start {} {index}/{total}", path.display())
+                }
+                PreloadEvent::FileParsed {
+                    path, properties, ..
+                } => format!("parsed {} {properties}", path.display()),
+                PreloadEvent::FileFailed { path, .. } => format!("failed {}", path.display()),
+                PreloadEvent::Added { name } => format!("added {name}"),
+                PreloadEvent::Skipped { name, .. } => format!("skipped {name}"),
+                PreloadEvent::Failed { name, .. } => format!("failed {name}"),
+                PreloadEvent::Finished(done) => {
+                    *summary.lock().unwrap() = Some(done);
+                    "finished".to_owned()
+                }
+                _ => unreachable!(),
+            };
+            events.lock().unwrap().push(line);
+        })
+    };
+    let config = ServiceConfig::default()
+        .tenant(
+            TenantConfig::new("t", dir.join("t"), dir.join("sockets"))
+                .build_prop_files(vec![first.clone(), second.clone()]),
+        )
+        .options(options.clone());
+    let tenants = run_tenants(config).await.unwrap();
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            format!("start {} 0/2", first.display()),
+            format!("parsed {} 2", first.display()),
+            format!("start {} 1/2", second.display()),
+            format!("parsed {} 3", second.display()),
+            "added test.preload.a".to_owned(),
+            "added test.preload.b".to_owned(),
+            "added test.preload.c".to_owned(),
+            "finished".to_owned(),
+        ]
+    );
+    let done = summary.lock().unwrap().unwrap();
+    assert_eq!((done.files, done.added, done.skipped), (2, 3, 0));
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    // A file that cannot be read is named before the start fails.
+    events.lock().unwrap().clear();
+    let missing = dir.join("missing.prop");
+    let config = ServiceConfig::default()
+        .tenant(
+            TenantConfig::new("u", dir.join("u"), dir.join("sockets_u"))
+                .build_prop_files(vec![missing.clone()]),
+        )
+        .options(options);
+    assert!(run_tenants(config).await.is_err());
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            format!("start {} 0/1", missing.display()),
+            format!("failed {}", missing.display()),
+        ]
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_requirements_at_bootstrap() {
+    let dir = temp_dir("tenant_requirements");
+    std::fs::create_dir_all(&dir).unwrap();
+    let build_prop = dir.join("build.prop");
+    std::fs::write(&build_prop, "ro.hardware=ranchu\n").unwrap();
+    let tenant = |name: &str| {
+        TenantConfig::new(name, dir.join(name), dir.join(format!("sockets_{name}")))
+            .build_prop_files(vec![build_prop.clone()])
+    };
+    let requirements = PropertyRequirement::parse(
+        "ro.hardware required\n\
+         ro.product.locale default en-US\n\
+         ro.serialno required\n",
+    )
+    .unwrap();
+
+    // Missing and required: the start fails, naming the property.
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let options = {
+        let reports = reports.clone();
+        ServiceOptions::default()
+            .requirements(requirements.clone())
+            .preload_progress(move |event| {
+                if let PreloadEvent::Requirements(report) = event {
+                    reports.lock().unwrap().push(report.clone());
+                }
+            })
+    };
+    let config = ServiceConfig::default()
+        .tenant(tenant("fail"))
+        .options(options.clone());
+    let Err(error) = run_tenants(config).await else {
+        panic!("started without a required property");
+    };
+    let error = error.to_string();
+    assert!(error.contains("ro.serialno"), "{error}");
+    let report = reports.lock().unwrap().pop().unwrap();
+    assert_eq!(report.defaulted, ["ro.product.locale"]);
+    assert_eq!(report.missing, ["ro.serialno"]);
+
+    // Warned about instead, the defaults still apply.
+    let config = ServiceConfig::default()
+        .tenant(tenant("warn"))
+        .options(options.required_policy(RequiredPolicy::Warn));
+    let tenants = run_tenants(config).await.unwrap();
+    let props = SystemProperties::open(&dir.join("warn")).unwrap();
+    assert_eq!(props.get_with_result("ro.hardware").unwrap(), "ranchu");
+    assert_eq!(props.get_with_result("ro.product.locale").unwrap(), "en-US");
+    assert!(props.get_with_result("ro.serialno").is_err());
+
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_record_and_replay() {
+    let dir = temp_dir("tenant_record");
+    let recording = dir.join("session.rec");
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("t", dir.join("t"), dir.join("sockets")))
+        .options(ServiceOptions::default().record(&recording));
+    let tenants = run_tenants(config).await.unwrap();
+    let sockets = dir.join("sockets");
+    for (name, value) in [
+        ("test.boot.stage", "early"),
+        ("test.boot.mode", "a b\nc"),
+        ("test.boot.stage", "late"),
+    ] {
+        assert_eq!(setprop2_raw(&sockets, name, value).await, PROP_SUCCESS);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    // Each tenant records to its own file; the service's own readiness
+    // set comes first.
+    let recording = dir.join("session.rec.t");
+    let entries = read_recording(&recording).unwrap();
+    let sets: Vec<(&str, &str)> = entries
+        .iter()
+        .map(|entry| (entry.name.as_str(), entry.value.as_str()))
+        .collect();
+    assert_eq!(
+        sets,
+        [
+            (rsproperties::SERVICE_READY_PROPERTY, "1"),
+            ("test.boot.stage", "early"),
+            ("test.boot.mode", "a b\nc"),
+            ("test.boot.stage", "late"),
+        ]
+    );
+
+    // Replayed into a fresh store, with the recorded gaps kept.
+    let fresh = dir.join("fresh");
+    build_property_info(&fresh, "");
+    let mut store = SystemProperties::new_area(&fresh).unwrap();
+    // Sets are paced against the start of the replay, so one applied late
+    // shortens the gap after it: measure from before the replay.
+    let recorded_span = entries[3].at.duration_since(entries[0].at).unwrap();
+    let replay_started = Instant::now();
+    let mut applied_at = Vec::new();
+    let replayed = replay_with(&recording, ReplaySpeed::Original, |name, value| {
+        applied_at.push(Instant::now());
+        store.set(name, value)
+    })
+    .unwrap();
+    assert_eq!(replayed, 4);
+    assert!(applied_at[3] - replay_started >= recorded_span);
+    assert_eq!(store.get_with_result("test.boot.stage").unwrap(), "late");
+    assert_eq!(store.get_with_result("test.boot.mode").unwrap(), "a b\nc");
+
+    // Unpaced it only applies; a failing set stops the replay.
+    let mut names = Vec::new();
+    let error = replay_with(&recording, ReplaySpeed::Unpaced, |name, _| {
+        names.push(name.to_owned());
+        if name == "test.boot.mode" {
+            return Err(rsproperties::Error::InvalidArgument("refused".into()));
+        }
+        Ok(())
+    })
+    .unwrap_err();
+    assert_eq!(names.len(), 3);
+    assert!(error.to_string().contains("record 3"), "{error}");
+    assert!(replay_with(&recording, ReplaySpeed::Scaled(0.0), |_, _| Ok(())).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_persistent_properties_survive_restart() {
+    let dir = temp_dir("tenant_persist");
+    let build_prop = dir.join("build.prop");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        &build_prop,
+        "persist.test.mode=default\npersist.test.kept=1\n",
+    )
+    .unwrap();
+    let tenant = TenantConfig::new("t", dir.join("t"), dir.join("sockets"))
+        .build_prop_files(vec![build_prop]);
+    let config = ServiceConfig::default()
+        .tenant(tenant)
+        .options(ServiceOptions::default().persistent_properties(dir.join("persistent")));
+    let sockets = dir.join("sockets");
+    let stored = dir.join("persistent.t");
+
+    let tenants = run_tenants(config.clone()).await.unwrap();
+    let store = SystemProperties::open(&dir.join("t")).unwrap();
+    assert_eq!(
+        store
+            .get_with_result(PERSISTENT_PROPERTIES_READY_PROPERTY)
+            .unwrap(),
+        "true"
+    );
+    for (name, value) in [
+        ("persist.test.mode", "changed"),
+        ("persist.test.added", "a b\nc"),
+        ("test.volatile", "1"),
+    ] {
+        assert_eq!(setprop2_raw(&sockets, name, value).await, PROP_SUCCESS);
+    }
+    drop(store);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let persisted = read_persistent_properties(&stored).unwrap();
+    assert_eq!(
+        persisted.keys().collect::<Vec<_>>(),
+        ["persist.test.added", "persist.test.mode"]
+    );
+
+    // The persisted values win over build.prop; other sets are gone.
+    let tenants = run_tenants(config.clone()).await.unwrap();
+    let store = SystemProperties::open(&dir.join("t")).unwrap();
+    assert_eq!(
+        store.get_with_result("persist.test.mode").unwrap(),
+        "changed"
+    );
+    assert_eq!(
+        store.get_with_result("persist.test.added").unwrap(),
+        "a b\nc"
+    );
+    assert_eq!(store.get_with_result("persist.test.kept").unwrap(), "1");
+    assert!(store.get_with_result("test.volatile").is_err());
+    drop(store);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    // A damaged file is moved aside instead of failing the start.
+    std::fs::write(&stored, b"\x0f").unwrap();
+    let tenants = run_tenants(config).await.unwrap();
+    let store = SystemProperties::open(&dir.join("t")).unwrap();
+    assert_eq!(
+        store.get_with_result("persist.test.mode").unwrap(),
+        "default"
+    );
+    assert!(dir.join("persistent.t.corrupt").exists());
+    drop(store);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_control_properties_are_dispatched() {
+    let dir = temp_dir("tenant_control");
+    let (handler, mut messages) = ControlHandler::channel();
+    let refusing = ControlHandler::new(|message| match message.action() {
+        Some(ControlAction::Stop) => Err(format!("cannot stop {}", message.target)),
+        _ => Ok(()),
+    });
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("a", dir.join("a"), dir.join("a_sockets")))
+        .options(ServiceOptions::default().control_handler(handler));
+    let tenants = run_tenants(config).await.unwrap();
+    let sockets = dir.join("a_sockets");
+
+    for (name, value) in [
+        ("ctl.start", "demo"),
+        ("ctl.interface_restart", "aidl/foo"),
+        ("test.plain", "1"),
+    ] {
+        assert_eq!(setprop2_raw(&sockets, name, value).await, PROP_SUCCESS);
+    }
+    let start = messages.recv().await.unwrap();
+    assert_eq!(start.action(), Some(ControlAction::Start));
+    assert_eq!(start.target, "demo");
+    // Sent by this process over the socket.
+    let credentials = start.credentials.unwrap();
+    assert_eq!(credentials.pid, Some(std::process::id() as i32));
+    assert_eq!(
+        credentials.uid,
+        std::os::unix::fs::MetadataExt::uid(&std::fs::metadata(&dir).unwrap())
+    );
+    let other = messages.recv().await.unwrap();
+    assert_eq!(other.action(), None);
+    assert_eq!(other.command, "interface_restart");
+    assert_eq!(other.target, "aidl/foo");
+
+    // Control properties are not stored; other properties are.
+    let store = SystemProperties::open(&dir.join("a")).unwrap();
+    assert!(store.get_with_result("ctl.start").is_err());
+    assert_eq!(store.get_with_result("test.plain").unwrap(), "1");
+    let stats = tenants[0].stats().await.unwrap();
+    assert_eq!(stats.service.control, 2);
+    drop(store);
+
+    // With the receiver gone, control sets fail.
+    drop(messages);
+    assert_eq!(
+        setprop2_raw(&sockets, "ctl.stop", "demo").await,
+        PROP_ERROR_HANDLE_CONTROL_MESSAGE
+    );
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    // A handler's refusal reaches the client.
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("b", dir.join("b"), dir.join("b_sockets")))
+        .options(ServiceOptions::default().control_handler(refusing));
+    let tenants = run_tenants(config).await.unwrap();
+    let sockets = dir.join("b_sockets");
+    assert_eq!(
+        setprop2_raw(&sockets, "ctl.start", "demo").await,
+        PROP_SUCCESS
+    );
+    assert_eq!(
+        setprop2_raw(&sockets, "ctl.stop", "demo").await,
+        PROP_ERROR_HANDLE_CONTROL_MESSAGE
+    );
+    let stats = tenants[0].stats().await.unwrap();
+    assert_eq!((stats.service.control, stats.service.rejected), (1, 1));
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_set_listener_receives_stored_sets() {
+    let dir = temp_dir("tenant_listener");
+    let (listener, mut events) = SetListener::channel(3);
+    let transforms = TransformChain::new().add("test.listen.", Transform::TrimValue);
+    let config = ServiceConfig::default()
+        .tenant(TenantConfig::new("a", dir.join("a"), dir.join("a_sockets")))
+        .options(
+            ServiceOptions::default()
+                .transforms(transforms)
+                .set_listener(listener),
+        );
+    let tenants = run_tenants(config).await.unwrap();
+    let sockets = dir.join("a_sockets");
+
+    assert_eq!(
+        setprop2_raw(&sockets, "test.listen.a", " 1 ").await,
+        PROP_SUCCESS
+    );
+    assert_eq!(
+        setprop2_raw(&sockets, "test..listen", "1").await,
+        PROP_ERROR_INVALID_NAME
+    );
+    assert_eq!(
+        setprop2_raw(&sockets, "test.listen.b", "2").await,
+        PROP_SUCCESS
+    );
+    // The channel is full: the set is stored but not forwarded.
+    assert_eq!(
+        setprop2_raw(&sockets, "test.listen.c", "3").await,
+        PROP_SUCCESS
+    );
+
+    // The service's own sets come first, without credentials.
+    let ready = events.recv().await.unwrap();
+    assert_eq!(ready.name, rsproperties::SERVICE_READY_PROPERTY);
+    assert_eq!(ready.credentials, None);
+    // As stored, after the transforms; refused sets are not forwarded.
+    let first = events.recv().await.unwrap();
+    assert_eq!(
+        (first.name.as_str(), first.value.as_str()),
+        ("test.listen.a", "1")
+    );
+    assert_eq!(
+        first.credentials.unwrap().pid,
+        Some(std::process::id() as i32)
+    );
+    let second = events.recv().await.unwrap();
+    assert_eq!(second.name, "test.listen.b");
+    assert!(events.try_recv().is_err());
+    let store = SystemProperties::open(&dir.join("a")).unwrap();
+    assert_eq!(store.get_with_result("test.listen.c").unwrap(), "3");
+    drop(store);
+
+    // With the receiver gone, sets still succeed.
+    drop(events);
+    assert_eq!(
+        setprop2_raw(&sockets, "test.listen.d", "4").await,
+        PROP_SUCCESS
+    );
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,124 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The UNSETPROP extension: the service removes a property from its store
+//! and from the persistent file, refuses read-only ones, and answers an
+//! unset of a property that is not set with success.
+
+use std::path::Path;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use rsproperties::wire::{
+    PROP_ERROR_INVALID_NAME, PROP_ERROR_READ_ONLY_PROPERTY, PROP_MSG_SETPROP2, PROP_MSG_UNSETPROP,
+    PROP_SUCCESS,
+};
+use rsproperties::{PropertiesClient, PropertyConfig, SystemProperties};
+use rsproperties_service::{
+    read_persistent_properties, run_tenants, ServiceConfig, ServiceOptions, TenantConfig,
+};
+
+mod common;
+use common::temp_dir;
+
+async fn request(socket_dir: &Path, msg: &[u8]) -> i32 {
+    let socket_path = socket_dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME);
+    let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+    stream.write_all(msg).await.unwrap();
+
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).await.unwrap();
+    i32::from_ne_bytes(status)
+}
+
+async fn setprop2_raw(socket_dir: &Path, name: &str, value: &str) -> i32 {
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_SETPROP2.to_ne_bytes());
+    msg.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(name.as_bytes());
+    msg.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(value.as_bytes());
+    request(socket_dir, &msg).await
+}
+
+async fn unsetprop_raw(socket_dir: &Path, name: &str) -> i32 {
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&PROP_MSG_UNSETPROP.to_ne_bytes());
+    msg.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(name.as_bytes());
+    request(socket_dir, &msg).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_unset_removes_properties() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = temp_dir("unset_remove");
+    let build_prop = dir.join("build.prop");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(&build_prop, "ro.test.unset.sku=a\n").unwrap();
+    let tenant = TenantConfig::new("t", dir.join("t"), dir.join("sockets"))
+        .build_prop_files(vec![build_prop]);
+    let config = ServiceConfig::default()
+        .tenant(tenant)
+        .options(ServiceOptions::default().persistent_properties(dir.join("persistent")));
+    let sockets = dir.join("sockets");
+    let tenants = run_tenants(config).await.unwrap();
+    let store = SystemProperties::open(&dir.join("t")).unwrap();
+
+    for (name, value) in [("test.unset.mode", "1"), ("persist.test.unset", "2")] {
+        assert_eq!(setprop2_raw(&sockets, name, value).await, PROP_SUCCESS);
+    }
+    let serial = store.context_serial();
+    assert_eq!(
+        unsetprop_raw(&sockets, "test.unset.mode").await,
+        PROP_SUCCESS
+    );
+    assert!(store.context_serial() > serial);
+    assert!(store.get_with_result("test.unset.mode").is_err());
+    assert_eq!(
+        unsetprop_raw(&sockets, "test.unset.mode").await,
+        PROP_SUCCESS
+    );
+
+    assert_eq!(
+        unsetprop_raw(&sockets, "ro.test.unset.sku").await,
+        PROP_ERROR_READ_ONLY_PROPERTY
+    );
+    assert_eq!(store.get_with_result("ro.test.unset.sku").unwrap(), "a");
+    assert_eq!(
+        unsetprop_raw(&sockets, "test..unset").await,
+        PROP_ERROR_INVALID_NAME
+    );
+
+    // The client API, against this tenant's directories.
+    let client =
+        PropertiesClient::new(PropertyConfig::with_both_dirs(dir.join("t"), &sockets)).unwrap();
+    client.remove("persist.test.unset").unwrap();
+    assert_eq!(client.get_optional("persist.test.unset").unwrap(), None);
+    client.set("persist.test.unset", "3").unwrap();
+    assert_eq!(
+        client
+            .get_optional("persist.test.unset")
+            .unwrap()
+            .as_deref(),
+        Some("3")
+    );
+    client.remove("persist.test.unset").unwrap();
+    let err = client.remove("ro.test.unset.sku").unwrap_err();
+    assert!(
+        matches!(err, rsproperties::Error::ServiceError(ref e) if e.code == PROP_ERROR_READ_ONLY_PROPERTY),
+        "{err:?}"
+    );
+
+    let stats = tenants[0].stats().await.unwrap();
+    assert_eq!(stats.service.rejected, 3);
+    drop(store);
+    for tenant in tenants {
+        tenant.stop().await;
+    }
+    let persisted = read_persistent_properties(&dir.join("persistent.t")).unwrap();
+    assert!(!persisted.contains_key("persist.test.unset"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
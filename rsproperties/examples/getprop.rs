@@ -3,21 +3,24 @@
 
 //! `getprop` - Android-compatible property getter
 //!
-//! This example mimics Android's `getprop` command functionality.
+//! This example mimics Android's `getprop` command functionality, output
+//! included, so scripts written against Android's work unchanged.
 //! It can get system properties with optional default values.
 //!
 //! Usage:
-//!   getprop [property_name] [default_value]
+//!   getprop [-T | -Z] [property_name] [default_value]
 //!   getprop --properties-dir <dir> [property_name] [default_value]
 //!
 //! Examples:
 //!   getprop                                    # List all properties
+//!   getprop -Z                                 # List all properties' contexts
 //!   getprop ro.build.version.sdk               # Get specific property
 //!   getprop ro.build.version.sdk 0             # Get with default value
+//!   getprop -T ro.build.version.sdk            # Get its declared type
 //!   getprop --properties-dir ./props ro.test   # Use custom properties directory
 
 use clap::Parser;
-use rsproperties::PropertyConfig;
+use rsproperties::{GetpropColumn, PropertyConfig};
 
 #[derive(Parser, Debug)]
 #[command(name = "getprop")]
@@ -34,6 +37,14 @@ struct Args {
     #[arg(help = "Default value to return if property is not found")]
     default_value: Option<String>,
 
+    /// Print declared types instead of values
+    #[arg(short = 'T', conflicts_with = "contexts")]
+    types: bool,
+
+    /// Print SELinux contexts instead of values
+    #[arg(short = 'Z')]
+    contexts: bool,
+
     /// Custom properties directory
     #[arg(long, help = "Custom properties directory")]
     properties_dir: Option<std::path::PathBuf>,
@@ -58,29 +69,47 @@ fn main() {
         rsproperties::init(config);
     }
 
+    let column = if args.types {
+        GetpropColumn::Type
+    } else if args.contexts {
+        GetpropColumn::Context
+    } else {
+        GetpropColumn::Value
+    };
+
     // Execute the appropriate command
-    match args.property_name {
-        Some(name) => {
-            // Get specific property
-            let value = if let Some(default) = args.default_value {
-                rsproperties::get_or(&name, default)
-            } else {
-                let result: Result<String, _> = rsproperties::get(&name);
-                match result {
-                    Ok(val) if !val.is_empty() => val,
-                    _ => {
-                        // Property not found, print nothing (Android getprop behavior)
-                        return;
-                    }
-                }
-            };
-            println!("{value}");
+    let result = match args.property_name {
+        Some(name) => print_property(&name, args.default_value, column),
+        // `[name]: [value]` lines, sorted, exactly as Android prints them
+        None => rsproperties::format_getprop(std::io::stdout().lock(), column),
+    };
+    if let Err(e) = result {
+        eprintln!("getprop: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Prints one value, type or context on its own line. Like Android's
+/// getprop, a missing or empty property prints the default, or an empty
+/// line.
+fn print_property(
+    name: &str,
+    default: Option<String>,
+    column: GetpropColumn,
+) -> rsproperties::Result<()> {
+    let shown = match column {
+        GetpropColumn::Value => rsproperties::try_get(name).filter(|value| !value.is_empty()),
+        GetpropColumn::Type => {
+            rsproperties::try_system_properties()?
+                .describe(name)?
+                .type_str
         }
-        None => {
-            // List all properties (simplified implementation)
-            println!("Listing all properties is not yet implemented.");
-            println!("Use 'getprop <property_name>' to get a specific property.");
-            std::process::exit(1);
+        GetpropColumn::Context => {
+            rsproperties::try_system_properties()?
+                .describe(name)?
+                .context
         }
-    }
+    };
+    println!("{}", shown.or(default).unwrap_or_default());
+    Ok(())
 }
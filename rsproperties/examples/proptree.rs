@@ -0,0 +1,108 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `proptree` - browse properties as a read-only file tree
+//!
+//! Shows the layout `rsproperties::fs_view` gives a filesystem front end
+//! such as a FUSE mount: `ro.build.version.sdk` is the file
+//! `ro/build/version/sdk`. The subcommands are the operations a mount
+//! serves — `ls` is readdir, `cat` is read — and `watch` prints the paths
+//! a mount would invalidate in the kernel cache after each change.
+//!
+//! Usage:
+//!   proptree ls [path]
+//!   proptree cat <path>
+//!   proptree watch [--timeout <secs>]
+//!
+//! Examples:
+//!   proptree ls ro/build                       # Entries under ro.build.
+//!   proptree cat ro/build/version/sdk          # Same as getprop ro.build.version.sdk
+//!   proptree --properties-dir ./props watch    # Invalidations until Ctrl-C
+
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use rsproperties::fs_view::{EntryKind, PropertyTree};
+use rsproperties::PropertyConfig;
+
+#[derive(Parser, Debug)]
+#[command(name = "proptree")]
+#[command(about = "Browse properties as a read-only file tree")]
+struct Args {
+    /// Custom properties directory
+    #[arg(long, global = true, help = "Custom properties directory")]
+    properties_dir: Option<std::path::PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List a directory; directories end with `/`
+    Ls {
+        /// Directory path, the root by default
+        #[arg(default_value = "")]
+        path: String,
+    },
+    /// Print the contents of a file
+    Cat {
+        /// File path, e.g. `ro/build/version/sdk`
+        path: String,
+    },
+    /// Print the paths each change invalidates
+    Watch {
+        /// Exit after this many seconds without a change
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+    },
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let args = Args::parse();
+    if let Some(dir) = args.properties_dir {
+        rsproperties::init(PropertyConfig::with_properties_dir(dir));
+    }
+
+    if let Err(e) = run(args.command) {
+        eprintln!("proptree: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(command: Command) -> rsproperties::Result<()> {
+    let props = rsproperties::try_system_properties()?;
+    let mut tree = PropertyTree::new(props)?;
+    match command {
+        Command::Ls { path } => {
+            let Some(entries) = tree.read_dir(&path) else {
+                return Err(rsproperties::Error::NotFound(format!(
+                    "{path}: not a directory"
+                )));
+            };
+            for (name, kind) in entries {
+                match kind {
+                    EntryKind::Dir => println!("{name}/"),
+                    EntryKind::File => println!("{name}"),
+                }
+            }
+        }
+        Command::Cat { path } => {
+            let Some(contents) = tree.contents(&path) else {
+                return Err(rsproperties::Error::NotFound(format!("{path}: not a file")));
+            };
+            print!("{contents}");
+        }
+        Command::Watch { timeout } => {
+            let timeout = timeout.map(Duration::from_secs);
+            while let Some(changed) = tree.wait_and_refresh(props, timeout)? {
+                for path in changed {
+                    println!("/{path}");
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `quickstart` - a first look at the property store on any Linux host
+//!
+//! Builds the demo environment of `rsproperties::quickstart` in a temp
+//! directory, lists it, describes a few names and writes to it. Nothing
+//! outside the temp directory is touched, and it is removed on exit.
+//!
+//! Usage:
+//!   cargo run -p rsproperties --example quickstart --features builder
+
+fn main() -> rsproperties::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let (dir, mut props) = rsproperties::quickstart::demo_store()?;
+    println!("Demo property store in {}", dir.path().display());
+
+    println!("\nproperty_contexts:");
+    print!("{}", rsproperties::quickstart::PROPERTY_CONTEXTS);
+
+    println!("\nProperties:");
+    let frozen = props.freeze()?;
+    let mut all: Vec<_> = frozen.iter().collect();
+    all.sort();
+    for (name, value) in all {
+        println!("  [{name}]: [{value}]");
+    }
+
+    println!("\nDeclarations:");
+    for name in [
+        "ro.product.model",
+        "demo.count",
+        "demo.enabled",
+        "other.name",
+    ] {
+        let descriptor = props.describe(name)?;
+        println!(
+            "  {name}: context {}, type {}, {}",
+            descriptor.context.as_deref().unwrap_or("-"),
+            descriptor.type_str.as_deref().unwrap_or("-"),
+            if descriptor.exists { "set" } else { "unset" }
+        );
+    }
+
+    println!("\nWrites:");
+    props.set("demo.greeting", "bonjour")?;
+    println!(
+        "  demo.greeting is now [{}]",
+        props.get_with_result("demo.greeting")?
+    );
+    match props.set("ro.product.model", "Other") {
+        Ok(()) => println!("  ro.product.model was rewritten"),
+        Err(e) => println!("  ro.product.model stays put: {e}"),
+    }
+    Ok(())
+}
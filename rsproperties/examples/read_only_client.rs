@@ -0,0 +1,26 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `read_only_client` - the smallest useful consumer of the crate
+//!
+//! Reads one property and nothing else, so it builds with
+//! `--no-default-features`: no property service client, no writer, no
+//! parsers. `tests/size_check.rs` builds it both ways to keep the
+//! client-only build honest.
+//!
+//! Usage:
+//!   read_only_client <property_name>
+
+fn main() {
+    let Some(name) = std::env::args().nth(1) else {
+        eprintln!("usage: read_only_client <property_name>");
+        std::process::exit(2);
+    };
+    match rsproperties::get::<String>(&name) {
+        Ok(value) => println!("{value}"),
+        Err(e) => {
+            eprintln!("{name}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,608 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `rsprops` - property debugging tool
+//!
+//! Interactive helpers on top of the library for Linux targets that lack
+//! Android's toolbox.
+//!
+//! Usage:
+//!   rsprops list [-T | -Z]
+//!   rsprops get <name> [default]
+//!   rsprops set <name> <value>
+//!   rsprops watch [prefix] [--format table|json] [--timeout <secs>] [--count <n>]
+//!   rsprops import <file> --prefix <prefix> [--format env|json] [--dry-run]
+//!   rsprops export --prefix <prefix> [--format env|json]
+//!   rsprops diff <source-a> <source-b> [--prefix <prefix>] [--format table|json]
+//!   rsprops fsck
+//!   rsprops status
+//!   rsprops state
+//!
+//! Examples:
+//!   rsprops list                               # Every property, as getprop prints them
+//!   rsprops list -Z                            # Every property's SELinux context
+//!   rsprops --properties-dir ./props get ro.product.device
+//!   rsprops --socket-dir ./sockets set debug.mode 1
+//!   rsprops watch                              # Print every change until Ctrl-C
+//!   rsprops watch sys.                         # Only properties under `sys.`
+//!   rsprops watch --format json --count 1      # Print the next change as JSON, exit
+//!   rsprops --properties-dir ./props watch --timeout 10
+//!   rsprops import /etc/default/mydaemon --prefix vendor.mydaemon --dry-run
+//!   rsprops export --prefix vendor.mydaemon --format json > mydaemon.json
+//!   rsprops diff golden.json out/system/build.prop --prefix ro.
+//!   rsprops --properties-dir /persist/properties fsck
+//!   PROPERTY_SERVICE_SOCKET_DIR=/run/props rsprops status
+//!   rsprops state > state.txt                  # Attach to a bug report
+//!
+//! `list` prints exactly what Android's `getprop` prints without a name
+//! (`[name]: [value]` lines sorted by name, nothing escaped), and `-T` /
+//! `-Z` swap the value for the declared type / context, so scripts that
+//! parse getprop output work unchanged.
+//!
+//! `get` prints one value, or the default (or an empty line) when the
+//! property is missing or empty, as getprop does. `set` goes through the
+//! property service behind `--socket-dir` (or `PROPERTY_SERVICE_SOCKET_DIR`)
+//! and exits 1 when the service rejects the value. With `--properties-dir`
+//! and `--socket-dir` every subcommand works against emulated areas, such
+//! as those a `builder` program or `rsproperties-service` created.
+//!
+//! `watch` waits on the global serial and diffs two
+//! `SystemProperties::freeze` snapshots per wakeup, so it reports every
+//! property that changed, including newly added ones. Several updates
+//! between two wakeups collapse into one `old → new` line per property.
+//!
+//! `import`/`export` convert between properties and classic service
+//! configuration (see `rsproperties::migrate` for the naming rules).
+//! Collisions and skipped keys are reported on stderr; `import` sets the
+//! properties through the property service unless `--dry-run` is given.
+//!
+//! `diff` compares two sources: a properties directory (read directly, no
+//! service needed), a `build.prop` file (`import`/`import_overrides`
+//! followed; needs the `builder` feature), or a `.json` dump written by
+//! `rsprops export --prefix "" --format json`. Like diff(1) it exits 0
+//! when the sources match, 1 when they differ and 2 on error, so it can
+//! gate CI on image property regressions.
+//!
+//! `fsck` verifies every property record against its checksum (see
+//! `SystemProperties::enable_checksums`) and lists the corrupt ones; it
+//! exits 0 when all records are intact, 1 when some are corrupt and 2 on
+//! error. Records in areas without checksums are counted as unchecked.
+//!
+//! `status` probes the property service socket (see
+//! `rsproperties::service_status`) and exits 1 when sets would not reach
+//! a ready service.
+//!
+//! `state` maps every area and prints `SystemProperties::debug_state` —
+//! the global serial, the areas and the entries caught mid-update — with
+//! each area's space usage. It exits 1 when an entry is dirty, which
+//! points at a writer that died mid-update.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::Path;
+
+use rsproperties::{
+    migrate, FrozenProperties, GetpropColumn, PropertyConfig, SystemProperties, Timespec,
+};
+
+#[derive(Parser, Debug)]
+#[command(name = "rsprops")]
+#[command(about = "Property debugging tool")]
+struct Args {
+    /// Custom properties directory
+    #[arg(long, global = true, help = "Custom properties directory")]
+    properties_dir: Option<std::path::PathBuf>,
+
+    /// Custom socket directory
+    #[arg(long, global = true, help = "Custom socket directory")]
+    socket_dir: Option<std::path::PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List every property in Android getprop's format
+    List {
+        /// Print declared types instead of values
+        #[arg(short = 'T', conflicts_with = "contexts")]
+        types: bool,
+
+        /// Print SELinux contexts instead of values
+        #[arg(short = 'Z')]
+        contexts: bool,
+    },
+    /// Print one property's value
+    Get {
+        /// Property name
+        name: String,
+
+        /// Printed when the property is missing or empty
+        default: Option<String>,
+    },
+    /// Set a property through the property service
+    Set {
+        /// Property name
+        name: String,
+
+        /// New value
+        value: String,
+    },
+    /// Print property changes as they happen
+    Watch {
+        /// Only report properties whose name starts with this prefix
+        prefix: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+
+        /// Exit after this many seconds without failing
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+
+        /// Exit after this many reported changes
+        #[arg(long, value_name = "N")]
+        count: Option<u64>,
+    },
+    /// Set properties from an env file or JSON document
+    Import {
+        /// Source file; the format defaults to JSON for `.json` files
+        file: std::path::PathBuf,
+
+        /// Property name prefix for the imported keys
+        #[arg(long)]
+        prefix: String,
+
+        /// Source format
+        #[arg(long, value_enum)]
+        format: Option<ConfigFormat>,
+
+        /// Print the properties that would be set instead of setting them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the properties under a prefix as an env file or JSON document
+    Export {
+        /// Property name prefix to export
+        #[arg(long)]
+        prefix: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ConfigFormat::Env)]
+        format: ConfigFormat,
+    },
+    /// Compare two property sources; exits 1 if they differ
+    Diff {
+        /// Properties directory, `build.prop` file or `.json` dump
+        a: std::path::PathBuf,
+
+        /// Properties directory, `build.prop` file or `.json` dump
+        b: std::path::PathBuf,
+
+        /// Only compare properties whose name starts with this prefix
+        #[arg(long, default_value = "")]
+        prefix: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Verify property record checksums; exits 1 if any record is corrupt
+    Fsck,
+    /// Probe the property service; exits 1 if it is not available
+    Status,
+    /// Print serials, mapped areas and dirty entries; exits 1 if any is dirty
+    State,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ConfigFormat {
+    Env,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+/// One reported change; `old == None` means the property was added.
+struct Change<'a> {
+    name: &'a str,
+    old: Option<&'a str>,
+    new: &'a str,
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let args = Args::parse();
+    // PropertyConfig is #[non_exhaustive] — build it through the builder.
+    if args.properties_dir.is_some() || args.socket_dir.is_some() {
+        let mut builder = PropertyConfig::builder();
+        if let Some(dir) = args.properties_dir {
+            builder = builder.properties_dir(dir);
+        }
+        if let Some(dir) = args.socket_dir {
+            builder = builder.socket_dir(dir);
+        }
+        rsproperties::init(builder.build());
+    }
+
+    // `diff` follows diff(1) and `fsck` does likewise: errors exit 2, as
+    // 1 means "different" or "corrupt".
+    let error_code = match args.command {
+        Command::Diff { .. } | Command::Fsck => 2,
+        _ => 1,
+    };
+    let result = match args.command {
+        Command::List { types, contexts } => {
+            let column = if types {
+                GetpropColumn::Type
+            } else if contexts {
+                GetpropColumn::Context
+            } else {
+                GetpropColumn::Value
+            };
+            rsproperties::format_getprop(std::io::stdout().lock(), column).map(|()| 0)
+        }
+        Command::Get { name, default } => {
+            let value = rsproperties::get_optional(&name)
+                .map(|value| value.filter(|value| !value.is_empty()));
+            value.map(|value| {
+                println!("{}", value.or(default).unwrap_or_default());
+                0
+            })
+        }
+        Command::Set { name, value } => rsproperties::set(&name, &value).map(|()| 0),
+        Command::Watch {
+            prefix,
+            format,
+            timeout,
+            count,
+        } => watch(
+            prefix.as_deref().unwrap_or(""),
+            format,
+            timeout.map(Duration::from_secs),
+            count,
+        )
+        .map(|()| 0),
+        Command::Import {
+            file,
+            prefix,
+            format,
+            dry_run,
+        } => import(&file, &prefix, format, dry_run).map(|()| 0),
+        Command::Export { prefix, format } => export(&prefix, format).map(|()| 0),
+        Command::Diff {
+            a,
+            b,
+            prefix,
+            format,
+        } => compare(&a, &b, &prefix, format).map(|differ| differ as i32),
+        Command::Fsck => fsck().map(|corrupt| corrupt as i32),
+        Command::Status => Ok(status()),
+        Command::State => state().map(|dirty| dirty as i32),
+    };
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("rsprops: {e}");
+            std::process::exit(error_code);
+        }
+    }
+}
+
+fn watch(
+    prefix: &str,
+    format: Format,
+    timeout: Option<Duration>,
+    count: Option<u64>,
+) -> rsproperties::Result<()> {
+    let props = rsproperties::try_system_properties()?;
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut reported = 0u64;
+    let mut previous = props.freeze()?;
+
+    if let Format::Table = format {
+        println!("{:<14}  {:<40}  OLD -> NEW", "TIME", "NAME");
+    }
+    loop {
+        if count.is_some_and(|n| reported >= n) {
+            return Ok(());
+        }
+        let remaining = match deadline {
+            Some(d) => match d.checked_duration_since(Instant::now()) {
+                Some(r) if !r.is_zero() => Some(Timespec {
+                    tv_sec: r.as_secs() as _,
+                    tv_nsec: r.subsec_nanos() as _,
+                }),
+                _ => return Ok(()),
+            },
+            None => None,
+        };
+        // `None` is a timeout — or a wait failure, including macOS, which
+        // has no futex: fall back to polling the serial there.
+        if props
+            .wait(None, Some(previous.serial()), remaining.as_ref())
+            .is_none()
+            && props.context_serial() == previous.serial()
+        {
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+
+        let current = props.freeze()?;
+        let timestamp = timestamp();
+        for change in diff(&previous, &current, prefix) {
+            print_change(format, &timestamp, &change);
+            reported += 1;
+            if count.is_some_and(|n| reported >= n) {
+                break;
+            }
+        }
+        previous = current;
+    }
+}
+
+fn import(
+    file: &std::path::Path,
+    prefix: &str,
+    format: Option<ConfigFormat>,
+    dry_run: bool,
+) -> rsproperties::Result<()> {
+    let text = std::fs::read_to_string(file)?;
+    let format = format.unwrap_or(match file.extension() {
+        Some(ext) if ext == "json" => ConfigFormat::Json,
+        _ => ConfigFormat::Env,
+    });
+    let import = match format {
+        ConfigFormat::Env => migrate::import_env(prefix, &text)?,
+        ConfigFormat::Json => migrate::import_json(prefix, &text)?,
+    };
+    for collision in &import.collisions {
+        eprintln!(
+            "collision: {} from {:?} (dropped {:?})",
+            collision.name, collision.kept, collision.dropped
+        );
+    }
+    for skipped in &import.skipped {
+        eprintln!("skipped: {:?}: {}", skipped.key, skipped.reason);
+    }
+    for (name, value) in &import.properties {
+        if dry_run {
+            println!("{name}={value}");
+        } else {
+            rsproperties::set(name, value)?;
+        }
+    }
+    if !dry_run {
+        eprintln!("imported {} properties", import.properties.len());
+    }
+    Ok(())
+}
+
+fn export(prefix: &str, format: ConfigFormat) -> rsproperties::Result<()> {
+    let frozen = rsproperties::try_system_properties()?.freeze()?;
+    let export = match format {
+        ConfigFormat::Env => migrate::export_env(prefix, frozen.iter()),
+        ConfigFormat::Json => migrate::export_json(prefix, frozen.iter()),
+    };
+    for skipped in &export.skipped {
+        eprintln!("skipped: {}: {}", skipped.key, skipped.reason);
+    }
+    print!("{}", export.text);
+    Ok(())
+}
+
+/// Prints the differences between sources `a` and `b` under `prefix`,
+/// sorted by name, and returns whether there were any.
+fn compare(a: &Path, b: &Path, prefix: &str, format: Format) -> rsproperties::Result<bool> {
+    let a = load_source(a)?;
+    let b = load_source(b)?;
+    let mut names: Vec<&str> = a
+        .iter()
+        .chain(b.iter())
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for name in names {
+        let (old, new) = (a.get(name), b.get(name));
+        let kind = match (old, new) {
+            (None, Some(_)) => {
+                added += 1;
+                "added"
+            }
+            (Some(_), None) => {
+                removed += 1;
+                "removed"
+            }
+            (Some(old), Some(new)) if old != new => {
+                changed += 1;
+                "changed"
+            }
+            _ => continue,
+        };
+        match format {
+            Format::Table => match (old, new) {
+                (None, Some(new)) => println!("+ {name}={new}"),
+                (Some(old), None) => println!("- {name}={old}"),
+                (old, new) => println!(
+                    "~ {name}: {} -> {}",
+                    old.unwrap_or_default(),
+                    new.unwrap_or_default()
+                ),
+            },
+            Format::Json => println!(
+                "{{\"kind\":\"{kind}\",\"name\":{},\"a\":{},\"b\":{}}}",
+                json_string(name),
+                old.map_or_else(|| "null".to_owned(), json_string),
+                new.map_or_else(|| "null".to_owned(), json_string)
+            ),
+        }
+    }
+    eprintln!("{added} added, {removed} removed, {changed} changed");
+    Ok(added + removed + changed > 0)
+}
+
+/// Scrubs the store, printing the corrupt records; returns whether there
+/// were any.
+fn fsck() -> rsproperties::Result<bool> {
+    let report = rsproperties::try_system_properties()?.scrub()?;
+    for name in &report.corrupt {
+        println!("corrupt: {name}");
+    }
+    eprintln!(
+        "{} verified, {} unchecked, {} corrupt",
+        report.verified,
+        report.unchecked,
+        report.corrupt.len()
+    );
+    Ok(!report.is_clean())
+}
+
+fn status() -> i32 {
+    let status = rsproperties::service_status();
+    println!("socket:    {}", status.socket_path.display());
+    println!("present:   {}", status.socket_present);
+    println!("connect:   {}", status.connectable);
+    println!("ready:     {}", status.ready);
+    println!("protocol:  v{}", status.protocol_version);
+    if let Some(latency) = status.latency {
+        println!("latency:   {latency:?}");
+    }
+    if let Some(error) = &status.error {
+        println!("error:     {error}");
+    }
+    !status.is_available() as i32
+}
+
+/// Prints the store's debug state and area usage; returns whether an
+/// entry is dirty.
+fn state() -> rsproperties::Result<bool> {
+    let props = rsproperties::try_system_properties()?;
+    // Walking the areas maps them all, so the state covers every context.
+    let usage = props.fragmentation_report()?;
+    let state = props.debug_state()?;
+    print!("{state}");
+    println!("usage:");
+    for area in &usage.areas {
+        println!(
+            "  {}: {} of {} bytes used, {} unreachable",
+            area.context, area.used, area.capacity, area.unreachable
+        );
+    }
+    Ok(state.has_dirty())
+}
+
+/// Reads a `diff` source: a properties directory, a `.json` dump or a
+/// `build.prop` file.
+fn load_source(path: &Path) -> rsproperties::Result<FrozenProperties> {
+    if path.is_dir() {
+        return SystemProperties::open(path)?.freeze();
+    }
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let import = migrate::import_json_dump(&std::fs::read_to_string(path)?)?;
+        for skipped in &import.skipped {
+            eprintln!(
+                "{}: skipped {:?}: {}",
+                path.display(),
+                skipped.key,
+                skipped.reason
+            );
+        }
+        return Ok(import.properties.into_iter().collect());
+    }
+    load_build_prop(path)
+}
+
+#[cfg(feature = "builder")]
+fn load_build_prop(path: &Path) -> rsproperties::Result<FrozenProperties> {
+    let mut properties = std::collections::HashMap::new();
+    rsproperties::load_properties_from_file(path, None, "u:r:init:s0", &mut properties)?;
+    Ok(properties.into_iter().collect())
+}
+
+#[cfg(not(feature = "builder"))]
+fn load_build_prop(path: &Path) -> rsproperties::Result<FrozenProperties> {
+    Err(rsproperties::Error::InvalidArgument(format!(
+        "{}: reading build.prop files needs the `builder` feature",
+        path.display()
+    )))
+}
+
+/// Changes from `old` to `new` under `prefix`, sorted by name so a burst
+/// prints in a stable order. Properties are never deleted, so only
+/// additions and value changes exist.
+fn diff<'a>(old: &'a FrozenProperties, new: &'a FrozenProperties, prefix: &str) -> Vec<Change<'a>> {
+    let mut changes: Vec<Change<'a>> = new
+        .iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .filter_map(|(name, value)| {
+            let old_value = old.get(name);
+            (old_value != Some(value)).then_some(Change {
+                name,
+                old: old_value,
+                new: value,
+            })
+        })
+        .collect();
+    changes.sort_by(|a, b| a.name.cmp(b.name));
+    changes
+}
+
+/// Seconds since the Unix epoch with millisecond precision — no date
+/// library needed, and trivially sortable/joinable with other logs.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}.{:03}", now.as_secs(), now.subsec_millis())
+}
+
+fn print_change(format: Format, timestamp: &str, change: &Change<'_>) {
+    match format {
+        Format::Table => println!(
+            "{timestamp:<14}  {:<40}  {} -> {}",
+            change.name,
+            change.old.unwrap_or("(unset)"),
+            change.new
+        ),
+        Format::Json => {
+            println!(
+                "{{\"time\":{timestamp},\"name\":{},\"old\":{},\"new\":{}}}",
+                json_string(change.name),
+                change.old.map_or_else(|| "null".to_owned(), json_string),
+                json_string(change.new)
+            );
+        }
+    }
+}
+
+/// Minimal JSON string encoder (RFC 8259 escapes) — enough for property
+/// names and values without pulling in a serializer.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,194 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rebuilding a live properties directory in another area format, see
+//! [`migrate_area_dir`].
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::errors::*;
+use crate::layout::Layout;
+use crate::system_properties::SystemProperties;
+
+/// How many times a source that changed while the new areas were being
+/// built is re-read before giving up.
+const RESYNC_ATTEMPTS: usize = 3;
+
+/// On-disk format of a property area.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AreaFormat {
+    /// bionic's format: `PROP_AREA_VERSION` 0xfc6ed0ab areas, 128 KiB
+    /// unless `Layout::area_size` says otherwise.
+    /// The only format readers and writers support today.
+    #[default]
+    Bionic,
+}
+
+/// What [`migrate_area_dir`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AreaMigrationReport {
+    /// Format of the areas now at the live path.
+    pub format: AreaFormat,
+    /// Properties copied.
+    pub properties: usize,
+    /// Properties copied again because the source changed while the new
+    /// areas were being built.
+    pub resynced: usize,
+    /// Whether the new areas carry checksum sidecars, like the old ones.
+    pub checksums: bool,
+    /// Where the previous areas ended up: the staging path.
+    pub backup_dir: PathBuf,
+}
+
+/// [`migrate_area_dir_with_layout`] with the AOSP [`Layout`].
+pub fn migrate_area_dir(
+    old_dir: &Path,
+    new_dir: &Path,
+    target: AreaFormat,
+) -> Result<AreaMigrationReport> {
+    migrate_area_dir_with_layout(old_dir, new_dir, target, &Layout::default())
+}
+
+/// Rebuilds the properties directory `old_dir` in the `target` format and
+/// swaps it in, without a moment where `old_dir` is missing.
+///
+/// Every property of `old_dir` (in any format readers support) is copied
+/// into fresh areas built in the staging directory `new_dir`, together
+/// with `old_dir`'s `property_info`; areas that carried checksum sidecars
+/// get them in the new format too. Then the two directories are exchanged
+/// with one `renameat2(RENAME_EXCHANGE)`: `old_dir` holds the new areas
+/// and `new_dir` the previous ones, kept as a backup for the caller to
+/// delete. Where an atomic exchange is not available (non-Linux kernels,
+/// file systems without it) three plain renames are used instead, which
+/// leave `old_dir` missing for a moment.
+///
+/// Readers that mapped the old areas keep reading them and follow the
+/// new ones on their next lookup miss (see [`Error::AreaVanished`]). A
+/// writer still running on `old_dir` is not redirected: stop it before
+/// the swap, or restart it on the migrated directory right after.
+/// Changes it makes while the copy is built are picked up (up to three
+/// rounds); later ones are lost, so migrate a quiescent directory when
+/// that matters.
+///
+/// `new_dir` must not exist or be empty.
+pub fn migrate_area_dir_with_layout(
+    old_dir: &Path,
+    new_dir: &Path,
+    target: AreaFormat,
+    layout: &Layout,
+) -> Result<AreaMigrationReport> {
+    if new_dir.exists()
+        && std::fs::read_dir(new_dir)
+            .context_with_location(format!("Failed to read {new_dir:?}"))?
+            .next()
+            .is_some()
+    {
+        return Err(Error::InvalidArgument(format!(
+            "staging directory {new_dir:?} is not empty"
+        )));
+    }
+
+    let source = SystemProperties::open_with_layout(old_dir, layout)?;
+    let mut snapshot = source.freeze()?;
+    let checksums = has_checksum_sidecars(old_dir)?;
+
+    std::fs::create_dir_all(new_dir)
+        .context_with_location(format!("Failed to create {new_dir:?}"))?;
+    std::fs::copy(
+        layout.property_info_path(old_dir),
+        layout.property_info_path(new_dir),
+    )
+    .context_with_location(format!("Failed to copy property_info into {new_dir:?}"))?;
+
+    let mut target_props = match target {
+        AreaFormat::Bionic => SystemProperties::new_area_with_layout(new_dir, layout)?,
+    };
+    if checksums {
+        target_props.enable_checksums()?;
+    }
+    // Sorted for a deterministic layout, like the service's build.prop
+    // load. `add`, not `set`: the copy must not trip over `ro.` names.
+    let ordered: BTreeMap<&str, &str> = snapshot.iter().collect();
+    target_props.batch(|props| {
+        ordered
+            .into_iter()
+            .try_for_each(|(name, value)| props.add(name, value))
+    })?;
+    let properties = snapshot.len();
+
+    let mut resynced = 0;
+    let mut attempts = 0;
+    while source.context_serial() != snapshot.serial() {
+        attempts += 1;
+        if attempts > RESYNC_ATTEMPTS {
+            return Err(Error::LimitExceeded(format!(
+                "{old_dir:?} kept changing during {RESYNC_ATTEMPTS} migration resyncs"
+            )));
+        }
+        let current = source.freeze()?;
+        for (name, value) in current.iter() {
+            if snapshot.get(name) != Some(value) {
+                match target_props.find(name)? {
+                    Some(index) => target_props.update(&index, value)?,
+                    None => target_props.add(name, value)?,
+                }
+                resynced += 1;
+            }
+        }
+        snapshot = current;
+    }
+    drop(target_props);
+    drop(source);
+
+    exchange(old_dir, new_dir)?;
+    log::info!("Migrated {properties} properties in {old_dir:?} to {target:?}; previous areas in {new_dir:?}");
+    Ok(AreaMigrationReport {
+        format: target,
+        properties,
+        resynced,
+        checksums,
+        backup_dir: new_dir.to_path_buf(),
+    })
+}
+
+fn has_checksum_sidecars(dir: &Path) -> Result<bool> {
+    for entry in std::fs::read_dir(dir).context_with_location(format!("Failed to read {dir:?}"))? {
+        let entry = entry.context_with_location(format!("Failed to read {dir:?}"))?;
+        if entry.path().extension().is_some_and(|ext| ext == "crc") {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Swaps the directories at `a` and `b`.
+fn exchange(a: &Path, b: &Path) -> Result<()> {
+    #[cfg(any(
+        target_os = "android",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "ios"
+    ))]
+    {
+        use rustix::fs::{renameat_with, RenameFlags, CWD};
+        match renameat_with(CWD, a, CWD, b, RenameFlags::EXCHANGE) {
+            Ok(()) => return Ok(()),
+            Err(e) => log::warn!("Atomic exchange of {a:?} and {b:?} failed ({e}); renaming"),
+        }
+    }
+    let parked = {
+        let mut name = a.as_os_str().to_owned();
+        name.push(format!(".migrating-{}", std::process::id()));
+        PathBuf::from(name)
+    };
+    std::fs::rename(a, &parked).context_with_location(format!("Failed to move {a:?} aside"))?;
+    if let Err(e) = std::fs::rename(b, a) {
+        // Put the live directory back before reporting.
+        let _ = std::fs::rename(&parked, a);
+        return Err(e).context_with_location(format!("Failed to move {b:?} to {a:?}"));
+    }
+    std::fs::rename(&parked, b).context_with_location(format!("Failed to move {parked:?} to {b:?}"))
+}
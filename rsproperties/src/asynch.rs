@@ -0,0 +1,149 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`get`](crate::get), [`set`](crate::set) and
+//! [`SystemProperties::wait`] for tokio programs (feature `async`).
+//!
+//! The blocking calls would stall a runtime worker: opening the
+//! properties directory on first use, the round trip to the property
+//! service on a set, and the futex wait. These run them on tokio's
+//! blocking pool instead, and must be called from within a tokio runtime.
+//!
+//! ```rust,no_run
+//! # async fn run() -> rsproperties::Result<()> {
+//! use std::time::Duration;
+//!
+//! rsproperties::asynch::set_async("my_app.mode", "fast").await?;
+//! let mode: String = rsproperties::asynch::get_async("my_app.mode").await?;
+//!
+//! let handle = rsproperties::system_properties().find("my_app.mode")?;
+//! if let Some(handle) = handle {
+//!     let serial = rsproperties::asynch::wait_async(
+//!         Some(handle),
+//!         None,
+//!         Some(Duration::from_secs(5)),
+//!     )
+//!     .await?;
+//!     # let _ = (mode, serial);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use crate::errors::Result;
+use crate::system_properties::{PropertyHandle, SystemProperties};
+use crate::Timespec;
+
+/// Longest single wait [`wait_async`] parks a blocking-pool thread for.
+/// Dropping the future stops the wait within one slice.
+const WAIT_SLICE: Duration = Duration::from_millis(100);
+
+/// Runs `f` on tokio's blocking pool, resuming its panic on the caller.
+async fn blocking<R, F>(f: F) -> R
+where
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => panic!("Blocking property task did not finish: {e}"),
+    }
+}
+
+/// The global property store, initialized on the blocking pool the first
+/// time; later calls return without leaving the task.
+async fn store() -> Result<&'static SystemProperties> {
+    match crate::system_properties_if_initialized() {
+        Some(props) => Ok(props),
+        None => blocking(crate::try_system_properties).await,
+    }
+}
+
+/// [`get`](crate::get) for async callers.
+///
+/// Reading a value is a lock-free read of the mapped areas and runs in
+/// place; only the first access, which opens the properties directory
+/// (or fetches memfd areas from the service), moves to the blocking pool.
+pub async fn get_async<T>(name: &str) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    store().await?;
+    crate::get(name)
+}
+
+/// [`set`](crate::set) for async callers: the request to the property
+/// service runs on the blocking pool.
+///
+/// The value is formatted before the call, so it need not be `Send`.
+/// Dropping the future does not cancel a set already sent.
+#[cfg(feature = "service-protocol")]
+pub async fn set_async<T: std::fmt::Display + ?Sized>(name: &str, value: &T) -> Result<()> {
+    let name = name.to_owned();
+    let value = value.to_string();
+    blocking(move || crate::set(&name, &value)).await
+}
+
+/// [`SystemProperties::wait`] on the global store for async callers:
+/// waits until the property of `handle` (or, with `None`, any property)
+/// changes, returning the new serial, or `None` on timeout or a failed
+/// lookup. `old_serial` is as for `wait`.
+///
+/// The wait runs on the blocking pool in slices of at most 100ms, so a
+/// dropped future — a `select!` that took another branch, a
+/// `tokio::time::timeout` that fired — releases its thread within one
+/// slice, rather than holding it until the property changes.
+pub async fn wait_async(
+    handle: Option<PropertyHandle>,
+    old_serial: Option<u32>,
+    timeout: Option<Duration>,
+) -> Result<Option<u32>> {
+    let props = store().await?;
+    let started = Instant::now();
+    // Sampled once, as `wait` does with `None`: a change between two
+    // slices must end the wait rather than move its baseline.
+    let old = match old_serial {
+        Some(old) => old,
+        None => match &handle {
+            Some(handle) => match props.serial(handle) {
+                Some(serial) => serial,
+                None => return Ok(None),
+            },
+            None => props.context_serial(),
+        },
+    };
+
+    loop {
+        let slice = match timeout {
+            Some(timeout) => {
+                let remaining = timeout.saturating_sub(started.elapsed());
+                if remaining.is_zero() {
+                    return Ok(None);
+                }
+                remaining.min(WAIT_SLICE)
+            }
+            None => WAIT_SLICE,
+        };
+        let slice_started = Instant::now();
+        let changed = blocking(move || {
+            let slice = Timespec {
+                tv_sec: slice.as_secs() as _,
+                tv_nsec: slice.subsec_nanos() as _,
+            };
+            props.wait(handle.as_ref(), Some(old), Some(&slice))
+        })
+        .await;
+        if changed.is_some() {
+            return Ok(changed);
+        }
+        // `wait` also returns `None` for a lookup or futex failure, and
+        // returns it at once; retrying that would spin.
+        if slice_started.elapsed() < slice / 2 {
+            return Ok(None);
+        }
+    }
+}
@@ -0,0 +1,575 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! OS backends: how property area files are mapped and how serial words
+//! are waited on.
+//!
+//! The trie, area layout and seqlock protocol only need a shared mapping
+//! of each area file and a wait/wake primitive on 32-bit serial words.
+//! [`PropertyAreaBackend`] and [`WaitBackend`] are those two seams; the
+//! defaults, [`MmapBackend`] and [`FutexBackend`], are the rustix
+//! `mmap(MAP_SHARED)` and futex implementations used on Linux and
+//! Android. Platforms without them (QNX shared memory, file polling
+//! under WSL) plug in their own with [`set_area_backend`] /
+//! [`set_wait_backend`] before the first property access.
+//!
+//! Backends are process-wide and latched on first use, like the
+//! properties directory: every mapping must be created and released by
+//! the same backend, and every waiter must use the same wake mechanism
+//! as the writer.
+
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use rustix::fs::Timespec;
+use rustix::mm;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use rustix::thread::futex;
+
+use crate::errors::*;
+use crate::lock_order;
+
+/// Maps property area files (the per-context areas, the serial area and
+/// `property_info`) into memory.
+///
+/// # Safety
+///
+/// A successful [`Self::map`] must return a pointer that is valid for
+/// reads (and writes, when `writable`) of `size` bytes until the matching
+/// [`Self::unmap`], aligned to at least 8 bytes, and *shared*: stores by
+/// a writer — in this or another process — must become visible to every
+/// other mapping of the same file, with atomic operations on the mapped
+/// words behaving as they do on ordinary memory. The area structures are
+/// materialized in place from this memory, so anything weaker is
+/// undefined behavior, not just a stale read.
+pub unsafe trait PropertyAreaBackend: Send + Sync + 'static {
+    /// Maps the first `size` bytes of `file`; `size` is non-zero.
+    fn map(&self, file: File, size: usize, writable: bool) -> Result<NonNull<u8>>;
+
+    /// Maps the first `size` bytes of `file` readable and writable, with
+    /// writes kept private to this mapping (copy-on-write) — used by
+    /// [`crate::SystemProperties::fork_cow`]. Pages never written must
+    /// read the file's contents; the shared-visibility requirement above
+    /// does not apply to written ones. Backends that cannot do this keep
+    /// the default, which fails with `ENOTSUP`.
+    fn map_private(&self, file: File, size: usize) -> Result<NonNull<u8>> {
+        let _ = (file, size);
+        Err(Error::Errno(rustix::io::Errno::NOTSUP))
+    }
+
+    /// Releases a mapping returned by [`Self::map`] or [`Self::map_private`].
+    /// Runs from `Drop`, so failures are logged rather than returned; a
+    /// panic is caught and the mapping leaked.
+    ///
+    /// # Safety
+    ///
+    /// `base` and `size` must come from one successful `map` or
+    /// `map_private` call on this backend, and no reference into the
+    /// mapping may outlive this call.
+    unsafe fn unmap(&self, base: NonNull<u8>, size: usize);
+}
+
+/// Blocks on and wakes serial words inside mapped areas. Serials are
+/// bumped by writers in other processes, so the wait must observe
+/// changes made through any mapping of the area.
+pub trait WaitBackend: Send + Sync + 'static {
+    /// Waits until `serial` differs from `value` or the relative `timeout`
+    /// elapses; `None` waits indefinitely. Spurious wakes must not be
+    /// reported as [`WaitOutcome::Changed`].
+    fn wait(&self, serial: &AtomicU32, value: u32, timeout: Option<&Timespec>) -> WaitOutcome;
+
+    /// Wakes every waiter blocked on `serial`, returning how many were
+    /// woken if the mechanism knows. Failures only delay waiters that
+    /// re-check on their own, so callers log them and carry on.
+    fn wake(&self, serial: &AtomicU32) -> Result<usize>;
+}
+
+/// Outcome of one [`WaitBackend::wait`] call. A three-way result rather
+/// than `Option`: the bounded dirty-wait loop in
+/// [`crate::SystemProperties::serial`] must re-check on an ordinary
+/// timeout but bail out on a failure — collapsing both into `None` would
+/// turn a persistent wait error into a busy loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitOutcome {
+    /// The serial changed; carries the freshly-loaded value.
+    Changed(u32),
+    /// The timeout elapsed (or the caller passed an invalid/negative one).
+    TimedOut,
+    /// Unexpected wait error — or no wait support on this platform.
+    Failed,
+}
+
+/// `mmap(MAP_SHARED)` of the area file; the default area backend.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MmapBackend;
+
+// SAFETY: a MAP_SHARED file mapping is page-aligned, stays valid until
+// munmap, and is coherent with every other shared mapping of the file.
+unsafe impl PropertyAreaBackend for MmapBackend {
+    fn map(&self, file: File, size: usize, writable: bool) -> Result<NonNull<u8>> {
+        let flags = if writable {
+            mm::ProtFlags::READ.union(mm::ProtFlags::WRITE)
+        } else {
+            mm::ProtFlags::READ
+        };
+
+        // SAFETY: `file` is a valid owned `File`, the caller guarantees
+        // `size > 0`, and `mm::mmap` reports failure via `Result` rather
+        // than `MAP_FAILED`.
+        let base = unsafe {
+            mm::mmap(
+                std::ptr::null_mut(),
+                size,
+                flags,
+                mm::MapFlags::SHARED,
+                file,
+                0,
+            )
+        }
+        .map_err(Error::from)?;
+        NonNull::new(base as *mut u8)
+            .ok_or_else(|| Error::FileValidation("mmap returned a null mapping".into()))
+    }
+
+    fn map_private(&self, file: File, size: usize) -> Result<NonNull<u8>> {
+        // SAFETY: as in `map`. PROT_WRITE on a MAP_PRIVATE mapping only
+        // needs a readable descriptor.
+        let base = unsafe {
+            mm::mmap(
+                std::ptr::null_mut(),
+                size,
+                mm::ProtFlags::READ.union(mm::ProtFlags::WRITE),
+                mm::MapFlags::PRIVATE,
+                file,
+                0,
+            )
+        }
+        .map_err(Error::from)?;
+        NonNull::new(base as *mut u8)
+            .ok_or_else(|| Error::FileValidation("mmap returned a null mapping".into()))
+    }
+
+    unsafe fn unmap(&self, base: NonNull<u8>, size: usize) {
+        // SAFETY: per the trait contract, `base`/`size` describe a live
+        // mapping created by `map` above.
+        if let Err(e) = unsafe { mm::munmap(base.as_ptr().cast(), size) } {
+            log::error!("Failed to unmap memory: {e:?}");
+        }
+    }
+}
+
+/// Futex wait/wake on the serial word; the default wait backend on Linux
+/// and Android. macOS has no futex: there its waits fail immediately and
+/// wakes are no-ops, which is why [`PollWaitBackend`] is the default
+/// there.
+///
+/// Where a seccomp profile refuses the futex syscall (`EPERM`/`ENOSYS`),
+/// the backend switches the whole process to polling the serial word with
+/// an adaptive sleep — 1ms at first, doubling up to
+/// [`PollWaitBackend::POLL_SLICE`] — so waits keep working with a little
+/// extra latency; see [`Self::is_polling`]. Such a writer cannot wake
+/// futex waiters in other processes, so those only see its changes when
+/// their own timeout expires.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FutexBackend;
+
+/// Set once a futex syscall was refused; never cleared.
+static FUTEX_REFUSED: AtomicBool = AtomicBool::new(false);
+
+impl FutexBackend {
+    /// Whether futex syscalls were refused in this process, so waits poll
+    /// the serial word instead.
+    pub fn is_polling() -> bool {
+        FUTEX_REFUSED.load(Ordering::Relaxed)
+    }
+}
+
+impl WaitBackend for FutexBackend {
+    fn wait(&self, serial: &AtomicU32, value: u32, timeout: Option<&Timespec>) -> WaitOutcome {
+        futex_wait(serial, value, timeout)
+    }
+
+    fn wake(&self, serial: &AtomicU32) -> Result<usize> {
+        futex_wake(serial)
+    }
+}
+
+/// Futex-free wait backend: each waiter parks in `poll(2)` on its own
+/// socket pair, and a wake writes a byte to every waiter registered on
+/// that serial word. The default on macOS.
+///
+/// Wakes only reach waiters in the *same process*; a change made by
+/// another process is picked up by re-checking the serial every
+/// [`Self::POLL_SLICE`], so cross-process waits see the change with up to
+/// that much extra latency instead of never.
+#[derive(Debug, Default)]
+pub struct PollWaitBackend {
+    /// `(waiter id, serial word address, write end)` per parked waiter.
+    waiters: Mutex<Vec<(u64, usize, UnixStream)>>,
+    next_id: AtomicU64,
+}
+
+impl PollWaitBackend {
+    /// Longest a waiter sleeps without re-checking the serial.
+    pub const POLL_SLICE: Duration = Duration::from_millis(10);
+
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> lock_order::Tracked<MutexGuard<'_, Vec<(u64, usize, UnixStream)>>> {
+        // A panic while holding the lock cannot leave the list
+        // inconsistent (push/retain are atomic from our point of view).
+        lock_order::lock("PollWaitBackend::waiters", &self.waiters)
+    }
+
+    /// One bounded park: registers, re-checks, polls, unregisters.
+    fn park(&self, serial: &AtomicU32, value: u32, slice: Duration) -> std::io::Result<()> {
+        let (rx, tx) = UnixStream::pair()?;
+        tx.set_nonblocking(true)?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let addr = serial as *const AtomicU32 as usize;
+        self.lock().push((id, addr, tx));
+        // Registered before this re-check, so a wake that lands after it
+        // finds us in the list: no lost wakeup between load and poll.
+        let result = if serial.load(Ordering::Acquire) != value {
+            Ok(())
+        } else {
+            let slice = Timespec {
+                tv_sec: slice.as_secs() as _,
+                tv_nsec: slice.subsec_nanos() as _,
+            };
+            let mut fds = [rustix::event::PollFd::new(
+                &rx,
+                rustix::event::PollFlags::IN,
+            )];
+            match rustix::event::poll(&mut fds, Some(&slice)) {
+                Ok(_) | Err(rustix::io::Errno::INTR) => Ok(()),
+                Err(e) => Err(e.into()),
+            }
+        };
+        self.lock().retain(|(waiter, _, _)| *waiter != id);
+        result
+    }
+}
+
+impl WaitBackend for PollWaitBackend {
+    fn wait(&self, serial: &AtomicU32, value: u32, timeout: Option<&Timespec>) -> WaitOutcome {
+        // Same timeout rules as the futex path: invalid values time out
+        // immediately, unrepresentable deadlines wait forever.
+        let deadline = match timeout {
+            None => None,
+            Some(t) if t.tv_sec < 0 || t.tv_nsec < 0 || t.tv_nsec >= 1_000_000_000 => {
+                return WaitOutcome::TimedOut;
+            }
+            Some(t) => Instant::now().checked_add(Duration::new(t.tv_sec as u64, t.tv_nsec as u32)),
+        };
+        loop {
+            let current = serial.load(Ordering::Acquire);
+            if current != value {
+                return WaitOutcome::Changed(current);
+            }
+            let slice = match deadline {
+                None => Self::POLL_SLICE,
+                Some(d) => {
+                    let remaining = d.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return WaitOutcome::TimedOut;
+                    }
+                    remaining.min(Self::POLL_SLICE)
+                }
+            };
+            if let Err(e) = self.park(serial, value, slice) {
+                log::error!("Failed to wait for property change: {e}");
+                return WaitOutcome::Failed;
+            }
+        }
+    }
+
+    fn wake(&self, serial: &AtomicU32) -> Result<usize> {
+        let addr = serial as *const AtomicU32 as usize;
+        let mut woken = 0;
+        for (_, _, tx) in self.lock().iter().filter(|(_, a, _)| *a == addr) {
+            let mut tx: &UnixStream = tx;
+            // WouldBlock means an earlier wake is still unread: the waiter
+            // is already runnable.
+            match tx.write(&[1]) {
+                Ok(_) => woken += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => woken += 1,
+                Err(e) => log::warn!("Failed to wake a polling waiter: {e}"),
+            }
+        }
+        Ok(woken)
+    }
+}
+
+#[cfg(target_os = "macos")]
+static DEFAULT_WAIT: PollWaitBackend = PollWaitBackend::new();
+#[cfg(not(target_os = "macos"))]
+static DEFAULT_WAIT: FutexBackend = FutexBackend;
+
+static AREA_BACKEND: OnceLock<&'static dyn PropertyAreaBackend> = OnceLock::new();
+static WAIT_BACKEND: OnceLock<&'static dyn WaitBackend> = OnceLock::new();
+
+/// Installs the area backend for this process. Fails with
+/// [`Error::AlreadyInitialized`] once a backend is latched — by an earlier
+/// call or by the first mapping, which latches [`MmapBackend`].
+pub fn set_area_backend(backend: &'static dyn PropertyAreaBackend) -> Result<()> {
+    AREA_BACKEND
+        .set(backend)
+        .map_err(|_| Error::AlreadyInitialized("property area backend".into()))
+}
+
+/// Installs the wait backend for this process. Fails with
+/// [`Error::AlreadyInitialized`] once a backend is latched — by an earlier
+/// call or by the first wait or write, which latches the platform default
+/// ([`FutexBackend`], or [`PollWaitBackend`] on macOS).
+pub fn set_wait_backend(backend: &'static dyn WaitBackend) -> Result<()> {
+    WAIT_BACKEND
+        .set(backend)
+        .map_err(|_| Error::AlreadyInitialized("wait backend".into()))
+}
+
+pub(crate) fn area() -> &'static dyn PropertyAreaBackend {
+    *AREA_BACKEND.get_or_init(|| &MmapBackend)
+}
+
+pub(crate) fn waiter() -> &'static dyn WaitBackend {
+    *WAIT_BACKEND.get_or_init(|| &DEFAULT_WAIT)
+}
+
+/// Records that the futex syscall is refused here; logs on the first time.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn futex_refused(e: rustix::io::Errno) {
+    if !FUTEX_REFUSED.swap(true, Ordering::Relaxed) {
+        log::warn!("futex is unavailable ({e}); property waits fall back to polling");
+    }
+}
+
+/// Waits for `serial` to leave `value` by re-checking it, sleeping 1ms at
+/// first and twice as long after every unchanged check, up to
+/// [`PollWaitBackend::POLL_SLICE`].
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn poll_serial(serial: &AtomicU32, value: u32, deadline: Option<Instant>) -> WaitOutcome {
+    let mut sleep = Duration::from_millis(1);
+    loop {
+        let current = serial.load(Ordering::Acquire);
+        if current != value {
+            return WaitOutcome::Changed(current);
+        }
+        let slice = match deadline {
+            None => sleep,
+            Some(d) => {
+                let remaining = d.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return WaitOutcome::TimedOut;
+                }
+                remaining.min(sleep)
+            }
+        };
+        std::thread::sleep(slice);
+        sleep = (sleep * 2).min(PollWaitBackend::POLL_SLICE);
+    }
+}
+
+fn futex_wake(_addr: &AtomicU32) -> Result<usize> {
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    {
+        use rustix::io::Errno;
+        // Pollers re-check on their own; nobody is parked in the kernel.
+        if FUTEX_REFUSED.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        match futex::wake(_addr, futex::Flags::empty(), i32::MAX as u32) {
+            Err(e @ (Errno::PERM | Errno::NOSYS)) => {
+                futex_refused(e);
+                Ok(0)
+            }
+            result => result.context_with_location("Failed to wake futex"),
+        }
+    }
+    #[cfg(target_os = "macos")]
+    Ok(0)
+}
+
+fn futex_wait(_serial: &AtomicU32, _value: u32, _timeout: Option<&Timespec>) -> WaitOutcome {
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    {
+        use rustix::io::Errno;
+        // Linux futex_wait takes a *relative* timeout. Spurious wakes restart
+        // the syscall, so we track a deadline and shrink the remaining timeout
+        // each iteration to keep the total wait bounded by the caller-supplied
+        // value.
+        //
+        // `Timespec.tv_sec`/`tv_nsec` are signed (i64). Negative values are
+        // not valid timeouts; treat them as immediate timeout to avoid
+        // panicking in `Instant + Duration` from a `usize::MAX`-ish wrap.
+        // A *huge* positive `tv_sec` (e.g. i64::MAX, a reasonable "wait
+        // forever") is the opposite hazard: `Instant + Duration` panics on
+        // overflow, so an unrepresentable deadline degrades to an infinite
+        // wait instead — matching bionic, which passes the value through to
+        // the futex untouched.
+        let deadline = match _timeout {
+            None => None,
+            Some(t) if t.tv_sec < 0 || t.tv_nsec < 0 || t.tv_nsec >= 1_000_000_000 => {
+                return WaitOutcome::TimedOut;
+            }
+            Some(t) => Instant::now().checked_add(Duration::new(t.tv_sec as u64, t.tv_nsec as u32)),
+        };
+        if FUTEX_REFUSED.load(Ordering::Relaxed) {
+            return poll_serial(_serial, _value, deadline);
+        }
+        loop {
+            let remaining_ts = match deadline {
+                None => None,
+                Some(d) => {
+                    let r = d.saturating_duration_since(Instant::now());
+                    if r.is_zero() {
+                        return WaitOutcome::TimedOut;
+                    }
+                    Some(Timespec {
+                        tv_sec: r.as_secs() as _,
+                        tv_nsec: r.subsec_nanos() as _,
+                    })
+                }
+            };
+            match futex::wait(
+                _serial,
+                futex::Flags::empty(),
+                _value as _,
+                remaining_ts.as_ref(),
+            ) {
+                Ok(_) => {
+                    let new_serial = _serial.load(Ordering::Acquire);
+                    if _value != new_serial {
+                        return WaitOutcome::Changed(new_serial);
+                    }
+                    // Spurious wake — loop with the recomputed remaining timeout.
+                }
+                // EAGAIN: the serial no longer equals `_value` at syscall
+                // time — i.e. the property changed between the caller's load
+                // and the wait. This is the *common* race, not a failure;
+                // bionic's wait loop falls through to the serial re-check
+                // and reports success. Treating it as an error here would
+                // silently swallow a real property change.
+                Err(Errno::AGAIN) => {
+                    let new_serial = _serial.load(Ordering::Acquire);
+                    if _value != new_serial {
+                        return WaitOutcome::Changed(new_serial);
+                    }
+                    // Serial changed and wrapped back to `_value` between the
+                    // syscall and the reload — vanishingly unlikely; retry.
+                }
+                // Interrupted by a signal — retry with the recomputed
+                // remaining timeout so the total wait stays bounded.
+                Err(Errno::INTR) => {}
+                // Timeout is a normal outcome, not an error worth logging.
+                Err(Errno::TIMEDOUT) => return WaitOutcome::TimedOut,
+                // Refused by a seccomp profile (or not built into the
+                // kernel): poll instead, for this wait and every later one.
+                Err(e @ (Errno::PERM | Errno::NOSYS)) => {
+                    futex_refused(e);
+                    return poll_serial(_serial, _value, deadline);
+                }
+                Err(e) => {
+                    log::error!("Failed to wait for property change: {e}");
+                    return WaitOutcome::Failed;
+                }
+            }
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let _ = (_serial, _value, _timeout);
+        WaitOutcome::Failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn timespec(d: Duration) -> Timespec {
+        Timespec {
+            tv_sec: d.as_secs() as _,
+            tv_nsec: d.subsec_nanos() as _,
+        }
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn test_poll_serial() {
+        let serial = Arc::new(AtomicU32::new(0));
+        let deadline = || Some(Instant::now() + Duration::from_secs(10));
+
+        assert_eq!(poll_serial(&serial, 1, deadline()), WaitOutcome::Changed(0));
+        let writer = {
+            let serial = serial.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                serial.store(2, Ordering::Release);
+            })
+        };
+        assert_eq!(poll_serial(&serial, 0, deadline()), WaitOutcome::Changed(2));
+        writer.join().unwrap();
+
+        let start = Instant::now();
+        let short = Some(start + Duration::from_millis(30));
+        assert_eq!(poll_serial(&serial, 2, short), WaitOutcome::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_poll_wait_backend() {
+        let backend = Arc::new(PollWaitBackend::new());
+        let serial = Arc::new(AtomicU32::new(0));
+        let long = timespec(Duration::from_secs(10));
+
+        // Woken: the waiter returns as soon as the writer wakes it.
+        let waiter = {
+            let (backend, serial) = (backend.clone(), serial.clone());
+            std::thread::spawn(move || backend.wait(&serial, 0, Some(&long)))
+        };
+        while backend.lock().is_empty() {
+            std::thread::yield_now();
+        }
+        serial.store(2, Ordering::Release);
+        assert!(backend.wake(&serial).unwrap() >= 1);
+        assert_eq!(waiter.join().unwrap(), WaitOutcome::Changed(2));
+        assert!(backend.lock().is_empty(), "waiter must unregister");
+
+        // Not woken (a writer in another process): seen on the next slice.
+        let waiter = {
+            let (backend, serial) = (backend.clone(), serial.clone());
+            std::thread::spawn(move || backend.wait(&serial, 2, Some(&long)))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        serial.store(4, Ordering::Release);
+        assert_eq!(waiter.join().unwrap(), WaitOutcome::Changed(4));
+
+        let start = Instant::now();
+        let short = timespec(Duration::from_millis(50));
+        assert_eq!(
+            backend.wait(&serial, 4, Some(&short)),
+            WaitOutcome::TimedOut
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        let invalid = Timespec {
+            tv_sec: -1,
+            tv_nsec: 0,
+        };
+        assert_eq!(
+            backend.wait(&serial, 4, Some(&invalid)),
+            WaitOutcome::TimedOut
+        );
+    }
+}
@@ -4,10 +4,11 @@
 use log::{error, warn};
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
 use crate::errors::*;
+use crate::line_reader::{read_bounded_line, MAX_LINE_LEN};
 
 const RESTORECON_PROPERTY: &str = "selinux.restorecon_recursive";
 
@@ -34,55 +35,6 @@ fn check_permissions(_key: &str, _value: &str, _context: &str) {
     // TODO: Implement proper permission checking
 }
 
-/// Bound on one line's in-memory size. Real prop-file lines are a few
-/// hundred bytes; the cap exists so a crafted newline-less file cannot
-/// grow a single `read_until` buffer without bound — the same threat
-/// model as [`MAX_IMPORT_DEPTH`] / [`MAX_TOTAL_LOADS`].
-pub(crate) const MAX_LINE_LEN: usize = 64 * 1024;
-
-/// `read_until(b'\n')` with `MAX_LINE_LEN` as a hard memory bound.
-/// Returns `(bytes_read_this_line, truncated)`; `(0, false)` is EOF. An
-/// over-long line is drained (in bounded chunks) to its newline and
-/// reported as `truncated = true` with only the first `MAX_LINE_LEN`
-/// bytes in `buf` — callers warn and skip it.
-pub(crate) fn read_bounded_line(
-    reader: &mut impl BufRead,
-    buf: &mut Vec<u8>,
-) -> std::io::Result<(usize, bool)> {
-    buf.clear();
-    // `+ 1`: reading one byte past the cap distinguishes "line of exactly
-    // MAX_LINE_LEN bytes" (fine) from "line longer than the cap".
-    // UFCS on a reborrow: plain `reader.take(...)` resolves to `take`
-    // *by value* on the opaque `impl BufRead` and fails to move out of
-    // the `&mut`.
-    let read = Read::take(&mut *reader, MAX_LINE_LEN as u64 + 1).read_until(b'\n', buf)?;
-    if buf.len() <= MAX_LINE_LEN || buf.ends_with(b"\n") {
-        return Ok((read, false));
-    }
-    // Over-long line: keep only the bounded prefix and drain the rest up
-    // to (and including) its newline through the reader's own buffer —
-    // memory stays bounded no matter the line length.
-    buf.truncate(MAX_LINE_LEN);
-    let mut total = read;
-    loop {
-        let available = reader.fill_buf()?;
-        if available.is_empty() {
-            return Ok((total, true)); // EOF inside the over-long line
-        }
-        match available.iter().position(|&b| b == b'\n') {
-            Some(nl) => {
-                reader.consume(nl + 1);
-                return Ok((total + nl + 1, true));
-            }
-            None => {
-                let n = available.len();
-                reader.consume(n);
-                total += n;
-            }
-        }
-    }
-}
-
 /// Loads `key=value` pairs from an Android build.prop-style file into
 /// `properties`.
 ///
@@ -0,0 +1,135 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Binary property values.
+//!
+//! Property values are NUL-free C strings, so binary data is stored as
+//! standard base64 (RFC 4648, padded). Properties meant to hold it are
+//! declared with the `bytes` type in `property_contexts`; the builder's
+//! `set` rejects values that do not decode, and the client helpers refuse
+//! properties declared otherwise.
+
+use crate::errors::*;
+use crate::wire::PROP_VALUE_MAX;
+
+/// `property_contexts` type tag for base64-encoded binary values.
+pub(crate) const BYTES_TYPE: &str = "bytes";
+
+/// Largest binary value a writable property — one not under the store's
+/// read-only prefixes — can hold: the longest
+/// multiple-of-4 encoding that fits below `PROP_VALUE_MAX`.
+pub const PROP_BYTES_MAX: usize = (PROP_VALUE_MAX - 1) / 4 * 3;
+
+#[cfg(any(test, feature = "service-protocol"))]
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[cfg(any(test, feature = "service-protocol"))]
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Strict decoder: padding is required, and non-zero trailing bits are
+/// rejected so every byte string has exactly one accepted encoding.
+pub(crate) fn decode(value: &str) -> Result<Vec<u8>> {
+    let bytes = value.as_bytes();
+    if bytes.len() % 4 != 0 {
+        return Err(Error::Parse(format!(
+            "base64 length {} is not a multiple of 4",
+            bytes.len()
+        )));
+    }
+    let mut out = Vec::with_capacity(bytes.len() / 4 * 3);
+    let quads = bytes.len() / 4;
+    for (q, quad) in bytes.chunks(4).enumerate() {
+        let padding = quad.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && q + 1 != quads) {
+            return Err(Error::Parse("misplaced base64 padding".into()));
+        }
+        let mut n = 0u32;
+        for &c in &quad[..4 - padding] {
+            let sextet = sextet(c)
+                .ok_or_else(|| Error::Parse(format!("invalid base64 character {:?}", c as char)))?;
+            n = n << 6 | u32::from(sextet);
+        }
+        n <<= 6 * padding as u32;
+        let decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        let keep = 3 - padding;
+        if decoded[keep..].iter().any(|&b| b != 0) {
+            return Err(Error::Parse("non-canonical base64 trailing bits".into()));
+        }
+        out.extend_from_slice(&decoded[..keep]);
+    }
+    Ok(out)
+}
+
+fn sextet(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Fails unless the trie declares `name` as `bytes`: reading or writing a
+/// text property as binary would hand back garbage or clobber it.
+pub(crate) fn check_bytes_type(
+    props: &crate::system_properties::SystemProperties,
+    name: &str,
+) -> Result<()> {
+    match props.property_type(name)? {
+        Some(BYTES_TYPE) => Ok(()),
+        other => Err(Error::InvalidArgument(format!(
+            "property '{name}' is declared as {:?}, not '{BYTES_TYPE}'",
+            other.unwrap_or("(no type)")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for len in 0..=PROP_BYTES_MAX {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            let encoded = encode(&data);
+            assert!(encoded.len() < PROP_VALUE_MAX);
+            assert_eq!(decode(&encoded).unwrap(), data, "len {len}");
+        }
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(&[0xff, 0xfe]), "//4=");
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed() {
+        for bad in [
+            "Zm8", "Zm8==", "Z===", "Zm=v", "Zm9=Zm8=", "Zm9v!A==", "Zm9=",
+        ] {
+            assert!(
+                matches!(decode(bad), Err(Error::Parse(_))),
+                "{bad:?} must be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A property read that is only repeated when the property changed, see
+//! [`CachedProperty`].
+
+use crate::errors::*;
+use crate::system_properties::{PropertyHandle, SystemProperties};
+
+/// One property's value, kept between reads — the counterpart of
+/// libbase's `CachedProperty`, for properties read on a hot path.
+///
+/// The first [`Self::get`] looks the name up and keeps the
+/// [`PropertyHandle`]; later ones only compare the property's serial with
+/// the one the value was read at, and read the value again when it moved.
+/// While the property is not set, the name is only looked up again once
+/// the global serial ([`SystemProperties::context_serial`]) moved; once it
+/// moved while the property is set, the cached record is checked to still
+/// be the name's, so a [`SystemProperties::remove`] turns the value back
+/// into `""`.
+///
+/// ```rust,no_run
+/// use rsproperties::CachedProperty;
+///
+/// let mut level = CachedProperty::new("debug.my_app.log_level");
+/// if level.get() == "verbose" {
+///     // ...
+/// }
+/// ```
+pub struct CachedProperty {
+    /// `None` for the global instance, resolved on every read so a
+    /// `CachedProperty` can be created before it is initialized.
+    props: Option<&'static SystemProperties>,
+    name: String,
+    state: State,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Not looked up yet, or the last lookup failed.
+    Unresolved,
+    /// Not set as of this global serial.
+    Missing { global_serial: u32 },
+    /// `value` was read at `serial`, and the name led to `handle` as of
+    /// this global serial.
+    Found {
+        handle: PropertyHandle,
+        serial: u32,
+        global_serial: u32,
+    },
+}
+
+impl CachedProperty {
+    /// Caches `name` of the global instance
+    /// ([`crate::system_properties()`]).
+    pub fn new(name: &str) -> Self {
+        Self::with_store(None, name)
+    }
+
+    /// Caches `name` of `props`.
+    pub fn with_properties(props: &'static SystemProperties, name: &str) -> Self {
+        Self::with_store(Some(props), name)
+    }
+
+    fn with_store(props: Option<&'static SystemProperties>, name: &str) -> Self {
+        Self {
+            props,
+            name: name.to_owned(),
+            state: State::Unresolved,
+            value: String::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The current value, or `""` while the property is not set. If the
+    /// store cannot be read, the last value read is returned and the
+    /// failure logged.
+    pub fn get(&mut self) -> &str {
+        let props = match self.props {
+            Some(props) => Ok(props),
+            None => crate::try_system_properties(),
+        };
+        match props.and_then(|props| self.refresh(props)) {
+            Ok(()) => {}
+            Err(e) => {
+                log::warn!("Failed to refresh cached property {}: {e}", self.name);
+                self.state = State::Unresolved;
+            }
+        }
+        &self.value
+    }
+
+    fn refresh(&mut self, props: &SystemProperties) -> Result<()> {
+        // Sampled before the lookup, so an add or remove racing it moves
+        // the serial past the one recorded below.
+        let global_serial = props.context_serial();
+        if let State::Found {
+            handle,
+            serial,
+            global_serial: seen,
+        } = self.state
+        {
+            // A remove moves the global serial but not the record's, so
+            // only then is the name's trie node read again.
+            if seen == global_serial || props.is_linked(&handle)? {
+                match props.serial(&handle) {
+                    Some(current) if current == serial => {
+                        self.state = State::Found {
+                            handle,
+                            serial,
+                            global_serial,
+                        };
+                        return Ok(());
+                    }
+                    Some(_) => return self.read(props, handle, global_serial),
+                    // The handle went stale: look the name up again.
+                    None => {}
+                }
+            }
+            self.state = State::Unresolved;
+        }
+        if let State::Missing {
+            global_serial: seen,
+        } = self.state
+        {
+            if seen == global_serial {
+                return Ok(());
+            }
+        }
+        match props.find(&self.name)? {
+            Some(handle) => self.read(props, handle, global_serial),
+            None => {
+                self.value.clear();
+                self.state = State::Missing { global_serial };
+                Ok(())
+            }
+        }
+    }
+
+    fn read(
+        &mut self,
+        props: &SystemProperties,
+        handle: PropertyHandle,
+        global_serial: u32,
+    ) -> Result<()> {
+        let value = &mut self.value;
+        let serial = props.read_handle_with(&handle, |current, serial| {
+            value.clear();
+            value.push_str(current);
+            serial
+        })?;
+        self.state = State::Found {
+            handle,
+            serial,
+            global_serial,
+        };
+        Ok(())
+    }
+}
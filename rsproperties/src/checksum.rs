@@ -0,0 +1,313 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-record checksums for property areas kept on storage that can rot,
+//! see [`crate::SystemProperties::enable_checksums`].
+//!
+//! bionic's record layout has no spare bytes, so the CRCs live in a
+//! sidecar next to each area file, `<area>.crc`: a header (magic,
+//! capacity, slot count) followed by one slot per record — record offset,
+//! the serial the CRC was computed for, and a CRC-32 of the name and
+//! value. Records are bump-allocated, so appending slots in creation
+//! order keeps them sorted by offset for the readers' binary search.
+//!
+//! A slot vouches only for the serial stored with it. The writer
+//! invalidates the slot, stores the CRC and then publishes the serial
+//! (a seqlock of its own); a reader that finds a different serial — an
+//! update still on its way to the sidecar — treats the record as
+//! unchecked, never as corrupt.
+
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicU32, Ordering};
+
+use crate::errors::*;
+use crate::property_area::{MemoryMap, MmapObject};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"RSPC");
+/// Never a published property serial: those have the dirty bit clear.
+#[cfg(feature = "writer")]
+const INVALID_SERIAL: u32 = u32::MAX;
+
+#[repr(C, align(4))]
+struct Header {
+    magic: AtomicU32,
+    capacity: AtomicU32,
+    len: AtomicU32,
+}
+
+#[repr(C, align(4))]
+struct Slot {
+    offset: AtomicU32,
+    serial: AtomicU32,
+    crc: AtomicU32,
+}
+
+// SAFETY: `repr(C, align(4))` with AtomicU32 fields only; every bit
+// pattern is valid and every access goes through the atomics.
+unsafe impl MmapObject for Header {}
+unsafe impl MmapObject for Slot {}
+
+const _: () = {
+    assert!(mem::size_of::<Header>() == 12);
+    assert!(mem::size_of::<Slot>() == 12);
+};
+
+/// Outcome of checking one record against its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecordCheck {
+    /// No sidecar, or no slot for the record.
+    Unchecked,
+    /// The slot was computed for a different serial.
+    Stale,
+    Verified,
+    Mismatch,
+}
+
+/// CRC-32 (IEEE 802.3, reflected) of the concatenation of `parts`.
+pub(crate) fn crc32(parts: &[&[u8]]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    let crc = parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(!0u32, |crc, &b| {
+            TABLE[((crc ^ u32::from(b)) & 0xff) as usize] ^ (crc >> 8)
+        });
+    !crc
+}
+
+/// CRC of one record: the name, a NUL, the value.
+pub(crate) fn record_crc(name: &[u8], value: &[u8]) -> u32 {
+    crc32(&[name, &[0], value])
+}
+
+/// `<area>.crc`.
+pub(crate) fn sidecar_path(area: &Path) -> PathBuf {
+    let mut path = OsString::from(area.as_os_str());
+    path.push(".crc");
+    PathBuf::from(path)
+}
+
+#[derive(Debug)]
+pub(crate) struct ChecksumTable {
+    mmap: MemoryMap,
+    capacity: usize,
+}
+
+impl ChecksumTable {
+    /// Creates a fresh, empty sidecar at `path` with room for `capacity`
+    /// slots, replacing any previous one — like area files, sidecars are
+    /// rebuilt by each writer instance rather than reopened.
+    #[cfg(feature = "writer")]
+    pub(crate) fn create(path: &Path, capacity: usize) -> Result<Self> {
+        remove_stale(path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .custom_flags(rustix::fs::OFlags::NOFOLLOW.bits() as _)
+            .mode(0o444)
+            .open(path)
+            .context_with_location(format!("Failed to create checksum sidecar {path:?}"))?;
+        let size = mem::size_of::<Header>() + capacity * mem::size_of::<Slot>();
+        file.set_len(size as u64)
+            .context_with_location(format!("Failed to size checksum sidecar {path:?}"))?;
+        let table = Self {
+            mmap: MemoryMap::new(file, size, true)?,
+            capacity,
+        };
+        let header = table.header();
+        header.capacity.store(capacity as u32, Ordering::Relaxed);
+        header.len.store(0, Ordering::Relaxed);
+        header.magic.store(MAGIC, Ordering::Release);
+        Ok(table)
+    }
+
+    /// Maps the sidecar of an existing area, if there is one. A sidecar
+    /// that fails validation is logged and ignored — the area stays
+    /// readable, just unchecked.
+    pub(crate) fn open(path: &Path, copy_on_write: bool) -> Option<Self> {
+        match Self::try_open(path, copy_on_write) {
+            Ok(table) => table,
+            Err(e) => {
+                log::warn!("Ignoring checksum sidecar {path:?}: {e}");
+                None
+            }
+        }
+    }
+
+    fn try_open(path: &Path, copy_on_write: bool) -> Result<Option<Self>> {
+        let file = match OpenOptions::new()
+            .read(true)
+            .custom_flags(rustix::fs::OFlags::NOFOLLOW.bits() as _)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context_with_location(format!("Failed to open {path:?}")),
+        };
+        let metadata = file
+            .metadata()
+            .context_with_location(format!("Failed to stat {path:?}"))?;
+        crate::file_validation::validate_file_metadata(
+            &metadata,
+            path,
+            mem::size_of::<Header>() as u64,
+        )?;
+        let size = usize::try_from(metadata.len())
+            .map_err(|_| Error::FileValidation(format!("{path:?} is too large to map")))?;
+        let mmap = if copy_on_write {
+            MemoryMap::new_private(file, size)?
+        } else {
+            MemoryMap::new(file, size, false)?
+        };
+        let mut table = Self { mmap, capacity: 0 };
+        let header = table.header();
+        if header.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(Error::FileValidation(format!("bad magic in {path:?}")));
+        }
+        let capacity = header.capacity.load(Ordering::Relaxed) as usize;
+        let needed = capacity
+            .checked_mul(mem::size_of::<Slot>())
+            .and_then(|slots| slots.checked_add(mem::size_of::<Header>()));
+        if needed.map_or(true, |needed| needed > size) {
+            return Err(Error::FileValidation(format!(
+                "{path:?} is too small for its {capacity} slots"
+            )));
+        }
+        table.capacity = capacity;
+        Ok(Some(table))
+    }
+
+    fn header(&self) -> &Header {
+        self.mmap
+            .to_object::<Header>(0, 0)
+            .expect("the header is at offset 0 of a mapping at least its size")
+    }
+
+    fn slot(&self, index: usize) -> Result<&Slot> {
+        self.mmap
+            .to_object::<Slot>(index * mem::size_of::<Slot>(), mem::size_of::<Header>())
+    }
+
+    fn len(&self) -> usize {
+        (self.header().len.load(Ordering::Acquire) as usize).min(self.capacity)
+    }
+
+    /// Slot of the record at `pi_offset`, by binary search.
+    fn find(&self, pi_offset: u32) -> Result<Option<&Slot>> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let slot = self.slot(mid)?;
+            match slot.offset.load(Ordering::Relaxed).cmp(&pi_offset) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(Some(slot)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks `crc` against the slot of the record at `pi_offset`, whose
+    /// value was read at `serial`.
+    pub(crate) fn check(&self, pi_offset: u32, serial: u32, crc: u32) -> Result<RecordCheck> {
+        let Some(slot) = self.find(pi_offset)? else {
+            return Ok(RecordCheck::Unchecked);
+        };
+        if slot.serial.load(Ordering::Acquire) != serial {
+            return Ok(RecordCheck::Stale);
+        }
+        let stored = slot.crc.load(Ordering::Relaxed);
+        fence(Ordering::Acquire);
+        if slot.serial.load(Ordering::Relaxed) != serial {
+            return Ok(RecordCheck::Stale);
+        }
+        Ok(if stored == crc {
+            RecordCheck::Verified
+        } else {
+            RecordCheck::Mismatch
+        })
+    }
+
+    /// Stores the CRC of the record at `pi_offset` for `serial`, adding a
+    /// slot for a record not seen before. New records must come in
+    /// increasing offset order.
+    #[cfg(feature = "writer")]
+    pub(crate) fn record(&self, pi_offset: u32, serial: u32, crc: u32) -> Result<()> {
+        self.mmap.require_writable()?;
+        let slot = match self.find(pi_offset)? {
+            Some(slot) => slot,
+            None => {
+                let len = self.len();
+                if len == self.capacity {
+                    return Err(Error::AreaFull(format!(
+                        "checksum sidecar holds {len} records"
+                    )));
+                }
+                let slot = self.slot(len)?;
+                slot.serial.store(INVALID_SERIAL, Ordering::Relaxed);
+                slot.offset.store(pi_offset, Ordering::Relaxed);
+                self.header().len.store(len as u32 + 1, Ordering::Release);
+                slot
+            }
+        };
+        slot.serial.store(INVALID_SERIAL, Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.crc.store(crc, Ordering::Relaxed);
+        slot.serial.store(serial, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Removes the sidecar a previous writer instance left at `path`: its
+/// slots describe records of an area that no longer exists.
+pub(crate) fn remove_stale(path: &Path) {
+    match std::fs::remove_file(path) {
+        Ok(()) => log::debug!("Removed stale checksum sidecar: {path:?}"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Failed to remove stale checksum sidecar {path:?}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(&[b"123456789"]), 0xcbf4_3926);
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xcbf4_3926);
+        assert_eq!(crc32(&[]), 0);
+        assert_ne!(record_crc(b"a", b"bc"), record_crc(b"ab", b"c"));
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("/dev/__properties__/u:object_r:test_prop:s0")),
+            Path::new("/dev/__properties__/u:object_r:test_prop:s0.crc")
+        );
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A property store and its property service as one value, see
+//! [`PropertiesClient`].
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::errors::*;
+use crate::system_properties::{PropertyHandle, SystemProperties};
+use crate::{Backing, PropertyConfig, ReadPolicy, Timespec};
+
+/// A handle on one property store and the property service that writes
+/// it, configured by a [`PropertyConfig`] of its own — so one process can
+/// talk to several, e.g. a host test area and the real
+/// `/dev/__properties__`.
+///
+/// The free functions ([`crate::get`], [`crate::set`], ...) go through
+/// [`PropertiesClient::global`], the handle on the store [`crate::init`]
+/// configures; a client from [`PropertiesClient::new`] touches none of
+/// that process-wide state, and decides the wire protocol version its
+/// sets speak from its own store.
+///
+/// ```rust,no_run
+/// use rsproperties::{PropertiesClient, PropertyConfig};
+///
+/// let host = PropertiesClient::new(PropertyConfig::with_both_dirs(
+///     "/tmp/test_properties",
+///     "/tmp/test_sockets",
+/// ))?;
+/// let device = PropertiesClient::global();
+///
+/// let mode: String = host.get("my_app.mode")?;
+/// let sdk: i32 = device.get("ro.build.version.sdk")?;
+/// # let _ = (mode, sdk);
+/// # Ok::<(), rsproperties::Error>(())
+/// ```
+pub struct PropertiesClient {
+    store: Store,
+    /// `None`: [`crate::socket_dir`].
+    socket_dir: Option<PathBuf>,
+    /// `None`: [`crate::read_only_prefixes`].
+    read_only_prefixes: Option<Vec<String>>,
+}
+
+enum Store {
+    /// [`crate::try_system_properties`], opened on first use.
+    Global,
+    Owned {
+        props: Box<SystemProperties>,
+        /// The wire protocol version, probed on the first set.
+        #[cfg(feature = "service-protocol")]
+        protocol_version: std::sync::OnceLock<crate::system_property_set::ProtocolVersion>,
+    },
+}
+
+static GLOBAL: PropertiesClient = PropertiesClient {
+    store: Store::Global,
+    socket_dir: None,
+    read_only_prefixes: None,
+};
+
+impl PropertiesClient {
+    /// The handle on the process-wide store: the directories passed to
+    /// [`crate::init`], or the defaults. Nothing is opened until the
+    /// first read, as with the free functions.
+    pub fn global() -> &'static PropertiesClient {
+        &GLOBAL
+    }
+
+    /// Opens the store `config` describes, read-only.
+    ///
+    /// Unset options take their defaults, not the values given to
+    /// [`crate::init`]: `/dev/__properties__`, AOSP's [`crate::Layout`]
+    /// and [`Backing::Files`]. A client without a socket directory sets
+    /// through [`crate::socket_dir`]. `read_only_prefixes` names the
+    /// service's write-once prefixes, whose values may be long as `ro.`
+    /// ones are (see [`Self::read_only_prefixes`]); `require_declared`
+    /// only applies to a writer and is ignored. A [`Backing::Memfd`] store is fetched from
+    /// the property service now.
+    pub fn new(config: PropertyConfig) -> Result<Self> {
+        let layout = config.layout.unwrap_or_default();
+        layout.validate()?;
+        let mut client = Self {
+            store: Store::Global,
+            socket_dir: config.socket_dir,
+            read_only_prefixes: config.read_only_prefixes,
+        };
+        let props = match config.backing.unwrap_or_default() {
+            Backing::Files => {
+                let dir = config
+                    .properties_dir
+                    .unwrap_or_else(|| PathBuf::from(crate::PROP_DIRNAME));
+                SystemProperties::open_with_layout(&dir, &layout)?
+            }
+            Backing::Memfd => crate::open_memfd_store(client.socket_dir(), &layout)??,
+        };
+        client.store = Store::Owned {
+            props: Box::new(props),
+            #[cfg(feature = "service-protocol")]
+            protocol_version: std::sync::OnceLock::new(),
+        };
+        Ok(client)
+    }
+
+    /// The store this client reads.
+    pub fn system_properties(&self) -> Result<&SystemProperties> {
+        match &self.store {
+            Store::Global => crate::try_system_properties(),
+            Store::Owned { props, .. } => Ok(props),
+        }
+    }
+
+    /// Where this client finds the property service sockets.
+    pub fn socket_dir(&self) -> &Path {
+        match &self.socket_dir {
+            Some(dir) => dir,
+            None => crate::socket_dir(),
+        }
+    }
+
+    /// The write-once name prefixes of this client's store: the
+    /// [`PropertyConfig`]'s, or [`crate::read_only_prefixes`]. Sets of
+    /// names under them are not held to [`crate::PROP_VALUE_MAX`], see
+    /// [`crate::wire::value_max_with`].
+    pub fn read_only_prefixes(&self) -> &[String] {
+        match &self.read_only_prefixes {
+            Some(prefixes) => prefixes,
+            None => crate::read_only_prefixes(),
+        }
+    }
+
+    /// The wire protocol version this client's sets speak: the process's
+    /// for the global client, otherwise probed from this client's store.
+    #[cfg(feature = "service-protocol")]
+    fn protocol_version(&self) -> crate::system_property_set::ProtocolVersion {
+        match &self.store {
+            Store::Global => crate::system_property_set::protocol_version(),
+            Store::Owned {
+                props,
+                protocol_version,
+            } => crate::system_property_set::probe_protocol_version(protocol_version, Some(props)),
+        }
+    }
+
+    /// [`crate::get`] on this client's store.
+    pub fn get<T>(&self, name: &str) -> Result<T>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        // Route through `read_with` so the parse-and-discard path never
+        // allocates a `String` — the value bytes are handed to `FromStr` as
+        // `&str` borrowed from the seqlock buffer (short variant) or the mmap
+        // (long variant).
+        let empty_is_unset = crate::read_policy() == ReadPolicy::EmptyIsUnset;
+        self.system_properties()?.read_with(name, |value| {
+            if empty_is_unset && value.is_empty() {
+                return Err(Error::NotFound(name.to_owned()));
+            }
+            value.parse().map_err(|e| {
+                Error::Parse(format!(
+                    "Failed to parse '{value}' for property '{name}': {e}"
+                ))
+            })
+        })?
+    }
+
+    /// [`crate::get_optional`] on this client's store.
+    pub fn get_optional(&self, name: &str) -> Result<Option<String>> {
+        // A name that could never be set would otherwise read as "not set".
+        crate::wire::validate_property_name(name)?;
+        match self.system_properties()?.get_with_result(name) {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// [`crate::get_or_else`] on this client's store.
+    pub fn get_or_else<T, F>(&self, name: &str, default: F) -> T
+    where
+        T: std::str::FromStr,
+        F: FnOnce() -> T,
+    {
+        let Ok(props) = self.system_properties() else {
+            return default();
+        };
+        let empty_is_value = crate::read_policy() == ReadPolicy::EmptyIsValue;
+        // Two-stage closure: the inner `Result<T, ()>` carries the parsed
+        // value back out of `read_with` without ever allocating a `String`.
+        // `Err(())` signals "use the default"; the default itself is produced
+        // at the match below, so the `FnOnce` callback never needs to own it.
+        match props.read_with(name, |value| {
+            if value.is_empty() && !empty_is_value {
+                return Err(());
+            }
+            value.parse::<T>().map_err(|_| ())
+        }) {
+            Ok(Ok(v)) => v,
+            _ => default(),
+        }
+    }
+
+    /// [`crate::list`] of this client's store.
+    pub fn list(&self) -> Result<Vec<(String, String)>> {
+        let mut properties = Vec::new();
+        self.system_properties()?
+            .foreach(|name, value| properties.push((name.to_owned(), value.to_owned())))?;
+        properties.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Ok(properties)
+    }
+
+    /// [`crate::format_getprop`] of this client's store.
+    pub fn format_getprop(
+        &self,
+        writer: impl std::io::Write,
+        column: crate::GetpropColumn,
+    ) -> Result<()> {
+        self.system_properties()?.format_getprop(writer, column)
+    }
+
+    /// [`crate::set_and_confirm`] through this client's property service
+    /// and store.
+    #[cfg(feature = "service-protocol")]
+    pub fn set_and_confirm<T: std::fmt::Display + ?Sized>(
+        &self,
+        name: &str,
+        value: &T,
+        timeout: Duration,
+    ) -> Result<Duration> {
+        let started = std::time::Instant::now();
+        // `None` when `timeout` is too large to add, e.g. `Duration::MAX`:
+        // no deadline.
+        let deadline = started.checked_add(timeout);
+        let value = value.to_string();
+        #[cfg(debug_assertions)]
+        crate::prefix_registry::check_unclaimed_set(name);
+        crate::system_property_set::set(
+            self.socket_dir(),
+            self.protocol_version(),
+            self.read_only_prefixes(),
+            name,
+            &value,
+        )?;
+
+        let props = self.system_properties()?;
+        loop {
+            // Serial before value: a commit landing in between ends the wait
+            // below at once.
+            let serial = props.context_serial();
+            match props.get_with_result(name) {
+                Ok(current) if current == value => {
+                    let latency = started.elapsed();
+                    log::debug!("'{name}' observable after {latency:?}");
+                    return Ok(latency);
+                }
+                Ok(_) | Err(Error::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                    if remaining.is_zero() {
+                        log::error!("'{name}' not observable after {timeout:?}");
+                        return Err(Error::Io(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            format!("'{name}' not observable after {timeout:?}"),
+                        )));
+                    }
+                    Some(Timespec {
+                        tv_sec: remaining.as_secs() as _,
+                        tv_nsec: remaining.subsec_nanos() as _,
+                    })
+                }
+                None => None,
+            };
+            props.wait(None, Some(serial), remaining.as_ref());
+        }
+    }
+
+    /// [`crate::set_bytes`] through this client's property service.
+    #[cfg(feature = "service-protocol")]
+    pub fn set_bytes(&self, name: &str, value: &[u8]) -> Result<()> {
+        crate::bytes_value::check_bytes_type(self.system_properties()?, name)
+            .inspect_err(|e| log::error!("setprop reject: {e}"))?;
+        // The store's own cap: only names it keeps out of line may exceed it.
+        let value_max = crate::wire::value_max_with(name, self.read_only_prefixes());
+        if value_max.is_some() && value.len() > crate::PROP_BYTES_MAX {
+            let e = Error::InvalidArgument(format!(
+                "binary value too long for '{name}': {} bytes (max {})",
+                value.len(),
+                crate::PROP_BYTES_MAX
+            ));
+            log::error!("setprop reject: {e}");
+            return Err(e);
+        }
+        crate::system_property_set::set(
+            self.socket_dir(),
+            self.protocol_version(),
+            self.read_only_prefixes(),
+            name,
+            &crate::bytes_value::encode(value),
+        )
+    }
+
+    /// [`crate::get_bytes`] from this client's store.
+    pub fn get_bytes(&self, name: &str) -> Result<Vec<u8>> {
+        let props = self.system_properties()?;
+        crate::bytes_value::check_bytes_type(props, name)?;
+        props
+            .read_with(name, crate::bytes_value::decode)?
+            .map_err(|e| match e {
+                Error::Parse(msg) => Error::Parse(format!("bytes property '{name}': {msg}")),
+                e => e,
+            })
+    }
+
+    /// [`crate::set`] through this client's property service. The wire
+    /// protocol version follows this client's store, as [`crate::set`]'s
+    /// follows the global one.
+    #[cfg(feature = "service-protocol")]
+    pub fn set<T: std::fmt::Display + ?Sized>(&self, name: &str, value: &T) -> Result<()> {
+        #[cfg(debug_assertions)]
+        crate::prefix_registry::check_unclaimed_set(name);
+        crate::system_property_set::set(
+            self.socket_dir(),
+            self.protocol_version(),
+            self.read_only_prefixes(),
+            name,
+            &value.to_string(),
+        )
+    }
+
+    /// [`crate::set_many`] through this client's property service.
+    #[cfg(feature = "service-protocol")]
+    pub fn set_many<N: AsRef<str>, V: AsRef<str>>(
+        &self,
+        entries: &[(N, V)],
+    ) -> Result<Vec<Result<()>>> {
+        let entries: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value.as_ref()))
+            .collect();
+        #[cfg(debug_assertions)]
+        for (name, _) in &entries {
+            crate::prefix_registry::check_unclaimed_set(name);
+        }
+        crate::system_property_set::set_many(
+            self.socket_dir(),
+            self.protocol_version(),
+            self.read_only_prefixes(),
+            &entries,
+        )
+    }
+
+    /// [`crate::remove`] through this client's property service.
+    #[cfg(feature = "service-protocol")]
+    pub fn remove(&self, name: &str) -> Result<()> {
+        crate::system_property_set::remove(self.socket_dir(), name)
+    }
+
+    /// [`SystemProperties::wait`] on this client's store, with the
+    /// timeout as a [`Duration`]. `handle` must come from
+    /// [`Self::system_properties`] of this client.
+    pub fn wait(
+        &self,
+        handle: Option<&PropertyHandle>,
+        old_serial: Option<u32>,
+        timeout: Option<Duration>,
+    ) -> Result<Option<u32>> {
+        let timeout = timeout.map(|timeout| Timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        });
+        Ok(self
+            .system_properties()?
+            .wait(handle, old_serial, timeout.as_ref()))
+    }
+
+    /// [`SystemProperties::wait_for_value`] on this client's store.
+    pub fn wait_for_value(&self, name: &str, expected: &str, timeout: Duration) -> Result<bool> {
+        self.system_properties()?
+            .wait_for_value(name, expected, timeout)
+    }
+}
+
+impl std::fmt::Debug for PropertiesClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PropertiesClient")
+            .field("global", &matches!(self.store, Store::Global))
+            .field("socket_dir", &self.socket_dir)
+            .finish()
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Client-side coalescing of rapid sets of the same property.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::errors::*;
+
+struct Slot {
+    /// When the last value of this property went to the service.
+    sent_at: Instant,
+    /// The latest value held back since then.
+    pending: Option<String>,
+}
+
+/// Sends at most one value per property and `window` to the property
+/// service, for callers that update a property far more often than anyone
+/// needs to see it — progress percentages during a download, say — and
+/// would otherwise wake every waiter on each step.
+///
+/// The first set of a property goes out at once; sets arriving within
+/// `window` of it only replace a held-back value, which goes out with the
+/// first set after the window or on [`Self::flush`]. There is no timer
+/// thread: a value held back when the updates stop stays pending until
+/// `flush` (or drop, which flushes and logs failures), so call it when the
+/// burst is over.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use rsproperties::CoalescingSetter;
+///
+/// let mut setter = CoalescingSetter::new(Duration::from_millis(500));
+/// for percent in 0..=100 {
+///     setter.set("sys.ota.progress", &percent)?;
+/// }
+/// setter.flush()?; // "100" is sent here
+/// # Ok::<(), rsproperties::Error>(())
+/// ```
+pub struct CoalescingSetter {
+    window: Duration,
+    slots: HashMap<String, Slot>,
+}
+
+impl CoalescingSetter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            slots: HashMap::new(),
+        }
+    }
+
+    /// [`crate::set`], unless `name` was sent less than the window ago: then
+    /// `value` is held back, replacing any value held back before it.
+    pub fn set<T: std::fmt::Display + ?Sized>(&mut self, name: &str, value: &T) -> Result<()> {
+        let now = Instant::now();
+        if let Some(slot) = self.slots.get_mut(name) {
+            if now.duration_since(slot.sent_at) < self.window {
+                slot.pending = Some(value.to_string());
+                return Ok(());
+            }
+        }
+        crate::set(name, value)?;
+        self.slots.insert(
+            name.to_owned(),
+            Slot {
+                sent_at: now,
+                pending: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Number of properties with a value held back.
+    pub fn pending(&self) -> usize {
+        self.slots
+            .values()
+            .filter(|slot| slot.pending.is_some())
+            .count()
+    }
+
+    /// Sends every held-back value and forgets the send times, so the
+    /// next set of any property goes out at once. A value the service
+    /// refuses stays pending; the first such error is returned after all
+    /// values were tried.
+    pub fn flush(&mut self) -> Result<()> {
+        let mut first_err = None;
+        self.slots.retain(|name, slot| {
+            let Some(value) = &slot.pending else {
+                return false;
+            };
+            match crate::set(name, value) {
+                Ok(()) => false,
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                    true
+                }
+            }
+        });
+        first_err.map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for CoalescingSetter {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!(
+                "Failed to flush {} coalesced properties: {e}",
+                self.pending()
+            );
+        }
+    }
+}
+
+impl std::fmt::Debug for CoalescingSetter {
+    // Counts only: held-back values may be sensitive.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoalescingSetter")
+            .field("window", &self.window)
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
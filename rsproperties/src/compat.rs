@@ -0,0 +1,111 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Drop-in replacement for the `android_system_properties` crate, see
+//! [`AndroidSystemProperties`].
+
+use std::ffi::CStr;
+use std::time::{Duration, Instant};
+
+use crate::errors::*;
+
+/// System properties with the API of the `android_system_properties`
+/// crate.
+///
+/// `new()`, `get(name)` and `get_from_cstr(name)` behave like
+/// `android_system_properties::AndroidSystemProperties`, but read through
+/// this crate, so they also work on Linux; [`Self::set`] and [`Self::wait`]
+/// come on top. Switching a crate over is a change of dependency, after
+/// which its `use android_system_properties::AndroidSystemProperties;`
+/// resolves to this type:
+///
+/// ```toml
+/// [dependencies]
+/// android_system_properties = { package = "rsproperties", version = "0.7" }
+/// ```
+///
+/// Like the original, lookups never fail loudly: a property that is not
+/// set, an invalid name and a property store that cannot be opened all
+/// read as `None`. The store is the global one
+/// ([`crate::system_properties()`]), so on Linux [`crate::init`] chooses the
+/// directory as usual.
+///
+/// ```rust,no_run
+/// use rsproperties::AndroidSystemProperties;
+///
+/// let properties = AndroidSystemProperties::new();
+/// if let Some(value) = properties.get("persist.sys.timezone") {
+///     println!("{}", value);
+/// }
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AndroidSystemProperties;
+
+impl AndroidSystemProperties {
+    /// Creates a handle. Cheap: the store is opened on first use.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Value of the property `name`; `None` if it is not set or cannot be
+    /// read.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let properties = crate::try_system_properties().ok()?;
+        match properties.get_with_result(name) {
+            Ok(value) => Some(value),
+            Err(Error::NotFound(_)) => None,
+            Err(e) => {
+                log::debug!("Failed to read {name}: {e}");
+                None
+            }
+        }
+    }
+
+    /// [`Self::get`] with a C string name.
+    pub fn get_from_cstr(&self, cname: &CStr) -> Option<String> {
+        self.get(cname.to_str().ok()?)
+    }
+
+    /// Sets the property `name` through the property service, like
+    /// [`crate::set`]. Not in the original crate.
+    #[cfg(feature = "service-protocol")]
+    pub fn set(&self, name: &str, value: &str) -> Result<()> {
+        crate::set(name, value)
+    }
+
+    /// Waits until the property `name` changes — or is created, if it is
+    /// not set yet — and returns its new value; `None` when `timeout`
+    /// (unbounded if `None`, or too large to add to the current time)
+    /// elapses first or the wait fails. Only changes after the call count.
+    /// Not in the original crate.
+    pub fn wait(&self, name: &str, timeout: Option<Duration>) -> Option<String> {
+        let properties = crate::try_system_properties().ok()?;
+        let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+        let remaining = || -> Option<Option<crate::Timespec>> {
+            match deadline {
+                None => Some(None),
+                Some(deadline) => {
+                    let left = deadline.checked_duration_since(Instant::now())?;
+                    Some(Some(crate::Timespec {
+                        tv_sec: left.as_secs() as _,
+                        tv_nsec: left.subsec_nanos() as _,
+                    }))
+                }
+            }
+        };
+
+        if let Some(index) = properties.find(name).ok()? {
+            let serial = properties.serial(&index)?;
+            properties.wait(Some(&index), Some(serial), remaining()?.as_ref())?;
+            return self.get(name);
+        }
+        // Not set yet: every global change may be its creation.
+        let mut global = properties.context_serial();
+        loop {
+            global = properties.wait(None, Some(global), remaining()?.as_ref())?;
+            if properties.find(name).ok()?.is_some() {
+                return self.get(name);
+            }
+        }
+    }
+}
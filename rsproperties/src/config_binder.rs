@@ -0,0 +1,221 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Property-backed daemon configuration.
+//!
+//! The glue most daemons write by hand — read N properties, parse and
+//! validate each, assemble a config struct, swap it in, log what changed —
+//! as one reusable type.
+
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::errors::*;
+use crate::lock_order;
+use crate::system_properties::SystemProperties;
+
+/// Parses a raw value and stores it into the config. Parsing and
+/// application are fused so the binder can stay generic over each field's
+/// value type.
+type ApplyFn<T> = Box<dyn Fn(&str, &mut T) -> Result<()> + Send + Sync>;
+
+struct Binding<T> {
+    name: String,
+    apply: ApplyFn<T>,
+}
+
+struct State<T> {
+    snapshot: Arc<T>,
+    /// Raw value each binding was last built from (`None`: unset), in
+    /// binding order — what "changed" is decided on.
+    raw: Vec<Option<String>>,
+}
+
+/// Builds a config struct `T` from properties and keeps the current
+/// snapshot behind an `Arc` that [`Self::reload`] swaps atomically.
+///
+/// Every reload starts from a clone of the `base` config and applies the
+/// bindings whose property is set, so a property that is cleared (or set
+/// to the empty string, Android's "unset") reverts its field to the base
+/// value.
+///
+/// ```rust,no_run
+/// use rsproperties::ConfigBinder;
+///
+/// #[derive(Clone, Default)]
+/// struct Config {
+///     workers: u32,
+///     endpoint: String,
+/// }
+///
+/// let binder = ConfigBinder::new(Config { workers: 4, ..Default::default() })
+///     .bind_parsed("vendor.mydaemon.workers", |c: &mut Config, v: u32| c.workers = v)
+///     .bind("vendor.mydaemon.endpoint", |c: &mut Config, v: &str| {
+///         if v.starts_with('/') {
+///             c.endpoint = v.to_owned();
+///             Ok(())
+///         } else {
+///             Err(rsproperties::Error::InvalidArgument("endpoint must be absolute".into()))
+///         }
+///     });
+/// binder.reload().unwrap();
+/// loop {
+///     rsproperties::system_properties().wait_any();
+///     match binder.reload() {
+///         Ok(update) if !update.changed.is_empty() => { /* restart workers... */ }
+///         Ok(_) => {}
+///         Err(e) => eprintln!("keeping previous config: {e}"),
+///     }
+/// }
+/// ```
+pub struct ConfigBinder<T> {
+    base: T,
+    bindings: Vec<Binding<T>>,
+    state: RwLock<State<T>>,
+}
+
+/// Result of a successful [`ConfigBinder::reload`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ConfigUpdate<T> {
+    /// The snapshot now current (unchanged `Arc` if nothing changed).
+    pub snapshot: Arc<T>,
+    /// Property names whose value differs from the previous snapshot's, in
+    /// binding order. Empty when nothing changed.
+    pub changed: Vec<String>,
+}
+
+impl<T: Clone> ConfigBinder<T> {
+    /// A binder with no bindings whose snapshot is `base` until the first
+    /// [`Self::reload`].
+    pub fn new(base: T) -> Self {
+        let snapshot = Arc::new(base.clone());
+        Self {
+            base,
+            bindings: Vec::new(),
+            state: RwLock::new(State {
+                snapshot,
+                raw: Vec::new(),
+            }),
+        }
+    }
+
+    /// Binds property `name` to a field. `apply` validates the value and
+    /// stores it; an error from it fails the whole reload.
+    pub fn bind<F>(mut self, name: &str, apply: F) -> Self
+    where
+        F: Fn(&mut T, &str) -> Result<()> + Send + Sync + 'static,
+    {
+        self.bindings.push(Binding {
+            name: name.to_owned(),
+            apply: Box::new(move |value, config| apply(config, value)),
+        });
+        self.state_mut().raw.push(None);
+        self
+    }
+
+    /// [`Self::bind`] for a `FromStr` value; a value that does not parse is
+    /// an [`Error::Parse`], as in [`crate::get`].
+    pub fn bind_parsed<V, F>(self, name: &str, apply: F) -> Self
+    where
+        V: FromStr,
+        V::Err: std::fmt::Display,
+        F: Fn(&mut T, V) + Send + Sync + 'static,
+    {
+        let owned_name = name.to_owned();
+        self.bind(name, move |config, value| {
+            let parsed = value.parse().map_err(|e| {
+                Error::Parse(format!(
+                    "Failed to parse '{value}' for property '{owned_name}': {e}"
+                ))
+            })?;
+            apply(config, parsed);
+            Ok(())
+        })
+    }
+
+    /// The current snapshot. Cheap (`Arc` clone); the snapshot never
+    /// changes under the caller.
+    pub fn snapshot(&self) -> Arc<T> {
+        Arc::clone(&lock_order::read("ConfigBinder::state", &self.state).snapshot)
+    }
+
+    /// [`Self::reload_from`] the global instance
+    /// ([`crate::system_properties()`]).
+    pub fn reload(&self) -> Result<ConfigUpdate<T>> {
+        self.reload_from(crate::try_system_properties()?)
+    }
+
+    /// Re-reads every bound property from `props` and, if any value
+    /// differs from the current snapshot's, publishes a new snapshot.
+    ///
+    /// All or nothing: if any binding fails to read or validate, the
+    /// current snapshot stays in place, the error is returned as is and the
+    /// failing property is logged.
+    /// The values are read between two equal global serials (see
+    /// [`SystemProperties::freeze`]), so a snapshot never mixes values
+    /// from before and after a concurrent update.
+    pub fn reload_from(&self, props: &SystemProperties) -> Result<ConfigUpdate<T>> {
+        let raw = self.read_consistent(props)?;
+
+        // Serialized with other reloads, so the comparison and the swap
+        // see the same previous state.
+        let mut state = lock_order::write("ConfigBinder::state", &self.state);
+        let changed: Vec<String> = self
+            .bindings
+            .iter()
+            .zip(raw.iter().zip(&state.raw))
+            .filter(|(_, (new, old))| new != old)
+            .map(|(binding, _)| binding.name.clone())
+            .collect();
+        if changed.is_empty() {
+            return Ok(ConfigUpdate {
+                snapshot: Arc::clone(&state.snapshot),
+                changed,
+            });
+        }
+
+        let mut config = self.base.clone();
+        for (binding, value) in self.bindings.iter().zip(&raw) {
+            if let Some(value) = value {
+                (binding.apply)(value, &mut config).inspect_err(|e| {
+                    log::warn!("Config binding for {} rejected: {e}", binding.name)
+                })?;
+            }
+        }
+        log::info!("Config reloaded; changed: {changed:?}");
+        state.snapshot = Arc::new(config);
+        state.raw = raw;
+        Ok(ConfigUpdate {
+            snapshot: Arc::clone(&state.snapshot),
+            changed,
+        })
+    }
+
+    fn read_consistent(&self, props: &SystemProperties) -> Result<Vec<Option<String>>> {
+        const READ_ATTEMPTS: usize = 8;
+        for _ in 0..READ_ATTEMPTS {
+            let before = props.context_serial();
+            let raw = self
+                .bindings
+                .iter()
+                .map(|binding| match props.get_with_result(&binding.name) {
+                    Ok(value) if value.is_empty() => Ok(None),
+                    Ok(value) => Ok(Some(value)),
+                    Err(Error::NotFound(_)) => Ok(None),
+                    Err(e) => Err(e),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if props.context_serial() == before {
+                return Ok(raw);
+            }
+        }
+        Err(Error::LimitExceeded(format!(
+            "properties changed during {READ_ATTEMPTS} consecutive config reads"
+        )))
+    }
+
+    fn state_mut(&mut self) -> &mut State<T> {
+        self.state.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
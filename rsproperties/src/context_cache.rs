@@ -0,0 +1,254 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Memoized `property_info` lookups, see [`set_context_cache_capacity`].
+//!
+//! Every read resolves the property name to a context (which area file to
+//! search) and a type by walking the `property_info` trie. Names come in
+//! families — `ro.product.*`, `persist.sys.*` — that usually resolve
+//! alike, so each store keeps a small LRU keyed by a name's first two
+//! segments (`"persist.sys."`; a name with fewer segments is its own key)
+//! mapping to the resolved `(context_index, type_index)`.
+//!
+//! A key is only memoized when the walk proved that every name under it
+//! resolves the same way: if the trie branches below the second segment
+//! (`ro.product.vendor.` declared next to `ro.product.`, say), names in
+//! that family keep walking the trie and count as
+//! [`ContextCacheStats::uncacheable`].
+//!
+//! The LRU sits behind a `Mutex` that is only ever `try_lock`ed: a reader
+//! that finds it busy walks the trie instead of waiting, so concurrent
+//! readers never block each other, and a child forked while another thread
+//! held the lock just runs without the cache.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::property_info_parser::PropertyInfoArea;
+
+/// Keys kept per store unless [`set_context_cache_capacity`] says
+/// otherwise.
+pub const DEFAULT_CONTEXT_CACHE_CAPACITY: usize = 64;
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CONTEXT_CACHE_CAPACITY);
+
+/// Sets how many name families each store's context cache keeps, for
+/// this process; `0` turns the cache off. Applies to open stores too: a
+/// smaller capacity evicts on their next insert.
+pub fn set_context_cache_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+}
+
+/// The current context cache capacity.
+pub fn context_cache_capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Counters of one store's context cache; see
+/// [`crate::SystemProperties::context_cache_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ContextCacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that walked the trie and memoized the result.
+    pub misses: u64,
+    /// Lookups that walked the trie because the trie branches below their
+    /// key.
+    pub uncacheable: u64,
+    /// Lookups that walked the trie because another thread held the
+    /// cache.
+    pub contended: u64,
+    /// Times the cache was emptied because `property_info` changed under
+    /// the mapping.
+    pub invalidations: u64,
+    /// Keys currently memoized.
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    context_index: u32,
+    type_index: u32,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<Box<str>, Entry>,
+    tick: u64,
+    /// Header fields of the trie the entries were resolved against.
+    fingerprint: [u32; 4],
+}
+
+/// Counters live outside the lock so [`ContextCache::stats`] never waits
+/// for it; apart from `contended` they are only updated with the lock held.
+#[derive(Debug, Default)]
+pub(crate) struct ContextCache {
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    uncacheable: AtomicU64,
+    contended: AtomicU64,
+    invalidations: AtomicU64,
+    len: AtomicUsize,
+}
+
+/// `name` up to and including its second `.`, or all of it.
+fn family_key(name: &str) -> &str {
+    let mut dots = name.match_indices('.').map(|(index, _)| index);
+    match (dots.next(), dots.next()) {
+        (Some(_), Some(second)) => &name[..=second],
+        _ => name,
+    }
+}
+
+/// Identifies the trie contents cheaply: a `property_info` rewritten in
+/// place (same inode, so the mapping follows it) with different contents
+/// changes its size or table offsets.
+fn fingerprint(area: &PropertyInfoArea<'_>) -> [u32; 4] {
+    let header = area.header();
+    [
+        header.size,
+        header.contexts_offset,
+        header.types_offset,
+        header.root_offset,
+    ]
+}
+
+impl ContextCache {
+    /// `area.get_property_info_indexes(name)`, memoized per name family.
+    pub(crate) fn indexes(&self, area: &PropertyInfoArea<'_>, name: &str) -> (u32, u32) {
+        let capacity = context_cache_capacity();
+        if capacity == 0 {
+            return area.get_property_info_indexes(name);
+        }
+        let Ok(mut lru) = self.lru.try_lock() else {
+            self.contended.fetch_add(1, Ordering::Relaxed);
+            return area.get_property_info_indexes(name);
+        };
+
+        let fingerprint = fingerprint(area);
+        if lru.fingerprint != fingerprint {
+            if !lru.entries.is_empty() {
+                log::debug!("property_info changed; clearing the context cache");
+                lru.entries.clear();
+                self.len.store(0, Ordering::Relaxed);
+                self.invalidations.fetch_add(1, Ordering::Relaxed);
+            }
+            lru.fingerprint = fingerprint;
+        }
+
+        let key = family_key(name);
+        lru.tick += 1;
+        let tick = lru.tick;
+        if let Some(entry) = lru.entries.get_mut(key) {
+            entry.last_used = tick;
+            let indexes = (entry.context_index, entry.type_index);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return indexes;
+        }
+
+        let (context_index, type_index, determined) =
+            area.get_property_info_indexes_keyed(name, key.len());
+        // A key without a second dot is the whole name, so no other name
+        // shares it.
+        if !determined && key.len() != name.len() {
+            self.uncacheable.fetch_add(1, Ordering::Relaxed);
+            return (context_index, type_index);
+        }
+        while lru.entries.len() >= capacity {
+            let Some(oldest) = lru
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+        lru.entries.insert(
+            key.into(),
+            Entry {
+                context_index,
+                type_index,
+                last_used: tick,
+            },
+        );
+        self.len.store(lru.entries.len(), Ordering::Relaxed);
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        (context_index, type_index)
+    }
+
+    pub(crate) fn stats(&self) -> ContextCacheStats {
+        ContextCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            uncacheable: self.uncacheable.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            len: self.len.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "info-builder")]
+    fn build_words(prefixes: &[(&str, &str)]) -> Vec<u32> {
+        use zerocopy::IntoBytes;
+
+        let entries: Vec<_> = prefixes
+            .iter()
+            .map(|(name, context)| {
+                crate::PropertyInfoEntry::new((*name).into(), (*context).into(), "string", false)
+                    .unwrap()
+            })
+            .collect();
+        let data = crate::build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap();
+        let mut words = vec![0u32; data.len().div_ceil(4)];
+        words.as_mut_bytes()[..data.len()].copy_from_slice(&data);
+        words
+    }
+
+    #[cfg(feature = "info-builder")]
+    #[test]
+    fn test_cache_invalidates_on_new_trie() {
+        use zerocopy::IntoBytes;
+
+        let cache = ContextCache::default();
+        let old = build_words(&[("persist.sys.", "u:object_r:system_prop:s0")]);
+        let old = PropertyInfoArea::new(old.as_bytes());
+        let expected = old.get_property_info_indexes("persist.sys.a");
+        assert_eq!(cache.indexes(&old, "persist.sys.a"), expected);
+        assert_eq!(cache.indexes(&old, "persist.sys.b"), expected);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (1, 1, 1));
+
+        // Same name family, different trie: resolved afresh.
+        let new = build_words(&[
+            ("persist.", "u:object_r:persist_prop:s0"),
+            ("persist.sys.", "u:object_r:sys_persist_prop:s0"),
+        ]);
+        let new = PropertyInfoArea::new(new.as_bytes());
+        assert_eq!(
+            cache.indexes(&new, "persist.sys.a"),
+            new.get_property_info_indexes("persist.sys.a")
+        );
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.invalidations, stats.len), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_family_key() {
+        assert_eq!(family_key("persist.sys.timezone"), "persist.sys.");
+        assert_eq!(family_key("ro.product.vendor.model"), "ro.product.");
+        assert_eq!(family_key("ro.product"), "ro.product");
+        assert_eq!(family_key("ro.product."), "ro.product.");
+        assert_eq!(family_key("hostname"), "hostname");
+    }
+}
@@ -1,16 +1,60 @@
 // Copyright 2024 Jeff Kim <hiking90@gmail.com>
 // SPDX-License-Identifier: Apache-2.0
 
-use std::ffi::CString;
-use std::path::PathBuf;
-#[cfg(feature = "builder")]
-use std::sync::RwLockWriteGuard;
-use std::sync::{RwLock, RwLockReadGuard};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
 
-use log::error;
+use arc_swap::{ArcSwap, Guard};
+use log::{debug, error, info};
 
 use crate::errors::*;
+use crate::lock_order;
 use crate::property_area::PropertyAreaMap;
+use crate::SelinuxContext;
+
+/// How often the read path of a read-only, file-backed node checks that
+/// the file at its path is still the one it mapped, in milliseconds. A
+/// miss checks at once; this bounds how long a name that exists in both
+/// the old and the new file keeps reading the old one.
+const RECHECK_INTERVAL_MS: u64 = 1000;
+
+/// Monotonic milliseconds for [`RECHECK_INTERVAL_MS`]. The coarse clock
+/// where there is one: it is read on every lookup, and tick precision is
+/// plenty for a one-second interval.
+fn now_millis() -> u64 {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let clock = rustix::time::ClockId::MonotonicCoarse;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let clock = rustix::time::ClockId::Monotonic;
+    let ts = rustix::time::clock_gettime(clock);
+    (ts.tv_sec as u64) * 1000 + (ts.tv_nsec as u64) / 1_000_000
+}
+
+/// A node's area as a reader sees it. A read-only node's mapping can be
+/// replaced (see `ContextNode::revalidate`), so readers hold a reference
+/// on it for as long as they use it rather than borrowing the node; the
+/// replaced mapping is unmapped once the last of them lets go. Keep it for
+/// the length of one operation — a reader that holds it across many
+/// lookups also holds a replaced file mapped that long.
+pub(crate) enum AreaRef<'a> {
+    /// The area of a writable node, which is never replaced.
+    Fixed(&'a PropertyAreaMap),
+    /// The current mapping of a read-only node.
+    Shared(Guard<Arc<PropertyAreaMap>>),
+}
+
+impl Deref for AreaRef<'_> {
+    type Target = PropertyAreaMap;
+
+    fn deref(&self) -> &PropertyAreaMap {
+        match self {
+            Self::Fixed(map) => map,
+            Self::Shared(map) => map,
+        }
+    }
+}
 
 pub(crate) struct ContextNode {
     access_rw: bool,
@@ -20,27 +64,123 @@ pub(crate) struct ContextNode {
     /// `context_node::open` which labels each per-context file. `Some`
     /// only for writable nodes — read-only instances never label files,
     /// so they skip the allocation.
-    context: Option<CString>,
+    context: Option<SelinuxContext>,
     filename: PathBuf,
-    /// Lazy-initialized property area. Once a writer puts `Some`, no code
-    /// path ever resets it to `None` — this is the invariant that lets the
-    /// `*Guard` types below skip the `expect()` runtime panic. The
-    /// invariant is enforced by keeping the field private and only
-    /// exposing it through this module's API.
-    property_area: RwLock<Option<PropertyAreaMap>>,
+    /// Area of a writable node, published once by `open()` (or by the
+    /// read path, which `open()` then refuses, see `mapped_read_only`) and
+    /// never replaced. A `OnceLock` gives readers a lock-free `Acquire`
+    /// load on every access instead of an `RwLock` read-lock round trip —
+    /// under multi-threaded `get()` load the shared lock word was the
+    /// contention point, not the mmap. Mutation goes through `&mut self`
+    /// (`property_area_mut`), so exclusivity against same-instance readers
+    /// is enforced by the borrow checker rather than a runtime lock;
+    /// cross-process readers are covered by the seqlock as before.
+    property_area: OnceLock<PropertyAreaMap>,
+    /// Area of a read-only node: mapped on first access, and swapped for a
+    /// mapping of the new file when the file is replaced. `ArcSwap` loads
+    /// are lock-free too, and do not bump a shared reference count.
+    mapped: OnceLock<ArcSwap<PropertyAreaMap>>,
+    /// Mappings `revalidate` replaced, to count the ones readers still
+    /// hold (see `retired_mappings`).
+    retired: Mutex<Vec<Weak<PropertyAreaMap>>>,
+    /// Mappings of the area so far, the current one included.
+    generations: AtomicUsize,
+    /// When the read path next checks a read-only node's file, in
+    /// [`now_millis`] time.
+    next_check: AtomicU64,
+    /// The memfd behind the area of a memfd-backed store (see
+    /// [`crate::Backing::Memfd`]), mapped at construction; `filename` is
+    /// then only a name, and there is no file to revalidate.
+    memfd: Option<std::fs::File>,
 }
 
 impl ContextNode {
-    pub(crate) fn new(access_rw: bool, context: Option<CString>, filename: PathBuf) -> Self {
+    pub(crate) fn new(access_rw: bool, context: Option<SelinuxContext>, filename: PathBuf) -> Self {
         Self {
             access_rw,
             context,
             filename,
-            property_area: RwLock::new(None),
+            property_area: OnceLock::new(),
+            mapped: OnceLock::new(),
+            retired: Mutex::new(Vec::new()),
+            generations: AtomicUsize::new(0),
+            next_check: AtomicU64::new(0),
+            memfd: None,
+        }
+    }
+
+    /// A writable node whose area is a new memfd named `filename`.
+    #[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn new_memfd(filename: PathBuf, area_size: usize) -> Result<Self> {
+        let (map, memfd) = PropertyAreaMap::new_rw_memfd(&filename, area_size)?;
+        let mut node = Self::new(true, None, filename);
+        node.publish(map);
+        node.memfd = Some(memfd);
+        Ok(node)
+    }
+
+    /// A read-only node mapping the area memfd `memfd` received from the
+    /// writer.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn from_memfd(filename: PathBuf, memfd: std::fs::File) -> Result<Self> {
+        let shared = memfd
+            .try_clone()
+            .context_with_location(format!("Failed to duplicate memfd {filename:?}"))?;
+        let map = PropertyAreaMap::new_ro_memfd(memfd, &filename)?;
+        let mut node = Self::new(false, None, filename);
+        node.publish(map);
+        node.memfd = Some(shared);
+        Ok(node)
+    }
+
+    /// A read-only node over a copy of the area in `bytes` (see
+    /// `SystemProperties::from_buffers`).
+    pub(crate) fn from_buffer(filename: PathBuf, bytes: &[u8]) -> Result<Self> {
+        let map = PropertyAreaMap::from_bytes(bytes, &filename)?;
+        let node = Self::new(false, None, filename);
+        node.publish(map);
+        Ok(node)
+    }
+
+    /// Whether the area lives in the file at [`Self::filename`] rather than
+    /// in a memfd or a buffer copy.
+    pub(crate) fn is_file_backed(&self) -> bool {
+        self.memfd.is_none() && self.current().map_or(true, |area| area.file_id().is_some())
+    }
+
+    /// The memfd behind this node's area, if the store is memfd-backed.
+    pub(crate) fn memfd(&self) -> Option<&std::fs::File> {
+        self.memfd.as_ref()
+    }
+
+    /// A node whose area is a copy-on-write mapping of `filename` (see
+    /// `PropertyAreaMap::new_cow`). Writable, so `revalidate` leaves it
+    /// alone and `open()` is a no-op; a context with no file yet gets an
+    /// unmapped node, which rejects writes.
+    #[cfg(feature = "writer")]
+    pub(crate) fn new_cow(filename: PathBuf) -> Result<Self> {
+        let node = Self::new(true, None, filename);
+        match node.filename.try_exists() {
+            Ok(true) => {
+                let map = PropertyAreaMap::new_cow(&node.filename)?;
+                node.publish(map);
+            }
+            Ok(false) => {}
+            Err(e) => {
+                return Err(e).context_with_location(format!("Failed to stat {:?}", node.filename))
+            }
         }
+        Ok(node)
     }
 
-    pub(crate) fn open(&self) -> Result<()> {
+    /// Path of the per-context area file this node maps (or would map).
+    pub(crate) fn filename(&self) -> &Path {
+        &self.filename
+    }
+
+    /// Creates the area file, `area_size` bytes large, and maps it
+    /// read-write.
+    pub(crate) fn open(&self, area_size: usize) -> Result<()> {
         if !self.access_rw {
             error!(
                 "Attempted to open context node without write access: {:?}",
@@ -53,160 +193,216 @@ impl ContextNode {
             )));
         }
 
-        let mut prop_area = self.property_area.write().map_err(lock_err("write"))?;
-        if prop_area.as_ref().is_some_and(|pa| pa.is_writable()) {
-            // Already opened read-write by a previous call.
-            return Ok(());
-        }
-        if prop_area.is_some() {
-            // A map exists but is read-only: the *read* path (`property_area`)
-            // lazily creates RO maps regardless of `access_rw`, and if it ran
-            // before this open() the slot holds one. Returning Ok here would
-            // report "opened" while every subsequent write fails — surface
-            // the ordering violation instead.
-            error!(
-                "open() called after the read path mapped {:?} read-only",
-                self.filename
-            );
-            // An initialization-order conflict, not an OS permission
-            // failure — `PermissionDenied` here sent users chasing file
-            // modes.
-            return Err(Error::AlreadyInitialized(format!(
-                "property area already mapped read-only: {:?}",
-                self.filename
-            )));
+        if let Some(map) = self.property_area.get() {
+            if map.is_writable() {
+                // Already opened read-write by a previous call.
+                return Ok(());
+            }
+            // A map exists but is read-only: the *read* path
+            // (`property_area`) lazily creates RO maps regardless of
+            // `access_rw`, and if it ran before this open() the slot holds
+            // one. Returning Ok here would report "opened" while every
+            // subsequent write fails — surface the ordering violation
+            // instead.
+            return Err(self.mapped_read_only());
         }
 
-        *prop_area = Some(PropertyAreaMap::new_rw(
-            self.filename.as_path(),
-            self.context.as_deref(),
-        )?);
-
+        // The read path can publish a RO map between the check above and
+        // this `set` — the slot is write-once, so the loser reports the
+        // same ordering violation and its freshly-created map is dropped.
+        self.property_area
+            .set(PropertyAreaMap::new_rw(
+                self.filename.as_path(),
+                self.context.as_ref(),
+                area_size,
+            )?)
+            .map_err(|_| self.mapped_read_only())?;
+        self.generations.store(1, Ordering::Relaxed);
         Ok(())
     }
 
-    pub(crate) fn property_area(&self) -> Result<PropertyAreaGuard<'_>> {
-        // The read path recovers from lock poison instead of failing: a
-        // writer panicking under this RwLock cannot leave the protected
-        // `Option` half-updated (`Some` is assigned whole and never reverts
-        // to `None`), and mmap *content* consistency is the seqlock's job —
-        // cross-process readers don't take this lock at all. Propagating
-        // poison here would trade zero safety for permanently failing every
-        // in-process read on this node. The builder-side paths (`open`,
-        // `property_area_mut`) stay strict.
-        use std::sync::PoisonError;
-        // Fast path: already initialized.
-        {
-            let guard = self
-                .property_area
-                .read()
-                .unwrap_or_else(PoisonError::into_inner);
-            if guard.is_some() {
-                return Ok(PropertyAreaGuard::from_initialized(guard));
+    fn mapped_read_only(&self) -> Error {
+        error!(
+            "open() called after the read path mapped {:?} read-only",
+            self.filename
+        );
+        // An initialization-order conflict, not an OS permission
+        // failure — `PermissionDenied` here sent users chasing file
+        // modes.
+        Error::AlreadyInitialized(format!(
+            "property area already mapped read-only: {:?}",
+            self.filename
+        ))
+    }
+
+    pub(crate) fn property_area(&self) -> Result<AreaRef<'_>> {
+        if self.recheck_due() {
+            // A failed check keeps the current mapping; a lookup miss
+            // reports the error, and the next check runs an interval later.
+            if let Err(e) = self.revalidate() {
+                debug!("Periodic check of {:?} failed: {e}", self.filename);
             }
         }
-        // Slow path: initialize under the write lock if still empty.
-        {
-            let mut guard = self
-                .property_area
-                .write()
-                .unwrap_or_else(PoisonError::into_inner);
-            if guard.is_none() {
-                *guard = Some(PropertyAreaMap::new_ro(self.filename.as_path())?);
-            }
+        if let Some(area) = self.current() {
+            return Ok(area);
         }
-        // Re-acquire read lock for the typed guard.
-        let guard = self
-            .property_area
-            .read()
-            .unwrap_or_else(PoisonError::into_inner);
-        Ok(PropertyAreaGuard::from_initialized(guard))
+        // Map outside the slot so a failed open leaves it unset for the
+        // next caller to retry. Two threads racing here both map the file;
+        // the loser's mapping is dropped (munmap) and both return the
+        // winner's — the same file, so the results agree.
+        let map = PropertyAreaMap::new_ro(self.filename.as_path())?;
+        Ok(self.publish(map))
     }
 
-    #[cfg(feature = "builder")]
-    pub(crate) fn property_area_mut(&self) -> Result<PropertyAreaMutGuard<'_>> {
-        let guard = self.property_area.write().map_err(lock_err("write"))?;
-        // Never lazily initialize here: the only mapping this path could
-        // create is a read-only one (`new_ro`), and handing out a mut
-        // guard over a PROT_READ mapping would SIGSEGV on first write.
-        // Writable areas are opened eagerly by `ContextsSerialized::new`
-        // via `open()`. Also reject a map that exists but is read-only
-        // (e.g. lazily created by the *read* path before this call) — the
-        // borrow-level guard must never wrap a PROT_READ mapping.
-        let writable = guard.as_ref().is_some_and(|pa| pa.is_writable());
-        if !writable {
-            error!(
-                "property_area_mut on an unopened or read-only area: {:?}",
-                self.filename
-            );
-            return Err(Error::PermissionDenied(format!(
-                "property area not opened read-write: {:?}",
-                self.filename
-            )));
+    /// Publishes `map` as the node's first mapping, in the slot its access
+    /// calls for, unless another thread got there first; returns the one
+    /// published.
+    fn publish(&self, map: PropertyAreaMap) -> AreaRef<'_> {
+        let first = || {
+            self.generations.store(1, Ordering::Relaxed);
+            self.next_check
+                .store(now_millis() + RECHECK_INTERVAL_MS, Ordering::Relaxed);
+            map
+        };
+        if self.access_rw {
+            AreaRef::Fixed(self.property_area.get_or_init(first))
+        } else {
+            AreaRef::Shared(
+                self.mapped
+                    .get_or_init(|| ArcSwap::from_pointee(first()))
+                    .load(),
+            )
         }
-        Ok(PropertyAreaMutGuard::from_initialized(guard))
     }
-}
 
-fn lock_err<T>(kind: &'static str) -> impl Fn(std::sync::PoisonError<T>) -> Error {
-    move |e| {
-        Error::Lock(format!(
-            "Failed to acquire {kind} lock on property area: {e}"
-        ))
+    /// Whether a lookup should check the node's file now (see
+    /// [`RECHECK_INTERVAL_MS`]). Only one of the threads racing past the
+    /// deadline gets `true`.
+    fn recheck_due(&self) -> bool {
+        if self.access_rw || self.memfd.is_some() || self.mapped.get().is_none() {
+            return false;
+        }
+        let now = now_millis();
+        let due = self.next_check.load(Ordering::Relaxed);
+        now >= due
+            && self
+                .next_check
+                .compare_exchange(
+                    due,
+                    now + RECHECK_INTERVAL_MS,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
     }
-}
 
-/// Read-guard that only exists once the underlying `Option` has been
-/// initialized. The `from_initialized` constructor is the single entry
-/// point and is `pub(self)` so callers in this module cannot bypass the
-/// `is_some()` check.
-pub(crate) struct PropertyAreaGuard<'a> {
-    guard: RwLockReadGuard<'a, Option<PropertyAreaMap>>,
-}
-
-impl<'a> PropertyAreaGuard<'a> {
-    /// Caller MUST have verified `guard.is_some()`. The `debug_assert!`
-    /// is a development-time tripwire; release builds rely on the
-    /// type-level invariant documented on `ContextNode::property_area`.
-    fn from_initialized(guard: RwLockReadGuard<'a, Option<PropertyAreaMap>>) -> Self {
-        debug_assert!(
-            guard.is_some(),
-            "PropertyAreaGuard constructed from uninitialized Option — \
-             this would have panicked in release at the access site"
-        );
-        Self { guard }
+    /// The current mapping, if the area was mapped at all.
+    fn current(&self) -> Option<AreaRef<'_>> {
+        if self.access_rw {
+            self.property_area.get().map(AreaRef::Fixed)
+        } else {
+            self.mapped.get().map(|slot| AreaRef::Shared(slot.load()))
+        }
     }
 
-    pub(crate) fn property_area(&self) -> &PropertyAreaMap {
-        // SAFETY-level invariant: `from_initialized` is the only ctor; it
-        // requires `Some(...)`. No code path in this module resets the
-        // underlying `Option<PropertyAreaMap>` back to `None`.
-        self.guard
-            .as_ref()
-            .expect("PropertyAreaGuard constructed only from Some(...); see ContextNode invariant")
+    /// The current mapping, if the area was mapped at all, and how many
+    /// mappings of it there were so far (more than one after
+    /// replacements). Never maps anything.
+    pub(crate) fn mapped_area(&self) -> Option<(AreaRef<'_>, usize)> {
+        Some((self.current()?, self.generations.load(Ordering::Relaxed)))
     }
-}
 
-/// Write-guard counterpart of [`PropertyAreaGuard`]. Same invariant.
-#[cfg(feature = "builder")]
-pub(crate) struct PropertyAreaMutGuard<'a> {
-    guard: RwLockWriteGuard<'a, Option<PropertyAreaMap>>,
-}
+    /// Replaced mappings some reader still holds; the others are unmapped
+    /// already.
+    pub(crate) fn retired_mappings(&self) -> usize {
+        let mut retired = lock_order::lock("ContextNode::retired", &self.retired);
+        retired.retain(|map| map.strong_count() > 0);
+        retired.len()
+    }
 
-#[cfg(feature = "builder")]
-impl<'a> PropertyAreaMutGuard<'a> {
-    fn from_initialized(guard: RwLockWriteGuard<'a, Option<PropertyAreaMap>>) -> Self {
-        debug_assert!(
-            guard.is_some(),
-            "PropertyAreaMutGuard constructed from uninitialized Option — \
-             this would have panicked in release at the access site"
-        );
-        Self { guard }
+    /// Checks that the file at this node's path is still the one the
+    /// current mapping was created from, and maps the new file if it was
+    /// replaced — e.g. by a property service restart that recreated the
+    /// directory. Returns whether a new mapping was published. Lookups
+    /// run it on a miss and, throttled to [`RECHECK_INTERVAL_MS`], on hits
+    /// too (see `property_area`).
+    ///
+    /// Only read-only, file-backed nodes are revalidated: a writable
+    /// node's files are owned by this very instance, and neither a memfd
+    /// nor a buffer copy can be replaced. A node that was never mapped has
+    /// nothing to revalidate. A path that no longer exists at all is
+    /// [`Error::AreaVanished`].
+    ///
+    /// This is a recovery path, not a guarantee: a file truncated in place
+    /// raises `SIGBUS` on access before any check can run (the same
+    /// precondition as `PropertyAreaMap::new_ro`), and a rebuilt
+    /// `property_info` that renumbers contexts is not picked up.
+    ///
+    /// The new mapping replaces the old one for later readers; the old one
+    /// stays mapped (address space and open file) only as long as readers
+    /// that loaded it before the swap still use it.
+    pub(crate) fn revalidate(&self) -> Result<bool> {
+        if self.access_rw || self.memfd.is_some() {
+            return Ok(false);
+        }
+        let Some(slot) = self.mapped.get() else {
+            return Ok(false);
+        };
+        let current = slot.load();
+        let Some(mapped) = current.file_id() else {
+            return Ok(false);
+        };
+        let on_disk = match std::fs::symlink_metadata(&self.filename) {
+            Ok(metadata) => crate::property_area::file_id(&metadata),
+            // Not logged here: the periodic check would repeat it every
+            // interval, and lookups log the error they return.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::AreaVanished(format!("{:?}", self.filename)));
+            }
+            Err(e) => {
+                return Err(e).context_with_location(format!("Failed to stat {:?}", self.filename))
+            }
+        };
+        if on_disk == mapped {
+            return Ok(false);
+        }
+        let map = Arc::new(PropertyAreaMap::new_ro(self.filename.as_path())?);
+        // A concurrent revalidation may have swapped in its own mapping of
+        // the same new file first; ours is then dropped and the caller
+        // retries against theirs, which is equivalent.
+        let previous = slot.compare_and_swap(&current, map);
+        if Arc::ptr_eq(&previous, &current) {
+            info!("Remapped replaced property area {:?}", self.filename);
+            self.generations.fetch_add(1, Ordering::Relaxed);
+            let mut retired = lock_order::lock("ContextNode::retired", &self.retired);
+            retired.retain(|map| map.strong_count() > 0);
+            retired.push(Arc::downgrade(&current));
+        }
+        Ok(true)
     }
 
-    pub(crate) fn property_area_mut(&mut self) -> &mut PropertyAreaMap {
-        self.guard.as_mut().expect(
-            "PropertyAreaMutGuard constructed only from Some(...); see ContextNode invariant",
-        )
+    #[cfg(feature = "writer")]
+    pub(crate) fn property_area_mut(&mut self) -> Result<&mut PropertyAreaMap> {
+        // Never lazily initialize here: the only mapping this path could
+        // create is a read-only one (`new_ro`), and handing out `&mut`
+        // over a PROT_READ mapping would SIGSEGV on first write. Writable
+        // areas are opened eagerly by `ContextsSerialized::new` via
+        // `open()`. Also reject a map that exists but is read-only (e.g.
+        // lazily created by the *read* path before this call).
+        //
+        // A writable node's area is never replaced, see `revalidate`.
+        match self.property_area.get_mut() {
+            Some(map) if map.is_writable() => Ok(map),
+            _ => {
+                error!(
+                    "property_area_mut on an unopened or read-only area: {:?}",
+                    self.filename
+                );
+                Err(Error::PermissionDenied(format!(
+                    "property area not opened read-write: {:?}",
+                    self.filename
+                )))
+            }
+        }
     }
 }
@@ -2,33 +2,19 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::ffi::CStr;
-use std::path::Path;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 
 use crate::errors::*;
 use log::{debug, error, info, warn};
 use rustix::fs;
 
-#[cfg(feature = "builder")]
-use crate::context_node::PropertyAreaMutGuard;
-use crate::context_node::{ContextNode, PropertyAreaGuard};
+use crate::context_cache::{ContextCache, ContextCacheStats};
+use crate::context_node::{AreaRef, ContextNode};
+use crate::layout::{Layout, DEFAULT_AREA_SIZE, WRITER_LOCK_FILENAME};
 use crate::property_area::{PropertyArea, PropertyAreaMap};
 use crate::property_info_parser::{PropertyInfoArea, PropertyInfoAreaFile};
-
-/// Filenames the property directory reserves for its own bookkeeping. A
-/// context named after one of these would make `ContextNode::open()` (which
-/// unlinks and recreates its file via `PropertyAreaMap::new_rw`) destroy
-/// that bookkeeping: `.writer_lock` → the flock the writer just acquired
-/// keeps guarding an orphan inode, so a second writer can "win" the lock
-/// and both writers unlink each other's areas; `properties_serial` → the
-/// serial mapping created right after the node loop re-unlinks the node's
-/// file, leaving the node on an orphan inode invisible to readers;
-/// `property_info` → the trie file itself is destroyed.
-///
-/// Compared (like the duplicate check) on the ASCII-case-folded name: on a
-/// case-insensitive filesystem (macOS APFS default, where this crate's
-/// tests run writable) `"PROPERTY_INFO"` would otherwise pass the exact
-/// match yet unlink the real `property_info`.
-const RESERVED_FILENAMES: &[&str] = &[".writer_lock", "properties_serial", "property_info"];
+use crate::SelinuxContext;
 
 /// Decodes one `ContextNode` entry from the property-info area. Returns
 /// `Err` on corrupt offset, missing NUL terminator, or non-UTF-8 name —
@@ -36,10 +22,10 @@ const RESERVED_FILENAMES: &[&str] = &[".writer_lock", "properties_serial", "prop
 /// indices stay aligned with the parser's `context_index` values.
 fn try_build_context_node(
     area: &PropertyInfoArea<'_>,
-    dirname: &Path,
-    writable: bool,
+    layout: &Layout,
     i: usize,
     seen_names: &mut std::collections::HashSet<String>,
+    make_node: &mut dyn FnMut(&str, &CStr) -> Result<ContextNode>,
 ) -> Result<ContextNode> {
     let context_offset = area.context_offset(i)?;
     // `cstr()` reports out-of-range offsets and missing NUL terminators as
@@ -89,13 +75,23 @@ fn try_build_context_node(
             )));
         }
     }
-    // Same-directory collisions are as destructive as directory escape —
-    // see `RESERVED_FILENAMES`. Both checks compare the ASCII-case-folded
-    // name so a case-insensitive filesystem cannot be used to alias two
-    // "different" names onto one file (the fold is also why the seen-set
-    // key is an owned `String` rather than a borrow of the mmap'd name).
+    // Same-directory collisions are as destructive as directory escape. A
+    // context named after one of the layout's bookkeeping files would make
+    // `ContextNode::open()` (which unlinks and recreates its file via
+    // `PropertyAreaMap::new_rw`) destroy it: the writer lock → the flock
+    // the writer just acquired keeps guarding an orphan inode, so a second
+    // writer can "win" the lock and both writers unlink each other's
+    // areas; the serial area → the serial mapping created right after the
+    // node loop re-unlinks the node's file, leaving the node on an orphan
+    // inode invisible to readers; the trie file → destroyed outright.
+    //
+    // Both checks compare the ASCII-case-folded name so a case-insensitive
+    // filesystem (macOS APFS default, where this crate's tests run
+    // writable) cannot be used to alias two "different" names onto one
+    // file (the fold is also why the seen-set key is an owned `String`
+    // rather than a borrow of the mmap'd name).
     let folded_name = context_name.to_ascii_lowercase();
-    if RESERVED_FILENAMES.contains(&folded_name.as_str()) {
+    if layout.is_reserved(&folded_name) {
         return Err(Error::FileValidation(format!(
             "context entry {i}: context name {context_name:?} collides with a reserved filename"
         )));
@@ -109,22 +105,21 @@ fn try_build_context_node(
             "context entry {i}: duplicate context name {context_name:?}"
         )));
     }
-    // The owned context is only consumed by `open()` (writable path) for
-    // SELinux labeling; read-only nodes skip the allocation.
-    let context = writable.then(|| context_cstr.to_owned());
-    Ok(ContextNode::new(
-        writable,
-        context,
-        dirname.join(context_name),
-    ))
+    make_node(context_name, context_cstr)
 }
 
-// Pre-defined CStr constants to avoid unsafe code at runtime
-// Using const_str macro or safer compile-time construction
-const PROPERTIES_SERIAL_CONTEXT: &CStr = c"u:object_r:properties_serial:s0";
+/// The memfds of a memfd-backed store besides the per-context areas,
+/// with the filenames they stand for.
+struct MemfdFiles {
+    property_info: (String, File),
+    serial: (String, File),
+}
 
 pub(crate) struct ContextsSerialized {
     property_info_area_file: PropertyInfoAreaFile,
+    /// Memoized lookups in `property_info_area_file`; dropped with it, so
+    /// a store that loads a new `property_info` starts empty.
+    context_cache: ContextCache,
     /// `None` slots are corrupt context entries that were skipped during init.
     /// We keep the slot so that `context_index` values produced by the
     /// property-info parser line up with the vector's indices.
@@ -137,19 +132,283 @@ pub(crate) struct ContextsSerialized {
     /// the loser fails fast before touching anything. The kernel drops the
     /// lock when the `File` closes — including on crash.
     _writer_lock: Option<std::fs::File>,
+    /// The areas are writable: a directory writer holding the lock above,
+    /// or a memfd writer.
+    #[cfg(feature = "writer")]
+    writable: bool,
+    /// `Some` for a memfd-backed store; see [`crate::Backing::Memfd`].
+    memfds: Option<MemfdFiles>,
+    /// Where the areas were loaded from, for `fork_cow`.
+    #[cfg(feature = "writer")]
+    dirname: std::path::PathBuf,
+    #[cfg(feature = "writer")]
+    layout: Layout,
 }
 
 impl ContextsSerialized {
-    pub(crate) fn new(writable: bool, dirname: &Path, load_default_path: bool) -> Result<Self> {
-        let tree_filename = dirname.join("property_info");
-        let serial_filename = dirname.join("properties_serial");
+    pub(crate) fn new(writable: bool, dirname: &Path, layout: &Layout) -> Result<Self> {
+        // Before any path is built: the writer unlinks and recreates the
+        // serial area, so an unchecked layout could aim that at any file.
+        layout.validate()?;
+        let tree_filename = layout.property_info_path(dirname);
+        let serial_filename = layout.serial_path(dirname);
+
+        let property_info_area_file = PropertyInfoAreaFile::load_path(tree_filename.as_path())?;
+        let context_nodes =
+            Self::build_context_nodes(&property_info_area_file, layout, &mut |name, _| {
+                // The parsed context is only consumed by `open()` (writable
+                // path) for SELinux labeling; read-only nodes skip the
+                // allocation. A trie built by this crate only holds valid
+                // contexts; a foreign one's malformed label is reported
+                // here and the area left unlabelled, as a failed xattr
+                // call would leave it.
+                let context = writable
+                    .then(|| {
+                        SelinuxContext::new(name)
+                            .inspect_err(|e| warn!("Not labelling the area of {name:?}: {e}"))
+                            .ok()
+                    })
+                    .flatten();
+                Ok(ContextNode::new(writable, context, dirname.join(name)))
+            })?;
+
+        let (writer_lock, serial_property_area_map) = if writable {
+            if !dirname.is_dir() {
+                info!("Creating directory: {dirname:?}");
+                match fs::mkdir(dirname, fs::Mode::RWXU | fs::Mode::XGRP | fs::Mode::XOTH) {
+                    Ok(()) => {}
+                    // Lost a create race with a concurrent writer — the
+                    // flock below is the real single-writer arbiter, so an
+                    // already-existing *directory* is not an error here.
+                    // Re-check with is_dir(): EEXIST for a plain file at
+                    // this path is a real configuration error and deferring
+                    // it would surface later as a confusing ENOTDIR.
+                    Err(rustix::io::Errno::EXIST) if dirname.is_dir() => {}
+                    Err(e) => return Err(Error::from(e)),
+                }
+            }
 
-        let property_info_area_file = if load_default_path {
-            PropertyInfoAreaFile::load_default_path()
+            // Must precede `open_all_areas` below: it unlinks and recreates
+            // area files, so a losing second writer has to bail out
+            // *before* touching anything the winner owns.
+            let lock = Self::acquire_writer_lock(dirname)?;
+
+            Self::open_all_areas(&context_nodes, layout.area_size)?;
+
+            (
+                Some(lock),
+                Self::map_serial_property_area(serial_filename.as_path(), Some(layout))?,
+            )
         } else {
-            PropertyInfoAreaFile::load_path(tree_filename.as_path())
-        }?;
+            (
+                None,
+                Self::map_serial_property_area(serial_filename.as_path(), None)?,
+            )
+        };
+
+        Ok(Self {
+            property_info_area_file,
+            context_cache: ContextCache::default(),
+            context_nodes,
+            serial_property_area_map,
+            _writer_lock: writer_lock,
+            #[cfg(feature = "writer")]
+            writable,
+            memfds: None,
+            #[cfg(feature = "writer")]
+            dirname: dirname.to_path_buf(),
+            #[cfg(feature = "writer")]
+            layout: layout.clone(),
+        })
+    }
+
+    /// A writable store kept in new memfds (see [`crate::Backing::Memfd`])
+    /// instead of a directory: `property_info` is written to a sealed
+    /// memfd and every area is created up front, like [`Self::new`] does
+    /// for files. No lock is needed — nobody else can reach the memfds
+    /// until they are handed out.
+    #[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn new_memfd(property_info: &[u8], layout: &Layout) -> Result<Self> {
+        use std::io::Write;
+
+        layout.validate()?;
+        let info_name = Path::new(&layout.property_info_filename);
+        let info_file = crate::memfd_area::create(info_name)?;
+        (&info_file)
+            .write_all(property_info)
+            .context_with_location(format!("Failed to write memfd {info_name:?}"))?;
+        crate::memfd_area::seal_immutable(&info_file, info_name)?;
+        let property_info_area_file = PropertyInfoAreaFile::load_file(
+            info_file
+                .try_clone()
+                .context_with_location(format!("Failed to duplicate memfd {info_name:?}"))?,
+            info_name,
+        )?;
+
+        // Entries that fail validation are skipped like in `new`; failing
+        // to create a memfd is an error, as failing to create a file is.
+        let mut create_error = None;
+        let context_nodes =
+            Self::build_context_nodes(&property_info_area_file, layout, &mut |name, _| {
+                ContextNode::new_memfd(PathBuf::from(name), layout.area_size).inspect_err(|e| {
+                    create_error.get_or_insert_with(|| e.to_string());
+                })
+            })?;
+        if let Some(e) = create_error {
+            return Err(Error::Io(std::io::Error::other(e)));
+        }
 
+        let serial_name = Path::new(&layout.serial_filename);
+        let (serial_property_area_map, serial_file) =
+            PropertyAreaMap::new_rw_memfd(serial_name, DEFAULT_AREA_SIZE)
+                .inspect_err(|e| error!("Failed to create serial property area memfd: {e}"))?;
+
+        Ok(Self {
+            property_info_area_file,
+            context_cache: ContextCache::default(),
+            context_nodes,
+            serial_property_area_map,
+            _writer_lock: None,
+            #[cfg(feature = "writer")]
+            writable: true,
+            memfds: Some(MemfdFiles {
+                property_info: (layout.property_info_filename.clone(), info_file),
+                serial: (layout.serial_filename.clone(), serial_file),
+            }),
+            dirname: PathBuf::new(),
+            layout: layout.clone(),
+        })
+    }
+
+    /// A read-only store over the memfds of a memfd-backed writer, keyed
+    /// by the filename each would have in a properties directory (see
+    /// [`Self::memfds`]). A context whose memfd is missing is skipped like
+    /// a corrupt entry.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn from_memfds(
+        mut files: std::collections::HashMap<String, File>,
+        layout: &Layout,
+    ) -> Result<Self> {
+        layout.validate()?;
+        let mut take = |name: &str| {
+            files.remove(name).ok_or_else(|| {
+                Error::NotFound(format!("the property service sent no memfd for {name:?}"))
+            })
+        };
+        let info_name = Path::new(&layout.property_info_filename);
+        let info_file = take(&layout.property_info_filename)?;
+        crate::memfd_area::check_sealed(&info_file, info_name)?;
+        let property_info_area_file = PropertyInfoAreaFile::load_file(
+            info_file
+                .try_clone()
+                .context_with_location(format!("Failed to duplicate memfd {info_name:?}"))?,
+            info_name,
+        )?;
+        let serial_file = take(&layout.serial_filename)?;
+
+        let context_nodes =
+            Self::build_context_nodes(&property_info_area_file, layout, &mut |name, _| {
+                ContextNode::from_memfd(PathBuf::from(name), take(name)?)
+            })?;
+
+        let serial_name = Path::new(&layout.serial_filename);
+        let serial_property_area_map = PropertyAreaMap::new_ro_memfd(
+            serial_file
+                .try_clone()
+                .context_with_location(format!("Failed to duplicate memfd {serial_name:?}"))?,
+            serial_name,
+        )
+        .inspect_err(|e| error!("Failed to map serial property area memfd: {e}"))?;
+
+        Ok(Self {
+            property_info_area_file,
+            context_cache: ContextCache::default(),
+            context_nodes,
+            serial_property_area_map,
+            _writer_lock: None,
+            #[cfg(feature = "writer")]
+            writable: false,
+            memfds: Some(MemfdFiles {
+                property_info: (layout.property_info_filename.clone(), info_file),
+                serial: (layout.serial_filename.clone(), serial_file),
+            }),
+            #[cfg(feature = "writer")]
+            dirname: PathBuf::new(),
+            #[cfg(feature = "writer")]
+            layout: layout.clone(),
+        })
+    }
+
+    /// A read-only store over copies of `property_info` and the areas in
+    /// `areas`, keyed by the filename each would have in a properties
+    /// directory. A context whose area is missing is skipped like a
+    /// corrupt entry.
+    pub(crate) fn from_buffers(
+        property_info: &[u8],
+        mut areas: std::collections::HashMap<String, Vec<u8>>,
+        layout: &Layout,
+    ) -> Result<Self> {
+        layout.validate()?;
+        let mut take = |name: &str| {
+            areas
+                .remove(name)
+                .ok_or_else(|| Error::NotFound(format!("no buffer for area {name:?}")))
+        };
+        let property_info_area_file = PropertyInfoAreaFile::load_bytes(
+            property_info,
+            Path::new(&layout.property_info_filename),
+        )?;
+        let serial = take(&layout.serial_filename)?;
+
+        let context_nodes =
+            Self::build_context_nodes(&property_info_area_file, layout, &mut |name, _| {
+                ContextNode::from_buffer(PathBuf::from(name), &take(name)?)
+            })?;
+
+        let serial_property_area_map =
+            PropertyAreaMap::from_bytes(&serial, Path::new(&layout.serial_filename))
+                .inspect_err(|e| error!("Failed to copy serial property area: {e}"))?;
+
+        Ok(Self {
+            property_info_area_file,
+            context_cache: ContextCache::default(),
+            context_nodes,
+            serial_property_area_map,
+            _writer_lock: None,
+            #[cfg(feature = "writer")]
+            writable: false,
+            memfds: None,
+            #[cfg(feature = "writer")]
+            dirname: PathBuf::new(),
+            #[cfg(feature = "writer")]
+            layout: layout.clone(),
+        })
+    }
+
+    /// Every memfd of a memfd-backed store, by the filename it would have
+    /// in a properties directory; `None` for a file-backed store.
+    pub(crate) fn memfds(&self) -> Option<Vec<(&str, &File)>> {
+        let memfds = self.memfds.as_ref()?;
+        let mut all = vec![
+            (memfds.property_info.0.as_str(), &memfds.property_info.1),
+            (memfds.serial.0.as_str(), &memfds.serial.1),
+        ];
+        for node in self.context_nodes.iter().flatten() {
+            if let (Some(name), Some(file)) = (node.filename().to_str(), node.memfd()) {
+                all.push((name, file));
+            }
+        }
+        Some(all)
+    }
+
+    /// Validates the context table of `property_info_area_file` and builds
+    /// a node per entry with `make_node(name, context)`. An entry that
+    /// fails validation or construction becomes a `None` slot.
+    fn build_context_nodes(
+        property_info_area_file: &PropertyInfoAreaFile,
+        layout: &Layout,
+        make_node: &mut dyn FnMut(&str, &CStr) -> Result<ContextNode>,
+    ) -> Result<Vec<Option<ContextNode>>> {
         let property_info_area = property_info_area_file.property_info_area();
         let num_context_nodes = property_info_area.num_contexts();
         // The count is untrusted file data; it sizes the allocation below
@@ -181,7 +440,7 @@ impl ContextsSerialized {
 
         let mut seen_names = std::collections::HashSet::new();
         for i in 0..num_context_nodes {
-            match try_build_context_node(&property_info_area, dirname, writable, i, &mut seen_names)
+            match try_build_context_node(&property_info_area, layout, i, &mut seen_names, make_node)
             {
                 Ok(n) => context_nodes.push(Some(n)),
                 Err(e) => {
@@ -190,60 +449,87 @@ impl ContextsSerialized {
                 }
             }
         }
+        Ok(context_nodes)
+    }
 
-        let (writer_lock, serial_property_area_map) = if writable {
-            if !dirname.is_dir() {
-                info!("Creating directory: {dirname:?}");
-                match fs::mkdir(dirname, fs::Mode::RWXU | fs::Mode::XGRP | fs::Mode::XOTH) {
-                    Ok(()) => {}
-                    // Lost a create race with a concurrent writer — the
-                    // flock below is the real single-writer arbiter, so an
-                    // already-existing *directory* is not an error here.
-                    // Re-check with is_dir(): EEXIST for a plain file at
-                    // this path is a real configuration error and deferring
-                    // it would surface later as a confusing ENOTDIR.
-                    Err(rustix::io::Errno::EXIST) if dirname.is_dir() => {}
-                    Err(e) => return Err(Error::from(e)),
-                }
-            }
-
-            // Must precede the `open()` calls below: they unlink and
-            // recreate area files, so a losing second writer has to bail
-            // out *before* touching anything the winner owns.
-            let lock = Self::acquire_writer_lock(dirname)?;
+    /// A private copy of this instance: the same `property_info`, with
+    /// every area (and the serial area) mapped copy-on-write, so writes
+    /// through the copy never reach the files. Works on read-only
+    /// instances too — a private writable mapping only needs read access.
+    #[cfg(feature = "writer")]
+    pub(crate) fn fork_cow(&self) -> Result<Self> {
+        if self.memfds.is_some() {
+            return Err(Error::InvalidArgument(
+                "copy-on-write forks of a memfd-backed store are not supported".to_owned(),
+            ));
+        }
+        if self.dirname.as_os_str().is_empty() {
+            return Err(Error::InvalidArgument(
+                "copy-on-write forks of a store built from buffers are not supported".to_owned(),
+            ));
+        }
+        let mut contexts = Self::new(false, &self.dirname, &self.layout)?;
+        for node in contexts.context_nodes.iter_mut().flatten() {
+            *node = ContextNode::new_cow(node.filename().to_path_buf())?;
+        }
+        contexts.serial_property_area_map =
+            PropertyAreaMap::new_cow(&self.layout.serial_path(&self.dirname))
+                .inspect_err(|e| error!("Failed to map serial property area copy-on-write: {e}"))?;
+        Ok(contexts)
+    }
 
-            // `open()` takes `&self` (interior mutability via its RwLock) —
-            // a `&mut` walk here would misread as structural mutation.
-            for node in context_nodes.iter().flatten() {
-                node.open()?;
-            }
+    /// Creates and maps every per-context area file up front, like init's
+    /// `InitializeProperties`: file modes and SELinux labels are in place
+    /// before any client looks, and the first write to a context pays no
+    /// file-creation cost. Slots skipped as corrupt at load are skipped
+    /// here too.
+    fn open_all_areas(context_nodes: &[Option<ContextNode>], area_size: usize) -> Result<()> {
+        // `open()` takes `&self` (it only publishes the node's write-once
+        // map) — a `&mut` walk here would misread as structural mutation.
+        for node in context_nodes.iter().flatten() {
+            node.open(area_size)?;
+        }
+        Ok(())
+    }
 
-            (
-                Some(lock),
-                Self::map_serial_property_area(serial_filename.as_path(), true)?,
-            )
-        } else {
-            (
-                None,
-                Self::map_serial_property_area(serial_filename.as_path(), false)?,
-            )
-        };
+    /// Starts per-record checksums on every writable area (see
+    /// `PropertyAreaMap::enable_checksums`).
+    #[cfg(feature = "writer")]
+    pub(crate) fn enable_checksums(&mut self) -> Result<()> {
+        // Sidecars are files next to the areas; a memfd store has none.
+        if self.memfds.is_some() {
+            return Err(Error::InvalidArgument(
+                "checksum sidecars need a file-backed store".to_owned(),
+            ));
+        }
+        for node in self.context_nodes.iter_mut().flatten() {
+            let sidecar = crate::checksum::sidecar_path(node.filename());
+            node.property_area_mut()?.enable_checksums(&sidecar)?;
+        }
+        Ok(())
+    }
 
-        Ok(Self {
-            property_info_area_file,
-            context_nodes,
-            serial_property_area_map,
-            _writer_lock: writer_lock,
-        })
+    /// Re-runs the eager area creation of a writable instance. `open()` is
+    /// idempotent for nodes already mapped read-write, so on an instance
+    /// from `new(true, ..)` this only verifies that every area is mapped
+    /// writable; it is an error on a read-only instance.
+    #[cfg(feature = "writer")]
+    pub(crate) fn initialize_all_areas(&self) -> Result<()> {
+        if !self.writable {
+            return Err(Error::PermissionDenied(
+                "initialize_all_areas requires a writable property area".to_owned(),
+            ));
+        }
+        Self::open_all_areas(&self.context_nodes, self.layout.area_size)
     }
 
-    /// Opens (creating if needed) `<dirname>/.writer_lock` and takes a
+    /// Opens (creating if needed) the directory's writer lock file and takes a
     /// non-blocking exclusive `flock`. The lock lives exactly as long as
     /// the returned `File`, so holding it in the struct scopes single-writer
     /// ownership of the directory to the instance's lifetime.
     fn acquire_writer_lock(dirname: &Path) -> Result<std::fs::File> {
         use std::os::unix::fs::OpenOptionsExt;
-        let lock_path = dirname.join(".writer_lock");
+        let lock_path = dirname.join(WRITER_LOCK_FILENAME);
         // O_NOFOLLOW + explicit mode, like the area files opened by
         // `PropertyAreaMap::new_rw`: this file is the single-writer
         // arbiter, so a symlink planted at `.writer_lock` must not be able
@@ -278,14 +564,19 @@ impl ContextsSerialized {
         Ok(lock_file)
     }
 
+    /// Maps the serial area read-write (labelled with the layout's serial
+    /// context) when `writer_layout` is given, read-only otherwise.
     fn map_serial_property_area(
         serial_filename: &Path,
-        access_rw: bool,
+        writer_layout: Option<&Layout>,
     ) -> Result<PropertyAreaMap> {
-        let result = if access_rw {
-            PropertyAreaMap::new_rw(serial_filename, Some(PROPERTIES_SERIAL_CONTEXT))
-        } else {
-            PropertyAreaMap::new_ro(serial_filename)
+        let result = match writer_layout {
+            Some(layout) => PropertyAreaMap::new_rw(
+                serial_filename,
+                Some(&layout.serial_selinux_context()?),
+                DEFAULT_AREA_SIZE,
+            ),
+            None => PropertyAreaMap::new_ro(serial_filename),
         };
 
         result
@@ -346,11 +637,25 @@ impl ContextsSerialized {
         }
     }
 
-    pub(crate) fn prop_area_for_name(&self, name: &str) -> Result<(PropertyAreaGuard<'_>, u32)> {
-        let (index, _) = self
-            .property_info_area_file
-            .property_info_area()
-            .get_property_info_indexes(name);
+    /// `&mut` counterpart of [`Self::context_node_at`] for the builder's
+    /// write path. Reuses the shared accessor for the checks and logging,
+    /// then re-borrows the slot mutably.
+    #[cfg(feature = "writer")]
+    fn context_node_at_mut(
+        &mut self,
+        index: u32,
+        what: &dyn std::fmt::Display,
+        miss_is_expected: bool,
+    ) -> Result<&mut ContextNode> {
+        self.context_node_at(index, what, miss_is_expected)?;
+        self.context_nodes
+            .get_mut(index as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| Error::NotFound(format!("no context for {what}")))
+    }
+
+    pub(crate) fn prop_area_for_name(&self, name: &str) -> Result<(AreaRef<'_>, u32)> {
+        let (index, _) = self.property_info_indexes(name);
         let node = self.context_node_at(index, &format_args!("property {name}"), true)?;
         let area = node
             .property_area()
@@ -358,16 +663,146 @@ impl ContextsSerialized {
         Ok((area, index))
     }
 
-    #[cfg(feature = "builder")]
+    /// Maps (if needed) and returns the area of every context whose file
+    /// exists (every area of a memfd or buffer store), paired with its
+    /// context index. A context nobody has written
+    /// to yet may have no file at all — with a lazily-creating writer — and
+    /// simply holds no properties; slots skipped as corrupt at load are
+    /// skipped here too.
+    pub(crate) fn existing_areas(&self) -> Result<Vec<(AreaRef<'_>, u32)>> {
+        let mut areas = Vec::with_capacity(self.context_nodes.len());
+        for (index, node) in self.context_nodes.iter().enumerate() {
+            let Some(node) = node else { continue };
+            match node.is_file_backed().then(|| node.filename().try_exists()) {
+                None | Some(Ok(true)) => {}
+                Some(Ok(false)) => continue,
+                Some(Err(e)) => {
+                    return Err(e)
+                        .context_with_location(format!("Failed to stat {:?}", node.filename()))
+                }
+            }
+            let area = node.property_area().inspect_err(|e| {
+                error!("Failed to get property area {:?}: {e}", node.filename())
+            })?;
+            areas.push((area, index as u32));
+        }
+        Ok(areas)
+    }
+
+    /// The areas mapped so far, with their context index and generation
+    /// count (see `ContextNode::mapped_area`), and the number of context
+    /// slots. Unlike `existing_areas`, maps nothing.
+    pub(crate) fn mapped_areas(&self) -> (Vec<(AreaRef<'_>, u32, usize)>, usize) {
+        let areas = self
+            .context_nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, node)| {
+                let (area, generations) = node.as_ref()?.mapped_area()?;
+                Some((area, index as u32, generations))
+            })
+            .collect();
+        (areas, self.context_nodes.len())
+    }
+
+    /// Replaced mappings readers still hold, over every node (see
+    /// `ContextNode::retired_mappings`).
+    pub(crate) fn retired_mappings(&self) -> usize {
+        self.context_nodes
+            .iter()
+            .flatten()
+            .map(ContextNode::retired_mappings)
+            .sum()
+    }
+
+    /// Runs `ContextNode::revalidate` on every node, so all replaced
+    /// files are remapped in one pass. Every node is visited even after a
+    /// failure; the first error is returned.
+    pub(crate) fn revalidate_all_areas(&self) -> Result<()> {
+        let mut first_err = None;
+        for node in self.context_nodes.iter().flatten() {
+            if let Err(e) = node.revalidate() {
+                first_err.get_or_insert(e);
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    /// See `ContextNode::revalidate`.
+    pub(crate) fn revalidate_area(&self, context_index: u32) -> Result<bool> {
+        self.context_node_at(
+            context_index,
+            &format_args!("context index {context_index}"),
+            false,
+        )?
+        .revalidate()
+    }
+
+    /// Resolves `name` to the area file of its context without mapping
+    /// it. Goes through the same `get_property_info_indexes` lookup and
+    /// node table as `prop_area_for_name`, so the answer always matches the
+    /// file a read of `name` would actually touch — including the
+    /// corrupt-at-init slot, which errors here too.
+    pub(crate) fn area_file_for_name(&self, name: &str) -> Result<&Path> {
+        let (index, _) = self.property_info_indexes(name);
+        let node = self.context_node_at(index, &format_args!("property {name}"), true)?;
+        Ok(node.filename())
+    }
+
+    /// `(context_index, type_index)` of `name` in the `property_info`
+    /// trie, through the context cache.
+    fn property_info_indexes(&self, name: &str) -> (u32, u32) {
+        self.context_cache
+            .indexes(&self.property_info_area_file.property_info_area(), name)
+    }
+
+    pub(crate) fn context_cache_stats(&self) -> ContextCacheStats {
+        self.context_cache.stats()
+    }
+
+    /// Type the `property_info` trie declares for `name`, or `None` when
+    /// no entry on its path declares one.
+    pub(crate) fn type_for_name(&self, name: &str) -> Result<Option<&str>> {
+        let (_, type_index) = self.property_info_indexes(name);
+        self.property_info_area_file
+            .property_info_area()
+            .type_at(type_index)
+    }
+
+    /// Context the `property_info` trie assigns to `name`, or `None` if it
+    /// has none.
+    pub(crate) fn context_for_name(&self, name: &str) -> Result<Option<&str>> {
+        let (context_index, _) = self.property_info_indexes(name);
+        self.property_info_area_file
+            .property_info_area()
+            .context_at(context_index)
+    }
+
+    /// Name of the context at `context_index`, as `existing_areas`
+    /// pairs it with an area.
+    pub(crate) fn context_name(&self, context_index: u32) -> Result<Option<&str>> {
+        self.property_info_area_file
+            .property_info_area()
+            .context_at(context_index)
+    }
+
+    /// Whether a `property_info` entry other than the trie's default
+    /// assigns `name` a context.
+    #[cfg(feature = "writer")]
+    pub(crate) fn is_declared(&self, name: &str) -> bool {
+        self.property_info_area_file
+            .property_info_area()
+            .declared_context_index(name)
+            != crate::property_info_parser::NO_INDEX
+    }
+
+    #[cfg(feature = "writer")]
     pub(crate) fn prop_area_mut_for_name(
-        &self,
+        &mut self,
         name: &str,
-    ) -> Result<(PropertyAreaMutGuard<'_>, u32)> {
-        let (index, _) = self
-            .property_info_area_file
-            .property_info_area()
-            .get_property_info_indexes(name);
-        let node = self.context_node_at(index, &format_args!("property {name}"), true)?;
+    ) -> Result<(&mut PropertyAreaMap, u32)> {
+        let (index, _) = self.property_info_indexes(name);
+        let node = self.context_node_at_mut(index, &format_args!("property {name}"), true)?;
         let area = node
             .property_area_mut()
             .inspect_err(|e| error!("Failed to get mutable property area for {name}: {e}"))?;
@@ -378,7 +813,7 @@ impl ContextsSerialized {
         self.serial_property_area_map.property_area()
     }
 
-    pub(crate) fn prop_area_with_index(&self, context_index: u32) -> Result<PropertyAreaGuard<'_>> {
+    pub(crate) fn prop_area_with_index(&self, context_index: u32) -> Result<AreaRef<'_>> {
         self.context_node_at(
             context_index,
             &format_args!("context index {context_index}"),
@@ -390,12 +825,12 @@ impl ContextsSerialized {
         })
     }
 
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn prop_area_mut_with_index(
-        &self,
+        &mut self,
         context_index: u32,
-    ) -> Result<PropertyAreaMutGuard<'_>> {
-        self.context_node_at(
+    ) -> Result<&mut PropertyAreaMap> {
+        self.context_node_at_mut(
             context_index,
             &format_args!("context index {context_index}"),
             false,
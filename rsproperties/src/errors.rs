@@ -76,8 +76,8 @@ pub enum Error {
     /// The property service accepted the connection but rejected the
     /// request at the protocol level — the socket itself is healthy, so
     /// this is deliberately not an [`Error::Io`].
-    #[error("Property service rejected \"{name}\": error code {code:#x}")]
-    ServiceError { name: String, code: i32 },
+    #[error("{0}")]
+    ServiceError(SetError),
 
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
@@ -92,6 +92,38 @@ pub enum Error {
     #[error("Property area full: {0}")]
     AreaFull(String),
 
+    /// A per-context area file that a read-only instance had mapped no
+    /// longer exists at its path (e.g. the property service's directory
+    /// was removed). Distinct from [`Error::NotFound`], which means the
+    /// *property* is absent: here the whole area is gone, and the remedy
+    /// is to retry once the service has recreated it — a replaced file is
+    /// remapped transparently on the next lookup.
+    #[error("Property area vanished: {0}")]
+    AreaVanished(String),
+
+    /// A `PropertyHandle` no longer refers to the record it was found
+    /// for: its area was rebuilt or replaced since. Look the name up
+    /// again.
+    #[error("Stale property handle: {0}")]
+    StaleHandle(String),
+
+    /// A property record failed its checksum (see
+    /// `SystemProperties::enable_checksums`): its name or value changed
+    /// on storage without going through a writer. `name` is the name as
+    /// stored, which may itself be the damaged part.
+    #[error("Property record is corrupt: {name}")]
+    Corrupt { name: String },
+
+    /// Strict mode (`PropertyConfig::require_declared`) refused to create
+    /// a property no `property_contexts` entry declares.
+    #[error("Property is not declared in property_info: {name}")]
+    Undeclared { name: String },
+
+    /// The instance was closed with `SystemProperties::close`; nothing
+    /// can be read or written through it any more.
+    #[error("Property store is closed")]
+    Closed,
+
     #[error("File ownership error: {0}")]
     FileOwnership(String),
 
@@ -128,6 +160,80 @@ pub enum Error {
     },
 }
 
+/// A property service's refusal of a set request: the wire status code
+/// and, from services that send one (`rsproperties-service` does, AOSP
+/// init does not), the reason — e.g. "value too long: 120 bytes (max 91
+/// for writable properties)".
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SetError {
+    pub name: String,
+    /// Raw status code; see [`SetError::kind`].
+    pub code: i32,
+    pub message: Option<String>,
+}
+
+/// Broad reason for a [`SetError`], decoded from the AOSP status codes in
+/// [`crate::wire`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SetErrorKind {
+    /// The property is read-only and already set.
+    ReadOnly,
+    InvalidName,
+    InvalidValue,
+    PermissionDenied,
+    /// The request frame was malformed, over the wire caps, or an unknown
+    /// command.
+    Protocol,
+    /// Anything else, including the generic `PROP_ERROR`.
+    Failed,
+}
+
+impl SetError {
+    pub fn new(name: impl Into<String>, code: i32, message: Option<String>) -> Self {
+        Self {
+            name: name.into(),
+            code,
+            message,
+        }
+    }
+
+    pub fn kind(&self) -> SetErrorKind {
+        use crate::wire::*;
+        match self.code {
+            PROP_ERROR_READ_ONLY_PROPERTY => SetErrorKind::ReadOnly,
+            PROP_ERROR_INVALID_NAME => SetErrorKind::InvalidName,
+            PROP_ERROR_INVALID_VALUE => SetErrorKind::InvalidValue,
+            PROP_ERROR_PERMISSION_DENIED => SetErrorKind::PermissionDenied,
+            PROP_ERROR_READ_CMD | PROP_ERROR_READ_DATA | PROP_ERROR_INVALID_CMD => {
+                SetErrorKind::Protocol
+            }
+            _ => SetErrorKind::Failed,
+        }
+    }
+}
+
+impl std::fmt::Display for SetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Property service rejected \"{}\": ", self.name)?;
+        match &self.message {
+            Some(message) => f.write_str(message)?,
+            None => f.write_str(match self.kind() {
+                SetErrorKind::ReadOnly => "read-only property",
+                SetErrorKind::InvalidName => "invalid name",
+                SetErrorKind::InvalidValue => "invalid value",
+                SetErrorKind::PermissionDenied => "permission denied",
+                SetErrorKind::Protocol => "malformed request",
+                SetErrorKind::Failed => "set failed",
+            })?,
+        }
+        write!(f, " (error code {:#x})", self.code)
+    }
+}
+
+impl std::error::Error for SetError {}
+
 pub trait ContextWithLocation<T> {
     #[track_caller]
     fn context_with_location(self, msg: impl Into<String>) -> Result<T>;
@@ -0,0 +1,42 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Removal of test directories when the process exits, for fixtures held
+//! in statics: Rust never drops those, so a `Drop` impl would not run.
+//!
+//! `tests/common` compiles this file in directly, for the integration
+//! tests that run without `test-utils`.
+
+use std::path::PathBuf;
+use std::sync::{Mutex, Once};
+
+static PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> std::os::raw::c_int;
+}
+
+extern "C" fn remove_paths() {
+    // A panicking test may have poisoned the lock; the paths are intact.
+    let paths = std::mem::take(&mut *PATHS.lock().unwrap_or_else(|e| e.into_inner()));
+    for path in paths {
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
+
+/// Removes the directory `path` (recursively) when the process exits
+/// normally, which is how a test binary ends once every test ran.
+pub fn remove_at_exit(path: impl Into<PathBuf>) {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        // SAFETY: `remove_paths` is a plain `extern "C"` function that
+        // stays valid for the life of the process.
+        if unsafe { atexit(remove_paths) } != 0 {
+            log::warn!("Could not register the test directory cleanup");
+        }
+    });
+    PATHS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(path.into());
+}
@@ -0,0 +1,91 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Point-in-time copies of every property, see
+//! [`SystemProperties::freeze`](crate::SystemProperties::freeze).
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::errors::*;
+
+/// An owned, immutable copy of every property visible to a
+/// [`SystemProperties`](crate::SystemProperties) instance at one point in
+/// time.
+///
+/// Queries never touch the property mmaps, so a snapshot stays valid (and
+/// consistent with itself) while the property service keeps writing —
+/// useful for diagnostics dumps, or for components that must see one
+/// coherent configuration for the duration of a task.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrozenProperties {
+    values: HashMap<String, String>,
+    serial: u32,
+}
+
+impl FrozenProperties {
+    pub(crate) fn new(values: HashMap<String, String>, serial: u32) -> Self {
+        Self { values, serial }
+    }
+
+    /// Value of `name` at the time of the snapshot.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Like [`Self::get`], but a missing property is
+    /// [`Error::NotFound`] — the same contract as
+    /// [`SystemProperties::get_with_result`](crate::SystemProperties::get_with_result).
+    pub fn get_with_result(&self, name: &str) -> Result<&str> {
+        self.get(name)
+            .ok_or_else(|| Error::NotFound(name.to_owned()))
+    }
+
+    /// Parses `name`'s value, falling back to `default` when it is absent
+    /// or does not parse — the snapshot counterpart of
+    /// [`crate::get_or`].
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> T {
+        self.get(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Iterates over `(name, value)` pairs in unspecified order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The global serial the snapshot is consistent with (see
+    /// [`SystemProperties::context_serial`](crate::SystemProperties::context_serial)):
+    /// if it still equals the live value, nothing has changed since.
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// Consumes the snapshot, returning the underlying name → value map.
+    pub fn into_map(self) -> HashMap<String, String> {
+        self.values
+    }
+}
+
+/// A snapshot of properties that did not come from a store — a parsed
+/// `build.prop` or a JSON dump — so they can be compared with live ones.
+/// Its [`serial`](FrozenProperties::serial) is 0. A repeated name keeps
+/// the last value, like a `build.prop` override.
+impl FromIterator<(String, String)> for FrozenProperties {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect(), 0)
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The properties laid out as a read-only file tree, for filesystem front
+//! ends such as a FUSE mount.
+//!
+//! Every `.`-separated name component is a directory and the last one a
+//! file holding the value, so `ro.build.version.sdk` reads at
+//! `ro/build/version/sdk`. A name that is also a prefix of others
+//! (`persist.sys` next to `persist.sys.tz`) becomes a directory; its own
+//! value moves to the [`VALUE_FILE`] inside it. Property names cannot
+//! contain `/` or start a component with `.`, so no property collides
+//! with that file.
+//!
+//! A [`PropertyTree`] is built from a snapshot ([`SystemProperties::freeze`])
+//! and answers lookups, listings and reads without touching the store.
+//! [`PropertyTree::refresh`] rebuilds it once the global serial moved and
+//! returns the paths whose entry or content changed, which a front end
+//! invalidates in its kernel cache (`notify_inval_inode` and
+//! `notify_inval_entry` in FUSE); [`PropertyTree::wait_and_refresh`]
+//! blocks for the next change first, for a watcher thread. The crate binds
+//! no FUSE library: `examples/proptree.rs` drives the same calls a mount
+//! would.
+//!
+//! Paths are relative, `/`-separated, and may carry a leading or trailing
+//! `/`; the empty path is the root.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::errors::*;
+use crate::frozen::FrozenProperties;
+use crate::system_properties::SystemProperties;
+
+/// File inside a directory holding the value of the property the
+/// directory is named after, when that property exists.
+pub const VALUE_FILE: &str = ".value";
+
+/// Kind of a [`PropertyTree`] entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    Dir,
+    File,
+}
+
+#[derive(Debug, Default)]
+struct Dir {
+    entries: BTreeMap<String, Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Dir(Dir),
+    /// Holds the property name; the value stays in the snapshot.
+    File(String),
+}
+
+impl Node {
+    fn kind(&self) -> EntryKind {
+        match self {
+            Node::Dir(_) => EntryKind::Dir,
+            Node::File(_) => EntryKind::File,
+        }
+    }
+}
+
+impl Dir {
+    fn insert(&mut self, name: &str) {
+        let mut dir = self;
+        let mut components = name.split('.').peekable();
+        while let Some(component) = components.next() {
+            if components.peek().is_none() {
+                match dir.entries.get_mut(component) {
+                    Some(Node::Dir(inner)) => {
+                        inner
+                            .entries
+                            .insert(VALUE_FILE.to_owned(), Node::File(name.to_owned()));
+                    }
+                    _ => {
+                        dir.entries
+                            .insert(component.to_owned(), Node::File(name.to_owned()));
+                    }
+                }
+                return;
+            }
+            let node = dir
+                .entries
+                .entry(component.to_owned())
+                .or_insert_with(|| Node::Dir(Dir::default()));
+            if let Node::File(owner) = node {
+                // A shorter name became a prefix: push its value down.
+                let owner = std::mem::take(owner);
+                let mut inner = Dir::default();
+                inner
+                    .entries
+                    .insert(VALUE_FILE.to_owned(), Node::File(owner));
+                *node = Node::Dir(inner);
+            }
+            let Node::Dir(inner) = node else {
+                unreachable!("converted to a directory above");
+            };
+            dir = inner;
+        }
+    }
+
+    fn listing(&self) -> impl Iterator<Item = (&str, EntryKind)> {
+        self.entries
+            .iter()
+            .map(|(name, node)| (name.as_str(), node.kind()))
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
+
+fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+/// A read-only file-tree view of one property snapshot, see the
+/// [module documentation](self).
+#[derive(Debug)]
+pub struct PropertyTree {
+    snapshot: FrozenProperties,
+    root: Dir,
+}
+
+impl PropertyTree {
+    /// Builds the tree from a fresh snapshot of `props`.
+    pub fn new(props: &SystemProperties) -> Result<Self> {
+        Ok(Self::from_frozen(props.freeze()?))
+    }
+
+    /// Builds the tree from `snapshot`, e.g. a parsed `build.prop`.
+    pub fn from_frozen(snapshot: FrozenProperties) -> Self {
+        let mut root = Dir::default();
+        for (name, _) in snapshot.iter() {
+            root.insert(name);
+        }
+        Self { snapshot, root }
+    }
+
+    /// The global serial the tree is consistent with, see
+    /// [`FrozenProperties::serial`].
+    pub fn serial(&self) -> u32 {
+        self.snapshot.serial()
+    }
+
+    /// The snapshot the tree shows.
+    pub fn snapshot(&self) -> &FrozenProperties {
+        &self.snapshot
+    }
+
+    fn node(&self, path: &str) -> Option<&Node> {
+        let mut components = components(path);
+        let mut node = self.root.entries.get(components.next()?)?;
+        for component in components {
+            let Node::Dir(dir) = node else {
+                return None;
+            };
+            node = dir.entries.get(component)?;
+        }
+        Some(node)
+    }
+
+    fn dir(&self, path: &str) -> Option<&Dir> {
+        if components(path).next().is_none() {
+            return Some(&self.root);
+        }
+        match self.node(path)? {
+            Node::Dir(dir) => Some(dir),
+            Node::File(_) => None,
+        }
+    }
+
+    /// Kind of the entry at `path`, or `None` when there is none.
+    pub fn kind(&self, path: &str) -> Option<EntryKind> {
+        if components(path).next().is_none() {
+            return Some(EntryKind::Dir);
+        }
+        self.node(path).map(Node::kind)
+    }
+
+    /// Entries of the directory at `path`, sorted by name; `None` when
+    /// `path` is not a directory.
+    pub fn read_dir(&self, path: &str) -> Option<impl Iterator<Item = (&str, EntryKind)>> {
+        self.dir(path).map(Dir::listing)
+    }
+
+    /// Name of the property the file at `path` shows.
+    pub fn property_name(&self, path: &str) -> Option<&str> {
+        match self.node(path)? {
+            Node::File(name) => Some(name),
+            Node::Dir(_) => None,
+        }
+    }
+
+    /// Value of the property the file at `path` shows.
+    pub fn value(&self, path: &str) -> Option<&str> {
+        self.snapshot.get(self.property_name(path)?)
+    }
+
+    /// Contents of the file at `path`: the value and a newline, as
+    /// `/proc` and sysfs files end, so `cat` prints one line. File sizes a
+    /// front end reports must be those of these contents.
+    pub fn contents(&self, path: &str) -> Option<String> {
+        self.value(path).map(|value| format!("{value}\n"))
+    }
+
+    /// Path of the file showing property `name` in this tree, or `None`
+    /// when the snapshot does not have it.
+    pub fn path_of(&self, name: &str) -> Option<String> {
+        self.snapshot.get(name)?;
+        let path = name.replace('.', "/");
+        match self.node(&path)? {
+            Node::Dir(_) => Some(join(&path, VALUE_FILE)),
+            Node::File(_) => Some(path),
+        }
+    }
+
+    /// Rebuilds the tree from a fresh snapshot if `props` changed since
+    /// this one was taken, returning the changed paths (see
+    /// [`Self::update`]); empty when nothing changed.
+    pub fn refresh(&mut self, props: &SystemProperties) -> Result<Vec<String>> {
+        if props.context_serial() == self.serial() {
+            return Ok(Vec::new());
+        }
+        Ok(self.update(props.freeze()?))
+    }
+
+    /// Waits until `props` changes after this tree's snapshot, or
+    /// `timeout` elapses, then [`Self::refresh`]es. Returns `Ok(None)` on
+    /// a timeout.
+    pub fn wait_and_refresh(
+        &mut self,
+        props: &SystemProperties,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Vec<String>>> {
+        let serial = self.serial();
+        let timeout = timeout.map(|t| crate::Timespec {
+            tv_sec: t.as_secs() as _,
+            tv_nsec: t.subsec_nanos() as _,
+        });
+        // `None` is a timeout or a wait failure; the serial tells them
+        // apart from a change that raced the wait.
+        if props.wait(None, Some(serial), timeout.as_ref()).is_none()
+            && props.context_serial() == serial
+        {
+            return Ok(None);
+        }
+        self.refresh(props).map(Some)
+    }
+
+    /// Replaces the snapshot with `snapshot` and returns, sorted, the
+    /// paths a front end must invalidate: files whose value changed,
+    /// entries that appeared, disappeared or changed kind, and every
+    /// directory whose listing changed. The root is the empty path.
+    pub fn update(&mut self, snapshot: FrozenProperties) -> Vec<String> {
+        let new = Self::from_frozen(snapshot);
+        let mut changed = Vec::new();
+        diff(
+            &self.root,
+            &self.snapshot,
+            &new.root,
+            &new.snapshot,
+            "",
+            &mut changed,
+        );
+        *self = new;
+        changed.sort_unstable();
+        changed
+    }
+}
+
+fn diff(
+    old: &Dir,
+    old_values: &FrozenProperties,
+    new: &Dir,
+    new_values: &FrozenProperties,
+    path: &str,
+    changed: &mut Vec<String>,
+) {
+    if !old.listing().eq(new.listing()) {
+        changed.push(path.to_owned());
+    }
+    for (name, node) in &new.entries {
+        let child = join(path, name);
+        match (old.entries.get(name), node) {
+            (Some(Node::Dir(old_dir)), Node::Dir(new_dir)) => {
+                diff(old_dir, old_values, new_dir, new_values, &child, changed);
+            }
+            (Some(Node::File(old_name)), Node::File(new_name))
+                if old_name == new_name && old_values.get(old_name) == new_values.get(new_name) => {
+            }
+            _ => changed.push(child),
+        }
+    }
+    for name in old.entries.keys() {
+        if !new.entries.contains_key(name) {
+            changed.push(join(path, name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frozen(pairs: &[(&str, &str)]) -> FrozenProperties {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_layout() {
+        let tree = PropertyTree::from_frozen(frozen(&[
+            ("ro.build.version.sdk", "34"),
+            ("persist.sys", "on"),
+            ("persist.sys.tz", "UTC"),
+        ]));
+        assert_eq!(
+            tree.read_dir("").unwrap().collect::<Vec<_>>(),
+            [("persist", EntryKind::Dir), ("ro", EntryKind::Dir)]
+        );
+        assert_eq!(
+            tree.contents("/ro/build/version/sdk").as_deref(),
+            Some("34\n")
+        );
+        assert_eq!(tree.kind("ro/build/"), Some(EntryKind::Dir));
+        assert_eq!(tree.read_dir("ro/build/version/sdk").map(|_| ()), None);
+        assert_eq!(tree.value("ro/build/version"), None);
+        assert_eq!(tree.kind("ro/missing"), None);
+
+        // The prefix property moved into its directory.
+        assert_eq!(
+            tree.read_dir("persist/sys").unwrap().collect::<Vec<_>>(),
+            [(VALUE_FILE, EntryKind::File), ("tz", EntryKind::File)]
+        );
+        assert_eq!(tree.value("persist/sys/.value"), Some("on"));
+        assert_eq!(tree.property_name("persist/sys/tz"), Some("persist.sys.tz"));
+        assert_eq!(
+            tree.path_of("persist.sys").as_deref(),
+            Some("persist/sys/.value")
+        );
+        assert_eq!(
+            tree.path_of("ro.build.version.sdk").as_deref(),
+            Some("ro/build/version/sdk")
+        );
+        assert_eq!(tree.path_of("ro.build"), None);
+    }
+
+    #[test]
+    fn test_update_reports_changed_paths() {
+        let mut tree = PropertyTree::from_frozen(frozen(&[
+            ("ro.build.id", "A"),
+            ("sys.boot", "0"),
+            ("sys.mode", "x"),
+            ("vendor.gone", "1"),
+        ]));
+        let changed = tree.update(frozen(&[
+            ("ro.build.id", "A"),
+            ("sys.boot", "1"),
+            ("sys.mode", "x"),
+            ("sys.mode.extra", "y"),
+        ]));
+        assert_eq!(
+            changed,
+            ["", "sys", "sys/boot", "sys/mode", "vendor"],
+            "the root lost vendor, sys/mode became a directory"
+        );
+        assert_eq!(tree.value("sys/mode/.value"), Some("x"));
+        assert_eq!(tree.value("sys/mode/extra"), Some("y"));
+
+        assert!(tree.update(tree.snapshot().clone()).is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Process-wide interning of property names (feature `intern`), see
+//! [`intern`].
+//!
+//! A caller that enumerates every property every few seconds — a
+//! telemetry agent, say — otherwise allocates the same few thousand names
+//! on each pass. Interned names are `Arc<str>`s shared by every snapshot
+//! that holds them.
+//!
+//! The table is bounded by [`set_intern_capacity`]. When it is full, names
+//! nobody else holds any more are dropped from it; if that frees nothing,
+//! the name is returned uninterned. Like the context cache, the table sits
+//! behind a `Mutex` that is only ever `try_lock`ed: a caller that finds it
+//! busy gets an uninterned name instead of waiting, and a child forked
+//! while another thread held the lock just runs without interning.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Names kept unless [`set_intern_capacity`] says otherwise.
+pub const DEFAULT_INTERN_CAPACITY: usize = 4096;
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_INTERN_CAPACITY);
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static UNINTERNED: AtomicU64 = AtomicU64::new(0);
+
+/// Counters of the process-wide interner; see [`intern_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InternStats {
+    /// Names answered with an `Arc` already in the table.
+    pub hits: u64,
+    /// Names added to the table.
+    pub misses: u64,
+    /// Names returned uninterned: the table was full or busy.
+    pub uninterned: u64,
+    /// Names currently in the table; `0` if it was busy when asked.
+    pub len: usize,
+}
+
+fn table() -> &'static Mutex<HashSet<Arc<str>>> {
+    static TABLE: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+    TABLE.get_or_init(Default::default)
+}
+
+/// Sets how many names the interner keeps, for this process; `0` turns
+/// interning off. A smaller capacity takes effect on the next insert.
+pub fn set_intern_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+}
+
+/// The current interner capacity.
+pub fn intern_capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Returns the shared `Arc<str>` for `name`, adding it to the table if
+/// there is room. Never blocks: a caller that finds the table busy gets
+/// the name uninterned.
+pub fn intern(name: &str) -> Arc<str> {
+    let uninterned = || {
+        UNINTERNED.fetch_add(1, Ordering::Relaxed);
+        Arc::from(name)
+    };
+    let Ok(mut table) = table().try_lock() else {
+        return uninterned();
+    };
+    if let Some(interned) = table.get(name) {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return Arc::clone(interned);
+    }
+    let capacity = intern_capacity();
+    if table.len() >= capacity {
+        table.retain(|interned| Arc::strong_count(interned) > 1);
+        if table.len() >= capacity {
+            return uninterned();
+        }
+    }
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    let interned: Arc<str> = Arc::from(name);
+    table.insert(Arc::clone(&interned));
+    interned
+}
+
+/// The interner's counters since the process started.
+pub fn intern_stats() -> InternStats {
+    InternStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        uninterned: UNINTERNED.load(Ordering::Relaxed),
+        len: table().try_lock().map_or(0, |table| table.len()),
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Write-ahead journal for [`crate::SystemProperties::update`].
+//!
+//! Before an update touches the mmap, the writer records it (name, old
+//! value, new value, serial) in the journal file; once the global serial
+//! is bumped the file is truncated again. A non-empty journal therefore
+//! means the writer stopped somewhere inside an update, and
+//! [`crate::SystemProperties::enable_journal`] replays it.
+//!
+//! The journal protects against a writer *process* dying mid-update: the
+//! record is written with a plain `write`, which the kernel keeps even if
+//! the process is killed right after. It is not synced to disk — the areas
+//! themselves live on a tmpfs on Android and do not survive a power loss
+//! either.
+//!
+//! Record layout (little-endian): magic, format version, serial, the
+//! three lengths, the three byte strings, then an FNV-1a checksum over
+//! everything before it. A record that fails the checksum was torn while
+//! being written, i.e. before the update began, and is dropped.
+
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::errors::*;
+
+const MAGIC: [u8; 4] = *b"RSPJ";
+const VERSION: u32 = 1;
+/// Magic, version, serial and three lengths.
+const HEADER_LEN: usize = 4 + 5 * 4;
+/// Far above any real record (name and values are capped by the wire
+/// limits); bounds what a corrupt journal can make us allocate.
+const MAX_RECORD_LEN: usize = 64 * 1024;
+
+/// One interrupted update, as read back from the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct JournalRecord {
+    pub name: String,
+    pub old: Vec<u8>,
+    pub new: String,
+    pub serial: u32,
+}
+
+pub(crate) struct Journal {
+    file: File,
+    path: PathBuf,
+}
+
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn encode(name: &[u8], old: &[u8], new: &[u8], serial: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + name.len() + old.len() + new.len() + 4);
+    buf.extend_from_slice(&MAGIC);
+    for word in [
+        VERSION,
+        serial,
+        name.len() as u32,
+        old.len() as u32,
+        new.len() as u32,
+    ] {
+        buf.extend_from_slice(&word.to_le_bytes());
+    }
+    buf.extend_from_slice(name);
+    buf.extend_from_slice(old);
+    buf.extend_from_slice(new);
+    let checksum = fnv1a(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+/// `Ok(None)` for an empty journal; `Err` for a record that is torn or
+/// not ours. Bytes after the checksum are ignored: a failed truncate
+/// followed by a shorter record leaves the old tail behind.
+fn decode(buf: &[u8]) -> Result<Option<JournalRecord>> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    let word = |i: usize| {
+        buf.get(4 + i * 4..8 + i * 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    };
+    if buf.len() < HEADER_LEN || buf[..4] != MAGIC {
+        return Err(Error::Parse("journal record header is torn".into()));
+    }
+    if word(0) != Some(VERSION) {
+        return Err(Error::Parse(format!(
+            "unsupported journal version {:?}",
+            word(0)
+        )));
+    }
+    let serial = word(1).unwrap_or_default();
+    let lens = [word(2), word(3), word(4)].map(|w| w.unwrap_or_default() as usize);
+    let body_end = lens
+        .iter()
+        .try_fold(HEADER_LEN, |end, &len| end.checked_add(len))
+        .filter(|&end| end <= MAX_RECORD_LEN)
+        .ok_or_else(|| Error::Parse("journal record lengths are out of range".into()))?;
+    let checksum = buf
+        .get(body_end..body_end + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| Error::Parse("journal record body is torn".into()))?;
+    if fnv1a(&buf[..body_end]) != checksum {
+        return Err(Error::Parse("journal record checksum mismatch".into()));
+    }
+    let name_end = HEADER_LEN + lens[0];
+    let old_end = name_end + lens[1];
+    let text = |bytes: &[u8]| {
+        std::str::from_utf8(bytes)
+            .map(str::to_owned)
+            .map_err(|e| Error::Parse(format!("journal record is not UTF-8: {e}")))
+    };
+    Ok(Some(JournalRecord {
+        name: text(&buf[HEADER_LEN..name_end])?,
+        old: buf[name_end..old_end].to_vec(),
+        new: text(&buf[old_end..body_end])?,
+        serial,
+    }))
+}
+
+impl Journal {
+    /// Opens (creating if needed) the journal at `path` and returns the
+    /// pending record, if any. A torn record is logged and dropped.
+    pub(crate) fn open(path: &Path) -> Result<(Self, Option<JournalRecord>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)
+            .context_with_location(format!("Failed to open journal {path:?}"))?;
+        let mut buf = Vec::new();
+        file.by_ref()
+            .take(MAX_RECORD_LEN as u64 + 4)
+            .read_to_end(&mut buf)
+            .context_with_location(format!("Failed to read journal {path:?}"))?;
+        let pending = decode(&buf).unwrap_or_else(|e| {
+            log::warn!("Dropping journal record in {path:?}: {e}");
+            None
+        });
+        Ok((
+            Self {
+                file,
+                path: path.to_path_buf(),
+            },
+            pending,
+        ))
+    }
+
+    /// Records an update about to be applied. Must complete before the
+    /// mmap is touched.
+    pub(crate) fn begin(&self, name: &[u8], old: &[u8], new: &str, serial: u32) -> Result<()> {
+        self.file
+            .write_all_at(&encode(name, old, new.as_bytes(), serial), 0)
+            .context_with_location(format!("Failed to write journal {:?}", self.path))
+    }
+
+    /// Marks the recorded update as finished.
+    pub(crate) fn commit(&self) -> Result<()> {
+        self.file
+            .set_len(0)
+            .context_with_location(format!("Failed to truncate journal {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let buf = encode(b"test.prop", b"old", b"new value", 0x0300_0004);
+        assert_eq!(
+            decode(&buf).unwrap(),
+            Some(JournalRecord {
+                name: "test.prop".into(),
+                old: b"old".to_vec(),
+                new: "new value".into(),
+                serial: 0x0300_0004,
+            })
+        );
+        assert_eq!(decode(&[]).unwrap(), None);
+
+        // A stale tail after a shorter record is ignored.
+        let mut with_tail = encode(b"a", b"", b"b", 2);
+        with_tail.extend_from_slice(&buf);
+        assert_eq!(decode(&with_tail).unwrap().unwrap().name, "a");
+    }
+
+    #[test]
+    fn test_torn_records_are_rejected() {
+        let buf = encode(b"test.prop", b"old", b"new", 2);
+        for len in 1..buf.len() {
+            assert!(decode(&buf[..len]).is_err(), "prefix of {len} bytes");
+        }
+        let mut flipped = buf.clone();
+        flipped[HEADER_LEN] ^= 1;
+        assert!(decode(&flipped).is_err());
+    }
+}
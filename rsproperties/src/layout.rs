@@ -0,0 +1,255 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-disk layout of a properties directory.
+//!
+//! Readers (`SystemProperties::new`) and the writer
+//! (`SystemProperties::new_area_with_layout`) resolve every bookkeeping
+//! file through the same [`Layout`], so the two sides cannot disagree on
+//! where the trie or the serial area lives. The default is AOSP's layout;
+//! an alternative (e.g. one flat directory per container, with its own
+//! serial label) only needs a different `Layout` on both sides.
+//!
+//! The layout also carries the one naming rule both sides must share:
+//! whether property names are case-insensitive (see
+//! [`Layout::case_insensitive`]), and the size the writer gives each
+//! context area (see [`Layout::area_size`]), which readers take from the
+//! files instead.
+//!
+//! What is *not* configurable: per-context area files are always named
+//! after their SELinux context (that name comes from `property_info`),
+//! and the writer's single-instance lock is always [`WRITER_LOCK_FILENAME`].
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use crate::errors::*;
+use crate::SelinuxContext;
+
+/// Name of the writer's `flock` file inside the properties directory.
+pub(crate) const WRITER_LOCK_FILENAME: &str = ".writer_lock";
+
+/// bionic's `PA_SIZE`: the size of every area init creates.
+pub const DEFAULT_AREA_SIZE: usize = 128 * 1024;
+
+/// Area sizes are whole pages, so an area never shares its last page with
+/// anything else mapped.
+const AREA_SIZE_GRANULE: usize = 4096;
+
+/// Filenames and labels of the bookkeeping files in a properties
+/// directory. Readers (`SystemProperties::new`) and the writer
+/// (`SystemProperties::new_area_with_layout`) resolve every bookkeeping
+/// file through the same `Layout`, so the two sides cannot disagree on
+/// where the trie or the serial area lives.
+///
+/// `#[non_exhaustive]` like [`crate::PropertyConfig`]: start from
+/// [`Layout::default`] (AOSP) and override with the `with_*` methods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Layout {
+    /// Serialized name → context trie (AOSP: `property_info`).
+    pub property_info_filename: String,
+    /// Global serial area bumped on every change (AOSP:
+    /// `properties_serial`).
+    pub serial_filename: String,
+    /// SELinux context the writer labels the serial area with (AOSP:
+    /// `u:object_r:properties_serial:s0`).
+    pub serial_context: String,
+    /// Property names are ASCII-case-insensitive: the writer stores them
+    /// lowercased and readers lowercase a name before looking it up, so
+    /// `Vendor.Mode` and `vendor.mode` are one property. Off by default
+    /// (AOSP names are case-sensitive).
+    ///
+    /// The `property_info` prefixes are matched against the lowercased
+    /// name, so they must be lowercase themselves. A writer refuses a name
+    /// that folds onto one it already wrote under a different spelling
+    /// (see [`crate::SystemProperties::set`]).
+    pub case_insensitive: bool,
+    /// Size in bytes of each per-context area file the writer creates
+    /// (AOSP: 128 KiB, [`DEFAULT_AREA_SIZE`]). An area does not grow: once
+    /// its properties fill it, adding another to its context fails with
+    /// [`Error::AreaFull`], so a store loaded from a large vendor
+    /// build.prop set needs a bigger one.
+    ///
+    /// Readers — including bionic's — map an area at the size of its
+    /// file, so they need not agree on this. It must be a multiple of
+    /// 4096 that fits area offsets (`u32`). The serial area is not
+    /// affected; it only holds serials.
+    pub area_size: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            property_info_filename: "property_info".to_owned(),
+            serial_filename: "properties_serial".to_owned(),
+            serial_context: "u:object_r:properties_serial:s0".to_owned(),
+            case_insensitive: false,
+            area_size: DEFAULT_AREA_SIZE,
+        }
+    }
+}
+
+impl Layout {
+    /// Overrides the trie filename.
+    pub fn with_property_info_filename<S: Into<String>>(mut self, name: S) -> Self {
+        self.property_info_filename = name.into();
+        self
+    }
+
+    /// Overrides the serial area filename.
+    pub fn with_serial_filename<S: Into<String>>(mut self, name: S) -> Self {
+        self.serial_filename = name.into();
+        self
+    }
+
+    /// Overrides the serial area's SELinux label.
+    pub fn with_serial_context<S: Into<String>>(mut self, context: S) -> Self {
+        self.serial_context = context.into();
+        self
+    }
+
+    /// Makes property names case-insensitive, see
+    /// [`Self::case_insensitive`].
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Overrides the size of the context areas, see [`Self::area_size`].
+    pub fn with_area_size(mut self, area_size: usize) -> Self {
+        self.area_size = area_size;
+        self
+    }
+
+    /// Path of the trie file inside `dir`.
+    pub fn property_info_path(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.property_info_filename)
+    }
+
+    /// Path of the serial area inside `dir`.
+    pub fn serial_path(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.serial_filename)
+    }
+
+    /// Checks that both filenames are plain, distinct, non-reserved names,
+    /// that the serial context is a valid [`SelinuxContext`] and that the
+    /// area size is usable.
+    ///
+    /// Run by every open path before a file is touched: the writer unlinks
+    /// and recreates the serial area, so a name with a `/` (or one equal
+    /// to the trie's) would let a misconfigured layout destroy a file
+    /// outside the area's bookkeeping.
+    pub fn validate(&self) -> Result<()> {
+        for (what, name) in [
+            ("property_info filename", &self.property_info_filename),
+            ("serial filename", &self.serial_filename),
+        ] {
+            if !is_plain_filename(name) {
+                return Err(Error::InvalidArgument(format!(
+                    "layout {what} {name:?} is not a plain filename"
+                )));
+            }
+            if name.eq_ignore_ascii_case(WRITER_LOCK_FILENAME) {
+                return Err(Error::InvalidArgument(format!(
+                    "layout {what} {name:?} collides with the writer lock"
+                )));
+            }
+        }
+        // Compared case-folded for the same reason context names are: a
+        // case-insensitive filesystem would alias the two onto one file.
+        if self
+            .property_info_filename
+            .eq_ignore_ascii_case(&self.serial_filename)
+        {
+            return Err(Error::InvalidArgument(format!(
+                "layout property_info and serial filenames collide: {:?}",
+                self.serial_filename
+            )));
+        }
+        if self.area_size == 0
+            || self.area_size % AREA_SIZE_GRANULE != 0
+            || u32::try_from(self.area_size).is_err()
+        {
+            return Err(Error::InvalidArgument(format!(
+                "layout area size {} is not a non-zero multiple of {AREA_SIZE_GRANULE} below 4 GiB",
+                self.area_size
+            )));
+        }
+        self.serial_selinux_context().map(drop)
+    }
+
+    /// `serial_context`, parsed for the xattr call.
+    pub(crate) fn serial_selinux_context(&self) -> Result<SelinuxContext> {
+        SelinuxContext::new(self.serial_context.as_str())
+    }
+
+    /// Whether `name` (ASCII-case-folded) is one of the files this layout
+    /// reserves — a context with that name would clobber it.
+    pub(crate) fn is_reserved(&self, name: &str) -> bool {
+        [
+            WRITER_LOCK_FILENAME,
+            self.property_info_filename.as_str(),
+            self.serial_filename.as_str(),
+        ]
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+    }
+}
+
+/// `name` ASCII-lowercased, borrowed when it has no uppercase letter.
+pub(crate) fn fold_case(name: &str) -> Cow<'_, str> {
+    if name.bytes().any(|b| b.is_ascii_uppercase()) {
+        Cow::Owned(name.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Non-empty, single normal path component — no separators, `.`/`..`.
+fn is_plain_filename(name: &str) -> bool {
+    use std::path::Component;
+    let mut components = Path::new(name).components();
+    !name.contains('/')
+        && matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_aosp() {
+        let layout = Layout::default();
+        layout.validate().unwrap();
+        assert_eq!(
+            layout.property_info_path(Path::new("/dev/__properties__")),
+            Path::new("/dev/__properties__/property_info")
+        );
+        assert!(layout.is_reserved("PROPERTIES_SERIAL"));
+        assert!(layout.is_reserved(".writer_lock"));
+        assert!(!layout.is_reserved("u:object_r:build_prop:s0"));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_layouts() {
+        for layout in [
+            Layout::default().with_serial_filename("../serial"),
+            Layout::default().with_serial_filename(""),
+            Layout::default().with_property_info_filename("."),
+            Layout::default().with_serial_filename("Property_Info"),
+            Layout::default().with_serial_filename(".writer_lock"),
+            Layout::default().with_serial_context("a\0b"),
+            Layout::default().with_area_size(0),
+            Layout::default().with_area_size(DEFAULT_AREA_SIZE + 1),
+        ] {
+            assert!(
+                matches!(layout.validate(), Err(Error::InvalidArgument(_))),
+                "{layout:?}"
+            );
+        }
+    }
+}
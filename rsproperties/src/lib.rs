@@ -13,6 +13,13 @@
 //! - Serialize/deserialize the property-info trie (requires the `builder`
 //!   feature).
 //!
+//! ## Fork safety
+//!
+//! The global state may be used on both sides of a `fork()`; no lock or
+//! lazy initializer held by a parent thread at fork time can deadlock the
+//! child. A child that outlives its parent's view of the property
+//! directory should call [`at_fork_child`] first.
+//!
 //! ## Usage
 //!
 //! ```rust,no_run
@@ -22,6 +29,7 @@
 //!
 //! // Set a value of the property - use string literals for compatibility
 //! // with values other Android components write (e.g. "1", not "true").
+//! # #[cfg(feature = "service-protocol")]
 //! rsproperties::set("test.property", "test.value").unwrap();
 //! ```
 
@@ -33,6 +41,7 @@
 
 use std::{
     path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
     sync::OnceLock,
 };
 
@@ -49,6 +58,19 @@ pub struct PropertyConfig {
     pub properties_dir: Option<PathBuf>,
     /// Directory for property service sockets (default: "/dev/socket")
     pub socket_dir: Option<PathBuf>,
+    /// Bookkeeping-file layout of the properties directory (default:
+    /// AOSP's, see [`Layout`]). Must match the layout the writer used.
+    pub layout: Option<Layout>,
+    /// Where the property store lives (default: [`Backing::Files`]). With
+    /// [`Backing::Memfd`] the properties directory is not used.
+    pub backing: Option<Backing>,
+    /// Whether writers refuse to create properties no `property_contexts`
+    /// entry declares (default: `false`), see
+    /// [`PropertyConfig::require_declared`].
+    pub require_declared: Option<bool>,
+    /// Name prefixes whose properties are write-once (default: `ro.`
+    /// only), see [`PropertyConfig::read_only_prefixes`].
+    pub read_only_prefixes: Option<Vec<String>>,
 }
 
 // Implement From traits for backward compatibility and convenience
@@ -57,6 +79,10 @@ impl From<PathBuf> for PropertyConfig {
         Self {
             properties_dir: Some(path),
             socket_dir: None,
+            layout: None,
+            backing: None,
+            require_declared: None,
+            read_only_prefixes: None,
         }
     }
 }
@@ -66,6 +92,10 @@ impl From<String> for PropertyConfig {
         Self {
             properties_dir: Some(PathBuf::from(path)),
             socket_dir: None,
+            layout: None,
+            backing: None,
+            require_declared: None,
+            read_only_prefixes: None,
         }
     }
 }
@@ -75,6 +105,10 @@ impl From<&str> for PropertyConfig {
         Self {
             properties_dir: Some(PathBuf::from(path)),
             socket_dir: None,
+            layout: None,
+            backing: None,
+            require_declared: None,
+            read_only_prefixes: None,
         }
     }
 }
@@ -85,6 +119,10 @@ impl PropertyConfig {
         Self {
             properties_dir: Some(dir.into()),
             socket_dir: None,
+            layout: None,
+            backing: None,
+            require_declared: None,
+            read_only_prefixes: None,
         }
     }
 
@@ -93,6 +131,10 @@ impl PropertyConfig {
         Self {
             properties_dir: None,
             socket_dir: Some(dir.into()),
+            layout: None,
+            backing: None,
+            require_declared: None,
+            read_only_prefixes: None,
         }
     }
 
@@ -104,9 +146,40 @@ impl PropertyConfig {
         Self {
             properties_dir: Some(properties_dir.into()),
             socket_dir: Some(socket_dir.into()),
+            layout: None,
+            backing: None,
+            require_declared: None,
+            read_only_prefixes: None,
         }
     }
 
+    /// Strict mode: with `true`, writers created afterwards in this process
+    /// — `SystemProperties::add`, and through it `set` and the property
+    /// service — refuse a new property whose name only the trie's default
+    /// context covers, with [`Error::Undeclared`], instead of storing it in
+    /// the default area. Production Android declares every property in
+    /// `property_contexts`; this catches typos and stray names early.
+    pub fn require_declared(mut self, require: bool) -> Self {
+        self.require_declared = Some(require);
+        self
+    }
+
+    /// Makes properties under these name prefixes write-once, like `ro.`
+    /// ones: writers created afterwards in this process — and through them
+    /// the property service — store their first value and refuse to change
+    /// it with [`Error::PermissionDenied`] (`PROP_ERROR_READ_ONLY_PROPERTY`
+    /// on the wire). `ro.` is always part of the list. The owning service
+    /// can still rewrite them inside
+    /// `SystemProperties::override_read_only`.
+    pub fn read_only_prefixes<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.read_only_prefixes = Some(with_ro_prefix(prefixes));
+        self
+    }
+
     /// Create a new builder for PropertyConfig
     pub fn builder() -> PropertyConfigBuilder {
         PropertyConfigBuilder::default()
@@ -122,6 +195,10 @@ impl PropertyConfig {
 pub struct PropertyConfigBuilder {
     properties_dir: Option<PathBuf>,
     socket_dir: Option<PathBuf>,
+    layout: Option<Layout>,
+    backing: Option<Backing>,
+    require_declared: Option<bool>,
+    read_only_prefixes: Option<Vec<String>>,
 }
 
 impl PropertyConfigBuilder {
@@ -137,85 +214,316 @@ impl PropertyConfigBuilder {
         self
     }
 
+    /// Set the properties directory layout
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Set where the property store lives
+    pub fn backing(mut self, backing: Backing) -> Self {
+        self.backing = Some(backing);
+        self
+    }
+
+    /// Set whether writers refuse undeclared properties, see
+    /// [`PropertyConfig::require_declared`]
+    pub fn require_declared(mut self, require: bool) -> Self {
+        self.require_declared = Some(require);
+        self
+    }
+
+    /// Set the write-once name prefixes, see
+    /// [`PropertyConfig::read_only_prefixes`]
+    pub fn read_only_prefixes<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.read_only_prefixes = Some(with_ro_prefix(prefixes));
+        self
+    }
+
     /// Build the PropertyConfig
     pub fn build(self) -> PropertyConfig {
         PropertyConfig {
             properties_dir: self.properties_dir,
             socket_dir: self.socket_dir,
+            layout: self.layout,
+            backing: self.backing,
+            require_declared: self.require_declared,
+            read_only_prefixes: self.read_only_prefixes,
+        }
+    }
+}
+
+/// `prefixes` as a list that starts with `ro.`, without duplicates.
+pub(crate) fn with_ro_prefix<I, S>(prefixes: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut list = vec![READ_ONLY_PREFIX.to_owned()];
+    for prefix in prefixes {
+        let prefix = prefix.into();
+        if !list.contains(&prefix) {
+            list.push(prefix);
         }
     }
+    list
 }
 
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod backend;
 pub mod errors;
+#[cfg(any(
+    all(test, feature = "builder", not(target_os = "android")),
+    feature = "test-utils"
+))]
+mod exit_cleanup;
+pub mod fs_view;
+pub mod migrate;
+pub mod mirror;
+pub mod prelude;
+#[cfg(all(feature = "writer", feature = "info-builder"))]
+pub mod quickstart;
+#[cfg(feature = "test-utils")]
+pub mod test_support;
 pub mod wire;
-pub use errors::{ContextWithLocation, Error, Result};
+pub use errors::{ContextWithLocation, Error, Result, SetError, SetErrorKind};
 
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
+mod area_migration;
+#[cfg(feature = "parser")]
 mod build_property_parser;
+mod bytes_value;
+mod cached_property;
+mod checksum;
+mod client;
+#[cfg(feature = "service-protocol")]
+mod coalesce;
+mod compat;
+mod config_binder;
+mod context_cache;
 mod context_node;
 mod contexts_serialized;
 mod file_validation;
+mod frozen;
+#[cfg(feature = "intern")]
+mod intern;
+#[cfg(feature = "writer")]
+mod journal;
+mod layout;
+#[cfg(any(feature = "parser", feature = "info-builder"))]
+mod line_reader;
+mod lock_order;
+mod lookup_stats;
+mod memfd_area;
+mod prefix_registry;
+#[cfg(feature = "parser")]
+mod prop_file_editor;
 mod property_area;
 mod property_info;
 mod property_info_parser;
-#[cfg(feature = "builder")]
+#[cfg(feature = "info-builder")]
 mod property_info_serializer;
+mod read_policy;
+#[cfg(feature = "info-builder")]
+mod schema;
+#[cfg(feature = "writer")]
+mod scratch;
+mod selinux_context;
+mod service_socket;
+mod snapshot;
 mod system_properties;
+#[cfg(feature = "service-protocol")]
 mod system_property_set;
-#[cfg(feature = "builder")]
+#[cfg(feature = "info-builder")]
 mod trie_builder;
-#[cfg(feature = "builder")]
+#[cfg(feature = "info-builder")]
 mod trie_node_arena;
-#[cfg(feature = "builder")]
+#[cfg(feature = "info-builder")]
 mod trie_serializer;
+mod typed_value;
+mod wait_stats;
 
 // Explicit re-export lists (not globs) so the public API surface is
 // visible here and additions to the modules don't silently become public.
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
+pub use area_migration::{
+    migrate_area_dir, migrate_area_dir_with_layout, AreaFormat, AreaMigrationReport,
+};
+#[cfg(feature = "parser")]
 pub use build_property_parser::load_properties_from_file;
-#[cfg(feature = "builder")]
-pub use property_info_serializer::{build_trie, PropertyInfoEntry};
-pub use system_properties::SystemProperties;
-pub use system_property_set::socket_dir;
+pub use bytes_value::PROP_BYTES_MAX;
+pub use cached_property::CachedProperty;
+pub use client::PropertiesClient;
+#[cfg(feature = "service-protocol")]
+pub use coalesce::CoalescingSetter;
+pub use compat::AndroidSystemProperties;
+pub use config_binder::{ConfigBinder, ConfigUpdate};
+pub use context_cache::{
+    context_cache_capacity, set_context_cache_capacity, ContextCacheStats,
+    DEFAULT_CONTEXT_CACHE_CAPACITY,
+};
+pub use frozen::FrozenProperties;
+#[cfg(feature = "intern")]
+pub use intern::{
+    intern, intern_capacity, intern_stats, set_intern_capacity, InternStats,
+    DEFAULT_INTERN_CAPACITY,
+};
+pub use layout::{Layout, DEFAULT_AREA_SIZE};
+pub use lookup_stats::{enable_lookup_stats, lookup_stats, reset_lookup_stats, LookupStats};
+pub use memfd_area::Backing;
+#[cfg(feature = "service-protocol")]
+pub use prefix_registry::publish_prefix_claims;
+pub use prefix_registry::{prefix_claims, reserve_prefix, PrefixClaim, PREFIX_CLAIMS_PROPERTY};
+#[cfg(feature = "parser")]
+pub use prop_file_editor::PropFileEditor;
+#[cfg(feature = "info-builder")]
+pub use property_info_serializer::{
+    build_trie, build_trie_with_stats, merge_tries, PropertyInfoEntry, TrieBuildStats,
+};
+pub use read_policy::{read_policy, set_read_policy, ReadPolicy};
+#[cfg(feature = "info-builder")]
+pub use schema::{
+    apply_requirements, export_schema, PropertyRequirement, RequirementReport, SchemaFormat,
+};
+#[cfg(feature = "writer")]
+pub use scratch::ScratchProperties;
+pub use selinux_context::SelinuxContext;
+pub use snapshot::{PropertySnapshot, SnapshotEntry};
+pub use system_properties::{
+    AreaFragmentation, AreaState, DebugState, FragmentationReport, GetpropColumn,
+    PropertyDescriptor, PropertyEntry, PropertyHandle, PropertyWatcher, ScrubReport,
+    SystemProperties, WatchId,
+};
+pub use typed_value::{ByteSize, HostPort, ParseValueError, PropertyDuration};
+pub use wait_stats::{
+    enable_wait_stats, reset_wait_stats, wait_stats, WaitStats, WAIT_STATS_BUCKETS,
+};
 
 /// Timeout type accepted by [`SystemProperties::wait`], re-exported so
 /// callers don't need a direct dependency on the exact `rustix` version
 /// this crate was built against.
 pub use rustix::fs::Timespec;
 
+pub use service_socket::{
+    socket_dir, PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME, PROPERTY_SERVICE_SOCKET_NAME,
+    SOCKET_DIR_ENV,
+};
+#[cfg(feature = "service-protocol")]
 pub use system_property_set::{
-    PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME, PROPERTY_SERVICE_SOCKET_NAME,
+    service_status, wait_for_service, ServiceStatus, SERVICE_READY_PROPERTY,
 };
 
 // Re-export (not a second definition): `wire::PROP_VALUE_MAX` is the single
 // source of truth — an independent constant here could drift and desync the
 // seqlock read buffer size from the area's reserved slot size.
-pub use wire::PROP_VALUE_MAX;
+pub use wire::{value_max, PROP_VALUE_MAX};
+// Likewise for names. `PROP_NAME_MAX` only bounds V1 wire frames; for what
+// a name may look like everywhere else, use `is_valid_property_name`.
+pub use wire::{is_valid_property_name, NamePolicy, PropertyName, PROP_NAME_MAX};
 pub const PROP_DIRNAME: &str = "/dev/__properties__";
 
 // System properties directory.
 static SYSTEM_PROPERTIES_DIR: OnceLock<PathBuf> = OnceLock::new();
 
+// Layout of the system properties directory. Latched together with the
+// directory it describes.
+static SYSTEM_PROPERTIES_LAYOUT: OnceLock<Layout> = OnceLock::new();
+
+// Backing of the global property store, latched like the layout.
+static SYSTEM_PROPERTIES_BACKING: OnceLock<Backing> = OnceLock::new();
+
+// Strict mode for writers, latched like the backing.
+static SYSTEM_PROPERTIES_REQUIRE_DECLARED: OnceLock<bool> = OnceLock::new();
+
+// Write-once name prefixes for writers, latched like the strict mode.
+static SYSTEM_PROPERTIES_READ_ONLY_PREFIXES: OnceLock<Vec<String>> = OnceLock::new();
+
+/// The prefix of Android's write-once properties, always read-only.
+pub(crate) const READ_ONLY_PREFIX: &str = "ro.";
+
 /// Serializes every commit to the first-write-wins directory cells
-/// (`SYSTEM_PROPERTIES_DIR` here and `SOCKET_DIR` in `system_property_set`).
+/// (`SYSTEM_PROPERTIES_DIR` / `SYSTEM_PROPERTIES_LAYOUT` /
+/// `SYSTEM_PROPERTIES_BACKING` / `SYSTEM_PROPERTIES_REQUIRE_DECLARED` /
+/// `SYSTEM_PROPERTIES_READ_ONLY_PREFIXES` here and `SOCKET_DIR` in
+/// `service_socket`).
 /// `try_init` must make its pre-check + set atomic against both concurrent
 /// inits and the implicit env/default latch performed by the first call to
 /// `properties_dir()` / `socket_dir()` — otherwise a lost race after the
 /// pre-check leaves the globals half-applied with no way to roll back a
 /// committed `OnceLock`. Read fast paths (`OnceLock::get`) stay lock-free.
 ///
-/// Private on purpose: all access goes through [`lock_global_dirs`] so no
-/// call site can bypass its poison recovery.
-static GLOBAL_DIRS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+/// Private on purpose: all access goes through [`lock_global_dirs`].
+static GLOBAL_DIRS_LOCK: GlobalDirsLock = GlobalDirsLock {
+    owner: AtomicU32::new(0),
+};
+
+/// A spin lock tagged with the PID of its holder, instead of a
+/// `std::sync::Mutex`, so it survives `fork()`: if another thread held it
+/// when the process forked, that thread does not exist in the child and a
+/// `Mutex` would stay locked forever. Here the child sees a foreign PID
+/// and takes the lock over. Every critical section is a handful of
+/// `OnceLock` checks, so spinning (with `yield_now`) costs nothing.
+struct GlobalDirsLock {
+    /// PID of the holder, `0` when free.
+    owner: AtomicU32,
+}
+
+pub(crate) struct GlobalDirsGuard {
+    lock: &'static GlobalDirsLock,
+    _order: lock_order::Held,
+}
+
+impl Drop for GlobalDirsGuard {
+    fn drop(&mut self) {
+        self.lock.owner.store(0, Ordering::Release);
+    }
+}
+
+impl GlobalDirsLock {
+    fn lock(&'static self) -> GlobalDirsGuard {
+        // Before spinning: taking it again on the holding thread would
+        // spin forever, the tracker panics instead.
+        let order = lock_order::acquire("global dirs", self);
+        let guard = |lock| GlobalDirsGuard {
+            lock,
+            _order: order,
+        };
+        let pid = std::process::id();
+        loop {
+            match self
+                .owner
+                .compare_exchange(0, pid, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return guard(self),
+                // Held by a thread of the parent process we were forked from.
+                Err(holder) if holder != pid => {
+                    if self
+                        .owner
+                        .compare_exchange(holder, pid, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        log::warn!("Took over the global directory lock held across fork()");
+                        return guard(self);
+                    }
+                }
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+    }
+}
 
-/// Acquire `GLOBAL_DIRS_LOCK`, recovering from poison: the lock only guards
-/// the check-then-set ordering of `OnceLock` cells, each of which is
-/// internally consistent even if a holder panicked mid-sequence.
-pub(crate) fn lock_global_dirs() -> std::sync::MutexGuard<'static, ()> {
-    GLOBAL_DIRS_LOCK
-        .lock()
-        .unwrap_or_else(std::sync::PoisonError::into_inner)
+/// Acquire `GLOBAL_DIRS_LOCK`. A holder that panicked releases it on
+/// unwind; the `OnceLock` cells it guards are internally consistent either
+/// way, so there is no poisoning.
+pub(crate) fn lock_global_dirs() -> GlobalDirsGuard {
+    GLOBAL_DIRS_LOCK.lock()
 }
+
 // Global system properties. Stores Result so initialization failure does not
 // poison the OnceLock and callers can observe the error. The error side is
 // `Arc<Error>` because the cache can only hand out references while callers
@@ -278,9 +586,42 @@ pub fn try_init(config: PropertyConfig) -> Result<()> {
                 .into(),
         ));
     }
-    if config.socket_dir.is_some() && system_property_set::socket_dir_is_set() {
+    if config.socket_dir.is_some() && service_socket::socket_dir_is_set() {
         return Err(Error::AlreadyInitialized("socket directory".into()));
     }
+    if let Some(layout) = &config.layout {
+        // Validated here, not at first read: a bad layout must not be
+        // latched, and this is the only point the caller sees an error.
+        layout.validate()?;
+        if SYSTEM_PROPERTIES_LAYOUT.get().is_some() {
+            return Err(Error::AlreadyInitialized(
+                "properties directory layout \
+                 (explicitly via init() or implicitly by a prior property read)"
+                    .into(),
+            ));
+        }
+    }
+    if config.backing.is_some() && SYSTEM_PROPERTIES_BACKING.get().is_some() {
+        return Err(Error::AlreadyInitialized(
+            "property store backing \
+             (explicitly via init() or implicitly by a prior property read)"
+                .into(),
+        ));
+    }
+    if config.require_declared.is_some() && SYSTEM_PROPERTIES_REQUIRE_DECLARED.get().is_some() {
+        return Err(Error::AlreadyInitialized(
+            "undeclared-property policy \
+             (explicitly via init() or implicitly by a prior writer)"
+                .into(),
+        ));
+    }
+    if config.read_only_prefixes.is_some() && SYSTEM_PROPERTIES_READ_ONLY_PREFIXES.get().is_some() {
+        return Err(Error::AlreadyInitialized(
+            "read-only prefixes \
+             (explicitly via init() or implicitly by a prior writer)"
+                .into(),
+        ));
+    }
 
     if let Some(props_dir) = config.properties_dir {
         log::info!("Setting system properties directory to: {props_dir:?}");
@@ -289,8 +630,36 @@ pub fn try_init(config: PropertyConfig) -> Result<()> {
             .map_err(|_| Error::AlreadyInitialized("system properties directory".into()))?;
     }
 
+    if let Some(layout) = config.layout {
+        log::info!("Setting properties directory layout to: {layout:?}");
+        SYSTEM_PROPERTIES_LAYOUT
+            .set(layout)
+            .map_err(|_| Error::AlreadyInitialized("properties directory layout".into()))?;
+    }
+
+    if let Some(backing) = config.backing {
+        log::info!("Setting property store backing to: {backing:?}");
+        SYSTEM_PROPERTIES_BACKING
+            .set(backing)
+            .map_err(|_| Error::AlreadyInitialized("property store backing".into()))?;
+    }
+
+    if let Some(require) = config.require_declared {
+        log::info!("Setting require_declared to: {require}");
+        SYSTEM_PROPERTIES_REQUIRE_DECLARED
+            .set(require)
+            .map_err(|_| Error::AlreadyInitialized("undeclared-property policy".into()))?;
+    }
+
+    if let Some(prefixes) = config.read_only_prefixes {
+        log::info!("Setting read-only prefixes to: {prefixes:?}");
+        SYSTEM_PROPERTIES_READ_ONLY_PREFIXES
+            .set(prefixes)
+            .map_err(|_| Error::AlreadyInitialized("read-only prefixes".into()))?;
+    }
+
     if let Some(socket_dir) = config.socket_dir {
-        if !system_property_set::set_socket_dir(&socket_dir) {
+        if !service_socket::set_socket_dir(&socket_dir) {
             // Unreachable while every committer honors `GLOBAL_DIRS_LOCK`
             // (pre-check and set are atomic under the guard above); kept as
             // defense in depth because a `OnceLock` cannot be un-set.
@@ -326,6 +695,56 @@ pub fn properties_dir() -> &'static Path {
         .as_path()
 }
 
+/// The old name of [`properties_dir`].
+#[deprecated(since = "0.7.0", note = "use `properties_dir`")]
+pub fn dirname() -> &'static Path {
+    properties_dir()
+}
+
+/// Get the layout of the system properties directory: the one passed to
+/// `init()`, otherwise AOSP's default [`Layout`]. Latched on first use,
+/// like [`properties_dir`].
+pub fn layout() -> &'static Layout {
+    if let Some(layout) = SYSTEM_PROPERTIES_LAYOUT.get() {
+        return layout;
+    }
+    let _guard = lock_global_dirs();
+    SYSTEM_PROPERTIES_LAYOUT.get_or_init(Layout::default)
+}
+
+/// Get where the global property store lives: the backing passed to
+/// `init()`, otherwise [`Backing::Files`]. Latched on first use, like
+/// [`layout()`].
+pub fn backing() -> Backing {
+    if let Some(backing) = SYSTEM_PROPERTIES_BACKING.get() {
+        return *backing;
+    }
+    let _guard = lock_global_dirs();
+    *SYSTEM_PROPERTIES_BACKING.get_or_init(Backing::default)
+}
+
+/// Whether writers refuse properties `property_contexts` does not declare:
+/// the value passed to `init()`, otherwise `false`. Latched on first use,
+/// like [`backing`] — writers read it when they are created.
+pub fn require_declared() -> bool {
+    if let Some(require) = SYSTEM_PROPERTIES_REQUIRE_DECLARED.get() {
+        return *require;
+    }
+    let _guard = lock_global_dirs();
+    *SYSTEM_PROPERTIES_REQUIRE_DECLARED.get_or_init(|| false)
+}
+
+/// Name prefixes whose properties writers keep write-once: the list
+/// passed to `init()`, otherwise just `ro.`. Latched on first use, like
+/// [`require_declared`].
+pub fn read_only_prefixes() -> &'static [String] {
+    if let Some(prefixes) = SYSTEM_PROPERTIES_READ_ONLY_PREFIXES.get() {
+        return prefixes;
+    }
+    let _guard = lock_global_dirs();
+    SYSTEM_PROPERTIES_READ_ONLY_PREFIXES.get_or_init(|| vec![READ_ONLY_PREFIX.to_owned()])
+}
+
 /// The cached global instance, or `None` when it has not been initialized
 /// yet or initialization failed. Never *triggers* initialization — used by
 /// call sites (e.g. the wire-protocol version probe in
@@ -344,19 +763,32 @@ pub(crate) fn system_properties_if_initialized(
 /// is latched for the process lifetime, so a property store that becomes
 /// available later (e.g. `/dev/__properties__` mounted after this process
 /// started) is not picked up. Early-boot callers should defer their first
-/// property access until the store is ready.
+/// property access until the store is ready. The exception is a
+/// [`Backing::Memfd`] store whose property service cannot be reached yet:
+/// that error is returned without being cached.
 pub fn try_system_properties() -> Result<&'static system_properties::SystemProperties> {
-    SYSTEM_PROPERTIES
-        .get_or_init(|| {
-            let dir = properties_dir();
-            log::debug!("Initializing global SystemProperties instance from: {dir:?}");
-
-            system_properties::SystemProperties::new(dir)
-                .inspect_err(|e| {
+    // Built outside the `OnceLock` and then published, instead of inside
+    // `get_or_init`: a `fork()` while another thread is mid-initializer
+    // would leave the cell "running" forever in the child, and opening the
+    // directory is slow enough for that to happen. Racing threads each
+    // build an instance; the losers' are dropped (they are read-only
+    // mappings of the same files, so the results agree).
+    if SYSTEM_PROPERTIES.get().is_none() {
+        let props = match backing() {
+            Backing::Files => {
+                let dir = properties_dir();
+                log::debug!("Initializing global SystemProperties instance from: {dir:?}");
+                system_properties::SystemProperties::new(dir, layout()).inspect_err(|e| {
                     log::error!("Failed to initialize SystemProperties from {dir:?}: {e}");
                 })
-                .map_err(std::sync::Arc::new)
-        })
+            }
+            Backing::Memfd => open_memfd_store(socket_dir(), layout())?,
+        };
+        let _ = SYSTEM_PROPERTIES.set(props.map_err(std::sync::Arc::new));
+    }
+    SYSTEM_PROPERTIES
+        .get()
+        .expect("SYSTEM_PROPERTIES published above")
         .as_ref()
         // `Error::Init` shares the cached original, so both the original
         // variant (via `source()` downcast) and the full error chain stay
@@ -364,6 +796,52 @@ pub fn try_system_properties() -> Result<&'static system_properties::SystemPrope
         .map_err(|e| Error::Init(std::sync::Arc::clone(e)))
 }
 
+/// Fetches the memfds of the global store from the property service. A
+/// service that cannot be reached is the outer error and is not latched,
+/// since the service may simply not be up yet; the inner result is.
+#[cfg(all(
+    feature = "service-protocol",
+    any(target_os = "linux", target_os = "android")
+))]
+fn open_memfd_store(
+    socket_dir: &Path,
+    layout: &Layout,
+) -> Result<Result<system_properties::SystemProperties>> {
+    log::debug!("Initializing global SystemProperties instance from the property service memfds");
+    let areas = system_property_set::fetch_memfd_areas(socket_dir)
+        .inspect_err(|e| log::error!("Failed to fetch memfd areas: {e}"))?;
+    Ok(
+        system_properties::SystemProperties::from_memfd_areas(areas, layout).inspect_err(|e| {
+            log::error!("Failed to map the memfd areas: {e}");
+        }),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn open_memfd_store(
+    _socket_dir: &Path,
+    _layout: &Layout,
+) -> Result<Result<system_properties::SystemProperties>> {
+    Ok(Err(Error::InvalidArgument(
+        "memfd-backed property stores are only supported on Linux and Android".into(),
+    )))
+}
+
+#[cfg(all(
+    not(feature = "service-protocol"),
+    any(target_os = "linux", target_os = "android")
+))]
+fn open_memfd_store(
+    _socket_dir: &Path,
+    _layout: &Layout,
+) -> Result<Result<system_properties::SystemProperties>> {
+    Ok(Err(Error::InvalidArgument(
+        "memfd-backed property stores are fetched from the property service, \
+         which needs the `service-protocol` feature"
+            .into(),
+    )))
+}
+
 /// Get the system properties.
 ///
 /// Calling this without a prior `init()` does **not** panic by itself: the
@@ -382,6 +860,38 @@ pub fn system_properties() -> &'static system_properties::SystemProperties {
     }
 }
 
+/// Refreshes the library's process-wide state in a child process after
+/// `fork()`. Call it in the child before the first property access — not
+/// from a `pthread_atfork` handler, since it does file I/O.
+///
+/// What survives `fork()` untouched, and why nothing is re-opened:
+/// - the property area mappings are `MAP_SHARED`, so the child sees the
+///   same live values as the parent;
+/// - `set()` connects to the property service per call — no socket is
+///   ever cached, so there is none to re-open;
+/// - the global-directory lock and the `OnceLock` caches are written so
+///   that a thread of the parent holding or initializing them at fork time
+///   cannot deadlock the child (see `lock_global_dirs`).
+///
+/// What this does: revalidates every mapped area of the global instance
+/// against the files on disk, remapping any that were replaced — the
+/// child of a long-lived process may start long after the mappings were
+/// made, e.g. after a property service restart. A no-op when the global
+/// instance was never initialized. A replaced mapping is unmapped once
+/// nothing reads through it, see [`SystemProperties::retired_mappings`].
+///
+/// Builder instances are not covered: a child inherits the parent's
+/// `flock` on the writer lock (it is per open file description), so a
+/// forked writer must not keep writing while the parent does.
+pub fn at_fork_child() -> Result<()> {
+    match system_properties_if_initialized() {
+        Some(props) => props
+            .revalidate_areas()
+            .inspect_err(|e| log::error!("at_fork_child: failed to revalidate areas: {e}")),
+        None => Ok(()),
+    }
+}
+
 /// Aligns `value` *up* to the given alignment (bionic style). The
 /// align-up contract — the result is never less than `value` — is
 /// load-bearing for allocation size computations, so overflow panics
@@ -408,15 +918,14 @@ pub(crate) fn bionic_align(value: usize, alignment: usize) -> usize {
 /// Returns Err if property not found, system error, or parse error occurs
 ///
 /// Note: unlike [`get_or`], an *empty* property value is not special-cased
-/// here — `get::<String>` returns `Ok("")` for a set-but-empty property,
-/// while `get_or` follows the Android convention (empty = unset) and
-/// falls back to the default.
+/// here by default — `get::<String>` returns `Ok("")` for a set-but-empty
+/// property, while `get_or` follows the Android convention (empty = unset)
+/// and falls back to the default. [`set_read_policy`] makes the two agree;
+/// [`get_optional`] tells unset, empty and errors apart.
 ///
-/// The `FromStr` parse runs while the property area's internal read lock
-/// is held (see [`SystemProperties::read_with`]): a `FromStr` impl that
-/// blocks, or re-enters this crate's property API, can deadlock against a
-/// same-process `builder` writer. Keep parsing cheap and non-reentrant —
-/// every ordinary `FromStr` (ints, floats, `String`) is fine.
+/// The `FromStr` parse runs while the value bytes are still borrowed from
+/// the property area (see [`SystemProperties::read_with`]), so keep it
+/// cheap — every ordinary `FromStr` (ints, floats, `String`) is fine.
 ///
 /// # Examples
 /// ```rust,no_run
@@ -438,17 +947,7 @@ where
     T: std::str::FromStr,
     T::Err: std::fmt::Display,
 {
-    // Route through `read_with` so the parse-and-discard path never
-    // allocates a `String` — the value bytes are handed to `FromStr` as
-    // `&str` borrowed from the seqlock buffer (short variant) or the mmap
-    // (long variant).
-    try_system_properties()?.read_with(name, |value| {
-        value.parse().map_err(|e| {
-            Error::Parse(format!(
-                "Failed to parse '{value}' for property '{name}': {e}"
-            ))
-        })
-    })?
+    PropertiesClient::global().get(name)
 }
 
 /// Get a property value with default fallback
@@ -458,8 +957,8 @@ where
 /// (e.g. an allocated `String`), prefer [`get_or_else`], which only
 /// constructs it on the fallback path.
 ///
-/// The `FromStr` parse runs under the property area's read lock — see the
-/// caution on [`get`].
+/// The `FromStr` parse runs on the borrowed value — see the note on
+/// [`get`].
 ///
 /// # Examples
 /// ```rust,no_run
@@ -482,14 +981,14 @@ where
 }
 
 /// Like [`get_or`], but the default is produced lazily — the closure runs
-/// only when the property is missing, empty, fails to parse, or the global
+/// only when the property is missing, empty (unless the [`ReadPolicy`] is
+/// [`ReadPolicy::EmptyIsValue`]), fails to parse, or the global
 /// property store failed to initialize (see [`try_system_properties`]:
 /// failure is latched), so the found-and-parsed hot path never pays for
 /// constructing it.
 ///
-/// The `FromStr` parse runs under the property area's read lock — see the
-/// caution on [`get`]. (The `default` closure runs after the lock is
-/// released.)
+/// The `FromStr` parse runs on the borrowed value — see the note on
+/// [`get`]. (The `default` closure runs after the borrow ends.)
 ///
 /// # Examples
 /// ```rust,no_run
@@ -502,22 +1001,115 @@ where
     T: std::str::FromStr,
     F: FnOnce() -> T,
 {
-    let Ok(props) = try_system_properties() else {
-        return default();
-    };
-    // Two-stage closure: the inner `Result<T, ()>` carries the parsed
-    // value back out of `read_with` without ever allocating a `String`.
-    // `Err(())` signals "use the default"; the default itself is produced
-    // at the match below, so the `FnOnce` callback never needs to own it.
-    match props.read_with(name, |value| {
-        if value.is_empty() {
-            return Err(());
-        }
-        value.parse::<T>().map_err(|_| ())
-    }) {
-        Ok(Ok(v)) => v,
-        _ => default(),
-    }
+    PropertiesClient::global().get_or_else(name, default)
+}
+
+/// The old name of [`get_or`] for string values, kept so code written
+/// against it still builds.
+#[deprecated(since = "0.7.0", note = "use `get_or` or `get_or_else`")]
+pub fn get_with_default(name: &str, default: &str) -> String {
+    get_or_else(name, || default.to_owned())
+}
+
+/// Reads a property as a string, or `None` when it cannot be read — the
+/// quiet probe for optional properties. Nothing is logged for an unset
+/// property or an invalid name; only a corrupt or unreadable property
+/// area is, at error level. Empty values follow the
+/// [`ReadPolicy`], as for [`get`]; use [`get_optional`] to tell an unset
+/// property from a store that cannot be read.
+///
+/// # Examples
+/// ```rust,no_run
+/// if let Some(mode) = rsproperties::try_get("persist.vendor.mode") {
+///     println!("mode: {mode}");
+/// }
+/// ```
+pub fn try_get(name: &str) -> Option<String> {
+    get(name).ok()
+}
+
+/// Reads a property as a string, telling the outcomes apart: `Ok(None)`
+/// when it is not set, `Ok(Some(""))` when it is set to the empty string,
+/// `Ok(Some(value))` otherwise, and `Err` for an invalid name
+/// ([`Error::InvalidArgument`]) or a property store that cannot be read. Not affected by [`ReadPolicy`].
+///
+/// # Examples
+/// ```rust,no_run
+/// match rsproperties::get_optional("persist.vendor.mode").unwrap() {
+///     None => println!("never set"),
+///     Some(mode) if mode.is_empty() => println!("cleared"),
+///     Some(mode) => println!("mode: {mode}"),
+/// }
+/// ```
+pub fn get_optional(name: &str) -> Result<Option<String>> {
+    PropertiesClient::global().get_optional(name)
+}
+
+/// Every property of the global store as `(name, value)` pairs, sorted
+/// by name; see [`SystemProperties::foreach`] for how the walk reads
+/// them.
+///
+/// # Examples
+/// ```rust,no_run
+/// for (name, value) in rsproperties::list().unwrap() {
+///     println!("{name}={value}");
+/// }
+/// ```
+pub fn list() -> Result<Vec<(String, String)>> {
+    PropertiesClient::global().list()
+}
+
+/// Writes every property of the global store in Android `getprop`'s
+/// listing format (`[name]: [value]` lines, sorted); see
+/// [`SystemProperties::format_getprop`].
+///
+/// # Examples
+/// ```rust,no_run
+/// use rsproperties::GetpropColumn;
+///
+/// rsproperties::format_getprop(std::io::stdout().lock(), GetpropColumn::Value).unwrap();
+/// ```
+pub fn format_getprop(writer: impl std::io::Write, column: GetpropColumn) -> Result<()> {
+    PropertiesClient::global().format_getprop(writer, column)
+}
+
+/// Reads a duration such as `"30s"`, `"5m"` or `"1500ms"` (see
+/// [`PropertyDuration`] for the accepted units), falling back to `default`
+/// like [`get_or`]: when the property is missing, empty or malformed.
+///
+/// # Examples
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let timeout = rsproperties::get_duration("vendor.app.timeout", Duration::from_secs(30));
+/// ```
+pub fn get_duration(name: &str, default: std::time::Duration) -> std::time::Duration {
+    get_or(name, PropertyDuration(default)).0
+}
+
+/// Reads a byte size such as `"512m"` or `"2g"` (binary multiples; see
+/// [`ByteSize`]) as a number of bytes. Errors like [`get`].
+///
+/// # Examples
+/// ```rust,no_run
+/// let heap_bytes = rsproperties::get_size("dalvik.vm.heapsize").unwrap_or(256 << 20);
+/// ```
+pub fn get_size(name: &str) -> Result<u64> {
+    get::<ByteSize>(name).map(|size| size.0)
+}
+
+/// Reads a `host:port` address (see [`HostPort`]). The host is not
+/// resolved. Errors like [`get`].
+///
+/// # Examples
+/// ```rust,no_run
+/// use std::net::TcpStream;
+///
+/// let endpoint = rsproperties::get_addr("vendor.app.endpoint").unwrap();
+/// let stream = TcpStream::connect(&endpoint).unwrap();
+/// ```
+pub fn get_addr(name: &str) -> Result<HostPort> {
+    get(name)
 }
 
 /// Set a value of the property with any Display type.
@@ -556,8 +1148,113 @@ where
 /// - Android system properties typically use "0"/"1" for boolean values, not "true"/"false"
 /// - Numeric properties may have specific formatting requirements
 /// - Always test compatibility when setting properties that will be read by other applications
+#[cfg(feature = "service-protocol")]
 pub fn set<T: std::fmt::Display + ?Sized>(name: &str, value: &T) -> Result<()> {
-    system_property_set::set(name, &value.to_string())
+    PropertiesClient::global().set(name, value)
+}
+
+/// Sets several properties over as few connections as possible, for
+/// callers that set dozens at once (init scripts, test fixtures), and
+/// returns one result per entry, in order.
+///
+/// The entries travel in batches of up to [`wire::MAX_WIRE_BATCH`]
+/// ([`wire::PROP_MSG_SETPROP_BATCH`], an rsproperties extension), applied
+/// in order and each on its own: a refused entry does not stop the
+/// others. Against a service without the extension (AOSP init), with the
+/// V1 protocol and for `sys.powerctl`, entries are [`set`] one by one.
+/// A batch the service could not be asked for, or refused as a whole,
+/// fails each of its entries with that error; the other batches are
+/// still sent.
+///
+/// # Examples
+/// ```rust,no_run
+/// let results = rsproperties::set_many(&[
+///     ("debug.my_app.trace", "1"),
+///     ("debug.my_app.level", "verbose"),
+/// ])
+/// .unwrap();
+/// for result in results {
+///     if let Err(e) = result {
+///         eprintln!("{e}");
+///     }
+/// }
+/// ```
+#[cfg(feature = "service-protocol")]
+pub fn set_many<N: AsRef<str>, V: AsRef<str>>(entries: &[(N, V)]) -> Result<Vec<Result<()>>> {
+    PropertiesClient::global().set_many(entries)
+}
+
+/// Removes a property through the property service, so reads of `name`
+/// miss until it is set again; see [`SystemProperties::remove`]. Removing
+/// a property that is not set succeeds.
+///
+/// An rsproperties extension ([`wire::PROP_MSG_UNSETPROP`]): AOSP init
+/// refuses it with an [`Error::ServiceError`], and like [`set`], read-only
+/// properties cannot be removed.
+///
+/// # Examples
+/// ```rust,no_run
+/// rsproperties::set("debug.my_app.trace", "1").unwrap();
+/// rsproperties::remove("debug.my_app.trace").unwrap();
+/// assert_eq!(rsproperties::get_optional("debug.my_app.trace").unwrap(), None);
+/// ```
+#[cfg(feature = "service-protocol")]
+pub fn remove(name: &str) -> Result<()> {
+    PropertiesClient::global().remove(name)
+}
+
+/// [`set`], then waits until the new value is what this process reads
+/// back, for callers that read right after setting: the service commits
+/// asynchronously on the V1 protocol, and a reader in another process is
+/// only guaranteed to see the value once it is in the areas.
+///
+/// Returns how long the set took to become observable, measured from the
+/// call. Fails with a `TimedOut` [`Error::Io`] when `timeout` passes first
+/// — for example because another writer set a different value in the
+/// meantime. The set itself is bounded by the client's own socket
+/// timeouts, not by `timeout`.
+#[cfg(feature = "service-protocol")]
+pub fn set_and_confirm<T: std::fmt::Display + ?Sized>(
+    name: &str,
+    value: &T,
+    timeout: std::time::Duration,
+) -> Result<std::time::Duration> {
+    PropertiesClient::global().set_and_confirm(name, value, timeout)
+}
+
+/// Waits until the global store's `name` holds `expected`, returning
+/// `false` if `timeout` passes first — the `sys.boot_completed` pattern;
+/// see [`SystemProperties::wait_for_value`].
+///
+/// # Examples
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// if !rsproperties::wait_for_value("sys.boot_completed", "1", Duration::from_secs(60)).unwrap() {
+///     eprintln!("boot did not complete in time");
+/// }
+/// ```
+pub fn wait_for_value(name: &str, expected: &str, timeout: std::time::Duration) -> Result<bool> {
+    PropertiesClient::global().wait_for_value(name, expected, timeout)
+}
+
+/// Sets a property declared with the `bytes` type to binary `value`.
+///
+/// The value is stored base64-encoded, so a writable property — one not
+/// under [`read_only_prefixes`], whose values are stored out of line —
+/// holds at most [`PROP_BYTES_MAX`] bytes. Fails with [`Error::InvalidArgument`]
+/// if the `property_info` trie does not declare `name` as `bytes`, or if
+/// the value is too long.
+#[cfg(feature = "service-protocol")]
+pub fn set_bytes(name: &str, value: &[u8]) -> Result<()> {
+    PropertiesClient::global().set_bytes(name, value)
+}
+
+/// Reads a property declared with the `bytes` type, decoding the stored
+/// base64. A value that does not decode is an [`Error::Parse`]; a
+/// property not declared `bytes` is an [`Error::InvalidArgument`].
+pub fn get_bytes(name: &str) -> Result<Vec<u8>> {
+    PropertiesClient::global().get_bytes(name)
 }
 
 #[cfg(test)]
@@ -578,8 +1275,19 @@ mod tests {
     #[cfg(all(feature = "builder", not(target_os = "android")))]
     use std::sync::{Mutex, MutexGuard};
 
+    /// Per-process, so concurrent `cargo test` runs (CI shards, several
+    /// workspace checkouts) never rebuild each other's areas.
     #[cfg(all(feature = "builder", not(target_os = "android")))]
-    const TEST_PROPERTY_DIR: &str = "__properties__";
+    fn test_property_dir() -> PathBuf {
+        static DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir =
+                std::env::temp_dir().join(format!("rsprops_unit_tests_{}", std::process::id()));
+            crate::exit_cleanup::remove_at_exit(&dir);
+            dir
+        })
+        .clone()
+    }
 
     #[cfg(any(feature = "builder", target_os = "android"))]
     fn enable_logger() {
@@ -639,6 +1347,11 @@ mod tests {
 
             println!("{}: [{}], [{}]", prop, value1, value2);
             assert_eq!(value1, value2);
+            // The look-alike agrees with the crate it stands in for.
+            assert_eq!(
+                compat::AndroidSystemProperties::new().get(prop),
+                AndroidSystemProperties::new().get(prop)
+            );
         }
     }
 
@@ -670,21 +1383,21 @@ mod tests {
         let mut system_properties_guard = SYSTEM_PROPERTIES.lock().unwrap();
 
         if system_properties_guard.is_none() {
-            *system_properties_guard = Some(build_property_dir(TEST_PROPERTY_DIR));
+            *system_properties_guard = Some(build_property_dir(&test_property_dir()));
         }
         system_properties_guard
     }
 
     #[cfg(all(feature = "builder", not(target_os = "android")))]
-    fn build_property_dir(dir: &str) -> SystemProperties {
-        crate::init(PropertyConfig::from(PathBuf::from(dir)));
+    fn build_property_dir(dir: &Path) -> SystemProperties {
+        crate::init(PropertyConfig::from(dir.to_path_buf()));
         // `init` is first-write-wins and swallows AlreadyInitialized with a
         // warn — if some other test in this binary latched a *different*
         // directory first, the `properties_dir()` cleanup below would
         // delete that directory instead of ours. Fail loudly instead.
         assert_eq!(
             properties_dir(),
-            Path::new(dir),
+            dir,
             "another test latched a different properties dir before this one"
         );
 
@@ -759,6 +1472,73 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_global_dirs_lock_taken_over_after_fork() {
+        static LOCK: GlobalDirsLock = GlobalDirsLock {
+            owner: AtomicU32::new(0),
+        };
+        // What a child sees when a parent thread held the lock at fork
+        // time: a holder PID that is not its own.
+        LOCK.owner.store(std::process::id() ^ 1, Ordering::Relaxed);
+        let guard = LOCK.lock();
+        assert_eq!(LOCK.owner.load(Ordering::Relaxed), std::process::id());
+        drop(guard);
+        assert_eq!(LOCK.owner.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(all(feature = "builder", not(target_os = "android")))]
+    #[test]
+    fn test_area_file_for() {
+        enable_logger();
+
+        let _guard = system_properties_area();
+        let system_properties = system_properties();
+
+        let path = system_properties.area_file_for("ro.build.host").unwrap();
+        assert_eq!(path, properties_dir().join("u:object_r:build_prop:s0"));
+        assert!(path.is_file());
+
+        // Resolution depends only on property_info, not on whether the
+        // property has been added yet.
+        let path = system_properties
+            .area_file_for("persist.sys.timezone")
+            .unwrap();
+        assert_eq!(path, properties_dir().join("u:object_r:timezone_prop:s0"));
+    }
+
+    #[cfg(all(feature = "builder", not(target_os = "android")))]
+    #[test]
+    fn test_describe() {
+        enable_logger();
+
+        let _guard = system_properties_area();
+        let system_properties = system_properties();
+
+        let host = system_properties.describe("ro.build.host").unwrap();
+        assert_eq!(host.context.as_deref(), Some("u:object_r:build_prop:s0"));
+        assert_eq!(host.type_str.as_deref(), Some("string"));
+        assert!(host.exists);
+        assert!(!host.is_long);
+        assert!(host.serial.is_some());
+
+        let codenames = system_properties
+            .describe("ro.build.version.known_codenames")
+            .unwrap();
+        assert!(codenames.exists);
+        assert!(codenames.is_long);
+
+        // Declared but never set: metadata without state.
+        let timezone = system_properties.describe("persist.sys.timezone").unwrap();
+        assert_eq!(
+            timezone.context.as_deref(),
+            Some("u:object_r:timezone_prop:s0")
+        );
+        assert_eq!(timezone.type_str.as_deref(), Some("string"));
+        assert!(!timezone.exists);
+        assert!(!timezone.is_long);
+        assert_eq!(timezone.serial, None);
+    }
+
     #[cfg(all(feature = "builder", not(target_os = "android")))]
     #[test]
     fn test_wait() {
@@ -0,0 +1,243 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Lock-order tracking for debug builds with the `lock-order` feature.
+//!
+//! Every lock the crate may block on is taken through [`lock`], [`read`],
+//! [`fn@write`] or [`acquire`], naming its class (e.g. `"global dirs"`). The
+//! tracker remembers, per thread, which locks are held and, process-wide,
+//! which classes were ever taken while holding which others. It panics
+//!
+//! - when a thread takes class `B` while holding `A`, after some thread
+//!   once took `A` while holding `B`: the two orders together deadlock as
+//!   soon as two threads run them concurrently; and
+//! - when a thread takes a lock it already holds: none of them is
+//!   re-entrant (the global-dirs spin lock would spin forever, a
+//!   `RwLock` read can block behind a queued writer).
+//!
+//! Both panic at the offending acquisition instead of hanging, with the
+//! locks involved in the message — e.g. a set issued from a change handler
+//! that runs under a lock the set path needs too. Two locks of one class
+//! are not ordered against each other. `try_lock` sites are left out:
+//! they never block.
+//!
+//! Without the feature, or in release builds, the wrappers compile down to
+//! the plain lock calls.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Registration of a held lock; removed from the tracker on drop.
+pub(crate) struct Held {
+    #[cfg(all(feature = "lock-order", debug_assertions))]
+    addr: usize,
+}
+
+/// Records that this thread is about to take the lock of `class` at
+/// `lock`, panicking on an order inversion or re-entry. Call it before
+/// blocking, and keep the result alive as long as the lock is held.
+#[cfg_attr(
+    not(all(feature = "lock-order", debug_assertions)),
+    allow(unused_variables)
+)]
+#[inline]
+pub(crate) fn acquire<T: ?Sized>(class: &'static str, lock: &T) -> Held {
+    #[cfg(all(feature = "lock-order", debug_assertions))]
+    {
+        let addr = lock as *const T as *const () as usize;
+        tracker::acquire(class, addr);
+        Held { addr }
+    }
+    #[cfg(not(all(feature = "lock-order", debug_assertions)))]
+    Held {}
+}
+
+#[cfg(all(feature = "lock-order", debug_assertions))]
+impl Drop for Held {
+    fn drop(&mut self) {
+        tracker::release(self.addr);
+    }
+}
+
+/// A lock guard together with its [`Held`] registration. The guard is
+/// released before the registration.
+pub(crate) struct Tracked<G> {
+    guard: G,
+    _held: Held,
+}
+
+impl<G: Deref> Deref for Tracked<G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for Tracked<G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// Locks `mutex`, ignoring poisoning like every caller in the crate does.
+pub(crate) fn lock<'a, T>(class: &'static str, mutex: &'a Mutex<T>) -> Tracked<MutexGuard<'a, T>> {
+    let held = acquire(class, mutex);
+    Tracked {
+        guard: mutex.lock().unwrap_or_else(PoisonError::into_inner),
+        _held: held,
+    }
+}
+
+/// Read-locks `lock`, ignoring poisoning.
+pub(crate) fn read<'a, T>(
+    class: &'static str,
+    lock: &'a RwLock<T>,
+) -> Tracked<RwLockReadGuard<'a, T>> {
+    let held = acquire(class, lock);
+    Tracked {
+        guard: lock.read().unwrap_or_else(PoisonError::into_inner),
+        _held: held,
+    }
+}
+
+/// Write-locks `lock`, ignoring poisoning.
+pub(crate) fn write<'a, T>(
+    class: &'static str,
+    lock: &'a RwLock<T>,
+) -> Tracked<RwLockWriteGuard<'a, T>> {
+    let held = acquire(class, lock);
+    Tracked {
+        guard: lock.write().unwrap_or_else(PoisonError::into_inner),
+        _held: held,
+    }
+}
+
+#[cfg(all(feature = "lock-order", debug_assertions))]
+mod tracker {
+    use std::cell::RefCell;
+    use std::collections::BTreeSet;
+    use std::sync::{Mutex, PoisonError};
+
+    thread_local! {
+        /// `(class, address)` of the locks this thread holds, in
+        /// acquisition order.
+        static HELD: RefCell<Vec<(&'static str, usize)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// `(outer, inner)` class pairs seen so far. The tracker's own lock is
+    /// only ever taken innermost and briefly, so it is not tracked.
+    static ORDER: Mutex<BTreeSet<(&'static str, &'static str)>> = Mutex::new(BTreeSet::new());
+
+    pub(super) fn acquire(class: &'static str, addr: usize) {
+        // `try_with`: locks taken while thread-locals are torn down go
+        // untracked rather than aborting.
+        let violation = HELD
+            .try_with(|held| {
+                let mut held = held.borrow_mut();
+                let violation = check(&held, class, addr);
+                if violation.is_none() {
+                    held.push((class, addr));
+                }
+                violation
+            })
+            .ok()
+            .flatten();
+        if let Some(violation) = violation {
+            panic!("{violation}");
+        }
+    }
+
+    fn check(held: &[(&'static str, usize)], class: &'static str, addr: usize) -> Option<String> {
+        if held.iter().any(|&(_, held_addr)| held_addr == addr) {
+            return Some(format!(
+                "lock order: \"{class}\" is already held by this thread; taking it again deadlocks"
+            ));
+        }
+        if held.is_empty() {
+            return None;
+        }
+        let mut order = ORDER.lock().unwrap_or_else(PoisonError::into_inner);
+        for &(outer, _) in held {
+            if outer == class {
+                continue;
+            }
+            if order.contains(&(class, outer)) {
+                return Some(format!(
+                    "lock order inversion: \"{class}\" taken while holding \"{outer}\", \
+                     but \"{outer}\" was earlier taken while holding \"{class}\" \
+                     (held now: {:?})",
+                    held.iter().map(|&(class, _)| class).collect::<Vec<_>>()
+                ));
+            }
+        }
+        for &(outer, _) in held {
+            if outer != class {
+                order.insert((outer, class));
+            }
+        }
+        None
+    }
+
+    pub(super) fn release(addr: usize) {
+        let _ = HELD.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(index) = held.iter().rposition(|&(_, held_addr)| held_addr == addr) {
+                held.remove(index);
+            }
+        });
+    }
+}
+
+#[cfg(all(test, feature = "lock-order", debug_assertions))]
+mod tests {
+    use super::*;
+
+    // Class names are unique per test: the recorded order is process-wide.
+
+    #[test]
+    fn test_consistent_order_is_accepted() {
+        let (a, b) = (Mutex::new(()), RwLock::new(()));
+        for _ in 0..2 {
+            let _a = lock("test consistent a", &a);
+            let _b = read("test consistent b", &b);
+        }
+        // Released in any order.
+        let a_guard = lock("test consistent a", &a);
+        let b_guard = write("test consistent b", &b);
+        drop(a_guard);
+        drop(b_guard);
+        let _b = read("test consistent b", &b);
+    }
+
+    #[test]
+    #[should_panic(expected = "lock order inversion")]
+    fn test_inversion_panics() {
+        let (a, b) = (Mutex::new(()), Mutex::new(()));
+        {
+            let _a = lock("test inversion a", &a);
+            let _b = lock("test inversion b", &b);
+        }
+        let _b = lock("test inversion b", &b);
+        let _a = lock("test inversion a", &a);
+    }
+
+    #[test]
+    #[should_panic(expected = "already held by this thread")]
+    fn test_reentry_panics() {
+        let lock = RwLock::new(());
+        let _first = read("test reentry", &lock);
+        let _second = read("test reentry", &lock);
+    }
+
+    #[test]
+    fn test_same_class_is_not_ordered() {
+        let (first, second) = (Mutex::new(()), Mutex::new(()));
+        {
+            let _first = lock("test same class", &first);
+            let _second = lock("test same class", &second);
+        }
+        let _second = lock("test same class", &second);
+        let _first = lock("test same class", &first);
+    }
+}
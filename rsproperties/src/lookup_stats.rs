@@ -0,0 +1,164 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Trie lookup statistics for property areas.
+//!
+//! A property name is resolved one `.`-separated segment at a time: each
+//! segment descends one trie level, and within a level the sibling nodes
+//! form an unbalanced binary search tree walked by name comparisons. When
+//! recording is enabled, every lookup adds its depth (segments resolved)
+//! and the comparisons and left/right turns it took to process-wide
+//! counters — the numbers needed to judge whether a different sibling
+//! structure (hashed children, say) would pay off for a real workload.
+//!
+//! There is no lookup cache in front of the trie; every lookup walks it.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static LOOKUPS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static DEPTH_SUM: AtomicU64 = AtomicU64::new(0);
+static MAX_DEPTH: AtomicU64 = AtomicU64::new(0);
+static COMPARISONS_SUM: AtomicU64 = AtomicU64::new(0);
+static MAX_COMPARISONS: AtomicU64 = AtomicU64::new(0);
+static LEFT_TURNS: AtomicU64 = AtomicU64::new(0);
+static RIGHT_TURNS: AtomicU64 = AtomicU64::new(0);
+
+/// Counters of one lookup, kept on the stack whether or not recording is
+/// enabled (a few register increments).
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct LookupTrace {
+    pub(crate) depth: u32,
+    pub(crate) comparisons: u32,
+    pub(crate) left: u32,
+    pub(crate) right: u32,
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records one finished lookup; `found` is `false` for a miss.
+pub(crate) fn record_lookup(trace: &LookupTrace, found: bool) {
+    LOOKUPS.fetch_add(1, Ordering::Relaxed);
+    if !found {
+        MISSES.fetch_add(1, Ordering::Relaxed);
+    }
+    DEPTH_SUM.fetch_add(u64::from(trace.depth), Ordering::Relaxed);
+    MAX_DEPTH.fetch_max(u64::from(trace.depth), Ordering::Relaxed);
+    COMPARISONS_SUM.fetch_add(u64::from(trace.comparisons), Ordering::Relaxed);
+    MAX_COMPARISONS.fetch_max(u64::from(trace.comparisons), Ordering::Relaxed);
+    LEFT_TURNS.fetch_add(u64::from(trace.left), Ordering::Relaxed);
+    RIGHT_TURNS.fetch_add(u64::from(trace.right), Ordering::Relaxed);
+}
+
+/// Turns trie lookup recording on or off for this process. Off by
+/// default: a recorded lookup costs eight relaxed atomic updates on
+/// shared counters, which contend across reader threads.
+pub fn enable_lookup_stats(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Snapshot of the lookup counters. Counters are read one by one without
+/// a lock, so a snapshot taken under load may be off by the lookups
+/// recorded while it was being copied.
+pub fn lookup_stats() -> LookupStats {
+    LookupStats {
+        lookups: LOOKUPS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        depth_sum: DEPTH_SUM.load(Ordering::Relaxed),
+        max_depth: MAX_DEPTH.load(Ordering::Relaxed),
+        comparisons_sum: COMPARISONS_SUM.load(Ordering::Relaxed),
+        max_comparisons: MAX_COMPARISONS.load(Ordering::Relaxed),
+        left_turns: LEFT_TURNS.load(Ordering::Relaxed),
+        right_turns: RIGHT_TURNS.load(Ordering::Relaxed),
+    }
+}
+
+/// Clears the counters (recording stays enabled or disabled as it was).
+pub fn reset_lookup_stats() {
+    for counter in [
+        &LOOKUPS,
+        &MISSES,
+        &DEPTH_SUM,
+        &MAX_DEPTH,
+        &COMPARISONS_SUM,
+        &MAX_COMPARISONS,
+        &LEFT_TURNS,
+        &RIGHT_TURNS,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Property area trie lookups recorded by this process. See
+/// [`enable_lookup_stats`].
+///
+/// A lookup that fails on a missing segment counts the levels it reached;
+/// one rejected before touching the trie (an invalid name) counts depth 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LookupStats {
+    /// Lookups recorded, hits and misses.
+    pub lookups: u64,
+    /// Lookups that did not find a property.
+    pub misses: u64,
+    /// Trie levels (name segments) descended, summed over all lookups.
+    pub depth_sum: u64,
+    pub max_depth: u64,
+    /// Name comparisons in the sibling search trees, summed over all
+    /// lookups.
+    pub comparisons_sum: u64,
+    /// Most comparisons a single lookup took.
+    pub max_comparisons: u64,
+    /// Steps to a left sibling (name sorts before the node's).
+    pub left_turns: u64,
+    /// Steps to a right sibling (name sorts after the node's).
+    pub right_turns: u64,
+}
+
+impl LookupStats {
+    /// Fraction of lookups that found their property; `None` if nothing
+    /// was recorded.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        (self.lookups > 0).then(|| (self.lookups - self.misses) as f64 / self.lookups as f64)
+    }
+
+    pub fn mean_depth(&self) -> Option<f64> {
+        (self.lookups > 0).then(|| self.depth_sum as f64 / self.lookups as f64)
+    }
+
+    pub fn mean_comparisons(&self) -> Option<f64> {
+        (self.lookups > 0).then(|| self.comparisons_sum as f64 / self.lookups as f64)
+    }
+
+    /// Mean comparisons per trie level — about 1 when sibling sets are
+    /// small or well balanced, growing with wide, skewed levels.
+    pub fn comparisons_per_level(&self) -> Option<f64> {
+        (self.depth_sum > 0).then(|| self.comparisons_sum as f64 / self.depth_sum as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ratios() {
+        let mut stats = LookupStats::default();
+        assert_eq!(stats.hit_ratio(), None);
+        assert_eq!(stats.mean_depth(), None);
+        assert_eq!(stats.comparisons_per_level(), None);
+
+        stats.lookups = 4;
+        stats.misses = 1;
+        stats.depth_sum = 10;
+        stats.comparisons_sum = 25;
+        assert_eq!(stats.hit_ratio(), Some(0.75));
+        assert_eq!(stats.mean_depth(), Some(2.5));
+        assert_eq!(stats.mean_comparisons(), Some(6.25));
+        assert_eq!(stats.comparisons_per_level(), Some(2.5));
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Property stores kept in memfds instead of files, see [`Backing`].
+//!
+//! The writer creates one memfd per file a properties directory would
+//! hold — `property_info`, the serial area and one area per context —
+//! under the same names, and readers receive them over the property
+//! service socket ([`crate::wire::PROP_MSG_GET_AREAS`]). Area memfds are
+//! sealed against growing and shrinking as soon as they are initialized
+//! (and, on kernels that support it, against new writable mappings: only
+//! the writer's own mapping stays writable); `property_info` is sealed
+//! read-only outright. Readers only ever receive descriptors reopened
+//! read-only.
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::fs::File;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::path::Path;
+
+#[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+use rustix::fs::MemfdFlags;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use rustix::fs::{self, SealFlags};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::errors::*;
+
+/// Where a property store keeps its areas.
+///
+/// Chosen with [`crate::PropertyConfigBuilder::backing`] on the reader
+/// side; the writer picks its constructor
+/// ([`crate::SystemProperties::new_area`] or
+/// [`crate::SystemProperties::new_memfd_area`]).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backing {
+    /// Files in the properties directory (AOSP's `/dev/__properties__`).
+    #[default]
+    Files,
+    /// Anonymous memfds handed out by the property service: nothing is
+    /// created in the filesystem, for environments where a world-readable
+    /// directory of property files is not allowed. Readers fetch the
+    /// memfds from the service socket on first access, so the service must
+    /// be running by then. Linux and Android only.
+    Memfd,
+}
+
+/// A new, empty memfd named after `name`, with the mode a property file
+/// would have (readers apply the same metadata checks to both).
+#[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+pub(crate) fn create(name: &Path) -> Result<File> {
+    let label = format!("rsproperties:{}", name.display());
+    let fd = fs::memfd_create(
+        label.as_str(),
+        MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+    )
+    .map_err(Error::from)
+    .context_with_location(format!("Failed to create memfd for {name:?}"))?;
+    fs::fchmod(&fd, fs::Mode::RUSR | fs::Mode::RGRP | fs::Mode::ROTH)
+        .map_err(Error::from)
+        .context_with_location(format!("Failed to set the mode of memfd {name:?}"))?;
+    Ok(File::from(fd))
+}
+
+/// Seals an initialized area memfd against resizing and, where the kernel
+/// has `F_SEAL_FUTURE_WRITE` (5.1+), against writable mappings other than
+/// the writer's existing one. Without it, readers are still only ever
+/// handed `O_RDONLY` descriptors (see
+/// [`crate::SystemProperties::memfd_areas`]), which cannot be mapped
+/// writable.
+#[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+pub(crate) fn seal_area(file: &File, name: &Path) -> Result<()> {
+    let resize = SealFlags::GROW | SealFlags::SHRINK;
+    if let Err(e) = fs::fcntl_add_seals(file, resize | SealFlags::FUTURE_WRITE) {
+        log::warn!(
+            "F_SEAL_FUTURE_WRITE unavailable for {name:?} ({e}); sealing the size only, \
+             readers rely on their read-only descriptors"
+        );
+        add_seals(file, name, resize)?;
+    }
+    add_seals(file, name, SealFlags::SEAL)
+}
+
+/// Seals a memfd that is complete (`property_info`) read-only.
+#[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+pub(crate) fn seal_immutable(file: &File, name: &Path) -> Result<()> {
+    add_seals(
+        file,
+        name,
+        SealFlags::WRITE | SealFlags::GROW | SealFlags::SHRINK | SealFlags::SEAL,
+    )
+}
+
+#[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+fn add_seals(file: &File, name: &Path, seals: SealFlags) -> Result<()> {
+    fs::fcntl_add_seals(file, seals)
+        .map_err(Error::from)
+        .context_with_location(format!("Failed to seal memfd {name:?}"))
+}
+
+/// Checks that a memfd received from the writer cannot shrink under the
+/// reader's mapping.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn check_sealed(file: &File, name: &Path) -> Result<()> {
+    let seals = fs::fcntl_get_seals(file)
+        .map_err(Error::from)
+        .context_with_location(format!("Failed to read the seals of {name:?}"))?;
+    if !seals.contains(SealFlags::SHRINK | SealFlags::SEAL) {
+        let msg = format!("memfd {name:?} is not sealed against shrinking");
+        log::error!("{msg}");
+        return Err(Error::FileValidation(msg));
+    }
+    Ok(())
+}
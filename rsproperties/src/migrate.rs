@@ -0,0 +1,759 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversion between classic Linux service configuration and properties.
+//!
+//! Importers map a `KEY=value` env file (`/etc/default/*` style) or a JSON
+//! document onto property names under a chosen prefix; exporters produce
+//! the same formats back from a set of properties. Nothing here touches
+//! the property store — callers apply an [`Import`] with [`crate::set`]
+//! (or `SystemProperties::set` in a builder) and feed exporters from
+//! [`crate::FrozenProperties::iter`], so a conversion can be reviewed as a
+//! dry run first.
+//!
+//! # Name normalization
+//!
+//! A source key is split into segments — on `__` for env keys (the usual
+//! convention for nesting in flat variables), on `.` and object nesting
+//! for JSON — and each segment is lowercased, with every character a
+//! property name cannot hold replaced by `_`. The segments are joined with
+//! `.` under the prefix:
+//!
+//! | source                          | property (prefix `vendor.app`)   |
+//! |---------------------------------|----------------------------------|
+//! | `LOG_LEVEL=debug`               | `vendor.app.log_level`           |
+//! | `DB__HOST=db1`                  | `vendor.app.db.host`             |
+//! | `{"db": {"max conns": 8}}`      | `vendor.app.db.max_conns`        |
+//!
+//! Two source keys normalizing to the same name are a [`Collision`]: the
+//! first one wins and both are reported. Keys that cannot become a valid
+//! name, values that do not fit (see [`crate::wire::validate_value_len`])
+//! and unsupported JSON values (arrays, `null`) are reported as
+//! [`Skipped`] rather than failing the whole import; only a syntax error
+//! in the source does that.
+
+use std::collections::HashMap;
+
+use crate::errors::*;
+use crate::wire::{validate_property_name, validate_value_len};
+
+/// Result of an import: the properties to set, in source order, plus
+/// everything that did not make it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Import {
+    /// `(name, value)` pairs, in source order.
+    pub properties: Vec<(String, String)>,
+    /// Source keys that normalized to an already-imported name.
+    pub collisions: Vec<Collision>,
+    /// Source keys that were not imported, with the reason.
+    pub skipped: Vec<Skipped>,
+}
+
+/// Two source keys that normalize to the same property name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Collision {
+    /// The property name both keys map to.
+    pub name: String,
+    /// The source key that was imported (the first one).
+    pub kept: String,
+    /// The source key that was dropped.
+    pub dropped: String,
+}
+
+/// A source key (import) or property name (export) left out, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Skipped {
+    pub key: String,
+    pub reason: String,
+}
+
+/// Result of an export: the document text plus the properties that the
+/// format cannot represent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Export {
+    pub text: String,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Bound on JSON object nesting, so a hostile document cannot exhaust the
+/// stack of the recursive parser.
+const MAX_JSON_DEPTH: usize = 64;
+
+/// Imports a `KEY=value` env file.
+///
+/// Accepts blank lines, `#` comments, an optional `export ` prefix, and
+/// values that are unquoted (trimmed, ` #` starts a comment), single-quoted
+/// (literal) or double-quoted (`\\`, `\"`, `\$`, `` \` `` and `\n`
+/// escapes). Values spanning several lines are not supported. A malformed
+/// line fails the import with [`Error::Parse`] naming the line.
+pub fn import_env(prefix: &str, text: &str) -> Result<Import> {
+    let mut collector = Collector::new(prefix)?;
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, raw_value) = line
+            .split_once('=')
+            .ok_or_else(|| Error::Parse(format!("line {line_no}: expected KEY=value")))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(Error::Parse(format!("line {line_no}: empty key")));
+        }
+        let value = parse_env_value(raw_value.trim_start())
+            .map_err(|reason| Error::Parse(format!("line {line_no}: {reason}")))?;
+        collector.push(key, key.split("__"), value);
+    }
+    Ok(collector.finish())
+}
+
+fn parse_env_value(raw: &str) -> std::result::Result<String, String> {
+    let (value, rest) = if let Some(quoted) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        let end = loop {
+            match chars.next() {
+                None => return Err("unterminated double quote".into()),
+                Some((i, '"')) => break i + 1,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, c @ ('\\' | '"' | '$' | '`'))) => value.push(c),
+                    Some((_, c)) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => return Err("unterminated double quote".into()),
+                },
+                Some((_, c)) => value.push(c),
+            }
+        };
+        (value, &quoted[end..])
+    } else if let Some(quoted) = raw.strip_prefix('\'') {
+        let end = quoted
+            .find('\'')
+            .ok_or_else(|| "unterminated single quote".to_owned())?;
+        (quoted[..end].to_owned(), &quoted[end + 1..])
+    } else {
+        let value = match raw.find(" #").or_else(|| raw.find("\t#")) {
+            Some(comment) => &raw[..comment],
+            None => raw,
+        };
+        (value.trim_end().to_owned(), "")
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected text after closing quote: {rest:?}"));
+    }
+    Ok(value)
+}
+
+/// Imports a JSON document whose top level is an object.
+///
+/// Nested objects extend the name; strings are imported as is, numbers by
+/// their literal text and booleans as `true`/`false`. Arrays and `null`
+/// are skipped. Invalid JSON fails the import with [`Error::Parse`].
+pub fn import_json(prefix: &str, text: &str) -> Result<Import> {
+    parse_json(Collector::new(prefix)?, text)
+}
+
+/// Imports a property dump as written by [`export_json`] with an empty
+/// prefix: a flat object of full property names. Keys are taken as they
+/// are, case and all, rather than normalized; nested objects are joined
+/// with `.` the same way.
+pub fn import_json_dump(text: &str) -> Result<Import> {
+    let mut collector = Collector::new("")?;
+    collector.verbatim = true;
+    parse_json(collector, text)
+}
+
+fn parse_json(mut collector: Collector<'_>, text: &str) -> Result<Import> {
+    let mut parser = JsonParser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_ws();
+    if parser.peek() != Some(b'{') {
+        return Err(parser.error("top level must be an object"));
+    }
+    parser.object(&mut Vec::new(), &mut collector)?;
+    parser.skip_ws();
+    if parser.pos != text.len() {
+        return Err(parser.error("trailing characters after the document"));
+    }
+    Ok(collector.finish())
+}
+
+/// Exports the properties under `prefix` as an env file, sorted by name.
+///
+/// Each name segment is uppercased and the segments are joined with `__`,
+/// the inverse of [`import_env`]; values are double-quoted. Names with a
+/// segment that is not an identifier once uppercased (e.g. containing
+/// `-`) are skipped.
+pub fn export_env<'a, I>(prefix: &str, properties: I) -> Export
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut export = Export::default();
+    for (name, rest, value) in under_prefix(prefix, properties) {
+        let Some(key) = env_key(rest) else {
+            export.skipped.push(Skipped {
+                key: name.to_owned(),
+                reason: "not representable as an environment variable name".into(),
+            });
+            continue;
+        };
+        export.text.push_str(&key);
+        export.text.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\n' => export.text.push_str("\\n"),
+                '\\' | '"' | '$' | '`' => {
+                    export.text.push('\\');
+                    export.text.push(c);
+                }
+                c => export.text.push(c),
+            }
+        }
+        export.text.push_str("\"\n");
+    }
+    export
+}
+
+fn env_key(rest: &str) -> Option<String> {
+    let mut key = String::with_capacity(rest.len() + 8);
+    for (i, segment) in rest.split('.').enumerate() {
+        let valid = !segment.is_empty()
+            && !segment.contains("__")
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return None;
+        }
+        if i > 0 {
+            key.push_str("__");
+        }
+        key.push_str(&segment.to_ascii_uppercase());
+    }
+    key.starts_with(|c: char| !c.is_ascii_digit())
+        .then_some(key)
+}
+
+/// Exports the properties under `prefix` as a flat JSON object of strings
+/// keyed by the name relative to the prefix, sorted by name. Flat rather
+/// than nested because a property and its "children" (`a` and `a.b`) can
+/// both hold values; [`import_json`] splits the dotted keys again.
+pub fn export_json<'a, I>(prefix: &str, properties: I) -> Export
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut text = String::from("{");
+    for (i, (_, rest, value)) in under_prefix(prefix, properties).into_iter().enumerate() {
+        text.push_str(if i == 0 { "\n  " } else { ",\n  " });
+        push_json_string(&mut text, rest);
+        text.push_str(": ");
+        push_json_string(&mut text, value);
+    }
+    text.push_str("\n}\n");
+    Export {
+        text,
+        skipped: Vec::new(),
+    }
+}
+
+/// `(name, name relative to prefix, value)` for every property under
+/// `prefix`, sorted by name.
+fn under_prefix<'a, I>(prefix: &str, properties: I) -> Vec<(&'a str, &'a str, &'a str)>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut selected: Vec<_> = properties
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let rest = if prefix.is_empty() {
+                name
+            } else {
+                name.strip_prefix(prefix)?.strip_prefix('.')?
+            };
+            Some((name, rest, value))
+        })
+        .collect();
+    selected.sort_unstable_by_key(|&(name, _, _)| name);
+    selected
+}
+
+pub(crate) fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Normalizes source keys and accumulates the [`Import`].
+struct Collector<'p> {
+    prefix: &'p str,
+    /// Take source keys as property names, without normalization.
+    verbatim: bool,
+    /// Property name → source key that claimed it.
+    claimed: HashMap<String, String>,
+    import: Import,
+}
+
+impl<'p> Collector<'p> {
+    fn new(prefix: &'p str) -> Result<Self> {
+        if !prefix.is_empty() {
+            validate_property_name(prefix)
+                .map_err(|e| Error::InvalidArgument(format!("invalid prefix {prefix:?}: {e}")))?;
+        }
+        Ok(Self {
+            prefix,
+            verbatim: false,
+            claimed: HashMap::new(),
+            import: Import::default(),
+        })
+    }
+
+    fn push<'s>(&mut self, key: &str, segments: impl IntoIterator<Item = &'s str>, value: String) {
+        let name = if self.verbatim {
+            key.to_owned()
+        } else {
+            self.normalize(segments)
+        };
+        let checked =
+            validate_property_name(&name).and_then(|()| validate_value_len(&name, &value));
+        if let Err(e) = checked {
+            self.skip(key, e.to_string());
+            return;
+        }
+        if let Some(kept) = self.claimed.get(&name) {
+            log::debug!("Import: {key:?} collides with {kept:?} on {name}");
+            self.import.collisions.push(Collision {
+                name,
+                kept: kept.clone(),
+                dropped: key.to_owned(),
+            });
+            return;
+        }
+        self.claimed.insert(name.clone(), key.to_owned());
+        self.import.properties.push((name, value));
+    }
+
+    fn normalize<'s>(&self, segments: impl IntoIterator<Item = &'s str>) -> String {
+        let mut name = self.prefix.to_owned();
+        for segment in segments {
+            if !name.is_empty() {
+                name.push('.');
+            }
+            name.extend(segment.chars().map(|c| {
+                let c = c.to_ascii_lowercase();
+                if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '@' | ':') {
+                    c
+                } else {
+                    '_'
+                }
+            }));
+        }
+        name
+    }
+
+    fn skip(&mut self, key: &str, reason: String) {
+        log::debug!("Import: skipping {key:?}: {reason}");
+        self.import.skipped.push(Skipped {
+            key: key.to_owned(),
+            reason,
+        });
+    }
+
+    fn finish(self) -> Import {
+        self.import
+    }
+}
+
+/// Just enough of a JSON parser for configuration documents: values are
+/// kept as text, objects are flattened into the collector as they are
+/// read.
+struct JsonParser<'t> {
+    text: &'t str,
+    pos: usize,
+    depth: usize,
+}
+
+/// A scalar JSON value, or the reason it is not imported.
+enum JsonValue {
+    Text(String),
+    Unsupported(&'static str),
+}
+
+impl JsonParser<'_> {
+    fn error(&self, what: &str) -> Error {
+        Error::Parse(format!("JSON at byte {}: {what}", self.pos))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_ws();
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Parses an object at `pos`, pushing its scalar members under `path`.
+    fn object(&mut self, path: &mut Vec<String>, collector: &mut Collector<'_>) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_JSON_DEPTH {
+            return Err(self.error("objects nested too deeply"));
+        }
+        self.expect(b'{')?;
+        self.skip_ws();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            self.depth -= 1;
+            return Ok(());
+        }
+        loop {
+            self.skip_ws();
+            let key = self.string()?;
+            self.expect(b':')?;
+            self.skip_ws();
+            let depth = path.len();
+            path.extend(key.split('.').map(str::to_owned));
+            if self.peek() == Some(b'{') {
+                self.object(path, collector)?;
+            } else {
+                let source_key = path.join(".");
+                match self.value()? {
+                    JsonValue::Text(value) => {
+                        collector.push(&source_key, path.iter().map(String::as_str), value)
+                    }
+                    JsonValue::Unsupported(reason) => collector.skip(&source_key, reason.into()),
+                }
+            }
+            path.truncate(depth);
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    self.depth -= 1;
+                    return Ok(());
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue> {
+        match self.peek() {
+            Some(b'"') => Ok(JsonValue::Text(self.string()?)),
+            Some(b'[') => {
+                self.skip_array()?;
+                Ok(JsonValue::Unsupported("arrays are not supported"))
+            }
+            Some(b't') => self.literal("true").map(JsonValue::Text),
+            Some(b'f') => self.literal("false").map(JsonValue::Text),
+            Some(b'n') => {
+                self.literal("null")?;
+                Ok(JsonValue::Unsupported("null value"))
+            }
+            Some(b'-' | b'0'..=b'9') => Ok(JsonValue::Text(self.number()?)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn literal(&mut self, word: &str) -> Result<String> {
+        if !self.text[self.pos..].starts_with(word) {
+            return Err(self.error(&format!("expected '{word}'")));
+        }
+        self.pos += word.len();
+        Ok(word.to_owned())
+    }
+
+    fn number(&mut self) -> Result<String> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        let literal = &self.text[start..self.pos];
+        // Grammar check by way of `f64`: every JSON number parses, and the
+        // literal text (not the float) is what gets imported.
+        if literal.starts_with("-.") || literal.starts_with('.') || literal.parse::<f64>().is_err()
+        {
+            self.pos = start;
+            return Err(self.error("invalid number"));
+        }
+        Ok(literal.to_owned())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// The code point of a `\u` escape (after the `u`), combining a
+    /// surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.text[self.pos..].starts_with("\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid code point"))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("truncated \\u escape"))?;
+        let value =
+            u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    /// Consumes an array, validating but discarding its contents.
+    fn skip_array(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_JSON_DEPTH {
+            return Err(self.error("arrays nested too deeply"));
+        }
+        self.expect(b'[')?;
+        self.skip_ws();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            self.depth -= 1;
+            return Ok(());
+        }
+        loop {
+            self.skip_ws();
+            if self.peek() == Some(b'{') {
+                // Parsed against a throwaway collector: objects inside an
+                // array are not imported either.
+                let mut discard = Collector::new("")?;
+                self.object(&mut Vec::new(), &mut discard)?;
+            } else {
+                self.value()?;
+            }
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    self.depth -= 1;
+                    return Ok(());
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(import: &Import) -> Vec<(&str, &str)> {
+        import
+            .properties
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_import_env() {
+        let text = "\
+# daemon defaults
+LOG_LEVEL=debug
+export DB__HOST = db1.local   # primary
+DB__PORT=\"5432\"
+GREETING='hello # not a comment'
+ESCAPED=\"a \\\"b\\\" \\$HOME\\nc\"
+Db__Host=duplicate
+BAD..KEY=x
+";
+        let import = import_env("vendor.app", text).unwrap();
+        assert_eq!(
+            pairs(&import),
+            [
+                ("vendor.app.log_level", "debug"),
+                ("vendor.app.db.host", "db1.local"),
+                ("vendor.app.db.port", "5432"),
+                ("vendor.app.greeting", "hello # not a comment"),
+                ("vendor.app.escaped", "a \"b\" $HOME\nc"),
+                ("vendor.app.bad__key", "x"),
+            ]
+        );
+        assert_eq!(
+            import.collisions,
+            [Collision {
+                name: "vendor.app.db.host".into(),
+                kept: "DB__HOST".into(),
+                dropped: "Db__Host".into(),
+            }]
+        );
+        assert!(import.skipped.is_empty());
+
+        assert!(matches!(
+            import_env("vendor.app", "OK=1\nNOT A PAIR\n"),
+            Err(Error::Parse(msg)) if msg.contains("line 2")
+        ));
+        assert!(matches!(
+            import_env("vendor.app", "A=\"open\n"),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            import_env("bad..prefix", ""),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_json() {
+        let text = r#"{
+            "db": {"host": "db1", "max conns": 8, "tls": true},
+            "db.host": "again",
+            "tags": ["a", {"b": 1}],
+            "unset": null,
+            "name": "café 😀",
+            "huge": "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"
+        }"#;
+        let import = import_json("vendor.app", text).unwrap();
+        assert_eq!(
+            pairs(&import),
+            [
+                ("vendor.app.db.host", "db1"),
+                ("vendor.app.db.max_conns", "8"),
+                ("vendor.app.db.tls", "true"),
+                ("vendor.app.name", "café 😀"),
+            ]
+        );
+        assert_eq!(import.collisions.len(), 1);
+        assert_eq!(import.collisions[0].dropped, "db.host");
+        let skipped: Vec<&str> = import.skipped.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(skipped, ["tags", "unset", "huge"]);
+
+        for bad in ["[]", "{\"a\": }", "{\"a\": 1,}", "{\"a\": 01x}", "{} x"] {
+            assert!(
+                matches!(import_json("p", bad), Err(Error::Parse(_))),
+                "{bad:?} must be rejected"
+            );
+        }
+        let deep = "{\"a\":".repeat(MAX_JSON_DEPTH + 1);
+        assert!(import_json("p", &deep).is_err());
+    }
+
+    #[test]
+    fn test_export_roundtrip() {
+        let props = [
+            ("vendor.app.db.host", "db1"),
+            ("vendor.app.log_level", "say \"hi\" $USER\n"),
+            ("vendor.app.odd-name", "x"),
+            ("vendor.other.key", "not exported"),
+        ];
+
+        let env = export_env("vendor.app", props);
+        assert_eq!(
+            env.text,
+            "DB__HOST=\"db1\"\nLOG_LEVEL=\"say \\\"hi\\\" \\$USER\\n\"\n"
+        );
+        assert_eq!(env.skipped.len(), 1);
+        assert_eq!(env.skipped[0].key, "vendor.app.odd-name");
+        let back = import_env("vendor.app", &env.text).unwrap();
+        assert_eq!(pairs(&back), props[..2]);
+
+        let json = export_json("vendor.app", props);
+        assert!(json.skipped.is_empty());
+        let back = import_json("vendor.app", &json.text).unwrap();
+        assert_eq!(pairs(&back), props[..3]);
+    }
+
+    #[test]
+    fn test_import_json_dump() {
+        let props = [
+            ("persist.sys.Locale", "en-US"),
+            ("ro.build.fingerprint", "vendor/device:14"),
+            ("vendor.app.odd-name", "x"),
+        ];
+        let dump = export_json("", props);
+        let back = import_json_dump(&dump.text).unwrap();
+        assert_eq!(pairs(&back), props);
+        assert!(back.collisions.is_empty() && back.skipped.is_empty());
+
+        let back = import_json_dump(r#"{"ro": {"a": "1"}, "bad..name": "2"}"#).unwrap();
+        assert_eq!(pairs(&back), [("ro.a", "1")]);
+        assert_eq!(back.skipped[0].key, "bad..name");
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Replaying the changes of one property store into another.
+//!
+//! A [`Mirror`] follows a source [`SystemProperties`] and copies selected
+//! properties into a destination [`MirrorSink`]: a builder instance
+//! writing another directory, a [`crate::ScratchProperties`] staging copy,
+//! or the property service through [`ServiceSink`]. Typical uses are
+//! bridging a container's private properties to a few host properties and
+//! keeping a staging directory in step with a live one.
+//!
+//! # Rules
+//!
+//! Only names matching a rule are mirrored. [`Mirror::include`] copies a
+//! prefix under the same name, [`Mirror::rewrite`] swaps the source
+//! prefix for another one, and [`Mirror::exclude`] drops a prefix; the
+//! first matching rule decides, so list exclusions first.
+//!
+//! | rule                                   | source            | destination        |
+//! |----------------------------------------|-------------------|--------------------|
+//! | `include("vendor.app.")`               | `vendor.app.mode` | `vendor.app.mode`  |
+//! | `rewrite("vendor.", "guest1.vendor.")` | `vendor.app.mode` | `guest1.vendor.app.mode` |
+//!
+//! # Conflicts
+//!
+//! A destination property *conflicts* when its value differs both from
+//! the source's and from what the mirror last wrote there: it was set by
+//! someone else, or already existed before the first sync. The
+//! [`ConflictPolicy`] decides whether the source overwrites it. Properties
+//! cannot be deleted, so a source property that disappears (a restarted
+//! source store) leaves its destination copy in place.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::errors::*;
+use crate::frozen::FrozenProperties;
+use crate::system_properties::SystemProperties;
+use crate::wire::is_valid_property_name;
+
+/// A store a [`Mirror`] writes into.
+pub trait MirrorSink {
+    /// Current value of `name`; `None` when it is not set.
+    fn get(&mut self, name: &str) -> Result<Option<String>>;
+
+    /// Creates or updates `name`.
+    fn set(&mut self, name: &str, value: &str) -> Result<()>;
+}
+
+#[cfg(any(feature = "writer", feature = "service-protocol"))]
+fn found(result: Result<String>) -> Result<Option<String>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(feature = "writer")]
+impl MirrorSink for SystemProperties {
+    fn get(&mut self, name: &str) -> Result<Option<String>> {
+        found(self.get_with_result(name))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        SystemProperties::set(self, name, value)
+    }
+}
+
+#[cfg(feature = "writer")]
+impl MirrorSink for crate::ScratchProperties {
+    fn get(&mut self, name: &str) -> Result<Option<String>> {
+        found(self.get_with_result(name))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        crate::ScratchProperties::set(self, name, value)
+    }
+}
+
+/// Writes through the property service with [`crate::set`] and reads the
+/// global instance ([`crate::system_properties()`]), like any client.
+#[cfg(feature = "service-protocol")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ServiceSink;
+
+#[cfg(feature = "service-protocol")]
+impl MirrorSink for ServiceSink {
+    fn get(&mut self, name: &str) -> Result<Option<String>> {
+        found(crate::try_system_properties()?.get_with_result(name))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        crate::set(name, value)
+    }
+}
+
+/// What happens to a destination property that conflicts (see the
+/// [module documentation](self#conflicts)).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The source value is written anyway.
+    #[default]
+    SourceWins,
+    /// The destination keeps its value until it matches the source's or
+    /// the mirror's last write again.
+    DestinationWins,
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Rewrite { from: String, to: String },
+    Exclude(String),
+}
+
+/// Result of one [`Mirror::sync`].
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct MirrorReport {
+    /// Destination names written.
+    pub applied: Vec<String>,
+    /// Destination names that conflicted; written under
+    /// [`ConflictPolicy::SourceWins`] (and listed in `applied` too),
+    /// left alone under [`ConflictPolicy::DestinationWins`].
+    pub conflicts: Vec<String>,
+    /// Destination names that could not be written, with the reason.
+    pub failed: Vec<(String, Error)>,
+}
+
+impl MirrorReport {
+    /// `true` when nothing was written, conflicted or failed.
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.conflicts.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Copies changes of a source store into a [`MirrorSink`]; see the
+/// [module documentation](self).
+///
+/// The first [`Self::sync`] copies every matching property; later ones
+/// only those whose source value changed since the previous sync.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "service-protocol")]
+/// # fn main() {
+/// use rsproperties::mirror::{ConflictPolicy, Mirror, ServiceSink};
+/// use rsproperties::SystemProperties;
+/// use std::path::Path;
+///
+/// let guest = SystemProperties::open(Path::new("/run/guest1/properties")).unwrap();
+/// let mut mirror = Mirror::new()
+///     .exclude("vendor.app.secret.")
+///     .rewrite("vendor.app.", "guest1.vendor.app.")
+///     .conflict_policy(ConflictPolicy::DestinationWins);
+/// loop {
+///     if let Some(report) = mirror.wait_and_sync(&guest, &mut ServiceSink, None).unwrap() {
+///         for (name, e) in &report.failed {
+///             eprintln!("{name}: {e}");
+///         }
+///     }
+/// }
+/// # }
+/// # #[cfg(not(feature = "service-protocol"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Default)]
+pub struct Mirror {
+    rules: Vec<Rule>,
+    policy: ConflictPolicy,
+    /// Source snapshot of the previous sync; `None` before the first.
+    previous: Option<FrozenProperties>,
+    /// Value last written to each destination name.
+    written: HashMap<String, String>,
+}
+
+impl Mirror {
+    /// A mirror without rules, which copies nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mirrors properties under `prefix` under the same name. An empty
+    /// prefix matches every property.
+    pub fn include(self, prefix: &str) -> Self {
+        self.rewrite(prefix, prefix)
+    }
+
+    /// Mirrors properties under `from` with `from` replaced by `to`.
+    pub fn rewrite(mut self, from: &str, to: &str) -> Self {
+        self.rules.push(Rule::Rewrite {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        });
+        self
+    }
+
+    /// Does not mirror properties under `prefix`.
+    pub fn exclude(mut self, prefix: &str) -> Self {
+        self.rules.push(Rule::Exclude(prefix.to_owned()));
+        self
+    }
+
+    /// Sets the [`ConflictPolicy`].
+    pub fn conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Destination name of source property `name`, or `None` when no rule
+    /// mirrors it.
+    pub fn map_name(&self, name: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| match rule {
+            Rule::Rewrite { from, to } => name
+                .strip_prefix(from.as_str())
+                .map(|rest| Some(format!("{to}{rest}"))),
+            Rule::Exclude(prefix) => name.starts_with(prefix.as_str()).then_some(None),
+        })?
+    }
+
+    /// Copies the source properties that changed since the previous sync
+    /// (all of them on the first) into `sink`.
+    ///
+    /// Failures to write single properties are collected in the report
+    /// and retried on the next sync; an error is returned only when the
+    /// source cannot be read or `sink` cannot be queried.
+    pub fn sync(
+        &mut self,
+        source: &SystemProperties,
+        sink: &mut dyn MirrorSink,
+    ) -> Result<MirrorReport> {
+        let current = source.freeze()?;
+        let mut report = MirrorReport::default();
+        let mut retry = Vec::new();
+        for (name, value) in current.iter() {
+            if self
+                .previous
+                .as_ref()
+                .is_some_and(|previous| previous.get(name) == Some(value))
+            {
+                continue;
+            }
+            let Some(target) = self.map_name(name) else {
+                continue;
+            };
+            if !is_valid_property_name(&target) {
+                report.failed.push((
+                    target.clone(),
+                    Error::InvalidArgument(format!(
+                        "'{name}' maps to invalid property name '{target}'"
+                    )),
+                ));
+                retry.push(name.to_owned());
+                continue;
+            }
+
+            let existing = sink.get(&target)?;
+            if existing.as_deref() == Some(value) {
+                self.written.insert(target, value.to_owned());
+                continue;
+            }
+            let diverged = match (self.written.get(&target), &existing) {
+                (Some(written), Some(existing)) => written != existing,
+                (Some(_), None) => false,
+                (None, existing) => existing.as_deref().is_some_and(|v| !v.is_empty()),
+            };
+            if diverged {
+                report.conflicts.push(target.clone());
+                if self.policy == ConflictPolicy::DestinationWins {
+                    continue;
+                }
+            }
+            match sink.set(&target, value) {
+                Ok(()) => {
+                    self.written.insert(target.clone(), value.to_owned());
+                    report.applied.push(target);
+                }
+                Err(e) => {
+                    log::warn!("Failed to mirror {name} to {target}: {e}");
+                    report.failed.push((target, e));
+                    retry.push(name.to_owned());
+                }
+            }
+        }
+
+        // Failed names are dropped from the snapshot, so the next sync
+        // sees them as changed and tries again.
+        self.previous = Some(if retry.is_empty() {
+            current
+        } else {
+            let serial = current.serial();
+            let mut map = current.into_map();
+            for name in retry {
+                map.remove(&name);
+            }
+            FrozenProperties::new(map, serial)
+        });
+        if !report.is_empty() {
+            log::debug!(
+                "Mirrored {} properties ({} conflicts, {} failed)",
+                report.applied.len(),
+                report.conflicts.len(),
+                report.failed.len()
+            );
+        }
+        Ok(report)
+    }
+
+    /// Waits until the source changes after the previous sync, or
+    /// `timeout` elapses, then [`Self::sync`]s. Returns `Ok(None)` on a
+    /// timeout; before the first sync it syncs without waiting.
+    pub fn wait_and_sync(
+        &mut self,
+        source: &SystemProperties,
+        sink: &mut dyn MirrorSink,
+        timeout: Option<Duration>,
+    ) -> Result<Option<MirrorReport>> {
+        if let Some(previous) = &self.previous {
+            let serial = previous.serial();
+            let timeout = timeout.map(|t| crate::Timespec {
+                tv_sec: t.as_secs() as _,
+                tv_nsec: t.subsec_nanos() as _,
+            });
+            // `None` is a timeout or a wait failure; the serial tells
+            // them apart from a change that raced the wait.
+            if source.wait(None, Some(serial), timeout.as_ref()).is_none()
+                && source.context_serial() == serial
+            {
+                return Ok(None);
+            }
+        }
+        self.sync(source, sink).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_name() {
+        let mirror = Mirror::new()
+            .exclude("vendor.app.secret.")
+            .rewrite("vendor.app.", "guest1.vendor.app.")
+            .include("persist.");
+        assert_eq!(
+            mirror.map_name("vendor.app.mode").as_deref(),
+            Some("guest1.vendor.app.mode")
+        );
+        assert_eq!(mirror.map_name("vendor.app.secret.key"), None);
+        assert_eq!(
+            mirror.map_name("persist.sys.tz").as_deref(),
+            Some("persist.sys.tz")
+        );
+        assert_eq!(mirror.map_name("ro.build.id"), None);
+        assert_eq!(Mirror::new().map_name("ro.build.id"), None);
+        assert_eq!(
+            Mirror::new().include("").map_name("ro.build.id").as_deref(),
+            Some("ro.build.id")
+        );
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-process property namespace claims, see [`reserve_prefix`].
+//!
+//! Claims are kept in an append-only list of leaked nodes linked through
+//! `OnceLock`s: reserving walks the list and appends at the tail, and a
+//! racing append only makes the walk continue past the new node. No lock
+//! is involved, so the registry stays usable on both sides of `fork()`.
+
+#[cfg(feature = "service-protocol")]
+use std::cell::Cell;
+use std::panic::Location;
+use std::sync::OnceLock;
+
+use crate::errors::*;
+use crate::wire::validate_property_name;
+
+/// Property [`publish_prefix_claims`] writes this process's claims to, as
+/// comma-separated prefixes.
+pub const PREFIX_CLAIMS_PROPERTY: &str = "debug.rsproperties.prefix_claims";
+
+struct Node {
+    prefix: String,
+    owner: &'static Location<'static>,
+    next: OnceLock<&'static Node>,
+}
+
+static HEAD: OnceLock<&'static Node> = OnceLock::new();
+
+#[cfg(feature = "service-protocol")]
+thread_local! {
+    /// Claim whose [`PrefixClaim::set`] is running on this thread.
+    static SETTING_THROUGH: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+fn claims() -> impl Iterator<Item = &'static Node> {
+    std::iter::successors(HEAD.get().copied(), |node| node.next.get().copied())
+}
+
+fn overlaps(a: &str, b: &str) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+/// A namespace reserved with [`reserve_prefix`]; claims last for the life
+/// of the process.
+#[derive(Debug, Clone, Copy)]
+pub struct PrefixClaim {
+    node: &'static Node,
+}
+
+impl std::fmt::Debug for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Node")
+            .field("prefix", &self.prefix)
+            .field("owner", &self.owner)
+            .finish()
+    }
+}
+
+impl PrefixClaim {
+    /// The reserved prefix, ending in `.`.
+    pub fn prefix(&self) -> &'static str {
+        &self.node.prefix
+    }
+
+    /// Where the claim was made, identifying the component that owns it.
+    pub fn owner(&self) -> &'static Location<'static> {
+        self.node.owner
+    }
+
+    /// Whether `name` lies in the reserved namespace.
+    pub fn contains(&self, name: &str) -> bool {
+        name.starts_with(self.prefix())
+    }
+
+    /// Sets `name` like [`crate::set`], after checking that it lies in the
+    /// reserved namespace; a name outside it fails with
+    /// [`Error::InvalidArgument`] without being sent.
+    #[cfg(feature = "service-protocol")]
+    pub fn set<T: std::fmt::Display + ?Sized>(&self, name: &str, value: &T) -> Result<()> {
+        if !self.contains(name) {
+            let e = Error::InvalidArgument(format!(
+                "'{name}' is outside the namespace '{}' reserved at {}",
+                self.prefix(),
+                self.owner()
+            ));
+            log::error!("setprop reject: {e}");
+            return Err(e);
+        }
+        let previous = SETTING_THROUGH.with(|c| c.replace(Some(self.prefix())));
+        let result = crate::set(name, value);
+        SETTING_THROUGH.with(|c| c.set(previous));
+        result
+    }
+}
+
+/// Reserves the property namespace `prefix` (e.g. `"com.mylib."`) for the
+/// calling component, so two libraries in one process cannot silently
+/// share property names.
+///
+/// The claim is recorded with the caller's source location as its owner.
+/// Reserving a prefix that overlaps one claimed at a different location —
+/// equal, or one a prefix of the other — fails with
+/// [`Error::InvalidArgument`] naming both owners; reserving the same
+/// prefix again from the same location returns the existing claim, so
+/// initialization code may run more than once.
+///
+/// Set through [`PrefixClaim::set`] to have names checked against the
+/// claim. In debug builds, [`crate::set`] also warns when a name inside a
+/// claimed namespace is set without going through its claim — another
+/// component writing into it. Claims are per process; see
+/// [`publish_prefix_claims`] to make them visible to others.
+#[track_caller]
+pub fn reserve_prefix(prefix: &str) -> Result<PrefixClaim> {
+    let owner = Location::caller();
+    let Some(stem) = prefix.strip_suffix('.') else {
+        return Err(Error::InvalidArgument(format!(
+            "prefix '{prefix}' must end with '.'"
+        )));
+    };
+    validate_property_name(stem)?;
+
+    let mut new: Option<&'static Node> = None;
+    let mut cursor = &HEAD;
+    loop {
+        if let Some(node) = cursor.get().copied() {
+            if overlaps(&node.prefix, prefix) {
+                if node.prefix == prefix && node.owner == owner {
+                    return Ok(PrefixClaim { node });
+                }
+                let e = Error::InvalidArgument(format!(
+                    "prefix '{prefix}' reserved at {owner} overlaps '{}' reserved at {}",
+                    node.prefix, node.owner
+                ));
+                log::warn!("{e}");
+                return Err(e);
+            }
+            cursor = &node.next;
+            continue;
+        }
+        let node = *new.get_or_insert_with(|| {
+            Box::leak(Box::new(Node {
+                prefix: prefix.to_owned(),
+                owner,
+                next: OnceLock::new(),
+            }))
+        });
+        // A racing append wins the slot; keep walking past it. (A node
+        // allocated for a reservation that then fails stays leaked; that
+        // takes a race between overlapping claims.)
+        if cursor.set(node).is_ok() {
+            log::debug!("Reserved property prefix '{prefix}' at {owner}");
+            return Ok(PrefixClaim { node });
+        }
+    }
+}
+
+/// Every namespace reserved in this process, in reservation order.
+pub fn prefix_claims() -> Vec<PrefixClaim> {
+    claims().map(|node| PrefixClaim { node }).collect()
+}
+
+/// Writes this process's claimed prefixes to [`PREFIX_CLAIMS_PROPERTY`]
+/// through the property service, for tools inspecting a running system.
+/// The property holds the claims of whichever process published last.
+#[cfg(feature = "service-protocol")]
+pub fn publish_prefix_claims() -> Result<()> {
+    let value = claims()
+        .map(|node| node.prefix.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    crate::set(PREFIX_CLAIMS_PROPERTY, &value)
+}
+
+/// Debug-build check in [`crate::set`]: warns when `name` lies in a
+/// claimed namespace and is not being set through that claim.
+#[cfg(all(debug_assertions, feature = "service-protocol"))]
+pub(crate) fn check_unclaimed_set(name: &str) {
+    let through = SETTING_THROUGH.with(Cell::get);
+    for node in claims() {
+        if name.starts_with(node.prefix.as_str()) && through != Some(node.prefix.as_str()) {
+            log::warn!(
+                "'{name}' is set outside the claim on '{}' reserved at {}",
+                node.prefix,
+                node.owner
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reserve_twice(prefix: &str) -> (Result<PrefixClaim>, Result<PrefixClaim>) {
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            results.push(reserve_prefix(prefix));
+        }
+        let second = results.pop().unwrap();
+        (results.pop().unwrap(), second)
+    }
+
+    #[test]
+    fn test_reserve_prefix() {
+        let claim = reserve_prefix("test.registry.lib_a.").unwrap();
+        assert_eq!(claim.prefix(), "test.registry.lib_a.");
+        assert_eq!(claim.owner().file(), file!());
+        assert!(claim.contains("test.registry.lib_a.mode"));
+        assert!(!claim.contains("test.registry.lib_b.mode"));
+
+        // Same call site: the existing claim.
+        let (first, again) = reserve_twice("test.registry.lib_c.");
+        assert!(std::ptr::eq(first.unwrap().node, again.unwrap().node));
+
+        // Overlapping claims from elsewhere. (Other tests reserve under
+        // "test.registry." too, so only the exact ones name lib_a.)
+        for prefix in ["test.registry.lib_a.", "test.registry.lib_a.sub."] {
+            let e = reserve_prefix(prefix).unwrap_err();
+            assert!(matches!(&e, Error::InvalidArgument(msg) if msg.contains("lib_a")));
+        }
+        assert!(matches!(
+            reserve_prefix("test.registry."),
+            Err(Error::InvalidArgument(_))
+        ));
+        // Disjoint siblings are fine, and bad prefixes are refused.
+        reserve_prefix("test.registry.lib_ab.").unwrap();
+        assert!(reserve_prefix("test.registry.lib_d").is_err());
+        assert!(reserve_prefix("test..registry.").is_err());
+
+        let claimed: Vec<_> = prefix_claims().iter().map(|c| c.prefix()).collect();
+        assert!(claimed.contains(&"test.registry.lib_ab."));
+    }
+
+    #[cfg(feature = "service-protocol")]
+    #[test]
+    fn test_claim_set_checks_namespace() {
+        let claim = reserve_prefix("test.registry.lib_e.").unwrap();
+        assert!(matches!(
+            claim.set("test.registry.other", "1"),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_reservations() {
+        let handles: Vec<_> = (0..8)
+            .map(|i| std::thread::spawn(move || reserve_prefix(&format!("test.race.lib{i}."))))
+            .collect();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        let racing = std::thread::scope(|s| {
+            let a = s.spawn(|| reserve_prefix("test.race.shared."));
+            let b = s.spawn(|| reserve_prefix("test.race.shared."));
+            [a.join().unwrap().is_ok(), b.join().unwrap().is_ok()]
+        });
+        // Two different call sites: exactly one wins.
+        assert_eq!(racing.iter().filter(|ok| **ok).count(), 1);
+    }
+}
@@ -0,0 +1,32 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The types most programs name, for a glob import.
+//!
+//! ```
+//! use rsproperties::prelude::*;
+//!
+//! let config = PropertyConfig::builder()
+//!     .properties_dir("/dev/__properties__")
+//!     .build();
+//! let name = PropertyName::new("persist.sys.locale").unwrap();
+//! let context = SelinuxContext::new("u:object_r:system_prop:s0").unwrap();
+//! # let _ = (config, name, context);
+//! ```
+//!
+//! `Result` is left out on purpose: the crate's alias takes one type
+//! parameter and would shadow `std`'s for the importing module. Use
+//! `rsproperties::Result` by path.
+
+pub use crate::errors::{Error, SetError, SetErrorKind};
+pub use crate::wire::{NamePolicy, PropertyName};
+pub use crate::{
+    Backing, ByteSize, ConfigBinder, ConfigUpdate, FrozenProperties, HostPort, Layout,
+    PropertiesClient, PropertyConfig, PropertyConfigBuilder, PropertyDuration, PropertyHandle,
+    PropertySnapshot, SelinuxContext, SystemProperties, Timespec,
+};
+
+#[cfg(feature = "service-protocol")]
+pub use crate::CoalescingSetter;
+#[cfg(feature = "writer")]
+pub use crate::ScratchProperties;
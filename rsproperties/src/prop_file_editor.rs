@@ -0,0 +1,289 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-place editing of build.prop-style files, see [`PropFileEditor`].
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::errors::*;
+use crate::wire::validate_property_name;
+
+#[derive(Debug, Clone)]
+enum Line {
+    /// Comments, blank lines, `import` lines and anything else that is not
+    /// an entry, byte for byte.
+    Verbatim(Vec<u8>),
+    Entry {
+        key: String,
+        value: String,
+        /// The original text, written back as long as the entry is
+        /// unchanged; `None` once it was set.
+        raw: Option<Vec<u8>>,
+        crlf: bool,
+    },
+}
+
+/// A build.prop-style file loaded for editing.
+///
+/// Lines are recognized the way [`crate::load_properties_from_file`]
+/// reads them — `key=value` with surrounding whitespace trimmed, `#`
+/// comments — but `import` lines are not followed, and everything that is
+/// not an entry is kept byte for byte, non-UTF-8 text included. Writing the
+/// file back reproduces every untouched line exactly, so an image
+/// customization step can patch a few keys in a human-maintained file
+/// without reformatting it.
+///
+/// A key may occur several times; the last occurrence is the one that
+/// takes effect on load, and the one [`Self::get`] and [`Self::set`]
+/// address.
+///
+/// ```rust,no_run
+/// use rsproperties::PropFileEditor;
+/// use std::path::Path;
+///
+/// let path = Path::new("out/vendor/build.prop");
+/// let mut editor = PropFileEditor::load(path).unwrap();
+/// editor.set("ro.product.vendor.model", "Board X").unwrap();
+/// editor.remove("ro.debuggable");
+/// editor.save(path).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PropFileEditor {
+    lines: Vec<Line>,
+    /// The last line ends with a newline (or the file is empty).
+    trailing_newline: bool,
+}
+
+impl PropFileEditor {
+    /// Reads and parses `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).context_with_location(format!("Failed to read {path:?}"))?;
+        Ok(Self::parse(&bytes))
+    }
+
+    /// Parses the contents of a prop file. Never fails: lines that are not
+    /// entries are kept as they are.
+    pub fn parse(bytes: &[u8]) -> Self {
+        let trailing_newline = bytes.is_empty() || bytes.ends_with(b"\n");
+        let body = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+        let lines = if bytes.is_empty() {
+            Vec::new()
+        } else {
+            body.split(|&b| b == b'\n').map(parse_line).collect()
+        };
+        Self {
+            lines,
+            trailing_newline,
+        }
+    }
+
+    /// Value of the effective (last) occurrence of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entry_index(key).map(|index| match &self.lines[index] {
+            Line::Entry { value, .. } => value.as_str(),
+            Line::Verbatim(_) => unreachable!("entry_index only returns entries"),
+        })
+    }
+
+    /// Sets `key`: rewrites its effective occurrence in place, or appends
+    /// `key=value` at the end of the file when the key is new.
+    ///
+    /// `key` must be a valid property name; a value spanning lines is
+    /// refused, as it would turn into a second, unintended line.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        validate_property_name(key)?;
+        if value.contains(['\n', '\r']) {
+            return Err(Error::InvalidArgument(format!(
+                "value for '{key}' spans several lines"
+            )));
+        }
+        let value = value.trim().to_owned();
+        match self.entry_index(key) {
+            Some(index) => {
+                if let Line::Entry {
+                    value: current,
+                    raw,
+                    ..
+                } = &mut self.lines[index]
+                {
+                    if *current != value {
+                        *current = value;
+                        *raw = None;
+                    }
+                }
+            }
+            None => {
+                // A new last line: the old last one needs its newline.
+                self.trailing_newline = true;
+                self.lines.push(Line::Entry {
+                    key: key.to_owned(),
+                    value,
+                    raw: None,
+                    crlf: false,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every occurrence of `key`, returning whether there was one.
+    /// Comments above the removed lines stay.
+    pub fn remove(&mut self, key: &str) -> bool {
+        let before = self.lines.len();
+        self.lines
+            .retain(|line| !matches!(line, Line::Entry { key: k, .. } if k == key));
+        self.lines.len() != before
+    }
+
+    /// Every entry, in file order, duplicates included.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => Some((key.as_str(), value.as_str())),
+            Line::Verbatim(_) => None,
+        })
+    }
+
+    /// The file contents.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                out.push(b'\n');
+            }
+            match line {
+                Line::Verbatim(bytes)
+                | Line::Entry {
+                    raw: Some(bytes), ..
+                } => out.extend_from_slice(bytes),
+                Line::Entry {
+                    key,
+                    value,
+                    raw: None,
+                    crlf,
+                } => {
+                    out.extend_from_slice(key.as_bytes());
+                    out.push(b'=');
+                    out.extend_from_slice(value.as_bytes());
+                    if *crlf {
+                        out.push(b'\r');
+                    }
+                }
+            }
+        }
+        if self.trailing_newline && !self.lines.is_empty() {
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// Writes the file to `path` through a temporary file in the same
+    /// directory, so readers see either the old or the new contents. The
+    /// permissions of an existing file are kept.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(format!(".tmp-{}", std::process::id()));
+        let tmp = std::path::PathBuf::from(tmp);
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&tmp)?;
+            file.write_all(&self.to_bytes())?;
+            if let Ok(metadata) = std::fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+            file.sync_all()?;
+            std::fs::rename(&tmp, path)
+        };
+        write().or_else(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e).context_with_location(format!("Failed to write {path:?}"))
+        })
+    }
+
+    fn entry_index(&self, key: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|line| matches!(line, Line::Entry { key: k, .. } if k == key))
+    }
+}
+
+fn parse_line(raw: &[u8]) -> Line {
+    let entry = std::str::from_utf8(raw).ok().and_then(|text| {
+        let line = text.trim();
+        if line.starts_with('#') || line.starts_with("import ") {
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        let key = key.trim_end();
+        (!key.is_empty()).then(|| (key.to_owned(), value.trim().to_owned()))
+    });
+    match entry {
+        Some((key, value)) => Line::Entry {
+            key,
+            value,
+            raw: Some(raw.to_vec()),
+            crlf: raw.ends_with(b"\r"),
+        },
+        None => Line::Verbatim(raw.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &[u8] = b"# begin build properties\n\
+        \n\
+        ro.build.id = AP1A\n\
+        import /vendor/default.prop\n\
+        ro.product.model=Pixel\n\
+        # comment \xff with a stray byte\n\
+        persist.sys.usb.config=mtp\n\
+        persist.sys.usb.config=adb\r\n\
+        odd line without equals\n";
+
+    #[test]
+    fn test_roundtrip_is_exact() {
+        let editor = PropFileEditor::parse(SAMPLE);
+        assert_eq!(editor.to_bytes(), SAMPLE);
+        assert_eq!(PropFileEditor::parse(b"").to_bytes(), b"");
+        assert_eq!(PropFileEditor::parse(b"a=b").to_bytes(), b"a=b");
+        assert_eq!(editor.get("ro.build.id"), Some("AP1A"));
+        assert_eq!(editor.get("persist.sys.usb.config"), Some("adb"));
+        assert_eq!(editor.entries().count(), 4);
+    }
+
+    #[test]
+    fn test_edits_keep_structure() {
+        let mut editor = PropFileEditor::parse(SAMPLE);
+        editor.set("ro.product.model", "Board X").unwrap();
+        editor.set("persist.sys.usb.config", "none").unwrap();
+        editor.set("ro.build.id", "AP1A").unwrap();
+        assert!(editor.remove("ro.product.model"));
+        assert!(!editor.remove("ro.product.model"));
+        editor.set("ro.new.key", "1").unwrap();
+
+        let expected: &[u8] = b"# begin build properties\n\
+            \n\
+            ro.build.id = AP1A\n\
+            import /vendor/default.prop\n\
+            # comment \xff with a stray byte\n\
+            persist.sys.usb.config=mtp\n\
+            persist.sys.usb.config=none\r\n\
+            odd line without equals\n\
+            ro.new.key=1\n";
+        assert_eq!(editor.to_bytes(), expected);
+
+        let mut unterminated = PropFileEditor::parse(b"a.b=1");
+        unterminated.set("c.d", "2").unwrap();
+        assert_eq!(unterminated.to_bytes(), b"a.b=1\nc.d=2\n");
+    }
+
+    #[test]
+    fn test_set_rejects_bad_input() {
+        let mut editor = PropFileEditor::default();
+        assert!(editor.set("bad key", "1").is_err());
+        assert!(editor.set("a.b", "1\nc.d=2").is_err());
+        assert_eq!(editor.entries().count(), 0);
+    }
+}
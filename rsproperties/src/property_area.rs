@@ -4,20 +4,23 @@
 use std::{
     ffi::CStr,
     fmt::Debug,
-    fs::{File, OpenOptions},
+    fs::{File, Metadata, OpenOptions},
     mem,
-    os::unix::fs::OpenOptionsExt,
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
     path::Path,
     sync::atomic::AtomicU32,
 };
 
 use crate::errors::*;
 use log::{debug, error, info, warn};
-use rustix::{fs, mm};
+use rustix::fs;
 
+use crate::checksum::{self, ChecksumTable, RecordCheck};
+use crate::lookup_stats::{self, LookupTrace};
 use crate::property_info::PropertyInfo;
+use crate::system_properties::AreaFragmentation;
+use crate::SelinuxContext;
 
-const PA_SIZE: u64 = 128 * 1024;
 const PROP_AREA_MAGIC: u32 = 0x504f5250;
 const PROP_AREA_VERSION: u32 = 0xfc6ed0ab;
 
@@ -65,7 +68,9 @@ const _: () = {
     assert!(mem::offset_of!(PropertyArea, serial) == 4);
     assert!(mem::offset_of!(PropertyArea, magic) == 8);
     assert!(mem::offset_of!(PropertyArea, version) == 12);
-    assert!(mem::offset_of!(PropertyArea, reserved) == 16);
+    assert!(mem::offset_of!(PropertyArea, bump_stamp) == 16);
+    assert!(mem::offset_of!(PropertyArea, context_serials) == 20);
+    assert!(mem::offset_of!(PropertyArea, reserved) == 24);
 };
 
 #[repr(C, align(4))]
@@ -83,7 +88,7 @@ impl PropertyTrieNode {
     /// base pointer — writing them through `&mut self` would step outside
     /// this reference's provenance (it covers exactly
     /// `size_of::<PropertyTrieNode>()` bytes).
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn init_header(&mut self, namelen: u32) {
         self.prop.store(0, std::sync::atomic::Ordering::Relaxed);
         self.left.store(0, std::sync::atomic::Ordering::Relaxed);
@@ -119,15 +124,30 @@ pub(crate) struct PropertyArea {
     serial: AtomicU32,
     magic: u32,
     version: u32,
-    reserved: [u32; 28],
+    /// First word of bionic's `reserved_`: the `wait_stats` stamp of the
+    /// latest change, kept in the serial area only. bionic never reads it.
+    bump_stamp: AtomicU32,
+    /// Second word of bionic's `reserved_`, in the serial area only:
+    /// [`CONTEXT_SERIALS_ENABLED`] once the store's writers bump the
+    /// `serial` of each context area too. bionic leaves those at 0.
+    context_serials: AtomicU32,
+    reserved: [u32; 26],
 }
 
+/// Value of `PropertyArea::context_serials` marking a store with
+/// per-context serials ("CTXS").
+pub(crate) const CONTEXT_SERIALS_ENABLED: u32 = 0x5358_5443;
+
 impl PropertyArea {
     fn init(&mut self, magic: u32, version: u32) {
         self.serial.store(0, std::sync::atomic::Ordering::Relaxed);
         self.magic = magic;
         self.version = version;
-        self.reserved = [0; 28];
+        self.bump_stamp
+            .store(0, std::sync::atomic::Ordering::Relaxed);
+        self.context_serials
+            .store(0, std::sync::atomic::Ordering::Relaxed);
+        self.reserved = [0; 26];
         self.bytes_used = mem::size_of::<PropertyTrieNode>() as _;
         self.bytes_used += crate::bionic_align(crate::PROP_VALUE_MAX, mem::size_of::<u32>()) as u32;
     }
@@ -135,6 +155,23 @@ impl PropertyArea {
     pub(crate) fn serial(&self) -> &AtomicU32 {
         &self.serial
     }
+
+    pub(crate) fn bump_stamp(&self) -> &AtomicU32 {
+        &self.bump_stamp
+    }
+
+    pub(crate) fn context_serials(&self) -> &AtomicU32 {
+        &self.context_serials
+    }
+}
+
+/// `(st_dev, st_ino)` identity of an area file. Compared against a fresh
+/// `lstat` of the path to tell whether the file a mapping was created from
+/// is still the one at that path.
+pub(crate) type FileId = (u64, u64);
+
+pub(crate) fn file_id(metadata: &Metadata) -> FileId {
+    (metadata.dev(), metadata.ino())
 }
 
 #[derive(Debug)]
@@ -142,11 +179,23 @@ pub(crate) struct PropertyAreaMap {
     mmap: MemoryMap,
     data_offset: usize,
     pa_data_size: usize,
+    /// Identity of the file this map was created from; the mmap keeps the
+    /// inode alive even after the path is unlinked or replaced. `None`
+    /// for an area copied from a buffer.
+    file_id: Option<FileId>,
+    /// Per-record CRCs, when the area has a sidecar (see the `checksum`
+    /// module).
+    checksums: Option<ChecksumTable>,
 }
 
 impl PropertyAreaMap {
-    // Initialize the property area map with the given file to create a new property area map.
-    pub(crate) fn new_rw(filename: &Path, context: Option<&CStr>) -> Result<Self> {
+    // Initialize the property area map with the given file to create a new property area map
+    // of `size` bytes (a validated `Layout::area_size`).
+    pub(crate) fn new_rw(
+        filename: &Path,
+        context: Option<&SelinuxContext>,
+        size: usize,
+    ) -> Result<Self> {
         debug!("Creating new read-write property area map: {filename:?}");
 
         // A leftover area file from a previous writer instance would make
@@ -167,6 +216,7 @@ impl PropertyAreaMap {
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
             Err(e) => warn!("Failed to remove stale property area file {filename:?}: {e}"),
         }
+        checksum::remove_stale(&checksum::sidecar_path(filename));
 
         let file = OpenOptions::new()
             .read(true) // O_RDWR
@@ -181,6 +231,9 @@ impl PropertyAreaMap {
             .open(filename)
             .context_with_location(format!("Failed to create property area {filename:?}"))?;
 
+        // macOS has no SELinux (and its `security.*` xattrs mean nothing
+        // to an Android reader): skip labeling there.
+        #[cfg(not(target_os = "macos"))]
         if let Some(context) = context {
             // Full xattr name required — the bare "selinux" (no namespace
             // prefix) is rejected by the kernel with EOPNOTSUPP, which made
@@ -196,7 +249,7 @@ impl PropertyAreaMap {
             if fs::fsetxattr(
                 &file,
                 "security.selinux",
-                context.to_bytes_with_nul(),
+                &context.xattr_value(),
                 fs::XattrFlags::empty(),
             )
             .is_err()
@@ -204,18 +257,49 @@ impl PropertyAreaMap {
                 warn!("Failed to set SELinux context for {filename:?}");
             }
         }
+        #[cfg(target_os = "macos")]
+        let _ = context;
 
-        fs::ftruncate(&file, PA_SIZE)
+        Self::init_rw(file, filename, size)
+    }
+
+    /// A read-write area in a new memfd instead of a file (see
+    /// [`crate::Backing::Memfd`]), sealed against resizing once it is
+    /// initialized. Returns the map and the memfd, to be shared with
+    /// readers; `name` only labels the memfd and log lines.
+    #[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn new_rw_memfd(name: &Path, size: usize) -> Result<(Self, File)> {
+        debug!("Creating read-write memfd property area: {name:?}");
+        let file = crate::memfd_area::create(name)?;
+        let shared = file
+            .try_clone()
+            .context_with_location(format!("Failed to duplicate memfd {name:?}"))?;
+        let thiz = Self::init_rw(file, name, size)?;
+        crate::memfd_area::seal_area(&shared, name)?;
+        Ok((thiz, shared))
+    }
+
+    /// Sizes the freshly created `file` to `size` bytes, maps it
+    /// read-write and writes an empty area header.
+    fn init_rw(file: File, filename: &Path, size: usize) -> Result<Self> {
+        fs::ftruncate(&file, size as u64)
             .map_err(Error::from)
             .context_with_location(format!("Failed to size property area {filename:?}"))?;
 
-        let pa_size = PA_SIZE as usize;
+        let pa_size = size;
         let pa_data_size = pa_size - std::mem::size_of::<PropertyArea>();
+        let file_id = file_id(
+            &file
+                .metadata()
+                .context_with_location(format!("Failed to stat {filename:?}"))?,
+        );
 
         let mut thiz = Self {
             mmap: MemoryMap::new(file, pa_size, true)?,
             data_offset: std::mem::size_of::<PropertyArea>(),
             pa_data_size,
+            file_id: Some(file_id),
+            checksums: None,
         };
 
         thiz.property_area_mut()?
@@ -234,13 +318,45 @@ impl PropertyAreaMap {
     // policy (see `new_rw`) is what rules this out in practice.
     pub(crate) fn new_ro(filename: &Path) -> Result<Self> {
         debug!("Opening read-only property area map: {filename:?}");
+        Self::map_existing(filename, false)
+    }
+
+    // Map an existing property area file copy-on-write (MAP_PRIVATE,
+    // PROT_READ|PROT_WRITE): writes land in pages private to this mapping
+    // and never reach the file or any other mapping of it. Pages not yet
+    // written keep following the file. Same precondition as `new_ro`.
+    #[cfg(feature = "writer")]
+    pub(crate) fn new_cow(filename: &Path) -> Result<Self> {
+        debug!("Opening copy-on-write property area map: {filename:?}");
+        Self::map_existing(filename, true)
+    }
 
+    /// Maps an area memfd received from the writer read-only. The memfd
+    /// must be sealed against shrinking: a reader cannot survive its
+    /// mapping being cut short (see `new_ro`).
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn new_ro_memfd(file: File, name: &Path) -> Result<Self> {
+        debug!("Mapping memfd property area read-only: {name:?}");
+        crate::memfd_area::check_sealed(&file, name)?;
+        Self::map_file(file, name, false, None)
+    }
+
+    fn map_existing(filename: &Path, copy_on_write: bool) -> Result<Self> {
         let file = OpenOptions::new()
             .read(true) // read only
             .custom_flags(fs::OFlags::NOFOLLOW.bits() as _) // additional flags
             .open(filename)
             .context_with_location(format!("Failed to open {filename:?}"))?;
+        let checksums = ChecksumTable::open(&checksum::sidecar_path(filename), copy_on_write);
+        Self::map_file(file, filename, copy_on_write, checksums)
+    }
 
+    fn map_file(
+        file: File,
+        filename: &Path,
+        copy_on_write: bool,
+        checksums: Option<ChecksumTable>,
+    ) -> Result<Self> {
         let metadata = file
             .metadata()
             .context_with_location("Failed to get metadata")?;
@@ -264,12 +380,48 @@ impl PropertyAreaMap {
         let pa_data_size = pa_size - std::mem::size_of::<PropertyArea>();
 
         let thiz = Self {
-            mmap: MemoryMap::new(file, pa_size, false)?,
+            mmap: if copy_on_write {
+                MemoryMap::new_private(file, pa_size)?
+            } else {
+                MemoryMap::new(file, pa_size, false)?
+            },
             data_offset: std::mem::size_of::<PropertyArea>(),
             pa_data_size,
+            file_id: Some(file_id(&metadata)),
+            checksums,
         };
 
-        let pa = thiz.property_area();
+        thiz.check_header(filename)
+    }
+
+    /// A read-only area over a heap copy of `bytes` (see
+    /// `MemoryMap::from_bytes`); `name` only names it in log lines and
+    /// errors.
+    pub(crate) fn from_bytes(bytes: &[u8], name: &Path) -> Result<Self> {
+        debug!("Copying property area from a buffer: {name:?}");
+        let header_size = mem::size_of::<PropertyArea>();
+        if bytes.len() < header_size {
+            let msg = format!(
+                "Buffer too small: size={}, min_size={header_size} for {name:?}",
+                bytes.len()
+            );
+            error!("{msg}");
+            return Err(Error::FileSize(msg));
+        }
+        let thiz = Self {
+            mmap: MemoryMap::from_bytes(bytes)?,
+            data_offset: header_size,
+            pa_data_size: bytes.len() - header_size,
+            file_id: None,
+            checksums: None,
+        };
+        thiz.check_header(name)
+    }
+
+    /// Rejects an area whose header is not a property area of this
+    /// version.
+    fn check_header(self, filename: &Path) -> Result<Self> {
+        let pa = self.property_area();
 
         if pa.magic != PROP_AREA_MAGIC || pa.version != PROP_AREA_VERSION {
             error!(
@@ -280,8 +432,8 @@ impl PropertyAreaMap {
                 "Invalid magic or version".to_string(),
             ))
         } else {
-            info!("Successfully opened read-only property area map: {filename:?}");
-            Ok(thiz)
+            info!("Successfully opened property area map: {filename:?}");
+            Ok(self)
         }
     }
 
@@ -296,6 +448,10 @@ impl PropertyAreaMap {
         self.mmap.writable
     }
 
+    pub(crate) fn file_id(&self) -> Option<FileId> {
+        self.file_id
+    }
+
     // `Result`, not `expect`: offset 0 is always in-bounds/aligned, but
     // `to_object_mut` also fails (by design) on a read-only mapping — that
     // must surface as a typed error, not a panic.
@@ -303,8 +459,43 @@ impl PropertyAreaMap {
         self.mmap.to_object_mut::<PropertyArea>(0, 0)
     }
 
-    // Find the property information with the given name.
-    pub(crate) fn find(&self, name: &str) -> Result<(&PropertyInfo, u32)> {
+    // Find the trie node of `name` and the offset of the `PropertyInfo` it
+    // links to.
+    pub(crate) fn find(&self, name: &str) -> Result<(u32, u32)> {
+        let mut trace = LookupTrace::default();
+        let result = self.find_traced(name, &mut trace);
+        if lookup_stats::enabled() {
+            lookup_stats::record_lookup(&trace, result.is_ok());
+        }
+        result
+    }
+
+    fn find_traced(&self, name: &str, trace: &mut LookupTrace) -> Result<(u32, u32)> {
+        let node_offset = self.find_node(name, trace)? as u32;
+        let prop_offset = self.node_prop(node_offset)?;
+        if prop_offset != 0 {
+            // Checked like every record offset before it is handed out.
+            self.mmap
+                .to_object::<PropertyInfo>(prop_offset as usize, self.data_offset)?;
+            Ok((node_offset, prop_offset))
+        } else {
+            Err(Error::NotFound(name.to_owned()))
+        }
+    }
+
+    // The `PropertyInfo` offset the trie node at `node_offset` links to, 0
+    // if none.
+    pub(crate) fn node_prop(&self, node_offset: u32) -> Result<u32> {
+        Ok(self
+            .mmap
+            .to_object::<PropertyTrieNode>(node_offset as usize, self.data_offset)?
+            .prop
+            .load(std::sync::atomic::Ordering::Acquire))
+    }
+
+    // Walk the trie to the node of `name`, whether or not it holds a
+    // property.
+    fn find_node(&self, name: &str, trace: &mut LookupTrace) -> Result<usize> {
         let mut remaining_name = name;
         let mut current_offset = 0usize;
         loop {
@@ -315,7 +506,8 @@ impl PropertyAreaMap {
             };
 
             if substr_size == 0 {
-                error!("Invalid property name (empty segment): '{name}'");
+                // Returned to the caller, which decides whether to log.
+                debug!("Invalid property name (empty segment): '{name}'");
                 return Err(Error::Parse(format!("Invalid property name: {name}")));
             }
 
@@ -330,7 +522,8 @@ impl PropertyAreaMap {
                 return Err(Error::NotFound(name.to_owned()));
             }
 
-            current_offset = self.find_prop_trie_node(children_offset, subname)? as usize;
+            trace.depth += 1;
+            current_offset = self.find_prop_trie_node(children_offset, subname, trace)? as usize;
 
             if sep.is_none() {
                 break;
@@ -339,24 +532,114 @@ impl PropertyAreaMap {
             remaining_name = &remaining_name[substr_size + 1..];
         }
 
-        let prop_offset = self
-            .mmap
-            .to_object::<PropertyTrieNode>(current_offset, self.data_offset)?
-            .prop
-            .load(std::sync::atomic::Ordering::Acquire);
-        if prop_offset != 0 {
-            Ok((
-                self.mmap
-                    .to_object(prop_offset as usize, self.data_offset)?,
-                prop_offset,
-            ))
-        } else {
-            Err(Error::NotFound(name.to_owned()))
+        Ok(current_offset)
+    }
+
+    /// Collects the `PropertyInfo` offset of every property in the area by
+    /// walking the whole trie (each node's BST siblings and its children).
+    /// The order is unspecified.
+    ///
+    /// Like `find_prop_trie_node`, the walk is bounded by the number of
+    /// nodes that fit in the data region, so a corrupt file that links
+    /// nodes into a cycle fails instead of looping.
+    pub(crate) fn property_offsets(&self) -> Result<Vec<u32>> {
+        use std::sync::atomic::Ordering::Acquire;
+
+        let max_nodes = self.pa_data_size / mem::size_of::<PropertyTrieNode>();
+        let mut offsets = Vec::new();
+        let mut pending = vec![0u32];
+        let mut visited = 0usize;
+        while let Some(node_offset) = pending.pop() {
+            visited += 1;
+            if visited > max_nodes + 1 {
+                return Err(Error::FileValidation(
+                    "Trie node cycle detected (corrupt property area)".into(),
+                ));
+            }
+            let node = self
+                .mmap
+                .to_object::<PropertyTrieNode>(node_offset as usize, self.data_offset)?;
+            let prop = node.prop.load(Acquire);
+            if prop != 0 {
+                offsets.push(prop);
+            }
+            for link in [&node.left, &node.right, &node.children] {
+                let next = link.load(Acquire);
+                if next != 0 {
+                    pending.push(next);
+                }
+            }
         }
+        Ok(offsets)
+    }
+
+    /// Accounts for every allocation reachable from the root, for
+    /// `SystemProperties::fragmentation_report`. Whatever `bytes_used`
+    /// covers beyond that is unreachable. `bytes_used` is read after the
+    /// walk, so an add in flight shows up as unreachable, never as a
+    /// negative count.
+    pub(crate) fn fragmentation(&self, context: String) -> Result<AreaFragmentation> {
+        use std::sync::atomic::Ordering::Acquire;
+
+        let align = |size: usize| crate::bionic_align(size, mem::size_of::<u32>());
+        let mut report = AreaFragmentation {
+            context,
+            capacity: self.pa_data_size,
+            // The root node and the dirty backup slot, laid down by `init`.
+            live: mem::size_of::<PropertyTrieNode>() + align(crate::PROP_VALUE_MAX),
+            ..Default::default()
+        };
+        let account = |report: &mut AreaFragmentation, size: usize| {
+            report.live += size;
+            report.padding += align(size) - size;
+        };
+
+        let max_nodes = self.pa_data_size / mem::size_of::<PropertyTrieNode>();
+        let mut pending = vec![0u32];
+        let mut visited = 0usize;
+        while let Some(node_offset) = pending.pop() {
+            visited += 1;
+            if visited > max_nodes + 1 {
+                return Err(Error::FileValidation(
+                    "Trie node cycle detected (corrupt property area)".into(),
+                ));
+            }
+            let node = self
+                .mmap
+                .to_object::<PropertyTrieNode>(node_offset as usize, self.data_offset)?;
+            if node_offset != 0 {
+                report.trie_nodes += 1;
+                account(
+                    &mut report,
+                    mem::size_of::<PropertyTrieNode>() + node.namelen as usize + 1,
+                );
+            }
+            let prop = node.prop.load(Acquire);
+            if prop != 0 {
+                report.properties += 1;
+                let name_len = self.property_info_name(prop)?.to_bytes().len();
+                account(&mut report, mem::size_of::<PropertyInfo>() + name_len + 1);
+                if self.property_info(prop)?.is_long() {
+                    report.long_values += 1;
+                    let value_len = self.long_property_value(prop)?.len();
+                    account(&mut report, value_len + 1);
+                }
+            }
+            for link in [&node.left, &node.right, &node.children] {
+                let next = link.load(Acquire);
+                if next != 0 {
+                    pending.push(next);
+                }
+            }
+        }
+
+        report.used = self.property_area().bytes_used as usize;
+        report.unreachable = report.used.saturating_sub(report.live + report.padding);
+        Ok(report)
     }
 
     // Add the property information with the given name and value.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn add(&mut self, name: &str, value: &str) -> Result<()> {
         debug!("Adding property: '{name}' = '{value}'");
 
@@ -431,11 +714,60 @@ impl PropertyAreaMap {
                 .to_object::<PropertyTrieNode>(current, self.data_offset)?
                 .prop
                 .store(offset, std::sync::atomic::Ordering::Release);
+            self.record_checksum(offset);
         }
 
         Ok(())
     }
 
+    // Unlink the property with the given name from its trie node and
+    // return the offset of its `PropertyInfo`, or `None` if it is not set.
+    //
+    // Nothing is freed — the area is a bump allocator — so the record,
+    // its name and its long value stay where they are: readers holding its
+    // offset keep reading the last value. A later `add` of the same name
+    // re-uses the trie node and allocates a fresh record, unless the writer
+    // puts the old one back with `relink`.
+    #[cfg(feature = "writer")]
+    pub(crate) fn remove(&mut self, name: &str) -> Result<Option<u32>> {
+        let node_offset = match self.find_node(name, &mut LookupTrace::default()) {
+            Ok(offset) => offset,
+            Err(Error::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // `to_object` does not check writability; the store below would
+        // fault on a read-only mapping.
+        self.mmap.require_writable()?;
+        let prop = &self
+            .mmap
+            .to_object::<PropertyTrieNode>(node_offset, self.data_offset)?
+            .prop;
+        match prop.swap(0, std::sync::atomic::Ordering::AcqRel) {
+            0 => Ok(None),
+            offset => Ok(Some(offset)),
+        }
+    }
+
+    // Link the record at `pi_offset`, which `remove` unlinked from `name`'s
+    // trie node, back into that node. Fails if the node holds a record
+    // again, or if `name`'s node is gone.
+    #[cfg(feature = "writer")]
+    pub(crate) fn relink(&mut self, name: &str, pi_offset: u32) -> Result<()> {
+        let node_offset = self.find_node(name, &mut LookupTrace::default())?;
+        self.mmap.require_writable()?;
+        self.mmap
+            .to_object::<PropertyTrieNode>(node_offset, self.data_offset)?
+            .prop
+            .compare_exchange(
+                0,
+                pi_offset,
+                std::sync::atomic::Ordering::AcqRel,
+                std::sync::atomic::Ordering::Acquire,
+            )
+            .map(drop)
+            .map_err(|_| Error::InvalidArgument(format!("{name} holds a record already")))
+    }
+
     // Snapshot the dirty backup slot into `dst`, byte-wise atomic.
     //
     // The slot is shared per-area and may be concurrently rewritten by
@@ -476,7 +808,7 @@ impl PropertyAreaMap {
     ///
     /// `backup` must hold the entry's current value bytes (snapshotted by
     /// the caller before taking `&mut self`).
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn backup_and_apply_write(
         &mut self,
         pi_offset: u32,
@@ -491,9 +823,74 @@ impl PropertyAreaMap {
         self.property_info_mut(pi_offset)?
             .writer()
             .apply_write(value)?;
+        self.record_checksum(pi_offset);
         Ok(())
     }
 
+    /// Starts keeping per-record checksums in a fresh sidecar at
+    /// `sidecar`, seeded with every record already in the area.
+    #[cfg(feature = "writer")]
+    pub(crate) fn enable_checksums(&mut self, sidecar: &Path) -> Result<()> {
+        self.mmap.require_writable()?;
+        // Every record takes at least a `PropertyInfo`, so this many slots
+        // always suffice.
+        let capacity = self.pa_data_size / mem::size_of::<PropertyInfo>();
+        let table = ChecksumTable::create(sidecar, capacity)?;
+        let mut offsets = self.property_offsets()?;
+        offsets.sort_unstable();
+        self.checksums = Some(table);
+        for offset in offsets {
+            self.record_checksum(offset);
+        }
+        Ok(())
+    }
+
+    /// Updates the checksum of the record at `pi_offset` after a write.
+    /// The value is already published at this point, so a failure is
+    /// logged rather than returned: the record is then merely unchecked.
+    #[cfg(feature = "writer")]
+    fn record_checksum(&self, pi_offset: u32) {
+        let Some(table) = &self.checksums else {
+            return;
+        };
+        let mut buf = [0u8; crate::PROP_VALUE_MAX];
+        let recorded = self.property_info(pi_offset).and_then(|pi| {
+            let serial = pi.serial.load(std::sync::atomic::Ordering::Relaxed);
+            let name = self.property_info_name(pi_offset)?.to_bytes();
+            let value = self.property_value_bytes(pi_offset, &mut buf)?;
+            table.record(pi_offset, serial, checksum::record_crc(name, value))
+        });
+        if let Err(e) = recorded {
+            warn!("Failed to record checksum at offset {pi_offset}: {e}");
+        }
+    }
+
+    /// Checks `value`, read from the record at `pi_offset` at `serial`,
+    /// against the record's checksum.
+    pub(crate) fn check_record(
+        &self,
+        pi_offset: u32,
+        serial: u32,
+        value: &[u8],
+    ) -> Result<RecordCheck> {
+        let Some(table) = &self.checksums else {
+            return Ok(RecordCheck::Unchecked);
+        };
+        let name = self.property_info_name(pi_offset)?.to_bytes();
+        table.check(pi_offset, serial, checksum::record_crc(name, value))
+    }
+
+    /// Name of the record at `pi_offset` for reporting it as corrupt:
+    /// lossy, since the name may be the damaged part.
+    pub(crate) fn corrupt_record_name(&self, pi_offset: u32) -> String {
+        let name = match self.property_info_name(pi_offset) {
+            Ok(name) => name.to_string_lossy().into_owned(),
+            Err(_) => format!("<record at {pi_offset:#x}>"),
+        };
+        error!("Checksum mismatch for property {name}");
+        name
+    }
+
     // Set the dirty backup area.
     // It is used to store the backup of the property area.
     //
@@ -502,7 +899,7 @@ impl PropertyAreaMap {
     // backup area without first materialising a `String`. The reader side
     // already validates UTF-8 after the seqlock re-check, so the backup
     // area itself stores raw bytes verbatim.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn set_dirty_backup_area(&mut self, value: &[u8]) -> Result<()> {
         // The stores below go through `atomic_data` (a `&self` accessor), so
         // check writability explicitly — a PROT_READ mapping would SIGSEGV.
@@ -551,7 +948,7 @@ impl PropertyAreaMap {
 
     // Add a new property trie node with the given name to the given trie node.
     // It uses trie offset to avoid the life time issue of the current trie node.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn add_prop_trie_node(&mut self, trie_offset: u32, name: &str) -> Result<u32> {
         let name_bytes = name.as_bytes();
         let mut current_offset = trie_offset;
@@ -625,7 +1022,12 @@ impl PropertyAreaMap {
         })
     }
 
-    fn find_prop_trie_node(&self, trie_offset: u32, name: &str) -> Result<u32> {
+    fn find_prop_trie_node(
+        &self,
+        trie_offset: u32,
+        name: &str,
+        trace: &mut LookupTrace,
+    ) -> Result<u32> {
         let name_bytes = name.as_bytes();
         let mut current_offset = trie_offset;
         // A corrupt file can link BST nodes into a cycle; a distinct-node
@@ -638,9 +1040,14 @@ impl PropertyAreaMap {
                 .to_object::<PropertyTrieNode>(current_offset as usize, self.data_offset)?;
             let node_name =
                 self.trie_node_name(current_offset as usize, current.namelen as usize)?;
+            trace.comparisons += 1;
             let next_offset = match cmp_prop_name(name_bytes, node_name.to_bytes()) {
-                std::cmp::Ordering::Less => current.left.load(std::sync::atomic::Ordering::Acquire),
+                std::cmp::Ordering::Less => {
+                    trace.left += 1;
+                    current.left.load(std::sync::atomic::Ordering::Acquire)
+                }
                 std::cmp::Ordering::Greater => {
+                    trace.right += 1;
                     current.right.load(std::sync::atomic::Ordering::Acquire)
                 }
                 std::cmp::Ordering::Equal => return Ok(current_offset),
@@ -655,7 +1062,7 @@ impl PropertyAreaMap {
         ))
     }
 
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn allocate_obj(&mut self, size: usize) -> Result<u32> {
         let aligned = crate::bionic_align(size, mem::size_of::<u32>());
         let offset = self.property_area().bytes_used;
@@ -675,7 +1082,7 @@ impl PropertyAreaMap {
 
         // Bounds check. Widen to u64 instead of truncating `pa_data_size`
         // with `as u32` — the module's checked-arithmetic discipline.
-        // `AreaFull`, not `FileSize`: exhausting the fixed 128 KiB area is a
+        // `AreaFull`, not `FileSize`: exhausting the area (`Layout::area_size`) is a
         // reachable operational condition (bionic returns false), not a
         // corrupt-file diagnosis — callers must be able to tell them apart.
         if u64::from(new_offset) > self.pa_data_size as u64 {
@@ -698,7 +1105,7 @@ impl PropertyAreaMap {
     /// the object at `obj_offset` (of header size `header_size`). Goes
     /// through the mmap base pointer so the write carries whole-mapping
     /// provenance instead of escaping an object reference.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn write_trailing_name(
         &mut self,
         obj_offset: usize,
@@ -720,7 +1127,7 @@ impl PropertyAreaMap {
     /// the only legitimate consumer is `add`, whose publish protocol
     /// (write name bytes, then Release-store the link) must not be
     /// bypassable from elsewhere in the crate.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn new_prop_trie_node(&mut self, name: &str) -> Result<u32> {
         let new_offset = self.allocate_obj(mem::size_of::<PropertyTrieNode>() + name.len() + 1)?;
         let node = self
@@ -737,7 +1144,7 @@ impl PropertyAreaMap {
 
     /// Allocates an *unlinked* property entry — private for the same
     /// publish-protocol reason as [`Self::new_prop_trie_node`].
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn new_prop_info(&mut self, name: &str, value: &str) -> Result<u32> {
         let new_offset = self.allocate_obj(mem::size_of::<PropertyInfo>() + name.len() + 1)?;
 
@@ -788,7 +1195,7 @@ impl PropertyAreaMap {
     /// which guarantees the dirty backup slot is written first. Together
     /// with `&mut PropertyAreaMap` it enforces single-writer inside one
     /// process via the borrow checker.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     fn property_info_mut(&mut self, offset: u32) -> Result<&mut PropertyInfo> {
         self.mmap.to_object_mut(offset as usize, self.data_offset)
     }
@@ -815,7 +1222,7 @@ impl PropertyAreaMap {
     /// [`Self::long_property_value`]. Builder-gated: the only caller is the
     /// update path's backup snapshot (readers use the seqlock loop's own
     /// accessors instead).
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn property_value_bytes<'a>(
         &'a self,
         pi_offset: u32,
@@ -871,6 +1278,11 @@ pub(crate) struct MemoryMap {
     /// accessors check this so a mut reference over a PROT_READ mapping
     /// (which would SIGSEGV on first write) is a typed error instead.
     writable: bool,
+    /// The backend that created the mapping, and so must release it —
+    /// kept per map rather than re-read from the process-wide latch.
+    /// `None` for a heap copy (see [`Self::from_bytes`]), which is freed
+    /// as the `u64` slice it was allocated as.
+    backend: Option<&'static dyn crate::backend::PropertyAreaBackend>,
 }
 
 // Manual impl so `data` is never printed: an ASLR base address in logs has
@@ -887,7 +1299,8 @@ impl Debug for MemoryMap {
 }
 
 // SAFETY: The `data` pointer is owned by this MemoryMap and remains valid for
-// `size` bytes until `Drop` calls `munmap`. The pointer itself is not mutated
+// `size` bytes until `Drop` unmaps it through the backend (whose contract
+// requires exactly that) or frees the heap copy. The pointer itself is not mutated
 // after construction. Higher-level invariants for the contents of the mapped
 // region (atomic vs non-atomic writes) are the responsibility of the callers
 // in this module — for shared writable mappings, the builder phase is expected
@@ -920,37 +1333,64 @@ impl MemoryMap {
             ));
         }
 
-        let flags = if writable {
-            mm::ProtFlags::READ.union(mm::ProtFlags::WRITE)
-        } else {
-            mm::ProtFlags::READ
-        };
+        let backend = crate::backend::area();
+        let memory_area = backend.map(file, size, writable)?;
 
-        // SAFETY: `file` is a valid owned `File`, `size > 0` is checked above,
-        // and `mm::mmap` reports failure via `Result` rather than `MAP_FAILED`.
-        let memory_area = unsafe {
-            mm::mmap(
-                std::ptr::null_mut(),
-                size,
-                flags,
-                mm::MapFlags::SHARED,
-                file,
-                0,
-            )
+        Ok(Self {
+            data: memory_area.as_ptr(),
+            size,
+            writable,
+            backend: Some(backend),
+        })
+    }
+
+    /// A read-only copy of `bytes` on the heap instead of a mapping, for
+    /// stores built from buffers (`SystemProperties::from_buffers`). The
+    /// copy is allocated as `u64`s, so it has the 8-byte alignment the
+    /// area structures need whatever the alignment of `bytes`.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.is_empty() {
+            return Err(Error::FileValidation("Cannot map an empty buffer".into()));
         }
-        .map_err(Error::from)? as *mut u8;
+        let words = vec![0u64; bytes.len().div_ceil(8)].into_boxed_slice();
+        let data = Box::into_raw(words) as *mut u8;
+        // SAFETY: `data` is a fresh allocation of at least `bytes.len()`
+        // bytes that nothing else references, so it cannot overlap `bytes`.
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len()) };
+        Ok(Self {
+            data,
+            size: bytes.len(),
+            writable: false,
+            backend: None,
+        })
+    }
+
+    /// A writable mapping whose writes stay private to this process (see
+    /// `PropertyAreaMap::new_cow`).
+    pub(crate) fn new_private(file: File, size: usize) -> Result<Self> {
+        debug!("Creating private memory map: size={size}");
+
+        if size == 0 {
+            return Err(Error::FileValidation(
+                "Cannot mmap zero-sized region".into(),
+            ));
+        }
+
+        let backend = crate::backend::area();
+        let memory_area = backend.map_private(file, size)?;
 
         Ok(Self {
-            data: memory_area,
+            data: memory_area.as_ptr(),
             size,
-            writable,
+            writable: true,
+            backend: Some(backend),
         })
     }
 
     /// Rejects mutable access to read-only mappings. Writing through a
     /// PROT_READ mapping kills the process with SIGSEGV — fail with a
     /// typed error at the accessor instead.
-    fn require_writable(&self) -> Result<()> {
+    pub(crate) fn require_writable(&self) -> Result<()> {
         if !self.writable {
             return Err(Error::PermissionDenied(
                 "attempted mutable access to a read-only property mapping".into(),
@@ -972,7 +1412,7 @@ impl MemoryMap {
         Ok(unsafe { std::slice::from_raw_parts(self.data.add(offset) as *const u8, size) })
     }
 
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn data_mut(
         &mut self,
         offset: usize,
@@ -1017,8 +1457,9 @@ impl MemoryMap {
     }
 
     /// Verifies that `self.data.add(offset)` produces a pointer with the
-    /// required alignment for `T`. The mmap base is page-aligned, so this
-    /// reduces to a check on `offset % align_of::<T>()`.
+    /// required alignment for `T`. The mmap base is page-aligned (a heap
+    /// copy 8-byte aligned), so this reduces to a check on
+    /// `offset % align_of::<T>()`.
     fn check_alignment<T>(&self, offset: usize) -> Result<()> {
         let align = mem::align_of::<T>();
         // Plain address arithmetic — no pointer is formed or dereferenced,
@@ -1146,12 +1587,33 @@ impl MemoryMap {
 
 impl std::ops::Drop for MemoryMap {
     fn drop(&mut self) {
-        // SAFETY: `self.data` was returned by `mm::mmap` with `self.size`
-        // bytes in `MemoryMap::new` and has not been unmapped since.
-        unsafe {
-            if let Err(e) = mm::munmap(self.data as _, self.size) {
-                error!("Failed to unmap memory: {e:?}");
-            }
+        let Some(backend) = self.backend else {
+            // SAFETY: `self.data` is the `Box<[u64]>` of `size.div_ceil(8)`
+            // words leaked in `from_bytes`, freed only here; `&mut self`
+            // rules out outstanding references into it.
+            drop(unsafe {
+                Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                    self.data as *mut u64,
+                    self.size.div_ceil(8),
+                ))
+            });
+            return;
+        };
+        let Some(base) = std::ptr::NonNull::new(self.data) else {
+            return;
+        };
+        // A panicking backend must not abort the process when this drop
+        // runs during unwinding (a panic inside a panic): log it and leak
+        // the mapping instead. `MmapBackend` itself only logs failures.
+        let size = self.size;
+        // SAFETY: `self.data` was returned by `backend.map` with
+        // `self.size` bytes in `MemoryMap::new` and has not been unmapped
+        // since; `&mut self` rules out outstanding references into it.
+        let unmapped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            backend.unmap(base, size)
+        }));
+        if unmapped.is_err() {
+            error!("Area backend panicked unmapping {size} bytes; the mapping is leaked");
         }
     }
 }
@@ -22,7 +22,7 @@ use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
 use crate::errors::{Error, Result};
 use crate::system_properties::PROP_VALUE_MAX;
 
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 const LONG_LEGACY_ERROR: &str = "Must use __system_property_read_callback() to read";
 
 const LONG_FLAG: u32 = 1 << 16;
@@ -32,7 +32,7 @@ const LONG_LEGACY_ERROR_BUFFER_SIZE: usize = 56;
 // terminator — a longer message would be silently truncated by
 // `error_bytes_padded` and lose NUL termination, while the serial length
 // byte kept the untruncated length.
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 const _: () = assert!(LONG_LEGACY_ERROR.len() < LONG_LEGACY_ERROR_BUFFER_SIZE);
 
 // `AtomicU8` / `AtomicU32` are guaranteed by `std::sync::atomic` to have the
@@ -83,7 +83,7 @@ impl PropertyInfo {
     /// are written separately by `PropertyAreaMap::new_prop_info` through
     /// the mmap base pointer — writing them through `&mut self` would step
     /// outside this reference's provenance.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn init_with_long_offset(&mut self, offset: u32) {
         let error_bytes = LONG_LEGACY_ERROR.as_bytes();
         let serial_value = ((error_bytes.len() as u32) << 24) | LONG_FLAG;
@@ -109,7 +109,7 @@ impl PropertyInfo {
     /// to the long variant — a short slot needs room for the NUL, so
     /// storing exactly `PROP_VALUE_MAX` bytes would truncate to
     /// `PROP_VALUE_MAX - 1` while the serial recorded the full length.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn init_with_value(&mut self, value: &str) {
         debug_assert!(
             value.len() < PROP_VALUE_MAX,
@@ -193,7 +193,7 @@ impl PropertyInfo {
     /// `PropertyAreaMap::backup_and_apply_write` — the only path from
     /// outside `property_area.rs` to this writer.
     /// Cross-process invariants are documented at the module level.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub(crate) fn writer(&mut self) -> PropertyInfoWriter<'_> {
         PropertyInfoWriter(self)
     }
@@ -202,16 +202,16 @@ impl PropertyInfo {
 /// Single-writer handle. Construction requires `&mut PropertyInfo`, so the
 /// borrow checker enforces one-writer-per-process. All publish operations
 /// use `Ordering::Release` so paired Acquire readers see the value writes.
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 pub(crate) struct PropertyInfoWriter<'a>(&'a mut PropertyInfo);
 
 /// Counter bits in `serial` — bits 0..24 excluding `LONG_FLAG` (bit 16).
 /// Using a wider mask would let the counter wrap into the LONG_FLAG bit and
 /// silently flip the union variant under readers.
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 const SERIAL_COUNTER_MASK: u32 = 0x00ff_ffff & !LONG_FLAG;
 
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 impl PropertyInfoWriter<'_> {
     /// Atomic short-value update: validate → set dirty → write bytes →
     /// publish new serial. Returns the published serial.
@@ -288,7 +288,7 @@ impl PropertyInfoWriter<'_> {
     }
 }
 
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 fn error_bytes_padded<const N: usize>(src: &[u8]) -> [u8; N] {
     let mut buf = [0u8; N];
     let copy_len = src.len().min(N);
@@ -323,7 +323,7 @@ fn read_value_atomic<'a>(
 /// Byte-wise atomic write. Truncates to `PROP_VALUE_MAX - 1`, then writes a
 /// NUL terminator. Caller is responsible for the surrounding seqlock fences
 /// (Release stores on `serial`).
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 fn write_value_atomic(slot: &[AtomicU8; PROP_VALUE_MAX], bytes: &[u8]) {
     let copy_len = bytes.len().min(PROP_VALUE_MAX - 1);
     for (i, &b) in bytes[..copy_len].iter().enumerate() {
@@ -335,7 +335,7 @@ fn write_value_atomic(slot: &[AtomicU8; PROP_VALUE_MAX], bytes: &[u8]) {
 /// Init-only variant for `init_with_value`. Uses `get_mut()` (plain
 /// non-atomic assignment) since the property has not yet been published to
 /// readers.
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 fn init_value_bytes(slot: &mut [AtomicU8; PROP_VALUE_MAX], bytes: &[u8]) {
     let copy_len = bytes.len().min(PROP_VALUE_MAX - 1);
     for (i, &b) in bytes[..copy_len].iter().enumerate() {
@@ -377,7 +377,6 @@ impl<'a> PropertyInfoArea<'a> {
         self.table_count(self.header().contexts_offset)
     }
 
-    #[cfg(feature = "builder")]
     #[inline]
     pub(crate) fn num_types(&self) -> usize {
         self.table_count(self.header().types_offset)
@@ -408,7 +407,6 @@ impl<'a> PropertyInfoArea<'a> {
         Ok(*value as _)
     }
 
-    #[cfg(feature = "builder")]
     pub(crate) fn type_offset(&self, index: usize) -> Result<usize> {
         // See `context_offset`: untrusted offset, checked arithmetic.
         let type_array_offset = (self.header().types_offset as usize)
@@ -429,6 +427,175 @@ impl<'a> PropertyInfoArea<'a> {
         Ok(*value as _)
     }
 
+    /// Load-time structural check of every count-bearing array in the
+    /// file: the context/type tables and, for each trie node reachable
+    /// from the root, its child/prefix/exact-match arrays and the entries
+    /// they point at.
+    ///
+    /// The per-lookup accessors already bound-check each slice against its
+    /// declared `num_*` field, but they degrade a failure into a `warn!`
+    /// and a lookup miss — a damaged file would then resolve some names to
+    /// the wrong (fallback) context for the lifetime of the process. One
+    /// walk here turns that into a single typed error at load time.
+    ///
+    /// The walk is bounded by the number of nodes the file could possibly
+    /// hold, so a cyclic child graph fails instead of looping.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let num_contexts = self.num_contexts();
+        if num_contexts > 0 {
+            self.context_offset(num_contexts - 1).map_err(|e| {
+                Error::FileValidation(format!(
+                    "context table ({num_contexts} entries) exceeds property_info bounds: {e}"
+                ))
+            })?;
+        }
+        let num_types = self.num_types();
+        if num_types > 0 {
+            self.type_offset(num_types - 1).map_err(|e| {
+                Error::FileValidation(format!(
+                    "type table ({num_types} entries) exceeds property_info bounds: {e}"
+                ))
+            })?;
+        }
+
+        let max_nodes = self.data_base.len() / size_of::<TrieNodeData>();
+        let mut visited = 0usize;
+        let mut pending = vec![self.header().root_offset];
+        while let Some(node_offset) = pending.pop() {
+            visited += 1;
+            if visited > max_nodes {
+                return Err(Error::FileValidation(format!(
+                    "trie walk exceeded {max_nodes} nodes (cyclic or overlapping child offsets)"
+                )));
+            }
+            let node = TrieNode::new(*self, node_offset as usize);
+            let located = |what: &str, e: Error| {
+                Error::FileValidation(format!("trie node at offset {node_offset}: {what}: {e}"))
+            };
+            node.property_entry()
+                .map_err(|e| located("property entry", e))?;
+            let prefixes = node
+                .prefix_offsets()
+                .map_err(|e| located("prefix array", e))?;
+            let exact_matches = node
+                .exact_match_offsets()
+                .map_err(|e| located("exact-match array", e))?;
+            for &entry_offset in prefixes.iter().chain(exact_matches) {
+                node.entry_at(entry_offset)
+                    .map_err(|e| located("match entry", e))?;
+            }
+            pending.extend_from_slice(
+                node.child_offsets()
+                    .map_err(|e| located("child array", e))?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Everything a loader checks before trusting a serialized trie of
+    /// `size` bytes: header version, header size, then [`Self::validate`].
+    ///
+    /// AOSP parity (`PropertyInfoAreaFile::LoadPath`): reject data this
+    /// parser cannot be trusted to interpret. Without the version gate a
+    /// future-format file parses "successfully" into garbage lookups;
+    /// without the size cross-check a truncated or concatenated file
+    /// degrades into per-lookup warnings instead of one load-time error.
+    pub(crate) fn verify(&self, size: usize) -> Result<()> {
+        let header = self.header();
+        if header.minimum_supported_version > 1 {
+            return Err(Error::FileValidation(format!(
+                "unsupported version: minimum_supported_version={} (max supported 1)",
+                header.minimum_supported_version
+            )));
+        }
+        if header.size as usize != size {
+            return Err(Error::FileValidation(format!(
+                "header size {} does not match data size {size}",
+                header.size
+            )));
+        }
+        self.validate()
+    }
+
+    /// Recovers the match rules a trie was built from: the root's
+    /// `(default_context, default_type)` and one entry per node context,
+    /// prefix and exact match — the inverse of `build_trie`, so rebuilding
+    /// from the result yields an equivalent trie. Expects data that passed
+    /// [`Self::verify`].
+    #[cfg(feature = "info-builder")]
+    pub(crate) fn decode_entries(&self) -> Result<(String, String, Vec<crate::PropertyInfoEntry>)> {
+        let root = self.root_node();
+        let (root_context, root_type) = root.context_and_type_indexes();
+        if root_context == NO_INDEX {
+            return Err(Error::FileValidation(
+                "root node has no default context".into(),
+            ));
+        }
+        let default_context = self.context_str(root_context)?;
+        let default_type = self.type_str(root_type)?;
+
+        let mut entries = Vec::new();
+        // (node, dotted path of the node including its trailing '.'); the
+        // root's path is empty.
+        let mut pending = vec![(root, String::new())];
+        while let Some((node, path)) = pending.pop() {
+            if !path.is_empty() {
+                let (context, rtype) = node.context_and_type_indexes();
+                if context != NO_INDEX {
+                    entries.push(crate::PropertyInfoEntry::new(
+                        path.clone(),
+                        self.context_str(context)?,
+                        &self.type_str(rtype)?,
+                        false,
+                    )?);
+                }
+            }
+            for (offsets, exact) in [
+                (node.prefix_offsets()?, false),
+                (node.exact_match_offsets()?, true),
+            ] {
+                for &offset in offsets {
+                    let entry = node.entry_at(offset)?;
+                    let name = entry.name(self)?.to_str().map_err(Error::Utf8)?;
+                    entries.push(crate::PropertyInfoEntry::new(
+                        format!("{path}{name}"),
+                        self.context_str(entry.context_index)?,
+                        &self.type_str(entry.type_index)?,
+                        exact,
+                    )?);
+                }
+            }
+            for &child_offset in node.child_offsets()? {
+                let child = TrieNode::new(*self, child_offset as usize);
+                let name = child.name()?.to_str().map_err(Error::Utf8)?;
+                pending.push((child, format!("{path}{name}.")));
+            }
+        }
+        Ok((default_context, default_type, entries))
+    }
+
+    #[cfg(feature = "info-builder")]
+    fn context_str(&self, index: u32) -> Result<String> {
+        Ok(self
+            .cstr(self.context_offset(index as usize)?)?
+            .to_str()
+            .map_err(Error::Utf8)?
+            .to_owned())
+    }
+
+    /// Type string at `index`; [`NO_INDEX`] (no type recorded) is empty.
+    #[cfg(feature = "info-builder")]
+    fn type_str(&self, index: u32) -> Result<String> {
+        if index == NO_INDEX {
+            return Ok(String::new());
+        }
+        Ok(self
+            .cstr(self.type_offset(index as usize)?)?
+            .to_str()
+            .map_err(Error::Utf8)?
+            .to_owned())
+    }
+
     /// Applies the first (longest, by serialization order) prefix entry
     /// matching `remaining_name`.
     ///
@@ -439,21 +606,26 @@ impl<'a> PropertyInfoArea<'a> {
     /// *misses* here instead of matching on a silently truncated prefix.
     /// Consistent files (the builder always writes `namelen ==
     /// strlen(name)`) behave identically.
+    ///
+    /// Returns the longest prefix name it compared, which tells
+    /// [`Self::get_property_info_indexes_keyed`] how much of
+    /// `remaining_name` the result depended on.
     fn check_prefix_match(
         &self,
         remaining_name: &str,
         trie_node: &TrieNode,
         context_index: &mut u32,
         type_index: &mut u32,
-    ) {
+    ) -> usize {
         let remaining_name_size = remaining_name.len();
+        let mut compared = 0;
         // One node-data validation + one array slice for the whole loop;
         // the per-entry `ref_from` below is the only per-iteration check.
         let offsets = match trie_node.prefix_offsets() {
             Ok(o) => o,
             Err(e) => {
                 warn!("Failed to read prefix entries: {e}");
-                return;
+                return compared;
             }
         };
         for (i, &entry_offset) in offsets.iter().enumerate() {
@@ -466,12 +638,14 @@ impl<'a> PropertyInfoArea<'a> {
             };
             // Widen the untrusted field instead of truncating the local
             // length with `as u32`.
+            compared = compared.max(prefix.namelen as usize);
             if prefix.namelen as usize > remaining_name_size {
                 continue;
             }
             let Some(prefix_name) = entry_name_str(prefix.name(self), "Prefix", i) else {
                 continue;
             };
+            compared = compared.max(prefix_name.len());
             if remaining_name.starts_with(prefix_name) {
                 if prefix.context_index != NO_INDEX {
                     *context_index = prefix.context_index;
@@ -479,22 +653,59 @@ impl<'a> PropertyInfoArea<'a> {
                 if prefix.type_index != NO_INDEX {
                     *type_index = prefix.type_index;
                 }
-                return;
+                return compared;
             }
         }
+        compared
     }
 
     pub(crate) fn get_property_info_indexes(&self, name: &str) -> (u32, u32) {
+        let (context_index, type_index, _) = self.get_property_info_indexes_keyed(name, name.len());
+        (context_index, type_index)
+    }
+
+    /// [`Self::get_property_info_indexes`], also reporting whether the
+    /// result is determined by `name[..key_len]` alone — i.e. every name
+    /// starting with that key resolves to the same indexes. The walk looks
+    /// past the key only when the trie branches (children, prefixes or
+    /// exact matches) below the point the key reaches; the context cache
+    /// memoizes only determined results.
+    pub(crate) fn get_property_info_indexes_keyed(
+        &self,
+        name: &str,
+        key_len: usize,
+    ) -> (u32, u32, bool) {
+        self.walk(name, key_len, true)
+    }
+
+    /// Context index some `property_contexts` entry assigns to `name`, or
+    /// [`NO_INDEX`] when only the trie's default (the root node's own
+    /// entry) covers it — the name is not declared.
+    #[cfg(any(feature = "writer", all(test, feature = "info-builder")))]
+    pub(crate) fn declared_context_index(&self, name: &str) -> u32 {
+        let (context_index, _, _) = self.walk(name, name.len(), false);
+        context_index
+    }
+
+    fn walk(&self, name: &str, key_len: usize, with_default: bool) -> (u32, u32, bool) {
         let mut return_context_index: u32 = NO_INDEX;
         let mut return_type_index: u32 = NO_INDEX;
         let mut remaining_name = name;
+        // Bytes of the key still ahead in `remaining_name`.
+        let mut key_left = key_len;
+        let mut determined = true;
         let mut trie_node = self.root_node();
+        let mut skip_own_entry = !with_default;
 
         loop {
             // Single TrieNodeData → PropertyEntry validation per node for
             // both indexes — separate accessors would double the per-level
             // cost of this lookup hot path.
-            let (context_index, type_index) = trie_node.context_and_type_indexes();
+            let (context_index, type_index) = if std::mem::take(&mut skip_own_entry) {
+                (NO_INDEX, NO_INDEX)
+            } else {
+                trie_node.context_and_type_indexes()
+            };
             if context_index != NO_INDEX {
                 return_context_index = context_index;
             }
@@ -502,20 +713,31 @@ impl<'a> PropertyInfoArea<'a> {
                 return_type_index = type_index;
             }
 
-            self.check_prefix_match(
+            let compared = self.check_prefix_match(
                 remaining_name,
                 &trie_node,
                 &mut return_context_index,
                 &mut return_type_index,
             );
+            determined &= compared <= key_left;
 
-            match remaining_name.find('.') {
+            // Whether the next segment (and the dot ending it) lies inside
+            // the key; past it, only a node without children is sure to
+            // end the walk the same way for every name.
+            let next_dot = remaining_name.find('.');
+            if next_dot.map_or(true, |index| index >= key_left)
+                && trie_node.child_offsets().map_or(true, |c| !c.is_empty())
+            {
+                determined = false;
+            }
+            match next_dot {
                 Some(index) => {
                     let segment = &remaining_name[..index];
 
                     match trie_node.find_child_for_string(segment) {
                         Some(node) => {
                             remaining_name = &remaining_name[index + 1..];
+                            key_left = key_left.saturating_sub(index + 1);
                             trie_node = node;
                         }
                         None => {
@@ -538,6 +760,9 @@ impl<'a> PropertyInfoArea<'a> {
                 &[][..]
             }
         };
+        if !exact_offsets.is_empty() && remaining_name.len() > key_left {
+            determined = false;
+        }
         for (i, &entry_offset) in exact_offsets.iter().enumerate() {
             let exact_match = match trie_node.entry_at(entry_offset) {
                 Ok(em) => em,
@@ -568,7 +793,7 @@ impl<'a> PropertyInfoArea<'a> {
                 trace!(
                     "Property '{name}' resolved: context_index={context_index}, type_index={type_index}"
                 );
-                return (context_index, type_index);
+                return (context_index, type_index, determined);
             }
         }
 
@@ -583,55 +808,31 @@ impl<'a> PropertyInfoArea<'a> {
             &mut return_context_index,
             &mut return_type_index,
         );
-        (return_context_index, return_type_index)
-    }
-
-    #[cfg(feature = "builder")]
-    pub(crate) fn find_context_index(&self, context: &str) -> Option<usize> {
-        self.find_string_index(self.num_contexts(), context, "context", |i| {
-            self.context_offset(i)
-        })
+        (return_context_index, return_type_index, determined)
     }
 
-    #[cfg(feature = "builder")]
-    pub(crate) fn find_type_index(&self, rtype: &str) -> Option<usize> {
-        self.find_string_index(self.num_types(), rtype, "type", |i| self.type_offset(i))
+    /// Type string at `type_index` as returned by
+    /// [`Self::get_property_info_indexes`] (e.g. `"string"`,
+    /// `"enum a b"`), or `None` for [`NO_INDEX`].
+    pub(crate) fn type_at(&self, type_index: u32) -> Result<Option<&'a str>> {
+        if type_index == NO_INDEX {
+            return Ok(None);
+        }
+        self.cstr(self.type_offset(type_index as usize)?)?
+            .to_str()
+            .map(Some)
+            .map_err(Error::Utf8)
     }
 
-    /// Shared binary search for context/type tables. Treats any entry that
-    /// fails to read or is not valid UTF-8 as a corruption signal; once set,
-    /// the search is short-circuited and returns `None` (the table's sorted
-    /// invariant can no longer be trusted).
-    #[cfg(feature = "builder")]
-    fn find_string_index(
-        &self,
-        n: usize,
-        needle: &str,
-        kind: &str,
-        offset_at: impl Fn(usize) -> Result<usize>,
-    ) -> Option<usize> {
-        // Same corruption-handling pattern as `find_child_for_string`: on
-        // failure we return `Equal` (which terminates `find`'s binary
-        // search) and surface the inability-to-trust-sort via `corrupted`.
-        let mut corrupted = false;
-        let idx = find(n, |i| {
-            match offset_at(i).and_then(|off| self.cstr(off)).and_then(|s| {
-                s.to_str()
-                    .map_err(|e| Error::FileValidation(format!("{kind} entry {i} not UTF-8: {e}")))
-            }) {
-                Ok(s) => s.cmp(needle),
-                Err(e) => {
-                    warn!("{kind} entry {i} read failed: {e}");
-                    corrupted = true;
-                    Ordering::Equal
-                }
-            }
-        });
-        if corrupted {
-            None
-        } else {
-            idx
+    /// Context name at `context_index`, or `None` for [`NO_INDEX`].
+    pub(crate) fn context_at(&self, context_index: u32) -> Result<Option<&'a str>> {
+        if context_index == NO_INDEX {
+            return Ok(None);
         }
+        self.cstr(self.context_offset(context_index as usize)?)?
+            .to_str()
+            .map(Some)
+            .map_err(Error::Utf8)
     }
 }
 
@@ -640,14 +841,15 @@ pub(crate) struct PropertyInfoAreaFile {
 }
 
 impl PropertyInfoAreaFile {
-    pub(crate) fn load_default_path() -> Result<Self> {
-        Self::load_path(Path::new(crate::system_properties::PROP_TREE_FILE))
-    }
-
     pub(crate) fn load_path(path: &Path) -> Result<Self> {
         let file: File =
             File::open(path).context_with_location(format!("File open is failed in: {path:?}"))?;
+        Self::load_file(file, path)
+    }
 
+    /// [`Self::load_path`] for an already open file; `path` only names it
+    /// in errors.
+    pub(crate) fn load_file(file: File, path: &Path) -> Result<Self> {
         let metadata = file
             .metadata()
             .context_with_location(format!("File metadata is failed in: {path:?}"))?;
@@ -674,26 +876,29 @@ impl PropertyInfoAreaFile {
             mmap: MemoryMap::new(file, size, false)?,
         };
 
-        // AOSP parity (`PropertyInfoAreaFile::LoadPath`): reject files this
-        // parser cannot be trusted to interpret. Without the version gate a
-        // future-format file parses "successfully" into garbage lookups;
-        // without the size cross-check a truncated or concatenated file
-        // degrades into per-lookup warnings instead of one load-time error.
-        let area = this.property_info_area();
-        let header = area.header();
-        if header.minimum_supported_version > 1 {
-            return Err(Error::FileValidation(format!(
-                "Unsupported property_info version in {path:?}: minimum_supported_version={} (max supported 1)",
-                header.minimum_supported_version
-            )));
-        }
-        if header.size as usize != size {
-            return Err(Error::FileValidation(format!(
-                "property_info header size {} does not match file size {size} in {path:?}",
-                header.size
+        this.property_info_area()
+            .verify(size)
+            .map_err(|e| Error::FileValidation(format!("Malformed property_info {path:?}: {e}")))?;
+
+        Ok(this)
+    }
+
+    /// A copy of the serialized trie in `bytes` (see
+    /// `MemoryMap::from_bytes`); `name` only names it in errors.
+    pub(crate) fn load_bytes(bytes: &[u8], name: &Path) -> Result<Self> {
+        if bytes.len() < size_of::<PropertyInfoAreaHeader>() {
+            return Err(Error::FileSize(format!(
+                "Buffer too small: size={}, min_size={} for {name:?}",
+                bytes.len(),
+                size_of::<PropertyInfoAreaHeader>()
             )));
         }
-
+        let this = Self {
+            mmap: MemoryMap::from_bytes(bytes)?,
+        };
+        this.property_info_area()
+            .verify(bytes.len())
+            .map_err(|e| Error::FileValidation(format!("Malformed property_info {name:?}: {e}")))?;
         Ok(this)
     }
 
@@ -705,3 +910,137 @@ impl PropertyInfoAreaFile {
         )
     }
 }
+
+#[cfg(all(test, feature = "info-builder"))]
+mod tests {
+    use super::*;
+    use zerocopy::IntoBytes;
+
+    /// Serialized trie copied into a `u32` backing so the area keeps the
+    /// 4-byte alignment `PropertyInfoArea::new` asserts.
+    fn build_words() -> Vec<u32> {
+        let entries = vec![
+            crate::PropertyInfoEntry::new(
+                "ro.build.".into(),
+                "u:object_r:build_prop:s0".into(),
+                "string",
+                false,
+            )
+            .unwrap(),
+            crate::PropertyInfoEntry::new(
+                "ro.build.host".into(),
+                "u:object_r:host_prop:s0".into(),
+                "string",
+                true,
+            )
+            .unwrap(),
+        ];
+        let data = crate::build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap();
+        let mut words = vec![0u32; data.len().div_ceil(4)];
+        words.as_mut_bytes()[..data.len()].copy_from_slice(&data);
+        words
+    }
+
+    fn root_field(words: &mut [u32], field: usize) -> &mut u32 {
+        let area = PropertyInfoArea::new(words.as_bytes());
+        let root = area.header().root_offset as usize / size_of::<u32>();
+        &mut words[root + field]
+    }
+
+    #[test]
+    fn test_validate_accepts_built_trie() {
+        let words = build_words();
+        PropertyInfoArea::new(words.as_bytes()).validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_rejects_oversized_counts() {
+        // Field order of `TrieNodeData`: num_child_nodes = 1,
+        // num_prefixes = 3, num_exact_matches = 5.
+        for field in [1, 3, 5] {
+            let mut words = build_words();
+            *root_field(&mut words, field) = u32::MAX / 4;
+            let err = PropertyInfoArea::new(words.as_bytes())
+                .validate()
+                .unwrap_err();
+            assert!(matches!(err, Error::FileValidation(_)), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_keyed_lookup_reports_branching() {
+        let entries: Vec<_> = [
+            ("ro.product.", "u:object_r:product_prop:s0", false),
+            ("ro.product.vendor.", "u:object_r:vendor_prop:s0", false),
+            ("persist.sys.", "u:object_r:system_prop:s0", false),
+            ("ro.build.host", "u:object_r:host_prop:s0", true),
+        ]
+        .into_iter()
+        .map(|(name, context, exact)| {
+            crate::PropertyInfoEntry::new(name.into(), context.into(), "string", exact).unwrap()
+        })
+        .collect();
+        let data = crate::build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap();
+        let mut words = vec![0u32; data.len().div_ceil(4)];
+        words.as_mut_bytes()[..data.len()].copy_from_slice(&data);
+        let area = PropertyInfoArea::new(words.as_bytes());
+
+        let names = [
+            "persist.sys.timezone",
+            "persist.sys.locale.region",
+            "ro.product.model",
+            "ro.product.vendor.model",
+            "ro.build.host",
+            "ro.build.id",
+            "vendor.x.y",
+            "vendor.x.z.w",
+        ];
+        let key_len = |name: &str| name.match_indices('.').nth(1).unwrap().0 + 1;
+        for name in names {
+            let (context, type_index, determined) =
+                area.get_property_info_indexes_keyed(name, key_len(name));
+            assert_eq!((context, type_index), area.get_property_info_indexes(name));
+            let expected = !name.starts_with("ro.product.") && !name.starts_with("ro.build.");
+            assert_eq!(determined, expected, "{name}");
+            if determined {
+                let key = &name[..key_len(name)];
+                for other in names.iter().filter(|other| other.starts_with(key)) {
+                    assert_eq!(
+                        area.get_property_info_indexes(other).0,
+                        context,
+                        "{name} vs {other}"
+                    );
+                }
+            }
+        }
+
+        // Only the root's default covers `vendor.`; an exact entry declares
+        // its name and nothing beside it.
+        for (name, declared) in [
+            ("persist.sys.timezone", true),
+            ("ro.build.host", true),
+            ("ro.build.id", false),
+            ("vendor.x.y", false),
+        ] {
+            let context = area.declared_context_index(name);
+            assert_eq!(context != NO_INDEX, declared, "{name}");
+            if declared {
+                assert_eq!(context, area.get_property_info_indexes(name).0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_cycle() {
+        let mut words = build_words();
+        let root_offset = PropertyInfoArea::new(words.as_bytes()).header().root_offset;
+        // Point the root's first child back at the root itself.
+        let child_nodes = *root_field(&mut words, 2) as usize / size_of::<u32>();
+        assert!(*root_field(&mut words, 1) > 0);
+        words[child_nodes] = root_offset;
+        let err = PropertyInfoArea::new(words.as_bytes())
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("cyclic"), "{err}");
+    }
+}
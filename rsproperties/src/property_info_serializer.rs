@@ -5,24 +5,26 @@ use log::{info, warn};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use crate::errors::*;
 use crate::trie_builder::*;
 use crate::trie_serializer::*;
+use crate::SelinuxContext;
 
 #[derive(Debug, Clone)]
 pub struct PropertyInfoEntry {
     name: String,
-    context: String,
+    context: SelinuxContext,
     type_str: String,
     exact_match: bool,
 }
 
 impl PropertyInfoEntry {
     /// Constructs an entry programmatically (the file-based path is
-    /// [`Self::parse_from_file`]). Validates `type_str` with the same rule
-    /// as the parser; AOSP's `PropertyInfoEntry` likewise exposes a public
-    /// constructor.
+    /// [`Self::parse_from_file`]). Validates `context` as a
+    /// [`SelinuxContext`] and `type_str` with the same rules as the parser;
+    /// AOSP's `PropertyInfoEntry` likewise exposes a public constructor.
     ///
     /// `type_str` is borrowed: only its whitespace-normalized copy is
     /// stored, so taking ownership would force callers to allocate a
@@ -45,7 +47,7 @@ impl PropertyInfoEntry {
         }
         Ok(Self {
             name,
-            context,
+            context: SelinuxContext::new(context)?,
             type_str: type_strings.join(" "),
             exact_match,
         })
@@ -57,7 +59,7 @@ impl PropertyInfoEntry {
     }
 
     /// SELinux context this property maps to.
-    pub fn context(&self) -> &str {
+    pub fn context(&self) -> &SelinuxContext {
         &self.context
     }
 
@@ -84,7 +86,15 @@ impl PropertyInfoEntry {
             return false;
         }
 
-        const NO_PARAMETER_TYPES: &[&str] = &["string", "int", "bool", "uint", "double", "size"];
+        const NO_PARAMETER_TYPES: &[&str] = &[
+            "string",
+            "int",
+            "bool",
+            "uint",
+            "double",
+            "size",
+            crate::bytes_value::BYTES_TYPE,
+        ];
 
         NO_PARAMETER_TYPES.contains(&type_strings[0])
     }
@@ -107,6 +117,13 @@ impl PropertyInfoEntry {
             .next()
             .ok_or_else(|| Error::Parse(format!("Did not find a context entry in '{line}'")))?;
 
+        // Checked here, with the line, rather than when the writer labels
+        // the area file with it.
+        let context = SelinuxContext::new(context).map_err(|e| match e {
+            Error::InvalidArgument(m) => Error::Parse(m),
+            other => other,
+        })?;
+
         let match_operation = tokenizer.next();
 
         // Borrow from `line` — the only owned copy is the final `join`.
@@ -141,7 +158,7 @@ impl PropertyInfoEntry {
 
         let entry = Self {
             name: property.to_owned(),
-            context: context.to_owned(),
+            context,
             type_str: type_strings.join(" "),
             exact_match,
         };
@@ -177,7 +194,7 @@ impl PropertyInfoEntry {
             // Bounded like `build_property_parser`'s loop: same crafted-
             // input threat model, same fix.
             let (read, truncated) =
-                crate::build_property_parser::read_bounded_line(&mut reader, &mut raw_line)
+                crate::line_reader::read_bounded_line(&mut reader, &mut raw_line)
                     .with_context_location(|| {
                         format!("Failed to read line {} of {filename:?}", line_count + 1)
                     })?;
@@ -189,7 +206,7 @@ impl PropertyInfoEntry {
                 warn!("Line {line_count}: skipping over-long line");
                 errors.push(Error::Parse(format!(
                     "line {line_count} of {filename:?}: line longer than {} bytes",
-                    crate::build_property_parser::MAX_LINE_LEN
+                    crate::line_reader::MAX_LINE_LEN
                 )));
                 continue;
             }
@@ -243,11 +260,37 @@ impl PropertyInfoEntry {
     }
 }
 
+/// What [`build_trie_with_stats`] built, and how long it took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TrieBuildStats {
+    /// Trie nodes, root included — one per distinct dotted prefix.
+    pub nodes: usize,
+    /// Distinct strings in the image: contexts, types and node/entry
+    /// names, each stored once however often it repeats.
+    pub strings: usize,
+    /// Size of the serialized `property_info` image.
+    pub bytes: usize,
+    /// Wall time of the whole build, trie construction and serialization.
+    pub duration: Duration,
+}
+
 pub fn build_trie(
     property_info: &[PropertyInfoEntry],
     default_context: &str,
     default_type: &str,
 ) -> Result<Vec<u8>> {
+    build_trie_with_stats(property_info, default_context, default_type).map(|(data, _)| data)
+}
+
+/// [`build_trie`], also returning [`TrieBuildStats`] — for tooling that
+/// reports on large context sets, and for the `trie_bench` benchmark.
+pub fn build_trie_with_stats(
+    property_info: &[PropertyInfoEntry],
+    default_context: &str,
+    default_type: &str,
+) -> Result<(Vec<u8>, TrieBuildStats)> {
+    let started = Instant::now();
     info!(
         "Building trie from {} property info entries (default_context='{}', default_type='{}')",
         property_info.len(),
@@ -262,6 +305,7 @@ pub fn build_trie(
     // violation" instead of an input error.
     crate::wire::validate_no_interior_nul("default context", default_context)?;
     crate::wire::validate_no_interior_nul("default type", default_type)?;
+    SelinuxContext::new(default_context)?;
 
     let mut trie = TrieBuilder::new(default_context, default_type);
 
@@ -275,13 +319,104 @@ pub fn build_trie(
     }
 
     let serializer = TrieSerializer::new(&trie)?;
+    let nodes = serializer.nodes();
+    let strings = serializer.strings();
     let data = serializer.into_data();
+    let stats = TrieBuildStats {
+        nodes,
+        strings,
+        bytes: data.len(),
+        duration: started.elapsed(),
+    };
+
+    info!("Trie built and serialized successfully: {stats:?}");
+    Ok((data, stats))
+}
 
-    info!(
-        "Trie built and serialized successfully: {} bytes",
-        data.len()
-    );
-    Ok(data)
+/// Merges serialized tries — e.g. one per partition (plat, system_ext,
+/// vendor, odm), each produced by [`build_trie`] from that partition's
+/// `property_contexts` — into a single `property_info` blob, the way
+/// AOSP assembles the device-wide file at build time.
+///
+/// Every fragment is verified like a loaded `property_info` file, then
+/// decoded back into its match rules. A rule present in several fragments
+/// must map to the same context and type everywhere (an identical
+/// duplicate is merged silently); a conflicting one is an
+/// [`Error::FileValidation`] naming the property and both fragments. The
+/// fragments must also agree on the default context and type.
+pub fn merge_tries(fragments: Vec<&[u8]>) -> Result<Vec<u8>> {
+    use std::collections::hash_map::{Entry, HashMap};
+    use zerocopy::IntoBytes;
+
+    if fragments.is_empty() {
+        return Err(Error::InvalidArgument("merge_tries: no fragments".into()));
+    }
+    info!("Merging {} property_info fragments", fragments.len());
+
+    let mut defaults: Option<(String, String)> = None;
+    let mut entries: Vec<PropertyInfoEntry> = Vec::new();
+    // (name, exact_match) → (index into `entries`, fragment index).
+    let mut seen: HashMap<(String, bool), (usize, usize)> = HashMap::new();
+
+    for (index, fragment) in fragments.iter().enumerate() {
+        let header_size =
+            std::mem::size_of::<crate::property_info_parser::PropertyInfoAreaHeader>();
+        if fragment.len() < header_size {
+            return Err(Error::FileValidation(format!(
+                "fragment {index}: {} bytes is smaller than the header",
+                fragment.len()
+            )));
+        }
+        // Copied into a `u32` backing: the parser needs 4-byte alignment,
+        // which an arbitrary `&[u8]` does not guarantee.
+        let mut words = vec![0u32; fragment.len().div_ceil(4)];
+        words.as_mut_bytes()[..fragment.len()].copy_from_slice(fragment);
+        let area =
+            crate::property_info_parser::PropertyInfoArea::new(&words.as_bytes()[..fragment.len()]);
+        area.verify(fragment.len())
+            .map_err(|e| Error::FileValidation(format!("fragment {index}: {e}")))?;
+        let (default_context, default_type, fragment_entries) = area.decode_entries()?;
+
+        match &defaults {
+            None => defaults = Some((default_context, default_type)),
+            Some((context, rtype)) if *context != default_context || *rtype != default_type => {
+                return Err(Error::FileValidation(format!(
+                    "fragment {index}: default '{default_context}' '{default_type}' \
+                     conflicts with fragment 0's '{context}' '{rtype}'"
+                )));
+            }
+            Some(_) => {}
+        }
+
+        for entry in fragment_entries {
+            match seen.entry((entry.name.clone(), entry.exact_match)) {
+                Entry::Vacant(slot) => {
+                    slot.insert((entries.len(), index));
+                    entries.push(entry);
+                }
+                Entry::Occupied(slot) => {
+                    let (existing_index, existing_fragment) = *slot.get();
+                    let existing = &entries[existing_index];
+                    if existing.context != entry.context || existing.type_str != entry.type_str {
+                        warn!("Conflicting property_info rule for '{}'", entry.name);
+                        return Err(Error::FileValidation(format!(
+                            "'{}' ({}) maps to '{}' '{}' in fragment {existing_fragment} \
+                             but to '{}' '{}' in fragment {index}",
+                            entry.name,
+                            if entry.exact_match { "exact" } else { "prefix" },
+                            existing.context,
+                            existing.type_str,
+                            entry.context,
+                            entry.type_str
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    let (default_context, default_type) = defaults.expect("at least one fragment");
+    build_trie(&entries, &default_context, &default_type)
 }
 
 #[cfg(test)]
@@ -340,6 +475,27 @@ mod tests {
         assert_eq!(entry.context, "u:object_r:build_prop:s0");
         assert_eq!(entry.type_str, "enum string int");
         assert!(entry.exact_match);
+
+        // Malformed contexts are refused with the line, not when the
+        // writer labels the area file.
+        for line in [
+            "ro.build.host build_prop exact string",
+            "ro.build.host u:object_r:*:s0 exact string",
+        ] {
+            assert!(matches!(
+                PropertyInfoEntry::parse_from_line(line, true),
+                Err(Error::Parse(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_new_validates_context() {
+        assert!(matches!(
+            PropertyInfoEntry::new("ro.a".into(), "build_prop".into(), "string", true),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(build_trie(&[], "default_prop", "string").is_err());
     }
 
     #[test]
@@ -375,4 +531,111 @@ mod tests {
         )
         .is_err());
     }
+
+    fn fragment(lines: &[(&str, &str, bool)]) -> Vec<u8> {
+        let entries: Vec<_> = lines
+            .iter()
+            .map(|&(name, context, exact)| {
+                PropertyInfoEntry::new(name.into(), context.into(), "string", exact).unwrap()
+            })
+            .collect();
+        build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap()
+    }
+
+    fn context_of(data: &[u8], name: &str) -> String {
+        use zerocopy::IntoBytes;
+        let mut words = vec![0u32; data.len().div_ceil(4)];
+        words.as_mut_bytes()[..data.len()].copy_from_slice(data);
+        let area =
+            crate::property_info_parser::PropertyInfoArea::new(&words.as_bytes()[..data.len()]);
+        let (context, _) = area.get_property_info_indexes(name);
+        let offset = area.context_offset(context as usize).unwrap();
+        area.cstr(offset).unwrap().to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_build_trie_with_stats() {
+        let entries: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .flat_map(|partition| {
+                ["enabled", "version"].map(|leaf| {
+                    PropertyInfoEntry::new(
+                        format!("{partition}.hw.{leaf}"),
+                        format!("u:object_r:{partition}_{leaf}_prop:s0"),
+                        "string",
+                        true,
+                    )
+                    .unwrap()
+                })
+            })
+            .collect();
+        let (data, stats) =
+            build_trie_with_stats(&entries, "u:object_r:default_prop:s0", "string").unwrap();
+        assert_eq!(
+            data,
+            build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap()
+        );
+
+        // root, a, b, c, and one `hw` under each.
+        assert_eq!(stats.nodes, 7);
+        // 7 contexts, 1 type, and the names root, a, b, c, hw, enabled,
+        // version: repeated names are stored once.
+        assert_eq!(stats.strings, 15);
+        assert_eq!(stats.bytes, data.len());
+        for entry in &entries {
+            assert_eq!(context_of(&data, entry.name()), entry.context().as_str());
+        }
+        assert_eq!(
+            context_of(&data, "a.hw.other"),
+            "u:object_r:default_prop:s0"
+        );
+    }
+
+    #[test]
+    fn test_merge_tries() {
+        let plat = fragment(&[
+            ("ro.build.", "u:object_r:build_prop:s0", false),
+            ("sys.usb", "u:object_r:usb_prop:s0", false),
+            ("ro.build.host", "u:object_r:host_prop:s0", true),
+        ]);
+        let vendor = fragment(&[
+            ("vendor.", "u:object_r:vendor_prop:s0", false),
+            // Identical duplicate of a plat rule: merged, not a conflict.
+            ("ro.build.", "u:object_r:build_prop:s0", false),
+        ]);
+
+        let merged = merge_tries(vec![&plat, &vendor]).unwrap();
+        for (name, context) in [
+            ("ro.build.id", "u:object_r:build_prop:s0"),
+            ("ro.build.host", "u:object_r:host_prop:s0"),
+            ("sys.usb.config", "u:object_r:usb_prop:s0"),
+            ("vendor.foo", "u:object_r:vendor_prop:s0"),
+            ("unlisted", "u:object_r:default_prop:s0"),
+        ] {
+            assert_eq!(context_of(&merged, name), context, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_merge_tries_rejects_conflicts() {
+        let plat = fragment(&[("ro.build.", "u:object_r:build_prop:s0", false)]);
+        let vendor = fragment(&[("ro.build.", "u:object_r:vendor_prop:s0", false)]);
+        let err = merge_tries(vec![&plat, &vendor]).unwrap_err();
+        assert!(matches!(err, Error::FileValidation(_)), "{err}");
+        assert!(err.to_string().contains("ro.build."), "{err}");
+
+        let other_default = build_trie(&[], "u:object_r:other_default:s0", "string").unwrap();
+        assert!(matches!(
+            merge_tries(vec![&plat, &other_default]),
+            Err(Error::FileValidation(_))
+        ));
+        assert!(matches!(
+            merge_tries(vec![&plat[..plat.len() - 4]]),
+            Err(Error::FileValidation(_))
+        ));
+        assert!(matches!(
+            merge_tries(vec![]),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
 }
@@ -0,0 +1,141 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A tiny property environment to try the crate on any Linux host
+//! (features `writer` and `info-builder`).
+//!
+//! [`demo_store`] builds everything a device would provide — a
+//! `property_contexts` file, the `property_info` trie compiled from it and
+//! populated areas — from data embedded in the crate, in a fresh temp
+//! directory. No Android device, image or test asset is involved:
+//!
+//! ```rust
+//! # fn main() -> rsproperties::Result<()> {
+//! let (dir, mut props) = rsproperties::quickstart::demo_store()?;
+//!
+//! assert_eq!(props.get_with_result("ro.product.model")?, "Quickstart");
+//! assert_eq!(props.property_type("demo.count")?, Some("int"));
+//!
+//! props.set("demo.greeting", "bonjour")?;
+//! assert_eq!(props.get_with_result("demo.greeting")?, "bonjour");
+//!
+//! // Other instances can open the same directory.
+//! println!("areas in {}", dir.path().display());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The returned [`SystemProperties`] is the writer; there is no property
+//! service, so `rsproperties::set` does not reach it. For end-to-end
+//! tests with a service, see `test_support::TestEnv` (feature
+//! `test-utils`).
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::errors::*;
+use crate::{build_trie, Layout, PropertyInfoEntry, SystemProperties};
+
+/// The `property_contexts` [`demo_store`] compiles.
+pub const PROPERTY_CONTEXTS: &str = "\
+ro. u:object_r:build_prop:s0 prefix string
+persist. u:object_r:persist_prop:s0 prefix string
+demo. u:object_r:demo_prop:s0 prefix string
+demo.count u:object_r:demo_prop:s0 exact int
+demo.enabled u:object_r:demo_prop:s0 exact bool
+";
+
+/// The properties [`demo_store`] starts with.
+pub const PROPERTIES: &[(&str, &str)] = &[
+    ("ro.product.model", "Quickstart"),
+    ("ro.build.version.sdk", "34"),
+    ("persist.demo.theme", "dark"),
+    ("demo.greeting", "hello"),
+    ("demo.count", "3"),
+    ("demo.enabled", "true"),
+];
+
+const DEFAULT_CONTEXT: &str = "u:object_r:default_prop:s0";
+const DEFAULT_TYPE: &str = "string";
+
+/// A directory under [`std::env::temp_dir`], removed on drop.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new() -> Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "rsprops_quickstart_{}_{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path)
+            .map_err(Error::from)
+            .context_with_location(format!("Failed to create {path:?}"))?;
+        Ok(Self { path })
+    }
+
+    /// The properties directory: `property_contexts`, `property_info` and
+    /// the areas.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Builds the demo environment in a new temp directory and returns it with
+/// a writer on it. The directory goes away when the [`TempDir`] drops;
+/// keep it alive as long as the store.
+pub fn demo_store() -> Result<(TempDir, SystemProperties)> {
+    let dir = TempDir::new()?;
+
+    let contexts_path = dir.path().join("property_contexts");
+    File::create(&contexts_path)?.write_all(PROPERTY_CONTEXTS.as_bytes())?;
+    let (entries, errors) = PropertyInfoEntry::parse_from_file(&contexts_path, false)?;
+    if let Some(e) = errors.into_iter().next() {
+        return Err(e);
+    }
+    let trie = build_trie(&entries, DEFAULT_CONTEXT, DEFAULT_TYPE)?;
+    File::create(Layout::default().property_info_path(dir.path()))?.write_all(&trie)?;
+
+    let mut props = SystemProperties::new_area(dir.path())?;
+    props.batch(|props| {
+        PROPERTIES
+            .iter()
+            .try_for_each(|(name, value)| props.add(name, value))
+    })?;
+    Ok((dir, props))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demo_store() {
+        let (dir, props) = demo_store().unwrap();
+        for (name, value) in PROPERTIES {
+            assert_eq!(props.get_with_result(name).unwrap(), *value);
+        }
+        assert_eq!(props.property_type("demo.enabled").unwrap(), Some("bool"));
+
+        // Each call gets its own directory.
+        let (other, _) = demo_store().unwrap();
+        assert_ne!(dir.path(), other.path());
+        let path = dir.path().to_owned();
+        drop(props);
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
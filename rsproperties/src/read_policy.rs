@@ -0,0 +1,53 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! How the crate-level getters treat a property set to the empty string,
+//! see [`ReadPolicy`].
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How [`crate::get`], [`crate::get_or`] and the getters built on them
+/// treat a property whose value is the empty string.
+///
+/// Android has no way to delete a property, so "clearing" one means
+/// setting it to `""`, and most Android code reads empty as unset. The
+/// default keeps this crate's historical split; the other two make every
+/// getter agree:
+///
+/// | getter            | `Mixed` (default) | `EmptyIsUnset` | `EmptyIsValue` |
+/// |-------------------|-------------------|----------------|----------------|
+/// | [`crate::get`]    | parses `""`       | `NotFound`     | parses `""`    |
+/// | [`crate::get_or`] | default           | default        | parses `""`    |
+///
+/// "Parses `""`" means `T::from_str("")`: `Ok("")` for `String`, a parse
+/// error (or, for `get_or`, the default) for numbers. [`crate::get_optional`]
+/// always tells the three cases apart and is not affected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadPolicy {
+    /// `get` returns the empty value, `get_or` falls back to its default.
+    #[default]
+    Mixed,
+    /// Empty reads as unset everywhere.
+    EmptyIsUnset,
+    /// Empty is an ordinary value everywhere.
+    EmptyIsValue,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(ReadPolicy::Mixed as u8);
+
+/// Sets the [`ReadPolicy`] of the crate-level getters for this process.
+/// Takes effect for reads that start afterwards; [`crate::SystemProperties`]
+/// methods are not affected.
+pub fn set_read_policy(policy: ReadPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// The current [`ReadPolicy`].
+pub fn read_policy() -> ReadPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => ReadPolicy::EmptyIsUnset,
+        2 => ReadPolicy::EmptyIsValue,
+        _ => ReadPolicy::Mixed,
+    }
+}
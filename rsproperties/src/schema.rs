@@ -0,0 +1,561 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Machine-readable descriptions of the properties `property_contexts`
+//! declares, for consumers outside Rust.
+//!
+//! [`export_schema`] turns the [`PropertyInfoEntry`] list the trie is
+//! built from into a JSON Schema, TypeScript definitions or a Kotlin
+//! object, so services and app-side code share one source of truth for
+//! property names, types and enum values. Values are strings on the wire
+//! whatever their declared type, so every format describes them as
+//! strings, narrowed where the type allows:
+//!
+//! | type          | JSON Schema                    | TypeScript                      |
+//! |---------------|--------------------------------|---------------------------------|
+//! | `string`      | `string`                       | `string`                        |
+//! | `bool`        | enum `true false 1 0`          | `"true" \| "false" \| "1" \| "0"` |
+//! | `enum a b`    | enum `a b`                     | `"a" \| "b"`                    |
+//! | `int`, `uint`, `double`, `size` | pattern of the value syntax | `string` |
+//! | `bytes`       | `contentEncoding: base64`      | `string`                        |
+//!
+//! An entry without a type is a `string`. Exact entries describe one
+//! property; prefix entries every property under the prefix (a JSON
+//! Schema `patternProperties` regex, a TypeScript template-literal index
+//! signature, a Kotlin `_PREFIX` constant).
+//!
+//! [`PropertyRequirement`]s add what `property_contexts` cannot say: a
+//! default for a property the build.prop files may leave out, and that a
+//! property must be set before anything depending on it starts.
+//! [`apply_requirements`] fills in the defaults and lists what is still
+//! missing; the property service runs it at bootstrap.
+
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::path::Path;
+
+use crate::errors::*;
+use crate::migrate::push_json_string;
+use crate::PropertyInfoEntry;
+
+/// Output format of [`export_schema`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SchemaFormat {
+    /// A JSON Schema (draft 2020-12) of a name → value object, the shape
+    /// `rsprops export --format json` writes.
+    JsonSchema,
+    /// An exported `SystemProperties` interface with one optional member
+    /// per property.
+    TypeScript,
+    /// An `object SystemProperties` of name constants; enum properties
+    /// get a `_VALUES` list next to their name.
+    Kotlin,
+}
+
+/// Value syntax of a declared property type.
+enum ValueType<'a> {
+    String,
+    Enum(Vec<&'a str>),
+    Pattern(&'static str),
+    Base64,
+}
+
+const BOOL_VALUES: &[&str] = &["true", "false", "1", "0"];
+
+fn value_type(type_str: &str) -> ValueType<'_> {
+    let mut tokens = type_str.split_whitespace();
+    match tokens.next() {
+        None | Some("string") => ValueType::String,
+        Some("enum") => ValueType::Enum(tokens.collect()),
+        Some("bool") => ValueType::Enum(BOOL_VALUES.to_vec()),
+        Some("int") => ValueType::Pattern("^[+-]?[0-9]+$"),
+        Some("uint") => ValueType::Pattern("^[0-9]+$"),
+        Some("double") => {
+            ValueType::Pattern("^[+-]?([0-9]+\\.?[0-9]*|\\.[0-9]+)([eE][+-]?[0-9]+)?$")
+        }
+        // The syntax `ByteSize` parses.
+        Some("size") => ValueType::Pattern("^[0-9]+([kKmMgGtT]([iI]?[bB])?|[bB])?$"),
+        Some(crate::bytes_value::BYTES_TYPE) => ValueType::Base64,
+        // `PropertyInfoEntry` validated the type; a new one is a string
+        // until described here.
+        Some(_) => ValueType::String,
+    }
+}
+
+/// Describes `entries` in `format`.
+///
+/// Fails with [`Error::InvalidArgument`] on two exact or two prefix
+/// entries for one name, as [`crate::build_trie`] does, and for
+/// [`SchemaFormat::Kotlin`] when two names map to the same constant
+/// (`a.b_c` and `a.b.c` are both `A_B_C`).
+pub fn export_schema(entries: &[PropertyInfoEntry], format: SchemaFormat) -> Result<String> {
+    let mut exact = BTreeMap::new();
+    let mut prefixes = BTreeMap::new();
+    for entry in entries {
+        let (map, what) = if entry.exact_match() {
+            (&mut exact, "Exact match")
+        } else {
+            (&mut prefixes, "Prefix")
+        };
+        match map.entry(entry.name()) {
+            Entry::Vacant(slot) => {
+                slot.insert(entry.type_str());
+            }
+            Entry::Occupied(_) => {
+                return Err(Error::InvalidArgument(format!(
+                    "{what} already exists for '{}'",
+                    entry.name()
+                )));
+            }
+        }
+    }
+    match format {
+        SchemaFormat::JsonSchema => Ok(json_schema(&exact, &prefixes)),
+        SchemaFormat::TypeScript => Ok(typescript(&exact, &prefixes)),
+        SchemaFormat::Kotlin => kotlin(&exact, &prefixes),
+    }
+}
+
+fn push_json_value_schema(out: &mut String, type_str: &str) {
+    out.push_str("{ \"type\": \"string\"");
+    match value_type(type_str) {
+        ValueType::String => {}
+        ValueType::Enum(values) => {
+            out.push_str(", \"enum\": [");
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                push_json_string(out, value);
+            }
+            out.push(']');
+        }
+        ValueType::Pattern(pattern) => {
+            out.push_str(", \"pattern\": ");
+            push_json_string(out, pattern);
+        }
+        ValueType::Base64 => out.push_str(", \"contentEncoding\": \"base64\""),
+    }
+    if !type_str.is_empty() {
+        out.push_str(", \"x-property-type\": ");
+        push_json_string(out, type_str);
+    }
+    out.push_str(" }");
+}
+
+fn push_json_members<'a>(
+    out: &mut String,
+    key: &str,
+    members: impl Iterator<Item = (String, &'a str)>,
+) {
+    out.push_str(",\n  \"");
+    out.push_str(key);
+    out.push_str("\": {");
+    for (i, (name, type_str)) in members.enumerate() {
+        out.push_str(if i == 0 { "\n    " } else { ",\n    " });
+        push_json_string(out, &name);
+        out.push_str(": ");
+        push_json_value_schema(out, type_str);
+    }
+    out.push_str("\n  }");
+}
+
+fn json_schema(exact: &BTreeMap<&str, &str>, prefixes: &BTreeMap<&str, &str>) -> String {
+    let mut out = String::from(
+        "{\n  \"$schema\": \"https://json-schema.org/draft/2020-12/schema\",\n  \
+         \"title\": \"System properties\",\n  \
+         \"type\": \"object\"",
+    );
+    push_json_members(
+        &mut out,
+        "properties",
+        exact
+            .iter()
+            .map(|(&name, &type_str)| (name.to_owned(), type_str)),
+    );
+    // Property names hold no regex metacharacter but `.`.
+    push_json_members(
+        &mut out,
+        "patternProperties",
+        prefixes
+            .iter()
+            .map(|(&prefix, &type_str)| (format!("^{}", prefix.replace('.', "\\.")), type_str)),
+    );
+    out.push_str(",\n  \"additionalProperties\": { \"type\": \"string\" }\n}\n");
+    out
+}
+
+fn typescript_type(type_str: &str) -> String {
+    match value_type(type_str) {
+        ValueType::Enum(values) => {
+            let mut out = String::new();
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(" | ");
+                }
+                push_json_string(&mut out, value);
+            }
+            out
+        }
+        _ => "string".to_owned(),
+    }
+}
+
+fn push_doc(out: &mut String, indent: &str, type_str: &str) {
+    if !type_str.is_empty() {
+        out.push_str(&format!("{indent}/** `{type_str}` */\n"));
+    }
+}
+
+fn typescript(exact: &BTreeMap<&str, &str>, prefixes: &BTreeMap<&str, &str>) -> String {
+    let mut out = String::from(
+        "// Generated by rsproperties::export_schema. Values are strings whatever\n\
+         // their declared type.\n\
+         export interface SystemProperties {\n",
+    );
+    for (&name, &type_str) in exact {
+        push_doc(&mut out, "  ", type_str);
+        out.push_str("  ");
+        push_json_string(&mut out, name);
+        out.push_str(&format!("?: {};\n", typescript_type(type_str)));
+    }
+    for (&prefix, &type_str) in prefixes {
+        push_doc(&mut out, "  ", type_str);
+        out.push_str(&format!(
+            "  [name: `{prefix}${{string}}`]: {} | undefined;\n",
+            typescript_type(type_str)
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A Kotlin string literal: JSON's escapes plus `$`, which starts a
+/// template.
+fn push_kotlin_string(out: &mut String, s: &str) {
+    let mut literal = String::new();
+    push_json_string(&mut literal, s);
+    out.push_str(&literal.replace('$', "\\$"));
+}
+
+/// `SCREAMING_SNAKE_CASE` constant name for a property name.
+fn kotlin_ident(name: &str, suffix: &str) -> String {
+    let mut ident: String = name
+        .trim_end_matches('.')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    ident.push_str(suffix);
+    ident
+}
+
+fn kotlin(exact: &BTreeMap<&str, &str>, prefixes: &BTreeMap<&str, &str>) -> Result<String> {
+    let mut out = String::from(
+        "// Generated by rsproperties::export_schema.\n\
+         object SystemProperties {\n",
+    );
+    let mut idents = BTreeMap::new();
+    let constants = exact
+        .iter()
+        .map(|(&name, &type_str)| (name, type_str, ""))
+        .chain(
+            prefixes
+                .iter()
+                .map(|(&prefix, &type_str)| (prefix, type_str, "_PREFIX")),
+        );
+    for (name, type_str, suffix) in constants {
+        let ident = kotlin_ident(name, suffix);
+        if let Some(other) = idents.insert(ident.clone(), name) {
+            return Err(Error::InvalidArgument(format!(
+                "'{other}' and '{name}' both map to the Kotlin constant {ident}"
+            )));
+        }
+        push_doc(&mut out, "    ", type_str);
+        out.push_str(&format!("    const val {ident} = "));
+        push_kotlin_string(&mut out, name);
+        out.push('\n');
+        if let ValueType::Enum(values) = value_type(type_str) {
+            let values_ident = format!("{ident}_VALUES");
+            if let Some(other) = idents.insert(values_ident.clone(), name) {
+                return Err(Error::InvalidArgument(format!(
+                    "'{other}' and '{name}' both map to the Kotlin constant {values_ident}"
+                )));
+            }
+            out.push_str(&format!("    val {values_ident} = listOf("));
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                push_kotlin_string(&mut out, value);
+            }
+            out.push_str(")\n");
+        }
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// A default value or a presence guarantee for one property, see
+/// [`apply_requirements`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PropertyRequirement {
+    /// Exact property name.
+    pub name: String,
+    /// Value to set when the property is missing.
+    pub default: Option<String>,
+    /// Whether a property still missing after the defaults is an error.
+    pub required: bool,
+}
+
+impl PropertyRequirement {
+    /// `name` must be set; it has no default.
+    pub fn required(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            default: None,
+            required: true,
+        }
+    }
+
+    /// `name` is set to `value` when missing.
+    pub fn with_default(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            default: Some(value.into()),
+            required: false,
+        }
+    }
+
+    /// Sets whether the property must be present.
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    /// Parses requirements, one per line:
+    ///
+    /// ```text
+    /// # comment
+    /// ro.hardware required
+    /// ro.product.locale default en-US
+    /// ```
+    ///
+    /// A default is the rest of the line after `default`, trimmed, and
+    /// may be empty. Fails with [`Error::Parse`] naming the first bad line,
+    /// and on a name given twice.
+    pub fn parse(text: &str) -> Result<Vec<Self>> {
+        let mut requirements: Vec<Self> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |what: &str| Error::Parse(format!("line {}: {what}", index + 1));
+            let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if !crate::is_valid_property_name(name) {
+                return Err(bad(&format!("invalid property name '{name}'")));
+            }
+            let rest = rest.trim_start();
+            let requirement = if rest == "required" {
+                Self::required(name)
+            } else if let Some(value) = rest
+                .strip_prefix("default")
+                .filter(|value| value.is_empty() || value.starts_with(char::is_whitespace))
+            {
+                Self::with_default(name, value.trim())
+            } else {
+                return Err(bad(&format!(
+                    "expected 'required' or 'default <value>' after '{name}'"
+                )));
+            };
+            if requirements.iter().any(|other| other.name == name) {
+                return Err(bad(&format!("'{name}' is listed twice")));
+            }
+            requirements.push(requirement);
+        }
+        Ok(requirements)
+    }
+
+    /// [`Self::parse`] on the contents of `path`.
+    pub fn parse_from_file(path: &Path) -> Result<Vec<Self>> {
+        let text = std::fs::read_to_string(path)
+            .context_with_location(format!("Failed to read requirements file {path:?}"))?;
+        Self::parse(&text).map_err(|e| Error::Parse(format!("{path:?}: {e}")))
+    }
+}
+
+/// What [`apply_requirements`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RequirementReport {
+    /// Properties that were missing and got their default, in
+    /// requirement order.
+    pub defaulted: Vec<String>,
+    /// Required properties that are missing and have no default, in
+    /// requirement order.
+    pub missing: Vec<String>,
+}
+
+impl RequirementReport {
+    /// Whether every required property is present.
+    pub fn is_satisfied(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Sets the default of each requirement whose property is missing from
+/// `properties` — absent or empty, as Android reads an empty value as
+/// unset — and reports the required ones that stay missing.
+pub fn apply_requirements(
+    properties: &mut BTreeMap<String, String>,
+    requirements: &[PropertyRequirement],
+) -> RequirementReport {
+    let mut report = RequirementReport::default();
+    for requirement in requirements {
+        if properties
+            .get(&requirement.name)
+            .is_some_and(|value| !value.is_empty())
+        {
+            continue;
+        }
+        match &requirement.default {
+            Some(value) if !value.is_empty() || !requirement.required => {
+                properties.insert(requirement.name.clone(), value.clone());
+                report.defaulted.push(requirement.name.clone());
+            }
+            _ if requirement.required => report.missing.push(requirement.name.clone()),
+            _ => {}
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, type_str: &str, exact_match: bool) -> PropertyInfoEntry {
+        PropertyInfoEntry::new(
+            name.to_owned(),
+            "u:object_r:test_prop:s0".to_owned(),
+            type_str,
+            exact_match,
+        )
+        .unwrap()
+    }
+
+    fn entries() -> Vec<PropertyInfoEntry> {
+        vec![
+            entry("ro.build.version.sdk", "int", true),
+            entry("persist.sys.mode", "enum fast slow", true),
+            entry("persist.radio.", "string", false),
+            entry("sys.boot_completed", "bool", true),
+        ]
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = export_schema(&entries(), SchemaFormat::JsonSchema).unwrap();
+        assert_eq!(
+            schema,
+            r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "System properties",
+  "type": "object",
+  "properties": {
+    "persist.sys.mode": { "type": "string", "enum": ["fast", "slow"], "x-property-type": "enum fast slow" },
+    "ro.build.version.sdk": { "type": "string", "pattern": "^[+-]?[0-9]+$", "x-property-type": "int" },
+    "sys.boot_completed": { "type": "string", "enum": ["true", "false", "1", "0"], "x-property-type": "bool" }
+  },
+  "patternProperties": {
+    "^persist\\.radio\\.": { "type": "string", "x-property-type": "string" }
+  },
+  "additionalProperties": { "type": "string" }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_typescript_and_kotlin() {
+        let ts = export_schema(&entries(), SchemaFormat::TypeScript).unwrap();
+        assert!(ts.contains("  \"persist.sys.mode\"?: \"fast\" | \"slow\";\n"));
+        assert!(ts.contains("  /** `int` */\n  \"ro.build.version.sdk\"?: string;\n"));
+        assert!(ts.contains("  [name: `persist.radio.${string}`]: string | undefined;\n"));
+
+        let kotlin = export_schema(&entries(), SchemaFormat::Kotlin).unwrap();
+        assert!(kotlin.contains("    const val RO_BUILD_VERSION_SDK = \"ro.build.version.sdk\"\n"));
+        assert!(kotlin.contains("    val PERSIST_SYS_MODE_VALUES = listOf(\"fast\", \"slow\")\n"));
+        assert!(kotlin.contains("    const val PERSIST_RADIO_PREFIX = \"persist.radio.\"\n"));
+
+        let dollar = [entry("a.mode", "enum $x y", true)];
+        let kotlin = export_schema(&dollar, SchemaFormat::Kotlin).unwrap();
+        assert!(kotlin.contains("listOf(\"\\$x\", \"y\")"));
+
+        let clash = [entry("a.b_c", "", true), entry("a.b.c", "", true)];
+        assert!(export_schema(&clash, SchemaFormat::Kotlin).is_err());
+        assert!(export_schema(&clash, SchemaFormat::TypeScript).is_ok());
+
+        let duplicate = [entry("a.b", "int", true), entry("a.b", "bool", true)];
+        assert!(matches!(
+            export_schema(&duplicate, SchemaFormat::JsonSchema),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_requirements() {
+        let requirements = PropertyRequirement::parse(
+            "# bootstrap guarantees\n\
+             ro.hardware required\n\
+             ro.product.locale default en-US\n\
+             ro.boot.mode   default  normal boot \n\
+             ro.serialno required\n",
+        )
+        .unwrap();
+        assert_eq!(
+            requirements,
+            [
+                PropertyRequirement::required("ro.hardware"),
+                PropertyRequirement::with_default("ro.product.locale", "en-US"),
+                PropertyRequirement::with_default("ro.boot.mode", "normal boot"),
+                PropertyRequirement::required("ro.serialno"),
+            ]
+        );
+
+        let mut properties = BTreeMap::from([
+            ("ro.hardware".to_owned(), "ranchu".to_owned()),
+            ("ro.product.locale".to_owned(), "ko-KR".to_owned()),
+            ("ro.serialno".to_owned(), String::new()),
+        ]);
+        let report = apply_requirements(&mut properties, &requirements);
+        assert_eq!(report.defaulted, ["ro.boot.mode"]);
+        assert_eq!(report.missing, ["ro.serialno"]);
+        assert!(!report.is_satisfied());
+        assert_eq!(properties["ro.product.locale"], "ko-KR");
+        assert_eq!(properties["ro.boot.mode"], "normal boot");
+
+        for bad in [
+            "ro.hardware\n",
+            "ro.hardware optional\n",
+            "ro.hardware defaults x\n",
+            "ro..hardware required\n",
+            "a.b required\na.b default 1\n",
+        ] {
+            assert!(
+                matches!(PropertyRequirement::parse(bad), Err(Error::Parse(_))),
+                "{bad:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! What-if copies of the property store, see
+//! [`SystemProperties::fork_cow`](crate::SystemProperties::fork_cow).
+
+use crate::errors::*;
+use crate::frozen::FrozenProperties;
+use crate::system_properties::{PropertyDescriptor, SystemProperties};
+
+/// A private, writable copy of a property store.
+///
+/// Every area is mapped copy-on-write, so [`Self::set`] goes through the
+/// same validation and trie code as a real write — `ro.` write-once,
+/// value length, `bytes` and area capacity checks included — but the
+/// modified pages belong to this process alone: the files, the property
+/// service and every other reader never see them, and they are discarded
+/// on drop. Useful for
+/// checking that a batch of changes (an OTA property delta, say) applies
+/// cleanly, and what it results in, before applying it for real.
+///
+/// Pages the copy has not written keep following the files, so a write
+/// to the real store after the fork shows through wherever the copy has
+/// not diverged; fork from a quiescent store when that matters.
+pub struct ScratchProperties {
+    properties: SystemProperties,
+}
+
+impl ScratchProperties {
+    pub(crate) fn new(properties: SystemProperties) -> Self {
+        Self { properties }
+    }
+
+    /// Creates or updates `name` in the copy; see [`SystemProperties::set`].
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        self.properties.set(name, value)
+    }
+
+    /// Sets each `(name, value)` pair in order, stopping at the first
+    /// failure. Pairs applied before the failure stay applied.
+    pub fn apply<'a, I>(&mut self, changes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        for (name, value) in changes {
+            self.set(name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Value of `name` in the copy; see [`SystemProperties::get_with_result`].
+    pub fn get_with_result(&self, name: &str) -> Result<String> {
+        self.properties.get_with_result(name)
+    }
+
+    /// See [`SystemProperties::describe`].
+    pub fn describe(&self, name: &str) -> Result<PropertyDescriptor> {
+        self.properties.describe(name)
+    }
+
+    /// Every property of the copy; see [`SystemProperties::freeze`].
+    pub fn freeze(&self) -> Result<FrozenProperties> {
+        self.properties.freeze()
+    }
+
+    /// The copy as a [`SystemProperties`], for the read-only queries not
+    /// mirrored above.
+    pub fn properties(&self) -> &SystemProperties {
+        &self.properties
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! SELinux contexts as they appear in `property_contexts`, `property_info`
+//! and the `security.selinux` xattr of the area files.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::*;
+
+/// A syntactically valid SELinux context, `user:role:type:level` — e.g.
+/// `u:object_r:system_prop:s0`.
+///
+/// Property area files are named after their context and labelled with
+/// it, so a malformed one used to surface only as a failed `fsetxattr`
+/// (logged, not fatal) when the writer created the file. Parsing into
+/// this type catches it where the context enters: in
+/// `PropertyInfoEntry::new`, in `property_contexts` lines, and in the
+/// default context of `build_trie`.
+///
+/// The check is syntax only: user, role and type are identifiers
+/// (ASCII letters, digits, `_`, `.` and `-`, starting with a letter), and
+/// the level starts with a sensitivity (`s0`) optionally followed by a
+/// range or categories (`s0-s15:c0.c1023`). Whether the policy knows the
+/// type is up to the kernel.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SelinuxContext(String);
+
+impl SelinuxContext {
+    /// Validates `context`; [`Error::InvalidArgument`] says what is wrong
+    /// with it.
+    pub fn new(context: impl Into<String>) -> Result<Self> {
+        let context = context.into();
+        match check(&context) {
+            Ok(()) => Ok(Self(context)),
+            Err(reason) => Err(Error::InvalidArgument(format!(
+                "SELinux context {context:?} is not valid: {reason}"
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    pub fn user(&self) -> &str {
+        self.field(0)
+    }
+
+    pub fn role(&self) -> &str {
+        self.field(1)
+    }
+
+    /// The type, e.g. `system_prop` — what `property_contexts` entries
+    /// actually differ in.
+    pub fn type_name(&self) -> &str {
+        self.field(2)
+    }
+
+    /// Sensitivity and categories, e.g. `s0` or `s0:c512,c768`.
+    pub fn level(&self) -> &str {
+        self.field(3)
+    }
+
+    fn field(&self, index: usize) -> &str {
+        // `check` guarantees four fields; the level keeps its colons.
+        self.0.splitn(4, ':').nth(index).unwrap_or_default()
+    }
+
+    /// The context as the `security.selinux` xattr value, NUL included
+    /// like bionic's.
+    pub(crate) fn xattr_value(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(self.0.len() + 1);
+        value.extend_from_slice(self.0.as_bytes());
+        value.push(0);
+        value
+    }
+}
+
+fn check(context: &str) -> std::result::Result<(), &'static str> {
+    if context.is_empty() {
+        return Err("it is empty");
+    }
+    if !context.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err("it contains whitespace, control or non-ASCII characters");
+    }
+    let fields: Vec<&str> = context.splitn(4, ':').collect();
+    let [user, role, rtype, level] = fields[..] else {
+        return Err("expected user:role:type:level");
+    };
+    for (identifier, reason) in [
+        (user, "the user is not an identifier"),
+        (role, "the role is not an identifier"),
+        (rtype, "the type is not an identifier"),
+    ] {
+        if !is_identifier(identifier) {
+            return Err(reason);
+        }
+    }
+    let mut level_chars = level.bytes();
+    if level_chars.next() != Some(b's') || !level_chars.next().is_some_and(|b| b.is_ascii_digit()) {
+        return Err("the level does not start with a sensitivity such as s0");
+    }
+    if !level
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b".,:-_".contains(&b))
+    {
+        return Err("the level contains characters other than sensitivities and categories");
+    }
+    Ok(())
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.bytes().next().is_some_and(|b| b.is_ascii_alphabetic())
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"_.-".contains(&b))
+}
+
+impl FromStr for SelinuxContext {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<String> for SelinuxContext {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<&str> for SelinuxContext {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for SelinuxContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for SelinuxContext {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for SelinuxContext {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SelinuxContext {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<SelinuxContext> for str {
+    fn eq(&self, other: &SelinuxContext) -> bool {
+        self == other.0
+    }
+}
+
+impl PartialEq<SelinuxContext> for &str {
+    fn eq(&self, other: &SelinuxContext) -> bool {
+        *self == other.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_contexts() {
+        for context in [
+            "u:object_r:build_prop:s0",
+            "u:object_r:vendor_default_prop:s0",
+            "system_u:object_r:etc_t:s0-s15:c0.c1023",
+            "u:object_r:app_data_file:s0:c512,c768",
+        ] {
+            let parsed: SelinuxContext = context.parse().unwrap();
+            assert_eq!(parsed, context);
+            assert_eq!(parsed.to_string(), context);
+        }
+
+        let context = SelinuxContext::new("u:object_r:app_data_file:s0:c512,c768").unwrap();
+        assert_eq!(context.user(), "u");
+        assert_eq!(context.role(), "object_r");
+        assert_eq!(context.type_name(), "app_data_file");
+        assert_eq!(context.level(), "s0:c512,c768");
+        assert_eq!(
+            context.xattr_value(),
+            b"u:object_r:app_data_file:s0:c512,c768\0"
+        );
+    }
+
+    #[test]
+    fn test_invalid_contexts() {
+        for context in [
+            "",
+            "build_prop",
+            "u:object_r:build_prop",
+            "u:object_r:*:s0",
+            "u:object_r::s0",
+            "u::build_prop:s0",
+            "u:object_r:build prop:s0",
+            "u:object_r:build_prop:s0\0",
+            "u:object_r:build_prop:c0",
+            "u:object_r:build_prop:s0/c0",
+            "u:object_r:9prop:s0",
+        ] {
+            let err = SelinuxContext::new(context).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidArgument(_)),
+                "{context:?}: {err}"
+            );
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Where the property service sockets live, see [`socket_dir`]. Kept apart
+//! from the client in `system_property_set` so builds without the
+//! `service-protocol` feature can still configure the directory for the
+//! service they embed.
+
+use std::sync::OnceLock;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+const DEFAULT_SOCKET_DIR: &str = "/dev/socket";
+pub const PROPERTY_SERVICE_SOCKET_NAME: &str = "property_service";
+pub const PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME: &str = "property_service_for_system";
+/// Environment variable [`socket_dir`] falls back to when no directory was
+/// configured in code; set it on child processes to point them at a
+/// non-default service.
+pub const SOCKET_DIR_ENV: &str = "PROPERTY_SERVICE_SOCKET_DIR";
+
+/// Global socket directory configuration
+static SOCKET_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Set the global socket directory for property services (internal use only).
+/// This function can only be called once. Subsequent calls will be ignored.
+///
+/// Callers must hold `crate::GLOBAL_DIRS_LOCK` (see `lib::try_init`) so the
+/// pre-check + set sequence stays atomic against the implicit latch in
+/// [`socket_dir`].
+///
+/// # Arguments
+/// * `dir` - The directory path where property service sockets are located
+///
+/// # Returns
+/// * `true` if the directory was successfully set (first call)
+/// * `false` if the directory was already set (subsequent calls)
+pub(crate) fn set_socket_dir<P: AsRef<Path>>(dir: P) -> bool {
+    let dir_path = dir.as_ref().to_path_buf();
+
+    SOCKET_DIR.set(dir_path).is_ok()
+}
+
+/// `true` once `set_socket_dir` has succeeded (or `socket_dir()` was called
+/// and populated the cell via env/default). Used by `lib::try_init` for
+/// pre-flight checks before committing other globals.
+pub(crate) fn socket_dir_is_set() -> bool {
+    SOCKET_DIR.get().is_some()
+}
+
+/// Get the current socket directory.
+/// Returns the configured socket directory, environment variable, or default.
+///
+/// Priority order:
+/// 1. Directory set via `set_socket_dir()`
+/// 2. The [`SOCKET_DIR_ENV`] (`PROPERTY_SERVICE_SOCKET_DIR`) environment
+///    variable
+/// 3. Default directory: `/dev/socket`
+pub fn socket_dir() -> &'static Path {
+    // Lock-free once initialized; the first call takes `GLOBAL_DIRS_LOCK` so
+    // the env/default latch cannot slip between `try_init`'s pre-check and
+    // its `set_socket_dir` commit.
+    if let Some(dir) = SOCKET_DIR.get() {
+        return dir.as_path();
+    }
+    let _guard = crate::lock_global_dirs();
+    SOCKET_DIR
+        .get_or_init(|| {
+            // `var_os`, not `var`: Unix paths are arbitrary bytes, and a
+            // non-UTF-8 configured directory must be *used*, not silently
+            // swapped for the default — the same
+            // different-path-on-lossy-conversion hazard
+            // `get_property_service_socket` documents.
+            env::var_os(SOCKET_DIR_ENV)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET_DIR))
+        })
+        .as_path()
+}
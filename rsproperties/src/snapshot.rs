@@ -0,0 +1,101 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Property sets that can be written out and restored elsewhere, see
+//! [`PropertySnapshot`].
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Every property of a store with its declared metadata, taken by
+/// [`SystemProperties::snapshot`](crate::SystemProperties::snapshot) and
+/// written back by
+/// [`SystemProperties::restore`](crate::SystemProperties::restore) — to
+/// move a property set between machines, or to keep one as a test
+/// fixture.
+///
+/// With the `serde` feature it serializes to any serde format; in JSON:
+///
+/// ```json
+/// {
+///   "properties": {
+///     "ro.build.version.sdk": {
+///       "value": "34",
+///       "context": "u:object_r:build_prop:s0",
+///       "type": "int"
+///     }
+///   }
+/// }
+/// ```
+///
+/// Properties are kept sorted by name, so the same set always serializes
+/// the same way and fixtures diff cleanly. `context` and `type` may be
+/// left out of a hand-written file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct PropertySnapshot {
+    pub properties: BTreeMap<String, SnapshotEntry>,
+}
+
+/// One property of a [`PropertySnapshot`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct SnapshotEntry {
+    pub value: String,
+    /// The SELinux context the `property_info` trie assigned, if any.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub context: Option<String>,
+    /// The declared type (e.g. `int`, `enum a b`), if any.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "type", default, skip_serializing_if = "Option::is_none")
+    )]
+    pub type_str: Option<String>,
+}
+
+impl SnapshotEntry {
+    /// An entry with `value` and no metadata.
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            context: None,
+            type_str: None,
+        }
+    }
+}
+
+impl PropertySnapshot {
+    /// Value of `name` in the snapshot.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|entry| entry.value.as_str())
+    }
+
+    /// Adds `name` with `value` and no metadata, replacing any entry it
+    /// had.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.properties
+            .insert(name.into(), SnapshotEntry::new(value));
+    }
+
+    /// Iterates over `(name, value)` pairs, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.properties
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry.value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+}
@@ -1,160 +1,437 @@
 // Copyright 2024 Jeff Kim <hiking90@gmail.com>
 // SPDX-License-Identifier: Apache-2.0
 
-use std::path::Path;
-use std::sync::atomic::{fence, AtomicU32, Ordering};
-#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use rustix::fs::Timespec;
-#[cfg(any(target_os = "android", target_os = "linux"))]
-use rustix::thread::futex;
 
+use crate::backend::{self, WaitOutcome};
+use crate::checksum::{self, RecordCheck};
 use crate::errors::*;
 
+use crate::context_cache::ContextCacheStats;
+use crate::context_node::AreaRef;
 use crate::contexts_serialized::ContextsSerialized;
+use crate::frozen::FrozenProperties;
+#[cfg(feature = "writer")]
+use crate::journal::{Journal, JournalRecord};
+use crate::layout::{fold_case, Layout};
+use crate::lock_order;
+use crate::property_area::{FileId, PropertyArea, PropertyAreaMap, CONTEXT_SERIALS_ENABLED};
+#[cfg(feature = "writer")]
+use crate::scratch::ScratchProperties;
+use crate::snapshot::{PropertySnapshot, SnapshotEntry};
+use crate::wait_stats;
 
 pub(crate) use crate::wire::PROP_VALUE_MAX;
-pub(crate) const PROP_TREE_FILE: &str = "/dev/__properties__/property_info";
 
 #[inline(always)]
 fn serial_dirty(serial: u32) -> bool {
     (serial & 1) != 0
 }
 
-#[cfg(feature = "builder")]
-fn futex_wake(_addr: &AtomicU32) -> Result<usize> {
-    #[cfg(any(target_os = "android", target_os = "linux"))]
-    {
-        futex::wake(_addr, futex::Flags::empty(), i32::MAX as u32)
-            .context_with_location("Failed to wake futex")
-    }
-    #[cfg(target_os = "macos")]
-    Ok(0)
-}
-
-/// Outcome of one [`futex_wait`] call. A three-way result rather than
-/// `Option`: the sliced wait loop in [`SystemProperties::wait`] must retry
-/// on an ordinary timeout but bail out on a syscall-level failure —
-/// collapsing both into `None` would turn a persistent futex error into a
-/// busy loop.
-#[derive(Clone, Copy, Debug)]
-// On macOS only `Failed` is ever constructed (no futex support).
-#[cfg_attr(target_os = "macos", allow(dead_code))]
-enum FutexWaitOutcome {
-    /// The serial changed; carries the freshly-loaded value.
-    Changed(u32),
-    /// The timeout elapsed (or the caller passed an invalid/negative one).
-    TimedOut,
-    /// Unexpected futex error — or no futex support on this platform.
-    Failed,
-}
-
-/// Waits until `_serial` differs from `_value`, or the timeout elapses.
+/// Whether a failed lookup is the caller's business rather than the
+/// store's: the property is absent, or the name cannot name one (the layer
+/// below reports an unknown context at `debug!`). These are returned
+/// without logging — apps probing optional properties must not fill the
+/// log — while a corrupt or vanished area or an I/O failure is logged with
+/// the property name.
+fn is_quiet_lookup_error(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::NotFound(_) | Error::InvalidArgument(_) | Error::Parse(_)
+    )
+}
+
+/// Whether the store whose serial area is `serial_area` keeps per-context
+/// serials (see [`SystemProperties::enable_context_serials`]).
+fn has_context_serials(serial_area: &PropertyArea) -> bool {
+    serial_area.context_serials().load(Ordering::Acquire) == CONTEXT_SERIALS_ENABLED
+}
+
+/// A property found with [`SystemProperties::find`], for repeated
+/// [`SystemProperties::update`], [`SystemProperties::serial`] and
+/// [`SystemProperties::wait`] calls without a name lookup each time.
 ///
-/// On macOS there is no futex; returns [`FutexWaitOutcome::Failed`]
-/// immediately — see `SystemProperties::wait`.
-fn futex_wait(_serial: &AtomicU32, _value: u32, _timeout: Option<&Timespec>) -> FutexWaitOutcome {
-    #[cfg(any(target_os = "android", target_os = "linux"))]
-    {
-        use rustix::io::Errno;
-        // Linux futex_wait takes a *relative* timeout. Spurious wakes restart
-        // the syscall, so we track a deadline and shrink the remaining timeout
-        // each iteration to keep the total wait bounded by the caller-supplied
-        // value.
-        //
-        // `Timespec.tv_sec`/`tv_nsec` are signed (i64). Negative values are
-        // not valid timeouts; treat them as immediate timeout to avoid
-        // panicking in `Instant + Duration` from a `usize::MAX`-ish wrap.
-        // A *huge* positive `tv_sec` (e.g. i64::MAX, a reasonable "wait
-        // forever") is the opposite hazard: `Instant + Duration` panics on
-        // overflow, so an unrepresentable deadline degrades to an infinite
-        // wait instead — matching bionic, which passes the value through to
-        // the futex untouched.
-        let deadline = match _timeout {
-            None => None,
-            Some(t) if t.tv_sec < 0 || t.tv_nsec < 0 || t.tv_nsec >= 1_000_000_000 => {
-                return FutexWaitOutcome::TimedOut;
-            }
-            Some(t) => Instant::now().checked_add(Duration::new(t.tv_sec as u64, t.tv_nsec as u32)),
-        };
-        loop {
-            let remaining_ts = match deadline {
-                None => None,
-                Some(d) => {
-                    let r = d.saturating_duration_since(Instant::now());
-                    if r.is_zero() {
-                        return FutexWaitOutcome::TimedOut;
-                    }
-                    Some(Timespec {
-                        tv_sec: r.as_secs() as _,
-                        tv_nsec: r.subsec_nanos() as _,
-                    })
-                }
-            };
-            match futex::wait(
-                _serial,
-                futex::Flags::empty(),
-                _value as _,
-                remaining_ts.as_ref(),
-            ) {
-                Ok(_) => {
-                    let new_serial = _serial.load(Ordering::Acquire);
-                    if _value != new_serial {
-                        return FutexWaitOutcome::Changed(new_serial);
-                    }
-                    // Spurious wake — loop with the recomputed remaining timeout.
-                }
-                // EAGAIN: the serial no longer equals `_value` at syscall
-                // time — i.e. the property changed between the caller's load
-                // and the wait. This is the *common* race, not a failure;
-                // bionic's wait loop falls through to the serial re-check
-                // and reports success. Treating it as an error here would
-                // silently swallow a real property change.
-                Err(Errno::AGAIN) => {
-                    let new_serial = _serial.load(Ordering::Acquire);
-                    if _value != new_serial {
-                        return FutexWaitOutcome::Changed(new_serial);
-                    }
-                    // Serial changed and wrapped back to `_value` between the
-                    // syscall and the reload — vanishingly unlikely; retry.
-                }
-                // Interrupted by a signal — retry with the recomputed
-                // remaining timeout so the total wait stays bounded.
-                Err(Errno::INTR) => {}
-                // Timeout is a normal outcome, not an error worth logging.
-                Err(Errno::TIMEDOUT) => return FutexWaitOutcome::TimedOut,
-                Err(e) => {
-                    log::error!("Failed to wait for property change: {e}");
-                    return FutexWaitOutcome::Failed;
-                }
-            }
+/// Besides the record's position, a handle remembers a checksum of the
+/// record's name and which area file it was found in. Every use checks
+/// both, so a handle that outlived its area — rebuilt by
+/// `migrate_area_dir`, or replaced by a restarted service and remapped by
+/// a later lookup — fails with [`Error::StaleHandle`] (`None` from
+/// `serial` and `wait`) instead of acting on whatever record now sits at
+/// its offset. [`Self::revalidate`] also picks up a replacement nobody
+/// has remapped yet; after a failure, [`SystemProperties::find`] the name
+/// again.
+///
+/// The handle also remembers the trie node it was found through, so
+/// whether the name still leads to the record can be checked without
+/// walking the trie again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PropertyHandle {
+    context_index: u32,
+    property_index: u32,
+    /// Offset of the trie node of the record's name.
+    node_offset: u32,
+    name_crc: u32,
+    /// Device and inode of the area file; `None` for memfd and buffer
+    /// areas, which are never replaced under a mapping.
+    area: Option<FileId>,
+}
+
+impl PropertyHandle {
+    fn new(
+        pa: &PropertyAreaMap,
+        context_index: u32,
+        node_offset: u32,
+        property_index: u32,
+    ) -> Result<Self> {
+        let name = pa.property_info_name(property_index)?.to_bytes();
+        Ok(Self {
+            context_index,
+            property_index,
+            node_offset,
+            name_crc: checksum::crc32(&[name]),
+            area: pa.file_id(),
+        })
+    }
+
+    /// Checks that the handle still names a record of `props`, remapping
+    /// its area first if the file was replaced. Fails with
+    /// [`Error::StaleHandle`] when it does not, and with the lookup error
+    /// when the area cannot be mapped.
+    ///
+    /// The replaced mapping is unmapped once no read uses it any more, see
+    /// [`SystemProperties::retired_mappings`].
+    pub fn revalidate(&self, props: &SystemProperties) -> Result<()> {
+        let contexts = props.contexts()?;
+        contexts.revalidate_area(self.context_index)?;
+        self.check(&*contexts.prop_area_with_index(self.context_index)?)
+    }
+
+    /// The name of the record the handle refers to, as stored.
+    pub fn name(&self, props: &SystemProperties) -> Result<String> {
+        let pa = props.resolve(self)?;
+        pa.property_info_name(self.property_index)?
+            .to_str()
+            .map(str::to_owned)
+            .map_err(Error::Utf8)
+    }
+
+    /// Whether `pa` is still the area the handle was found in and its
+    /// record still carries the same name.
+    fn check(&self, pa: &PropertyAreaMap) -> Result<()> {
+        if pa.file_id() != self.area {
+            return Err(Error::StaleHandle(format!(
+                "area of context {} was replaced",
+                self.context_index
+            )));
+        }
+        let same_name = pa
+            .property_info_name(self.property_index)
+            .is_ok_and(|name| checksum::crc32(&[name.to_bytes()]) == self.name_crc);
+        if !same_name {
+            return Err(Error::StaleHandle(format!(
+                "record at offset {} of context {} holds another property",
+                self.property_index, self.context_index
+            )));
         }
+        Ok(())
     }
-    #[cfg(target_os = "macos")]
-    {
-        let _ = (_serial, _value, _timeout);
-        FutexWaitOutcome::Failed
+}
+
+/// What a property is declared as, together with its current state; see
+/// [`SystemProperties::describe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PropertyDescriptor {
+    /// SELinux context the `property_info` trie assigns to the name, or
+    /// `None` if no entry (nor a default) covers it.
+    pub context: Option<String>,
+    /// Declared type (`"string"`, `"int"`, `"enum a b"`, ...), or `None`.
+    pub type_str: Option<String>,
+    /// Whether the property currently has a value.
+    pub exists: bool,
+    /// Whether the value is stored out of line (`ro.` values of
+    /// `PROP_VALUE_MAX` bytes or more). `false` when it does not exist.
+    pub is_long: bool,
+    /// The property's serial as [`SystemProperties::serial`] returns it,
+    /// or `None` when it does not exist.
+    pub serial: Option<u32>,
+}
+
+/// A property's value together with its metadata, read in one lookup;
+/// see [`SystemProperties::get_entry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PropertyEntry {
+    /// The name as stored (lowercased in a case-insensitive store).
+    pub name: String,
+    pub value: String,
+    /// SELinux context of the area the property lives in, or `None` if no
+    /// `property_info` entry (nor a default) covers it.
+    pub context: Option<String>,
+    /// Declared type (`"string"`, `"int"`, `"enum a b"`, ...), or `None`.
+    pub type_str: Option<String>,
+    /// The serial `value` was read at, as bionic's
+    /// `__system_property_read_callback` reports it.
+    pub serial: u32,
+    /// Whether the value is stored out of line (`ro.` values of
+    /// `PROP_VALUE_MAX` bytes or more).
+    pub is_long: bool,
+}
+
+/// What [`SystemProperties::format_getprop`] prints next to each name,
+/// as Android's `getprop` does without flags, with `-T` and with `-Z`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GetpropColumn {
+    /// The value.
+    #[default]
+    Value,
+    /// The declared type (`-T`).
+    Type,
+    /// The SELinux context (`-Z`).
+    Context,
+}
+
+/// Result of [`SystemProperties::scrub`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ScrubReport {
+    /// Records whose checksum matched.
+    pub verified: usize,
+    /// Records with no checksum to compare against: areas without a
+    /// sidecar, or records added by a writer that does not keep them.
+    pub unchecked: usize,
+    /// Names of the records that failed their checksum, sorted.
+    pub corrupt: Vec<String>,
+}
+
+impl ScrubReport {
+    /// Whether no record failed its checksum.
+    pub fn is_clean(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+/// How the space of one context area is spent, see
+/// [`SystemProperties::fragmentation_report`]. All sizes are in bytes of
+/// the area's data region.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AreaFragmentation {
+    /// The SELinux context the area belongs to.
+    pub context: String,
+    /// Room for allocations, fixed when the area was created.
+    pub capacity: usize,
+    /// Allocated so far (the area's `bytes_used`); the rest is free.
+    pub used: usize,
+    /// Held by reachable data — the root node and dirty backup slot, trie
+    /// nodes, entries and long values — without alignment padding.
+    pub live: usize,
+    /// Alignment padding after the reachable allocations. Inherent to the
+    /// format: a rewrite would need it too.
+    pub padding: usize,
+    /// Allocated but reachable from nothing: nodes, entries or long values
+    /// of an add that failed or was interrupted between allocating and
+    /// linking, and the entries of removed properties. Only a rewrite of
+    /// the area (`migrate_area_dir`) gets it back.
+    pub unreachable: usize,
+    /// Trie nodes below the root, one per name component.
+    pub trie_nodes: usize,
+    /// Entries (properties) linked into the trie.
+    pub properties: usize,
+    /// Entries whose value lives out of line.
+    pub long_values: usize,
+}
+
+impl AreaFragmentation {
+    /// Bytes still available for new properties.
+    pub fn free(&self) -> usize {
+        self.capacity.saturating_sub(self.used)
+    }
+}
+
+/// Result of [`SystemProperties::fragmentation_report`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FragmentationReport {
+    /// One entry per existing area, sorted by context.
+    pub areas: Vec<AreaFragmentation>,
+}
+
+impl FragmentationReport {
+    /// Bytes a rewrite of the areas would reclaim.
+    pub fn unreachable(&self) -> usize {
+        self.areas.iter().map(|area| area.unreachable).sum()
+    }
+}
+
+/// The state of one mapped area in a [`DebugState`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AreaState {
+    /// The SELinux context the area belongs to.
+    pub context: String,
+    /// Whether this instance maps the area read-write.
+    pub writable: bool,
+    /// Mappings of the area so far: more than one after its file was
+    /// replaced and remapped. Older ones are unmapped once their last
+    /// reader is done (see [`SystemProperties::retired_mappings`]).
+    pub generations: usize,
+    /// The area's own serial, when the store keeps per-context serials
+    /// (see [`SystemProperties::enable_context_serials`]).
+    pub serial: Option<u32>,
+    /// Entries in the current mapping.
+    pub properties: usize,
+    /// Names of the entries whose serial has the dirty bit set: a writer
+    /// is mid-update, or died in the middle of one.
+    pub dirty: Vec<String>,
+    /// Why the area could not be walked, when it could not.
+    pub error: Option<String>,
+}
+
+/// A snapshot of an instance's view of the store for bug reports, see
+/// [`SystemProperties::debug_state`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DebugState {
+    /// The global serial, as [`SystemProperties::context_serial`] returns it.
+    pub global_serial: u32,
+    /// Context slots in `property_info`.
+    pub contexts: usize,
+    /// The areas this instance has mapped, sorted by context. Areas not
+    /// looked at yet are not mapped and not listed.
+    pub areas: Vec<AreaState>,
+}
+
+impl DebugState {
+    /// Whether some entry was caught mid-update.
+    pub fn has_dirty(&self) -> bool {
+        self.areas.iter().any(|area| !area.dirty.is_empty())
     }
 }
 
-// To avoid lifetime issues, the property index is used to access the property value.
-#[derive(Clone, Copy, Debug)]
-pub struct PropertyIndex {
-    pub(crate) context_index: u32,
-    pub(crate) property_index: u32,
+impl std::fmt::Display for DebugState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "global serial: {:#010x}", self.global_serial)?;
+        writeln!(
+            f,
+            "mapped areas:  {} of {} contexts",
+            self.areas.len(),
+            self.contexts
+        )?;
+        for area in &self.areas {
+            writeln!(
+                f,
+                "  {} ({}, generation {}): {} properties, {} dirty",
+                area.context,
+                if area.writable { "rw" } else { "ro" },
+                area.generations,
+                area.properties,
+                area.dirty.len()
+            )?;
+            if let Some(serial) = area.serial {
+                writeln!(f, "    serial: {serial:#010x}")?;
+            }
+            for name in &area.dirty {
+                writeln!(f, "    dirty: {name}")?;
+            }
+            if let Some(error) = &area.error {
+                writeln!(f, "    error: {error}")?;
+            }
+        }
+        Ok(())
+    }
 }
 
 /// System properties
 /// It can't be created directly. Use `system_properties()` or `system_properties_area()` instead.
+///
+/// A read-only instance follows a property service restart by mapping the
+/// recreated area files on the next lookup miss, or on a hit once a second
+/// has passed since the area was last checked. An old mapping is
+/// unmapped once nothing reads through it any more, see
+/// [`Self::retired_mappings`].
 pub struct SystemProperties {
-    contexts: ContextsSerialized,
+    /// `None` once [`Self::close`]d.
+    contexts: Option<ContextsSerialized>,
+    /// Names are looked up and stored lowercased, see
+    /// [`Layout::case_insensitive`].
+    case_insensitive: bool,
+    /// Spelling each folded name was first written with, to refuse a
+    /// second spelling; only kept when `case_insensitive`.
+    #[cfg(feature = "writer")]
+    spellings: HashMap<String, String>,
+    /// Records [`Self::remove`] unlinked, by name, for a later add of the
+    /// name to link back.
+    #[cfg(feature = "writer")]
+    removed: HashMap<String, PropertyHandle>,
+    /// Write-ahead journal for `update`; see [`Self::enable_journal`].
+    #[cfg(feature = "writer")]
+    journal: Option<Journal>,
+    /// Wakes held back while a [`Self::batch`] runs.
+    #[cfg(feature = "writer")]
+    wake_batch: Option<WakeBatch>,
+    /// Refuse to add names `property_contexts` does not declare; see
+    /// [`Self::set_require_declared`].
+    #[cfg(feature = "writer")]
+    require_declared: bool,
+    /// Name prefixes `update` refuses; see
+    /// [`Self::set_read_only_prefixes`].
+    #[cfg(feature = "writer")]
+    read_only_prefixes: Vec<String>,
+    /// Inside [`Self::override_read_only`].
+    #[cfg(feature = "writer")]
+    read_only_override: bool,
+}
+
+/// The prefix of `prefixes` that makes `name` read-only, if any. With
+/// `overridden`, only `ro.` does.
+#[cfg(feature = "writer")]
+fn read_only_prefix<'a>(prefixes: &'a [String], overridden: bool, name: &[u8]) -> Option<&'a str> {
+    prefixes
+        .iter()
+        .map(String::as_str)
+        .filter(|prefix| !overridden || *prefix == crate::READ_ONLY_PREFIX)
+        .find(|prefix| name.starts_with(prefix.as_bytes()))
+}
+
+/// Futex wakes deferred by [`SystemProperties::batch`].
+#[cfg(feature = "writer")]
+#[derive(Default)]
+struct WakeBatch {
+    /// Records updated in the batch as `(context_index, property_index)`;
+    /// each is woken once when the batch ends.
+    records: std::collections::HashSet<(u32, u32)>,
+    /// Context areas whose serial was bumped; each is woken once.
+    contexts: std::collections::HashSet<u32>,
+    /// The global serial was bumped.
+    global: bool,
+    /// Wakes the batch saved.
+    coalesced: usize,
+}
+
+/// Ends the [`SystemProperties::batch`] it holds when dropped, on unwind
+/// too.
+#[cfg(feature = "writer")]
+struct WakeBatchGuard<'a>(&'a mut SystemProperties);
+
+#[cfg(feature = "writer")]
+impl Drop for WakeBatchGuard<'_> {
+    fn drop(&mut self) {
+        self.0.end_wake_batch();
+    }
 }
 
 impl SystemProperties {
     // Create a new system properties to read system properties from a file or a directory.
-    pub(crate) fn new(filename: &Path) -> Result<Self> {
-        let contexts = match ContextsSerialized::new(false, filename, false) {
+    pub(crate) fn new(filename: &Path, layout: &Layout) -> Result<Self> {
+        let contexts = match ContextsSerialized::new(false, filename, layout) {
             Ok(contexts) => contexts,
             Err(e) => {
                 log::error!("Failed to load contexts from {filename:?}: {e}");
@@ -162,14 +439,86 @@ impl SystemProperties {
             }
         };
 
-        Ok(Self { contexts })
+        Ok(Self::with_contexts(contexts, layout))
+    }
+
+    fn with_contexts(contexts: ContextsSerialized, layout: &Layout) -> Self {
+        Self {
+            contexts: Some(contexts),
+            case_insensitive: layout.case_insensitive,
+            #[cfg(feature = "writer")]
+            spellings: HashMap::new(),
+            #[cfg(feature = "writer")]
+            removed: HashMap::new(),
+            #[cfg(feature = "writer")]
+            journal: None,
+            #[cfg(feature = "writer")]
+            wake_batch: None,
+            #[cfg(feature = "writer")]
+            require_declared: crate::require_declared(),
+            #[cfg(feature = "writer")]
+            read_only_prefixes: crate::read_only_prefixes().to_vec(),
+            #[cfg(feature = "writer")]
+            read_only_override: false,
+        }
+    }
+
+    /// The areas, or [`Error::Closed`] after [`Self::close`].
+    fn contexts(&self) -> Result<&ContextsSerialized> {
+        self.contexts.as_ref().ok_or(Error::Closed)
+    }
+
+    #[cfg(feature = "writer")]
+    fn contexts_mut(&mut self) -> Result<&mut ContextsSerialized> {
+        self.contexts.as_mut().ok_or(Error::Closed)
+    }
+
+    /// Unmaps every area and `property_info` now instead of when the
+    /// instance drops, and releases a writer's directory lock — for
+    /// long-running tools that move between many property directories
+    /// and keep the instances around.
+    ///
+    /// Afterwards every method that can fail returns [`Error::Closed`],
+    /// including for [`PropertyHandle`]es obtained before the call;
+    /// [`Self::serial`] and [`Self::wait`] return `None`, and
+    /// [`Self::context_serial`] returns 0. Closing twice is a no-op.
+    pub fn close(&mut self) {
+        if self.contexts.take().is_some() {
+            log::debug!("Closed property store");
+        }
+    }
+
+    /// Whether [`Self::close`] was called.
+    pub fn is_closed(&self) -> bool {
+        self.contexts.is_none()
+    }
+
+    /// Opens the properties directory `dirname` read-only, independently
+    /// of the process-wide configuration — for tools that compare or
+    /// inspect several stores at once. Everything else should go through
+    /// [`crate::system_properties()`].
+    pub fn open(dirname: &Path) -> Result<Self> {
+        Self::new(dirname, &Layout::default())
+    }
+
+    /// [`Self::open`] for a directory that uses a non-default [`Layout`].
+    pub fn open_with_layout(dirname: &Path, layout: &Layout) -> Result<Self> {
+        Self::new(dirname, layout)
     }
 
     // Create a new area for system properties
     // The new area is used by the property service to store system properties.
-    #[cfg(feature = "builder")]
+    #[cfg(feature = "writer")]
     pub fn new_area(dirname: &Path) -> Result<Self> {
-        let contexts = match ContextsSerialized::new(true, dirname, false) {
+        Self::new_area_with_layout(dirname, &Layout::default())
+    }
+
+    /// [`Self::new_area`] for a directory that uses a non-default
+    /// [`Layout`]. Readers of the directory must be initialized with the
+    /// same layout (see [`crate::PropertyConfig::layout`]).
+    #[cfg(feature = "writer")]
+    pub fn new_area_with_layout(dirname: &Path, layout: &Layout) -> Result<Self> {
+        let contexts = match ContextsSerialized::new(true, dirname, layout) {
             Ok(contexts) => contexts,
             Err(e) => {
                 log::error!("Failed to create area from {dirname:?}: {e}");
@@ -177,7 +526,106 @@ impl SystemProperties {
             }
         };
 
-        Ok(Self { contexts })
+        Ok(Self::with_contexts(contexts, layout))
+    }
+
+    /// Creates a writable store in memfds instead of a directory (see
+    /// [`crate::Backing::Memfd`]), from the serialized `property_info` trie
+    /// (as produced by [`crate::build_trie`]). Nothing is created in the
+    /// filesystem; share the store with readers through
+    /// [`Self::memfd_areas`]. Checksum sidecars and copy-on-write forks
+    /// need files and are not available.
+    #[cfg(all(feature = "writer", any(target_os = "linux", target_os = "android")))]
+    pub fn new_memfd_area(property_info: &[u8], layout: &Layout) -> Result<Self> {
+        let contexts = ContextsSerialized::new_memfd(property_info, layout)
+            .inspect_err(|e| log::error!("Failed to create memfd property areas: {e}"))?;
+        Ok(Self::with_contexts(contexts, layout))
+    }
+
+    /// Opens a memfd-backed store read-only from its memfds, as returned by
+    /// [`Self::memfd_areas`] of the writer (typically received over the
+    /// property service socket — [`crate::init`] with
+    /// [`crate::Backing::Memfd`] does that for the global instance).
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn from_memfd_areas(
+        areas: Vec<(String, std::os::fd::OwnedFd)>,
+        layout: &Layout,
+    ) -> Result<Self> {
+        let files = areas
+            .into_iter()
+            .map(|(name, fd)| (name, std::fs::File::from(fd)))
+            .collect();
+        let contexts = ContextsSerialized::from_memfds(files, layout)
+            .inspect_err(|e| log::error!("Failed to map memfd property areas: {e}"))?;
+        Ok(Self::with_contexts(contexts, layout))
+    }
+
+    /// Builds a read-only store from a serialized `property_info` trie
+    /// and the contents of its areas, keyed by the filename each has in a
+    /// properties directory (`properties_serial` and one per context) —
+    /// for tests, fuzzers and tools working on property dumps, without
+    /// touching the filesystem. The buffers are copied; nothing is mapped.
+    ///
+    /// Every buffer gets the validation an area file would. The serial
+    /// area is required; a context whose area is missing or corrupt is
+    /// skipped, so reading its properties fails with
+    /// [`Error::FileValidation`] while the other contexts stay readable.
+    /// The store never changes, so waits only return on timeout.
+    pub fn from_buffers(info: &[u8], areas: HashMap<String, Vec<u8>>) -> Result<Self> {
+        Self::from_buffers_with_layout(info, areas, &Layout::default())
+    }
+
+    /// [`Self::from_buffers`] for buffers laid out with a non-default
+    /// [`Layout`].
+    pub fn from_buffers_with_layout(
+        info: &[u8],
+        areas: HashMap<String, Vec<u8>>,
+        layout: &Layout,
+    ) -> Result<Self> {
+        let contexts = ContextsSerialized::from_buffers(info, areas, layout)
+            .inspect_err(|e| log::error!("Failed to load property areas from buffers: {e}"))?;
+        Ok(Self::with_contexts(contexts, layout))
+    }
+
+    /// Read-only descriptors of every memfd of a memfd-backed store, each
+    /// with the filename it stands for in a properties directory, for
+    /// handing to readers. Fails with [`Error::InvalidArgument`] for a
+    /// file-backed store.
+    ///
+    /// Each memfd is reopened `O_RDONLY` through `/proc/self/fd`, never
+    /// duplicated: a reader given the writer's own descriptor could map
+    /// the area writable on kernels without `F_SEAL_FUTURE_WRITE`.
+    pub fn memfd_areas(&self) -> Result<Vec<(String, std::os::fd::OwnedFd)>> {
+        let Some(memfds) = self.contexts()?.memfds() else {
+            return Err(Error::InvalidArgument(
+                "the property store is not memfd-backed".to_owned(),
+            ));
+        };
+        memfds
+            .into_iter()
+            .map(|(name, file)| {
+                use std::os::fd::AsRawFd;
+                let fd = std::fs::File::open(format!("/proc/self/fd/{}", file.as_raw_fd()))
+                    .context_with_location(format!("Failed to reopen memfd {name:?} read-only"))?;
+                Ok((name.to_owned(), fd.into()))
+            })
+            .collect()
+    }
+
+    /// Makes sure every per-context area file listed in `property_info`
+    /// exists and is mapped read-write — the equivalent of init's
+    /// `InitializeProperties`.
+    ///
+    /// [`Self::new_area`] already does this before returning (areas are
+    /// never created lazily on first write), so calling it again is cheap
+    /// and only re-verifies the mappings; it exists so a service can make
+    /// the up-front creation explicit at startup. Context entries skipped
+    /// as corrupt at load have no file and are ignored.
+    #[cfg(feature = "writer")]
+    pub fn initialize_all_areas(&self) -> Result<()> {
+        self.contexts()?
+            .initialize_all_areas()
+            .inspect_err(|e| log::error!("Failed to initialize property areas: {e}"))
     }
 
     /// Reads the mutable property value under the seqlock protocol and
@@ -189,14 +637,42 @@ impl SystemProperties {
     /// Returning a value through `f` instead of allocating a `String` is
     /// what makes the parse-and-discard hot path (`get<T>`/`get_or<T>`)
     /// allocation-free for short and long properties alike.
-    fn read_with_callback<R, F>(
-        &self,
-        pa: &crate::property_area::PropertyAreaMap,
-        pi_offset: u32,
-        f: F,
-    ) -> Result<R>
+    ///
+    /// In an area with a checksum sidecar the bytes are verified before
+    /// they are decoded, so a damaged value fails with [`Error::Corrupt`]
+    /// rather than as bad UTF-8 or, worse, as a plausible wrong value.
+    fn read_with_callback<R, F>(&self, pa: &PropertyAreaMap, pi_offset: u32, f: F) -> Result<R>
     where
         F: FnOnce(&str) -> R,
+    {
+        self.read_with_serial(pa, pi_offset, |value, _| f(value))
+    }
+
+    /// [`Self::read_with_callback`], also handing `f` the serial the value
+    /// was read at.
+    fn read_with_serial<R, F>(&self, pa: &PropertyAreaMap, pi_offset: u32, f: F) -> Result<R>
+    where
+        F: FnOnce(&str, u32) -> R,
+    {
+        self.read_raw(pa, pi_offset, |bytes, serial| {
+            if pa.check_record(pi_offset, serial, bytes)? == RecordCheck::Mismatch {
+                return Err(Error::Corrupt {
+                    name: pa.corrupt_record_name(pi_offset),
+                });
+            }
+            // `Error::Utf8`, not `Encoding(String)`: keep every UTF-8
+            // decode failure on the same source-preserving variant.
+            let s = std::str::from_utf8(bytes).map_err(Error::Utf8)?;
+            Ok(f(s, serial))
+        })?
+    }
+
+    /// The seqlock loop behind [`Self::read_with_callback`]: hands `f` the
+    /// raw value bytes and the serial they were read at, once they are
+    /// known to be consistent.
+    fn read_raw<R, F>(&self, pa: &PropertyAreaMap, pi_offset: u32, f: F) -> Result<R>
+    where
+        F: FnOnce(&[u8], u32) -> R,
     {
         let prop_info = pa.property_info(pi_offset)?;
         // Long entries are write-once (their serial never changes after
@@ -254,10 +730,9 @@ impl SystemProperties {
             // on aarch64 for nothing.
             let final_serial = prop_info.serial.load(Ordering::Relaxed);
             if final_serial == serial {
-                // `Error::Utf8`, not `Encoding(String)`: keep every UTF-8
-                // decode failure on the same source-preserving variant.
-                let s = std::str::from_utf8(bytes).map_err(Error::Utf8)?;
-                return Ok(f.take().expect("callback consumed once on success")(s));
+                return Ok(f.take().expect("callback consumed once on success")(
+                    bytes, serial,
+                ));
             }
             // serial changed → retry; spurious UTF-8 from a torn read is
             // naturally absorbed here. The loop is unbounded like bionic's,
@@ -276,48 +751,60 @@ impl SystemProperties {
     /// (from `buf` for short properties, from the mmap for long ones), so
     /// it should be cheap and non-blocking.
     ///
-    /// The callback also runs under the context node's read lock: a
-    /// callback that blocks until a *same-process* builder writer makes
-    /// progress deadlocks (the writer needs that node's write lock) —
-    /// same caution as [`Self::wait`].
+    /// No lock is held while the callback runs — the context node's
+    /// mapping is loaded lock-free, and kept mapped until the callback
+    /// returns even if a restart replaces it meanwhile — but the callback
+    /// still borrows `self`, so it cannot overlap a write through the same
+    /// builder instance (`set`/`update`/`add` take `&mut self`).
     pub fn read_with<R, F>(&self, name: &str, f: F) -> Result<R>
     where
         F: FnOnce(&str) -> R,
     {
-        let res = match self.contexts.prop_area_for_name(name) {
-            Ok(res) => res,
-            // Don't add a second log line for NotFound: the layer below
-            // already reports it at the appropriate level (debug for an
-            // unknown context, error for a corrupt-at-init slot). Other
-            // errors (corrupt mapping, poisoned lock) get logged here with
-            // the property name for context.
-            Err(e @ Error::NotFound(_)) => return Err(e),
-            Err(e) => {
-                log::error!("Failed to find property area for {name}: {e}");
-                return Err(e);
-            }
-        };
-        let pa = res.0.property_area();
-
-        match pa.find(name) {
-            Ok((_, pi_offset)) => match self.read_with_callback(pa, pi_offset, f) {
+        match self.find_in_area(name) {
+            Ok((pa, _, _, pi_offset)) => match self.read_with_callback(&pa, pi_offset, f) {
                 Ok(r) => Ok(r),
                 Err(e) => {
                     log::error!("Failed to read property {name}: {e}");
                     Err(e)
                 }
             },
-            // Absence is the caller's normal fallback flow — no log. Every
-            // other failure (corrupt trie, bad name) is logged with the
-            // property name, same policy as the arms above.
-            Err(e @ Error::NotFound(_)) => Err(e),
             Err(e) => {
-                log::error!("Failed to find {name} in property area: {e}");
+                if !is_quiet_lookup_error(&e) {
+                    log::error!("Failed to find {name} in property area: {e}");
+                }
                 Err(e)
             }
         }
     }
 
+    /// Looks `name` up in its context's area, returning the area, the
+    /// context index, the trie node offset and the `PropertyInfo` offset.
+    ///
+    /// Any in-area miss or lookup failure also revalidates the area's
+    /// mapping (one `lstat`): if the file was replaced since it was mapped
+    /// — a property service restart recreating the directory — the new
+    /// file is mapped and the lookup retried once against it, so
+    /// long-running readers follow the restart instead of serving stale
+    /// values forever. A file that is gone entirely surfaces as
+    /// [`Error::AreaVanished`]. Hits revalidate too, at most once a second
+    /// per area, so a name the new file also holds stops reading the old
+    /// one within that second.
+    fn find_in_area(&self, name: &str) -> Result<(AreaRef<'_>, u32, u32, u32)> {
+        let name = &*self.fold(name);
+        let (pa, context_index) = self.contexts()?.prop_area_for_name(name)?;
+        match pa.find(name) {
+            Ok((node_offset, pi_offset)) => Ok((pa, context_index, node_offset, pi_offset)),
+            Err(e) => {
+                if !self.contexts()?.revalidate_area(context_index)? {
+                    return Err(e);
+                }
+                let pa = self.contexts()?.prop_area_with_index(context_index)?;
+                let (node_offset, pi_offset) = pa.find(name)?;
+                Ok((pa, context_index, node_offset, pi_offset))
+            }
+        }
+    }
+
     /// Get property value that returns error for missing properties.
     ///
     /// Allocates a `String`; for the parse-and-discard hot path prefer
@@ -327,78 +814,650 @@ impl SystemProperties {
         self.read_with(name, str::to_owned)
     }
 
-    /// Get the property index of a system property by name.
-    /// The property index is used to update the property value.
-    /// If the property is not found, it returns Ok(None)
-    pub fn find(&self, name: &str) -> Result<Option<PropertyIndex>> {
-        let res = match self.contexts.prop_area_for_name(name) {
-            Ok(res) => res,
-            // A name that maps to no context cannot have a property — the
-            // same "genuine absence" contract as the in-area miss below.
-            // (Unlike a flattened in-area lookup *failure*, sending `set`
-            // down the `add` path here is harmless: `add` hits the same
-            // context miss and fails loudly.) The lower layer already
-            // logged the miss at the appropriate level. A corrupt-at-init
-            // context slot is NOT folded in here: `context_node_at` reports
-            // it as `FileValidation`, which propagates through the arm
-            // below — corruption must stay distinguishable from absence.
-            Err(Error::NotFound(_)) => return Ok(None),
-            Err(e) => {
-                log::error!("Failed to find property area for {name}: {e}");
-                return Err(e);
+    /// Returns the per-context area file backing `name` (e.g.
+    /// `/dev/__properties__/u:object_r:build_prop:s0`).
+    ///
+    /// The property does not have to exist: the mapping is decided by the
+    /// `property_info` trie alone, so this also answers "where would `name`
+    /// be stored". Useful when debugging permission failures on a specific
+    /// area, or for tools that watch a single file with inotify. Nothing is
+    /// mapped by this call.
+    ///
+    /// Returns [`Error::NotFound`] if no context covers `name`, and
+    /// [`Error::FileValidation`] if its context entry was skipped as
+    /// corrupt at load time.
+    pub fn area_file_for(&self, name: &str) -> Result<PathBuf> {
+        self.contexts()?
+            .area_file_for_name(&self.fold(name))
+            .map(Path::to_path_buf)
+    }
+
+    /// Takes a point-in-time copy of every property, which can then be
+    /// queried without touching the mmaps.
+    ///
+    /// Each value is read through the same seqlock protocol as
+    /// [`Self::get_with_result`]; on top of that the global serial
+    /// ([`Self::context_serial`]) is sampled before and after the walk,
+    /// and the walk is repeated if any property was set or added in
+    /// between — so the snapshot is one state the property set actually
+    /// had, never a mix of two. Fails with [`Error::LimitExceeded`] if the
+    /// set kept changing for `FREEZE_ATTEMPTS` walks in a row.
+    pub fn freeze(&self) -> Result<FrozenProperties> {
+        let (values, serial) = self.consistent_walk(str::to_owned)?;
+        Ok(FrozenProperties::new(values, serial))
+    }
+
+    /// [`Self::freeze`] with each property's declared context and type,
+    /// as a [`PropertySnapshot`] that [`Self::restore`] writes into
+    /// another store — with the `serde` feature, after a trip through
+    /// JSON, TOML or any other serde format.
+    pub fn snapshot(&self) -> Result<PropertySnapshot> {
+        let frozen = self.freeze()?;
+        let contexts = self.contexts()?;
+        let mut properties = std::collections::BTreeMap::new();
+        for (name, value) in frozen.into_map() {
+            let entry = SnapshotEntry {
+                value,
+                context: contexts.context_for_name(&name)?.map(str::to_owned),
+                type_str: self.property_type(&name)?.map(str::to_owned),
+            };
+            properties.insert(name, entry);
+        }
+        Ok(PropertySnapshot { properties })
+    }
+
+    /// Sets every property of `snapshot` in one [`Self::batch`], with the
+    /// prefixes of [`Self::set_read_only_prefixes`] writable, and returns
+    /// how many it holds. Meant for a fresh store: a property that already
+    /// has its snapshot value is left alone, but `ro.` ones stay
+    /// write-once, so one holding a different value fails.
+    ///
+    /// Each value goes through [`Self::set`] and its checks. The
+    /// snapshot's metadata is not applied — this store's `property_info`
+    /// trie decides contexts and types; an entry whose recorded context
+    /// differs from the one here is logged. Stops at the first property that cannot be
+    /// set, with the error naming it; the ones before it stay set.
+    #[cfg(feature = "writer")]
+    pub fn restore(&mut self, snapshot: &PropertySnapshot) -> Result<usize> {
+        self.override_read_only(|props| {
+            props.batch(|props| {
+                for (name, entry) in &snapshot.properties {
+                    if let Some(context) = &entry.context {
+                        let here = props.contexts()?.context_for_name(&props.fold(name))?;
+                        if here != Some(context.as_str()) {
+                            log::warn!(
+                                "restore: {name} was in {context}, here it is in {}",
+                                here.unwrap_or("no context")
+                            );
+                        }
+                    }
+                    if props
+                        .get_with_result(name)
+                        .is_ok_and(|value| value == entry.value)
+                    {
+                        continue;
+                    }
+                    props
+                        .set(name, &entry.value)
+                        .with_context_location(|| format!("Failed to restore {name}"))?;
+                }
+                Ok(snapshot.len())
+            })
+        })
+    }
+
+    /// Calls `f` with the name and value of every property, area by area
+    /// — the counterpart of bionic's `__system_property_foreach`.
+    ///
+    /// Each value is read through the same seqlock protocol as
+    /// [`Self::get_with_result`], but the walk as a whole is not: a
+    /// property set while it runs may be visited with either value, and
+    /// one added may be missed. Use [`Self::freeze`] for a copy that is
+    /// one state of the property set. Properties come in no particular
+    /// order; the walk stops at the first record that cannot be read.
+    pub fn foreach(&self, mut f: impl FnMut(&str, &str)) -> Result<()> {
+        for (pa, _) in self.contexts()?.existing_areas()? {
+            for pi_offset in pa.property_offsets()? {
+                let name = pa
+                    .property_info_name(pi_offset)?
+                    .to_str()
+                    .map_err(Error::Utf8)?;
+                self.read_with_callback(&pa, pi_offset, |value| f(name, value))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every property the way Android's `getprop` lists them, so
+    /// scripts parsing that output work unchanged: one
+    /// `[name]: [value]` line per property, sorted by name bytewise,
+    /// nothing escaped — a value holding `]` or a newline is printed
+    /// as is, as bionic's getprop does. `column` swaps the value for the
+    /// declared type or the context; a name no `property_info` entry
+    /// covers then shows `[]`.
+    ///
+    /// The properties come from one [`Self::freeze`] snapshot.
+    pub fn format_getprop(
+        &self,
+        mut writer: impl std::io::Write,
+        column: GetpropColumn,
+    ) -> Result<()> {
+        let snapshot = self.freeze()?;
+        let mut properties: Vec<_> = snapshot.iter().collect();
+        properties.sort_unstable_by_key(|&(name, _)| name);
+        let mut out = String::new();
+        for (name, value) in properties {
+            let shown = match column {
+                GetpropColumn::Value => Some(value),
+                GetpropColumn::Type => self.property_type(name)?,
+                GetpropColumn::Context => self.contexts()?.context_for_name(&self.fold(name))?,
+            };
+            out.push('[');
+            out.push_str(name);
+            out.push_str("]: [");
+            out.push_str(shown.unwrap_or_default());
+            out.push_str("]\n");
+        }
+        writer.write_all(out.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes every property to `path` as a build.prop-style file — one
+    /// `name=value` line per property, sorted by name — that
+    /// [`Self::import_prop_file`] and [`crate::load_properties_from_file`]
+    /// read back. Returns how many properties were written.
+    ///
+    /// The properties come from one [`Self::freeze`] snapshot. A value
+    /// spanning lines cannot be expressed in the format and is left out
+    /// with a warning; surrounding whitespace in a value does not survive
+    /// a reload, as prop files trim it. The file is written through a
+    /// temporary file in the same directory, so readers see either the
+    /// old or the new contents.
+    pub fn export_prop_file(&self, path: &Path) -> Result<usize> {
+        let snapshot = self.freeze()?;
+        let mut properties: Vec<_> = snapshot.iter().collect();
+        properties.sort_unstable_by_key(|&(name, _)| name);
+        let mut out = String::new();
+        let mut written = 0;
+        for (name, value) in properties {
+            if value.contains(['\n', '\r']) {
+                log::warn!("Not exporting {name}: its value spans several lines");
+                continue;
             }
+            out.push_str(name);
+            out.push('=');
+            out.push_str(value);
+            out.push('\n');
+            written += 1;
+        }
+
+        // Unique per call, so concurrent exports to one path never share a
+        // temporary file; create_new and NOFOLLOW refuse anything planted
+        // at the name, a symlink included.
+        static EXPORT_SEQ: AtomicU64 = AtomicU64::new(0);
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            EXPORT_SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp = PathBuf::from(tmp);
+        let write = || -> std::io::Result<()> {
+            use std::os::unix::fs::OpenOptionsExt;
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .custom_flags(rustix::fs::OFlags::NOFOLLOW.bits() as _)
+                .open(&tmp)?;
+            std::io::Write::write_all(&mut file, out.as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&tmp, path)
         };
-        let pa = res.0.property_area();
-        match pa.find(name) {
-            Ok(pi) => {
-                let index = PropertyIndex {
-                    context_index: res.1,
-                    property_index: pi.1,
+        write().or_else(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e).context_with_location(format!("Failed to write {path:?}"))
+        })?;
+        Ok(written)
+    }
+
+    /// Sets the properties of the build.prop-style file at `path`, read
+    /// the way Android's init loads them: see
+    /// [`crate::load_properties_from_file`] for `import` lines and
+    /// `filter` (`"name"` or `"prefix*"`; imports are not followed with a
+    /// filter). A name that occurs more than once, across imports too,
+    /// takes its last value. Returns how many properties were set.
+    ///
+    /// The load runs in one [`Self::batch`] with the prefixes of
+    /// [`Self::set_read_only_prefixes`] writable, as a service seeding its
+    /// own namespaces does, but `ro.` properties stay write-once: one the
+    /// store already holds keeps its value, and a different value in the
+    /// file is skipped with a warning. Undeclared names (see
+    /// [`Self::set_require_declared`]) are skipped too; any other failure
+    /// to set a property ends the import with that error.
+    #[cfg(all(feature = "writer", feature = "parser"))]
+    pub fn import_prop_file(&mut self, path: &Path, filter: Option<&str>) -> Result<usize> {
+        let mut loaded = HashMap::new();
+        crate::load_properties_from_file(path, filter, "u:r:init:s0", &mut loaded)?;
+        // Sorted, so the area layout does not depend on HashMap order.
+        let properties: std::collections::BTreeMap<_, _> = loaded.into_iter().collect();
+        self.override_read_only(|props| {
+            props.batch(|props| {
+                let mut set = 0;
+                for (name, value) in &properties {
+                    if name.starts_with(crate::READ_ONLY_PREFIX) {
+                        match props.get_with_result(name) {
+                            Ok(current) if current == *value => continue,
+                            Ok(current) => {
+                                log::warn!(
+                                    "Not importing {name}={value:?} from {path:?}: read-only property is already {current:?}"
+                                );
+                                continue;
+                            }
+                            Err(Error::NotFound(_)) => {}
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    match props.set(name, value) {
+                        Ok(()) => set += 1,
+                        // Already logged; one stray entry must not fail the load.
+                        Err(Error::Undeclared { .. }) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(set)
+            })
+        })
+    }
+
+    /// [`Self::freeze`] with names from the process-wide interner (see
+    /// [`crate::intern()`]), for callers that enumerate every property often
+    /// and would otherwise allocate every name on each pass. Compare
+    /// [`Self::context_serial`] before calling to skip unchanged passes.
+    #[cfg(feature = "intern")]
+    pub fn freeze_interned(&self) -> Result<HashMap<std::sync::Arc<str>, String>> {
+        Ok(self.consistent_walk(crate::intern::intern)?.0)
+    }
+
+    /// The retry loop of [`Self::freeze`], with names made by `key`;
+    /// returns the values and the serial they were consistent at.
+    fn consistent_walk<K: Eq + std::hash::Hash>(
+        &self,
+        key: impl Fn(&str) -> K,
+    ) -> Result<(HashMap<K, String>, u32)> {
+        const FREEZE_ATTEMPTS: usize = 8;
+        for _ in 0..FREEZE_ATTEMPTS {
+            let before = self.context_serial();
+            let values = self.collect_all(&key)?;
+            if self.context_serial() == before {
+                return Ok((values, before));
+            }
+        }
+        log::warn!("freeze: properties kept changing for {FREEZE_ATTEMPTS} walks");
+        Err(Error::LimitExceeded(format!(
+            "property set changed during {FREEZE_ATTEMPTS} consecutive snapshot attempts"
+        )))
+    }
+
+    /// Forks a [`ScratchProperties`]: a copy of this store whose writes
+    /// stay private to the process, for evaluating a batch of sets
+    /// without touching the real areas. Works from a read-only instance
+    /// (such as [`crate::system_properties()`]) as well as a writer.
+    ///
+    /// Fails with `ENOTSUP` if the area backend cannot map privately (see
+    /// [`crate::backend::PropertyAreaBackend::map_private`]).
+    #[cfg(feature = "writer")]
+    pub fn fork_cow(&self) -> Result<ScratchProperties> {
+        let contexts = self
+            .contexts()?
+            .fork_cow()
+            .inspect_err(|e| log::error!("Failed to fork property areas: {e}"))?;
+        Ok(ScratchProperties::new(Self {
+            contexts: Some(contexts),
+            case_insensitive: self.case_insensitive,
+            spellings: self.spellings.clone(),
+            removed: self.removed.clone(),
+            journal: None,
+            wake_batch: None,
+            require_declared: self.require_declared,
+            read_only_prefixes: self.read_only_prefixes.clone(),
+            read_only_override: false,
+        }))
+    }
+
+    /// Verifies every record of every existing area against its checksum
+    /// (see [`Self::enable_checksums`]) — for a periodic or at-boot scrub
+    /// of areas kept on storage that can rot.
+    ///
+    /// A record whose checksum is still being written by a live writer
+    /// is re-checked a few times; one that stays out of step with its
+    /// checksum is reported as corrupt, so run the scrub while the store
+    /// is quiet for a clean result. Fails only if an area is structurally
+    /// unreadable.
+    pub fn scrub(&self) -> Result<ScrubReport> {
+        const STALE_RETRIES: usize = 3;
+        let mut report = ScrubReport::default();
+        for (pa, _) in self.contexts()?.existing_areas()? {
+            for pi_offset in pa.property_offsets()? {
+                let mut retries = 0;
+                let check = loop {
+                    let check = self.read_raw(&pa, pi_offset, |bytes, serial| {
+                        pa.check_record(pi_offset, serial, bytes)
+                    })??;
+                    if check != RecordCheck::Stale || retries == STALE_RETRIES {
+                        break check;
+                    }
+                    retries += 1;
+                    std::thread::sleep(Duration::from_millis(1));
                 };
-                Ok(Some(index))
+                match check {
+                    RecordCheck::Verified => report.verified += 1,
+                    RecordCheck::Unchecked => report.unchecked += 1,
+                    RecordCheck::Stale | RecordCheck::Mismatch => {
+                        report.corrupt.push(pa.corrupt_record_name(pi_offset))
+                    }
+                }
+            }
+        }
+        report.corrupt.sort_unstable();
+        Ok(report)
+    }
+
+    /// Walks every existing area and reports, per context, how its space
+    /// is spent: live data, alignment padding, unreachable allocations and
+    /// what is left — to see why an area filled up after a long uptime and
+    /// whether rewriting it would help.
+    ///
+    /// Long values are write-once (updating a long property is refused),
+    /// so a changed value never strands an allocation; space is lost only
+    /// to adds that died between allocating and linking, reported as
+    /// [`AreaFragmentation::unreachable`]. An add in flight during the
+    /// walk counts there too, so run it while the store is quiet, as
+    /// [`Self::scrub`].
+    pub fn fragmentation_report(&self) -> Result<FragmentationReport> {
+        let contexts = self.contexts()?;
+        let mut report = FragmentationReport::default();
+        for (pa, index) in contexts.existing_areas()? {
+            let context = contexts.context_name(index)?.unwrap_or_default();
+            report.areas.push(pa.fragmentation(context.to_owned())?);
+        }
+        report.areas.sort_by(|a, b| a.context.cmp(&b.context));
+        Ok(report)
+    }
+
+    /// Describes what this instance sees — the global serial, the areas
+    /// it has mapped and the entries caught mid-update — for reports about
+    /// waiters that never wake or a property stuck dirty. Nothing is
+    /// mapped by the call, and an area that cannot be walked is reported
+    /// in its [`AreaState::error`] rather than failing the whole call.
+    ///
+    /// bionic's format keeps one serial for the whole store; per-context
+    /// serials are only shown for stores that keep them.
+    pub fn debug_state(&self) -> Result<DebugState> {
+        let contexts = self.contexts()?;
+        let (mapped, context_count) = contexts.mapped_areas();
+        let mut state = DebugState {
+            global_serial: self.context_serial(),
+            contexts: context_count,
+            areas: Vec::with_capacity(mapped.len()),
+        };
+        for (pa, index, generations) in mapped {
+            let mut area = AreaState {
+                context: contexts.context_name(index)?.unwrap_or_default().to_owned(),
+                writable: pa.is_writable(),
+                generations,
+                serial: has_context_serials(contexts.serial_prop_area())
+                    .then(|| pa.property_area().serial().load(Ordering::Acquire)),
+                ..Default::default()
+            };
+            let walk = pa.property_offsets().and_then(|offsets| {
+                area.properties = offsets.len();
+                for pi_offset in offsets {
+                    let serial = pa.property_info(pi_offset)?.serial.load(Ordering::Acquire);
+                    if serial_dirty(serial) {
+                        let name = pa.property_info_name(pi_offset)?;
+                        area.dirty.push(name.to_string_lossy().into_owned());
+                    }
+                }
+                Ok(())
+            });
+            if let Err(e) = walk {
+                area.error = Some(e.to_string());
             }
-            // Only genuine absence maps to `None`. Lookup *failures* (bad
-            // name, corrupt mmap) must propagate — flattening them would
-            // send `set` down the `add` path, which is a silent no-op for
+            area.dirty.sort_unstable();
+            state.areas.push(area);
+        }
+        state.areas.sort_by(|a, b| a.context.cmp(&b.context));
+        Ok(state)
+    }
+
+    /// One unsynchronized walk for [`Self::freeze`]: every entry of every
+    /// existing area, each value read consistently on its own.
+    fn collect_all<K: Eq + std::hash::Hash>(
+        &self,
+        key: impl Fn(&str) -> K,
+    ) -> Result<HashMap<K, String>> {
+        let mut values = HashMap::new();
+        for (pa, _) in self.contexts()?.existing_areas()? {
+            for pi_offset in pa.property_offsets()? {
+                // Names are written once, before the entry is linked into
+                // the trie, so no seqlock is needed for them.
+                let name = key(pa
+                    .property_info_name(pi_offset)?
+                    .to_str()
+                    .map_err(Error::Utf8)?);
+                let value = self.read_with_callback(&pa, pi_offset, str::to_owned)?;
+                values.insert(name, value);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Checks every mapped area against the file currently at its path and
+    /// remaps the replaced ones (see [`Error::AreaVanished`] for the
+    /// missing-file case). Lookups do this lazily on a miss; this is the
+    /// eager form used by [`crate::at_fork_child`].
+    pub(crate) fn revalidate_areas(&self) -> Result<()> {
+        self.contexts()?.revalidate_all_areas()
+    }
+
+    /// Get the property index of a system property by name.
+    /// The property index is used to update the property value.
+    /// If the property is not found, it returns Ok(None)
+    pub fn find(&self, name: &str) -> Result<Option<PropertyHandle>> {
+        match self.find_in_area(name) {
+            Ok((pa, context_index, node_offset, property_index)) => Ok(Some(PropertyHandle::new(
+                &pa,
+                context_index,
+                node_offset,
+                property_index,
+            )?)),
+            // Only genuine absence maps to `None` — both an in-area miss
+            // and a name that maps to no context (which cannot have a
+            // property; sending `set` down the `add` path there is harmless
+            // since `add` hits the same context miss and fails loudly).
+            // Lookup *failures* (bad name, corrupt mmap, a corrupt-at-init
+            // context slot, which `context_node_at` reports as
+            // `FileValidation`) must propagate — flattening them would send
+            // `set` down the `add` path, which is a silent no-op for
             // existing names, turning a real error into a successful-looking
             // write that never happened.
             Err(Error::NotFound(_)) => Ok(None),
-            Err(e) => Err(e),
+            Err(e) => {
+                if !is_quiet_lookup_error(&e) {
+                    log::error!("Failed to find {name}: {e}");
+                }
+                Err(e)
+            }
         }
     }
 
+    /// Counters of this store's context cache, which memoizes name →
+    /// context resolution per name family (see
+    /// [`crate::set_context_cache_capacity`]).
+    pub fn context_cache_stats(&self) -> Result<ContextCacheStats> {
+        Ok(self.contexts()?.context_cache_stats())
+    }
+
+    /// Type the `property_info` trie declares for `name` (e.g. `"string"`,
+    /// `"enum a b"`, `"bytes"`), or `None` if no entry declares one. Like
+    /// [`Self::area_file_for`], the property does not have to exist.
+    pub fn property_type(&self, name: &str) -> Result<Option<&str>> {
+        self.contexts()?.type_for_name(&self.fold(name))
+    }
+
+    /// Declared metadata and live state of `name` in one call: the
+    /// context and type come from the `property_info` trie, so they are
+    /// reported even when the property has no value yet.
+    pub fn describe(&self, name: &str) -> Result<PropertyDescriptor> {
+        let context = self
+            .contexts()?
+            .context_for_name(&self.fold(name))?
+            .map(str::to_owned);
+        let type_str = self.property_type(name)?.map(str::to_owned);
+        let (exists, is_long, serial) = match self.find(name)? {
+            Some(idx) => {
+                let is_long = self.with_property_info(&idx, |pi| pi.is_long()) == Some(true);
+                (true, is_long, self.serial(&idx))
+            }
+            None => (false, false, None),
+        };
+        Ok(PropertyDescriptor {
+            context,
+            type_str,
+            exists,
+            is_long,
+            serial,
+        })
+    }
+
+    /// `name`'s value with its context, declared type, serial and storage
+    /// kind, for tools that audit or debug a store. Unlike
+    /// [`Self::describe`], the property must exist: a missing one is
+    /// [`Error::NotFound`]. The value and the serial come from the same
+    /// consistent read.
+    pub fn get_entry(&self, name: &str) -> Result<PropertyEntry> {
+        let name = self.fold(name);
+        let (pa, _, _, pi_offset) = self.find_in_area(&name).inspect_err(|e| {
+            if !is_quiet_lookup_error(e) {
+                log::error!("Failed to find {name} in property area: {e}");
+            }
+        })?;
+        let (value, serial) = self
+            .read_with_serial(&pa, pi_offset, |value, serial| (value.to_owned(), serial))
+            .inspect_err(|e| log::error!("Failed to read property {name}: {e}"))?;
+        let is_long = pa.property_info(pi_offset)?.is_long();
+        let contexts = self.contexts()?;
+        Ok(PropertyEntry {
+            context: contexts.context_for_name(&name)?.map(str::to_owned),
+            type_str: contexts.type_for_name(&name)?.map(str::to_owned),
+            name: name.into_owned(),
+            value,
+            serial,
+            is_long,
+        })
+    }
+
     /// Set the value of a system property
     /// If the property is not found, it creates a new property.
-    /// If the property value is too long, it returns an error.
+    /// If the property value is too long (see [`crate::value_max`]), it
+    /// returns an error; values of any length under
+    /// [`Self::read_only_prefixes`] (`ro.` among them) are stored long.
     /// If the property is read-only, it returns an error.
+    /// If the property is declared `bytes` and the value is not valid
+    /// base64, it returns an error.
     /// If the property is updated successfully, it returns Ok(()).
-    #[cfg(feature = "builder")]
+    ///
+    /// The type check lives here, on the service's write path, rather than
+    /// in `add`/`update`, which stay raw primitives (build.prop loading
+    /// goes through `add`).
+    ///
+    /// In a case-insensitive store (see [`Layout::case_insensitive`]) the
+    /// name is stored lowercased, and one writer accepts one spelling per
+    /// name: `set`/`add` of `Vendor.Mode` after `vendor.mode` fails with
+    /// [`Error::InvalidArgument`] rather than silently merging the two.
+    /// Properties the area already held when this writer opened it are
+    /// not checked.
+    #[cfg(feature = "writer")]
     pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let name = &*self.claim_spelling(name)?;
+        if self.property_type(name)? == Some(crate::bytes_value::BYTES_TYPE) {
+            if let Err(Error::Parse(msg)) = crate::bytes_value::decode(value) {
+                log::error!("Rejected value for bytes property {name}: {msg}");
+                return Err(Error::InvalidArgument(format!(
+                    "value for bytes property '{name}': {msg}"
+                )));
+            }
+        }
         // No extra logging here: every failure path inside `update`/`add`
         // already logs with full context — a second line per failure only
         // duplicated the noise.
         match self.find(name)? {
             Some(prop_ref) => self.update(&prop_ref, value),
-            None => self.add(name, value),
+            None => self.add_folded(name, value),
         }
     }
 
-    #[cfg(feature = "builder")]
-    pub fn update(&mut self, index: &PropertyIndex, value: &str) -> Result<()> {
-        let mut res = match self.contexts.prop_area_mut_with_index(index.context_index) {
-            Ok(res) => res,
-            Err(e) => {
-                log::error!(
-                    "Failed to get mutable property area for context {}: {}",
-                    index.context_index,
-                    e
-                );
+    /// Lowercases `name` in a case-insensitive store.
+    fn fold<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.case_insensitive {
+            fold_case(name)
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// [`Self::fold`] for a write: also records the spelling `name` is
+    /// written with, refusing one that folds onto a different spelling.
+    #[cfg(feature = "writer")]
+    fn claim_spelling<'a>(&mut self, name: &'a str) -> Result<Cow<'a, str>> {
+        let folded = self.fold(name);
+        if !self.case_insensitive {
+            return Ok(folded);
+        }
+        crate::wire::validate_property_name(name).inspect_err(|e| log::error!("{e}"))?;
+        match self.spellings.get(&*folded) {
+            Some(first) if first != name => {
+                let e = Error::InvalidArgument(format!(
+                    "'{name}' collides with '{first}' when case is folded"
+                ));
+                log::error!("{e}");
+                Err(e)
+            }
+            Some(_) => Ok(folded),
+            None => {
+                self.spellings
+                    .insert(folded.clone().into_owned(), name.to_owned());
+                Ok(folded)
+            }
+        }
+    }
+
+    #[cfg(feature = "writer")]
+    pub fn update(&mut self, index: &PropertyHandle, value: &str) -> Result<()> {
+        // Stamped before the per-property serial flips, so waiters on the
+        // property (woken first) already see it. Taken before `pa` borrows
+        // the contexts; a failed update leaves a stamp with no bump, which
+        // a later waiter may attribute to the next change — harmless for
+        // statistics.
+        self.stamp_bump();
+        // The field, not `contexts_mut`: `pa` is used alongside the journal
+        // and the wake batch.
+        let contexts = self.contexts.as_mut().ok_or(Error::Closed)?;
+        let pa = match contexts.prop_area_mut_with_index(index.context_index) {
+            Ok(pa) => pa,
+            Err(e) => {
+                log::error!(
+                    "Failed to get mutable property area for context {}: {}",
+                    index.context_index,
+                    e
+                );
                 return Err(e);
             }
         };
-        let pa = res.property_area_mut();
+        index.check(pa).inspect_err(|e| log::error!("{e}"))?;
 
-        // Inspect through `&pi` first: validate ro., snapshot backup into a
+        // Inspect through `&pi` first: refuse read-only names, snapshot backup into a
         // stack buffer. `pi` borrow is dropped at the end of this block so
         // we can take `&mut pa` for `backup_and_apply_write` immediately
         // after. The buffer outlives the inner borrow scope, so the bytes
@@ -415,9 +1474,11 @@ impl SystemProperties {
                     e
                 })?
                 .to_bytes();
-            if name.starts_with(b"ro.") {
+            if let Some(prefix) =
+                read_only_prefix(&self.read_only_prefixes, self.read_only_override, name)
+            {
                 let error_msg = format!(
-                    "Try to update the read-only property: {}",
+                    "Try to update the read-only ({prefix}) property: {}",
                     String::from_utf8_lossy(name)
                 );
                 log::error!("{error_msg}");
@@ -426,7 +1487,7 @@ impl SystemProperties {
             // Value-length check — `update` cannot promote to a long
             // property in-place (`apply_write` rejects on LONG_FLAG), so
             // use the short-value variant, which has no `ro.` exemption.
-            // Deliberately *after* the `ro.` check above: for a read-only
+            // Deliberately *after* the read-only check above: for a read-only
             // property the dominant refusal reason is read-only-ness, and
             // reporting "value too long" instead would misdirect the
             // caller. Still before the backup snapshot, preserving "every
@@ -461,8 +1522,21 @@ impl SystemProperties {
             // because values are validated NUL-free on every write path
             // (`wire::validate_value_len`) — dirty-path readers size their
             // backup copy from that serial length.
-            pa.property_value_bytes(index.property_index, &mut backup_buf)?
-                .len()
+            let backup_len = pa
+                .property_value_bytes(index.property_index, &mut backup_buf)?
+                .len();
+            // Last fallible step before the mmap is touched.
+            if let Some(journal) = &self.journal {
+                journal
+                    .begin(
+                        name,
+                        &backup_buf[..backup_len],
+                        value,
+                        pi.serial.load(Ordering::Relaxed),
+                    )
+                    .inspect_err(|e| log::error!("{e}"))?;
+            }
+            backup_len
         };
 
         // Backup-then-publish as a single fused operation: readers that
@@ -484,24 +1558,132 @@ impl SystemProperties {
         // failure here is theoretical): a missed FUTEX_WAKE only delays
         // waiters — they re-check the serial on their own; bionic ignores
         // the wake result entirely. Log and continue.
-        match pa.property_info(index.property_index) {
-            Ok(pi) => {
-                if let Err(e) = futex_wake(&pi.serial) {
-                    log::warn!("Failed to wake property futex: {e}");
+        match &mut self.wake_batch {
+            Some(batch) => {
+                if !batch
+                    .records
+                    .insert((index.context_index, index.property_index))
+                {
+                    batch.coalesced += 1;
                 }
             }
-            Err(e) => log::warn!("Failed to re-fetch property info for futex wake: {e}"),
+            None => match pa.property_info(index.property_index) {
+                Ok(pi) => {
+                    if let Err(e) = backend::waiter().wake(&pi.serial) {
+                        log::warn!("Failed to wake property futex: {e}");
+                    }
+                }
+                Err(e) => log::warn!("Failed to re-fetch property info for futex wake: {e}"),
+            },
         }
 
-        let serial_pa = self.contexts.serial_prop_area();
+        self.bump_context_serial(index.context_index);
+        let serial_pa = self.contexts()?.serial_prop_area();
         // Atomic RMW: multiple service writers (or multi-process mmap sharing)
         // would otherwise lose updates with a load + store pair.
         serial_pa.serial().fetch_add(1, Ordering::Release);
+        self.wake_global();
 
-        if let Err(e) = futex_wake(serial_pa.serial()) {
-            log::warn!("Failed to wake global serial futex: {e}");
+        // A leftover record is harmless: replaying a finished update only
+        // bumps the global serial once more.
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.commit() {
+                log::warn!("{e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Journals every later [`Self::update`] to `path` (created if
+    /// missing), so a writer that dies between marking a property dirty
+    /// and publishing its new value can be repaired.
+    ///
+    /// If the journal holds an update that never finished, it is replayed
+    /// first:
+    /// - the property is still dirty (the writer died mid-write in this
+    ///   very area): the recorded old value goes back to the backup slot
+    ///   and the new value is written, so readers stop seeing a torn entry;
+    /// - the property still holds the recorded old value (the update never
+    ///   reached the mmap, or the area was rebuilt): the update is applied;
+    /// - the property holds the new value: only the global serial bump
+    ///   that may have been missed is repeated;
+    /// - otherwise (gone, or changed since) the record is dropped.
+    ///
+    /// Costs a write and a truncate per update. See the `journal` module
+    /// notes for what the journal does not cover (power loss).
+    #[cfg(feature = "writer")]
+    pub fn enable_journal(&mut self, path: &Path) -> Result<()> {
+        let (journal, pending) = Journal::open(path)?;
+        if let Some(record) = pending {
+            self.replay(&record)
+                .inspect_err(|e| log::error!("Failed to replay journal {path:?}: {e}"))?;
+        }
+        journal.commit()?;
+        self.journal = Some(journal);
+        Ok(())
+    }
+
+    /// Keeps a CRC of every record's name and value in a sidecar next to
+    /// each area file (`<area>.crc`), for products that keep the areas on
+    /// storage where they can rot across reboots. Existing records are
+    /// checksummed right away; later `add`/`update` calls keep the sidecar
+    /// current.
+    ///
+    /// Readers that map an area with a sidecar verify each value they
+    /// read and fail with [`Error::Corrupt`] on a mismatch;
+    /// [`Self::scrub`] checks every record at once. Readers that mapped
+    /// an area before its sidecar existed keep reading it unchecked.
+    /// Sidecars are removed with their areas when a new writer recreates
+    /// them, so this must be called again after [`Self::new_area`].
+    #[cfg(feature = "writer")]
+    pub fn enable_checksums(&mut self) -> Result<()> {
+        self.contexts_mut()?
+            .enable_checksums()
+            .inspect_err(|e| log::error!("Failed to enable checksums: {e}"))
+    }
+
+    #[cfg(feature = "writer")]
+    fn replay(&mut self, record: &JournalRecord) -> Result<()> {
+        let name = record.name.as_str();
+        let Some(index) = self.find(name)? else {
+            log::info!("journal: {name} no longer exists; dropping its update");
+            return Ok(());
+        };
+        let pa = self
+            .contexts_mut()?
+            .prop_area_mut_with_index(index.context_index)?;
+        let serial = pa
+            .property_info(index.property_index)?
+            .serial
+            .load(Ordering::Acquire);
+        if serial_dirty(serial) {
+            log::warn!("journal: {name} was left dirty; completing its update");
+            pa.backup_and_apply_write(index.property_index, &record.old, &record.new)?;
+        } else {
+            let mut buf = [0u8; PROP_VALUE_MAX];
+            let current = pa.property_value_bytes(index.property_index, &mut buf)?;
+            if current == record.old.as_slice() {
+                log::info!("journal: re-applying the interrupted update of {name}");
+                return self.update(&index, &record.new);
+            }
+            if current != record.new.as_bytes() {
+                log::info!("journal: {name} changed since its update; dropping it");
+                return Ok(());
+            }
         }
 
+        if let Ok(pi) = pa.property_info(index.property_index) {
+            if let Err(e) = backend::waiter().wake(&pi.serial) {
+                log::warn!("Failed to wake property futex: {e}");
+            }
+        }
+        self.bump_context_serial(index.context_index);
+        let serial_pa = self.contexts()?.serial_prop_area();
+        serial_pa.serial().fetch_add(1, Ordering::Release);
+        if let Err(e) = backend::waiter().wake(serial_pa.serial()) {
+            log::warn!("Failed to wake global serial futex: {e}");
+        }
         Ok(())
     }
 
@@ -510,21 +1692,146 @@ impl SystemProperties {
     /// If a property with `name` already exists this is a silent no-op that
     /// returns `Ok(())` **without** updating the value — the same contract
     /// as bionic `prop_area::add`. Use [`Self::set`] (or `find` +
-    /// [`Self::update`]) for create-or-update semantics.
-    #[cfg(feature = "builder")]
+    /// [`Self::update`]) for create-or-update semantics. Names are folded
+    /// and checked like in [`Self::set`].
+    #[cfg(feature = "writer")]
     pub fn add(&mut self, name: &str, value: &str) -> Result<()> {
-        // Shared policy across client/server: only `ro.` names may exceed
-        // PROP_VALUE_MAX (stored as long properties).
-        crate::wire::validate_value_len(name, value).inspect_err(|e| log::error!("{e}"))?;
+        let name = &*self.claim_spelling(name)?;
+        self.add_folded(name, value)
+    }
+
+    /// Removes a property, so lookups of `name` miss until it is set
+    /// again. Returns whether it was set.
+    ///
+    /// Areas never free space: the property is unlinked from the trie and
+    /// the global serial (and its context's, see
+    /// [`Self::enable_context_serials`]) is bumped, but its record stays in
+    /// place until the area is rebuilt — [`Self::fragmentation_report`]
+    /// counts it as unreachable. A [`PropertyHandle`] taken before the
+    /// removal keeps reading the last value until the name is set again:
+    /// this instance then stores the new value in the same record and
+    /// links it back, so the handle, its waiters and watchers follow the
+    /// re-add. Another writer instance stores a new record, which handles
+    /// taken before the removal do not see; look the name up again after
+    /// a global serial change.
+    ///
+    /// Read-only properties are refused with [`Error::PermissionDenied`]
+    /// like in [`Self::update`]; `ro.` ones even inside
+    /// [`Self::override_read_only`].
+    #[cfg(feature = "writer")]
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        crate::wire::validate_property_name(name).inspect_err(|e| log::error!("{e}"))?;
+        let folded = self.fold(name).into_owned();
+        if let Some(prefix) = read_only_prefix(
+            &self.read_only_prefixes,
+            self.read_only_override,
+            folded.as_bytes(),
+        ) {
+            let error_msg = format!("Try to remove the read-only ({prefix}) property: {name}");
+            log::error!("{error_msg}");
+            return Err(Error::PermissionDenied(error_msg));
+        }
+        let Some(index) = self.find(&folded)? else {
+            return Ok(false);
+        };
+        let pa = self
+            .contexts_mut()?
+            .prop_area_mut_with_index(index.context_index)
+            .inspect_err(|e| log::error!("Failed to get mutable property area for {name}: {e}"))?;
+        let removed = pa
+            .remove(&folded)
+            .inspect_err(|e| log::error!("Failed to remove property {name}: {e}"))?;
+        // The next writer may pick any spelling.
+        self.spellings.remove(&folded);
+        if removed.is_none() {
+            return Ok(false);
+        }
+        self.removed.insert(folded, index);
+
+        self.stamp_bump();
+        self.bump_context_serial(index.context_index);
+        let serial_pa = self.contexts()?.serial_prop_area();
+        // Atomic RMW: see note in `update`.
+        serial_pa.serial().fetch_add(1, Ordering::Release);
+        self.wake_global();
+        log::debug!("Removed property {name}");
+        Ok(true)
+    }
+
+    /// Makes [`Self::add`] (and [`Self::set`] for a new name) refuse names
+    /// no `property_contexts` entry declares with [`Error::Undeclared`],
+    /// instead of storing them in the trie's default context. Starts out as
+    /// [`crate::require_declared`].
+    #[cfg(feature = "writer")]
+    pub fn set_require_declared(&mut self, require: bool) {
+        self.require_declared = require;
+    }
+
+    /// Makes [`Self::update`] (and [`Self::set`] for an existing name)
+    /// refuse properties under any of `prefixes` with
+    /// [`Error::PermissionDenied`], as it refuses `ro.` ones; their first
+    /// value is still stored. `ro.` stays read-only whatever the list
+    /// says. Starts out as [`crate::read_only_prefixes`].
+    #[cfg(feature = "writer")]
+    pub fn set_read_only_prefixes<I, S>(&mut self, prefixes: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.read_only_prefixes = crate::with_ro_prefix(prefixes);
+    }
+
+    /// The prefixes of [`Self::set_read_only_prefixes`], `ro.` included.
+    /// Their names may take long values, see
+    /// [`crate::wire::value_max_with`].
+    #[cfg(feature = "writer")]
+    pub fn read_only_prefixes(&self) -> &[String] {
+        &self.read_only_prefixes
+    }
+
+    /// Runs `f` — the owning service populating or repairing its
+    /// namespaces, say — with the prefixes of
+    /// [`Self::set_read_only_prefixes`] writable. `ro.` properties stay
+    /// write-once: bionic readers cache them as immutable. Nests like
+    /// [`Self::batch`].
+    #[cfg(feature = "writer")]
+    pub fn override_read_only<R>(&mut self, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let outer = std::mem::replace(&mut self.read_only_override, true);
+        let result = f(self);
+        self.read_only_override = outer;
+        result
+    }
+
+    #[cfg(feature = "writer")]
+    fn add_folded(&mut self, name: &str, value: &str) -> Result<()> {
+        // Same name rules as the client and the service, so nothing lands
+        // in an area that could not be set through the socket.
+        crate::wire::validate_property_name(name).inspect_err(|e| log::error!("{e}"))?;
+        // Shared policy across client/server: only read-only names may
+        // exceed PROP_VALUE_MAX (stored as long properties).
+        crate::wire::validate_value_len_with(name, value, &self.read_only_prefixes)
+            .inspect_err(|e| log::error!("{e}"))?;
+        if self.require_declared && !self.contexts()?.is_declared(name) {
+            let e = Error::Undeclared {
+                name: name.to_owned(),
+            };
+            log::error!("{e}");
+            return Err(e);
+        }
 
-        let mut res = match self.contexts.prop_area_mut_for_name(name) {
+        if let Some(handle) = self.removed.remove(name) {
+            if self.relink_removed(name, &handle, value)? {
+                return Ok(());
+            }
+        }
+
+        let (pa, context_index) = match self.contexts_mut()?.prop_area_mut_for_name(name) {
             Ok(res) => res,
             Err(e) => {
                 log::error!("Failed to get mutable property area for {name}: {e}");
                 return Err(e);
             }
         };
-        let pa = res.0.property_area_mut();
 
         match pa.add(name, value) {
             Ok(_) => {}
@@ -534,22 +1841,293 @@ impl SystemProperties {
             }
         }
 
-        let serial_pa = self.contexts.serial_prop_area();
+        self.stamp_bump();
+        self.bump_context_serial(context_index);
+        let serial_pa = self.contexts()?.serial_prop_area();
         // Atomic RMW: see note in `update`.
         serial_pa.serial().fetch_add(1, Ordering::Release);
-
         // See the wake-failure note in `update`: the property is already
         // added and the serial bumped — report success.
-        if let Err(e) = futex_wake(serial_pa.serial()) {
-            log::warn!("Failed to wake global serial futex after adding property: {e}");
+        self.wake_global();
+
+        Ok(())
+    }
+
+    /// Stores `value` in the record [`Self::remove`] unlinked from `name`
+    /// and links it back, so handles on the record read the new value and
+    /// its waiters wake. Returns `false`, changing nothing, when the record
+    /// cannot take the value in place — a long record, or a long value —
+    /// and the caller adds a new one.
+    ///
+    /// The value goes in before the link: a lookup in between misses, as
+    /// it did before the add, instead of finding the removed value.
+    #[cfg(feature = "writer")]
+    fn relink_removed(&mut self, name: &str, handle: &PropertyHandle, value: &str) -> Result<bool> {
+        {
+            let pa = self
+                .contexts()?
+                .prop_area_with_index(handle.context_index)?;
+            if handle.check(&pa).is_err()
+                || pa.property_info(handle.property_index)?.is_long()
+                || crate::wire::validate_short_value_len(value).is_err()
+            {
+                return Ok(false);
+            }
         }
+        // `remove` refused read-only names; one that became read-only since
+        // still gets its first value back, as a new add would.
+        self.override_read_only(|props| props.update(handle, value))?;
+        self.contexts_mut()?
+            .prop_area_mut_with_index(handle.context_index)?
+            .relink(name, handle.property_index)
+            .inspect_err(|e| log::error!("Failed to relink property {name}: {e}"))?;
 
+        // `update` bumped the serials before the link; bump them again so
+        // waiters that looked the name up in between see it now.
+        self.stamp_bump();
+        self.bump_context_serial(handle.context_index);
+        let serial_pa = self.contexts()?.serial_prop_area();
+        serial_pa.serial().fetch_add(1, Ordering::Release);
+        self.wake_global();
+        log::debug!("Relinked removed property {name}");
+        Ok(true)
+    }
+
+    /// Wakes waiters on the global serial after a bump — or, inside a
+    /// [`Self::batch`], leaves that to the end of the batch.
+    #[cfg(feature = "writer")]
+    fn wake_global(&mut self) {
+        if let Some(batch) = &mut self.wake_batch {
+            if batch.global {
+                batch.coalesced += 1;
+            }
+            batch.global = true;
+            return;
+        }
+        let Some(contexts) = &self.contexts else {
+            return;
+        };
+        if let Err(e) = backend::waiter().wake(contexts.serial_prop_area().serial()) {
+            log::warn!("Failed to wake global serial futex: {e}");
+        }
+    }
+
+    /// Makes this store keep a serial per context area, next to the
+    /// global one: every change also bumps the serial of the area it
+    /// lands in, so [`Self::wait_any_in`] waiters are only woken by
+    /// changes to their own context instead of by every change.
+    ///
+    /// The setting is recorded in the serial area, so it holds for every
+    /// writer of this store (in any process, from this crate) until the
+    /// directory is recreated. bionic's `init` does not maintain them;
+    /// against its stores `wait_any_in` falls back to the global serial.
+    #[cfg(feature = "writer")]
+    pub fn enable_context_serials(&mut self) -> Result<()> {
+        self.contexts()?
+            .serial_prop_area()
+            .context_serials()
+            .store(CONTEXT_SERIALS_ENABLED, Ordering::Release);
         Ok(())
     }
 
+    /// Bumps the serial of the area at `context_index`, if the store keeps
+    /// per-context serials, and wakes its waiters — inside a
+    /// [`Self::batch`], at the end of the batch. Like the global bump,
+    /// called once the change is published: failures are only logged.
+    #[cfg(feature = "writer")]
+    fn bump_context_serial(&mut self, context_index: u32) {
+        let Some(contexts) = &self.contexts else {
+            return;
+        };
+        if !has_context_serials(contexts.serial_prop_area()) {
+            return;
+        }
+        let pa = match contexts.prop_area_with_index(context_index) {
+            Ok(pa) => pa,
+            Err(e) => {
+                log::warn!("Failed to bump the serial of context {context_index}: {e}");
+                return;
+            }
+        };
+        let area = pa.property_area();
+        area.serial().fetch_add(1, Ordering::Release);
+        match &mut self.wake_batch {
+            Some(batch) => {
+                if !batch.contexts.insert(context_index) {
+                    batch.coalesced += 1;
+                }
+            }
+            None => {
+                if let Err(e) = backend::waiter().wake(area.serial()) {
+                    log::warn!("Failed to wake context serial futex: {e}");
+                }
+            }
+        }
+    }
+
+    /// Runs `f` — typically a bulk load or a batch of `set`s — with futex
+    /// wakes coalesced: every change is published and bumps the serials
+    /// as usual, but waiters are woken once when `f` returns, once per
+    /// updated property, per context (see
+    /// [`Self::enable_context_serials`]) and on the global serial,
+    /// instead of after each change. Boot-time property floods otherwise cost two wake
+    /// syscalls per update.
+    ///
+    /// Waiters therefore learn about the batch's changes only when it
+    /// ends (a wait that starts after a change still returns at once, as
+    /// the serial it compares against has moved). The wakes are issued
+    /// even if `f` fails or panics, for the changes made before. A
+    /// `batch` inside `f` joins the outer one.
+    ///
+    /// bionic has no per-record "waiters" bit to skip wakes nobody waits
+    /// for — its record layout has no spare bits and readers map the areas
+    /// read-only — so coalescing is what keeps a flood cheap here.
+    #[cfg(feature = "writer")]
+    pub fn batch<R>(&mut self, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        if self.wake_batch.is_some() {
+            return f(self);
+        }
+        self.wake_batch = Some(WakeBatch::default());
+        // Ends the batch even if `f` panics: a caller catching the unwind
+        // must not be left with every later wake deferred.
+        let guard = WakeBatchGuard(self);
+        f(&mut *guard.0)
+    }
+
+    /// Ends the batch [`Self::batch`] opened, issuing its pending wakes.
+    #[cfg(feature = "writer")]
+    fn end_wake_batch(&mut self) {
+        let Some(batch) = self.wake_batch.take() else {
+            return;
+        };
+        for (context_index, property_index) in &batch.records {
+            let woken = self
+                .contexts()
+                .and_then(|contexts| contexts.prop_area_with_index(*context_index))
+                .and_then(|pa| {
+                    let pi = pa.property_info(*property_index)?;
+                    Ok(backend::waiter().wake(&pi.serial))
+                });
+            match woken {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => log::warn!("Failed to wake property futex: {e}"),
+                Err(e) => log::error!("Failed to get PropertyInfo for index {property_index}: {e}"),
+            }
+        }
+        for context_index in &batch.contexts {
+            match self
+                .contexts()
+                .and_then(|contexts| contexts.prop_area_with_index(*context_index))
+            {
+                Ok(pa) => {
+                    if let Err(e) = backend::waiter().wake(pa.property_area().serial()) {
+                        log::warn!("Failed to wake context serial futex: {e}");
+                    }
+                }
+                Err(e) => log::error!("Failed to get the area of context {context_index}: {e}"),
+            }
+        }
+        if batch.global {
+            self.wake_global();
+        }
+        log::debug!(
+            "Batch woke {} properties; {} wakes coalesced",
+            batch.records.len(),
+            batch.coalesced
+        );
+    }
+
+    /// Records the publish time for [`crate::wait_stats()`]. Relaxed: the
+    /// Release serial store that follows orders it for waiters.
+    #[cfg(feature = "writer")]
+    fn stamp_bump(&self) {
+        let Some(contexts) = &self.contexts else {
+            return;
+        };
+        contexts
+            .serial_prop_area()
+            .bump_stamp()
+            .store(wait_stats::now_stamp(), Ordering::Relaxed);
+    }
+
     pub fn context_serial(&self) -> u32 {
-        let serial_pa = self.contexts.serial_prop_area();
-        serial_pa.serial().load(Ordering::Acquire)
+        self.contexts.as_ref().map_or(0, |contexts| {
+            contexts.serial_prop_area().serial().load(Ordering::Acquire)
+        })
+    }
+
+    /// Mappings of replaced area files still mapped: a remap after a
+    /// property service restart keeps the old mapping only while a read,
+    /// wait or walk that started on it is still running, and unmaps it
+    /// when the last one ends. [`AreaState::generations`] counts the
+    /// remaps themselves.
+    pub fn retired_mappings(&self) -> usize {
+        self.contexts
+            .as_ref()
+            .map_or(0, ContextsSerialized::retired_mappings)
+    }
+
+    /// The area of `handle`, once [`PropertyHandle::check`] passed.
+    fn resolve(&self, handle: &PropertyHandle) -> Result<AreaRef<'_>> {
+        let pa = self
+            .contexts()?
+            .prop_area_with_index(handle.context_index)?;
+        handle.check(&pa)?;
+        Ok(pa)
+    }
+
+    /// Whether `handle`'s name still leads to its record — `false` once it
+    /// was removed and not set again — read off the trie node the handle
+    /// was found through.
+    pub(crate) fn is_linked(&self, handle: &PropertyHandle) -> Result<bool> {
+        let pa = self.resolve(handle)?;
+        Ok(pa.node_prop(handle.node_offset)? == handle.property_index)
+    }
+
+    /// `handle`'s value and the serial it was read at, handed to `f`
+    /// without looking the name up again.
+    pub(crate) fn read_handle_with<R, F>(&self, handle: &PropertyHandle, f: F) -> Result<R>
+    where
+        F: FnOnce(&str, u32) -> R,
+    {
+        let pa = self.resolve(handle)?;
+        self.read_with_serial(&pa, handle.property_index, f)
+    }
+
+    /// Resolves `idx` to its `PropertyInfo` and hands it to `f`, logging
+    /// lookup failures. Shared by the serial/wait accessors, which report
+    /// failure as `None`.
+    fn with_property_info<R>(
+        &self,
+        idx: &PropertyHandle,
+        f: impl FnOnce(&crate::property_info::PropertyInfo) -> R,
+    ) -> Option<R> {
+        let pa = self
+            .resolve(idx)
+            .inspect_err(|e| {
+                log::error!(
+                    "Failed to resolve handle in context {}: {e}",
+                    idx.context_index
+                )
+            })
+            .ok()?;
+        let pi = pa
+            .property_info(idx.property_index)
+            .inspect_err(|e| {
+                log::error!(
+                    "Failed to get PropertyInfo for index {}: {e}",
+                    idx.property_index
+                )
+            })
+            .ok()?;
+        Some(f(pi))
+    }
+
+    /// Whether the property's value is stored out of line (a `ro.` value of
+    /// `PROP_VALUE_MAX` bytes or more, see [`crate::value_max`]), or `None`
+    /// if the context/property lookup fails.
+    pub fn is_long(&self, idx: &PropertyHandle) -> Option<bool> {
+        self.with_property_info(idx, |pi| pi.is_long())
     }
 
     /// Reads the per-property serial counter, or `None` if the context/property
@@ -563,104 +2141,49 @@ impl SystemProperties {
     /// *bounded* to 200ms total (dirty windows are microseconds; the bound
     /// only triggers if a writer crashed mid-update, where bionic would
     /// hang): on expiry the dirty serial is returned as-is with a warning.
-    /// The wait is sliced like [`Self::wait`] — the node's read lock is
-    /// released between 20ms slices so a queued same-process builder
-    /// writer (and, behind it on a writer-preferring `RwLock`, new
-    /// readers) is never blocked for the whole bound. On macOS (no futex)
-    /// the raw — possibly dirty — serial is returned immediately.
-    pub fn serial(&self, idx: &PropertyIndex) -> Option<u32> {
-        #[cfg(any(target_os = "android", target_os = "linux"))]
-        {
-            // A same-process builder writer cannot be mid-update while we
-            // hold the read guard — it takes the node's write lock for the
-            // whole update — so a dirty serial implies a *cross-process*
-            // writer and each bounded slice below cannot deadlock.
-            const DIRTY_SLICE: Duration = Duration::from_millis(20);
-            const DIRTY_WAIT_TOTAL: Duration = Duration::from_millis(200);
-            let start = Instant::now();
-            loop {
-                // (Re-)acquire the node lock for this slice only.
-                let guard = self
-                    .contexts
-                    .prop_area_with_index(idx.context_index)
-                    .inspect_err(|e| {
-                        log::error!(
-                            "Failed to get PropertyArea for index {}: {e}",
-                            idx.context_index
-                        )
-                    })
-                    .ok()?;
-                let pi = guard
-                    .property_area()
-                    .property_info(idx.property_index)
-                    .inspect_err(|e| {
-                        log::error!(
-                            "Failed to get PropertyInfo for index {}: {e}",
-                            idx.property_index
-                        )
-                    })
-                    .ok()?;
-                let serial = pi.serial.load(Ordering::Acquire);
-                if !serial_dirty(serial) {
-                    return Some(serial);
-                }
-                // Clamp the final slice so the total bound is exact, and
-                // check it BEFORE waiting so expiry never adds a slice.
-                let remaining = DIRTY_WAIT_TOTAL.saturating_sub(start.elapsed());
-                if remaining.is_zero() {
-                    log::warn!(
-                        "serial: entry still dirty after {DIRTY_WAIT_TOTAL:?} \
-                         (writer crashed mid-update?); returning the dirty serial"
-                    );
-                    return Some(serial);
-                }
-                let slice = remaining.min(DIRTY_SLICE);
-                // Derive both fields from the Duration (like `wait`) —
-                // hardcoding `tv_sec: 0` would silently truncate whole
-                // seconds if `DIRTY_SLICE` ever grew past 1s.
-                let slice_ts = Timespec {
-                    tv_sec: slice.as_secs() as _,
-                    tv_nsec: slice.subsec_nanos() as _,
-                };
-                match futex_wait(&pi.serial, serial, Some(&slice_ts)) {
-                    FutexWaitOutcome::Changed(s) if !serial_dirty(s) => return Some(s),
-                    // Still dirty (writer burst) or slice expired: drop the
-                    // guard at the end of this iteration and re-acquire.
-                    FutexWaitOutcome::Changed(_) | FutexWaitOutcome::TimedOut => {}
-                    FutexWaitOutcome::Failed => {
-                        let current = pi.serial.load(Ordering::Acquire);
-                        if serial_dirty(current) {
-                            log::warn!("serial: futex wait failed; returning the dirty serial");
-                        }
-                        return Some(current);
+    pub fn serial(&self, idx: &PropertyHandle) -> Option<u32> {
+        // A same-process builder writer cannot be mid-update while we
+        // borrow `self` (writes take `&mut self`), so a dirty serial
+        // implies a *cross-process* writer and the bounded wait in
+        // `settled_serial` cannot deadlock.
+        self.with_property_info(idx, |pi| Self::settled_serial(&pi.serial))
+    }
+
+    /// The value of the record serial word `serial` once it is clean, or
+    /// the dirty one after the bounded wait of [`Self::serial`].
+    fn settled_serial(serial_word: &AtomicU32) -> u32 {
+        const DIRTY_WAIT_TOTAL: Duration = Duration::from_millis(200);
+        let start = Instant::now();
+        let mut serial = serial_word.load(Ordering::Acquire);
+        while serial_dirty(serial) {
+            // Check the bound BEFORE waiting so expiry never adds
+            // another wait.
+            let remaining = DIRTY_WAIT_TOTAL.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                log::warn!(
+                    "serial: entry still dirty after {DIRTY_WAIT_TOTAL:?} \
+                     (writer crashed mid-update?); returning the dirty serial"
+                );
+                return serial;
+            }
+            let remaining_ts = Timespec {
+                tv_sec: remaining.as_secs() as _,
+                tv_nsec: remaining.subsec_nanos() as _,
+            };
+            serial = match backend::waiter().wait(serial_word, serial, Some(&remaining_ts)) {
+                // Still dirty (writer burst) loops; clean returns.
+                WaitOutcome::Changed(s) => s,
+                WaitOutcome::TimedOut => serial_word.load(Ordering::Acquire),
+                WaitOutcome::Failed => {
+                    let current = serial_word.load(Ordering::Acquire);
+                    if serial_dirty(current) {
+                        log::warn!("serial: wait failed; returning the dirty serial");
                     }
+                    return current;
                 }
-            }
-        }
-        #[cfg(target_os = "macos")]
-        {
-            let guard = self
-                .contexts
-                .prop_area_with_index(idx.context_index)
-                .inspect_err(|e| {
-                    log::error!(
-                        "Failed to get PropertyArea for index {}: {e}",
-                        idx.context_index
-                    )
-                })
-                .ok()?;
-            let pi = guard
-                .property_area()
-                .property_info(idx.property_index)
-                .inspect_err(|e| {
-                    log::error!(
-                        "Failed to get PropertyInfo for index {}: {e}",
-                        idx.property_index
-                    )
-                })
-                .ok()?;
-            Some(pi.serial.load(Ordering::Acquire))
+            };
         }
+        serial
     }
 
     /// Waits for any property to change. Equivalent to
@@ -670,6 +2193,57 @@ impl SystemProperties {
         self.wait(None, None, None)
     }
 
+    /// Whether the store's writers keep a serial per context area (see
+    /// [`Self::enable_context_serials`]), which [`Self::wait_any_in`]
+    /// waits on.
+    pub fn has_context_serials(&self) -> bool {
+        self.contexts
+            .as_ref()
+            .is_some_and(|contexts| has_context_serials(contexts.serial_prop_area()))
+    }
+
+    /// The serial [`Self::wait_any_in`] waits on for `name`: that of the
+    /// context area `name` resolves to, or the global serial
+    /// ([`Self::context_serial`]) when the store keeps no per-context
+    /// serials. Pass it back as `old_serial`.
+    pub fn context_serial_of(&self, name: &str) -> Result<u32> {
+        self.with_filter_serial(name, |serial| serial.load(Ordering::Acquire))
+    }
+
+    /// Waits for any property in the context `name` resolves to — e.g.
+    /// `persist.radio.` for everything declared in the same context as it
+    /// — to change, returning the new [`Self::context_serial_of`].
+    ///
+    /// With tens of thousands of changing properties, every change wakes
+    /// every [`Self::wait_any`] waiter. A store with per-context serials
+    /// wakes these waiters only for changes to their context. Without
+    /// them this waits on the global serial, as `wait_any` does: changes
+    /// elsewhere then end the wait too, so callers must re-check what they
+    /// are waiting for either way. `old_serial`, `timeout` and the `None`
+    /// return are as for [`Self::wait`]; a name whose area cannot be
+    /// resolved also returns `None`.
+    pub fn wait_any_in(
+        &self,
+        name: &str,
+        old_serial: Option<u32>,
+        timeout: Option<&Timespec>,
+    ) -> Option<u32> {
+        self.with_filter_serial(name, |serial| self.wait_on(serial, old_serial, timeout))
+            .inspect_err(|e| log::error!("Failed to resolve the context of {name}: {e}"))
+            .ok()?
+    }
+
+    /// Hands `f` the serial word [`Self::wait_any_in`] waits on for
+    /// `name`.
+    fn with_filter_serial<R>(&self, name: &str, f: impl FnOnce(&AtomicU32) -> R) -> Result<R> {
+        let contexts = self.contexts()?;
+        if !has_context_serials(contexts.serial_prop_area()) {
+            return Ok(f(contexts.serial_prop_area().serial()));
+        }
+        let (pa, _) = contexts.prop_area_for_name(&self.fold(name))?;
+        Ok(f(pa.property_area().serial()))
+    }
+
     /// Waits until the property at `index` (or, with `index == None`, the
     /// global serial — i.e. any property) changes, returning the new serial.
     /// Returns `None` on timeout, lookup failure, **or a futex syscall
@@ -688,149 +2262,308 @@ impl SystemProperties {
     /// With `None`, the current serial is sampled at entry, so a change
     /// that lands before this call is only observed at the *next* change.
     ///
-    /// On macOS there is no futex, so this cannot *block*: the
-    /// `old_serial` fast path still works (a serial that already differs
-    /// returns `Some` immediately — that part is a plain atomic load),
-    /// but once the serial has to be waited on, `None` is returned
-    /// immediately. Do not call it in a polling loop there.
-    ///
-    /// # Same-process builder writers
-    ///
-    /// Per-property waits need the context node's read lock to reach the
-    /// serial word inside the mmap, and a *same-process* builder writer
-    /// (`set`/`update`) needs that node's write lock. To keep the two from
-    /// deadlocking, the wait is **sliced**: the lock is released and
-    /// re-acquired roughly every 100ms, with a serial re-check on each
-    /// re-acquisition closing the missed-wakeup window. A same-process
-    /// writer is therefore delayed by at most one slice instead of
-    /// blocking forever; cross-process waiters (the normal Android
-    /// arrangement, and this crate's service) never contend on the lock at
-    /// all. Global-serial waits (`index == None`) take no lock and are not
-    /// sliced.
+    /// macOS has no futex; there the default [`crate::backend::PollWaitBackend`]
+    /// wakes same-process waiters immediately and notices changes made
+    /// by other processes within its poll slice (10ms). On Linux, a
+    /// sandbox that refuses the futex syscall switches waits to polling
+    /// the serial (see [`crate::backend::FutexBackend`]) rather than
+    /// failing them.
+    ///
+    /// The wait holds no lock (context mappings are read lock-free), but
+    /// it borrows `self` for its whole duration: waiting on a builder
+    /// instance blocks that instance's writers, which need `&mut self`.
+    /// Wait through a separate reader instance (as the global
+    /// [`crate::system_properties()`] is) when the writer lives in the same
+    /// process.
     pub fn wait(
         &self,
-        index: Option<&PropertyIndex>,
+        index: Option<&PropertyHandle>,
         old_serial: Option<u32>,
         timeout: Option<&Timespec>,
     ) -> Option<u32> {
-        // No index → wait on the global serial (lock-free, no slicing).
-        let Some(idx) = index else {
-            let serial_pa = self.contexts.serial_prop_area().serial();
-            // Documented already-changed fast path, checked BEFORE the
-            // futex: on Linux it merely pre-empts the syscall's EAGAIN,
-            // but on macOS (no futex — `futex_wait` fails immediately)
-            // it is the only thing keeping the `old_serial` contract.
-            let current = serial_pa.load(Ordering::Acquire);
-            let old = match old_serial {
-                Some(old) if old != current => return Some(current),
-                Some(old) => old,
-                None => current,
-            };
-            return match futex_wait(serial_pa, old, timeout) {
-                FutexWaitOutcome::Changed(s) => Some(s),
-                FutexWaitOutcome::TimedOut | FutexWaitOutcome::Failed => None,
-            };
-        };
+        match index {
+            Some(idx) => {
+                self.with_property_info(idx, |pi| self.wait_on(&pi.serial, old_serial, timeout))?
+            }
+            None => self.wait_on(
+                self.contexts.as_ref()?.serial_prop_area().serial(),
+                old_serial,
+                timeout,
+            ),
+        }
+    }
 
-        #[cfg(any(target_os = "android", target_os = "linux"))]
-        {
-            /// Upper bound on how long one slice may hold the node's read
-            /// lock — i.e. the worst-case delay imposed on a same-process
-            /// builder writer.
-            const LOCK_SLICE: Duration = Duration::from_millis(100);
-
-            // Convert the caller timeout to a deadline once, mirroring
-            // `futex_wait`'s own validation: negative/invalid → immediate
-            // timeout; unrepresentably-huge → wait forever (like bionic).
-            let deadline = match timeout {
-                None => None,
-                Some(t) if t.tv_sec < 0 || t.tv_nsec < 0 || t.tv_nsec >= 1_000_000_000 => {
-                    return None;
-                }
-                Some(t) => {
-                    Instant::now().checked_add(Duration::new(t.tv_sec as u64, t.tv_nsec as u32))
-                }
+    /// Waits until `name` holds `expected`, returning `true` once it does
+    /// — immediately if it already does — and `false` when `timeout`
+    /// passes first. A property that does not exist yet is waited for;
+    /// an unset property never matches, not even an empty `expected`.
+    ///
+    /// The serial is sampled before each read of the value and passed to
+    /// the wait as `old_serial`, so a set landing between the read and the
+    /// wait ends the wait at once instead of being missed. Until the
+    /// property exists the wait is on the global serial, which an add
+    /// bumps; afterwards on the property's own.
+    ///
+    /// A `timeout` too large to add to the current time, such as
+    /// [`Duration::MAX`], waits without a bound.
+    pub fn wait_for_value(&self, name: &str, expected: &str, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            let handle = self.find(name)?;
+            let serial = match &handle {
+                Some(handle) => self.serial(handle),
+                None => Some(self.context_serial()),
             };
-
-            let mut old = old_serial;
-            loop {
-                // Compute the slice BEFORE (re-)acquiring the lock: an
-                // already-expired deadline must return without another
-                // acquisition, and blocking on a writer-held lock right
-                // after expiry would overshoot the caller's timeout.
-                let slice = match deadline {
-                    None => LOCK_SLICE,
-                    Some(d) => {
-                        let remaining = d.saturating_duration_since(Instant::now());
-                        if remaining.is_zero() {
-                            return None;
-                        }
-                        remaining.min(LOCK_SLICE)
+            if handle.is_some() {
+                match self.get_with_result(name) {
+                    Ok(value) if value == expected => return Ok(true),
+                    Ok(_) | Err(Error::NotFound(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(false);
                     }
-                };
-                // (Re-)acquire the node lock for this slice only.
-                let guard = self
-                    .contexts
-                    .prop_area_with_index(idx.context_index)
-                    .inspect_err(|e| {
-                        log::error!(
-                            "Failed to get PropertyArea for index {}: {e}",
-                            idx.context_index
-                        )
-                    })
-                    .ok()?;
-                let pi = guard
-                    .property_area()
-                    .property_info(idx.property_index)
-                    .inspect_err(|e| {
-                        log::error!(
-                            "Failed to get PropertyInfo for index {}: {e}",
-                            idx.property_index
-                        )
+                    Some(Timespec {
+                        tv_sec: remaining.as_secs() as _,
+                        tv_nsec: remaining.subsec_nanos() as _,
                     })
-                    .ok()?;
-                let old_val = *old.get_or_insert_with(|| pi.serial.load(Ordering::Acquire));
-                // The serial may have changed while the lock was released
-                // between slices — the futex wake fired with no waiter, so
-                // this re-check is what closes that window.
-                let current = pi.serial.load(Ordering::Acquire);
-                if current != old_val {
-                    return Some(current);
                 }
-                let slice_ts = Timespec {
-                    tv_sec: slice.as_secs() as _,
-                    tv_nsec: slice.subsec_nanos() as _,
-                };
-                match futex_wait(&pi.serial, old_val, Some(&slice_ts)) {
-                    FutexWaitOutcome::Changed(s) => return Some(s),
-                    // Slice expired: fall through, dropping `guard` at the
-                    // end of the iteration so writers get a window.
-                    FutexWaitOutcome::TimedOut => {}
-                    FutexWaitOutcome::Failed => return None,
+                None => None,
+            };
+            // `None` is a timeout, checked above on the next pass, or a
+            // handle gone stale, which the next `find` replaces.
+            self.wait(handle.as_ref(), serial, remaining.as_ref());
+        }
+    }
+
+    fn wait_on(
+        &self,
+        serial: &AtomicU32,
+        old_serial: Option<u32>,
+        timeout: Option<&Timespec>,
+    ) -> Option<u32> {
+        // Documented already-changed fast path, checked BEFORE the wait:
+        // with the futex backend it merely pre-empts the syscall's EAGAIN,
+        // but a backend that cannot block (`FutexBackend` on macOS) relies
+        // on it for the `old_serial` contract.
+        let current = serial.load(Ordering::Acquire);
+        let old = match old_serial {
+            Some(old) if old != current => return Some(current),
+            Some(old) => old,
+            None => current,
+        };
+        let wait_start = wait_stats::enabled().then(wait_stats::now_stamp);
+        match backend::waiter().wait(serial, old, timeout) {
+            WaitOutcome::Changed(s) => {
+                if let Some(start) = wait_start {
+                    let bump = self.contexts.as_ref()?.serial_prop_area().bump_stamp();
+                    wait_stats::record_wake(start, bump.load(Ordering::Relaxed));
                 }
+                Some(s)
             }
+            WaitOutcome::TimedOut | WaitOutcome::Failed => None,
         }
-        #[cfg(target_os = "macos")]
-        {
-            // No futex, so blocking is impossible — but the documented
-            // `old_serial` fast path is a plain load and must still hold:
-            // a serial that already moved past `old_serial` returns
-            // immediately instead of being misreported as a failure.
-            let _ = timeout;
-            let current = self.serial(idx)?;
-            if old_serial.is_some_and(|old| old != current) {
-                return Some(current);
+    }
+}
+
+/// Callback of a [`PropertyWatcher`] subscription, called with the
+/// property's name and new value.
+type WatchFn = dyn FnMut(&str, &str) + Send;
+
+/// Identifies a subscription of a [`PropertyWatcher`], for
+/// [`PropertyWatcher::unwatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+struct Watch {
+    id: WatchId,
+    name: String,
+    /// Value the callback last saw, or the one at subscription; `None`
+    /// while the property is unset.
+    last: Option<String>,
+    callback: Arc<Mutex<Box<WatchFn>>>,
+}
+
+#[derive(Default)]
+struct WatcherShared {
+    watches: Mutex<Vec<Watch>>,
+    next_id: AtomicU64,
+    stop: AtomicBool,
+}
+
+/// How long the watcher thread waits before checking for [`PropertyWatcher::stop`].
+const WATCH_SLICE: Timespec = Timespec {
+    tv_sec: 0,
+    tv_nsec: 100_000_000,
+};
+
+/// Calls a closure whenever a property's value changes, from a thread
+/// waiting on the global serial — the loop around [`SystemProperties::wait`]
+/// that re-reads the value, managed.
+///
+/// ```rust,no_run
+/// use rsproperties::PropertyWatcher;
+///
+/// let mut watcher = PropertyWatcher::global()?;
+/// watcher.watch("persist.sys.timezone", |name, value| {
+///     println!("{name} is now {value}");
+/// });
+/// watcher.start()?;
+/// # Ok::<(), rsproperties::Error>(())
+/// ```
+///
+/// A subscription fires when the value differs from the one its callback
+/// last saw (at first, the value when [`Self::watch`] was called): setting
+/// the same value again does not fire, and changes landing in quick
+/// succession may be seen only as the last one. A property that is not
+/// set yet fires once it is. Callbacks run on the watcher thread, one at a
+/// time, and may watch and unwatch; a slow callback delays the others.
+///
+/// Subscriptions can be added before or after [`Self::start`]. The thread
+/// ends on [`Self::stop`] or when the watcher is dropped, within 100ms.
+pub struct PropertyWatcher {
+    props: &'static SystemProperties,
+    shared: Arc<WatcherShared>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl PropertyWatcher {
+    /// A stopped watcher without subscriptions over `props`.
+    pub fn new(props: &'static SystemProperties) -> Self {
+        Self {
+            props,
+            shared: Arc::default(),
+            thread: None,
+        }
+    }
+
+    /// [`Self::new`] over the global instance
+    /// ([`crate::system_properties()`]).
+    pub fn global() -> Result<Self> {
+        Ok(Self::new(crate::try_system_properties()?))
+    }
+
+    /// Calls `f` with `name` and its value whenever the value changes.
+    pub fn watch<F>(&self, name: &str, f: F) -> WatchId
+    where
+        F: FnMut(&str, &str) + Send + 'static,
+    {
+        let id = WatchId(self.shared.next_id.fetch_add(1, Ordering::Relaxed));
+        let last = read_watched(self.props, name);
+        lock_order::lock("PropertyWatcher::watches", &self.shared.watches).push(Watch {
+            id,
+            name: name.to_owned(),
+            last,
+            callback: Arc::new(Mutex::new(Box::new(f))),
+        });
+        id
+    }
+
+    /// Ends the subscription `id`; `false` if there is none. A callback
+    /// already running finishes.
+    pub fn unwatch(&self, id: WatchId) -> bool {
+        let mut watches = lock_order::lock("PropertyWatcher::watches", &self.shared.watches);
+        let before = watches.len();
+        watches.retain(|watch| watch.id != id);
+        watches.len() != before
+    }
+
+    /// Starts the watcher thread; does nothing if it runs already.
+    pub fn start(&mut self) -> Result<()> {
+        if self.thread.is_some() {
+            return Ok(());
+        }
+        self.shared.stop.store(false, Ordering::Relaxed);
+        let (props, shared) = (self.props, Arc::clone(&self.shared));
+        let thread = std::thread::Builder::new()
+            .name("rsproperties-watcher".to_owned())
+            .spawn(move || run_watcher(props, &shared))
+            .context_with_location("Failed to spawn the property watcher thread")?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    /// Stops the watcher thread and waits for it to end. The subscriptions
+    /// stay; [`Self::start`] resumes them, firing for what changed
+    /// meanwhile.
+    pub fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if thread.join().is_err() {
+            log::error!("Property watcher thread panicked");
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.thread.is_some()
+    }
+}
+
+impl Drop for PropertyWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn read_watched(props: &SystemProperties, name: &str) -> Option<String> {
+    props
+        .get_with_result(name)
+        .inspect_err(|e| {
+            if !is_quiet_lookup_error(e) {
+                log::warn!("Property watcher failed to read {name}: {e}");
             }
-            None
+        })
+        .ok()
+}
+
+fn run_watcher(props: &SystemProperties, shared: &WatcherShared) {
+    // Sampled before the first pass: a change landing during it ends the
+    // first wait at once.
+    let mut serial = props.context_serial();
+    dispatch_watches(props, shared);
+    while !shared.stop.load(Ordering::Relaxed) {
+        // `None` is a timeout (or a failed wait); the serial tells whether
+        // anything changed either way.
+        let current = props
+            .wait(None, Some(serial), Some(&WATCH_SLICE))
+            .unwrap_or_else(|| props.context_serial());
+        if current != serial {
+            serial = current;
+            dispatch_watches(props, shared);
         }
     }
 }
 
+/// Calls the callbacks of the subscriptions whose value changed, outside
+/// the subscription lock so they can watch and unwatch.
+fn dispatch_watches(props: &SystemProperties, shared: &WatcherShared) {
+    let mut fired = Vec::new();
+    for watch in lock_order::lock("PropertyWatcher::watches", &shared.watches).iter_mut() {
+        let Some(value) = read_watched(props, &watch.name) else {
+            continue;
+        };
+        if watch.last.as_ref() != Some(&value) {
+            watch.last = Some(value.clone());
+            fired.push((Arc::clone(&watch.callback), watch.name.clone(), value));
+        }
+    }
+    for (callback, name, value) in fired {
+        let mut callback = lock_order::lock("PropertyWatcher::callback", &callback);
+        callback(&name, &value);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    // Everything in this module is android-only; scope the imports the same
-    // way instead of blanket-allowing unused_imports.
-    #[cfg(target_os = "android")]
+    // Everything else in this module is android-only; scope the imports the
+    // same way instead of blanket-allowing unused_imports.
+    #[cfg(any(target_os = "android", feature = "builder"))]
     use super::*;
 
     #[cfg(target_os = "android")]
@@ -842,7 +2575,8 @@ mod tests {
     #[cfg(target_os = "android")]
     #[test]
     fn test_system_properties() -> Result<()> {
-        let system_properties = SystemProperties::new(Path::new(crate::PROP_DIRNAME))?;
+        let system_properties =
+            SystemProperties::new(Path::new(crate::PROP_DIRNAME), &Layout::default())?;
 
         let handle = std::thread::spawn(move || {
             let version1 = system_properties
@@ -858,4 +2592,111 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(all(feature = "builder", not(target_os = "android")))]
+    #[test]
+    fn test_journal_replay() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("rsprops_journal_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let trie = crate::build_trie(&[], "u:object_r:default_prop:s0", "string").unwrap();
+        std::fs::File::create(dir.join("property_info"))
+            .unwrap()
+            .write_all(&trie)
+            .unwrap();
+        let journal_path = dir.join("journal");
+
+        let mut props = SystemProperties::new_area(&dir).unwrap();
+        props.set("test.torn", "old").unwrap();
+        props.set("test.pending", "old").unwrap();
+        props.set("test.moved_on", "other").unwrap();
+        props.enable_journal(&journal_path).unwrap();
+        props.set("test.torn", "journaled").unwrap();
+        assert_eq!(std::fs::metadata(&journal_path).unwrap().len(), 0);
+
+        // A writer that died after setting the dirty bit.
+        let torn = props.find("test.torn").unwrap().unwrap();
+        let serial = props.serial(&torn).unwrap();
+        let journal = Journal::open(&journal_path).unwrap().0;
+        journal
+            .begin(b"test.torn", b"journaled", "new", serial)
+            .unwrap();
+        props
+            .with_property_info(&torn, |pi| pi.serial.store(serial | 1, Ordering::Release))
+            .unwrap();
+        props.journal = None;
+        props.enable_journal(&journal_path).unwrap();
+        assert!(!serial_dirty(props.serial(&torn).unwrap()));
+        assert_eq!(props.get_with_result("test.torn").unwrap(), "new");
+
+        // A writer that died before touching the mmap.
+        journal.begin(b"test.pending", b"old", "new", 0).unwrap();
+        props.enable_journal(&journal_path).unwrap();
+        assert_eq!(props.get_with_result("test.pending").unwrap(), "new");
+
+        // Stale and torn records change nothing.
+        journal.begin(b"test.moved_on", b"old", "new", 0).unwrap();
+        props.enable_journal(&journal_path).unwrap();
+        assert_eq!(props.get_with_result("test.moved_on").unwrap(), "other");
+        journal.begin(b"test.moved_on", b"other", "new", 0).unwrap();
+        let len = std::fs::metadata(&journal_path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&journal_path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        props.enable_journal(&journal_path).unwrap();
+        assert_eq!(props.get_with_result("test.moved_on").unwrap(), "other");
+        assert_eq!(std::fs::metadata(&journal_path).unwrap().len(), 0);
+
+        drop(props);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(all(feature = "builder", not(target_os = "android")))]
+    #[test]
+    fn test_fork_cow() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("rsprops_fork_cow_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let trie = crate::build_trie(&[], "u:object_r:default_prop:s0", "string").unwrap();
+        std::fs::File::create(dir.join("property_info"))
+            .unwrap()
+            .write_all(&trie)
+            .unwrap();
+
+        let mut props = SystemProperties::new_area(&dir).unwrap();
+        props.set("ro.cow.fixed", "1").unwrap();
+        props.set("test.cow.mode", "real").unwrap();
+        let serial = props.context_serial();
+
+        let mut scratch = props.fork_cow().unwrap();
+        scratch
+            .apply([("test.cow.mode", "what_if"), ("test.cow.added", "yes")])
+            .unwrap();
+        assert!(scratch.set("ro.cow.fixed", "2").is_err());
+        assert_eq!(scratch.get_with_result("test.cow.mode").unwrap(), "what_if");
+        assert_eq!(scratch.get_with_result("test.cow.added").unwrap(), "yes");
+        assert_eq!(scratch.freeze().unwrap().len(), 3);
+
+        // Neither the writer nor a fresh reader of the files sees the copy.
+        let reader = SystemProperties::open(&dir).unwrap();
+        for real in [&props, &reader] {
+            assert_eq!(real.get_with_result("test.cow.mode").unwrap(), "real");
+            assert!(real.find("test.cow.added").unwrap().is_none());
+            assert_eq!(real.context_serial(), serial);
+        }
+
+        // A reader can fork too, and each fork starts from the files.
+        let scratch = reader.fork_cow().unwrap();
+        assert!(!scratch.describe("test.cow.added").unwrap().exists);
+
+        drop((props, reader, scratch));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
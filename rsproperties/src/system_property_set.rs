@@ -15,84 +15,24 @@ use zerocopy::IntoBytes;
 use zerocopy_derive::*;
 
 use crate::errors::*;
-
-const DEFAULT_SOCKET_DIR: &str = "/dev/socket";
-pub const PROPERTY_SERVICE_SOCKET_NAME: &str = "property_service";
-pub const PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME: &str = "property_service_for_system";
-
+use crate::service_socket::{
+    socket_dir, PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME, PROPERTY_SERVICE_SOCKET_NAME,
+};
 use crate::wire::{
     PROP_MSG_SETPROP, PROP_MSG_SETPROP2, PROP_NAME_MAX, PROP_SUCCESS, PROP_VALUE_MAX,
 };
 
-/// Global socket directory configuration
-static SOCKET_DIR: OnceLock<PathBuf> = OnceLock::new();
-
-/// Set the global socket directory for property services (internal use only).
-/// This function can only be called once. Subsequent calls will be ignored.
-///
-/// Callers must hold `crate::GLOBAL_DIRS_LOCK` (see `lib::try_init`) so the
-/// pre-check + set sequence stays atomic against the implicit latch in
-/// [`socket_dir`].
-///
-/// # Arguments
-/// * `dir` - The directory path where property service sockets are located
-///
-/// # Returns
-/// * `true` if the directory was successfully set (first call)
-/// * `false` if the directory was already set (subsequent calls)
-pub(crate) fn set_socket_dir<P: AsRef<Path>>(dir: P) -> bool {
-    let dir_path = dir.as_ref().to_path_buf();
-
-    SOCKET_DIR.set(dir_path).is_ok()
-}
-
-/// `true` once `set_socket_dir` has succeeded (or `socket_dir()` was called
-/// and populated the cell via env/default). Used by `lib::try_init` for
-/// pre-flight checks before committing other globals.
-pub(crate) fn socket_dir_is_set() -> bool {
-    SOCKET_DIR.get().is_some()
-}
-
-/// Get the current socket directory.
-/// Returns the configured socket directory, environment variable, or default.
-///
-/// Priority order:
-/// 1. Directory set via `set_socket_dir()`
-/// 2. `PROPERTY_SERVICE_SOCKET_DIR` environment variable
-/// 3. Default directory: `/dev/socket`
-pub fn socket_dir() -> &'static Path {
-    // Lock-free once initialized; the first call takes `GLOBAL_DIRS_LOCK` so
-    // the env/default latch cannot slip between `try_init`'s pre-check and
-    // its `set_socket_dir` commit.
-    if let Some(dir) = SOCKET_DIR.get() {
-        return dir.as_path();
-    }
-    let _guard = crate::lock_global_dirs();
-    SOCKET_DIR
-        .get_or_init(|| {
-            // `var_os`, not `var`: Unix paths are arbitrary bytes, and a
-            // non-UTF-8 configured directory must be *used*, not silently
-            // swapped for the default — the same
-            // different-path-on-lossy-conversion hazard
-            // `get_property_service_socket` documents.
-            env::var_os("PROPERTY_SERVICE_SOCKET_DIR")
-                .map(PathBuf::from)
-                .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET_DIR))
-        })
-        .as_path()
-}
-
-/// Get the full path to the property service socket.
+/// Get the full path to the property service socket in `dir`.
 /// Returns `PathBuf` (not `String`): a lossy string conversion would make
 /// the client connect to a *different* path when the configured directory
 /// is not valid UTF-8.
-fn get_property_service_socket() -> PathBuf {
-    socket_dir().join(PROPERTY_SERVICE_SOCKET_NAME)
+fn get_property_service_socket(dir: &Path) -> PathBuf {
+    dir.join(PROPERTY_SERVICE_SOCKET_NAME)
 }
 
-/// Get the full path to the system property service socket
-fn get_property_service_for_system_socket() -> PathBuf {
-    socket_dir().join(PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME)
+/// Get the full path to the system property service socket in `dir`
+fn get_property_service_for_system_socket(dir: &Path) -> PathBuf {
+    dir.join(PROPERTY_SERVICE_FOR_SYSTEM_SOCKET_NAME)
 }
 
 /// Bound on every socket operation against the property service —
@@ -229,14 +169,14 @@ struct ServiceConnection {
 }
 
 impl ServiceConnection {
-    fn new(name: &str) -> Result<Self> {
-        let property_service_socket = get_property_service_socket();
+    fn new(socket_dir: &Path, name: &str) -> Result<Self> {
+        let property_service_socket = get_property_service_socket(socket_dir);
 
         // Try the system-property socket for `sys.powerctl`, falling back to
         // the regular service socket if connection fails. Connect itself is
         // the only authoritative check — `fs::metadata` would race the open.
         let stream = if name == "sys.powerctl" {
-            let system_socket = get_property_service_for_system_socket();
+            let system_socket = get_property_service_for_system_socket(socket_dir);
             connect_with_timeout(&system_socket, SERVICE_IO_TIMEOUT)
                 .or_else(|first_err| {
                     log::warn!(
@@ -263,6 +203,86 @@ impl ServiceConnection {
         // deadline pattern as `send` and `wait_for_socket_close`.
         let deadline = Instant::now() + SERVICE_IO_TIMEOUT;
         let mut buf = [0u8; 4];
+        self.recv_exact(&mut buf, deadline)?;
+        Ok(i32::from_ne_bytes(buf))
+    }
+
+    /// Reads the optional reason trailing a V2 error code (see
+    /// [`crate::wire::MAX_WIRE_ERROR_MESSAGE_LEN`]). Services that send
+    /// none — AOSP init — close right after the code, so EOF, a malformed
+    /// trailer or a timeout all mean "no message", never an error: the
+    /// code alone is the authoritative answer.
+    fn recv_error_message(&mut self) -> Option<String> {
+        let deadline = Instant::now() + SERVICE_IO_TIMEOUT;
+        let mut len = [0u8; 4];
+        self.recv_exact(&mut len, deadline).ok()?;
+        let len = u32::from_ne_bytes(len) as usize;
+        if len == 0 || len > crate::wire::MAX_WIRE_ERROR_MESSAGE_LEN {
+            return None;
+        }
+        let mut message = vec![0u8; len];
+        self.recv_exact(&mut message, deadline).ok()?;
+        Some(String::from_utf8_lossy(&message).into_owned())
+    }
+
+    /// Receives the start of a message carrying one `SCM_RIGHTS` fd into
+    /// `buf`, returning the fd and how many bytes of `buf` were filled. The
+    /// kernel attaches the fd to the message's first byte and never merges
+    /// it into a read of other data, so this must start exactly at the
+    /// message boundary; plain reads would drop the fd.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn recv_fd(
+        &mut self,
+        buf: &mut [u8],
+        deadline: Instant,
+    ) -> Result<(std::os::fd::OwnedFd, usize)> {
+        use rustix::net::{recvmsg, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags};
+        use std::mem::MaybeUninit;
+
+        let mut space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(1))];
+        let mut control = RecvAncillaryBuffer::new(&mut space);
+        let msg = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(map_timeout_err(
+                    std::io::ErrorKind::TimedOut.into(),
+                    "waiting for a memfd from the property service",
+                ));
+            }
+            let _ = self.stream.set_read_timeout(Some(remaining));
+            match recvmsg(
+                &self.stream,
+                &mut [std::io::IoSliceMut::new(buf)],
+                &mut control,
+                RecvFlags::CMSG_CLOEXEC,
+            ) {
+                Ok(msg) => break msg,
+                Err(rustix::io::Errno::INTR) => {}
+                Err(e) => {
+                    return Err(map_timeout_err(
+                        e.into(),
+                        "waiting for a memfd from the property service",
+                    ))
+                }
+            }
+        };
+        if msg.bytes == 0 {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "property service closed before sending all memfds",
+            )));
+        }
+        let mut fds = control.drain().filter_map(|message| match message {
+            RecvAncillaryMessage::ScmRights(fds) => Some(fds),
+            _ => None,
+        });
+        let fd = fds.next().and_then(|mut fds| fds.next()).ok_or_else(|| {
+            Error::Parse("property service sent an area without a memfd".to_owned())
+        })?;
+        Ok((fd, msg.bytes))
+    }
+
+    fn recv_exact(&mut self, buf: &mut [u8], deadline: Instant) -> Result<()> {
         let mut filled = 0usize;
         while filled < buf.len() {
             let remaining = deadline.saturating_duration_since(Instant::now());
@@ -271,7 +291,8 @@ impl ServiceConnection {
                     std::io::ErrorKind::TimedOut,
                     format!(
                         "timed out waiting for property service response \
-                         ({SERVICE_IO_TIMEOUT:?} total, {filled}/4 bytes received)"
+                         ({SERVICE_IO_TIMEOUT:?} total, {filled}/{} bytes received)",
+                        buf.len()
                     ),
                 )));
             }
@@ -301,7 +322,7 @@ impl ServiceConnection {
         // may have already closed its end after responding, where a
         // `setsockopt` would fail with EINVAL on macOS ("the socket has
         // been shut down") depending on FIN arrival timing.
-        Ok(i32::from_ne_bytes(buf))
+        Ok(())
     }
 }
 
@@ -423,7 +444,7 @@ impl<'a> ServiceWriter<'a> {
 }
 
 #[derive(Clone, Copy)]
-enum ProtocolVersion {
+pub(crate) enum ProtocolVersion {
     V1 = 1,
     V2 = 2,
 }
@@ -510,10 +531,19 @@ impl PropertyMessage {
 /// env/default without latching, so a later `init()` still lets the
 /// property win. After the first post-init `set()` the version is fixed
 /// for the process lifetime.
-fn protocol_version() -> ProtocolVersion {
+pub(crate) fn protocol_version() -> ProtocolVersion {
     static PROTOCOL_VERSION: OnceLock<ProtocolVersion> = OnceLock::new();
+    probe_protocol_version(&PROTOCOL_VERSION, crate::system_properties_if_initialized())
+}
 
-    if let Some(v) = PROTOCOL_VERSION.get() {
+/// [`protocol_version`] against `store`, latched in `cell` once a store
+/// is there to read. A [`crate::PropertiesClient`] of its own keeps its
+/// cell, so its sets follow its own store's property.
+pub(crate) fn probe_protocol_version(
+    cell: &OnceLock<ProtocolVersion>,
+    store: Option<&crate::system_properties::SystemProperties>,
+) -> ProtocolVersion {
+    if let Some(v) = cell.get() {
         return *v;
     }
 
@@ -533,19 +563,24 @@ fn protocol_version() -> ProtocolVersion {
         Err(_) => ProtocolVersion::V2,
     };
 
-    match crate::system_properties_if_initialized() {
-        Some(sp) => *PROTOCOL_VERSION.get_or_init(|| {
-            sp.read_with("ro.property_service.version", |v| {
-                match v.trim().parse::<u32>() {
-                    Ok(n) if n >= 2 => ProtocolVersion::V2,
-                    // Present but not a parseable ≥2: bionic parity → V1.
-                    _ => ProtocolVersion::V1,
-                }
-            })
-            // Property absent (or store read failed): env var, then the
-            // documented V2 default.
-            .unwrap_or_else(|_| env_or_default())
-        }),
+    match store {
+        // Probed outside the cell and then published (not `get_or_init`)
+        // so no `fork()` can catch the cell mid-initialization — see
+        // `try_system_properties`. Racing probes read the same property.
+        Some(sp) => {
+            let version = sp
+                .read_with("ro.property_service.version", |v| {
+                    match v.trim().parse::<u32>() {
+                        Ok(n) if n >= 2 => ProtocolVersion::V2,
+                        // Present but not a parseable ≥2: bionic parity → V1.
+                        _ => ProtocolVersion::V1,
+                    }
+                })
+                // Property absent (or store read failed): env var, then the
+                // documented V2 default.
+                .unwrap_or_else(|_| env_or_default());
+            *cell.get_or_init(|| version)
+        }
         // Store not initialized yet: provisional, deliberately NOT latched.
         None => env_or_default(),
     }
@@ -606,8 +641,16 @@ fn wait_for_socket_close(stream: &mut UnixStream, timeout: Duration) {
     let _ = stream.set_read_timeout(original_timeout);
 }
 
-// Set a system property via local domain socket.
-pub(crate) fn set(name: &str, value: &str) -> Result<()> {
+// Set a system property via the local domain socket in `socket_dir`,
+// speaking `version` of the wire protocol. Names under
+// `read_only_prefixes` may take long values, as in the store.
+pub(crate) fn set(
+    socket_dir: &Path,
+    version: ProtocolVersion,
+    read_only_prefixes: &[String],
+    name: &str,
+    value: &str,
+) -> Result<()> {
     // Validate name and value up front, for BOTH protocol versions. This
     // is load-bearing for interior NUL bytes in particular: the server
     // decodes both wire formats as C strings, so a NUL-carrying `&str`
@@ -618,10 +661,10 @@ pub(crate) fn set(name: &str, value: &str) -> Result<()> {
     // `validate_value_len` rejects NUL in values explicitly.
     crate::wire::validate_property_name(name)
         .inspect_err(|e| log::error!("setprop reject: {e}"))?;
-    crate::wire::validate_value_len(name, value)
+    crate::wire::validate_value_len_with(name, value, read_only_prefixes)
         .inspect_err(|e| log::error!("setprop reject: {e}"))?;
 
-    match protocol_version() {
+    match version {
         ProtocolVersion::V1 => {
             if name.len() >= PROP_NAME_MAX {
                 log::error!(
@@ -651,7 +694,7 @@ pub(crate) fn set(name: &str, value: &str) -> Result<()> {
             // `sys.powerctl` to the for_system socket by name, on V1 as
             // well as V2 (bionic's `send_prop_msg` constructs its V1
             // connection from `msg->name` the same way).
-            let mut conn = ServiceConnection::new(name)?;
+            let mut conn = ServiceConnection::new(socket_dir, name)?;
             let prop_msg = PropertyMessage::new(PROP_MSG_SETPROP, name, value)?;
 
             ServiceWriter::new()
@@ -664,25 +707,9 @@ pub(crate) fn set(name: &str, value: &str) -> Result<()> {
             // (Name/value policy is validated at the top of `set` — shared
             // with the V1 arm. Length prefixes are derived inside
             // `write_str`, so no separate truncation hazard here.)
-            // Mirror the server's wire caps so an oversized frame fails
-            // here with a clear message instead of the server's opaque
-            // error status.
-            if name.len() > crate::wire::MAX_WIRE_NAME_LEN {
-                return Err(Error::InvalidArgument(format!(
-                    "Property name exceeds the wire cap: {} > {}",
-                    name.len(),
-                    crate::wire::MAX_WIRE_NAME_LEN
-                )));
-            }
-            if value.len() > crate::wire::MAX_WIRE_VALUE_LEN {
-                return Err(Error::InvalidArgument(format!(
-                    "Property value exceeds the wire cap: {} > {}",
-                    value.len(),
-                    crate::wire::MAX_WIRE_VALUE_LEN
-                )));
-            }
+            check_wire_caps(name, value)?;
 
-            let mut conn = ServiceConnection::new(name)?;
+            let mut conn = ServiceConnection::new(socket_dir, name)?;
 
             ServiceWriter::new()
                 .write_u32(PROP_MSG_SETPROP2)
@@ -704,10 +731,8 @@ pub(crate) fn set(name: &str, value: &str) -> Result<()> {
                 // socket round-trip succeeded. A dedicated variant so callers
                 // can tell a permanent policy denial from a retryable
                 // `Error::Io`.
-                return Err(Error::ServiceError {
-                    name: name.to_owned(),
-                    code: res,
-                });
+                let message = conn.recv_error_message();
+                return Err(Error::ServiceError(SetError::new(name, res, message)));
             }
         }
     }
@@ -715,6 +740,426 @@ pub(crate) fn set(name: &str, value: &str) -> Result<()> {
     Ok(())
 }
 
+/// Mirrors the server's V2 wire caps so an oversized frame fails here with
+/// a clear message instead of the server's opaque error status.
+fn check_wire_caps(name: &str, value: &str) -> Result<()> {
+    if name.len() > crate::wire::MAX_WIRE_NAME_LEN {
+        return Err(Error::InvalidArgument(format!(
+            "Property name exceeds the wire cap: {} > {}",
+            name.len(),
+            crate::wire::MAX_WIRE_NAME_LEN
+        )));
+    }
+    if value.len() > crate::wire::MAX_WIRE_VALUE_LEN {
+        return Err(Error::InvalidArgument(format!(
+            "Property value exceeds the wire cap: {} > {}",
+            value.len(),
+            crate::wire::MAX_WIRE_VALUE_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Sets several properties, batched into
+/// [`crate::wire::PROP_MSG_SETPROP_BATCH`] requests of at most
+/// [`crate::wire::MAX_WIRE_BATCH`] entries, and returns one result per
+/// entry in order. Entries the client already rejects are not sent. A
+/// batch that fails as a whole fails each of its entries, and leaves the
+/// other batches' results alone.
+///
+/// With the V1 protocol, for `sys.powerctl` (which has a socket of its
+/// own) and once the service turns out not to know the batch command,
+/// the remaining entries go through [`set`] one by one.
+pub(crate) fn set_many(
+    socket_dir: &Path,
+    version: ProtocolVersion,
+    read_only_prefixes: &[String],
+    entries: &[(&str, &str)],
+) -> Result<Vec<Result<()>>> {
+    let mut results: Vec<Option<Result<()>>> = entries
+        .iter()
+        .map(|&(name, value)| {
+            crate::wire::validate_property_name(name)
+                .and_then(|()| {
+                    crate::wire::validate_value_len_with(name, value, read_only_prefixes)
+                })
+                .and_then(|()| check_wire_caps(name, value))
+                .inspect_err(|e| log::error!("setprop reject: {e}"))
+                .err()
+                .map(Err)
+        })
+        .collect();
+
+    if matches!(version, ProtocolVersion::V2) {
+        let batched: Vec<usize> = (0..entries.len())
+            .filter(|&i| results[i].is_none() && entries[i].0 != "sys.powerctl")
+            .collect();
+        for chunk in batched.chunks(crate::wire::MAX_WIRE_BATCH) {
+            let chunk_entries: Vec<(&str, &str)> = chunk.iter().map(|&i| entries[i]).collect();
+            match send_batch(socket_dir, &chunk_entries) {
+                Ok(Some(statuses)) => {
+                    for (&i, status) in chunk.iter().zip(statuses) {
+                        results[i] = Some(status);
+                    }
+                }
+                Ok(None) => break,
+                // The earlier batches stand; only this one's entries
+                // failed.
+                Err(e) => {
+                    for &i in chunk {
+                        results[i] = Some(Err(batch_failure(&e, entries[i].0)));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(results
+        .into_iter()
+        .zip(entries)
+        .map(|(result, &(name, value))| {
+            result.unwrap_or_else(|| set(socket_dir, version, read_only_prefixes, name, value))
+        })
+        .collect())
+}
+
+/// The error of a batch that failed as a whole, for its entry `name`:
+/// [`Error`] is not `Clone`, so each entry gets a copy of its own.
+fn batch_failure(e: &Error, name: &str) -> Error {
+    match e {
+        Error::ServiceError(e) => {
+            Error::ServiceError(SetError::new(name, e.code, e.message.clone()))
+        }
+        Error::Io(e) => Error::Io(std::io::Error::new(e.kind(), e.to_string())),
+        Error::Errno(e) => Error::Errno(*e),
+        Error::Parse(message) => Error::Parse(message.clone()),
+        Error::InvalidArgument(message) => Error::InvalidArgument(message.clone()),
+        e => Error::Io(std::io::Error::other(e.to_string())),
+    }
+}
+
+/// Sends one batch request, returning the entries' results, or `None`
+/// when the service does not know the command (AOSP init).
+fn send_batch(socket_dir: &Path, entries: &[(&str, &str)]) -> Result<Option<Vec<Result<()>>>> {
+    use crate::wire::{MAX_WIRE_ERROR_MESSAGE_LEN, PROP_ERROR_INVALID_CMD, PROP_MSG_SETPROP_BATCH};
+
+    let mut conn = ServiceConnection::new(socket_dir, "")?;
+    let mut writer = ServiceWriter::new()
+        .write_u32(PROP_MSG_SETPROP_BATCH)
+        .write_u32(entries.len() as u32);
+    for &(name, value) in entries {
+        writer = writer.write_str(name)?.write_str(value)?;
+    }
+    // AOSP init answers an unknown command after its first word and
+    // closes, so a frame larger than the socket buffer fails mid-write
+    // with EPIPE, or ECONNRESET for the bytes it never read. Its answer
+    // is still in the receive queue and says what happened.
+    let res = match writer.send(&mut conn) {
+        Ok(()) => conn.recv_i32()?,
+        Err(Error::Io(e))
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::ConnectionReset
+            ) =>
+        {
+            conn.recv_i32().map_err(|_| Error::Io(e))?
+        }
+        Err(e) => return Err(e),
+    };
+    if res == PROP_ERROR_INVALID_CMD {
+        log::debug!("Property service does not take batches; setting one by one");
+        return Ok(None);
+    }
+    if res != PROP_SUCCESS {
+        let message = conn.recv_error_message();
+        log::error!(
+            "Property service refused a batch of {} sets: 0x{res:X}",
+            entries.len()
+        );
+        return Err(Error::ServiceError(SetError::new(
+            format!("batch of {} properties", entries.len()),
+            res,
+            message,
+        )));
+    }
+
+    let deadline = Instant::now() + SERVICE_IO_TIMEOUT;
+    let mut word = [0u8; 4];
+    conn.recv_exact(&mut word, deadline)?;
+    let count = u32::from_ne_bytes(word) as usize;
+    if count != entries.len() {
+        return Err(Error::Parse(format!(
+            "property service answered {count} statuses for {} sets",
+            entries.len()
+        )));
+    }
+    let mut results = Vec::with_capacity(count);
+    for &(name, value) in entries {
+        conn.recv_exact(&mut word, deadline)?;
+        let code = i32::from_ne_bytes(word);
+        conn.recv_exact(&mut word, deadline)?;
+        let len = u32::from_ne_bytes(word) as usize;
+        if len > MAX_WIRE_ERROR_MESSAGE_LEN {
+            return Err(Error::Parse(format!("invalid reason length {len}")));
+        }
+        let mut message = vec![0u8; len];
+        conn.recv_exact(&mut message, deadline)?;
+        if code == PROP_SUCCESS {
+            results.push(Ok(()));
+            continue;
+        }
+        // Masked as in `set`.
+        log::error!(
+            "Property service returned error for '{name}' (<{} bytes>): 0x{code:X}",
+            value.len()
+        );
+        let message = (!message.is_empty()).then(|| String::from_utf8_lossy(&message).into_owned());
+        results.push(Err(Error::ServiceError(SetError::new(name, code, message))));
+    }
+    Ok(Some(results))
+}
+
+/// Removes a property through the property service
+/// ([`crate::wire::PROP_MSG_UNSETPROP`]). Always framed like V2, whatever
+/// [`protocol_version`] says: no V1 service knows the command either way.
+pub(crate) fn remove(socket_dir: &Path, name: &str) -> Result<()> {
+    use crate::wire::{MAX_WIRE_NAME_LEN, PROP_MSG_UNSETPROP};
+
+    crate::wire::validate_property_name(name)
+        .inspect_err(|e| log::error!("unsetprop reject: {e}"))?;
+    if name.len() > MAX_WIRE_NAME_LEN {
+        return Err(Error::InvalidArgument(format!(
+            "Property name exceeds the wire cap: {} > {MAX_WIRE_NAME_LEN}",
+            name.len()
+        )));
+    }
+
+    let mut conn = ServiceConnection::new(socket_dir, name)?;
+    ServiceWriter::new()
+        .write_u32(PROP_MSG_UNSETPROP)
+        .write_str(name)?
+        .send(&mut conn)?;
+
+    let res = conn.recv_i32()?;
+    if res != PROP_SUCCESS {
+        log::error!("Property service refused to remove '{name}': 0x{res:X}");
+        let message = conn.recv_error_message();
+        return Err(Error::ServiceError(SetError::new(name, res, message)));
+    }
+    Ok(())
+}
+
+/// Fetches the areas of a memfd-backed store from the property service
+/// ([`crate::wire::PROP_MSG_GET_AREAS`]), as file name and memfd pairs.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn fetch_memfd_areas(socket_dir: &Path) -> Result<Vec<(String, std::os::fd::OwnedFd)>> {
+    use crate::wire::{MAX_WIRE_AREAS, MAX_WIRE_AREA_NAME_LEN, PROP_MSG_GET_AREAS};
+
+    let mut conn = ServiceConnection::new(socket_dir, "")?;
+    ServiceWriter::new()
+        .write_u32(PROP_MSG_GET_AREAS)
+        .send(&mut conn)?;
+
+    let res = conn.recv_i32()?;
+    if res != PROP_SUCCESS {
+        let message = conn.recv_error_message();
+        log::error!("Property service refused to hand out memfd areas: 0x{res:X}");
+        return Err(Error::ServiceError(SetError::new(
+            "memfd areas",
+            res,
+            message,
+        )));
+    }
+    let deadline = Instant::now() + SERVICE_IO_TIMEOUT;
+    let mut word = [0u8; 4];
+    conn.recv_exact(&mut word, deadline)?;
+    let count = u32::from_ne_bytes(word) as usize;
+    if count > MAX_WIRE_AREAS {
+        return Err(Error::Parse(format!(
+            "property service announced {count} areas (cap {MAX_WIRE_AREAS})"
+        )));
+    }
+
+    let mut areas = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (fd, filled) = conn.recv_fd(&mut word, deadline)?;
+        conn.recv_exact(&mut word[filled..], deadline)?;
+        let len = u32::from_ne_bytes(word) as usize;
+        if len == 0 || len > MAX_WIRE_AREA_NAME_LEN {
+            return Err(Error::Parse(format!("invalid area name length {len}")));
+        }
+        let mut name = vec![0u8; len];
+        conn.recv_exact(&mut name, deadline)?;
+        let name = String::from_utf8(name)
+            .map_err(|e| Error::Parse(format!("area name is not UTF-8: {e}")))?;
+        // Names key the areas like file names in a properties directory;
+        // anything else would be a confused or hostile service.
+        if name.contains('/') || name == "." || name == ".." {
+            return Err(Error::Parse(format!("invalid area name {name:?}")));
+        }
+        areas.push((name, fd));
+    }
+    Ok(areas)
+}
+
+/// Property `rsproperties-service` sets to `"1"` once its sockets are
+/// bound and the areas are populated from the build.prop files.
+pub const SERVICE_READY_PROPERTY: &str = "sys.rsproperties.ready";
+
+/// Poll interval bounds for `wait_for_service`: start fast so a service
+/// that is just finishing startup costs little latency, then back off.
+const READY_POLL_MIN: Duration = Duration::from_millis(5);
+const READY_POLL_MAX: Duration = Duration::from_millis(100);
+
+/// Blocks until the property service accepts connections, or `timeout`
+/// elapses (`TimedOut` I/O error). A `timeout` too large to add to the
+/// current time, such as [`Duration::MAX`], waits without a bound.
+///
+/// Off Android, the service must also have published
+/// [`SERVICE_READY_PROPERTY`]: `rsproperties-service` binds its sockets
+/// while the areas are still being populated, so a connectable socket
+/// alone does not mean reads see the build.prop values yet. On Android,
+/// init owns readiness and the socket is the only signal.
+pub fn wait_for_service(timeout: Duration) -> Result<()> {
+    let deadline = Instant::now().checked_add(timeout);
+    let time_left = || {
+        deadline.map_or(Duration::MAX, |d| {
+            d.saturating_duration_since(Instant::now())
+        })
+    };
+    let path = get_property_service_socket(socket_dir());
+    let mut interval = READY_POLL_MIN;
+    loop {
+        let remaining = time_left();
+        // The probe connection is closed without sending a command, which
+        // the service treats as a no-op.
+        let connected = connect_with_timeout(&path, remaining.min(READY_POLL_MAX)).is_ok();
+        if connected && service_ready_published() {
+            log::debug!("Property service at {path:?} is ready");
+            return Ok(());
+        }
+
+        let remaining = time_left();
+        if remaining.is_zero() {
+            log::error!("Property service at {path:?} not ready after {timeout:?}");
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("property service at {path:?} not ready after {timeout:?}"),
+            )));
+        }
+        std::thread::sleep(interval.min(remaining));
+        interval = (interval * 2).min(READY_POLL_MAX);
+    }
+}
+
+/// Connect bound for [`service_status`]: a probe must stay cheap, and a
+/// service that cannot accept within it is unhealthy either way.
+const STATUS_PROBE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// What [`service_status`] found out about the property service endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ServiceStatus {
+    /// The socket [`crate::set`] connects to.
+    pub socket_path: PathBuf,
+    /// A socket file exists at `socket_path`.
+    pub socket_present: bool,
+    /// A connection to `socket_path` succeeded.
+    pub connectable: bool,
+    /// The wire protocol version (1 or 2) [`crate::set`] would speak; see
+    /// `PROPERTY_SERVICE_VERSION`.
+    pub protocol_version: u32,
+    /// How long the probe connection took, when it succeeded.
+    pub latency: Option<Duration>,
+    /// The service published [`SERVICE_READY_PROPERTY`] (always `true` on
+    /// Android, where init owns readiness).
+    pub ready: bool,
+    /// Why the socket is missing or the connection failed.
+    pub error: Option<String>,
+}
+
+impl ServiceStatus {
+    /// `true` when a [`crate::set`] can be expected to reach a service
+    /// that finished starting.
+    pub fn is_available(&self) -> bool {
+        self.connectable && self.ready
+    }
+}
+
+/// Probes the property service endpoint without setting anything, so an
+/// application can tell up front whether sets will work — and queue them
+/// or write a properties directory directly if not — instead of failing
+/// on the first [`crate::set`].
+///
+/// The probe is one connection, closed without sending a command (which
+/// the service treats as a no-op), bounded to 250ms. It never fails: every
+/// problem is reported in the returned [`ServiceStatus`].
+pub fn service_status() -> ServiceStatus {
+    let socket_path = get_property_service_socket(socket_dir());
+    let mut error = None;
+    let socket_present = match std::fs::symlink_metadata(&socket_path) {
+        Ok(metadata) => {
+            use std::os::unix::fs::FileTypeExt;
+            let is_socket = metadata.file_type().is_socket();
+            if !is_socket {
+                error = Some(format!("{socket_path:?} is not a socket"));
+            }
+            is_socket
+        }
+        Err(e) => {
+            error = Some(format!("{socket_path:?}: {e}"));
+            false
+        }
+    };
+
+    let started = Instant::now();
+    let (connectable, latency) = match connect_with_timeout(&socket_path, STATUS_PROBE_TIMEOUT) {
+        Ok(_stream) => (true, Some(started.elapsed())),
+        Err(e) => {
+            error.get_or_insert_with(|| format!("connect to {socket_path:?}: {e}"));
+            (false, None)
+        }
+    };
+
+    let status = ServiceStatus {
+        socket_path,
+        socket_present,
+        connectable,
+        protocol_version: protocol_version() as u32,
+        latency,
+        ready: connectable && service_ready_published(),
+        error,
+    };
+    log::debug!("Property service status: {status:?}");
+    status
+}
+
+#[cfg(target_os = "android")]
+fn service_ready_published() -> bool {
+    true
+}
+
+/// Reads [`SERVICE_READY_PROPERTY`] without going through
+/// `try_system_properties` while the areas may not exist yet — that would
+/// latch the "not found" error for the rest of the process. (A memfd store
+/// has no areas to look for; an unreachable service is not latched.)
+#[cfg(not(target_os = "android"))]
+fn service_ready_published() -> bool {
+    let ready = |props: &crate::SystemProperties| {
+        props
+            .get_with_result(SERVICE_READY_PROPERTY)
+            .is_ok_and(|v| v == "1")
+    };
+    match crate::system_properties_if_initialized() {
+        Some(props) => ready(props),
+        None if crate::backing() == crate::Backing::Memfd => {
+            crate::try_system_properties().is_ok_and(ready)
+        }
+        None => crate::SystemProperties::new(crate::properties_dir(), crate::layout())
+            .is_ok_and(|props| ready(&props)),
+    }
+}
+
 #[cfg(all(test, not(target_os = "android")))]
 mod tests {
     use super::*;
@@ -761,6 +1206,34 @@ mod tests {
         let _ = feeder.join();
     }
 
+    /// The reason after an error code is optional: a trailer is read
+    /// when present, and a bare code (AOSP init) is not an error.
+    #[test]
+    fn test_recv_error_message() {
+        let (client, mut server) = UnixStream::pair().unwrap();
+        let frame = crate::wire::encode_error_response(
+            crate::wire::PROP_ERROR_INVALID_VALUE,
+            "value too long",
+        );
+        server.write_all(&frame).unwrap();
+        drop(server);
+        let mut conn = ServiceConnection { stream: client };
+        assert_eq!(
+            conn.recv_i32().unwrap(),
+            crate::wire::PROP_ERROR_INVALID_VALUE
+        );
+        assert_eq!(conn.recv_error_message().as_deref(), Some("value too long"));
+
+        let (client, mut server) = UnixStream::pair().unwrap();
+        server
+            .write_all(&crate::wire::PROP_ERROR.to_ne_bytes())
+            .unwrap();
+        drop(server);
+        let mut conn = ServiceConnection { stream: client };
+        assert_eq!(conn.recv_i32().unwrap(), crate::wire::PROP_ERROR);
+        assert_eq!(conn.recv_error_message(), None);
+    }
+
     /// A server that closes before a full 4-byte status is a protocol
     /// error, reported as EOF — not a hang, not a success.
     #[test]
@@ -0,0 +1,458 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Ready-made property environments for integration tests (feature
+//! `test-utils`).
+//!
+//! A [`TestEnv`] is a private temp directory holding a properties
+//! directory (trie plus populated areas) and a socket directory with a
+//! minimal in-process property service, so `rsproperties::set`/`get`
+//! work end to end without `rsproperties-service`:
+//!
+//! ```rust,no_run
+//! # fn main() -> rsproperties::Result<()> {
+//! use rsproperties::test_support::TestEnv;
+//!
+//! let env = TestEnv::builder()
+//!     .context("my_app. u:object_r:my_app_prop:s0 prefix string")
+//!     .property("my_app.mode", "fast")
+//!     .build()?;
+//! env.install()?;
+//!
+//! rsproperties::set("my_app.mode", "slow")?;
+//! assert_eq!(rsproperties::get::<String>("my_app.mode")?, "slow");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every environment lives in its own directory under
+//! [`std::env::temp_dir`], named after the process id, so parallel test
+//! binaries and CI shards never share areas or sockets. Child processes
+//! started with [`TestEnv::command`] find the service through
+//! [`crate::SOCKET_DIR_ENV`].
+//!
+//! The global configuration latches once per process, so only one
+//! environment can be [`TestEnv::install`]ed per test binary; tests that
+//! share a binary use [`TestEnv::shared`]. The service speaks both wire
+//! protocols with the same validation and status codes as
+//! `rsproperties-service`, but handles one connection at a time — it is
+//! a fixture, not a substitute for the real service.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::errors::*;
+pub use crate::exit_cleanup::remove_at_exit;
+use crate::wire::*;
+use crate::{
+    build_trie, load_properties_from_file, lock_order, Layout, PropertyConfig, PropertyInfoEntry,
+    SystemProperties,
+};
+
+const DEFAULT_CONTEXT: &str = "u:object_r:default_prop:s0";
+const DEFAULT_TYPE: &str = "string";
+/// Per-connection read timeout, so a stalled client cannot wedge the
+/// single-threaded accept loop.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Options for a [`TestEnv`]; see [`TestEnv::builder`].
+#[derive(Debug, Clone)]
+pub struct TestEnvBuilder {
+    contexts: Vec<String>,
+    contexts_files: Vec<PathBuf>,
+    build_prop_files: Vec<PathBuf>,
+    properties: Vec<(String, String)>,
+    read_only_prefixes: Option<Vec<String>>,
+    service: bool,
+}
+
+impl Default for TestEnvBuilder {
+    fn default() -> Self {
+        Self {
+            contexts: Vec::new(),
+            contexts_files: Vec::new(),
+            build_prop_files: Vec::new(),
+            properties: Vec::new(),
+            read_only_prefixes: None,
+            service: true,
+        }
+    }
+}
+
+impl TestEnvBuilder {
+    /// Adds one `property_contexts` line, e.g.
+    /// `"vendor.blob. u:object_r:vendor_prop:s0 prefix bytes"`. Names no
+    /// line matches fall back to `u:object_r:default_prop:s0` / `string`.
+    pub fn context(mut self, line: impl Into<String>) -> Self {
+        self.contexts.push(line.into());
+        self
+    }
+
+    /// Adds a `property_contexts` file.
+    pub fn contexts_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.contexts_files.push(path.into());
+        self
+    }
+
+    /// Loads a `build.prop` file into the areas, like the service does at
+    /// startup. Later files win key conflicts.
+    pub fn build_prop(mut self, path: impl Into<PathBuf>) -> Self {
+        self.build_prop_files.push(path.into());
+        self
+    }
+
+    /// Adds an initial property; applied after the `build.prop` files.
+    pub fn property(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.push((name.into(), value.into()));
+        self
+    }
+
+    /// Makes the writer (and so the service) keep properties under
+    /// `prefixes` write-once, see
+    /// [`SystemProperties::set_read_only_prefixes`]. Defaults to
+    /// [`crate::read_only_prefixes`].
+    pub fn read_only_prefixes<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.read_only_prefixes = Some(prefixes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Skips the property service: the socket directory stays empty, and
+    /// writes go through [`TestEnv::set`] only.
+    pub fn without_service(mut self) -> Self {
+        self.service = false;
+        self
+    }
+
+    pub fn build(self) -> Result<TestEnv> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "rsprops_testenv_{}_{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        let properties_dir = root.join("properties");
+        let socket_dir = root.join("sockets");
+        std::fs::create_dir_all(&properties_dir)?;
+        std::fs::create_dir_all(&socket_dir)?;
+
+        let mut entries = Vec::new();
+        if !self.contexts.is_empty() {
+            let path = root.join("property_contexts");
+            File::create(&path)?.write_all(self.contexts.join("\n").as_bytes())?;
+            entries.append(&mut parse_contexts(&path)?);
+        }
+        for path in &self.contexts_files {
+            entries.append(&mut parse_contexts(path)?);
+        }
+        let trie = build_trie(&entries, DEFAULT_CONTEXT, DEFAULT_TYPE)?;
+        File::create(Layout::default().property_info_path(&properties_dir))?.write_all(&trie)?;
+
+        let mut loaded = HashMap::new();
+        for path in &self.build_prop_files {
+            load_properties_from_file(path, None, "u:r:init:s0", &mut loaded)?;
+        }
+        // Sorted, so the area layout does not depend on HashMap order.
+        let mut properties: BTreeMap<String, String> = loaded.into_iter().collect();
+        properties.extend(self.properties);
+
+        let mut writer = SystemProperties::new_area(&properties_dir)?;
+        if let Some(prefixes) = self.read_only_prefixes {
+            writer.set_read_only_prefixes(prefixes);
+        }
+        for (name, value) in &properties {
+            writer.set(name, value)?;
+        }
+        let writer = Arc::new(Mutex::new(writer));
+
+        let service = if self.service {
+            let service = ServiceThread::spawn(&socket_dir, writer.clone())?;
+            lock(&writer).set(crate::SERVICE_READY_PROPERTY, "1")?;
+            Some(service)
+        } else {
+            None
+        };
+
+        Ok(TestEnv {
+            root,
+            properties_dir,
+            socket_dir,
+            writer,
+            service,
+        })
+    }
+}
+
+fn parse_contexts(path: &Path) -> Result<Vec<PropertyInfoEntry>> {
+    let (entries, errors) = PropertyInfoEntry::parse_from_file(path, false)?;
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(entries),
+    }
+}
+
+fn lock(
+    writer: &Mutex<SystemProperties>,
+) -> lock_order::Tracked<std::sync::MutexGuard<'_, SystemProperties>> {
+    // A test that panicked mid-write must not poison every later test.
+    lock_order::lock("TestEnv::writer", writer)
+}
+
+/// A temp-dir property environment; removed on drop. See the module
+/// docs.
+pub struct TestEnv {
+    root: PathBuf,
+    properties_dir: PathBuf,
+    socket_dir: PathBuf,
+    writer: Arc<Mutex<SystemProperties>>,
+    service: Option<ServiceThread>,
+}
+
+impl std::fmt::Debug for TestEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestEnv")
+            .field("properties_dir", &self.properties_dir)
+            .field("socket_dir", &self.socket_dir)
+            .field("service", &self.service.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TestEnv {
+    /// An environment with the default context only and a running
+    /// service.
+    pub fn new() -> Result<Self> {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> TestEnvBuilder {
+        TestEnvBuilder::default()
+    }
+
+    /// The process-wide default environment, created and installed on
+    /// first use. Panics if either step fails — it is meant for tests.
+    pub fn shared() -> &'static TestEnv {
+        static SHARED: OnceLock<TestEnv> = OnceLock::new();
+        SHARED.get_or_init(|| {
+            let env = TestEnv::new().expect("failed to create the shared TestEnv");
+            // A static is never dropped, so `Drop` would leave the
+            // directory behind.
+            remove_at_exit(&env.root);
+            env.install()
+                .expect("failed to install the shared TestEnv (globals already initialized?)");
+            env
+        })
+    }
+
+    /// Points the global configuration (`rsproperties::get`/`set`, ...)
+    /// at this environment. Fails with [`Error::AlreadyInitialized`] if
+    /// the globals were already configured or used.
+    pub fn install(&self) -> Result<()> {
+        crate::try_init(PropertyConfig::with_both_dirs(
+            &self.properties_dir,
+            &self.socket_dir,
+        ))
+    }
+
+    pub fn properties_dir(&self) -> &Path {
+        &self.properties_dir
+    }
+
+    pub fn socket_dir(&self) -> &Path {
+        &self.socket_dir
+    }
+
+    /// A [`Command`] for `program` whose environment points it at this
+    /// environment's service ([`crate::SOCKET_DIR_ENV`]). The properties
+    /// directory has no environment override, so a child that reads
+    /// properties must be given [`Self::properties_dir`] explicitly (e.g.
+    /// `getprop --properties-dir`).
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new(program);
+        command.env(crate::SOCKET_DIR_ENV, &self.socket_dir);
+        command
+    }
+
+    /// Writes a property directly into the areas, bypassing the service
+    /// (and its `ro.` and name checks beyond the area's own).
+    pub fn set(&self, name: &str, value: &str) -> Result<()> {
+        lock(&self.writer).set(name, value)
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        // Stop the service before the socket file goes away.
+        self.service.take();
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// The fixture's property service: a blocking accept loop on its own
+/// thread.
+struct ServiceThread {
+    stop: Arc<AtomicBool>,
+    socket_path: PathBuf,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ServiceThread {
+    fn spawn(socket_dir: &Path, writer: Arc<Mutex<SystemProperties>>) -> Result<Self> {
+        let socket_path = socket_dir.join(crate::PROPERTY_SERVICE_SOCKET_NAME);
+        let listener = UnixListener::bind(&socket_path)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::Builder::new()
+            .name("rsprops-test-service".into())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    for stream in listener.incoming() {
+                        if stop.load(Ordering::Acquire) {
+                            break;
+                        }
+                        match stream {
+                            Ok(stream) => {
+                                if let Err(e) = serve(stream, &writer) {
+                                    log::debug!("test service: connection failed: {e}");
+                                }
+                            }
+                            Err(e) => log::warn!("test service: accept failed: {e}"),
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            stop,
+            socket_path,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for ServiceThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Unblock `accept`; the loop sees the flag on this connection.
+        let _ = UnixStream::connect(&self.socket_path);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn read_u32(stream: &mut UnixStream) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf)?;
+    Ok(u32::from_ne_bytes(buf))
+}
+
+fn read_string(stream: &mut UnixStream, len: usize) -> std::io::Result<Option<String>> {
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf)?;
+    Ok(String::from_utf8(buf).ok().filter(|s| !s.contains('\0')))
+}
+
+fn fixed_string(buf: &[u8]) -> Option<String> {
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..end].to_vec()).ok()
+}
+
+/// One request, mirroring `rsproperties-service`: V2 answers with a
+/// status (plus a reason on error), V1 gets no reply.
+fn serve(mut stream: UnixStream, writer: &Mutex<SystemProperties>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    let cmd = match read_u32(&mut stream) {
+        Ok(cmd) => cmd,
+        // Probes (and the shutdown wake-up) connect and close.
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    match cmd {
+        PROP_MSG_SETPROP2 => {
+            let name_len = read_u32(&mut stream)? as usize;
+            if name_len > MAX_WIRE_NAME_LEN {
+                return reply(
+                    &mut stream,
+                    Err((PROP_ERROR_INVALID_NAME, "name too long".into())),
+                );
+            }
+            let name = read_string(&mut stream, name_len)?;
+            let value_len = read_u32(&mut stream)? as usize;
+            if value_len > MAX_WIRE_VALUE_LEN {
+                return reply(
+                    &mut stream,
+                    Err((PROP_ERROR_INVALID_VALUE, "value too long".into())),
+                );
+            }
+            let value = read_string(&mut stream, value_len)?;
+            let result = match (name, value) {
+                (Some(name), Some(value)) => apply(writer, &name, &value),
+                _ => Err((PROP_ERROR_READ_DATA, "malformed string".into())),
+            };
+            reply(&mut stream, result)
+        }
+        PROP_MSG_SETPROP => {
+            let mut name = [0u8; PROP_NAME_MAX];
+            let mut value = [0u8; PROP_VALUE_MAX];
+            stream.read_exact(&mut name)?;
+            stream.read_exact(&mut value)?;
+            name[PROP_NAME_MAX - 1] = 0;
+            value[PROP_VALUE_MAX - 1] = 0;
+            if let (Some(name), Some(value)) = (fixed_string(&name), fixed_string(&value)) {
+                if let Err((_, e)) = apply(writer, &name, &value) {
+                    log::debug!("test service: V1 set of {name} rejected: {e}");
+                }
+            }
+            Ok(())
+        }
+        _ => reply(
+            &mut stream,
+            Err((
+                PROP_ERROR_INVALID_CMD,
+                format!("unknown command 0x{cmd:08X}"),
+            )),
+        ),
+    }
+}
+
+fn apply(
+    writer: &Mutex<SystemProperties>,
+    name: &str,
+    value: &str,
+) -> std::result::Result<(), (i32, String)> {
+    validate_property_name(name).map_err(|e| (PROP_ERROR_INVALID_NAME, e.to_string()))?;
+    let mut writer = lock(writer);
+    validate_value_len_with(name, value, writer.read_only_prefixes())
+        .map_err(|e| (PROP_ERROR_INVALID_VALUE, e.to_string()))?;
+    writer.set(name, value).map_err(|e| {
+        let code = match e {
+            Error::PermissionDenied(_) => PROP_ERROR_READ_ONLY_PROPERTY,
+            Error::InvalidArgument(_) => PROP_ERROR_INVALID_VALUE,
+            _ => PROP_ERROR_SET_FAILED,
+        };
+        (code, e.to_string())
+    })
+}
+
+fn reply(
+    stream: &mut UnixStream,
+    result: std::result::Result<(), (i32, String)>,
+) -> std::io::Result<()> {
+    match result {
+        Ok(()) => stream.write_all(&PROP_SUCCESS.to_ne_bytes()),
+        Err((code, message)) => stream.write_all(&encode_error_response(code, &message)),
+    }
+}
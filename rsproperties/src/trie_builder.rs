@@ -3,11 +3,61 @@
 
 use log::error;
 use std::collections::{BTreeSet, HashMap, HashSet};
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasherDefault, Hash, Hasher};
 use std::rc::Rc;
 
 use crate::errors::*;
 
+/// FxHash, the multiply-rotate hasher rustc uses for its own tables. The
+/// builder's maps are keyed by short name segments and interned strings,
+/// for which SipHash's per-hash setup dominated `build_trie` profiles.
+/// Keys come from the build's own `property_contexts`, so SipHash's
+/// flooding resistance buys nothing here, and iteration order never
+/// reaches the output — the serializer sorts every list it writes.
+#[derive(Default, Clone, Copy)]
+pub(crate) struct FxHasher {
+    hash: u64,
+}
+
+impl FxHasher {
+    #[inline]
+    fn add(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for FxHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.add(u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        let rest = chunks.remainder();
+        if !rest.is_empty() {
+            let mut word = [0u8; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            self.add(u64::from_le_bytes(word));
+        }
+    }
+
+    #[inline]
+    fn write_u8(&mut self, i: u8) {
+        self.add(u64::from(i));
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+pub(crate) type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
 #[derive(Debug)]
 pub(crate) struct PropertyEntryBuilder {
     // `Rc<str>` over `Rc<String>`: one level of indirection less, and
@@ -37,9 +87,9 @@ impl Hash for PropertyEntryBuilder {
 
 pub(crate) struct TrieBuilderNode {
     pub(crate) property_entry: PropertyEntryBuilder,
-    pub(crate) prefixes: HashSet<PropertyEntryBuilder>,
-    pub(crate) exact_matches: HashSet<PropertyEntryBuilder>,
-    pub(crate) children: HashMap<Rc<str>, TrieBuilderNode>,
+    pub(crate) prefixes: HashSet<PropertyEntryBuilder, FxBuildHasher>,
+    pub(crate) exact_matches: HashSet<PropertyEntryBuilder, FxBuildHasher>,
+    pub(crate) children: HashMap<Rc<str>, TrieBuilderNode, FxBuildHasher>,
 }
 
 impl TrieBuilderNode {
@@ -50,9 +100,9 @@ impl TrieBuilderNode {
                 context: None,
                 rtype: None,
             },
-            children: HashMap::new(),
-            prefixes: HashSet::new(),
-            exact_matches: HashSet::new(),
+            children: HashMap::default(),
+            prefixes: HashSet::default(),
+            exact_matches: HashSet::default(),
         }
     }
 
@@ -139,6 +189,18 @@ fn intern(set: &mut BTreeSet<Rc<str>>, s: &str) -> Rc<str> {
     }
 }
 
+/// [`intern`] for the builder's hashed segment set.
+fn intern_segment(set: &mut HashSet<Rc<str>, FxBuildHasher>, s: &str) -> Rc<str> {
+    match set.get(s) {
+        Some(existing) => Rc::clone(existing),
+        None => {
+            let rc: Rc<str> = Rc::from(s);
+            set.insert(Rc::clone(&rc));
+            rc
+        }
+    }
+}
+
 /// Upper bound on dot-separated segments per property name. Each segment
 /// becomes one trie level, and both `TrieSerializer::write_trie_node` and
 /// the `TrieBuilderNode` drop glue recurse per level — an unbounded input
@@ -150,6 +212,15 @@ pub(crate) struct TrieBuilder {
     pub(crate) root: TrieBuilderNode,
     pub(crate) contexts: BTreeSet<Rc<str>>,
     pub(crate) types: BTreeSet<Rc<str>>,
+    /// Every name segment seen so far; see `add_to_trie`.
+    segments: HashSet<Rc<str>, FxBuildHasher>,
+    /// Trie nodes, root included.
+    pub(crate) nodes: usize,
+    /// Prefix and exact-match entries stored in node lists.
+    pub(crate) entries: usize,
+    /// Bytes of node and entry names, NUL terminators included — with
+    /// the counts above, what `TrieSerializer` sizes its arena from.
+    pub(crate) name_bytes: usize,
 }
 
 impl TrieBuilder {
@@ -171,6 +242,10 @@ impl TrieBuilder {
             root,
             contexts,
             types,
+            segments: HashSet::default(),
+            nodes: 1,
+            entries: 0,
+            name_bytes: "root".len() + 1,
         }
     }
 
@@ -240,34 +315,41 @@ impl TrieBuilder {
         let mut current_node = &mut self.root;
 
         for part in name_parts {
-            // `Rc<str>: Borrow<str>` lets the existence check use the
-            // borrowed segment directly — interior segments allocate only
-            // the first time they are seen.
-            if !current_node.children.contains_key(part) {
-                let key: Rc<str> = Rc::from(part);
-                current_node
-                    .children
-                    .insert(Rc::clone(&key), TrieBuilderNode::new(key));
-            }
-            current_node = current_node
-                .children
-                .get_mut(part)
-                .expect("child inserted above");
+            // Segments are interned builder-wide (a small, cache-hot set),
+            // so the child map is probed once with a ready `Rc` key instead
+            // of a `contains_key` plus a re-fetch — on large context sets
+            // the walk is bound by those cache-missing probes. Each segment
+            // string is also allocated once, not once per parent.
+            let key = intern_segment(&mut self.segments, part);
+            current_node = current_node.children.entry(key).or_insert_with_key(|key| {
+                self.nodes += 1;
+                self.name_bytes += key.len() + 1;
+                TrieBuilderNode::new(Rc::clone(key))
+            });
         }
 
-        let last_name: Rc<str> = Rc::from(last_name);
+        let last_name = intern_segment(&mut self.segments, last_name);
 
         // The three branches are mutually exclusive, so each can consume
         // `context`/`rtype` directly — no refcount traffic needed.
+        let last_name_bytes = last_name.len() + 1;
         if exact {
             current_node.add_exact_match_context(last_name, context, rtype, name)?;
+            self.entries += 1;
+            self.name_bytes += last_name_bytes;
         } else if !ends_with_dot {
             current_node.add_prefix_context(last_name, context, rtype, name)?;
+            self.entries += 1;
+            self.name_bytes += last_name_bytes;
         } else {
             let child = current_node
                 .children
                 .entry(Rc::clone(&last_name))
-                .or_insert_with(|| TrieBuilderNode::new(last_name));
+                .or_insert_with(|| {
+                    self.nodes += 1;
+                    self.name_bytes += last_name_bytes;
+                    TrieBuilderNode::new(last_name)
+                });
 
             if child.context().is_some() || child.rtype().is_some() {
                 error!("Duplicate prefix match detected for '{name}'");
@@ -1,12 +1,14 @@
 // Copyright 2024 Jeff Kim <hiking90@gmail.com>
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
 use std::mem;
+use std::rc::Rc;
 
 use zerocopy::{FromBytes, IntoBytes};
 
 use crate::errors::{Error, Result};
-use crate::property_info_parser::*;
+use crate::trie_builder::FxBuildHasher;
 
 #[derive(Debug)]
 pub(crate) struct TrieNodeArena {
@@ -20,6 +22,11 @@ pub(crate) struct TrieNodeArena {
     /// in **bytes**; byte views are derived via zerocopy (`as_bytes`).
     data: Vec<u32>,
     current_data_pointer: usize,
+    /// Offset of every string written through [`Self::intern_string`].
+    /// Node names repeat across subtrees (`enabled`, `version`, ...) and
+    /// readers only ever follow a `name_offset`, so identical strings can
+    /// share one copy — smaller files, and fewer bytes to write.
+    strings: HashMap<Rc<str>, u32, FxBuildHasher>,
 }
 
 /// Compile-time guard for arena-stored types: alignment must not exceed
@@ -38,10 +45,14 @@ impl<T> AlignFitsArena<T> {
 }
 
 impl TrieNodeArena {
-    pub(crate) fn new() -> Self {
+    /// An arena that can grow to `bytes` without reallocating. The
+    /// serializer passes an estimate from the builder's counts, so a
+    /// large trie is not built through a chain of doubling copies.
+    pub(crate) fn with_capacity(bytes: usize) -> Self {
         Self {
-            data: Vec::with_capacity(16 * 1024 / mem::size_of::<u32>()),
+            data: Vec::with_capacity(bytes.div_ceil(mem::size_of::<u32>())),
             current_data_pointer: 0,
+            strings: HashMap::default(),
         }
     }
 
@@ -151,6 +162,22 @@ impl TrieNodeArena {
         Ok(offset)
     }
 
+    /// Like [`Self::allocate_and_write_string`], but returns the offset of
+    /// an earlier copy of `string` when there is one.
+    pub(crate) fn intern_string(&mut self, string: &Rc<str>) -> Result<u32> {
+        if let Some(&offset) = self.strings.get(string) {
+            return Ok(offset);
+        }
+        let offset = self.allocate_and_write_string(string)?;
+        self.strings.insert(Rc::clone(string), offset);
+        Ok(offset)
+    }
+
+    /// Number of distinct strings written through [`Self::intern_string`].
+    pub(crate) fn interned_strings(&self) -> usize {
+        self.strings.len()
+    }
+
     pub(crate) fn allocate_and_write_uint32(&mut self, value: u32) -> Result<()> {
         let offset = self.allocate_data(mem::size_of::<u32>())? as usize;
         // A plain byte copy needs no alignment at all, so the previous
@@ -196,9 +223,17 @@ impl TrieNodeArena {
         let byte_len = self.data.len() * mem::size_of::<u32>();
         if needed > byte_len {
             // Standard doubling growth, but never less than what this
-            // allocation needs. `aligned_size` is a multiple of 4, so
-            // `needed` always divides into whole u32 words.
-            let target_bytes = needed.max(byte_len.saturating_mul(2));
+            // allocation needs, and never past the reserved capacity
+            // while the allocation still fits in it — doubling across
+            // that line would reallocate a buffer that had room.
+            // `aligned_size` is a multiple of 4, so `needed` always
+            // divides into whole u32 words.
+            let capacity_bytes = self.data.capacity() * mem::size_of::<u32>();
+            let mut doubled = byte_len.saturating_mul(2);
+            if needed <= capacity_bytes {
+                doubled = doubled.min(capacity_bytes);
+            }
+            let target_bytes = needed.max(doubled);
             self.data
                 .resize(target_bytes.div_ceil(mem::size_of::<u32>()), 0);
         }
@@ -214,15 +249,6 @@ impl TrieNodeArena {
         self.current_data_pointer
     }
 
-    /// Parser view of the arena. Sliced to the allocated extent — handing
-    /// out the growth slack too would bypass the `current_data_pointer`
-    /// bounds discipline the checked accessors above enforce: the parser's
-    /// own bounds checks run against `data_base.len()`, so a miscomputed
-    /// offset into zero-filled slack would read 0 instead of erroring.
-    pub(crate) fn info(&self) -> PropertyInfoArea<'_> {
-        PropertyInfoArea::new(&self.bytes()[..self.current_data_pointer])
-    }
-
     /// Consumes the arena into the serialized byte image. One copy — the
     /// price of the `u32` backing that guarantees alignment during the
     /// build; callers receive a plain `Vec<u8>` as before.
@@ -239,7 +265,7 @@ mod arena_tests {
     /// bounds checks (which run against the allocated extent, not the
     /// resize slack) behave as in production.
     fn arena_with(size: usize) -> TrieNodeArena {
-        let mut arena = TrieNodeArena::new();
+        let mut arena = TrieNodeArena::with_capacity(0);
         arena.allocate_data(size).unwrap();
         arena
     }
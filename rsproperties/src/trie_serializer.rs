@@ -1,7 +1,8 @@
 // Copyright 2024 Jeff Kim <hiking90@gmail.com>
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
+use std::mem::size_of;
 use std::rc::Rc;
 
 use crate::errors::{Error, Result};
@@ -11,6 +12,30 @@ use crate::trie_node_arena::TrieNodeArena;
 
 pub(crate) struct TrieSerializer {
     arena: TrieNodeArena,
+    /// String-table index of every context and type, filled in by
+    /// `serialize_strings`. Entries resolve their indexes here instead of
+    /// binary-searching the serialized tables once per entry.
+    context_indexes: HashMap<Rc<str>, usize, FxBuildHasher>,
+    type_indexes: HashMap<Rc<str>, usize, FxBuildHasher>,
+    nodes: usize,
+}
+
+/// Upper estimate of the serialized size, so the arena is allocated once.
+fn estimated_size(trie_builder: &TrieBuilder) -> usize {
+    // Every string is NUL-terminated and padded to 4 bytes, and each table
+    // has a count word and one offset word per string.
+    let table =
+        |strings: &BTreeSet<Rc<str>>| strings.iter().map(|s| s.len() + 4 + 4).sum::<usize>() + 4;
+    size_of::<PropertyInfoAreaHeader>()
+        + table(&trie_builder.contexts)
+        + table(&trie_builder.types)
+        // Node record, its own entry, and its slot in the parent's child
+        // array.
+        + trie_builder.nodes * (size_of::<TrieNodeData>() + size_of::<PropertyEntry>() + 4)
+        // Entry record and its slot in the node's prefix/exact array.
+        + trie_builder.entries * (size_of::<PropertyEntry>() + 4)
+        + trie_builder.name_bytes
+        + 3 * (trie_builder.nodes + trie_builder.entries)
 }
 
 /// Resolves an optional context/type name to a u32 index. Absent or empty
@@ -47,7 +72,10 @@ where
 impl TrieSerializer {
     pub(crate) fn new(trie_builder: &TrieBuilder) -> Result<Self> {
         let mut this = Self {
-            arena: TrieNodeArena::new(),
+            arena: TrieNodeArena::with_capacity(estimated_size(trie_builder)),
+            context_indexes: HashMap::default(),
+            type_indexes: HashMap::default(),
+            nodes: 0,
         };
 
         let header_offset = this.arena.allocate_object::<PropertyInfoAreaHeader>()? as usize;
@@ -66,19 +94,19 @@ impl TrieSerializer {
         this.arena
             .get_object::<PropertyInfoAreaHeader>(header_offset)?
             .contexts_offset = this.arena.size() as u32;
-        this.serialize_strings(&trie_builder.contexts)?;
+        this.context_indexes = this.serialize_strings(&trie_builder.contexts)?;
 
         this.arena
             .get_object::<PropertyInfoAreaHeader>(header_offset)?
             .types_offset = this.arena.size() as u32;
-        this.serialize_strings(&trie_builder.types)?;
+        this.type_indexes = this.serialize_strings(&trie_builder.types)?;
 
         // AOSP parity: upstream stamps an intermediate `size` here because
         // its Find*Offset helpers consult it during trie writing. This
-        // port's `find_context_index`/`find_type_index` never read
-        // `header.size`, and the value is unconditionally overwritten with
-        // the final size below — kept only to match the reference
-        // serializer's write sequence.
+        // port resolves indexes from `serialize_strings`' maps instead and
+        // never reads `header.size`, and the value is unconditionally
+        // overwritten with the final size below — kept only to match the
+        // reference serializer's write sequence.
         this.arena
             .get_object::<PropertyInfoAreaHeader>(header_offset)?
             .size = this.arena.size() as u32;
@@ -98,14 +126,14 @@ impl TrieSerializer {
 
     fn write_property_entry(&mut self, property_entry: &PropertyEntryBuilder) -> Result<u32> {
         let context_index = resolve_index(property_entry.context.as_deref(), |s| {
-            self.arena.info().find_context_index(s)
+            self.context_indexes.get(s).copied()
         })?;
         let type_index = resolve_index(property_entry.rtype.as_deref(), |s| {
-            self.arena.info().find_type_index(s)
+            self.type_indexes.get(s).copied()
         })?;
 
         let entry_offset = self.arena.allocate_object::<PropertyEntry>()?;
-        let name_offset = self.arena.allocate_and_write_string(&property_entry.name)?;
+        let name_offset = self.arena.intern_string(&property_entry.name)?;
         let namelen = u32::try_from(property_entry.name.len()).map_err(|_| {
             Error::FileValidation(format!(
                 "Property name too long: {} bytes",
@@ -135,6 +163,7 @@ impl TrieSerializer {
             )));
         }
         let trie_offset = self.arena.allocate_object::<TrieNodeData>()? as usize;
+        self.nodes += 1;
 
         let property_entry = self.write_property_entry(&builder_node.property_entry)?;
         self.arena
@@ -151,7 +180,7 @@ impl TrieSerializer {
         // two distinct equal-length prefixes can never both match one
         // name.
         let mut sorted_prefix_matches: Vec<_> = builder_node.prefixes.iter().collect();
-        sorted_prefix_matches.sort_by(|a, b| {
+        sorted_prefix_matches.sort_unstable_by(|a, b| {
             b.name
                 .len()
                 .cmp(&a.name.len())
@@ -182,9 +211,11 @@ impl TrieSerializer {
             .uint32_array(prefix_entries_array_offset as usize, prefix_offsets.len())?
             .copy_from_slice(&prefix_offsets);
 
-        // Sort exact matches alphabetically
+        // Sort exact matches alphabetically. Names are unique within a
+        // set, so an unstable sort gives the same order without the
+        // stable sort's scratch allocation.
         let mut sorted_exact_matches: Vec<_> = builder_node.exact_matches.iter().collect();
-        sorted_exact_matches.sort_by(|a, b| a.name.cmp(&b.name));
+        sorted_exact_matches.sort_unstable_by(|a, b| a.name.cmp(&b.name));
 
         self.arena
             .get_object::<TrieNodeData>(trie_offset)?
@@ -209,7 +240,7 @@ impl TrieSerializer {
 
         // Sort children alphabetically
         let mut sorted_children: Vec<_> = builder_node.children.values().collect();
-        sorted_children.sort_by(|a, b| a.property_entry.name.cmp(&b.property_entry.name));
+        sorted_children.sort_unstable_by(|a, b| a.property_entry.name.cmp(&b.property_entry.name));
 
         self.arena
             .get_object::<TrieNodeData>(trie_offset)?
@@ -232,20 +263,40 @@ impl TrieSerializer {
         Ok(trie_offset as u32)
     }
 
-    fn serialize_strings(&mut self, strings: &BTreeSet<Rc<str>>) -> Result<()> {
+    /// Writes one sorted string table and returns each string's index in
+    /// it — the `BTreeSet` iteration order is the table order readers
+    /// binary-search.
+    fn serialize_strings(
+        &mut self,
+        strings: &BTreeSet<Rc<str>>,
+    ) -> Result<HashMap<Rc<str>, usize, FxBuildHasher>> {
         self.arena.allocate_and_write_uint32(strings.len() as u32)?;
         let n = strings.len();
         let offset_array_offset = self.arena.allocate_uint32_array(n)?;
 
         let offsets = strings
             .iter()
-            .map(|s| self.arena.allocate_and_write_string(s))
+            .map(|s| self.arena.intern_string(s))
             .collect::<Result<Vec<u32>>>()?;
         self.arena
             .uint32_array(offset_array_offset as usize, n)?
             .copy_from_slice(&offsets);
 
-        Ok(())
+        Ok(strings
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (Rc::clone(s), i))
+            .collect())
+    }
+
+    /// Trie nodes written.
+    pub(crate) fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// Distinct strings in the serialized image.
+    pub(crate) fn strings(&self) -> usize {
+        self.arena.interned_strings()
     }
 
     pub(crate) fn into_data(self) -> Vec<u8> {
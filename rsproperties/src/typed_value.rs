@@ -0,0 +1,255 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Value formats that `FromStr` on std types does not cover: durations
+//! with a unit suffix (`30s`), byte sizes with a binary multiplier
+//! (`512m`, as in `dalvik.vm.heapsize`) and `host:port` addresses.
+//!
+//! Each format is a `FromStr` newtype, so it works with [`crate::get`],
+//! [`crate::get_or`] and [`crate::ConfigBinder::bind_parsed`] like any
+//! other value type; [`crate::get_duration`], [`crate::get_size`] and
+//! [`crate::get_addr`] are shorthands.
+
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Why a value did not parse as one of this module's formats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseValueError(String);
+
+impl fmt::Display for ParseValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseValueError {}
+
+/// Splits `"1500ms"` into `(1500, "ms")`. The number is a plain decimal
+/// integer: no sign, no fraction, no exponent.
+fn split_number(s: &str) -> Result<(u64, &str), ParseValueError> {
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 {
+        return Err(ParseValueError(format!("expected a number: {s:?}")));
+    }
+    let number = s[..digits]
+        .parse()
+        .map_err(|_| ParseValueError(format!("number out of range: {s:?}")))?;
+    Ok((number, &s[digits..]))
+}
+
+/// A duration written as an integer with a unit: `ns`, `us`, `ms`, `s`,
+/// `m`, `h` or `d` (`"1500ms"`, `"30s"`, `"5m"`). A bare integer is
+/// seconds, the unit most timeout properties use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PropertyDuration(pub Duration);
+
+impl FromStr for PropertyDuration {
+    type Err = ParseValueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (n, unit) = split_number(s.trim())?;
+        let secs = |multiplier: u64| {
+            n.checked_mul(multiplier)
+                .map(Duration::from_secs)
+                .ok_or_else(|| ParseValueError(format!("duration out of range: {s:?}")))
+        };
+        let duration = match unit {
+            "ns" => Duration::from_nanos(n),
+            "us" => Duration::from_micros(n),
+            "ms" => Duration::from_millis(n),
+            "" | "s" => Duration::from_secs(n),
+            "m" => secs(60)?,
+            "h" => secs(60 * 60)?,
+            "d" => secs(24 * 60 * 60)?,
+            _ => {
+                return Err(ParseValueError(format!(
+                    "unknown duration unit {unit:?} (expected ns, us, ms, s, m, h or d)"
+                )))
+            }
+        };
+        Ok(Self(duration))
+    }
+}
+
+/// A byte count with an optional binary multiplier, case-insensitive:
+/// `k`, `m`, `g`, `t`, optionally followed by `b` or `ib` (`"512m"`,
+/// `"2G"`, `"64KiB"`). Binary (1024) rather than decimal multiples, as in
+/// `dalvik.vm.heapsize` and the JVM options it mirrors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = ParseValueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (n, unit) = split_number(s.trim())?;
+        let unit = unit.to_ascii_lowercase();
+        let unit = unit
+            .strip_suffix("ib")
+            .or_else(|| unit.strip_suffix('b'))
+            .unwrap_or(&unit);
+        let shift = match unit {
+            "" => 0,
+            "k" => 10,
+            "m" => 20,
+            "g" => 30,
+            "t" => 40,
+            _ => {
+                return Err(ParseValueError(format!(
+                    "unknown size unit in {s:?} (expected k, m, g or t)"
+                )))
+            }
+        };
+        n.checked_mul(1 << shift)
+            .map(Self)
+            .ok_or_else(|| ParseValueError(format!("size out of range: {s:?}")))
+    }
+}
+
+/// A `host:port` pair; the host may be a name, an IPv4 address or a
+/// bracketed IPv6 address (`"[::1]:8080"`). Parsing never resolves the
+/// name — use [`Self::socket_addr`] for literal addresses or
+/// [`ToSocketAddrs`] (which may block on DNS) for names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HostPort {
+    /// The host, without IPv6 brackets.
+    pub host: String,
+    pub port: u16,
+}
+
+impl HostPort {
+    /// The address if the host is an IP literal, without name resolution.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        let ip = self.host.parse().ok()?;
+        Some(SocketAddr::new(ip, self.port))
+    }
+}
+
+impl FromStr for HostPort {
+    type Err = ParseValueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let malformed = || ParseValueError(format!("expected host:port: {s:?}"));
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, port) = rest.split_once("]:").ok_or_else(malformed)?;
+            if host.parse::<std::net::Ipv6Addr>().is_err() {
+                return Err(ParseValueError(format!(
+                    "invalid IPv6 address in brackets: {s:?}"
+                )));
+            }
+            (host, port)
+        } else {
+            let (host, port) = s.rsplit_once(':').ok_or_else(malformed)?;
+            // An unbracketed IPv6 address would be ambiguous with the port.
+            if host.contains(':') {
+                return Err(ParseValueError(format!(
+                    "IPv6 hosts must be bracketed: {s:?}"
+                )));
+            }
+            (host, port)
+        };
+        if host.is_empty() || host.chars().any(|c| c.is_whitespace() || c == '/') {
+            return Err(malformed());
+        }
+        let port = port
+            .parse()
+            .map_err(|_| ParseValueError(format!("invalid port in {s:?}")))?;
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for HostPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl ToSocketAddrs for HostPort {
+    type Iter = std::vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
+        (self.host.as_str(), self.port).to_socket_addrs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration() {
+        let parse = |s: &str| s.parse::<PropertyDuration>().map(|d| d.0);
+        assert_eq!(parse("1500ms"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse(" 2h "), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse("250us"), Ok(Duration::from_micros(250)));
+        for bad in [
+            "",
+            "s",
+            "-1s",
+            "1.5s",
+            "10 s",
+            "3w",
+            "99999999999999999999",
+            "18446744073709551615d",
+        ] {
+            assert!(parse(bad).is_err(), "{bad:?} must be rejected");
+        }
+    }
+
+    #[test]
+    fn test_size() {
+        let parse = |s: &str| s.parse::<ByteSize>().map(|b| b.0);
+        assert_eq!(parse("4096"), Ok(4096));
+        assert_eq!(parse("512m"), Ok(512 << 20));
+        assert_eq!(parse("2G"), Ok(2 << 30));
+        assert_eq!(parse("64KiB"), Ok(64 << 10));
+        assert_eq!(parse("1tb"), Ok(1 << 40));
+        assert_eq!(parse("7b"), Ok(7));
+        for bad in ["", "m", "0.75", "512x", "16777216t", "1 m"] {
+            assert!(parse(bad).is_err(), "{bad:?} must be rejected");
+        }
+    }
+
+    #[test]
+    fn test_host_port() {
+        let hp: HostPort = "example.com:443".parse().unwrap();
+        assert_eq!((hp.host.as_str(), hp.port), ("example.com", 443));
+        assert_eq!(hp.socket_addr(), None);
+
+        let hp: HostPort = "10.0.0.1:80".parse().unwrap();
+        assert_eq!(hp.socket_addr(), Some("10.0.0.1:80".parse().unwrap()));
+
+        let hp: HostPort = "[::1]:8080".parse().unwrap();
+        assert_eq!(hp.host, "::1");
+        assert_eq!(hp.to_string(), "[::1]:8080");
+        assert_eq!(hp.socket_addr(), Some("[::1]:8080".parse().unwrap()));
+
+        for bad in [
+            "",
+            "host",
+            ":80",
+            "host:",
+            "host:65536",
+            "::1:80",
+            "[host]:80",
+            "a b:1",
+        ] {
+            assert!(bad.parse::<HostPort>().is_err(), "{bad:?} must be rejected");
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wake-latency statistics for [`crate::SystemProperties::wait`].
+//!
+//! Writers stamp the serial area with the (microsecond, wrapping)
+//! `CLOCK_MONOTONIC` time at which they start publishing a change; the
+//! clock is system-wide, so the stamp is meaningful to waiters in other
+//! processes. When recording is enabled, a wait that actually blocks and
+//! is woken by a change reads the stamp and adds `now - stamp` to a
+//! process-wide log2 histogram.
+//!
+//! A stamp older than the wait itself is skipped rather than recorded: it
+//! belongs to an earlier change, or the writer does not stamp at all
+//! (bionic `init` leaves the slot zeroed).
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Number of histogram buckets. Bucket 0 counts wakes under 1µs; bucket
+/// `i` counts `[2^(i-1), 2^i)` µs; the last bucket also absorbs anything
+/// longer (2^30µs is about 18 minutes).
+pub const WAIT_STATS_BUCKETS: usize = 32;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// `[AtomicU64::new(0); N]` needs a `const` item to repeat (inline `const`
+// blocks are newer than our MSRV).
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static BUCKETS: [AtomicU64; WAIT_STATS_BUCKETS] = [ZERO; WAIT_STATS_BUCKETS];
+static SUM_MICROS: AtomicU64 = AtomicU64::new(0);
+static MAX_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Current `CLOCK_MONOTONIC` time in microseconds, truncated to 32 bits.
+/// Wraps every ~71 minutes; only differences (`wrapping_sub`) are used.
+pub(crate) fn now_stamp() -> u32 {
+    let ts = rustix::time::clock_gettime(rustix::time::ClockId::Monotonic);
+    (ts.tv_sec as u64)
+        .wrapping_mul(1_000_000)
+        .wrapping_add(ts.tv_nsec as u64 / 1_000) as u32
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records one wake. `wait_start` is the stamp taken before blocking and
+/// `bump` the writer's stamp read after waking.
+pub(crate) fn record_wake(wait_start: u32, bump: u32) {
+    let now = now_stamp();
+    let since_bump = now.wrapping_sub(bump);
+    if since_bump > now.wrapping_sub(wait_start) {
+        return;
+    }
+    let micros = u64::from(since_bump);
+    BUCKETS[bucket_index(micros)].fetch_add(1, Ordering::Relaxed);
+    SUM_MICROS.fetch_add(micros, Ordering::Relaxed);
+    MAX_MICROS.fetch_max(micros, Ordering::Relaxed);
+}
+
+fn bucket_index(micros: u64) -> usize {
+    ((u64::BITS - micros.leading_zeros()) as usize).min(WAIT_STATS_BUCKETS - 1)
+}
+
+/// Turns wake-latency recording on or off for this process. Off by
+/// default: a recorded wait costs two clock reads and a few relaxed
+/// atomic adds. Writers stamp unconditionally, so enabling it in a waiter
+/// process needs no cooperation from the setter.
+pub fn enable_wait_stats(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Snapshot of the wake-latency histogram. Buckets are read one by one
+/// without a lock, so a snapshot taken under load may be off by the wakes
+/// recorded while it was being copied.
+pub fn wait_stats() -> WaitStats {
+    WaitStats {
+        buckets: std::array::from_fn(|i| BUCKETS[i].load(Ordering::Relaxed)),
+        sum_micros: SUM_MICROS.load(Ordering::Relaxed),
+        max_micros: MAX_MICROS.load(Ordering::Relaxed),
+    }
+}
+
+/// Clears the histogram (recording stays enabled or disabled as it was).
+pub fn reset_wait_stats() {
+    for bucket in &BUCKETS {
+        bucket.store(0, Ordering::Relaxed);
+    }
+    SUM_MICROS.store(0, Ordering::Relaxed);
+    MAX_MICROS.store(0, Ordering::Relaxed);
+}
+
+/// Wake latencies recorded by this process: the time from a writer
+/// starting to publish a change to a blocked waiter returning from the
+/// futex wait. See [`enable_wait_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WaitStats {
+    /// Per-bucket counts; see [`WAIT_STATS_BUCKETS`] for the bounds.
+    pub buckets: [u64; WAIT_STATS_BUCKETS],
+    pub sum_micros: u64,
+    pub max_micros: u64,
+}
+
+impl WaitStats {
+    /// Number of recorded wakes.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_micros(self.sum_micros / count))
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_micros(self.max_micros)
+    }
+
+    /// Exclusive upper bound of bucket `index`; the last bucket's bound is
+    /// nominal since it also holds everything longer.
+    pub fn bucket_bound(index: usize) -> Duration {
+        Duration::from_micros(1 << index.min(WAIT_STATS_BUCKETS - 1))
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile (`0.0..=1.0`),
+    /// e.g. `percentile(0.99)` for p99. Bucket resolution: the true value
+    /// is at most this and more than half of it. `None` if nothing was
+    /// recorded.
+    pub fn percentile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(Self::bucket_bound(i).min(self.max().max(Duration::from_micros(1))));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_index() {
+        assert_eq!(bucket_index(0), 0);
+        assert_eq!(bucket_index(1), 1);
+        assert_eq!(bucket_index(2), 2);
+        assert_eq!(bucket_index(3), 2);
+        assert_eq!(bucket_index(1023), 10);
+        assert_eq!(bucket_index(1024), 11);
+        assert_eq!(bucket_index(u64::MAX), WAIT_STATS_BUCKETS - 1);
+        for micros in [0, 1, 5, 700, 1 << 20] {
+            assert!(Duration::from_micros(micros) < WaitStats::bucket_bound(bucket_index(micros)));
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut stats = WaitStats {
+            buckets: [0; WAIT_STATS_BUCKETS],
+            sum_micros: 0,
+            max_micros: 0,
+        };
+        assert_eq!(stats.percentile(0.5), None);
+        assert_eq!(stats.mean(), None);
+
+        // 90 wakes around 10µs, 10 around 3ms.
+        stats.buckets[bucket_index(10)] = 90;
+        stats.buckets[bucket_index(3000)] = 10;
+        stats.sum_micros = 90 * 10 + 10 * 3000;
+        stats.max_micros = 3000;
+        assert_eq!(stats.count(), 100);
+        assert_eq!(stats.mean(), Some(Duration::from_micros(309)));
+        assert_eq!(stats.percentile(0.5), Some(Duration::from_micros(16)));
+        assert_eq!(stats.percentile(0.9), Some(Duration::from_micros(16)));
+        // Capped by the observed maximum rather than the bucket's 4096µs.
+        assert_eq!(stats.percentile(0.99), Some(Duration::from_millis(3)));
+    }
+
+    #[test]
+    fn test_stale_stamp_is_skipped() {
+        // The process-wide histogram is shared with other tests; only
+        // check that this wake did not land anywhere.
+        let before = wait_stats().count();
+        let start = now_stamp();
+        record_wake(start, start.wrapping_sub(1_000_000));
+        assert_eq!(wait_stats().count(), before);
+    }
+}
@@ -33,11 +33,60 @@ pub const PROP_MSG_SETPROP: u32 = 1;
 /// V2 SETPROP wire command id (length-prefixed name/value).
 pub const PROP_MSG_SETPROP2: u32 = 0x00020001;
 
+/// rsproperties extension: requests the memfds of a store created with
+/// [`crate::Backing::Memfd`]. The request is the bare command word. A
+/// service without a memfd store answers with a V2 error code; otherwise
+/// with [`PROP_SUCCESS`], a `u32` count, and then per area one message of
+/// a `u32` length and the area's file name, carrying the memfd as
+/// `SCM_RIGHTS` ancillary data. AOSP init answers [`PROP_ERROR_INVALID_CMD`].
+pub const PROP_MSG_GET_AREAS: u32 = 0x5250_4101;
+
+/// rsproperties extension: removes a property. The request is the command
+/// word and a `u32`-length-prefixed name, as in [`PROP_MSG_SETPROP2`]; the
+/// reply is a V2 response code. Removing a property that is not set
+/// succeeds. AOSP init answers [`PROP_ERROR_INVALID_CMD`].
+pub const PROP_MSG_UNSETPROP: u32 = 0x5250_4102;
+
+/// rsproperties extension: sets several properties over one connection.
+/// The request is the command word, a `u32` entry count (at most
+/// [`MAX_WIRE_BATCH`]) and per entry a name and a value framed as in
+/// [`PROP_MSG_SETPROP2`]. The reply is [`PROP_SUCCESS`], the `u32` count
+/// and per entry, in request order, a response encoded as
+/// [`encode_error_response`] does (the reason is empty on success). A
+/// request the service cannot read is answered with a single V2 error
+/// code, and none of it is applied. The entries are applied in order and
+/// each on its own: a refused one does not stop the others. AOSP init
+/// answers [`PROP_ERROR_INVALID_CMD`].
+pub const PROP_MSG_SETPROP_BATCH: u32 = 0x5250_4103;
+
 /// V2 success response code.
 pub const PROP_SUCCESS: i32 = 0;
 /// V2 generic error response code.
 pub const PROP_ERROR: i32 = -1;
 
+// Specific V2 error response codes, as AOSP init sends them
+// (bionic `sys/_system_properties.h`).
+/// The command word could not be read.
+pub const PROP_ERROR_READ_CMD: i32 = 0x0004;
+/// The name/value frame could not be read (short read, bad encoding,
+/// over the wire caps).
+pub const PROP_ERROR_READ_DATA: i32 = 0x0008;
+/// The property is `ro.` and already set.
+pub const PROP_ERROR_READ_ONLY_PROPERTY: i32 = 0x000B;
+pub const PROP_ERROR_INVALID_NAME: i32 = 0x0010;
+pub const PROP_ERROR_INVALID_VALUE: i32 = 0x0014;
+pub const PROP_ERROR_PERMISSION_DENIED: i32 = 0x0018;
+pub const PROP_ERROR_INVALID_CMD: i32 = 0x001B;
+pub const PROP_ERROR_HANDLE_CONTROL_MESSAGE: i32 = 0x0020;
+/// The service accepted the request but could not store it.
+pub const PROP_ERROR_SET_FAILED: i32 = 0x0024;
+
+/// Cap on the optional reason that follows a V2 error code: a `u32`
+/// length and that many UTF-8 bytes. bionic reads only the code and
+/// closes, so the trailer is invisible to it; the rsproperties client
+/// reads it when present (AOSP init never sends one).
+pub const MAX_WIRE_ERROR_MESSAGE_LEN: usize = 512;
+
 /// Sanity cap on a V2 wire property-name length. The wire format is
 /// length-prefixed, so this only exists to bound the server's upfront
 /// allocation against a hostile peer; `validate_property_name` rejects
@@ -51,6 +100,30 @@ pub const MAX_WIRE_NAME_LEN: usize = 1024;
 /// `MAX_WIRE_NAME_LEN` for why it lives in this module.
 pub const MAX_WIRE_VALUE_LEN: usize = 8192;
 
+/// Cap on the entries of one [`PROP_MSG_SETPROP_BATCH`] request; the
+/// client splits longer lists into several requests.
+pub const MAX_WIRE_BATCH: usize = 256;
+
+/// Cap on the number of areas in a [`PROP_MSG_GET_AREAS`] reply, and on
+/// the length of each area name (`NAME_MAX`).
+pub const MAX_WIRE_AREAS: usize = 4096;
+pub const MAX_WIRE_AREA_NAME_LEN: usize = 255;
+
+/// Encodes a V2 error response: `code`, then the length-prefixed reason,
+/// truncated (on a char boundary) to [`MAX_WIRE_ERROR_MESSAGE_LEN`].
+pub fn encode_error_response(code: i32, message: &str) -> Vec<u8> {
+    let mut end = message.len().min(MAX_WIRE_ERROR_MESSAGE_LEN);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    let message = &message.as_bytes()[..end];
+    let mut out = Vec::with_capacity(8 + message.len());
+    out.extend_from_slice(&code.to_ne_bytes());
+    out.extend_from_slice(&(message.len() as u32).to_ne_bytes());
+    out.extend_from_slice(message);
+    out
+}
+
 /// Decides whether a property value is storable: length policy plus a
 /// NUL-byte check.
 ///
@@ -61,7 +134,8 @@ pub const MAX_WIRE_VALUE_LEN: usize = 8192;
 /// rejected by the server (or vice versa).
 ///
 /// Names starting with `ro.` are allowed to exceed the limit (the server
-/// stores them as long properties). In-place update paths, which cannot
+/// stores them as long properties); [`validate_value_len_with`] extends
+/// that to a store's other read-only prefixes. In-place update paths, which cannot
 /// promote a value to the out-of-line long-property representation, must
 /// use `validate_short_value_len` instead — the exemption is selected
 /// by *which function* is called, not by an in-band sentinel name.
@@ -75,23 +149,59 @@ pub const MAX_WIRE_VALUE_LEN: usize = 8192;
 /// stale bytes from an earlier update of a *different* property. bionic
 /// cannot even express such a value (its API takes C strings).
 pub fn validate_value_len(name: &str, value: &str) -> Result<()> {
+    validate_value_len_with(name, value, &[])
+}
+
+/// [`validate_value_len`] for a store whose read-only prefixes
+/// ([`crate::PropertyConfig::read_only_prefixes`]) are
+/// `read_only_prefixes`, see [`value_max_with`].
+pub fn validate_value_len_with(
+    name: &str,
+    value: &str,
+    read_only_prefixes: &[String],
+) -> Result<()> {
     reject_value_nul(value)?;
-    if value.len() >= PROP_VALUE_MAX && !name.starts_with("ro.") {
-        return Err(Error::InvalidArgument(format!(
-            "value too long: {} bytes (max {} for non-'ro.' properties)",
-            value.len(),
-            PROP_VALUE_MAX - 1
-        )));
+    if let Some(max) = value_max_with(name, read_only_prefixes) {
+        if value.len() > max {
+            return Err(Error::InvalidArgument(format!(
+                "value too long: {} bytes (max {max} for writable properties)",
+                value.len(),
+            )));
+        }
     }
     Ok(())
 }
 
+/// The longest value, in bytes, [`validate_value_len`] accepts for `name`:
+/// `PROP_VALUE_MAX - 1` (room for the NUL), or `None` for a `ro.` name,
+/// whose value is stored out of line and bounded only by the space left
+/// in its area.
+pub fn value_max(name: &str) -> Option<usize> {
+    value_max_with(name, &[])
+}
+
+/// [`value_max`] for a store whose read-only prefixes are
+/// `read_only_prefixes`: their names are write-once like `ro.` ones, so
+/// they are stored out of line too and have no cap. `ro.` is exempt
+/// whether or not the list holds it.
+pub fn value_max_with(name: &str, read_only_prefixes: &[String]) -> Option<usize> {
+    if name.starts_with(crate::READ_ONLY_PREFIX)
+        || read_only_prefixes
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()))
+    {
+        None
+    } else {
+        Some(PROP_VALUE_MAX - 1)
+    }
+}
+
 /// [`validate_value_len`] with the `ro.` long-property exemption
 /// unconditionally disabled. For in-place update paths
 /// (`SystemProperties::update`) that cannot promote a value to the
 /// out-of-line long representation, so the short-value cap applies
 /// regardless of the property's real name.
-#[cfg(feature = "builder")]
+#[cfg(feature = "writer")]
 pub(crate) fn validate_short_value_len(value: &str) -> Result<()> {
     reject_value_nul(value)?;
     if value.len() >= PROP_VALUE_MAX {
@@ -123,9 +233,9 @@ fn reject_value_nul(value: &str) -> Result<()> {
 ///
 /// `pub(crate)`: this guards internal storage invariants (trie/string
 /// table), not the wire protocol — exporting it would freeze an
-/// implementation detail into the public API. Builder-gated because every
-/// caller (trie builder, serializer, `PropertyArea::add`) is.
-#[cfg(feature = "builder")]
+/// implementation detail into the public API. Gated like its callers
+/// (trie builder and serializer, `PropertyArea::add`).
+#[cfg(any(feature = "writer", feature = "info-builder"))]
 pub(crate) fn validate_no_interior_nul(kind: &str, s: &str) -> Result<()> {
     if s.as_bytes().contains(&0) {
         return Err(Error::InvalidArgument(format!(
@@ -146,13 +256,22 @@ pub(crate) fn validate_no_interior_nul(kind: &str, s: &str) -> Result<()> {
 /// - no consecutive `.`
 /// - allowed chars: ASCII alphanumeric, `_`, `.`, `-`, `@`, `:`
 ///
+/// On top of that, names longer than [`MAX_WIRE_NAME_LEN`] are rejected:
+/// AOSP has no length limit, but a name stored past the V2 wire cap could
+/// never be set through the service, so every layer refuses it alike.
 /// The V1 wire protocol additionally enforces `name.len() < PROP_NAME_MAX`
 /// at the message-encoding layer (the fixed buffer must keep room for the
-/// NUL terminator); V2 has no length cap here.
+/// NUL terminator).
 pub fn validate_property_name(name: &str) -> Result<()> {
     if name.is_empty() {
         return Err(Error::InvalidArgument("name is empty".into()));
     }
+    if name.len() > MAX_WIRE_NAME_LEN {
+        return Err(Error::InvalidArgument(format!(
+            "name too long: {} bytes (max {MAX_WIRE_NAME_LEN})",
+            name.len()
+        )));
+    }
     if name.starts_with('.') {
         return Err(Error::InvalidArgument(format!(
             "name cannot start with '.': {name}"
@@ -181,10 +300,172 @@ pub fn validate_property_name(name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Whether `name` is a legal property name, as [`validate_property_name`]
+/// decides: the client, the area writer and the service all accept
+/// exactly these names.
+pub fn is_valid_property_name(name: &str) -> bool {
+    validate_property_name(name).is_ok()
+}
+
+/// A property name that passed [`validate_property_name`].
+///
+/// Produced by [`PropertyName::new`] (strict: the input must already be
+/// well-formed) or [`canonicalize_name`] (lenient: sloppy input is
+/// cleaned up first).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PropertyName(String);
+
+impl PropertyName {
+    /// Validates `name` as is, without any rewriting.
+    pub fn new(name: &str) -> Result<Self> {
+        validate_property_name(name)?;
+        Ok(Self(name.to_owned()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl AsRef<str> for PropertyName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for PropertyName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<PropertyName> for String {
+    fn from(name: PropertyName) -> Self {
+        name.0
+    }
+}
+
+/// How [`canonicalize_name_with`] rewrites a raw name before validating
+/// it.
+///
+/// The default only trims surrounding whitespace: property names are
+/// case-sensitive on Android (`persist.sys.Foo` and `persist.sys.foo` are
+/// two properties), so lowercasing or character mapping is opt-in for
+/// deployments whose clients are known to disagree on spelling.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct NamePolicy {
+    /// Lowercase ASCII letters.
+    pub lowercase: bool,
+    /// Characters replaced before validation, e.g. `('-', '_')`. The first
+    /// matching pair wins; replacements are not mapped again.
+    pub char_map: Vec<(char, char)>,
+    /// Longest accepted name in bytes, after trimming.
+    pub max_len: usize,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        Self {
+            lowercase: false,
+            char_map: Vec::new(),
+            max_len: MAX_WIRE_NAME_LEN,
+        }
+    }
+}
+
+impl NamePolicy {
+    /// Sets whether ASCII letters are lowercased.
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Adds a character replacement.
+    pub fn map_char(mut self, from: char, to: char) -> Self {
+        self.char_map.push((from, to));
+        self
+    }
+
+    /// Sets the longest accepted name, in bytes.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+/// [`canonicalize_name_with`] under the default [`NamePolicy`]: trims
+/// surrounding whitespace, then validates.
+pub fn canonicalize_name(raw: &str) -> Result<PropertyName> {
+    canonicalize_name_with(raw, &NamePolicy::default())
+}
+
+/// Turns a raw, possibly sloppy name into a well-formed one: trims
+/// surrounding ASCII whitespace, applies `policy`'s lowercasing and
+/// character map, and then requires the result to pass
+/// [`validate_property_name`] (which rejects empty segments such as
+/// `a..b` or a trailing `.`) and to fit `policy.max_len`.
+///
+/// Total for any input — it never panics and allocates at most
+/// `policy.max_len`-ish bytes, since oversized input is rejected before
+/// any rewriting.
+pub fn canonicalize_name_with(raw: &str, policy: &NamePolicy) -> Result<PropertyName> {
+    let trimmed = raw.trim_matches(|c: char| c.is_ascii_whitespace());
+    let too_long = |len: usize| {
+        Error::InvalidArgument(format!(
+            "name too long: {len} bytes (max {})",
+            policy.max_len
+        ))
+    };
+    if trimmed.len() > policy.max_len {
+        return Err(too_long(trimmed.len()));
+    }
+
+    let mut name = String::with_capacity(trimmed.len());
+    for c in trimmed.chars() {
+        let c = policy
+            .char_map
+            .iter()
+            .find(|&&(from, _)| from == c)
+            .map_or(c, |&(_, to)| to);
+        name.push(if policy.lowercase {
+            c.to_ascii_lowercase()
+        } else {
+            c
+        });
+    }
+    // A map to multi-byte characters can grow the name; those are invalid
+    // anyway, but report the length first so the error names the real
+    // limit.
+    if name.len() > policy.max_len {
+        return Err(too_long(name.len()));
+    }
+    validate_property_name(&name)?;
+    Ok(PropertyName(name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn error_response_truncates_on_char_boundary() {
+        let frame = encode_error_response(PROP_ERROR_INVALID_VALUE, "too long");
+        assert_eq!(frame[..4], PROP_ERROR_INVALID_VALUE.to_ne_bytes());
+        assert_eq!(frame[4..8], 8u32.to_ne_bytes());
+        assert_eq!(&frame[8..], b"too long");
+
+        let long = "é".repeat(MAX_WIRE_ERROR_MESSAGE_LEN);
+        let frame = encode_error_response(PROP_ERROR, &long);
+        let len = u32::from_ne_bytes(frame[4..8].try_into().unwrap()) as usize;
+        assert!(len <= MAX_WIRE_ERROR_MESSAGE_LEN);
+        assert!(std::str::from_utf8(&frame[8..]).is_ok());
+    }
+
     #[test]
     fn value_len_short_ok() {
         assert!(validate_value_len("foo", "x".repeat(PROP_VALUE_MAX - 1).as_str()).is_ok());
@@ -200,7 +481,21 @@ mod tests {
         assert!(validate_value_len("ro.foo", "x".repeat(PROP_VALUE_MAX * 10).as_str()).is_ok());
     }
 
-    #[cfg(feature = "builder")]
+    #[test]
+    fn value_max_follows_read_only_prefixes() {
+        let prefixes = ["vendor.fixed.".to_owned()];
+        assert_eq!(value_max_with("vendor.fixed.key", &prefixes), None);
+        assert_eq!(value_max_with("ro.foo", &prefixes), None);
+        assert_eq!(
+            value_max_with("vendor.other", &prefixes),
+            Some(PROP_VALUE_MAX - 1)
+        );
+        let long = "x".repeat(PROP_VALUE_MAX);
+        assert!(validate_value_len_with("vendor.fixed.key", &long, &prefixes).is_ok());
+        assert!(validate_value_len("vendor.fixed.key", &long).is_err());
+    }
+
+    #[cfg(feature = "writer")]
     #[test]
     fn short_value_len_ignores_ro_exemption() {
         // The in-place-update variant enforces the cap even for `ro.`
@@ -261,4 +556,47 @@ mod tests {
         assert!(validate_property_name("has space").is_err());
         assert!(validate_property_name("has/slash").is_err());
     }
+
+    #[test]
+    fn name_rejects_over_wire_cap() {
+        assert!(is_valid_property_name(&"a".repeat(MAX_WIRE_NAME_LEN)));
+        assert!(!is_valid_property_name(&"a".repeat(MAX_WIRE_NAME_LEN + 1)));
+        // PROP_NAME_MAX is a V1 framing limit only.
+        assert!(is_valid_property_name(&"a".repeat(PROP_NAME_MAX)));
+        assert!(!is_valid_property_name("a..b"));
+    }
+
+    #[test]
+    fn canonicalize_trims_and_validates() {
+        assert_eq!(
+            canonicalize_name("  sys.Foo-bar \t\n").unwrap().as_str(),
+            "sys.Foo-bar"
+        );
+        for bad in ["", "   ", "a..b", ".a", "a.", "a b", "a\0b", "a.\u{e9}"] {
+            assert!(canonicalize_name(bad).is_err(), "{bad:?} must be rejected");
+        }
+        let long = "a".repeat(MAX_WIRE_NAME_LEN + 1);
+        assert!(canonicalize_name(&long).is_err());
+        assert!(canonicalize_name(&format!(" {} ", &long[1..])).is_ok());
+    }
+
+    #[test]
+    fn canonicalize_applies_policy() {
+        let policy = NamePolicy::default()
+            .lowercase(true)
+            .map_char('-', '_')
+            .map_char(' ', '.')
+            .max_len(20);
+        assert_eq!(
+            canonicalize_name_with(" Vendor.My-App level ", &policy)
+                .unwrap()
+                .as_str(),
+            "vendor.my_app.level"
+        );
+        // Too long after trimming.
+        assert!(canonicalize_name_with("vendor.my_app.levels2", &policy).is_err());
+        // Mapping must not be able to produce an empty segment.
+        assert!(canonicalize_name_with("a  b", &policy).is_err());
+        assert!(PropertyName::new(" a").is_err());
+    }
 }
@@ -0,0 +1,64 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `migrate_area_dir` rebuilds a properties directory through a staging
+//! directory and swaps it in: values (including `ro.` ones) and checksum
+//! sidecars carry over, and the previous areas stay behind as a backup.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::fs::File;
+
+use rsproperties::{migrate_area_dir, AreaFormat, Error, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_migrate_area_dir() {
+    let root = std::env::temp_dir().join(format!("rsprops_area_migration_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let (live, staging) = (root.join("live"), root.join("staging"));
+    build_property_info(&live, CONTEXTS);
+
+    {
+        let mut writer = SystemProperties::new_area(&live).unwrap();
+        writer.enable_checksums().unwrap();
+        writer.set("test.migrate.mode", "on").unwrap();
+        writer.set("test.migrate.mode", "off").unwrap();
+        writer.set("ro.test.migrate.id", "42").unwrap();
+    }
+    let before = SystemProperties::open(&live).unwrap();
+
+    std::fs::create_dir_all(&staging).unwrap();
+    File::create(staging.join("leftover")).unwrap();
+    assert!(matches!(
+        migrate_area_dir(&live, &staging, AreaFormat::Bionic),
+        Err(Error::InvalidArgument(_))
+    ));
+    std::fs::remove_file(staging.join("leftover")).unwrap();
+
+    let report = migrate_area_dir(&live, &staging, AreaFormat::Bionic).unwrap();
+    assert_eq!(report.format, AreaFormat::Bionic);
+    assert_eq!(report.properties, 2);
+    assert_eq!(report.resynced, 0);
+    assert!(report.checksums);
+    assert_eq!(report.backup_dir, staging);
+
+    let after = SystemProperties::open(&live).unwrap();
+    assert_eq!(after.get_with_result("test.migrate.mode").unwrap(), "off");
+    assert_eq!(after.get_with_result("ro.test.migrate.id").unwrap(), "42");
+    let scrub = after.scrub().unwrap();
+    assert!(scrub.is_clean());
+    assert_eq!(scrub.verified, 2);
+
+    // A reader from before the swap still reads its mapping.
+    assert_eq!(before.get_with_result("test.migrate.mode").unwrap(), "off");
+    // The previous areas are the backup.
+    let backup = SystemProperties::open(&staging).unwrap();
+    assert_eq!(backup.get_with_result("ro.test.migrate.id").unwrap(), "42");
+
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -13,36 +13,18 @@
 
 #![cfg(all(feature = "builder", not(target_os = "android")))]
 
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use rsproperties::SystemProperties;
 
-use rsproperties::{build_trie, PropertyInfoEntry, SystemProperties};
+mod common;
+use common::build_property_info;
 
-fn build_property_info(dir: &Path) {
-    std::fs::create_dir_all(dir).unwrap();
-
-    let contexts_path = dir.join("property_contexts");
-    File::create(&contexts_path)
-        .unwrap()
-        .write_all(b"test. u:object_r:test_prop:s0 prefix string\n")
-        .unwrap();
-
-    let (entries, errors) = PropertyInfoEntry::parse_from_file(&contexts_path, false).unwrap();
-    assert!(errors.is_empty(), "parse errors: {errors:?}");
-
-    let data = build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap();
-    File::create(dir.join("property_info"))
-        .unwrap()
-        .write_all(&data)
-        .unwrap();
-}
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
 
 #[test]
 fn test_new_area_restart_over_existing_dir() {
     let dir = std::env::temp_dir().join(format!("rsprops_restart_{}", std::process::id()));
     let _ = std::fs::remove_dir_all(&dir);
-    build_property_info(&dir);
+    build_property_info(&dir, CONTEXTS);
 
     {
         let mut props = SystemProperties::new_area(&dir).expect("first new_area");
@@ -68,7 +50,7 @@ fn test_new_area_restart_over_existing_dir() {
 fn test_concurrent_writer_rejected_by_lock() {
     let dir = std::env::temp_dir().join(format!("rsprops_wlock_{}", std::process::id()));
     let _ = std::fs::remove_dir_all(&dir);
-    build_property_info(&dir);
+    build_property_info(&dir, CONTEXTS);
 
     let first = SystemProperties::new_area(&dir).expect("first writer");
 
@@ -94,3 +76,29 @@ fn test_concurrent_writer_rejected_by_lock() {
 
     let _ = std::fs::remove_dir_all(&dir);
 }
+
+#[test]
+fn test_all_areas_created_up_front() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("rsprops_eager_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let props = SystemProperties::new_area(&dir).expect("new_area");
+
+    // Every context file exists before the first write, already with the
+    // final read-only mode.
+    for context in ["u:object_r:test_prop:s0", "u:object_r:default_prop:s0"] {
+        let metadata = std::fs::metadata(dir.join(context)).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o444, "{context}");
+    }
+
+    // Idempotent on a writer; the areas stay usable afterwards.
+    props.initialize_all_areas().unwrap();
+    let mut props = props;
+    props.add("test.eager", "1").unwrap();
+    assert_eq!(props.get_with_result("test.eager").unwrap(), "1");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,94 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `Layout::area_size`: writers create context areas of the configured
+//! size, fill them up to it, and readers map them at their file size.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, Layout, SystemProperties, DEFAULT_AREA_SIZE};
+
+mod common;
+use common::{build_property_info, temp_dir};
+
+const CONTEXT: &str = "u:object_r:vendor_prop:s0";
+
+fn contexts() -> String {
+    format!("vendor. {CONTEXT} prefix string\n")
+}
+
+/// Adds `vendor.fill.N` properties until the area is full, returning how
+/// many fit.
+fn fill(props: &mut SystemProperties) -> usize {
+    for i in 0.. {
+        match props.add(&format!("vendor.fill.{i}"), "1") {
+            Ok(()) => {}
+            Err(Error::AreaFull(_)) => return i,
+            Err(e) => panic!("add failed: {e}"),
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn test_area_size() {
+    let small_dir = temp_dir("area_size_small");
+    build_property_info(&small_dir, &contexts());
+    let small_layout = Layout::default().with_area_size(8 * 1024);
+    let mut small = SystemProperties::new_area_with_layout(&small_dir, &small_layout).unwrap();
+    assert_eq!(
+        std::fs::metadata(small_dir.join(CONTEXT)).unwrap().len(),
+        8 * 1024
+    );
+    // The serial area keeps bionic's size.
+    assert_eq!(
+        std::fs::metadata(small_dir.join("properties_serial"))
+            .unwrap()
+            .len(),
+        DEFAULT_AREA_SIZE as u64
+    );
+    let small_count = fill(&mut small);
+    assert!(small_count > 0);
+
+    // An area bigger than bionic's holds more than the default one could.
+    let large_dir = temp_dir("area_size_large");
+    build_property_info(&large_dir, &contexts());
+    let large_layout = Layout::default().with_area_size(512 * 1024);
+    let mut large = SystemProperties::new_area_with_layout(&large_dir, &large_layout).unwrap();
+    let large_count = fill(&mut large);
+    assert!(
+        large_count > small_count * (DEFAULT_AREA_SIZE / (8 * 1024)),
+        "{large_count} vs {small_count}"
+    );
+    let report = large.fragmentation_report().unwrap();
+    let area = report.areas.iter().find(|a| a.context == CONTEXT).unwrap();
+    assert!(area.capacity > DEFAULT_AREA_SIZE, "{area:?}");
+
+    // Readers take the size from the file, whatever their layout says.
+    let reader = SystemProperties::open(&large_dir).unwrap();
+    let last = format!("vendor.fill.{}", large_count - 1);
+    assert_eq!(reader.get_with_result(&last).unwrap(), "1");
+
+    // Sizes that are not whole pages, or that area offsets cannot
+    // address, are refused before anything is created.
+    let bad_dir = temp_dir("area_size_bad");
+    build_property_info(&bad_dir, &contexts());
+    let too_large = usize::try_from(1u64 << 32).ok();
+    for size in [Some(0), Some(DEFAULT_AREA_SIZE + 1), too_large]
+        .into_iter()
+        .flatten()
+    {
+        let layout = Layout::default().with_area_size(size);
+        match SystemProperties::new_area_with_layout(&bad_dir, &layout) {
+            Err(Error::InvalidArgument(_)) => {}
+            Err(e) => panic!("area size {size}: {e:?}"),
+            Ok(_) => panic!("area size {size} was accepted"),
+        }
+    }
+    assert!(!bad_dir.join(CONTEXT).exists());
+
+    drop((small, large, reader));
+    for dir in [small_dir, large_dir, bad_dir] {
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
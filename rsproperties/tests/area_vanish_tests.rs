@@ -0,0 +1,90 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A long-running reader across a writer restart that recreates the area
+//! files, and across an area file that disappears outright.
+//!
+//! Own test binary because the reader is the process-global instance,
+//! which `rsproperties::init` latches once per process.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_reader_follows_recreated_area() {
+    let dir = std::env::temp_dir().join(format!("rsprops_vanish_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.vanish.old", "1").unwrap();
+
+    rsproperties::init(PropertyConfig::with_properties_dir(&dir));
+    let reader = rsproperties::system_properties();
+    assert_eq!(reader.get_with_result("test.vanish.old").unwrap(), "1");
+    assert_eq!(reader.retired_mappings(), 0);
+
+    // Restart: the new writer unlinks and recreates every area file. The
+    // reader's mapping still points at the old inode until a miss makes it
+    // revalidate.
+    drop(writer);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.vanish.new", "2").unwrap();
+
+    // A read still running on the old mapping keeps it mapped across the
+    // remap; the mapping goes once that read is done.
+    let retired = reader
+        .read_with("test.vanish.old", |value| {
+            assert_eq!(value, "1");
+            assert_eq!(reader.get_with_result("test.vanish.new").unwrap(), "2");
+            reader.retired_mappings()
+        })
+        .unwrap();
+    assert_eq!(retired, 1);
+    assert_eq!(reader.retired_mappings(), 0);
+    assert_eq!(reader.debug_state().unwrap().areas[0].generations, 2);
+    assert!(reader.find("test.vanish.old").unwrap().is_none());
+
+    // The file vanishing entirely is reported as such, not as absence.
+    std::fs::remove_file(dir.join("u:object_r:test_prop:s0")).unwrap();
+    let err = reader.get_with_result("test.vanish.missing").unwrap_err();
+    assert!(matches!(err, Error::AreaVanished(_)), "{err}");
+    assert!(matches!(
+        reader.find("test.vanish.missing"),
+        Err(Error::AreaVanished(_))
+    ));
+
+    drop(writer);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_reader_rechecks_found_names() {
+    let dir = std::env::temp_dir().join(format!("rsprops_recheck_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.recheck.kept", "1").unwrap();
+    let reader = SystemProperties::open(&dir).unwrap();
+    assert_eq!(reader.get_with_result("test.recheck.kept").unwrap(), "1");
+
+    // The restarted writer stores the same name again, so lookups of it
+    // never miss in the old mapping; the periodic check still moves them
+    // to the new file.
+    drop(writer);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.recheck.kept", "2").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    assert_eq!(reader.get_with_result("test.recheck.kept").unwrap(), "2");
+    assert_eq!(reader.retired_mappings(), 0);
+
+    drop(writer);
+    let _ = std::fs::remove_dir_all(&dir);
+}
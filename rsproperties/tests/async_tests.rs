@@ -0,0 +1,80 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `asynch`: gets, sets and waits from a tokio runtime against a
+//! `TestEnv` service. The global configuration latches once per process,
+//! so the phases run sequentially in one #[test] fn.
+
+#![cfg(all(feature = "async", feature = "test-utils", not(target_os = "android")))]
+
+use std::time::{Duration, Instant};
+
+use rsproperties::asynch::{get_async, set_async, wait_async};
+use rsproperties::test_support::TestEnv;
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_async_api() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let env = TestEnv::builder()
+        .context("async_test. u:object_r:async_test_prop:s0 prefix string")
+        .property("async_test.mode", "initial")
+        .build()
+        .unwrap();
+    env.install().unwrap();
+    rsproperties::wait_for_service(Duration::from_secs(5)).unwrap();
+
+    // Phase 1: get and set.
+    assert_eq!(
+        get_async::<String>("async_test.mode").await.unwrap(),
+        "initial"
+    );
+    set_async("async_test.count", &7).await.unwrap();
+    assert_eq!(get_async::<i32>("async_test.count").await.unwrap(), 7);
+    assert!(get_async::<String>("async_test.missing").await.is_err());
+
+    // Phase 2: a set made while waiting ends the wait. The runtime has a
+    // single worker, so the set only runs if the wait does not block it.
+    let handle = rsproperties::system_properties()
+        .find("async_test.mode")
+        .unwrap()
+        .unwrap();
+    let old = rsproperties::system_properties().serial(&handle).unwrap();
+    let setter = tokio::spawn(async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        set_async("async_test.mode", "changed").await.unwrap();
+    });
+    let serial = wait_async(Some(handle), Some(old), Some(Duration::from_secs(5)))
+        .await
+        .unwrap();
+    setter.await.unwrap();
+    assert!(serial.is_some_and(|serial| serial != old));
+    assert_eq!(
+        get_async::<String>("async_test.mode").await.unwrap(),
+        "changed"
+    );
+
+    // Phase 3: a serial that already moved returns at once.
+    let serial = wait_async(Some(handle), Some(old), None).await.unwrap();
+    assert!(serial.is_some_and(|serial| serial != old));
+
+    // Phase 4: timeouts, also for any-property waits.
+    let started = Instant::now();
+    assert_eq!(
+        wait_async(Some(handle), None, Some(Duration::from_millis(250)))
+            .await
+            .unwrap(),
+        None
+    );
+    assert!(started.elapsed() >= Duration::from_millis(250));
+    assert_eq!(
+        wait_async(None, None, Some(Duration::from_millis(50)))
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Phase 5: a dropped wait does not keep the runtime from finishing.
+    let dropped = tokio::time::timeout(Duration::from_millis(150), wait_async(None, None, None));
+    assert!(dropped.await.is_err());
+}
@@ -0,0 +1,147 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Custom OS backends: counting wrappers around the defaults must see
+//! every mapping, unmapping, wait and wake once installed, and a backend
+//! panicking in `unmap` must not abort an unwinding thread.
+//!
+//! Backends latch process-wide on first use, so everything runs in one
+//! #[test] fn that installs them before touching any property.
+
+#![cfg(all(feature = "builder", target_os = "linux"))]
+
+use std::fs::File;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use rsproperties::backend::{
+    FutexBackend, MmapBackend, PropertyAreaBackend, WaitBackend, WaitOutcome,
+};
+use rsproperties::{Error, PropertyConfig, SystemProperties};
+use rsproperties::{Result, Timespec};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+struct CountingArea {
+    live: AtomicUsize,
+    maps: AtomicUsize,
+    panic_on_unmap: AtomicBool,
+}
+
+unsafe impl PropertyAreaBackend for CountingArea {
+    fn map(&self, file: File, size: usize, writable: bool) -> Result<NonNull<u8>> {
+        let base = MmapBackend.map(file, size, writable)?;
+        self.live.fetch_add(1, Ordering::SeqCst);
+        self.maps.fetch_add(1, Ordering::SeqCst);
+        Ok(base)
+    }
+
+    unsafe fn unmap(&self, base: NonNull<u8>, size: usize) {
+        if self.panic_on_unmap.load(Ordering::SeqCst) {
+            panic!("unmap refused");
+        }
+        self.live.fetch_sub(1, Ordering::SeqCst);
+        unsafe { MmapBackend.unmap(base, size) }
+    }
+}
+
+struct CountingWait {
+    waits: AtomicUsize,
+    wakes: AtomicUsize,
+}
+
+impl WaitBackend for CountingWait {
+    fn wait(&self, serial: &AtomicU32, value: u32, timeout: Option<&Timespec>) -> WaitOutcome {
+        self.waits.fetch_add(1, Ordering::SeqCst);
+        FutexBackend.wait(serial, value, timeout)
+    }
+
+    fn wake(&self, serial: &AtomicU32) -> Result<usize> {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+        FutexBackend.wake(serial)
+    }
+}
+
+static AREA: CountingArea = CountingArea {
+    live: AtomicUsize::new(0),
+    maps: AtomicUsize::new(0),
+    panic_on_unmap: AtomicBool::new(false),
+};
+static WAIT: CountingWait = CountingWait {
+    waits: AtomicUsize::new(0),
+    wakes: AtomicUsize::new(0),
+};
+
+#[test]
+fn test_custom_backends() {
+    rsproperties::backend::set_area_backend(&AREA).unwrap();
+    rsproperties::backend::set_wait_backend(&WAIT).unwrap();
+    assert!(matches!(
+        rsproperties::backend::set_wait_backend(&FutexBackend),
+        Err(Error::AlreadyInitialized(_))
+    ));
+
+    let dir = std::env::temp_dir().join(format!("rsprops_backend_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    assert!(AREA.maps.load(Ordering::SeqCst) > 0);
+    writer.add("test.backend.prop", "0").unwrap();
+    let wakes = WAIT.wakes.load(Ordering::SeqCst);
+    assert!(wakes > 0);
+
+    rsproperties::init(PropertyConfig::with_properties_dir(&dir));
+    let reader = rsproperties::system_properties();
+    let old = reader.context_serial();
+    let waiter = std::thread::spawn(move || {
+        let timeout = Timespec {
+            tv_sec: 10,
+            tv_nsec: 0,
+        };
+        reader.wait(None, Some(old), Some(&timeout))
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    writer.set("test.backend.prop", "1").unwrap();
+    assert!(waiter.join().unwrap().is_some());
+    assert!(WAIT.waits.load(Ordering::SeqCst) > 0);
+    assert!(WAIT.wakes.load(Ordering::SeqCst) > wakes);
+    assert_eq!(
+        rsproperties::get::<String>("test.backend.prop").unwrap(),
+        "1"
+    );
+
+    // The writer's mappings go back through the backend; the global
+    // reader's stay mapped for the life of the process.
+    let live = AREA.live.load(Ordering::SeqCst);
+    drop(writer);
+    assert!(AREA.live.load(Ordering::SeqCst) < live);
+
+    // `close` releases a reader's mappings without dropping it.
+    let live = AREA.live.load(Ordering::SeqCst);
+    let mut other = SystemProperties::open(&dir).unwrap();
+    assert_eq!(other.get_with_result("test.backend.prop").unwrap(), "1");
+    assert!(AREA.live.load(Ordering::SeqCst) > live);
+    other.close();
+    assert_eq!(AREA.live.load(Ordering::SeqCst), live);
+
+    // An instance dropped while its thread unwinds, with a backend that
+    // panics in `unmap`: the mappings leak instead of the process aborting.
+    AREA.panic_on_unmap.store(true, Ordering::SeqCst);
+    let dir_clone = dir.clone();
+    let unwound = std::thread::spawn(move || {
+        let props = SystemProperties::open(&dir_clone).unwrap();
+        assert_eq!(props.get_with_result("test.backend.prop").unwrap(), "1");
+        panic!("unwinding with mapped areas");
+    })
+    .join();
+    assert!(unwound.is_err());
+    AREA.panic_on_unmap.store(false, Ordering::SeqCst);
+    assert!(AREA.live.load(Ordering::SeqCst) > live);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,99 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::from_buffers`: a read-only store built from a
+//! property_info trie and area contents held in memory.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use rsproperties::{Error, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n\
+    other. u:object_r:other_prop:s0 prefix int\n";
+
+/// The serial area and every context area of `dir`, by filename.
+fn read_areas(dir: &Path) -> HashMap<String, Vec<u8>> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name == "properties_serial" || name.starts_with("u:object_r:"))
+        .map(|name| {
+            let bytes = std::fs::read(dir.join(&name)).unwrap();
+            (name, bytes)
+        })
+        .collect()
+}
+
+#[test]
+fn test_from_buffers() {
+    let dir = std::env::temp_dir().join(format!("rsprops_buffers_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let info = build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.mode", "on").unwrap();
+    writer.add("other.count", "7").unwrap();
+    writer.add("unlisted.prop", "x").unwrap();
+    let long = "v".repeat(200);
+    writer.add("ro.test.long", &long).unwrap();
+
+    let areas = read_areas(&dir);
+    let props = SystemProperties::from_buffers(&info, areas.clone()).unwrap();
+    assert_eq!(props.get_with_result("test.mode").unwrap(), "on");
+    assert_eq!(props.get_with_result("other.count").unwrap(), "7");
+    assert_eq!(props.get_with_result("unlisted.prop").unwrap(), "x");
+    assert_eq!(props.get_with_result("ro.test.long").unwrap(), long);
+    assert_eq!(props.property_type("other.count").unwrap(), Some("int"));
+    assert_eq!(props.freeze().unwrap().len(), 4);
+
+    // A copy: later writes are not seen.
+    writer.set("test.mode", "off").unwrap();
+    assert_eq!(props.get_with_result("test.mode").unwrap(), "on");
+    let serial = props.context_serial();
+    let timeout = rustix::fs::Timespec {
+        tv_sec: 0,
+        tv_nsec: 10_000_000,
+    };
+    assert_eq!(props.wait(None, None, Some(&timeout)), None);
+    assert_eq!(props.context_serial(), serial);
+
+    // A missing or corrupt context area only takes out that context.
+    let mut partial = areas.clone();
+    partial.remove("u:object_r:other_prop:s0");
+    let mut corrupt = areas.clone();
+    // The magic, after `bytes_used` and the serial.
+    corrupt.get_mut("u:object_r:other_prop:s0").unwrap()[8..12].fill(0);
+    for areas in [partial, corrupt] {
+        let props = SystemProperties::from_buffers(&info, areas).unwrap();
+        assert!(matches!(
+            props.get_with_result("other.count"),
+            Err(Error::FileValidation(_))
+        ));
+        assert_eq!(props.get_with_result("test.mode").unwrap(), "on");
+    }
+
+    // A missing or corrupt serial area or trie is refused.
+
+    let mut no_serial = areas.clone();
+    no_serial.remove("properties_serial");
+    assert!(matches!(
+        SystemProperties::from_buffers(&info, no_serial),
+        Err(Error::NotFound(_))
+    ));
+    let mut short_serial = areas.clone();
+    short_serial.insert("properties_serial".to_owned(), vec![0; 8]);
+    assert!(matches!(
+        SystemProperties::from_buffers(&info, short_serial),
+        Err(Error::FileSize(_))
+    ));
+    assert!(SystemProperties::from_buffers(&info[..info.len() / 2], areas.clone()).is_err());
+    assert!(SystemProperties::from_buffers(&[], areas).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
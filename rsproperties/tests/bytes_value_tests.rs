@@ -0,0 +1,67 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Binary values on properties declared with the `bytes` type.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n\
+    test.blob. u:object_r:test_prop:s0 prefix bytes\n";
+
+#[test]
+fn test_bytes_type_is_enforced() {
+    let dir = std::env::temp_dir().join(format!("rsprops_bytes_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    rsproperties::init(
+        PropertyConfig::with_properties_dir(dir.clone()).read_only_prefixes(["test.blob.fixed."]),
+    );
+
+    let mut props = SystemProperties::new_area(&dir).unwrap();
+    assert_eq!(props.property_type("test.blob.key").unwrap(), Some("bytes"));
+    assert_eq!(props.property_type("test.text").unwrap(), Some("string"));
+
+    // The writer rejects text in a bytes property, but not in a string one.
+    assert!(matches!(
+        props.set("test.blob.key", "not base64!"),
+        Err(Error::InvalidArgument(_))
+    ));
+    props.set("test.text", "not base64!").unwrap();
+    props.set("test.blob.key", "AAEC/w==").unwrap();
+
+    assert_eq!(
+        rsproperties::get_bytes("test.blob.key").unwrap(),
+        [0x00, 0x01, 0x02, 0xff]
+    );
+    assert!(matches!(
+        rsproperties::get_bytes("test.text"),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    // Refused before anything is sent to the (absent) service.
+    #[cfg(feature = "service-protocol")]
+    {
+        assert!(matches!(
+            rsproperties::set_bytes("test.text", b"x"),
+            Err(Error::InvalidArgument(_))
+        ));
+        let too_long = vec![0u8; rsproperties::PROP_BYTES_MAX + 1];
+        assert!(matches!(
+            rsproperties::set_bytes("test.blob.key", &too_long),
+            Err(Error::InvalidArgument(_))
+        ));
+        // A configured read-only prefix is write-once and stored out of
+        // line, so the set goes out — to find no service.
+        assert!(matches!(
+            rsproperties::set_bytes("test.blob.fixed.key", &too_long),
+            Err(Error::Io(_))
+        ));
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,64 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `CachedProperty` follows its property's value, walking the trie only
+//! when the property (or, while it is unset, the global serial) changed,
+//! and noticing the property's removal.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{CachedProperty, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test.cached. u:object_r:cached_prop:s0 prefix string\n";
+
+fn lookups() -> u64 {
+    rsproperties::lookup_stats().lookups
+}
+
+#[test]
+fn test_cached_property() {
+    let dir = std::env::temp_dir().join(format!("rsprops_cached_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    let reader: &'static SystemProperties =
+        Box::leak(Box::new(SystemProperties::open(&dir).unwrap()));
+    rsproperties::enable_lookup_stats(true);
+
+    let mut cached = CachedProperty::with_properties(reader, "test.cached.mode");
+    assert_eq!(cached.name(), "test.cached.mode");
+    assert_eq!(cached.get(), "");
+    // Unset, and the global serial has not moved: no second lookup.
+    let before = lookups();
+    assert_eq!(cached.get(), "");
+    assert_eq!(lookups(), before);
+
+    writer.set("test.cached.mode", "idle").unwrap();
+    assert_eq!(cached.get(), "idle");
+    let before = lookups();
+    assert_eq!(cached.get(), "idle");
+    assert_eq!(lookups(), before);
+
+    // Another property's change moves the global serial, not this one's.
+    writer.set("test.cached.other", "1").unwrap();
+    let before = lookups();
+    assert_eq!(cached.get(), "idle");
+    assert_eq!(lookups(), before);
+
+    writer.set("test.cached.mode", "busy").unwrap();
+    let before = lookups();
+    assert_eq!(cached.get(), "busy");
+    assert_eq!(lookups(), before);
+
+    // A remove leaves the record's serial alone; the name is unlinked.
+    writer.remove("test.cached.mode").unwrap();
+    assert_eq!(cached.get(), "");
+    writer.set("test.cached.mode", "back").unwrap();
+    assert_eq!(cached.get(), "back");
+
+    rsproperties::enable_lookup_stats(false);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,80 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A store whose [`Layout`] makes names case-insensitive: the writer
+//! stores names lowercased, readers find them under any spelling, and a
+//! second spelling of one name is refused.
+//!
+//! Own test binary because `rsproperties::init` latches the directory and
+//! layout once per process.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, Layout, PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_case_insensitive_store() {
+    let dir = std::env::temp_dir().join(format!("rsprops_case_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let layout = Layout::default().with_case_insensitive(true);
+    let mut writer = SystemProperties::new_area_with_layout(&dir, &layout).unwrap();
+    writer.set("test.Net.Timeout", "30").unwrap();
+    writer.set("test.Net.Timeout", "40").unwrap();
+    writer.add("TEST.Host.Name", "box").unwrap();
+
+    for spelling in ["test.Net.Timeout", "test.net.timeout", "TEST.NET.TIMEOUT"] {
+        assert_eq!(writer.get_with_result(spelling).unwrap(), "40");
+        assert!(writer.find(spelling).unwrap().is_some());
+    }
+    assert_eq!(
+        writer.property_type("Test.Anything").unwrap(),
+        Some("string")
+    );
+
+    // A second spelling of a name this writer already wrote.
+    for result in [
+        writer.set("test.net.timeout", "1"),
+        writer.add("test.NET.timeout", "1"),
+        writer.set("test.host.name", "other"),
+    ] {
+        assert!(
+            matches!(result, Err(Error::InvalidArgument(_))),
+            "{result:?}"
+        );
+    }
+    assert_eq!(writer.get_with_result("test.net.timeout").unwrap(), "40");
+
+    // Stored lowercased: a case-sensitive reader only finds that spelling.
+    let frozen = writer.freeze().unwrap();
+    assert_eq!(frozen.get("test.net.timeout"), Some("40"));
+    assert_eq!(frozen.get("test.host.name"), Some("box"));
+    let plain = SystemProperties::open(&dir).unwrap();
+    assert_eq!(plain.get_with_result("test.net.timeout").unwrap(), "40");
+    assert!(matches!(
+        plain.get_with_result("test.Net.Timeout"),
+        Err(Error::NotFound(_))
+    ));
+
+    rsproperties::try_init(
+        PropertyConfig::builder()
+            .properties_dir(&dir)
+            .layout(layout)
+            .build(),
+    )
+    .unwrap();
+    let timeout: u32 = rsproperties::get("Test.Net.TimeOut").unwrap();
+    assert_eq!(timeout, 40);
+    assert_eq!(
+        rsproperties::get_optional("test.host.NAME").unwrap(),
+        Some("box".to_owned())
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,82 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-record checksums: a value damaged on storage is reported as
+//! `Error::Corrupt` on read and by `scrub`, and a new writer drops the
+//! sidecars along with the areas.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::os::unix::fs::{FileExt, PermissionsExt};
+use std::path::Path;
+
+use rsproperties::{Error, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+/// Flips a bit of the first occurrence of `needle` in `path`, as storage
+/// bit rot would.
+fn flip_bit(path: &Path, needle: &[u8]) {
+    let mut permissions = std::fs::metadata(path).unwrap().permissions();
+    permissions.set_mode(0o644);
+    std::fs::set_permissions(path, permissions).unwrap();
+    let bytes = std::fs::read(path).unwrap();
+    let at = bytes
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .expect("value not found in the area file");
+    let file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+    file.write_all_at(&[bytes[at] ^ 0x04], at as u64).unwrap();
+}
+
+#[test]
+fn test_checksums() {
+    let dir = std::env::temp_dir().join(format!("rsprops_checksum_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let area = dir.join("u:object_r:test_prop:s0");
+    let sidecar = dir.join("u:object_r:test_prop:s0.crc");
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set("test.crc.before", "seeded").unwrap();
+    writer.enable_checksums().unwrap();
+    assert!(sidecar.exists());
+    writer.set("test.crc.after", "added").unwrap();
+    writer.set("test.crc.before", "updated").unwrap();
+    writer.set("test.crc.target", "pristine-value").unwrap();
+
+    let reader = SystemProperties::open(&dir).unwrap();
+    assert_eq!(
+        reader.get_with_result("test.crc.before").unwrap(),
+        "updated"
+    );
+    let report = reader.scrub().unwrap();
+    assert!(report.is_clean(), "{report:?}");
+    assert_eq!(report.verified, 3);
+    assert_eq!(report.unchecked, 0);
+
+    flip_bit(&area, b"pristine-value");
+    match reader.get_with_result("test.crc.target") {
+        Err(Error::Corrupt { name }) => assert_eq!(name, "test.crc.target"),
+        other => panic!("expected Error::Corrupt, got {other:?}"),
+    }
+    assert_eq!(reader.get_with_result("test.crc.after").unwrap(), "added");
+    let report = reader.scrub().unwrap();
+    assert_eq!(report.corrupt, ["test.crc.target"]);
+    assert_eq!(report.verified, 2);
+
+    // A new writer starts from fresh areas without sidecars.
+    drop(reader);
+    drop(writer);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    assert!(!sidecar.exists());
+    writer.set("test.crc.target", "pristine-value").unwrap();
+    let report = writer.scrub().unwrap();
+    assert!(report.is_clean());
+    assert_eq!((report.verified, report.unchecked), (0, 1));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,128 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `PropertiesClient`: two property stores, each with its own service,
+//! used from one process — one through the global functions, the other
+//! through a client of its own.
+
+#![cfg(all(feature = "test-utils", not(target_os = "android")))]
+
+use std::time::Duration;
+
+use rsproperties::test_support::TestEnv;
+use rsproperties::{Error, PropertiesClient, PropertyConfig, PROP_BYTES_MAX};
+
+#[test]
+fn test_two_stores_in_one_process() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let global_env = TestEnv::builder()
+        .context("client_test. u:object_r:client_test_prop:s0 prefix string")
+        .property("client_test.name", "global")
+        .build()
+        .unwrap();
+    let other_env = TestEnv::builder()
+        .context("client_test. u:object_r:client_test_prop:s0 prefix string")
+        .context(
+            "ro.property_service.version u:object_r:property_service_version_prop:s0 exact int",
+        )
+        .property("client_test.name", "other")
+        .property("ro.property_service.version", "1")
+        .build()
+        .unwrap();
+    global_env.install().unwrap();
+    rsproperties::wait_for_service(Duration::from_secs(5)).unwrap();
+
+    let client = PropertiesClient::new(PropertyConfig::with_both_dirs(
+        other_env.properties_dir(),
+        other_env.socket_dir(),
+    ))
+    .unwrap();
+    assert_eq!(client.socket_dir(), other_env.socket_dir());
+
+    // Reads go to each client's own store.
+    assert_eq!(
+        rsproperties::get::<String>("client_test.name").unwrap(),
+        "global"
+    );
+    assert_eq!(
+        PropertiesClient::global()
+            .get::<String>("client_test.name")
+            .unwrap(),
+        "global"
+    );
+    assert_eq!(client.get::<String>("client_test.name").unwrap(), "other");
+    assert_eq!(client.get_optional("client_test.missing").unwrap(), None);
+
+    // So do sets, through each store's service.
+    client.set("client_test.count", &2).unwrap();
+    rsproperties::set("client_test.count", "1").unwrap();
+    assert!(client
+        .wait_for_value("client_test.count", "2", Duration::from_secs(5))
+        .unwrap());
+    assert!(
+        rsproperties::wait_for_value("client_test.count", "1", Duration::from_secs(5)).unwrap()
+    );
+    assert_eq!(client.get::<i32>("client_test.count").unwrap(), 2);
+    assert_eq!(rsproperties::get::<i32>("client_test.count").unwrap(), 1);
+
+    // Each client speaks the protocol version its own store names: V1
+    // frames cannot carry a name of 32 bytes or more.
+    let long_name = "client_test.a_name_longer_than_v1";
+    assert!(matches!(
+        client.set(long_name, "1"),
+        Err(rsproperties::Error::InvalidArgument(_))
+    ));
+    rsproperties::set(long_name, "1").unwrap();
+
+    // A set ends a wait on the client's store only.
+    let props = client.system_properties().unwrap();
+    let handle = props.find("client_test.count").unwrap().unwrap();
+    let old = props.serial(&handle).unwrap();
+    rsproperties::set("client_test.count", "3").unwrap();
+    assert_eq!(
+        client
+            .wait(Some(&handle), Some(old), Some(Duration::from_millis(100)))
+            .unwrap(),
+        None
+    );
+    other_env.set("client_test.count", "4").unwrap();
+    let serial = client
+        .wait(Some(&handle), Some(old), Some(Duration::from_secs(5)))
+        .unwrap();
+    assert!(serial.is_some_and(|serial| serial != old));
+
+    // A directory without a store fails up front.
+    let missing =
+        std::env::temp_dir().join(format!("rsprops_client_missing_{}", std::process::id()));
+    assert!(PropertiesClient::new(PropertyConfig::with_properties_dir(missing)).is_err());
+}
+
+#[test]
+fn test_set_bytes_under_read_only_prefix() {
+    let env = TestEnv::builder()
+        .context("client_test.blob. u:object_r:client_test_prop:s0 prefix bytes")
+        .read_only_prefixes(["client_test.blob.fixed."])
+        .build()
+        .unwrap();
+    let client = PropertiesClient::new(
+        PropertyConfig::with_both_dirs(env.properties_dir(), env.socket_dir())
+            .read_only_prefixes(["client_test.blob.fixed."]),
+    )
+    .unwrap();
+
+    // Write-once names are stored out of line, like `ro.` ones, so the
+    // inline cap does not apply to them.
+    let long = vec![0x5a; PROP_BYTES_MAX + 1];
+    client
+        .set_bytes("client_test.blob.fixed.key", &long)
+        .unwrap();
+    assert_eq!(
+        client.get_bytes("client_test.blob.fixed.key").unwrap(),
+        long
+    );
+    assert!(matches!(
+        client.set_bytes("client_test.blob.key", &long),
+        Err(Error::InvalidArgument(_))
+    ));
+}
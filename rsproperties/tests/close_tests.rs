@@ -0,0 +1,68 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::close`: a closed instance fails cleanly, including
+//! for indexes taken before it was closed, and a closed writer gives up
+//! its directory.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_close() {
+    let dir = std::env::temp_dir().join(format!("rsprops_close_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.close.prop", "1").unwrap();
+    let writer_index = writer.find("test.close.prop").unwrap().unwrap();
+
+    let mut reader = SystemProperties::open(&dir).unwrap();
+    let index = reader.find("test.close.prop").unwrap().unwrap();
+    assert!(reader.serial(&index).is_some());
+    assert!(!reader.is_closed());
+
+    reader.close();
+    assert!(reader.is_closed());
+    assert!(matches!(
+        reader.get_with_result("test.close.prop"),
+        Err(Error::Closed)
+    ));
+    assert!(matches!(reader.find("test.close.prop"), Err(Error::Closed)));
+    assert!(matches!(
+        reader.property_type("test.close.prop"),
+        Err(Error::Closed)
+    ));
+    assert!(matches!(reader.freeze(), Err(Error::Closed)));
+    assert_eq!(reader.serial(&index), None);
+    assert_eq!(reader.wait(Some(&index), None, None), None);
+    assert_eq!(reader.wait(None, None, None), None);
+    assert_eq!(reader.context_serial(), 0);
+    reader.close();
+
+    // The writer is unaffected by the reader closing; once closed itself,
+    // it stops writing and lets a new writer take the directory.
+    writer.set("test.close.prop", "2").unwrap();
+    assert!(SystemProperties::new_area(&dir).is_err());
+    writer.close();
+    assert!(matches!(
+        writer.update(&writer_index, "3"),
+        Err(Error::Closed)
+    ));
+    assert!(matches!(
+        writer.add("test.close.other", "1"),
+        Err(Error::Closed)
+    ));
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.close.prop", "4").unwrap();
+    assert_eq!(writer.get_with_result("test.close.prop").unwrap(), "4");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
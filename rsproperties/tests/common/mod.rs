@@ -1,25 +1,56 @@
+// Shared by many test binaries, each using only some of it.
+#![allow(dead_code)]
+
 #[cfg(not(target_os = "android"))]
 use std::fs::{create_dir_all, remove_dir_all};
 #[cfg(not(target_os = "android"))]
-use std::sync::Once;
+use std::path::PathBuf;
 #[cfg(not(target_os = "android"))]
-use std::{path::PathBuf, str::FromStr};
+use std::sync::Once;
 
 #[cfg(not(target_os = "android"))]
 use rsproperties::PropertyConfig;
 
+/// Per-process, so concurrent test binaries and CI shards never share
+/// sockets. Removed when the test binary exits.
+#[cfg(not(target_os = "android"))]
+pub fn test_properties_dir() -> PathBuf {
+    static DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("rsprops_tests_{}", std::process::id()));
+        remove_at_exit(&dir);
+        dir
+    })
+    .clone()
+}
+
+// The crate's own cleanup, compiled in: `rsproperties::test_support`
+// needs the `test-utils` feature, which most of these tests run without,
+// and a self dev-dependency enabling it would also turn it on for
+// examples, including the client-only build `size_check` measures.
 #[cfg(not(target_os = "android"))]
-pub static TEST_PROPERTIES_DIR: &str = "__properties__";
+#[path = "../../src/exit_cleanup.rs"]
+mod exit_cleanup;
+#[cfg(not(target_os = "android"))]
+use exit_cleanup::remove_at_exit;
+
+/// `rsprops_<tag>_<pid>` in the temp dir, emptied now and removed when
+/// the test binary exits. Not created.
+#[cfg(not(target_os = "android"))]
+pub fn temp_dir(tag: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rsprops_{tag}_{}", std::process::id()));
+    let _ = remove_dir_all(&dir);
+    remove_at_exit(&dir);
+    dir
+}
 
 #[cfg(not(target_os = "android"))]
 static INIT: Once = Once::new();
 
-#[allow(dead_code)]
 pub fn init_test() {
     #[cfg(not(target_os = "android"))]
     INIT.call_once(|| {
-        let properties_dir = PathBuf::from_str(TEST_PROPERTIES_DIR)
-            .expect("Failed to parse properties directory path");
+        let properties_dir = test_properties_dir();
         let socket_dir = properties_dir.join("sockets");
 
         remove_dir_all(&socket_dir).unwrap_or_default();
@@ -28,3 +59,26 @@ pub fn init_test() {
         rsproperties::init(PropertyConfig::with_both_dirs(properties_dir, socket_dir));
     });
 }
+
+/// Writes `contexts` to `dir/property_contexts`, creating `dir`, and
+/// returns the `property_info` trie built from it, with
+/// `u:object_r:default_prop:s0` / `string` for undeclared names.
+#[cfg(all(feature = "builder", not(target_os = "android")))]
+pub fn contexts_trie(dir: &std::path::Path, contexts: &str) -> Vec<u8> {
+    create_dir_all(dir).unwrap();
+    let contexts_path = dir.join("property_contexts");
+    std::fs::write(&contexts_path, contexts).unwrap();
+
+    let (entries, errors) =
+        rsproperties::PropertyInfoEntry::parse_from_file(&contexts_path, false).unwrap();
+    assert!(errors.is_empty(), "parse errors: {errors:?}");
+    rsproperties::build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap()
+}
+
+/// [`contexts_trie`], written to `dir/property_info` and returned.
+#[cfg(all(feature = "builder", not(target_os = "android")))]
+pub fn build_property_info(dir: &std::path::Path, contexts: &str) -> Vec<u8> {
+    let data = contexts_trie(dir, contexts);
+    std::fs::write(dir.join("property_info"), &data).unwrap();
+    data
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `AndroidSystemProperties`, the `android_system_properties` look-alike,
+//! reads through the global instance: missing properties and bad names
+//! are `None`, and `wait` returns the value of the next change.
+//!
+//! The global instance latches its directory, so everything runs in one
+//! #[test] fn.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::ffi::CString;
+use std::time::Duration;
+
+use rsproperties::SystemProperties;
+use rsproperties::{AndroidSystemProperties, PropertyConfig};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_android_system_properties_compat() {
+    let dir = std::env::temp_dir().join(format!("rsprops_compat_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set("test.compat.model", "Board X").unwrap();
+    writer.set("test.compat.empty", "").unwrap();
+    rsproperties::init(PropertyConfig::with_properties_dir(&dir));
+
+    let properties = AndroidSystemProperties::new();
+    assert_eq!(
+        properties.get("test.compat.model").as_deref(),
+        Some("Board X")
+    );
+    assert_eq!(properties.get("test.compat.empty").as_deref(), Some(""));
+    assert_eq!(properties.get("test.compat.missing"), None);
+    assert_eq!(properties.get("bad..name"), None);
+    let cname = CString::new("test.compat.model").unwrap();
+    assert_eq!(properties.get_from_cstr(&cname).as_deref(), Some("Board X"));
+
+    // Nothing changes: the wait times out.
+    assert_eq!(
+        properties.wait("test.compat.model", Some(Duration::from_millis(20))),
+        None
+    );
+
+    // An existing property changes, and a missing one is created. A
+    // timeout too large for a deadline waits without one.
+    for (name, value, timeout) in [
+        ("test.compat.model", "Board Y", Duration::from_secs(10)),
+        ("test.compat.late", "1", Duration::from_secs(10)),
+        ("test.compat.model", "Board Z", Duration::MAX),
+    ] {
+        let waiter =
+            std::thread::spawn(move || AndroidSystemProperties::new().wait(name, Some(timeout)));
+        std::thread::sleep(Duration::from_millis(100));
+        writer.set("test.compat.other", value).unwrap();
+        writer.set(name, value).unwrap();
+        assert_eq!(waiter.join().unwrap().as_deref(), Some(value));
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -35,6 +35,10 @@ fn init_test_and_dirname() {
         !dirname.to_string_lossy().is_empty(),
         "dirname should not be empty after init"
     );
+    #[allow(deprecated)]
+    {
+        assert_eq!(rsproperties::dirname(), dirname);
+    }
 
     println!("✓ init() and dirname() work correctly");
     println!("  Property directory: {dirname:?}");
@@ -54,6 +58,10 @@ fn test_get_with_default_functionality() {
         "Should return default for non-existent property"
     );
 
+    #[allow(deprecated)]
+    let result = rsproperties::get_with_default("test.nonexistent.property.12345", "default_value");
+    assert_eq!(result, "default_value");
+
     // Test with empty property name
     let result = rsproperties::get_or("", "empty_default".to_string());
     assert_eq!(
@@ -86,7 +94,7 @@ fn test_get_functionality() {
     println!("✓ get() returns error for non-existent properties");
 }
 
-#[cfg(feature = "builder")]
+#[cfg(all(feature = "builder", feature = "service-protocol"))]
 mod write_tests {
     use super::*;
 
@@ -228,8 +236,8 @@ fn test_api_completeness() {
     let _: String = rsproperties::get_or("test", "default".to_string());
     let _ = rsproperties::get::<String>("test");
 
-    // Write functions (if builder feature enabled)
-    #[cfg(feature = "builder")]
+    // Write functions (if service-protocol feature enabled)
+    #[cfg(feature = "service-protocol")]
     {
         let _: Result<(), _> = rsproperties::set("test", "value");
     }
@@ -243,11 +251,11 @@ fn test_api_completeness() {
 
     println!("✓ All expected API functions are accessible");
 
-    #[cfg(feature = "builder")]
-    println!("  ✓ Builder feature enabled - write functions available");
+    #[cfg(feature = "service-protocol")]
+    println!("  ✓ Service protocol enabled - write functions available");
 
-    #[cfg(not(feature = "builder"))]
-    println!("  ⚠ Builder feature disabled - write functions not available");
+    #[cfg(not(feature = "service-protocol"))]
+    println!("  ⚠ Service protocol disabled - write functions not available");
 }
 
 #[test]
@@ -224,7 +224,7 @@ fn test_property_value_length_constraints() {
     println!("✓ Property value length constraint tests passed");
 }
 
-#[cfg(feature = "builder")]
+#[cfg(all(feature = "builder", feature = "service-protocol"))]
 mod builder_tests {
     use super::*;
 
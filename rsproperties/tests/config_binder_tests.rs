@@ -0,0 +1,95 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `ConfigBinder` reloads against a builder-owned properties directory.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::sync::Arc;
+
+use rsproperties::{ConfigBinder, Error, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[derive(Clone, Debug, PartialEq)]
+struct Config {
+    workers: u32,
+    name: String,
+}
+
+#[test]
+fn test_reload_tracks_changes() {
+    let dir = std::env::temp_dir().join(format!("rsprops_binder_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut props = SystemProperties::new_area(&dir).expect("new_area");
+
+    let base = Config {
+        workers: 4,
+        name: "default".to_owned(),
+    };
+    let binder = ConfigBinder::new(base.clone())
+        .bind_parsed("test.binder.workers", |c: &mut Config, v: u32| {
+            c.workers = v
+        })
+        .bind("test.binder.name", |c: &mut Config, v: &str| {
+            if v.len() > 8 {
+                return Err(Error::InvalidArgument("name too long".into()));
+            }
+            c.name = v.to_owned();
+            Ok(())
+        });
+
+    // Nothing set yet: the base config, nothing changed.
+    let update = binder.reload_from(&props).unwrap();
+    assert!(update.changed.is_empty());
+    assert_eq!(*update.snapshot, base);
+
+    props.add("test.binder.workers", "8").unwrap();
+    let update = binder.reload_from(&props).unwrap();
+    assert_eq!(update.changed, ["test.binder.workers"]);
+    assert_eq!(update.snapshot.workers, 8);
+    assert_eq!(update.snapshot.name, "default");
+    assert!(Arc::ptr_eq(&update.snapshot, &binder.snapshot()));
+
+    // Unchanged values keep the same snapshot.
+    let previous = binder.snapshot();
+    let update = binder.reload_from(&props).unwrap();
+    assert!(update.changed.is_empty());
+    assert!(Arc::ptr_eq(&update.snapshot, &previous));
+
+    // A rejected value fails the whole reload and keeps the old snapshot.
+    props.set("test.binder.workers", "16").unwrap();
+    props.add("test.binder.name", "much_too_long").unwrap();
+    assert!(matches!(
+        binder.reload_from(&props),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(Arc::ptr_eq(&binder.snapshot(), &previous));
+
+    props.set("test.binder.name", "ok").unwrap();
+    let update = binder.reload_from(&props).unwrap();
+    assert_eq!(update.changed, ["test.binder.workers", "test.binder.name"]);
+    assert_eq!(
+        *update.snapshot,
+        Config {
+            workers: 16,
+            name: "ok".to_owned()
+        }
+    );
+
+    // Clearing a property reverts its field to the base value.
+    props.set("test.binder.workers", "").unwrap();
+    let update = binder.reload_from(&props).unwrap();
+    assert_eq!(update.changed, ["test.binder.workers"]);
+    assert_eq!(update.snapshot.workers, 4);
+
+    // Unparseable values are `Error::Parse`, as with `get`.
+    props.set("test.binder.workers", "many").unwrap();
+    assert!(matches!(binder.reload_from(&props), Err(Error::Parse(_))));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
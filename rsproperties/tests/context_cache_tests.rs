@@ -0,0 +1,90 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The context cache: repeated reads of a name family skip the
+//! `property_info` walk, families the trie branches under are never
+//! memoized, and the capacity knob bounds and disables it.
+//!
+//! Own test binary because the capacity is process-wide.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::SystemProperties;
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "persist.sys. u:object_r:system_prop:s0 prefix string\n\
+    ro.product. u:object_r:product_prop:s0 prefix string\n\
+    ro.product.vendor. u:object_r:vendor_prop:s0 prefix string\n\
+    test.int. u:object_r:test_prop:s0 prefix int\n";
+
+#[test]
+fn test_context_cache() {
+    let dir = std::env::temp_dir().join(format!("rsprops_context_cache_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    for (name, value) in [
+        ("persist.sys.timezone", "Asia/Seoul"),
+        ("persist.sys.locale", "ko-KR"),
+        ("ro.product.model", "Board"),
+        ("ro.product.vendor.model", "Vendor Board"),
+    ] {
+        writer.set(name, value).unwrap();
+    }
+    let reader = SystemProperties::open(&dir).unwrap();
+    let stats = reader.context_cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses), (0, 0));
+
+    // One walk for the family, then hits.
+    for _ in 0..3 {
+        assert_eq!(
+            reader.get_with_result("persist.sys.timezone").unwrap(),
+            "Asia/Seoul"
+        );
+        assert_eq!(
+            reader.get_with_result("persist.sys.locale").unwrap(),
+            "ko-KR"
+        );
+    }
+    let stats = reader.context_cache_stats().unwrap();
+    assert_eq!((stats.misses, stats.hits, stats.len), (1, 5, 1));
+
+    // `ro.product.vendor.` branches below `ro.product.`: every read walks,
+    // and each still lands in its own context.
+    for _ in 0..2 {
+        assert_eq!(reader.get_with_result("ro.product.model").unwrap(), "Board");
+        assert_eq!(
+            reader.get_with_result("ro.product.vendor.model").unwrap(),
+            "Vendor Board"
+        );
+    }
+    assert_eq!(reader.context_cache_stats().unwrap().uncacheable, 4);
+    assert_eq!(
+        reader.describe("ro.product.vendor.model").unwrap().context,
+        Some("u:object_r:vendor_prop:s0".to_owned())
+    );
+    // Types come through the cache too.
+    assert_eq!(reader.property_type("test.int.a").unwrap(), Some("int"));
+    assert_eq!(reader.property_type("test.int.b").unwrap(), Some("int"));
+
+    // A smaller capacity evicts on the next new family.
+    rsproperties::set_context_cache_capacity(1);
+    assert_eq!(reader.context_cache_stats().unwrap().len, 2);
+    assert_eq!(
+        reader.property_type("vendor.x.mode").unwrap(),
+        Some("string")
+    );
+    assert_eq!(reader.context_cache_stats().unwrap().len, 1);
+
+    // Off: lookups bypass the cache entirely.
+    rsproperties::set_context_cache_capacity(0);
+    let before = reader.context_cache_stats().unwrap();
+    reader.get_with_result("persist.sys.locale").unwrap();
+    assert_eq!(reader.context_cache_stats().unwrap(), before);
+    rsproperties::set_context_cache_capacity(rsproperties::DEFAULT_CONTEXT_CACHE_CAPACITY);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
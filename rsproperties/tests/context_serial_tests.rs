@@ -0,0 +1,98 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-context serials: once enabled, a change bumps only the serial of
+//! the area it lands in, and `wait_any_in` waiters sleep through changes
+//! to other contexts. Without them `wait_any_in` follows the global serial.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::time::Duration;
+
+use rsproperties::{SystemProperties, Timespec};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test.radio. u:object_r:radio_prop:s0 prefix string\n\
+    test.wifi. u:object_r:wifi_prop:s0 prefix string\n";
+
+const SHORT: Timespec = Timespec {
+    tv_sec: 0,
+    tv_nsec: 50_000_000,
+};
+
+#[test]
+fn test_context_serials() {
+    let dir = std::env::temp_dir().join(format!("rsprops_context_serial_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    let reader = SystemProperties::open(&dir).unwrap();
+
+    // Not kept: the filter falls back to the global serial.
+    assert!(!reader.has_context_serials());
+    writer.set("test.wifi.ssid", "home").unwrap();
+    assert_eq!(
+        reader.context_serial_of("test.radio.").unwrap(),
+        reader.context_serial()
+    );
+    assert!(writer
+        .debug_state()
+        .unwrap()
+        .areas
+        .iter()
+        .all(|area| area.serial.is_none()));
+
+    writer.enable_context_serials().unwrap();
+    assert!(reader.has_context_serials());
+    let radio = reader.context_serial_of("test.radio.").unwrap();
+    let wifi = reader.context_serial_of("test.wifi.ssid").unwrap();
+
+    writer.set("test.wifi.ssid", "office").unwrap();
+    writer.set("test.wifi.band", "5").unwrap();
+    assert_eq!(reader.context_serial_of("test.radio.mode").unwrap(), radio);
+    assert_eq!(reader.context_serial_of("test.wifi.").unwrap(), wifi + 2);
+    assert_eq!(
+        reader.wait_any_in("test.radio.", Some(radio), Some(&SHORT)),
+        None,
+        "a change to another context must not end the wait"
+    );
+
+    // A change to the context wakes a waiter already asleep on it.
+    std::thread::scope(|scope| {
+        let waiter = scope.spawn(|| {
+            let timeout = Timespec {
+                tv_sec: 10,
+                tv_nsec: 0,
+            };
+            reader.wait_any_in("test.radio.", Some(radio), Some(&timeout))
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        writer.set("test.radio.mode", "nr").unwrap();
+        assert_eq!(waiter.join().unwrap(), Some(radio + 1));
+    });
+
+    // Inside a batch every change still bumps its context.
+    let radio = reader.context_serial_of("test.radio.").unwrap();
+    writer
+        .batch(|props| {
+            props.set("test.radio.mode", "lte")?;
+            props.set("test.radio.band", "3")
+        })
+        .unwrap();
+    assert_eq!(
+        reader.wait_any_in("test.radio.", Some(radio), None),
+        Some(radio + 2)
+    );
+
+    let state = writer.debug_state().unwrap();
+    let radio_area = state
+        .areas
+        .iter()
+        .find(|area| area.context == "u:object_r:radio_prop:s0")
+        .unwrap();
+    assert_eq!(radio_area.serial, Some(radio + 2));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
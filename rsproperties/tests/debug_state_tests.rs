@@ -0,0 +1,44 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::debug_state`: it lists only the areas an instance
+//! has mapped, follows the global serial and finds no dirty entries in a
+//! quiet store.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::SystemProperties;
+
+#[test]
+fn test_debug_state() {
+    let (dir, mut writer) = rsproperties::quickstart::demo_store().unwrap();
+
+    let state = writer.debug_state().unwrap();
+    assert_eq!(state.global_serial, writer.context_serial());
+    assert!(state.areas.iter().all(|area| area.writable));
+    assert!(!state.has_dirty());
+
+    // A reader maps areas on first use only.
+    let reader = SystemProperties::open(dir.path()).unwrap();
+    let state = reader.debug_state().unwrap();
+    assert!(state.areas.is_empty());
+    assert_eq!(state.contexts, writer.debug_state().unwrap().contexts);
+
+    assert_eq!(reader.get_with_result("demo.count").unwrap(), "3");
+    let state = reader.debug_state().unwrap();
+    assert_eq!(state.areas.len(), 1);
+    let area = &state.areas[0];
+    assert_eq!(area.context, "u:object_r:demo_prop:s0");
+    assert!(!area.writable);
+    assert_eq!(area.generations, 1);
+    // demo.greeting, demo.count, demo.enabled.
+    assert_eq!(area.properties, 3);
+    assert!(area.dirty.is_empty() && area.error.is_none());
+
+    let before = state.global_serial;
+    writer.set("demo.greeting", "hi").unwrap();
+    let state = reader.debug_state().unwrap();
+    assert_ne!(state.global_serial, before);
+    let text = state.to_string();
+    assert!(text.contains("u:object_r:demo_prop:s0 (ro, generation 1): 3 properties, 0 dirty"));
+}
@@ -146,6 +146,7 @@ fn test_long_property_names() {
 
 /// Test edge cases with property values
 #[test]
+#[cfg(feature = "service-protocol")]
 fn test_property_value_edge_cases() {
     setup_edge_test_env();
 
@@ -204,7 +205,7 @@ fn test_property_value_edge_cases() {
 
 /// Test maximum length property values
 #[test]
-#[cfg(feature = "builder")]
+#[cfg(all(feature = "builder", feature = "service-protocol"))]
 fn test_maximum_length_values() {
     setup_edge_test_env();
 
@@ -277,7 +278,7 @@ fn test_error_handling() {
         println!("Error message for non-existent property: {error_msg}");
     }
 
-    #[cfg(feature = "builder")]
+    #[cfg(all(feature = "builder", feature = "service-protocol"))]
     {
         // Test set with invalid inputs
         let invalid_cases = vec![("", "some_value", "empty property name")];
@@ -298,7 +299,7 @@ fn test_error_handling() {
 
 /// Test behavior with null bytes and other special characters
 #[test]
-#[cfg(feature = "builder")]
+#[cfg(all(feature = "builder", feature = "service-protocol"))]
 fn test_null_bytes_and_special_chars() {
     setup_edge_test_env();
 
@@ -0,0 +1,61 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::foreach` and `rsproperties::list`.
+//!
+//! Own test binary because `list` reads the process-global instance,
+//! which `rsproperties::init` latches once per process.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n\
+    other. u:object_r:other_prop:s0 prefix string\n";
+
+#[test]
+fn test_foreach_visits_every_property() {
+    let dir = std::env::temp_dir().join(format!("rsprops_foreach_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut props = SystemProperties::new_area(&dir).expect("new_area");
+    let mut visited = 0;
+    props.foreach(|_, _| visited += 1).unwrap();
+    assert_eq!(visited, 0);
+
+    // Across two context areas and the default one, with a long value
+    // stored out of line.
+    let long = "l".repeat(200);
+    let mut expected = vec![
+        ("test.b".to_owned(), "2".to_owned()),
+        ("test.a".to_owned(), "1".to_owned()),
+        ("test.c.deep.er".to_owned(), "3".to_owned()),
+        ("other.x".to_owned(), "5".to_owned()),
+        ("no_context_prop".to_owned(), "6".to_owned()),
+        ("ro.test.long".to_owned(), long),
+    ];
+    for (name, value) in &expected {
+        props.add(name, value).unwrap();
+    }
+    props.set("test.a", "updated").unwrap();
+    expected[1].1 = "updated".to_owned();
+
+    let mut seen = Vec::new();
+    props
+        .foreach(|name, value| seen.push((name.to_owned(), value.to_owned())))
+        .unwrap();
+    seen.sort();
+    expected.sort();
+    assert_eq!(seen, expected);
+
+    // `list` walks the global instance and sorts by name.
+    rsproperties::init(PropertyConfig::with_properties_dir(&dir));
+    assert_eq!(rsproperties::list().unwrap(), expected);
+
+    drop(props);
+    let _ = std::fs::remove_dir_all(&dir);
+}
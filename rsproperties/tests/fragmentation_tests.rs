@@ -0,0 +1,89 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::fragmentation_report`: a fresh store has no
+//! unreachable bytes, and an add that runs out of room after allocating
+//! its entry shows up as unreachable space in its area only.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, FragmentationReport};
+
+const BUILD_CONTEXT: &str = "u:object_r:build_prop:s0";
+
+fn check_accounting(report: &FragmentationReport) {
+    for area in &report.areas {
+        assert_eq!(
+            area.used,
+            area.live + area.padding + area.unreachable,
+            "{area:?}"
+        );
+        assert_eq!(area.free(), area.capacity - area.used);
+    }
+}
+
+#[test]
+fn test_fragmentation_report() {
+    let (_dir, mut props) = rsproperties::quickstart::demo_store().unwrap();
+
+    let report = props.fragmentation_report().unwrap();
+    check_accounting(&report);
+    assert_eq!(report.unreachable(), 0);
+    let contexts: Vec<_> = report.areas.iter().map(|a| a.context.as_str()).collect();
+    let mut sorted = contexts.clone();
+    sorted.sort();
+    assert_eq!(contexts, sorted);
+    let build = report
+        .areas
+        .iter()
+        .find(|a| a.context == BUILD_CONTEXT)
+        .unwrap();
+    assert_eq!((build.properties, build.long_values), (2, 0));
+    // "ro", "product", "model", "build", "version", "sdk".
+    assert_eq!(build.trie_nodes, 6);
+
+    let long_value = "v".repeat(200);
+    props.add("ro.fill.long", &long_value).unwrap();
+    let build = props
+        .fragmentation_report()
+        .unwrap()
+        .areas
+        .into_iter()
+        .find(|a| a.context == BUILD_CONTEXT)
+        .unwrap();
+    assert_eq!((build.properties, build.long_values), (3, 1));
+    assert_eq!(build.unreachable, 0);
+
+    // Fill the area until an entry for one more name still fits but its
+    // out-of-line value does not: the entry is allocated, then the add
+    // fails and nothing links it. The node and entry of "ro.fill.z" take
+    // 132 bytes, its value 204; each filler takes about 132, so stopping
+    // below 336 free leaves room for the first part only.
+    let mut i = 0;
+    loop {
+        let report = props.fragmentation_report().unwrap();
+        let build = report
+            .areas
+            .iter()
+            .find(|a| a.context == BUILD_CONTEXT)
+            .unwrap();
+        if build.free() < 336 {
+            break;
+        }
+        props.add(&format!("ro.fill.{i}"), "1").unwrap();
+        i += 1;
+    }
+    let err = props.add("ro.fill.z", &long_value).unwrap_err();
+    assert!(matches!(err, Error::AreaFull(_)), "{err:?}");
+
+    let report = props.fragmentation_report().unwrap();
+    check_accounting(&report);
+    let build = report
+        .areas
+        .iter()
+        .find(|a| a.context == BUILD_CONTEXT)
+        .unwrap();
+    assert!(build.unreachable > 0, "{build:?}");
+    assert_eq!(report.unreachable(), build.unreachable);
+    assert!(props.get_with_result("ro.fill.z").is_err());
+}
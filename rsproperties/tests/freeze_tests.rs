@@ -0,0 +1,59 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::freeze` snapshots.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::SystemProperties;
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n\
+    other. u:object_r:other_prop:s0 prefix string\n";
+
+#[test]
+fn test_freeze_copies_every_area() {
+    let dir = std::env::temp_dir().join(format!("rsprops_freeze_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut props = SystemProperties::new_area(&dir).expect("new_area");
+    assert!(props.freeze().unwrap().is_empty());
+
+    // Siblings and nested segments exercise every trie link the walk
+    // follows (left/right/children), across two context areas.
+    let expected = [
+        ("test.b", "2"),
+        ("test.a", "1"),
+        ("test.c.deep.er", "3"),
+        ("test.c", "4"),
+        ("other.x", "5"),
+        ("no_context_prop", "6"),
+    ];
+    for (name, value) in expected {
+        props.add(name, value).unwrap();
+    }
+
+    let frozen = props.freeze().unwrap();
+    assert_eq!(frozen.len(), expected.len());
+    for (name, value) in expected {
+        assert_eq!(frozen.get(name), Some(value), "{name}");
+    }
+    assert_eq!(frozen.serial(), props.context_serial());
+    assert_eq!(frozen.get_or("test.a", 0u32), 1);
+    assert!(matches!(
+        frozen.get_with_result("test.missing"),
+        Err(rsproperties::Error::NotFound(_))
+    ));
+
+    // Later writes do not reach the snapshot.
+    props.set("test.a", "changed").unwrap();
+    props.add("test.new", "7").unwrap();
+    assert_eq!(frozen.get("test.a"), Some("1"));
+    assert!(!frozen.contains("test.new"));
+    assert_ne!(frozen.serial(), props.context_serial());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
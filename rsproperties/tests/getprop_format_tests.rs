@@ -0,0 +1,60 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `format_getprop` prints what Android's `getprop` prints: sorted
+//! `[name]: [value]` lines with nothing escaped, and the declared type or
+//! the context instead of the value for `-T` / `-Z`.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{GetpropColumn, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test.radio. u:object_r:radio_prop:s0 prefix string\n\
+    test.radio.band u:object_r:radio_prop:s0 exact int\n";
+
+fn format(props: &SystemProperties, column: GetpropColumn) -> String {
+    let mut out = Vec::new();
+    props.format_getprop(&mut out, column).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_format_getprop() {
+    let dir = std::env::temp_dir().join(format!("rsprops_getprop_format_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set("test.radio.band", "3").unwrap();
+    writer.set("test.radio.Mode", "a]b").unwrap();
+    writer.set("test.other", "line1\nline2").unwrap();
+    writer.set("test.empty", "").unwrap();
+    let reader = SystemProperties::open(&dir).unwrap();
+
+    // Bytewise order puts `M` before `b`; values are printed raw.
+    assert_eq!(
+        format(&reader, GetpropColumn::Value),
+        "[test.empty]: []\n\
+         [test.other]: [line1\nline2]\n\
+         [test.radio.Mode]: [a]b]\n\
+         [test.radio.band]: [3]\n"
+    );
+    assert_eq!(
+        format(&reader, GetpropColumn::Type),
+        "[test.empty]: [string]\n\
+         [test.other]: [string]\n\
+         [test.radio.Mode]: [string]\n\
+         [test.radio.band]: [int]\n"
+    );
+    assert_eq!(
+        format(&reader, GetpropColumn::Context),
+        "[test.empty]: [u:object_r:default_prop:s0]\n\
+         [test.other]: [u:object_r:default_prop:s0]\n\
+         [test.radio.Mode]: [u:object_r:radio_prop:s0]\n\
+         [test.radio.band]: [u:object_r:radio_prop:s0]\n"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
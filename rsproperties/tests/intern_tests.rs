@@ -0,0 +1,50 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! The name interner: snapshots share their names, and a full table drops
+//! names nobody holds before it stops interning.
+//!
+//! Own test binary because the interner and its capacity are
+//! process-global.
+
+#![cfg(all(feature = "builder", feature = "intern", not(target_os = "android")))]
+
+use std::sync::Arc;
+
+#[test]
+fn test_interned_snapshots() {
+    let (_dir, props) = rsproperties::quickstart::demo_store().unwrap();
+
+    let first = props.freeze_interned().unwrap();
+    let second = props.freeze_interned().unwrap();
+    let frozen = props.freeze().unwrap();
+    assert_eq!(first.len(), frozen.len());
+    for (name, value) in &first {
+        assert_eq!(frozen.get(name), Some(value.as_str()));
+        let (other, _) = second.get_key_value(name.as_ref()).unwrap();
+        assert!(Arc::ptr_eq(name, other), "{name} interned twice");
+    }
+    let stats = rsproperties::intern_stats();
+    assert_eq!(stats.misses, first.len() as u64);
+    assert_eq!(stats.hits, first.len() as u64);
+
+    // Full table: names still held stay, so a new name is not interned.
+    rsproperties::set_intern_capacity(first.len());
+    let extra = rsproperties::intern("test.intern.extra");
+    assert!(!Arc::ptr_eq(
+        &extra,
+        &rsproperties::intern("test.intern.extra")
+    ));
+    assert_eq!(rsproperties::intern_stats().uninterned, 2);
+
+    // Once the snapshots are gone their names make room.
+    drop((first, second));
+    let extra = rsproperties::intern("test.intern.extra");
+    assert!(Arc::ptr_eq(
+        &extra,
+        &rsproperties::intern("test.intern.extra")
+    ));
+    assert_eq!(rsproperties::intern_stats().len, 1);
+
+    rsproperties::set_intern_capacity(rsproperties::DEFAULT_INTERN_CAPACITY);
+}
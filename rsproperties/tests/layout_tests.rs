@@ -0,0 +1,75 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A writer and the global reader sharing a non-default [`Layout`].
+//!
+//! Own test binary because `rsproperties::init` latches the directory and
+//! layout once per process.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Layout, PropertyConfig, SystemProperties};
+
+mod common;
+use common::contexts_trie;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_custom_layout_round_trip() {
+    let dir = std::env::temp_dir().join(format!("rsprops_layout_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let layout = Layout::default()
+        .with_property_info_filename("container_property_info")
+        .with_serial_filename("container_serial")
+        .with_serial_context("u:object_r:container_serial:s0");
+    let data = contexts_trie(&dir, CONTEXTS);
+    std::fs::write(layout.property_info_path(&dir), data).unwrap();
+
+    let mut writer = SystemProperties::new_area_with_layout(&dir, &layout).unwrap();
+    writer.add("test.layout", "flat").unwrap();
+
+    assert!(dir.join("container_serial").is_file());
+    assert!(!dir.join("properties_serial").exists());
+    assert!(!dir.join("property_info").exists());
+
+    // A writer with the default layout does not find the trie.
+    drop(writer);
+    assert!(SystemProperties::new_area(&dir).is_err());
+    let mut writer = SystemProperties::new_area_with_layout(&dir, &layout).unwrap();
+    writer.add("test.layout", "flat").unwrap();
+
+    rsproperties::try_init(
+        PropertyConfig::builder()
+            .properties_dir(&dir)
+            .layout(layout.clone())
+            .build(),
+    )
+    .unwrap();
+    assert_eq!(rsproperties::layout(), &layout);
+    let value: String = rsproperties::get("test.layout").unwrap();
+    assert_eq!(value, "flat");
+
+    // The layout is latched like the directory.
+    assert!(
+        rsproperties::try_init(PropertyConfig::builder().layout(Layout::default()).build())
+            .is_err()
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_try_init_rejects_invalid_layout() {
+    let err = rsproperties::try_init(
+        PropertyConfig::builder()
+            .layout(Layout::default().with_serial_filename("../escape"))
+            .build(),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(err, rsproperties::Error::InvalidArgument(_)),
+        "{err}"
+    );
+}
@@ -0,0 +1,57 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Long (`ro.`, `PROP_VALUE_MAX` bytes or more) values: written through
+//! `add`, read back whole, and reported by `is_long` and `value_max`.
+//! A store's other read-only prefixes take them too.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{value_max, Error, SystemProperties, PROP_VALUE_MAX};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "vendor. u:object_r:vendor_prop:s0 prefix string\n\
+    ro. u:object_r:build_prop:s0 prefix string\n";
+
+#[test]
+fn test_long_values() {
+    assert_eq!(value_max("ro.build.fingerprint"), None);
+    assert_eq!(value_max("vendor.mode"), Some(PROP_VALUE_MAX - 1));
+
+    let dir = std::env::temp_dir().join(format!("rsprops_long_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+
+    // Exactly PROP_VALUE_MAX bytes is the first length stored out of line.
+    let boundary = "b".repeat(PROP_VALUE_MAX);
+    let long = "l".repeat(4 * PROP_VALUE_MAX);
+    let short = "s".repeat(PROP_VALUE_MAX - 1);
+    writer.add("ro.long.boundary", &boundary).unwrap();
+    writer.add("ro.long.value", &long).unwrap();
+    writer.add("vendor.long.short", &short).unwrap();
+    assert!(matches!(
+        writer.add("vendor.long.value", &boundary),
+        Err(Error::InvalidArgument(_))
+    ));
+    // Write-once prefixes of the store are stored long as `ro.` is.
+    writer.set_read_only_prefixes(["vendor.fixed."]);
+    writer.add("vendor.fixed.value", &long).unwrap();
+
+    let reader = SystemProperties::open(&dir).unwrap();
+    for (name, value, is_long) in [
+        ("ro.long.boundary", &boundary, true),
+        ("ro.long.value", &long, true),
+        ("vendor.long.short", &short, false),
+        ("vendor.fixed.value", &long, true),
+    ] {
+        assert_eq!(&reader.get_with_result(name).unwrap(), value, "{name}");
+        let handle = reader.find(name).unwrap().unwrap();
+        assert_eq!(reader.is_long(&handle), Some(is_long), "{name}");
+    }
+    assert!(reader.find("vendor.long.value").unwrap().is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
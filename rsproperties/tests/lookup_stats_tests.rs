@@ -0,0 +1,69 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Trie lookup counters: depth, comparisons and misses are recorded only
+//! while recording is enabled.
+//!
+//! The counters are process-wide, so the phases run sequentially in one
+//! #[test] fn.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::SystemProperties;
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_lookup_stats() {
+    let dir = std::env::temp_dir().join(format!("rsprops_lookup_stats_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut props = SystemProperties::new_area(&dir).unwrap();
+    for name in ["test.m", "test.a", "test.z", "test.deep.er.name"] {
+        props.set(name, "1").unwrap();
+    }
+    let reader = SystemProperties::open(&dir).unwrap();
+
+    // Disabled by default.
+    rsproperties::reset_lookup_stats();
+    reader.get_with_result("test.m").unwrap();
+    assert_eq!(rsproperties::lookup_stats().lookups, 0);
+
+    rsproperties::enable_lookup_stats(true);
+    reader.get_with_result("test.deep.er.name").unwrap();
+    let stats = rsproperties::lookup_stats();
+    assert_eq!(stats.lookups, 1);
+    assert_eq!(stats.misses, 0);
+    assert_eq!(stats.depth_sum, 4);
+    assert_eq!(stats.max_depth, 4);
+    assert!(stats.comparisons_sum >= stats.depth_sum);
+    assert_eq!(
+        stats.left_turns + stats.right_turns + stats.depth_sum,
+        stats.comparisons_sum
+    );
+
+    // A miss on the last segment still counts the levels it reached.
+    assert!(reader.get_with_result("test.missing").is_err());
+    let stats = rsproperties::lookup_stats();
+    assert_eq!(stats.lookups, 2);
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.depth_sum, 6);
+    assert_eq!(stats.hit_ratio(), Some(0.5));
+    assert_eq!(stats.mean_depth(), Some(3.0));
+    assert!(stats.comparisons_per_level().unwrap() >= 1.0);
+
+    rsproperties::enable_lookup_stats(false);
+    reader.get_with_result("test.a").unwrap();
+    assert_eq!(rsproperties::lookup_stats().lookups, 2);
+    rsproperties::reset_lookup_stats();
+    assert_eq!(
+        rsproperties::lookup_stats(),
+        rsproperties::LookupStats::default()
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
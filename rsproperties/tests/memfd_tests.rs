@@ -0,0 +1,134 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! A store kept in memfds: the writer creates no files, readers map the
+//! memfds it hands out and see its updates, and memfds that could still
+//! shrink under a reader are refused.
+
+#![cfg(all(feature = "builder", target_os = "linux"))]
+
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::OwnedFd;
+
+use rsproperties::{Error, Layout, SystemProperties};
+
+mod common;
+use common::contexts_trie;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+/// An unsealed memfd with the same contents as `fd`.
+fn unsealed_copy(fd: &OwnedFd) -> OwnedFd {
+    let mut source = File::from(fd.try_clone().unwrap());
+    let copy =
+        rustix::fs::memfd_create("rsproperties-test", rustix::fs::MemfdFlags::CLOEXEC).unwrap();
+    std::io::copy(&mut source, &mut File::from(copy.try_clone().unwrap())).unwrap();
+    copy
+}
+
+#[test]
+fn test_memfd_store() {
+    let dir = std::env::temp_dir().join(format!("rsprops_memfd_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let property_info = contexts_trie(&dir, CONTEXTS);
+    let layout = Layout::default();
+
+    let mut writer = SystemProperties::new_memfd_area(&property_info, &layout).unwrap();
+    writer.set("test.memfd.mode", "on").unwrap();
+    // Only the contexts file written above: the store lives in memfds.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let areas = writer.memfd_areas().unwrap();
+    let mut names: Vec<_> = areas.iter().map(|(name, _)| name.as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(
+        names,
+        [
+            "properties_serial",
+            "property_info",
+            "u:object_r:default_prop:s0",
+            "u:object_r:test_prop:s0",
+        ]
+    );
+
+    // Readers get read-only descriptors, whatever the kernel's seals.
+    for (name, fd) in writer.memfd_areas().unwrap() {
+        use std::os::fd::AsRawFd;
+        let info =
+            std::fs::read_to_string(format!("/proc/self/fdinfo/{}", fd.as_raw_fd())).unwrap();
+        let flags = info
+            .lines()
+            .find_map(|line| line.strip_prefix("flags:"))
+            .unwrap();
+        let flags = u32::from_str_radix(flags.trim(), 8).unwrap();
+        assert_eq!(flags & 0o3, 0, "{name} is not O_RDONLY");
+    }
+
+    let reader = SystemProperties::from_memfd_areas(areas, &layout).unwrap();
+    assert_eq!(reader.get_with_result("test.memfd.mode").unwrap(), "on");
+    assert_eq!(
+        reader.property_type("test.memfd.mode").unwrap(),
+        Some("string")
+    );
+
+    // Updates and new properties are visible through the shared mapping.
+    let serial = reader.context_serial();
+    writer.set("test.memfd.mode", "off").unwrap();
+    writer.set("test.memfd.added", "1").unwrap();
+    assert_eq!(reader.get_with_result("test.memfd.mode").unwrap(), "off");
+    assert_eq!(reader.get_with_result("test.memfd.added").unwrap(), "1");
+    assert_ne!(reader.context_serial(), serial);
+
+    // A reader can pass its memfds on in turn.
+    let relay = SystemProperties::from_memfd_areas(reader.memfd_areas().unwrap(), &layout).unwrap();
+    assert_eq!(relay.get_with_result("test.memfd.added").unwrap(), "1");
+
+    // Memfds that are not sealed against shrinking are refused.
+    for target in ["properties_serial", "property_info"] {
+        let areas = writer
+            .memfd_areas()
+            .unwrap()
+            .into_iter()
+            .map(|(name, fd)| {
+                let fd = if name == target {
+                    unsealed_copy(&fd)
+                } else {
+                    fd
+                };
+                (name, fd)
+            })
+            .collect();
+        let result = SystemProperties::from_memfd_areas(areas, &layout);
+        assert!(
+            matches!(result, Err(Error::FileValidation(_))),
+            "{target}: {:?}",
+            result.err()
+        );
+    }
+    // So is a set without the trie.
+    let areas = writer
+        .memfd_areas()
+        .unwrap()
+        .into_iter()
+        .filter(|(name, _)| name != "property_info")
+        .collect();
+    assert!(matches!(
+        SystemProperties::from_memfd_areas(areas, &layout),
+        Err(Error::NotFound(_))
+    ));
+
+    // File-only features.
+    assert!(matches!(writer.fork_cow(), Err(Error::InvalidArgument(_))));
+    File::create(dir.join("property_info"))
+        .unwrap()
+        .write_all(&property_info)
+        .unwrap();
+    let files = SystemProperties::new_area(&dir).unwrap();
+    assert!(matches!(
+        files.memfd_areas(),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,97 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `Mirror` between two property directories: initial full sync, then
+//! only changed properties, prefix rewriting, and both conflict policies.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::time::Duration;
+
+use rsproperties::mirror::{ConflictPolicy, Mirror, MirrorSink};
+use rsproperties::SystemProperties;
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_mirror() {
+    let root = std::env::temp_dir().join(format!("rsprops_mirror_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let (source_dir, dest_dir) = (root.join("source"), root.join("dest"));
+    build_property_info(&source_dir, CONTEXTS);
+    build_property_info(&dest_dir, CONTEXTS);
+
+    let mut source = SystemProperties::new_area(&source_dir).unwrap();
+    source.set("test.guest.mode", "a").unwrap();
+    source.set("test.guest.level", "1").unwrap();
+    source.set("test.guest.secret.key", "hidden").unwrap();
+    source.set("test.other", "x").unwrap();
+    let reader = SystemProperties::open(&source_dir).unwrap();
+
+    let mut dest = SystemProperties::new_area(&dest_dir).unwrap();
+    dest.set("test.host.guest.level", "preset").unwrap();
+
+    let mut mirror = Mirror::new()
+        .exclude("test.guest.secret.")
+        .rewrite("test.guest.", "test.host.guest.")
+        .conflict_policy(ConflictPolicy::DestinationWins);
+
+    // Initial sync: everything mapped, except the pre-existing value.
+    let report = mirror.sync(&reader, &mut dest).unwrap();
+    assert_eq!(report.applied, ["test.host.guest.mode"]);
+    assert_eq!(report.conflicts, ["test.host.guest.level"]);
+    assert!(report.failed.is_empty());
+    assert_eq!(dest.get("test.host.guest.mode").unwrap().unwrap(), "a");
+    assert_eq!(
+        dest.get("test.host.guest.level").unwrap().unwrap(),
+        "preset"
+    );
+    assert_eq!(dest.get("test.host.guest.secret.key").unwrap(), None);
+    assert_eq!(dest.get("test.other").unwrap(), None);
+
+    // Nothing changed: nothing to do, and waiting times out.
+    assert!(mirror.sync(&reader, &mut dest).unwrap().is_empty());
+    assert!(mirror
+        .wait_and_sync(&reader, &mut dest, Some(Duration::from_millis(10)))
+        .unwrap()
+        .is_none());
+
+    // Only the changed property is replayed.
+    source.set("test.guest.mode", "b").unwrap();
+    let report = mirror
+        .wait_and_sync(&reader, &mut dest, Some(Duration::from_secs(5)))
+        .unwrap()
+        .expect("source changed");
+    assert_eq!(report.applied, ["test.host.guest.mode"]);
+    assert!(report.conflicts.is_empty());
+
+    // A local edit of a mirrored property is a conflict too.
+    dest.set("test.host.guest.mode", "local").unwrap();
+    source.set("test.guest.mode", "c").unwrap();
+    let report = mirror.sync(&reader, &mut dest).unwrap();
+    assert_eq!(report.conflicts, ["test.host.guest.mode"]);
+    assert!(report.applied.is_empty());
+    assert_eq!(dest.get("test.host.guest.mode").unwrap().unwrap(), "local");
+
+    // The source overwrites conflicts under the default policy.
+    let mut overwrite = Mirror::new().rewrite("test.guest.", "test.host.guest.");
+    let report = overwrite.sync(&reader, &mut dest).unwrap();
+    let mut applied = report.applied.clone();
+    applied.sort();
+    assert_eq!(
+        applied,
+        [
+            "test.host.guest.level",
+            "test.host.guest.mode",
+            "test.host.guest.secret.key"
+        ]
+    );
+    assert_eq!(report.conflicts.len(), 2);
+    assert_eq!(dest.get("test.host.guest.mode").unwrap().unwrap(), "c");
+    assert_eq!(dest.get("test.host.guest.level").unwrap().unwrap(), "1");
+
+    let _ = std::fs::remove_dir_all(&root);
+}
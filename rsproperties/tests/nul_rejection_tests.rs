@@ -11,36 +11,18 @@
 
 #![cfg(all(feature = "builder", not(target_os = "android")))]
 
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
-
 use rsproperties::{build_trie, PropertyInfoEntry, SystemProperties};
 
-fn build_property_info(dir: &Path) {
-    std::fs::create_dir_all(dir).unwrap();
-
-    let contexts_path = dir.join("property_contexts");
-    File::create(&contexts_path)
-        .unwrap()
-        .write_all(b"test. u:object_r:test_prop:s0 prefix string\n")
-        .unwrap();
-
-    let (entries, errors) = PropertyInfoEntry::parse_from_file(&contexts_path, false).unwrap();
-    assert!(errors.is_empty(), "parse errors: {errors:?}");
+mod common;
+use common::build_property_info;
 
-    let data = build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap();
-    File::create(dir.join("property_info"))
-        .unwrap()
-        .write_all(&data)
-        .unwrap();
-}
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
 
 #[test]
 fn test_interior_nul_rejected_and_area_stays_healthy() {
     let dir = std::env::temp_dir().join(format!("rsprops_nul_{}", std::process::id()));
     let _ = std::fs::remove_dir_all(&dir);
-    build_property_info(&dir);
+    build_property_info(&dir, CONTEXTS);
 
     let mut props = SystemProperties::new_area(&dir).expect("new_area");
 
@@ -79,15 +61,16 @@ fn test_interior_nul_rejected_and_area_stays_healthy() {
 #[test]
 fn test_build_trie_rejects_nul_in_context_and_type() {
     // `build_trie` writes contexts/types into the serialized C-string
-    // pool; a NUL would desync the on-disk format for every consumer.
-    let entries = vec![PropertyInfoEntry::new(
+    // pool; a NUL would desync the on-disk format for every consumer. A
+    // NUL context is not a valid SELinux context, so the entry itself
+    // already refuses it.
+    assert!(PropertyInfoEntry::new(
         "test.prop".to_string(),
         "u:object_r:bad\0ctx:s0".to_string(),
         "string",
         false,
     )
-    .unwrap()];
-    assert!(build_trie(&entries, "u:object_r:default:s0", "string").is_err());
+    .is_err());
 
     // The defaults bypass `add_to_trie` and are interned directly, so they
     // have their own gate at `build_trie`'s entry.
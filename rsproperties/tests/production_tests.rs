@@ -231,7 +231,7 @@ fn test_error_conditions() {
 }
 
 // Tests that require the builder feature
-#[cfg(feature = "builder")]
+#[cfg(all(feature = "builder", feature = "service-protocol"))]
 mod builder_tests {
     use super::*;
 
@@ -0,0 +1,105 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::{import_prop_file, export_prop_file}`: seeding a
+//! store from build.prop-style files and dumping it back to one.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::SystemProperties;
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "vendor. u:object_r:vendor_prop:s0 prefix string\n\
+    ro. u:object_r:build_prop:s0 prefix string\n";
+
+#[test]
+fn test_import_and_export() {
+    let dir = std::env::temp_dir().join(format!("rsprops_prop_io_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let props_dir = dir.join("props");
+    build_property_info(&props_dir, CONTEXTS);
+
+    let base = dir.join("base.prop");
+    std::fs::write(
+        &base,
+        "ro.io.model=base\n\
+         ro.io.board=base\n\
+         vendor.io.mode=base\n",
+    )
+    .unwrap();
+    let build = dir.join("build.prop");
+    std::fs::write(
+        &build,
+        format!(
+            "# Imported first, then overridden below.\n\
+             import {}\n\
+             ro.io.model=device\n\
+             vendor.io.level=3\n",
+            base.display()
+        ),
+    )
+    .unwrap();
+
+    let mut writer = SystemProperties::new_area(&props_dir).unwrap();
+    writer.set("ro.io.board", "preset").unwrap();
+    writer.set("vendor.io.mode", "preset").unwrap();
+
+    // `ro.io.board` is already set and keeps its value; the later
+    // `ro.io.model` wins over the imported one.
+    assert_eq!(writer.import_prop_file(&build, None).unwrap(), 3);
+    let get = |name| writer.get_with_result(name).unwrap();
+    assert_eq!(get("ro.io.model"), "device");
+    assert_eq!(get("ro.io.board"), "preset");
+    assert_eq!(get("vendor.io.mode"), "base");
+    assert_eq!(get("vendor.io.level"), "3");
+
+    // With a filter, imports are not followed.
+    let extra = dir.join("extra.prop");
+    std::fs::write(&extra, "vendor.io.level=4\nvendor.other=1\n").unwrap();
+    assert_eq!(
+        writer
+            .import_prop_file(&extra, Some("vendor.io.*"))
+            .unwrap(),
+        1
+    );
+    assert_eq!(writer.get_with_result("vendor.io.level").unwrap(), "4");
+    assert!(writer.get_with_result("vendor.other").is_err());
+
+    writer.set("vendor.io.note", "two\nlines").unwrap();
+    let exported = dir.join("exported.prop");
+    assert_eq!(writer.export_prop_file(&exported).unwrap(), 4);
+    assert_eq!(
+        std::fs::read_to_string(&exported).unwrap(),
+        "ro.io.board=preset\n\
+         ro.io.model=device\n\
+         vendor.io.level=4\n\
+         vendor.io.mode=base\n"
+    );
+
+    // Concurrent exports to one path each use their own temporary file:
+    // every one publishes a complete dump and none is left behind.
+    let expected = std::fs::read_to_string(&exported).unwrap();
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| assert_eq!(writer.export_prop_file(&exported).unwrap(), 4));
+        }
+    });
+    assert_eq!(std::fs::read_to_string(&exported).unwrap(), expected);
+    let leftovers: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().contains(".tmp-"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+
+    // The dump seeds an empty store with the same properties.
+    let copy_dir = dir.join("copy");
+    build_property_info(&copy_dir, CONTEXTS);
+    let mut copy = SystemProperties::new_area(&copy_dir).unwrap();
+    assert_eq!(copy.import_prop_file(&exported, None).unwrap(), 4);
+    assert_eq!(copy.get_with_result("ro.io.board").unwrap(), "preset");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,62 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::get_entry`: a property's value with its context,
+//! declared type, serial and storage kind.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, SystemProperties, PROP_VALUE_MAX};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "vendor. u:object_r:vendor_prop:s0 prefix string\n\
+    vendor.entry.level u:object_r:vendor_prop:s0 exact int\n\
+    ro. u:object_r:build_prop:s0 prefix string\n";
+
+#[test]
+fn test_get_entry() {
+    let dir = std::env::temp_dir().join(format!("rsprops_entry_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    let long = "x".repeat(PROP_VALUE_MAX + 10);
+    writer.set("vendor.entry.level", "3").unwrap();
+    writer.set("vendor.entry.level", "4").unwrap();
+    writer.set("ro.entry.fingerprint", &long).unwrap();
+    writer.set("other.entry", "").unwrap();
+    let reader = SystemProperties::open(&dir).unwrap();
+
+    let entry = reader.get_entry("vendor.entry.level").unwrap();
+    assert_eq!(entry.name, "vendor.entry.level");
+    assert_eq!(entry.value, "4");
+    assert_eq!(entry.context.as_deref(), Some("u:object_r:vendor_prop:s0"));
+    assert_eq!(entry.type_str.as_deref(), Some("int"));
+    assert!(!entry.is_long);
+    let handle = reader.find("vendor.entry.level").unwrap().unwrap();
+    assert_eq!(Some(entry.serial), reader.serial(&handle));
+
+    let entry = reader.get_entry("ro.entry.fingerprint").unwrap();
+    assert_eq!(entry.value, long);
+    assert_eq!(entry.context.as_deref(), Some("u:object_r:build_prop:s0"));
+    assert_eq!(entry.type_str.as_deref(), Some("string"));
+    assert!(entry.is_long);
+
+    // Outside every entry: the defaults the trie was built with.
+    let entry = reader.get_entry("other.entry").unwrap();
+    assert_eq!(entry.value, "");
+    assert_eq!(entry.context.as_deref(), Some("u:object_r:default_prop:s0"));
+    assert_eq!(entry.type_str.as_deref(), Some("string"));
+
+    // A declared property without a value has no entry; `describe` still
+    // reports its declaration.
+    assert!(matches!(
+        reader.get_entry("vendor.entry.unset"),
+        Err(Error::NotFound(_))
+    ));
+    assert!(!reader.describe("vendor.entry.unset").unwrap().exists);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
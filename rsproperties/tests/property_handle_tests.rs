@@ -0,0 +1,58 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `PropertyHandle`: a handle keeps working while its area stays put and
+//! goes stale, instead of reaching another record, once the area is
+//! rebuilt under it.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{migrate_area_dir, AreaFormat, Error, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_property_handle() {
+    let root = std::env::temp_dir().join(format!("rsprops_handle_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let (live, staging) = (root.join("live"), root.join("staging"));
+    build_property_info(&live, CONTEXTS);
+
+    {
+        let mut writer = SystemProperties::new_area(&live).unwrap();
+        writer.set("test.handle.a", "1").unwrap();
+        writer.set("test.handle.b", "2").unwrap();
+        let handle = writer.find("test.handle.b").unwrap().unwrap();
+        assert_eq!(handle.name(&writer).unwrap(), "test.handle.b");
+        handle.revalidate(&writer).unwrap();
+        writer.update(&handle, "3").unwrap();
+        assert_eq!(writer.get_with_result("test.handle.b").unwrap(), "3");
+    }
+
+    let reader = SystemProperties::open(&live).unwrap();
+    let handle = reader.find("test.handle.b").unwrap().unwrap();
+    assert!(reader.serial(&handle).is_some());
+
+    migrate_area_dir(&live, &staging, AreaFormat::Bionic).unwrap();
+
+    // The swap is noticed by `revalidate`, which remaps the area; from
+    // then on the handle is refused everywhere.
+    assert!(matches!(
+        handle.revalidate(&reader),
+        Err(Error::StaleHandle(_))
+    ));
+    assert!(matches!(handle.name(&reader), Err(Error::StaleHandle(_))));
+    assert_eq!(reader.serial(&handle), None);
+    assert_eq!(reader.wait(Some(&handle), None, None), None);
+
+    let fresh = reader.find("test.handle.b").unwrap().unwrap();
+    assert_ne!(fresh, handle);
+    fresh.revalidate(&reader).unwrap();
+    assert_eq!(fresh.name(&reader).unwrap(), "test.handle.b");
+    assert!(reader.serial(&fresh).is_some());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,39 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::add` accepts exactly the names
+//! `is_valid_property_name` does, so nothing lands in an area that the
+//! client or the service would refuse.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::wire::MAX_WIRE_NAME_LEN;
+use rsproperties::{SystemProperties, PROP_NAME_MAX};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_add_rejects_illegal_names() {
+    let dir = std::env::temp_dir().join(format!("rsprops_names_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut props = SystemProperties::new_area(&dir).expect("new_area");
+
+    let too_long = format!("test.{}", "a".repeat(MAX_WIRE_NAME_LEN));
+    for bad in ["test..name", "test.name.", "test name", too_long.as_str()] {
+        assert!(!rsproperties::is_valid_property_name(bad), "{bad:?}");
+        assert!(props.add(bad, "1").is_err(), "{bad:?}");
+    }
+
+    // PROP_NAME_MAX only bounds V1 frames: longer names are legal.
+    let long = format!("test.{}", "a".repeat(PROP_NAME_MAX));
+    assert!(rsproperties::is_valid_property_name(&long));
+    props.add(&long, "1").unwrap();
+    assert_eq!(props.get_with_result(&long).unwrap(), "1");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `PropertyWatcher` calls each subscription's closure when its property
+//! takes a new value, including one that was unset when watched, and not
+//! for a set that leaves the value as it was.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rsproperties::{PropertyWatcher, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test.watch. u:object_r:watch_prop:s0 prefix string\n";
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn test_property_watcher() {
+    let dir = std::env::temp_dir().join(format!("rsprops_watcher_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set("test.watch.mode", "idle").unwrap();
+    // The watcher thread needs a reader that outlives it.
+    let reader: &'static SystemProperties =
+        Box::leak(Box::new(SystemProperties::open(&dir).unwrap()));
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = PropertyWatcher::new(reader);
+    let mode_tx = tx.clone();
+    let mode = watcher.watch("test.watch.mode", move |name, value| {
+        mode_tx.send(format!("{name}={value}")).unwrap();
+    });
+    watcher.watch("test.watch.late", move |name, value| {
+        tx.send(format!("{name}={value}")).unwrap();
+    });
+    // A change before the start is reported once the thread runs.
+    writer.set("test.watch.mode", "busy").unwrap();
+    watcher.start().unwrap();
+    assert!(watcher.is_running());
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap(), "test.watch.mode=busy");
+
+    // The same value again is no change; a property set later is.
+    writer.set("test.watch.mode", "busy").unwrap();
+    writer.set("test.watch.late", "1").unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap(), "test.watch.late=1");
+    writer.set("test.watch.mode", "").unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap(), "test.watch.mode=");
+
+    assert!(watcher.unwatch(mode));
+    assert!(!watcher.unwatch(mode));
+    writer.set("test.watch.mode", "idle").unwrap();
+    writer.set("test.watch.late", "2").unwrap();
+    assert_eq!(rx.recv_timeout(TIMEOUT).unwrap(), "test.watch.late=2");
+
+    watcher.stop();
+    assert!(!watcher.is_running());
+    writer.set("test.watch.late", "3").unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only prefixes beyond `ro.`: writers store the first value and
+//! refuse to change it, unless the owner overrides them.
+//!
+//! Own test binary because the prefix list is latched process-wide.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "vendor. u:object_r:vendor_prop:s0 prefix string\n\
+    ro. u:object_r:build_prop:s0 prefix string\n";
+
+#[test]
+fn test_read_only_prefixes() {
+    let dir = std::env::temp_dir().join(format!("rsprops_read_only_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    rsproperties::try_init(
+        PropertyConfig::with_properties_dir(&dir).read_only_prefixes(["vendor.fixed."]),
+    )
+    .unwrap();
+    assert_eq!(rsproperties::read_only_prefixes(), ["ro.", "vendor.fixed."]);
+    assert!(matches!(
+        rsproperties::try_init(
+            PropertyConfig::builder()
+                .read_only_prefixes(["sys."])
+                .build()
+        ),
+        Err(Error::AlreadyInitialized(_))
+    ));
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set("vendor.fixed.sku", "a").unwrap();
+    writer.set("ro.serialno", "1234").unwrap();
+    writer.set("vendor.mutable", "1").unwrap();
+    writer.set("vendor.mutable", "2").unwrap();
+    for name in ["vendor.fixed.sku", "ro.serialno"] {
+        let err = writer.set(name, "b").unwrap_err();
+        assert!(matches!(err, Error::PermissionDenied(_)), "{err:?}");
+    }
+    assert_eq!(writer.get_with_result("vendor.fixed.sku").unwrap(), "a");
+
+    // The owner may rewrite its namespaces; `ro.` stays write-once.
+    writer
+        .override_read_only(|props| props.set("vendor.fixed.sku", "b"))
+        .unwrap();
+    assert_eq!(writer.get_with_result("vendor.fixed.sku").unwrap(), "b");
+    assert!(matches!(
+        writer.override_read_only(|props| props.set("ro.serialno", "5678")),
+        Err(Error::PermissionDenied(_))
+    ));
+    assert!(writer.set("vendor.fixed.sku", "c").is_err());
+
+    // Per writer, the list can be replaced; `ro.` is always on it.
+    writer.set_read_only_prefixes(Vec::<String>::new());
+    writer.set("vendor.fixed.sku", "c").unwrap();
+    assert!(writer.set("ro.serialno", "5678").is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
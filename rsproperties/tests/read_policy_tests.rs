@@ -0,0 +1,92 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `get_optional` tells unset, empty and errors apart, `try_get` folds
+//! them into `None`, and `ReadPolicy` decides how `get`, `get_or` and
+//! `try_get` treat an empty value.
+//!
+//! The global instance latches its directory and the policy is
+//! process-wide, so everything runs in one #[test] fn.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, PropertyConfig, ReadPolicy, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_read_policy() {
+    let dir = std::env::temp_dir().join(format!("rsprops_read_policy_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set("test.policy.mode", "fast").unwrap();
+    writer.set("test.policy.empty", "").unwrap();
+    rsproperties::init(PropertyConfig::with_properties_dir(&dir));
+
+    assert_eq!(
+        rsproperties::get_optional("test.policy.mode").unwrap(),
+        Some("fast".to_owned())
+    );
+    assert_eq!(
+        rsproperties::get_optional("test.policy.empty").unwrap(),
+        Some(String::new())
+    );
+    assert_eq!(
+        rsproperties::get_optional("test.policy.missing").unwrap(),
+        None
+    );
+    assert!(rsproperties::get_optional("bad..name").is_err());
+    assert_eq!(
+        rsproperties::try_get("test.policy.mode").as_deref(),
+        Some("fast")
+    );
+    assert_eq!(rsproperties::try_get("test.policy.missing"), None);
+    assert_eq!(rsproperties::try_get("bad..name"), None);
+
+    let fallback = || "default".to_owned();
+
+    assert_eq!(rsproperties::read_policy(), ReadPolicy::Mixed);
+    assert_eq!(
+        rsproperties::get::<String>("test.policy.empty").unwrap(),
+        ""
+    );
+    assert_eq!(
+        rsproperties::get_or_else("test.policy.empty", fallback),
+        "default"
+    );
+
+    rsproperties::set_read_policy(ReadPolicy::EmptyIsUnset);
+    assert_eq!(rsproperties::try_get("test.policy.empty"), None);
+    assert!(matches!(
+        rsproperties::get::<String>("test.policy.empty"),
+        Err(Error::NotFound(_))
+    ));
+    assert_eq!(
+        rsproperties::get_or_else("test.policy.empty", fallback),
+        "default"
+    );
+    assert_eq!(
+        rsproperties::get_optional("test.policy.empty").unwrap(),
+        Some(String::new())
+    );
+
+    rsproperties::set_read_policy(ReadPolicy::EmptyIsValue);
+    assert_eq!(
+        rsproperties::get::<String>("test.policy.empty").unwrap(),
+        ""
+    );
+    assert_eq!(rsproperties::get_or_else("test.policy.empty", fallback), "");
+    // Numbers still fall back: "" does not parse.
+    assert_eq!(rsproperties::get_or("test.policy.empty", 7), 7);
+    assert_eq!(
+        rsproperties::get::<String>("test.policy.mode").unwrap(),
+        "fast"
+    );
+
+    rsproperties::set_read_policy(ReadPolicy::default());
+    let _ = std::fs::remove_dir_all(&dir);
+}
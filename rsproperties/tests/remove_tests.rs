@@ -0,0 +1,155 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::remove`: a removed property reads as unset in every
+//! mapping of the area until it is set again, setting it again through the
+//! same writer brings its record back, and read-only properties cannot be
+//! removed.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::time::Duration;
+
+use rsproperties::{CachedProperty, Error, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "vendor. u:object_r:vendor_prop:s0 prefix string\n\
+    ro. u:object_r:build_prop:s0 prefix string\n";
+
+#[test]
+fn test_remove_and_set_again() {
+    let dir = std::env::temp_dir().join(format!("rsprops_remove_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    let reader = SystemProperties::open(&dir).unwrap();
+    writer.set("vendor.remove.a", "1").unwrap();
+    writer.set("vendor.remove.a.child", "2").unwrap();
+    writer.set("vendor.remove.b", "3").unwrap();
+    let handle = reader.find("vendor.remove.a").unwrap().unwrap();
+    assert_eq!(writer.fragmentation_report().unwrap().unreachable(), 0);
+
+    let serial = reader.context_serial();
+    assert!(writer.remove("vendor.remove.a").unwrap());
+    assert!(reader.context_serial() > serial);
+    assert!(matches!(
+        reader.get_with_result("vendor.remove.a"),
+        Err(Error::NotFound(_))
+    ));
+    assert!(reader.find("vendor.remove.a").unwrap().is_none());
+    // Only the one node lost its property: its children and siblings
+    // are still reachable.
+    assert_eq!(
+        reader.get_with_result("vendor.remove.a.child").unwrap(),
+        "2"
+    );
+    assert_eq!(reader.get_with_result("vendor.remove.b").unwrap(), "3");
+    // The record stays behind for handles taken before the removal.
+    assert!(reader.serial(&handle).is_some());
+    assert!(writer.fragmentation_report().unwrap().unreachable() > 0);
+
+    // Removing it again, or a name that was never set, changes nothing.
+    let serial = reader.context_serial();
+    assert!(!writer.remove("vendor.remove.a").unwrap());
+    assert!(!writer.remove("vendor.remove.never").unwrap());
+    assert!(!writer.remove("vendor.remove").unwrap());
+    assert_eq!(reader.context_serial(), serial);
+    assert!(matches!(
+        writer.remove("vendor..remove"),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    writer.set("vendor.remove.a", "4").unwrap();
+    assert_eq!(reader.get_with_result("vendor.remove.a").unwrap(), "4");
+    let names: Vec<String> = reader
+        .freeze()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.to_owned())
+        .filter(|name| name.starts_with("vendor.remove."))
+        .collect();
+    assert_eq!(names.len(), 3, "{names:?}");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_set_again_reuses_the_record() {
+    let dir = std::env::temp_dir().join(format!("rsprops_remove_reuse_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    // `CachedProperty` wants a store that outlives it.
+    let reader: &'static SystemProperties =
+        Box::leak(Box::new(SystemProperties::open(&dir).unwrap()));
+    writer.set("vendor.reuse.a", "1").unwrap();
+    let handle = reader.find("vendor.reuse.a").unwrap().unwrap();
+    let mut cached = CachedProperty::with_properties(reader, "vendor.reuse.a");
+    assert_eq!(cached.get(), "1");
+
+    assert!(writer.remove("vendor.reuse.a").unwrap());
+    let serial = reader.serial(&handle).unwrap();
+    let unreachable = writer.fragmentation_report().unwrap().unreachable();
+    assert!(unreachable > 0);
+
+    let waiter = std::thread::spawn(move || {
+        let timeout = rsproperties::Timespec {
+            tv_sec: 5,
+            tv_nsec: 0,
+        };
+        reader.wait(Some(&handle), Some(serial), Some(&timeout))
+    });
+    std::thread::sleep(Duration::from_millis(50));
+    writer.set("vendor.reuse.a", "2").unwrap();
+
+    // The handle, its waiter and the cached value all follow the re-add,
+    // and no space went to a second record.
+    assert!(waiter.join().unwrap().is_some());
+    assert_eq!(reader.find("vendor.reuse.a").unwrap(), Some(handle));
+    assert_eq!(handle.name(reader).unwrap(), "vendor.reuse.a");
+    assert_eq!(cached.get(), "2");
+    assert_eq!(reader.get_with_result("vendor.reuse.a").unwrap(), "2");
+    assert_eq!(writer.fragmentation_report().unwrap().unreachable(), 0);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_remove_read_only() {
+    let dir = std::env::temp_dir().join(format!("rsprops_remove_ro_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set_read_only_prefixes(["vendor.fixed."]);
+    writer.set("ro.remove.sku", "a").unwrap();
+    writer.set("vendor.fixed.mode", "b").unwrap();
+
+    for name in ["ro.remove.sku", "vendor.fixed.mode"] {
+        assert!(matches!(
+            writer.remove(name),
+            Err(Error::PermissionDenied(_))
+        ));
+        assert!(writer.get_with_result(name).is_ok());
+    }
+
+    // The owner may remove its own prefixes, never `ro.`.
+    writer
+        .override_read_only(|writer| {
+            assert!(writer.remove("vendor.fixed.mode")?);
+            assert!(matches!(
+                writer.remove("ro.remove.sku"),
+                Err(Error::PermissionDenied(_))
+            ));
+            Ok(())
+        })
+        .unwrap();
+    assert!(writer.get_with_result("vendor.fixed.mode").is_err());
+    assert_eq!(writer.get_with_result("ro.remove.sku").unwrap(), "a");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,51 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Strict mode: with `require_declared`, writers refuse names that only
+//! the trie's default context covers.
+//!
+//! Own test binary because the policy is latched process-wide.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{Error, PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "persist.sys. u:object_r:system_prop:s0 prefix string\n\
+    ro.build.host u:object_r:build_prop:s0 exact string\n";
+
+#[test]
+fn test_require_declared() {
+    let dir = std::env::temp_dir().join(format!("rsprops_require_declared_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    rsproperties::try_init(PropertyConfig::with_properties_dir(&dir).require_declared(true))
+        .unwrap();
+    assert!(rsproperties::require_declared());
+    assert!(matches!(
+        rsproperties::try_init(PropertyConfig::builder().require_declared(false).build()),
+        Err(Error::AlreadyInitialized(_))
+    ));
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.set("persist.sys.timezone", "Asia/Seoul").unwrap();
+    writer.add("ro.build.host", "builder").unwrap();
+    for name in ["vendor.stray", "ro.build.id"] {
+        let err = writer.set(name, "1").unwrap_err();
+        assert!(
+            matches!(&err, Error::Undeclared { name: undeclared } if undeclared == name),
+            "{err:?}"
+        );
+        assert!(writer.get_with_result(name).is_err());
+    }
+
+    // Per writer, the policy can be lifted again.
+    writer.set_require_declared(false);
+    writer.set("vendor.stray", "1").unwrap();
+    assert_eq!(writer.get_with_result("vendor.stray").unwrap(), "1");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,98 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Builds `examples/read_only_client.rs` without default features and
+//! with `builder`, and checks that the client-only library is the smaller
+//! one. Ignored by default (two extra release builds); run with
+//! `cargo test -p rsproperties --test size_check -- --ignored --nocapture`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Builds the example with `features` into its own target directory and
+/// returns the `rsproperties` rlib and the example binary.
+fn build(label: &str, features: &[&str]) -> (PathBuf, PathBuf) {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("size_check")
+        .join(label);
+    let output = Command::new(env!("CARGO"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args([
+            "build",
+            "--release",
+            "--example",
+            "read_only_client",
+            "--message-format=json",
+        ])
+        .args(features)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .output()
+        .expect("failed to run cargo");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{label} build failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut rlib = None;
+    let mut executable = None;
+    for line in stdout.lines() {
+        if !line.contains(r#""reason":"compiler-artifact""#) {
+            continue;
+        }
+        if line.contains(r#""name":"rsproperties""#) {
+            rlib = artifact_path(line, ".rlib");
+        }
+        if line.contains(r#""name":"read_only_client""#) {
+            executable = json_string(line, "executable");
+        }
+    }
+    (
+        rlib.expect("no rsproperties rlib in cargo output"),
+        executable.expect("no read_only_client executable in cargo output"),
+    )
+}
+
+/// The entry of the artifact's `filenames` ending in `suffix`.
+fn artifact_path(line: &str, suffix: &str) -> Option<PathBuf> {
+    let list = &line[line.find(r#""filenames":["#)? + 13..];
+    let list = &list[..list.find(']')?];
+    list.split(',')
+        .map(|entry| entry.trim_matches('"'))
+        .find(|entry| entry.ends_with(suffix))
+        .map(PathBuf::from)
+}
+
+/// The string value of `key`; cargo's paths need no unescaping on Unix.
+fn json_string(line: &str, key: &str) -> Option<PathBuf> {
+    let pattern = format!(r#""{key}":""#);
+    let value = &line[line.find(&pattern)? + pattern.len()..];
+    Some(PathBuf::from(&value[..value.find('"')?]))
+}
+
+fn size(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .unwrap_or_else(|e| panic!("{path:?}: {e}"))
+        .len()
+}
+
+#[test]
+#[ignore = "builds the crate twice in release mode"]
+fn test_read_only_build_is_smaller() {
+    let (client_rlib, client_exe) = build("read-only", &["--no-default-features"]);
+    let (full_rlib, full_exe) = build("builder", &["--features", "builder"]);
+
+    let (client_rlib, full_rlib) = (size(&client_rlib), size(&full_rlib));
+    println!("rsproperties rlib: read-only {client_rlib} bytes, builder {full_rlib} bytes");
+    println!(
+        "read_only_client:  read-only {} bytes, builder {} bytes",
+        size(&client_exe),
+        size(&full_exe)
+    );
+    assert!(
+        client_rlib < full_rlib,
+        "read-only rlib ({client_rlib}) is not smaller than the builder one ({full_rlib})"
+    );
+}
@@ -0,0 +1,92 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::snapshot` and `restore`, and with the `serde`
+//! feature their JSON form.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use rsproperties::{PropertySnapshot, SystemProperties};
+
+mod common;
+use common::{build_property_info, temp_dir};
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n\
+    test.count u:object_r:test_prop:s0 exact int\n\
+    ro.test. u:object_r:build_prop:s0 prefix string\n";
+
+#[test]
+fn test_snapshot_restores_into_another_store() {
+    let (source_dir, target_dir) = (temp_dir("snapshot_source"), temp_dir("snapshot_target"));
+    build_property_info(&source_dir, CONTEXTS);
+    build_property_info(&target_dir, CONTEXTS);
+
+    let mut source = SystemProperties::new_area(&source_dir).unwrap();
+    source.add("test.mode", "fast").unwrap();
+    source.add("test.count", "3").unwrap();
+    source.add("ro.test.board", "fixture").unwrap();
+    source.add("other", "x").unwrap();
+
+    let snapshot = source.snapshot().unwrap();
+    assert_eq!(snapshot.len(), 4);
+    let names: Vec<_> = snapshot.iter().map(|(name, _)| name).collect();
+    assert_eq!(names, ["other", "ro.test.board", "test.count", "test.mode"]);
+    let count = &snapshot.properties["test.count"];
+    assert_eq!(count.value, "3");
+    assert_eq!(count.context.as_deref(), Some("u:object_r:test_prop:s0"));
+    assert_eq!(count.type_str.as_deref(), Some("int"));
+    assert_eq!(
+        snapshot.properties["other"].context.as_deref(),
+        Some("u:object_r:default_prop:s0")
+    );
+
+    // Properties that already hold their value are left alone.
+    let mut target = SystemProperties::new_area(&target_dir).unwrap();
+    target.add("ro.test.board", "fixture").unwrap();
+    assert_eq!(target.restore(&snapshot).unwrap(), 4);
+    assert_eq!(target.snapshot().unwrap(), snapshot);
+
+    // `ro.` properties stay write-once.
+    let mut changed = snapshot.clone();
+    changed.insert("ro.test.board", "other");
+    assert!(target.restore(&changed).is_err());
+    assert_eq!(target.get_with_result("ro.test.board").unwrap(), "fixture");
+
+    // Values go through `set`: a restore stops at the first refused one.
+    let mut bad = PropertySnapshot::default();
+    bad.insert("test.a", "1");
+    bad.insert("test.long", "x".repeat(200));
+    bad.insert("test.z", "1");
+    let error = target.restore(&bad).unwrap_err();
+    assert!(error.to_string().contains("test.long"), "{error}");
+    assert_eq!(target.get_with_result("test.a").unwrap(), "1");
+    assert!(target.get_with_result("test.z").is_err());
+
+    let _ = std::fs::remove_dir_all(&source_dir);
+    let _ = std::fs::remove_dir_all(&target_dir);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_snapshot_json_round_trip() {
+    let dir = temp_dir("snapshot_json");
+    build_property_info(&dir, CONTEXTS);
+    let mut props = SystemProperties::new_area(&dir).unwrap();
+    props.add("test.count", "7").unwrap();
+    props.add("test.text", "a \"quoted\"\nline").unwrap();
+
+    let snapshot = props.snapshot().unwrap();
+    let json = serde_json::to_string_pretty(&snapshot).unwrap();
+    assert!(json.contains(r#""type": "int""#), "{json}");
+    let parsed: PropertySnapshot = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, snapshot);
+
+    // Hand-written fixtures may leave the metadata out.
+    let fixture: PropertySnapshot =
+        serde_json::from_str(r#"{"properties": {"test.count": {"value": "9"}}}"#).unwrap();
+    assert_eq!(fixture.get("test.count"), Some("9"));
+    assert_eq!(props.restore(&fixture).unwrap(), 1);
+    assert_eq!(props.get_with_result("test.count").unwrap(), "9");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,114 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `test_support::TestEnv`: populated areas, the in-process service and
+//! cleanup. The global configuration latches once per process, so the
+//! phases run sequentially in one #[test] fn.
+
+#![cfg(all(feature = "test-utils", not(target_os = "android")))]
+
+use std::io::Write;
+use std::time::Duration;
+
+use rsproperties::test_support::TestEnv;
+use rsproperties::{Error, SetErrorKind};
+
+#[test]
+fn test_env() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let scratch = std::env::temp_dir().join(format!("rsprops_test_env_{}", std::process::id()));
+    std::fs::create_dir_all(&scratch).unwrap();
+    let build_prop = scratch.join("build.prop");
+    std::fs::File::create(&build_prop)
+        .unwrap()
+        .write_all(b"ro.test_env.board=fixture\ntest_env.mode=from_build_prop\n")
+        .unwrap();
+
+    // Phase 1: a service-less environment is populated and cleaned up.
+    let dir = {
+        let env = TestEnv::builder()
+            .property("plain.value", "1")
+            .without_service()
+            .build()
+            .unwrap();
+        assert!(env.properties_dir().join("property_info").exists());
+        assert!(std::fs::read_dir(env.socket_dir())
+            .unwrap()
+            .next()
+            .is_none());
+        env.properties_dir().to_path_buf()
+    };
+    assert!(!dir.exists(), "dropping the env must remove its files");
+
+    // Phase 2: contexts, build.prop and explicit properties, installed as
+    // the global configuration.
+    let env = TestEnv::builder()
+        .context("test_env. u:object_r:test_env_prop:s0 prefix string")
+        .context("test_env.count u:object_r:test_env_prop:s0 exact int")
+        .build_prop(&build_prop)
+        .property("test_env.mode", "explicit")
+        .property("test_env.count", "3")
+        .build()
+        .unwrap();
+    env.install().unwrap();
+    assert!(matches!(env.install(), Err(Error::AlreadyInitialized(_))));
+    rsproperties::wait_for_service(Duration::from_secs(5)).unwrap();
+
+    assert_eq!(
+        rsproperties::get::<String>("ro.test_env.board").unwrap(),
+        "fixture"
+    );
+    // Explicit properties override build.prop.
+    assert_eq!(
+        rsproperties::get::<String>("test_env.mode").unwrap(),
+        "explicit"
+    );
+    assert_eq!(rsproperties::get::<i32>("test_env.count").unwrap(), 3);
+    let props = rsproperties::system_properties();
+    assert_eq!(props.property_type("test_env.count").unwrap(), Some("int"));
+
+    // Phase 3: writes through the service.
+    rsproperties::set("test_env.mode", "via_service").unwrap();
+    assert_eq!(
+        rsproperties::get::<String>("test_env.mode").unwrap(),
+        "via_service"
+    );
+
+    match rsproperties::set("ro.test_env.board", "other") {
+        Err(Error::ServiceError(e)) => {
+            assert_eq!(e.kind(), SetErrorKind::ReadOnly);
+            assert!(e.message.is_some());
+        }
+        other => panic!("expected a read-only rejection, got {other:?}"),
+    }
+    assert_eq!(
+        rsproperties::get::<String>("ro.test_env.board").unwrap(),
+        "fixture"
+    );
+
+    // Phase 4: direct writes skip the service but are visible to readers.
+    env.set("test_env.direct", "yes").unwrap();
+    assert_eq!(
+        rsproperties::get::<String>("test_env.direct").unwrap(),
+        "yes"
+    );
+
+    // Phase 5: child processes inherit the socket directory, which is
+    // unique to this process.
+    assert!(env
+        .socket_dir()
+        .to_string_lossy()
+        .contains(&std::process::id().to_string()));
+    let output = env.command("env").output().unwrap();
+    let expected = format!(
+        "{}={}",
+        rsproperties::SOCKET_DIR_ENV,
+        env.socket_dir().display()
+    );
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line == expected));
+
+    let _ = std::fs::remove_dir_all(&scratch);
+}
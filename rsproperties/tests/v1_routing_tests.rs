@@ -12,7 +12,7 @@
 //! One #[test] fn with sequential phases: the socket dir and protocol
 //! version latch process-wide, and the phases share the two socket paths.
 
-#![cfg(not(target_os = "android"))]
+#![cfg(all(feature = "service-protocol", not(target_os = "android")))]
 
 use std::io::Read;
 use std::os::unix::net::{UnixListener, UnixStream};
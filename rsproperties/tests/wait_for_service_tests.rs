@@ -0,0 +1,58 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `wait_for_service` readiness gating.
+
+#![cfg(all(
+    feature = "builder",
+    feature = "service-protocol",
+    not(target_os = "android")
+))]
+
+use std::os::unix::net::UnixListener;
+use std::time::{Duration, Instant};
+
+use rsproperties::{PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+#[test]
+fn test_wait_for_service_requires_socket_and_ready_property() {
+    let base = std::env::temp_dir().join(format!("rsprops_ready_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let properties_dir = base.join("properties");
+    let socket_dir = base.join("sockets");
+    build_property_info(&properties_dir, CONTEXTS);
+    std::fs::create_dir_all(&socket_dir).unwrap();
+    rsproperties::init(PropertyConfig::with_both_dirs(
+        properties_dir.clone(),
+        socket_dir.clone(),
+    ));
+
+    // Nothing listening.
+    let start = Instant::now();
+    let err = rsproperties::wait_for_service(Duration::from_millis(100)).unwrap_err();
+    assert!(
+        err.to_string().contains("not ready"),
+        "unexpected error: {err}"
+    );
+    assert!(start.elapsed() < Duration::from_secs(2));
+
+    // Connectable, but the areas do not announce readiness yet.
+    let _listener =
+        UnixListener::bind(socket_dir.join(rsproperties::PROPERTY_SERVICE_SOCKET_NAME)).unwrap();
+    let mut props = SystemProperties::new_area(&properties_dir).unwrap();
+    assert!(rsproperties::wait_for_service(Duration::from_millis(100)).is_err());
+
+    props
+        .add(rsproperties::SERVICE_READY_PROPERTY, "1")
+        .unwrap();
+    rsproperties::wait_for_service(Duration::from_secs(5)).expect("service ready");
+    // No representable deadline: waits without one, and is already ready.
+    rsproperties::wait_for_service(Duration::MAX).expect("service ready");
+
+    let _ = std::fs::remove_dir_all(&base);
+}
@@ -0,0 +1,66 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `rsproperties::wait_for_value` against a writer in another thread.
+//!
+//! Own test binary because it reads the process-global instance, which
+//! `rsproperties::init` latches once per process.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rsproperties::{PropertyConfig, SystemProperties};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "sys. u:object_r:system_prop:s0 prefix string\n";
+
+#[test]
+fn test_wait_for_value() {
+    let dir = std::env::temp_dir().join(format!("rsprops_wait_value_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut props = SystemProperties::new_area(&dir).expect("new_area");
+    rsproperties::init(PropertyConfig::with_properties_dir(&dir));
+
+    // A property that never appears times out; so does an unset one
+    // waited for with the empty value.
+    let started = Instant::now();
+    let timeout = Duration::from_millis(100);
+    assert!(!rsproperties::wait_for_value("sys.boot_completed", "1", timeout).unwrap());
+    assert!(started.elapsed() >= timeout);
+    assert!(!rsproperties::wait_for_value("sys.boot_completed", "", timeout).unwrap());
+
+    // Added, then changed twice: only the final value ends the wait.
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        props.add("sys.boot_completed", "0").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        props.set("sys.boot_completed", "").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        props.set("sys.boot_completed", "1").unwrap();
+        props
+    });
+    let started = Instant::now();
+    assert!(
+        rsproperties::wait_for_value("sys.boot_completed", "1", Duration::from_secs(5)).unwrap()
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let props = writer.join().unwrap();
+
+    // A value already in place returns at once; a different one times out.
+    let started = Instant::now();
+    assert!(rsproperties::wait_for_value("sys.boot_completed", "1", Duration::ZERO).unwrap());
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(!rsproperties::wait_for_value("sys.boot_completed", "0", timeout).unwrap());
+
+    // "Wait forever" is a timeout with no representable deadline.
+    assert!(rsproperties::wait_for_value("sys.boot_completed", "1", Duration::MAX).unwrap());
+
+    drop(props);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,88 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wake-latency histogram: blocked waits woken by a change are recorded
+//! only while recording is enabled, for both global and per-property
+//! waits.
+//!
+//! The histogram is process-wide, so the phases run sequentially in one
+//! #[test] fn.
+
+#![cfg(all(feature = "builder", not(target_os = "android")))]
+
+use std::time::Duration;
+
+use rsproperties::{PropertyConfig, SystemProperties, Timespec};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+const TIMEOUT: Timespec = Timespec {
+    tv_sec: 10,
+    tv_nsec: 0,
+};
+
+/// Parks a waiter (global serial, or the property's with `by_index`),
+/// sets the property from `writer` once it is parked, and returns what the
+/// waiter saw.
+fn wake_once(
+    writer: &mut SystemProperties,
+    reader: &SystemProperties,
+    by_index: bool,
+    value: &str,
+) -> Option<u32> {
+    let idx = reader.find("test.stats.prop").unwrap().unwrap();
+    let old = if by_index {
+        reader.serial(&idx).unwrap()
+    } else {
+        reader.context_serial()
+    };
+    std::thread::scope(|s| {
+        let waiter = s.spawn(|| {
+            let index = by_index.then_some(&idx);
+            reader.wait(index, Some(old), Some(&TIMEOUT))
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        writer.set("test.stats.prop", value).unwrap();
+        waiter.join().unwrap()
+    })
+}
+
+#[test]
+fn test_wake_latency_histogram() {
+    let dir = std::env::temp_dir().join(format!("rsprops_waitstats_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+
+    let mut writer = SystemProperties::new_area(&dir).unwrap();
+    writer.add("test.stats.prop", "0").unwrap();
+    rsproperties::init(PropertyConfig::with_properties_dir(&dir));
+    let reader = rsproperties::system_properties();
+
+    // Disabled by default: wakes are not recorded.
+    rsproperties::reset_wait_stats();
+    assert!(wake_once(&mut writer, reader, false, "1").is_some());
+    assert_eq!(rsproperties::wait_stats().count(), 0);
+
+    rsproperties::enable_wait_stats(true);
+    assert!(wake_once(&mut writer, reader, false, "2").is_some());
+    assert!(wake_once(&mut writer, reader, true, "3").is_some());
+    let stats = rsproperties::wait_stats();
+    assert_eq!(stats.count(), 2, "{stats:?}");
+    assert!(stats.max() < Duration::from_secs(5), "{stats:?}");
+    assert!(stats.percentile(1.0).unwrap() >= stats.mean().unwrap());
+
+    // The already-changed fast path never blocks and is not a wake.
+    let old = reader.context_serial();
+    writer.set("test.stats.prop", "4").unwrap();
+    assert!(reader.wait(None, Some(old), Some(&TIMEOUT)).is_some());
+    assert_eq!(rsproperties::wait_stats().count(), 2);
+
+    rsproperties::reset_wait_stats();
+    assert_eq!(rsproperties::wait_stats().count(), 0);
+    rsproperties::enable_wait_stats(false);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -1,12 +1,12 @@
 // Copyright 2024 Jeff Kim <hiking90@gmail.com>
 // SPDX-License-Identifier: Apache-2.0
 
-//! Linux regression tests for `SystemProperties::wait` / `serial`.
+//! Host (Linux, macOS) regression tests for `SystemProperties::wait` / `serial`.
 //!
-//! The wait path (sliced futex waits, deadline math, the lost-wakeup
+//! The wait path (futex waits, deadline math, the lost-wakeup
 //! re-check) was previously exercised only by the Android-gated tests in
 //! `property_change_wait_tests.rs`, i.e. never in CI. These tests run the
-//! same-process service arrangement on plain Linux: a builder writer
+//! same-process service arrangement on a plain host: a builder writer
 //! (`SystemProperties::new_area`) and the global read-only instance mapping
 //! the same files.
 //!
@@ -14,33 +14,16 @@
 //! globals once per process — hence a single #[test] fn with sequential
 //! phases instead of independent tests racing on the latch.
 
-#![cfg(all(feature = "builder", target_os = "linux"))]
+#![cfg(all(feature = "builder", not(target_os = "android")))]
 
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
 use std::time::{Duration, Instant};
 
-use rsproperties::{build_trie, PropertyConfig, PropertyInfoEntry, SystemProperties, Timespec};
+use rsproperties::{PropertyConfig, SystemProperties, Timespec};
 
-fn build_property_info(dir: &Path) {
-    std::fs::create_dir_all(dir).unwrap();
+mod common;
+use common::build_property_info;
 
-    let contexts_path = dir.join("property_contexts");
-    File::create(&contexts_path)
-        .unwrap()
-        .write_all(b"test. u:object_r:test_prop:s0 prefix string\n")
-        .unwrap();
-
-    let (entries, errors) = PropertyInfoEntry::parse_from_file(&contexts_path, false).unwrap();
-    assert!(errors.is_empty(), "parse errors: {errors:?}");
-
-    let data = build_trie(&entries, "u:object_r:default_prop:s0", "string").unwrap();
-    File::create(dir.join("property_info"))
-        .unwrap()
-        .write_all(&data)
-        .unwrap();
-}
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
 
 fn timespec(d: Duration) -> Timespec {
     Timespec {
@@ -53,7 +36,7 @@ fn timespec(d: Duration) -> Timespec {
 fn test_wait_wake_across_instances() {
     let dir = std::env::temp_dir().join(format!("rsprops_waitwake_{}", std::process::id()));
     let _ = std::fs::remove_dir_all(&dir);
-    build_property_info(&dir);
+    build_property_info(&dir, CONTEXTS);
 
     let mut writer = SystemProperties::new_area(&dir).expect("writer new_area");
     writer.add("test.wait.prop", "0").unwrap();
@@ -66,9 +49,8 @@ fn test_wait_wake_across_instances() {
         .expect("property added by the writer must be visible to the reader");
 
     // Phase 1 — wake: a waiter parked on the property's serial must observe
-    // a cross-instance write. The 300ms delay before the set spans several
-    // 100ms lock slices, so this also exercises the re-acquire/re-check
-    // loop, not just the first futex wait.
+    // a cross-instance write. The 300ms delay before the set makes sure the
+    // waiter is parked in the futex, not racing through the fast path.
     let old = reader.serial(&idx).expect("initial serial");
     let waiter = std::thread::spawn(move || {
         let reader = rsproperties::system_properties();
@@ -86,7 +68,7 @@ fn test_wait_wake_across_instances() {
     assert_eq!(reader.get_with_result("test.wait.prop").unwrap(), "1");
 
     // Phase 2 — timeout: with no writer activity the wait must expire close
-    // to the requested bound (deadline math, clamped final slice), not hang
+    // to the requested bound (deadline math), not hang
     // and not return early.
     let old = reader.serial(&idx).unwrap();
     let start = Instant::now();
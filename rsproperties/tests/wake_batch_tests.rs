@@ -0,0 +1,122 @@
+// Copyright 2024 Jeff Kim <hiking90@gmail.com>
+// SPDX-License-Identifier: Apache-2.0
+
+//! `SystemProperties::batch` coalesces futex wakes: one per updated
+//! property and one on the global serial when the batch ends, instead of
+//! up to two per change — counted through a wrapping wait backend.
+//!
+//! Backends latch process-wide on first use, so everything runs in one
+//! #[test] fn that installs the backend before touching any property.
+
+#![cfg(all(feature = "builder", target_os = "linux"))]
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use rsproperties::backend::{FutexBackend, WaitBackend, WaitOutcome};
+use rsproperties::{Error, SystemProperties};
+use rsproperties::{Result, Timespec};
+
+mod common;
+use common::build_property_info;
+
+const CONTEXTS: &str = "test. u:object_r:test_prop:s0 prefix string\n";
+
+struct CountingWake {
+    wakes: AtomicUsize,
+}
+
+impl WaitBackend for CountingWake {
+    fn wait(&self, serial: &AtomicU32, value: u32, timeout: Option<&Timespec>) -> WaitOutcome {
+        FutexBackend.wait(serial, value, timeout)
+    }
+
+    fn wake(&self, serial: &AtomicU32) -> Result<usize> {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+        FutexBackend.wake(serial)
+    }
+}
+
+static WAKE: CountingWake = CountingWake {
+    wakes: AtomicUsize::new(0),
+};
+
+fn wakes_during(f: impl FnOnce()) -> usize {
+    let before = WAKE.wakes.load(Ordering::SeqCst);
+    f();
+    WAKE.wakes.load(Ordering::SeqCst) - before
+}
+
+#[test]
+fn test_batch_coalesces_wakes() {
+    rsproperties::backend::set_wait_backend(&WAKE).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("rsprops_wake_batch_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    build_property_info(&dir, CONTEXTS);
+    let mut props = SystemProperties::new_area(&dir).unwrap();
+    let names = ["test.batch.a", "test.batch.b", "test.batch.c"];
+
+    // Unbatched: an add wakes the global serial, an update the record too.
+    assert_eq!(
+        wakes_during(|| {
+            for name in names {
+                props.set(name, "0").unwrap();
+            }
+        }),
+        names.len()
+    );
+    assert_eq!(wakes_during(|| props.set("test.batch.a", "1").unwrap()), 2);
+
+    // Batched: each updated record once, the global serial once.
+    let serial = props.context_serial();
+    let woken = wakes_during(|| {
+        props
+            .batch(|props| {
+                for round in 0..5 {
+                    for name in names {
+                        props.set(name, &round.to_string())?;
+                    }
+                }
+                props.set("test.batch.new", "1")?;
+                // A nested batch joins the outer one.
+                props.batch(|props| props.set("test.batch.a", "nested"))
+            })
+            .unwrap()
+    });
+    assert_eq!(woken, names.len() + 1);
+    assert_ne!(props.context_serial(), serial);
+    assert_eq!(props.get_with_result("test.batch.a").unwrap(), "nested");
+    assert_eq!(props.get_with_result("test.batch.new").unwrap(), "1");
+
+    // A failing batch still wakes for the changes it made.
+    let woken = wakes_during(|| {
+        let result = props.batch(|props| {
+            props.set("test.batch.b", "before-error")?;
+            props.set("bad name", "x")
+        });
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    });
+    assert_eq!(woken, 2);
+    assert_eq!(
+        props.get_with_result("test.batch.b").unwrap(),
+        "before-error"
+    );
+
+    // So does a panicking one, and later sets are not deferred.
+    let woken = wakes_during(|| {
+        let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            props.batch(|props| -> Result<()> {
+                props.set("test.batch.c", "before-panic")?;
+                panic!("batch body panicked");
+            })
+        }));
+        assert!(unwound.is_err());
+    });
+    assert_eq!(woken, 2);
+    assert_eq!(
+        wakes_during(|| props.set("test.batch.c", "after").unwrap()),
+        2
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}